@@ -0,0 +1,110 @@
+//! Checks whether the messages in a new version of a `.bilrost` schema can decode data that was
+//! encoded with an old version, and lists every change between them.
+//!
+//! ```text
+//! bilrost-compat [--distinguished] <OLD> <NEW> [MESSAGE...]
+//! ```
+//!
+//! Messages are matched by name, and only the named messages are checked, along with the messages
+//! nested in them; with no names, every message defined in both schemas is checked. The exit
+//! status is 1 if any change is breaking, or with `--distinguished` if any change only works in
+//! expedient mode, 2 if the schemas can't be read, and 0 otherwise.
+
+use std::env;
+use std::fs;
+use std::process::ExitCode;
+
+use bilrost::compat::{self, Change, Compatibility};
+use bilrost::descriptor::MessageDescriptor;
+use bilrost_build::schema::Schema;
+
+const USAGE: &str = "usage: bilrost-compat [--distinguished] <OLD> <NEW> [MESSAGE...]";
+
+fn main() -> ExitCode {
+    let mut distinguished = false;
+    let mut args = Vec::new();
+    for arg in env::args().skip(1) {
+        match arg.as_str() {
+            "--distinguished" => distinguished = true,
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                return ExitCode::SUCCESS;
+            }
+            _ => args.push(arg),
+        }
+    }
+    let [old_path, new_path, names @ ..] = args.as_slice() else {
+        eprintln!("{USAGE}");
+        return ExitCode::from(2);
+    };
+    let (old, new) = match (read_schema(old_path), read_schema(new_path)) {
+        (Ok(old), Ok(new)) => (old, new),
+        (Err(err), _) | (_, Err(err)) => {
+            eprintln!("{err}");
+            return ExitCode::from(2);
+        }
+    };
+    let find = |descriptors: &[&'static MessageDescriptor], name: &str| {
+        descriptors
+            .iter()
+            .copied()
+            .find(|descriptor| descriptor.name == name)
+    };
+    let mut pairs = Vec::new();
+    if names.is_empty() {
+        for &old_message in &old {
+            if let Some(new_message) = find(&new, old_message.name) {
+                pairs.push((old_message, new_message));
+            }
+        }
+    } else {
+        for name in names {
+            match (find(&old, name), find(&new, name)) {
+                (Some(old_message), Some(new_message)) => pairs.push((old_message, new_message)),
+                (None, _) => {
+                    eprintln!("{old_path}: there is no message named {name}");
+                    return ExitCode::from(2);
+                }
+                (_, None) => {
+                    eprintln!("{new_path}: there is no message named {name}");
+                    return ExitCode::from(2);
+                }
+            }
+        }
+    }
+
+    // Nested messages are reported by each message that contains them, so only list them once.
+    let mut changes: Vec<Change> = Vec::new();
+    for (old_message, new_message) in pairs {
+        for change in compat::check(old_message, new_message).changes {
+            if !changes.contains(&change) {
+                changes.push(change);
+            }
+        }
+    }
+    for change in &changes {
+        println!("{change}");
+    }
+    let compatibility = changes
+        .iter()
+        .map(|change| change.compatibility)
+        .max()
+        .unwrap_or(Compatibility::Compatible);
+    println!("{compatibility}");
+    let failing = if distinguished {
+        Compatibility::ExpedientOnly
+    } else {
+        Compatibility::Breaking
+    };
+    if compatibility >= failing {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+fn read_schema(path: &str) -> Result<Vec<&'static MessageDescriptor>, String> {
+    let text = fs::read_to_string(path).map_err(|err| format!("{path}: {err}"))?;
+    let schema = Schema::parse(&text).map_err(|err| format!("{path}:{err}"))?;
+    Ok(schema.message_descriptors())
+}
@@ -0,0 +1,378 @@
+//! Generates Rust code for a validated schema.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+use crate::schema::{Encoding, EnumerationDef, FieldDef, Item, MessageDef, OneofDef, Schema, Type};
+use crate::validate::{named_types, Named};
+
+/// Words that must be written as raw identifiers to be used as names in Rust.
+const RUST_KEYWORDS: &[&str] = &[
+    "abstract", "as", "async", "await", "become", "box", "break", "const", "continue", "do", "dyn",
+    "else", "enum", "extern", "false", "final", "fn", "for", "gen", "if", "impl", "in", "let",
+    "loop", "macro", "match", "mod", "move", "mut", "override", "priv", "pub", "ref", "return",
+    "static", "struct", "trait", "true", "try", "type", "typeof", "unsafe", "unsized", "use",
+    "virtual", "where", "while", "yield",
+];
+
+/// Names that cannot be used in Rust at all, even as raw identifiers.
+pub(crate) const UNUSABLE_NAMES: &[&str] = &["_", "crate", "self", "Self", "super"];
+
+fn ident(name: &str) -> String {
+    if RUST_KEYWORDS.contains(&name) {
+        format!("r#{name}")
+    } else {
+        name.to_string()
+    }
+}
+
+/// Facts about the messages in a schema that decide how their Rust types are written.
+pub(crate) struct Analysis<'a> {
+    types: BTreeMap<&'a str, Named<'a>>,
+    /// For each message, the messages that can be reached from its fields, directly or not.
+    reachable: BTreeMap<&'a str, BTreeSet<&'a str>>,
+    /// Messages that contain floating point values somewhere, and so cannot implement `Eq` or be
+    /// decoded in distinguished mode.
+    has_floats: BTreeSet<&'a str>,
+}
+
+pub(crate) fn all_fields(message: &MessageDef) -> impl Iterator<Item = &FieldDef> {
+    message
+        .fields
+        .iter()
+        .chain(message.oneofs.iter().flat_map(|oneof| &oneof.variants))
+}
+
+/// Calls `f` with the name of every named type that appears in the type, and returns true if the
+/// type contains floating point values itself.
+fn visit_type<'a>(ty: &'a Type, f: &mut impl FnMut(&'a str)) -> bool {
+    match ty {
+        Type::F32 | Type::F64 => true,
+        Type::Named(name) => {
+            f(name);
+            false
+        }
+        Type::Optional(inner) | Type::List(inner) | Type::Set(inner) => visit_type(inner, f),
+        Type::Map(key, value) => visit_type(key, f) | visit_type(value, f),
+        _ => false,
+    }
+}
+
+impl<'a> Analysis<'a> {
+    pub(crate) fn new(schema: &'a Schema) -> Self {
+        let types = named_types(schema).expect("schema was not validated");
+        let mut direct: BTreeMap<&str, BTreeSet<&str>> = BTreeMap::new();
+        let mut has_floats = BTreeSet::new();
+        for message in schema.messages() {
+            let references = direct.entry(&message.name).or_default();
+            for field in all_fields(message) {
+                let floats = visit_type(&field.ty, &mut |name| {
+                    if let Named::Message(_) = types[name] {
+                        references.insert(name);
+                    }
+                });
+                if floats {
+                    has_floats.insert(message.name.as_str());
+                }
+            }
+        }
+        let reachable: BTreeMap<&str, BTreeSet<&str>> = direct
+            .keys()
+            .map(|&name| {
+                let mut seen = BTreeSet::new();
+                let mut queue: Vec<&str> = direct[name].iter().copied().collect();
+                while let Some(next) = queue.pop() {
+                    if seen.insert(next) {
+                        queue.extend(direct[next].iter().copied());
+                    }
+                }
+                (name, seen)
+            })
+            .collect();
+        // Messages containing messages with floats have floats too.
+        let has_floats = reachable
+            .iter()
+            .filter(|(name, reached)| {
+                has_floats.contains(*name) || reached.iter().any(|r| has_floats.contains(r))
+            })
+            .map(|(name, _)| *name)
+            .collect();
+        Self {
+            types,
+            reachable,
+            has_floats,
+        }
+    }
+
+    /// Returns true if a value of the type contains floating point values.
+    fn type_has_floats(&self, ty: &Type) -> bool {
+        let mut nested_floats = false;
+        let floats = visit_type(ty, &mut |name| {
+            nested_floats |= self.has_floats.contains(name);
+        });
+        floats || nested_floats
+    }
+
+    /// Returns true if a field of the given message with this type is part of a recursion of
+    /// messages that includes that message.
+    pub(crate) fn recurses(&self, message: &MessageDef, ty: &Type) -> bool {
+        let mut recurses = false;
+        visit_type(ty, &mut |name| {
+            recurses |= name == message.name
+                || self
+                    .reachable
+                    .get(name)
+                    .map_or(false, |reached| reached.contains(message.name.as_str()));
+        });
+        recurses
+    }
+
+    /// Writes the Rust type for values of the given type with the given encoding. Messages that
+    /// recurse are boxed unless they are inside a collection.
+    fn rust_type(&self, ty: &Type, encoding: &Encoding, boxed: bool) -> String {
+        match ty {
+            Type::Bool => "bool".into(),
+            Type::U8 => "u8".into(),
+            Type::U16 => "u16".into(),
+            Type::U32 => "u32".into(),
+            Type::U64 => "u64".into(),
+            Type::U128 => "u128".into(),
+            Type::I8 => "i8".into(),
+            Type::I16 => "i16".into(),
+            Type::I32 => "i32".into(),
+            Type::I64 => "i64".into(),
+            Type::I128 => "i128".into(),
+            Type::F32 => "f32".into(),
+            Type::F64 => "f64".into(),
+            Type::String => "::bilrost::alloc::string::String".into(),
+            Type::Bytes => match encoding {
+                Encoding::PlainBytes => "::bilrost::alloc::vec::Vec<u8>".into(),
+                _ => "::bilrost::Blob".into(),
+            },
+            Type::Named(name) => match self.types[name.as_str()] {
+                Named::Message(_) if boxed => {
+                    format!("::bilrost::alloc::boxed::Box<{}>", ident(name))
+                }
+                _ => ident(name),
+            },
+            Type::Optional(inner) => format!(
+                "::core::option::Option<{}>",
+                self.rust_type(inner, encoding, boxed)
+            ),
+            Type::List(item) | Type::Set(item) => {
+                let item_encoding = match encoding {
+                    Encoding::Packed(item_encoding) | Encoding::Unpacked(item_encoding) => {
+                        item_encoding
+                    }
+                    other => other,
+                };
+                let collection = if let Type::List(_) = ty {
+                    "::bilrost::alloc::vec::Vec"
+                } else {
+                    "::bilrost::alloc::collections::BTreeSet"
+                };
+                format!(
+                    "{collection}<{}>",
+                    self.rust_type(item, item_encoding, false)
+                )
+            }
+            Type::Map(key, value) => {
+                let (key_encoding, value_encoding) = match encoding {
+                    Encoding::Map(key_encoding, value_encoding) => {
+                        (&**key_encoding, &**value_encoding)
+                    }
+                    _ => (&Encoding::General, &Encoding::General),
+                };
+                format!(
+                    "::bilrost::alloc::collections::BTreeMap<{}, {}>",
+                    self.rust_type(key, key_encoding, false),
+                    self.rust_type(value, value_encoding, false)
+                )
+            }
+        }
+    }
+}
+
+fn write_doc(out: &mut String, doc: &[String], indent: &str) {
+    for line in doc {
+        if line.is_empty() {
+            writeln!(out, "{indent}///").unwrap();
+        } else {
+            writeln!(out, "{indent}/// {line}").unwrap();
+        }
+    }
+}
+
+fn tag_list(tags: &[std::ops::RangeInclusive<u32>]) -> String {
+    tags.iter()
+        .map(|range| {
+            if range.start() == range.end() {
+                range.start().to_string()
+            } else {
+                format!("{}-{}", range.start(), range.end())
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Writes the `bilrost` attribute of a field or oneof variant.
+fn field_attribute(field: &FieldDef, recurses: bool) -> String {
+    let mut parts = Vec::new();
+    if field.encoding != Encoding::General {
+        parts.push(format!("encoding({})", field.encoding));
+    }
+    if recurses {
+        parts.push("recurses".to_string());
+    }
+    if parts.is_empty() {
+        format!("#[bilrost({})]", field.tag)
+    } else {
+        format!("#[bilrost(tag({}), {})]", field.tag, parts.join(", "))
+    }
+}
+
+fn derives(default: bool, eq: bool, traits: &[&str]) -> String {
+    let mut derives = vec!["Clone", "Debug"];
+    if default {
+        derives.push("Default");
+    }
+    derives.push("PartialEq");
+    if eq {
+        derives.push("Eq");
+    }
+    derives.extend(traits);
+    format!("#[derive({})]", derives.join(", "))
+}
+
+pub(crate) fn generate(schema: &Schema) -> String {
+    let analysis = Analysis::new(schema);
+    let mut out = String::from(
+        "// This file is @generated by bilrost-build. Do not edit it by hand; edit the schema it \
+        was\n// generated from instead.\n",
+    );
+    for item in &schema.items {
+        out.push('\n');
+        match item {
+            Item::Message(message) => write_message(&mut out, message, &analysis),
+            Item::Enumeration(enumeration) => write_enumeration(&mut out, enumeration),
+        }
+    }
+    out
+}
+
+fn write_enumeration(out: &mut String, enumeration: &EnumerationDef) {
+    // Enumerations with a zero value default to it, like the empty state of their fields.
+    let has_zero = enumeration
+        .variants
+        .iter()
+        .any(|variant| variant.number == 0);
+    write_doc(out, &enumeration.doc, "");
+    writeln!(
+        out,
+        "#[derive(Clone, Copy, Debug,{} PartialEq, Eq, Hash, ::bilrost::CanonicalOrd, \
+        ::bilrost::Enumeration)]\npub enum {} {{",
+        if has_zero { " Default," } else { "" },
+        ident(&enumeration.name)
+    )
+    .unwrap();
+    for variant in &enumeration.variants {
+        write_doc(out, &variant.doc, "    ");
+        let default = if variant.number == 0 {
+            "#[default]\n    "
+        } else {
+            ""
+        };
+        writeln!(
+            out,
+            "    {default}#[bilrost({})]\n    {},",
+            variant.number,
+            ident(&variant.name)
+        )
+        .unwrap();
+    }
+    out.push_str("}\n");
+}
+
+fn write_message(out: &mut String, message: &MessageDef, analysis: &Analysis) {
+    let distinguished = !analysis.has_floats.contains(message.name.as_str());
+    write_doc(out, &message.doc, "");
+    let traits: &[&str] = if distinguished {
+        &["::bilrost::Message", "::bilrost::DistinguishedMessage"]
+    } else {
+        &["::bilrost::Message"]
+    };
+    writeln!(out, "{}", derives(true, distinguished, traits)).unwrap();
+    if !message.reserved_tags.is_empty() {
+        writeln!(
+            out,
+            "#[bilrost(reserved_tags({}))]",
+            tag_list(&message.reserved_tags)
+        )
+        .unwrap();
+    }
+    writeln!(out, "pub struct {} {{", ident(&message.name)).unwrap();
+    for field in &message.fields {
+        let recurses = analysis.recurses(message, &field.ty);
+        write_doc(out, &field.doc, "    ");
+        writeln!(
+            out,
+            "    {}\n    pub {}: {},",
+            field_attribute(field, recurses),
+            ident(&field.name),
+            analysis.rust_type(&field.ty, &field.encoding, recurses)
+        )
+        .unwrap();
+    }
+    for oneof in &message.oneofs {
+        write_doc(out, &oneof.doc, "    ");
+        writeln!(
+            out,
+            "    #[bilrost(oneof({}))]\n    pub {}: ::core::option::Option<{}>,",
+            tag_list(&oneof.tags),
+            ident(&oneof.name),
+            message.oneof_type_name(oneof)
+        )
+        .unwrap();
+    }
+    if message.extensions {
+        out.push_str(
+            "    #[bilrost(extensions)]\n    pub extensions: \
+            ::bilrost::encoding::opaque::OpaqueMessage<'static>,\n",
+        );
+    }
+    out.push_str("}\n");
+
+    for oneof in &message.oneofs {
+        out.push('\n');
+        write_oneof(out, message, oneof, analysis);
+    }
+}
+
+fn write_oneof(out: &mut String, message: &MessageDef, oneof: &OneofDef, analysis: &Analysis) {
+    let distinguished = !oneof
+        .variants
+        .iter()
+        .any(|variant| analysis.type_has_floats(&variant.ty));
+    write_doc(out, &oneof.doc, "");
+    let traits: &[&str] = if distinguished {
+        &["::bilrost::Oneof", "::bilrost::DistinguishedOneof"]
+    } else {
+        &["::bilrost::Oneof"]
+    };
+    writeln!(out, "{}", derives(false, distinguished, traits)).unwrap();
+    writeln!(out, "pub enum {} {{", message.oneof_type_name(oneof)).unwrap();
+    for variant in &oneof.variants {
+        let recurses = analysis.recurses(message, &variant.ty);
+        write_doc(out, &variant.doc, "    ");
+        writeln!(
+            out,
+            "    {}\n    {}({}),",
+            field_attribute(variant, recurses),
+            ident(&variant.name),
+            analysis.rust_type(&variant.ty, &variant.encoding, recurses)
+        )
+        .unwrap();
+    }
+    out.push_str("}\n");
+}
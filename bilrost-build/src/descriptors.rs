@@ -0,0 +1,155 @@
+//! Builds runtime descriptors for the messages in a validated schema.
+
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use bilrost::descriptor::{
+    EncodingDescriptor, EnumerationDescriptor, EnumerationVariant, FieldDescriptor,
+    MessageDescriptor, MessageRef, OneofDescriptor, ValueKind,
+};
+
+use crate::codegen::Analysis;
+use crate::schema::{Encoding, FieldDef, MessageDef, Schema, Type};
+
+/// Holds the descriptor of a message once it is built. Messages refer to each other through these,
+/// so that a message can refer to messages that are built after it, including itself.
+type Slot = Mutex<Option<&'static MessageDescriptor>>;
+
+fn leak<T>(value: T) -> &'static T {
+    Box::leak(Box::new(value))
+}
+
+fn leak_str(value: &str) -> &'static str {
+    Box::leak(value.into())
+}
+
+fn leak_slice<T>(values: Vec<T>) -> &'static [T] {
+    Box::leak(values.into_boxed_slice())
+}
+
+struct Builder<'a> {
+    analysis: Analysis<'a>,
+    slots: BTreeMap<&'a str, &'static Slot>,
+    enumerations: BTreeMap<&'a str, &'static EnumerationDescriptor>,
+}
+
+pub(crate) fn message_descriptors(schema: &Schema) -> Vec<&'static MessageDescriptor> {
+    let builder = Builder {
+        analysis: Analysis::new(schema),
+        slots: schema
+            .messages()
+            .map(|message| (message.name.as_str(), leak(Mutex::new(None))))
+            .collect(),
+        enumerations: schema
+            .enumerations()
+            .map(|enumeration| {
+                let variants = enumeration
+                    .variants
+                    .iter()
+                    .map(|variant| EnumerationVariant {
+                        name: leak_str(&variant.name),
+                        number: variant.number,
+                    })
+                    .collect();
+                let descriptor = EnumerationDescriptor {
+                    name: leak_str(&enumeration.name),
+                    variants: leak_slice(variants),
+                };
+                (enumeration.name.as_str(), leak(descriptor))
+            })
+            .collect(),
+    };
+    schema
+        .messages()
+        .map(|message| {
+            let descriptor = leak(builder.message(message));
+            *builder.slots[message.name.as_str()].lock().unwrap() = Some(descriptor);
+            descriptor
+        })
+        .collect()
+}
+
+impl Builder<'_> {
+    fn message(&self, message: &MessageDef) -> MessageDescriptor {
+        let fields = |fields: &[FieldDef]| {
+            leak_slice(
+                fields
+                    .iter()
+                    .map(|field| self.field(message, field))
+                    .collect(),
+            )
+        };
+        let oneofs = message
+            .oneofs
+            .iter()
+            .map(|oneof| OneofDescriptor {
+                name: leak_str(&oneof.name),
+                fields: fields(&oneof.variants),
+            })
+            .collect();
+        MessageDescriptor {
+            name: leak_str(&message.name),
+            fields: fields(&message.fields),
+            oneofs: leak_slice(oneofs),
+            reserved_tags: leak_slice(message.reserved_tags.clone()),
+            has_extensions: message.extensions,
+        }
+    }
+
+    fn field(&self, message: &MessageDef, field: &FieldDef) -> FieldDescriptor {
+        FieldDescriptor {
+            name: leak_str(&field.name),
+            tag: field.tag,
+            encoding: encoding(&field.encoding),
+            value: self.value(&field.ty),
+            recurses: self.analysis.recurses(message, &field.ty),
+        }
+    }
+
+    fn value(&self, ty: &Type) -> &'static ValueKind {
+        match ty {
+            Type::Bool => &ValueKind::Bool,
+            Type::U8 => &ValueKind::U8,
+            Type::U16 => &ValueKind::U16,
+            Type::U32 => &ValueKind::U32,
+            Type::U64 => &ValueKind::U64,
+            Type::U128 => &ValueKind::U128,
+            Type::I8 => &ValueKind::I8,
+            Type::I16 => &ValueKind::I16,
+            Type::I32 => &ValueKind::I32,
+            Type::I64 => &ValueKind::I64,
+            Type::I128 => &ValueKind::I128,
+            Type::F32 => &ValueKind::F32,
+            Type::F64 => &ValueKind::F64,
+            Type::String => &ValueKind::String,
+            Type::Bytes => &ValueKind::Bytes,
+            Type::Named(name) => match self.enumerations.get(name.as_str()) {
+                Some(enumeration) => leak(ValueKind::Enumeration(enumeration)),
+                None => {
+                    let slot: &'static Slot = self.slots[name.as_str()];
+                    let get = leak(move || slot.lock().unwrap().expect("message is built"));
+                    leak(ValueKind::Message(MessageRef::from_fn(get)))
+                }
+            },
+            Type::Optional(inner) => leak(ValueKind::Optional(self.value(inner))),
+            Type::List(inner) => leak(ValueKind::List(self.value(inner))),
+            Type::Set(inner) => leak(ValueKind::Set(self.value(inner))),
+            Type::Map(key, value) => leak(ValueKind::Map(self.value(key), self.value(value))),
+        }
+    }
+}
+
+fn encoding(encoding: &Encoding) -> &'static EncodingDescriptor {
+    match encoding {
+        Encoding::General => &EncodingDescriptor::General,
+        Encoding::Fixed => &EncodingDescriptor::Fixed,
+        Encoding::Varint => &EncodingDescriptor::Varint,
+        Encoding::PlainBytes => &EncodingDescriptor::PlainBytes,
+        Encoding::Packed(inner) => leak(EncodingDescriptor::Packed(self::encoding(inner))),
+        Encoding::Unpacked(inner) => leak(EncodingDescriptor::Unpacked(self::encoding(inner))),
+        Encoding::Map(key, value) => leak(EncodingDescriptor::Map(
+            self::encoding(key),
+            self::encoding(value),
+        )),
+    }
+}
@@ -0,0 +1,218 @@
+#![doc(html_root_url = "https://docs.rs/bilrost-build/0.1005.0-dev")]
+
+//! `bilrost-build` generates Rust types for [`bilrost`][bilrost] messages from schemas written in
+//! `.bilrost` files, so that the same message definitions can be shared with projects that don't
+//! depend on the Rust crates that define them. It is meant to be used from a `build.rs` script.
+//! Going the other way, the schema of message types written in Rust can be exported with
+//! `bilrost::schema::export`.
+//!
+//! A schema contains messages and enumerations:
+//!
+//! ```text
+//! /// Doc comments are copied onto the generated types.
+//! enum Status {
+//!     Active = 0;
+//!     Retired = 1;
+//! }
+//!
+//! message Employee {
+//!     reserved_tags(4, 10-19);
+//!     name: string = 1;
+//!     // Fields without a tag take the tag after the previous field's
+//!     badges: list<u32> encoding(packed<fixed>);
+//!     status: Status = 3;
+//!     manager_id: optional<u64> = 5;
+//!     oneof contact(6-7) {
+//!         Email: string = 6;
+//!         Phone: u64 = 7 encoding(varint);
+//!     }
+//!     // Keeps unknown fields when decoding; requires bilrost's "opaque" feature
+//!     extensions;
+//! }
+//! ```
+//!
+//! The types of fields are the scalars `bool`, `u8`, `u16`, `u32`, `u64`, `u128`, `i8`, `i16`,
+//! `i32`, `i64`, `i128`, `f32`, `f64`, `string`, and `bytes`; the messages and enumerations defined
+//! in the schema; and `optional<T>`, `list<T>`, `set<T>`, and `map<K, V>`. Encodings are spelled
+//! the same as in the `encoding` attribute of a derived field and default to `general`.
+//!
+//! Each message becomes a struct deriving `Message`, and each enumeration an enum deriving
+//! `Enumeration` and `CanonicalOrd`, so that it is ordered by its numbers. Every oneof becomes an
+//! enum named after its message and field (`EmployeeContact` above) that is held in the message as
+//! an `Option`. Messages and oneofs also derive `Eq` and their distinguished traits unless they
+//! contain floating point values. Fields that make a
+//! message contain itself are given the `recurses` attribute, and boxed when they aren't in a
+//! collection. `bytes` fields are `Vec<u8>` with the `plainbytes` encoding and `bilrost::Blob`
+//! otherwise.
+//!
+//! Schemas are checked for the same mistakes that `bilrost-derive` rejects, such as tags used more
+//! than once in a message, fields using reserved tags, and oneofs whose declared tags don't match
+//! their variants, as well as for types that aren't supported by their encodings. Messages with
+//! extensions also cannot contain themselves, because derived messages can't decode their
+//! extensions fields when they are part of a recursion.
+//!
+//! To generate code from a build script:
+//!
+//! ```no_run
+//! // build.rs
+//! fn main() -> Result<(), bilrost_build::Error> {
+//!     bilrost_build::compile_schemas(&["schemas/employees.bilrost"])
+//! }
+//! ```
+//!
+//! Then include the generated code, which is named after the schema file, in the crate:
+//!
+//! ```ignore
+//! include!(concat!(env!("OUT_DIR"), "/employees.rs"));
+//! ```
+//!
+//! The crate also has a `bilrost-compat` tool, which checks whether a new version of a schema can
+//! decode data encoded with an old one using `bilrost::compat`, and exits with an error when any
+//! change between them is breaking:
+//!
+//! ```text
+//! bilrost-compat [--distinguished] old.bilrost new.bilrost [MESSAGE...]
+//! ```
+//!
+//! [bilrost]: https://docs.rs/bilrost
+
+use std::env;
+use std::fmt::{self, Display};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+mod codegen;
+mod descriptors;
+mod parse;
+pub mod schema;
+mod validate;
+
+use schema::{Position, Schema};
+
+/// An error reading, parsing, or generating code for a schema.
+#[derive(Debug)]
+pub enum Error {
+    /// A file could not be read or written.
+    Io { path: PathBuf, error: io::Error },
+    /// A schema is invalid.
+    Schema {
+        /// The file containing the schema, if it was read from one.
+        path: Option<PathBuf>,
+        position: Position,
+        message: String,
+    },
+    /// There is no `OUT_DIR` to write generated code into, because the code generator isn't
+    /// running in a build script and was not configured with a directory.
+    NoOutDir,
+}
+
+impl Error {
+    pub(crate) fn schema(position: Position, message: impl Into<String>) -> Self {
+        Error::Schema {
+            path: None,
+            position,
+            message: message.into(),
+        }
+    }
+
+    fn in_file(self, file: &Path) -> Self {
+        match self {
+            Error::Schema {
+                path: None,
+                position,
+                message,
+            } => Error::Schema {
+                path: Some(file.to_path_buf()),
+                position,
+                message,
+            },
+            other => other,
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io { path, error } => write!(f, "{}: {error}", path.display()),
+            Error::Schema {
+                path: Some(path),
+                position,
+                message,
+            } => write!(f, "{}:{position}: {message}", path.display()),
+            Error::Schema {
+                path: None,
+                position,
+                message,
+            } => write!(f, "{position}: {message}"),
+            Error::NoOutDir => f.write_str("OUT_DIR is not set and no output directory was given"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+/// Parses and validates the text of a schema and returns the Rust code generated for it.
+pub fn generate(schema: &str) -> Result<String, Error> {
+    Ok(codegen::generate(&Schema::parse(schema)?))
+}
+
+/// Generates code for each of the given schema files into `OUT_DIR`, with the default
+/// configuration. See [`Config::compile`].
+pub fn compile_schemas(schemas: &[impl AsRef<Path>]) -> Result<(), Error> {
+    Config::new().compile(schemas)
+}
+
+/// Configures how code is generated from schema files.
+#[derive(Clone, Debug, Default)]
+pub struct Config {
+    out_dir: Option<PathBuf>,
+}
+
+impl Config {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the directory that generated code is written into. By default, this is the `OUT_DIR`
+    /// of the build script.
+    pub fn out_dir(&mut self, path: impl Into<PathBuf>) -> &mut Self {
+        self.out_dir = Some(path.into());
+        self
+    }
+
+    /// Generates code for each of the given schema files. Each schema is compiled by itself, and
+    /// its code is written to a file with the same name and the extension `.rs`. Cargo is told to
+    /// run the build script again when any of the schemas change.
+    pub fn compile(&self, schemas: &[impl AsRef<Path>]) -> Result<(), Error> {
+        let out_dir = match &self.out_dir {
+            Some(out_dir) => out_dir.clone(),
+            None => env::var_os("OUT_DIR").ok_or(Error::NoOutDir)?.into(),
+        };
+        for path in schemas {
+            let path = path.as_ref();
+            println!("cargo:rerun-if-changed={}", path.display());
+            let text = fs::read_to_string(path).map_err(|error| Error::Io {
+                path: path.to_path_buf(),
+                error,
+            })?;
+            let code = generate(&text).map_err(|err| err.in_file(path))?;
+            let mut file_name = path.file_stem().unwrap_or(path.as_os_str()).to_os_string();
+            file_name.push(".rs");
+            let out_path = out_dir.join(file_name);
+            fs::write(&out_path, code).map_err(|error| Error::Io {
+                path: out_path,
+                error,
+            })?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,616 @@
+//! Parses the text of a `.bilrost` schema.
+
+use std::fmt::{self, Display};
+use std::iter::Peekable;
+use std::ops::RangeInclusive;
+use std::str::Chars;
+
+use crate::schema::{
+    Encoding, EnumerationDef, EnumerationVariantDef, FieldDef, Item, MessageDef, OneofDef,
+    Position, Schema, Type,
+};
+use crate::Error;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Token {
+    Ident(String),
+    Number(u32),
+    Punct(char),
+    Doc(String),
+    End,
+}
+
+impl Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Ident(ident) => write!(f, "`{ident}`"),
+            Token::Number(n) => write!(f, "`{n}`"),
+            Token::Punct(c) => write!(f, "`{c}`"),
+            Token::Doc(_) => f.write_str("a doc comment"),
+            Token::End => f.write_str("the end of the schema"),
+        }
+    }
+}
+
+/// Iterates over the characters of a schema, keeping track of their position.
+struct Cursor<'a> {
+    chars: Peekable<Chars<'a>>,
+    position: Position,
+}
+
+impl Cursor<'_> {
+    fn peek(&mut self) -> Option<char> {
+        self.chars.peek().copied()
+    }
+
+    fn bump(&mut self) {
+        if self.chars.next() == Some('\n') {
+            self.position.line += 1;
+            self.position.column = 1;
+        } else {
+            self.position.column += 1;
+        }
+    }
+
+    /// Takes characters for as long as they match the predicate.
+    fn take_while(&mut self, predicate: impl Fn(char) -> bool) -> String {
+        let mut taken = String::new();
+        while let Some(c) = self.peek().filter(|&c| predicate(c)) {
+            taken.push(c);
+            self.bump();
+        }
+        taken
+    }
+}
+
+/// Splits the text of a schema into tokens, each with the position where it starts. Ordinary
+/// comments are discarded, but doc comments starting with exactly three slashes are kept.
+fn tokenize(text: &str) -> Result<Vec<(Token, Position)>, Error> {
+    let mut tokens = Vec::new();
+    let mut cursor = Cursor {
+        chars: text.chars().peekable(),
+        position: Position { line: 1, column: 1 },
+    };
+    loop {
+        let start = cursor.position;
+        let Some(c) = cursor.peek() else {
+            break;
+        };
+        let token = if c.is_whitespace() {
+            cursor.bump();
+            continue;
+        } else if c == '/' {
+            cursor.bump();
+            if cursor.peek() != Some('/') {
+                return Err(Error::schema(start, "unexpected character '/'"));
+            }
+            cursor.bump();
+            let comment = cursor.take_while(|c| c != '\n');
+            match comment.strip_prefix('/') {
+                Some(doc) if !doc.starts_with('/') => {
+                    Token::Doc(doc.strip_prefix(' ').unwrap_or(doc).trim_end().to_string())
+                }
+                _ => continue,
+            }
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            Token::Ident(cursor.take_while(|c| c.is_ascii_alphanumeric() || c == '_'))
+        } else if c.is_ascii_digit() {
+            let digits = cursor.take_while(|c| c.is_ascii_digit());
+            match digits.parse() {
+                Ok(n) => Token::Number(n),
+                Err(_) => {
+                    return Err(Error::schema(
+                        start,
+                        format!("number {digits} is too large"),
+                    ))
+                }
+            }
+        } else if "{}()<>,;:=-".contains(c) {
+            cursor.bump();
+            Token::Punct(c)
+        } else {
+            return Err(Error::schema(start, format!("unexpected character {c:?}")));
+        };
+        tokens.push((token, start));
+    }
+    tokens.push((Token::End, cursor.position));
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<(Token, Position)>,
+    index: usize,
+}
+
+pub(crate) fn parse_schema(text: &str) -> Result<Schema, Error> {
+    let mut parser = Parser {
+        tokens: tokenize(text)?,
+        index: 0,
+    };
+    let mut items = Vec::new();
+    loop {
+        let doc = parser.docs();
+        let position = parser.position();
+        match parser.next() {
+            Token::Ident(keyword) if keyword == "message" => {
+                items.push(Item::Message(parser.message(doc)?))
+            }
+            Token::Ident(keyword) if keyword == "enum" => {
+                items.push(Item::Enumeration(parser.enumeration(doc)?))
+            }
+            Token::End if doc.is_empty() => break,
+            token => {
+                return Err(Error::schema(
+                    position,
+                    format!("expected `message` or `enum`, found {token}"),
+                ))
+            }
+        }
+    }
+    Ok(Schema { items })
+}
+
+impl Parser {
+    fn peek(&self) -> &Token {
+        self.peek_at(0)
+    }
+
+    fn peek_at(&self, offset: usize) -> &Token {
+        let index = (self.index + offset).min(self.tokens.len() - 1);
+        &self.tokens[index].0
+    }
+
+    fn position(&self) -> Position {
+        self.tokens[self.index].1
+    }
+
+    fn next(&mut self) -> Token {
+        let token = self.tokens[self.index].0.clone();
+        if token != Token::End {
+            self.index += 1;
+        }
+        token
+    }
+
+    fn unexpected(&self, expected: &str) -> Error {
+        Error::schema(
+            self.position(),
+            format!("expected {expected}, found {}", self.peek()),
+        )
+    }
+
+    fn eat_punct(&mut self, punct: char) -> bool {
+        if self.peek() == &Token::Punct(punct) {
+            self.next();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn punct(&mut self, punct: char) -> Result<(), Error> {
+        if self.eat_punct(punct) {
+            Ok(())
+        } else {
+            Err(self.unexpected(&format!("`{punct}`")))
+        }
+    }
+
+    fn ident(&mut self, what: &str) -> Result<(String, Position), Error> {
+        let position = self.position();
+        match self.peek() {
+            Token::Ident(ident) => {
+                let ident = ident.clone();
+                self.next();
+                Ok((ident, position))
+            }
+            _ => Err(self.unexpected(what)),
+        }
+    }
+
+    fn number(&mut self, what: &str) -> Result<u32, Error> {
+        match self.peek() {
+            &Token::Number(n) => {
+                self.next();
+                Ok(n)
+            }
+            _ => Err(self.unexpected(what)),
+        }
+    }
+
+    /// Returns true if the next token is the given keyword being used as one, rather than as the
+    /// name of a field.
+    fn at_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Token::Ident(ident) if ident == keyword)
+            && self.peek_at(1) != &Token::Punct(':')
+    }
+
+    /// Consumes any doc comments, returning their lines.
+    fn docs(&mut self) -> Vec<String> {
+        let mut doc = Vec::new();
+        while let Token::Doc(line) = self.peek() {
+            doc.push(line.clone());
+            self.next();
+        }
+        doc
+    }
+
+    fn type_name(&mut self, what: &str) -> Result<(String, Position), Error> {
+        let (name, position) = self.ident(what)?;
+        if Type::is_builtin_name(&name) {
+            return Err(Error::schema(
+                position,
+                format!("`{name}` is a built-in type and cannot be redefined"),
+            ));
+        }
+        Ok((name, position))
+    }
+
+    fn enumeration(&mut self, doc: Vec<String>) -> Result<EnumerationDef, Error> {
+        let (name, position) = self.type_name("an enumeration name")?;
+        self.punct('{')?;
+        let mut variants = Vec::new();
+        loop {
+            let doc = self.docs();
+            if self.eat_punct('}') {
+                break;
+            }
+            let (variant_name, position) = self.ident("a variant name or `}`")?;
+            self.punct('=')?;
+            let number = self.number("the variant's value")?;
+            self.punct(';')?;
+            variants.push(EnumerationVariantDef {
+                name: variant_name,
+                doc,
+                number,
+                position,
+            });
+        }
+        Ok(EnumerationDef {
+            name,
+            doc,
+            variants,
+            position,
+        })
+    }
+
+    fn message(&mut self, doc: Vec<String>) -> Result<MessageDef, Error> {
+        let (name, position) = self.type_name("a message name")?;
+        self.punct('{')?;
+        let mut message = MessageDef {
+            name,
+            doc,
+            fields: Vec::new(),
+            oneofs: Vec::new(),
+            reserved_tags: Vec::new(),
+            extensions: false,
+            position,
+        };
+        let mut has_reserved_tags = false;
+        // Fields without a tag take the tag after the previous field's, as in derived messages.
+        let mut next_tag = Some(1);
+        loop {
+            let doc = self.docs();
+            let position = self.position();
+            if self.eat_punct('}') {
+                break;
+            } else if self.at_keyword("reserved_tags") {
+                self.next();
+                if has_reserved_tags {
+                    return Err(Error::schema(
+                        position,
+                        format!("message {} has more than one reserved_tags", message.name),
+                    ));
+                }
+                has_reserved_tags = true;
+                message.reserved_tags = self.tag_list()?;
+                self.punct(';')?;
+            } else if self.at_keyword("extensions") {
+                self.next();
+                if message.extensions {
+                    return Err(Error::schema(
+                        position,
+                        format!("message {} has more than one extensions", message.name),
+                    ));
+                }
+                message.extensions = true;
+                self.punct(';')?;
+            } else if self.at_keyword("oneof") {
+                self.next();
+                let oneof = self.oneof(doc)?;
+                next_tag = oneof
+                    .tags
+                    .iter()
+                    .map(|range| *range.end())
+                    .max()
+                    .and_then(|tag| tag.checked_add(1));
+                message.oneofs.push(oneof);
+            } else {
+                let field = self.field(doc, next_tag, &message.name)?;
+                next_tag = field.tag.checked_add(1);
+                message.fields.push(field);
+            }
+        }
+        Ok(message)
+    }
+
+    fn oneof(&mut self, doc: Vec<String>) -> Result<OneofDef, Error> {
+        let (name, position) = self.ident("the oneof's name")?;
+        let tags = self.tag_list()?;
+        self.punct('{')?;
+        let mut variants = Vec::new();
+        loop {
+            let doc = self.docs();
+            if self.eat_punct('}') {
+                break;
+            }
+            let (variant_name, position) = self.ident("a variant name or `}`")?;
+            self.punct(':')?;
+            let ty = self.ty()?;
+            self.punct('=')?;
+            let tag = self.number("the variant's tag")?;
+            let encoding = self.field_encoding()?;
+            self.punct(';')?;
+            variants.push(FieldDef {
+                name: variant_name,
+                doc,
+                tag,
+                ty,
+                encoding,
+                position,
+            });
+        }
+        Ok(OneofDef {
+            name,
+            doc,
+            tags,
+            variants,
+            position,
+        })
+    }
+
+    fn field(
+        &mut self,
+        doc: Vec<String>,
+        next_tag: Option<u32>,
+        message_name: &str,
+    ) -> Result<FieldDef, Error> {
+        let (name, position) = self.ident("a field, `reserved_tags`, `oneof`, or `}`")?;
+        self.punct(':')?;
+        let ty = self.ty()?;
+        let tag = if self.eat_punct('=') {
+            self.number("the field's tag")?
+        } else {
+            next_tag.ok_or_else(|| {
+                Error::schema(position, format!("field {message_name}.{name} needs a tag"))
+            })?
+        };
+        let encoding = self.field_encoding()?;
+        self.punct(';')?;
+        Ok(FieldDef {
+            name,
+            doc,
+            tag,
+            ty,
+            encoding,
+            position,
+        })
+    }
+
+    /// Parses a parenthesized, comma-separated list of tags and ranges of tags.
+    fn tag_list(&mut self) -> Result<Vec<RangeInclusive<u32>>, Error> {
+        self.punct('(')?;
+        let mut tags = Vec::new();
+        loop {
+            let start = self.number("a tag")?;
+            let end = if self.eat_punct('-') {
+                self.number("the end of the tag range")?
+            } else {
+                start
+            };
+            tags.push(start..=end);
+            if !self.eat_punct(',') || self.peek() == &Token::Punct(')') {
+                break;
+            }
+        }
+        self.punct(')')?;
+        Ok(tags)
+    }
+
+    fn ty(&mut self) -> Result<Type, Error> {
+        let (name, _) = self.ident("a type")?;
+        if let Some((_, scalar)) = Type::SCALARS.iter().find(|(scalar, _)| *scalar == name) {
+            return Ok(scalar.clone());
+        }
+        Ok(match name.as_str() {
+            "optional" | "list" | "set" => {
+                self.punct('<')?;
+                let inner = Box::new(self.ty()?);
+                self.punct('>')?;
+                match name.as_str() {
+                    "optional" => Type::Optional(inner),
+                    "list" => Type::List(inner),
+                    _ => Type::Set(inner),
+                }
+            }
+            "map" => {
+                self.punct('<')?;
+                let key = Box::new(self.ty()?);
+                self.punct(',')?;
+                let value = Box::new(self.ty()?);
+                self.punct('>')?;
+                Type::Map(key, value)
+            }
+            _ => Type::Named(name),
+        })
+    }
+
+    /// Parses the optional `encoding(...)` of a field, which is `general` when it is absent.
+    fn field_encoding(&mut self) -> Result<Encoding, Error> {
+        if !self.at_keyword("encoding") {
+            return Ok(Encoding::General);
+        }
+        self.next();
+        self.punct('(')?;
+        let encoding = self.encoding()?;
+        self.punct(')')?;
+        Ok(encoding)
+    }
+
+    fn encoding(&mut self) -> Result<Encoding, Error> {
+        let (name, position) = self.ident("an encoding")?;
+        Ok(match name.as_str() {
+            "general" => Encoding::General,
+            "fixed" => Encoding::Fixed,
+            "varint" => Encoding::Varint,
+            "plainbytes" => Encoding::PlainBytes,
+            "packed" | "unpacked" => {
+                let inner = if self.eat_punct('<') {
+                    let inner = self.encoding()?;
+                    self.punct('>')?;
+                    inner
+                } else {
+                    Encoding::General
+                };
+                if name == "packed" {
+                    Encoding::Packed(Box::new(inner))
+                } else {
+                    Encoding::Unpacked(Box::new(inner))
+                }
+            }
+            "map" => {
+                self.punct('<')?;
+                let key = self.encoding()?;
+                self.punct(',')?;
+                let value = self.encoding()?;
+                self.punct('>')?;
+                Encoding::Map(Box::new(key), Box::new(value))
+            }
+            _ => {
+                return Err(Error::schema(
+                    position,
+                    format!("unknown encoding `{name}`"),
+                ))
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_schema;
+    use crate::schema::{Encoding, Item, Position, Type};
+    use crate::Error;
+
+    #[track_caller]
+    fn assert_syntax_error(schema: &str, line: usize, column: usize, expected: &str) {
+        match parse_schema(schema) {
+            Err(Error::Schema {
+                position, message, ..
+            }) => {
+                assert_eq!(message, expected);
+                assert_eq!(position, Position { line, column });
+            }
+            other => panic!("expected a syntax error, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_reports_syntax_errors_with_positions() {
+        assert_syntax_error(
+            "message M {\n    a: u32 = 1\n}",
+            3,
+            1,
+            "expected `;`, found `}`",
+        );
+        assert_syntax_error(
+            "struct M {}",
+            1,
+            1,
+            "expected `message` or `enum`, found `struct`",
+        );
+        assert_syntax_error(
+            "message M { a: u32 = 1 $ }",
+            1,
+            24,
+            "unexpected character '$'",
+        );
+        assert_syntax_error(
+            "message M {\n  a: u32 = 99999999999;\n}",
+            2,
+            12,
+            "number 99999999999 is too large",
+        );
+        assert_syntax_error(
+            "message list {}",
+            1,
+            9,
+            "`list` is a built-in type and cannot be redefined",
+        );
+        assert_syntax_error(
+            "message M { a: list<u32 = 1; }",
+            1,
+            25,
+            "expected `>`, found `=`",
+        );
+    }
+
+    #[test]
+    fn test_infers_tags_like_derived_messages() {
+        let schema = parse_schema(
+            "message M {
+                a: u32;
+                b: u32 = 5;
+                c: u32;
+                oneof o(7-8) { X: u32 = 7; Y: u32 = 8; }
+                d: u32;
+            }",
+        )
+        .unwrap();
+        let Item::Message(message) = &schema.items[0] else {
+            panic!("expected a message");
+        };
+        let tags: Vec<(&str, u32)> = message
+            .fields
+            .iter()
+            .map(|field| (field.name.as_str(), field.tag))
+            .collect();
+        assert_eq!(tags, [("a", 1), ("b", 5), ("c", 6), ("d", 9)]);
+    }
+
+    #[test]
+    fn test_parses_types_encodings_and_docs() {
+        let schema = parse_schema(
+            "// Not a doc comment.
+            /// The message.
+            message M {
+                /// First line.
+                ///
+                /// Third line.
+                a: map<string, list<u64>> = 1 encoding(map<general, packed<fixed>>);
+                b: list<u32> = 2 encoding(unpacked);
+                type: u32 = 3;
+            }",
+        )
+        .unwrap();
+        let Item::Message(message) = &schema.items[0] else {
+            panic!("expected a message");
+        };
+        assert_eq!(message.doc, ["The message."]);
+        let a = &message.fields[0];
+        assert_eq!(a.doc, ["First line.", "", "Third line."]);
+        assert_eq!(
+            a.ty,
+            Type::Map(
+                Box::new(Type::String),
+                Box::new(Type::List(Box::new(Type::U64)))
+            )
+        );
+        assert_eq!(a.encoding.to_string(), "map<general, packed<fixed>>");
+        assert_eq!(
+            message.fields[1].encoding,
+            Encoding::Unpacked(Box::new(Encoding::General))
+        );
+        assert_eq!(message.fields[2].name, "type");
+    }
+}
@@ -0,0 +1,251 @@
+//! The parsed contents of a `.bilrost` schema.
+
+use std::fmt::{self, Display};
+use std::ops::RangeInclusive;
+
+use bilrost::descriptor::MessageDescriptor;
+
+use crate::{descriptors, parse, validate, Error};
+
+/// A location in the text of a schema. Lines and columns are both numbered from 1.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+/// A complete schema, containing message and enumeration definitions in the order they appear.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Schema {
+    pub items: Vec<Item>,
+}
+
+impl Schema {
+    /// Parses and validates the text of a schema. Schemas are checked for the same mistakes that
+    /// would prevent the equivalent Rust types from deriving their traits, such as tags used more
+    /// than once in a message, fields with reserved tags, oneof fields whose variants don't match
+    /// their tags, and encodings that don't support the types they are used with.
+    pub fn parse(text: &str) -> Result<Schema, Error> {
+        let schema = parse::parse_schema(text)?;
+        validate::validate(&schema)?;
+        Ok(schema)
+    }
+
+    /// Returns an iterator over the messages defined in the schema.
+    pub fn messages(&self) -> impl Iterator<Item = &MessageDef> {
+        self.items.iter().filter_map(|item| match item {
+            Item::Message(message) => Some(message),
+            Item::Enumeration(_) => None,
+        })
+    }
+
+    /// Returns an iterator over the enumerations defined in the schema.
+    pub fn enumerations(&self) -> impl Iterator<Item = &EnumerationDef> {
+        self.items.iter().filter_map(|item| match item {
+            Item::Message(_) => None,
+            Item::Enumeration(enumeration) => Some(enumeration),
+        })
+    }
+
+    /// Returns the message with the given name, if there is one.
+    pub fn message(&self, name: &str) -> Option<&MessageDef> {
+        self.messages().find(|message| message.name == name)
+    }
+
+    /// Returns the enumeration with the given name, if there is one.
+    pub fn enumeration(&self, name: &str) -> Option<&EnumerationDef> {
+        self.enumerations()
+            .find(|enumeration| enumeration.name == name)
+    }
+
+    /// Builds runtime descriptors for the messages defined in the schema, in the order they are
+    /// defined. They describe the same fields as the descriptors of the types generated for the
+    /// schema, so schemas can be compared with `bilrost::compat` or used with other tools that
+    /// work from descriptors without generating code for them. The descriptors are leaked so
+    /// that they live for the rest of the program.
+    pub fn message_descriptors(&self) -> Vec<&'static MessageDescriptor> {
+        descriptors::message_descriptors(self)
+    }
+}
+
+/// A top-level definition in a schema.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Item {
+    Message(MessageDef),
+    Enumeration(EnumerationDef),
+}
+
+/// A message definition.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MessageDef {
+    pub name: String,
+    pub doc: Vec<String>,
+    /// The message's fields that are not part of a oneof, in declaration order.
+    pub fields: Vec<FieldDef>,
+    /// The message's oneof fields, in declaration order.
+    pub oneofs: Vec<OneofDef>,
+    /// Ranges of tags that are reserved and cannot be used by any field.
+    pub reserved_tags: Vec<RangeInclusive<u32>>,
+    /// Whether the message keeps the fields it doesn't know in an extensions field.
+    pub extensions: bool,
+    pub position: Position,
+}
+
+impl MessageDef {
+    /// Returns the name of the Rust type that is generated for the given oneof of this message,
+    /// which is the name of the message followed by the name of the oneof in `UpperCamelCase`.
+    pub fn oneof_type_name(&self, oneof: &OneofDef) -> String {
+        let mut name = self.name.clone();
+        for word in oneof.name.split('_') {
+            let mut chars = word.chars();
+            if let Some(first) = chars.next() {
+                name.extend(first.to_uppercase());
+                name.extend(chars);
+            }
+        }
+        name
+    }
+}
+
+/// A field of a message, or a variant of a oneof.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FieldDef {
+    pub name: String,
+    pub doc: Vec<String>,
+    pub tag: u32,
+    pub ty: Type,
+    pub encoding: Encoding,
+    pub position: Position,
+}
+
+/// A oneof field of a message, of which at most one variant may be present at a time.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OneofDef {
+    pub name: String,
+    pub doc: Vec<String>,
+    /// The tags declared for the oneof, which must be exactly the tags of its variants.
+    pub tags: Vec<RangeInclusive<u32>>,
+    pub variants: Vec<FieldDef>,
+    pub position: Position,
+}
+
+/// An enumeration definition.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EnumerationDef {
+    pub name: String,
+    pub doc: Vec<String>,
+    pub variants: Vec<EnumerationVariantDef>,
+    pub position: Position,
+}
+
+/// A variant of an enumeration.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EnumerationVariantDef {
+    pub name: String,
+    pub doc: Vec<String>,
+    pub number: u32,
+    pub position: Position,
+}
+
+/// The type of a field, spelled in a schema as it is displayed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Type {
+    Bool,
+    U8,
+    U16,
+    U32,
+    U64,
+    U128,
+    I8,
+    I16,
+    I32,
+    I64,
+    I128,
+    F32,
+    F64,
+    String,
+    Bytes,
+    /// A message or enumeration defined in the schema.
+    Named(String),
+    Optional(Box<Type>),
+    List(Box<Type>),
+    Set(Box<Type>),
+    Map(Box<Type>, Box<Type>),
+}
+
+impl Type {
+    /// The names of the scalar types, which cannot be used as the names of messages or
+    /// enumerations.
+    pub(crate) const SCALARS: &'static [(&'static str, Type)] = &[
+        ("bool", Type::Bool),
+        ("u8", Type::U8),
+        ("u16", Type::U16),
+        ("u32", Type::U32),
+        ("u64", Type::U64),
+        ("u128", Type::U128),
+        ("i8", Type::I8),
+        ("i16", Type::I16),
+        ("i32", Type::I32),
+        ("i64", Type::I64),
+        ("i128", Type::I128),
+        ("f32", Type::F32),
+        ("f64", Type::F64),
+        ("string", Type::String),
+        ("bytes", Type::Bytes),
+    ];
+
+    /// Returns true if the name is spelled the same as one of the built-in types.
+    pub(crate) fn is_builtin_name(name: &str) -> bool {
+        matches!(name, "optional" | "list" | "set" | "map")
+            || Type::SCALARS.iter().any(|(scalar, _)| *scalar == name)
+    }
+}
+
+impl Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::Named(name) => f.write_str(name),
+            Type::Optional(inner) => write!(f, "optional<{inner}>"),
+            Type::List(inner) => write!(f, "list<{inner}>"),
+            Type::Set(inner) => write!(f, "set<{inner}>"),
+            Type::Map(key, value) => write!(f, "map<{key}, {value}>"),
+            scalar => {
+                let (name, _) = Type::SCALARS.iter().find(|(_, ty)| ty == scalar).unwrap();
+                f.write_str(name)
+            }
+        }
+    }
+}
+
+/// The encoding of a field, spelled in a schema the same way as in the `encoding` attribute of a
+/// derived field.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    General,
+    Fixed,
+    Varint,
+    PlainBytes,
+    Packed(Box<Encoding>),
+    Unpacked(Box<Encoding>),
+    Map(Box<Encoding>, Box<Encoding>),
+}
+
+impl Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Encoding::General => f.write_str("general"),
+            Encoding::Fixed => f.write_str("fixed"),
+            Encoding::Varint => f.write_str("varint"),
+            Encoding::PlainBytes => f.write_str("plainbytes"),
+            Encoding::Packed(inner) => write!(f, "packed<{inner}>"),
+            Encoding::Unpacked(inner) => write!(f, "unpacked<{inner}>"),
+            Encoding::Map(key, value) => write!(f, "map<{key}, {value}>"),
+        }
+    }
+}
@@ -0,0 +1,637 @@
+//! Checks a parsed schema for the same mistakes that `bilrost-derive` rejects, so that the code
+//! generated from a valid schema always compiles.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::RangeInclusive;
+
+use crate::codegen::{all_fields, UNUSABLE_NAMES};
+use crate::schema::{Encoding, FieldDef, Item, MessageDef, Position, Schema, Type};
+use crate::Error;
+
+/// What a named type in the schema refers to.
+#[derive(Clone, Copy)]
+pub(crate) enum Named<'a> {
+    Message(&'a MessageDef),
+    /// An enumeration, and whether it has a variant with the value zero.
+    Enumeration {
+        has_zero: bool,
+    },
+}
+
+/// Indexes the types defined in a schema by name, failing if any name is defined twice. The types
+/// generated for oneofs are included, so they cannot collide with any other type either.
+pub(crate) fn named_types<'a>(schema: &'a Schema) -> Result<BTreeMap<&'a str, Named<'a>>, Error> {
+    let mut types = BTreeMap::new();
+    let mut oneof_types = Vec::new();
+    for item in &schema.items {
+        let (name, named, position) = match item {
+            Item::Message(message) => {
+                for oneof in &message.oneofs {
+                    oneof_types.push((message.oneof_type_name(oneof), oneof.position));
+                }
+                (&message.name, Named::Message(message), message.position)
+            }
+            Item::Enumeration(enumeration) => (
+                &enumeration.name,
+                Named::Enumeration {
+                    has_zero: enumeration
+                        .variants
+                        .iter()
+                        .any(|variant| variant.number == 0),
+                },
+                enumeration.position,
+            ),
+        };
+        if types.insert(name.as_str(), named).is_some() {
+            return Err(Error::schema(
+                position,
+                format!("type {name} is defined more than once"),
+            ));
+        }
+    }
+    for (name, position) in oneof_types {
+        if types.contains_key(name.as_str()) {
+            return Err(Error::schema(
+                position,
+                format!("the type generated for this oneof, {name}, is already defined"),
+            ));
+        }
+    }
+    Ok(types)
+}
+
+/// Checks that a name can be used in Rust.
+fn check_name(name: &str, position: Position) -> Result<(), Error> {
+    if UNUSABLE_NAMES.contains(&name) {
+        Err(Error::schema(
+            position,
+            format!("`{name}` cannot be used as a name"),
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+pub(crate) fn validate(schema: &Schema) -> Result<(), Error> {
+    for item in &schema.items {
+        match item {
+            Item::Message(message) => {
+                check_name(&message.name, message.position)?;
+                for field in all_fields(message) {
+                    check_name(&field.name, field.position)?;
+                }
+                for oneof in &message.oneofs {
+                    check_name(&oneof.name, oneof.position)?;
+                }
+            }
+            Item::Enumeration(enumeration) => {
+                check_name(&enumeration.name, enumeration.position)?;
+                for variant in &enumeration.variants {
+                    check_name(&variant.name, variant.position)?;
+                }
+            }
+        }
+    }
+    let types = named_types(schema)?;
+    for item in &schema.items {
+        match item {
+            Item::Message(message) => validate_message(message, &types)?,
+            Item::Enumeration(enumeration) => {
+                let name = &enumeration.name;
+                if enumeration.variants.is_empty() {
+                    return Err(Error::schema(
+                        enumeration.position,
+                        format!("enumeration {name} must have at least one variant"),
+                    ));
+                }
+                let mut names = BTreeSet::new();
+                let mut numbers = BTreeSet::new();
+                for variant in &enumeration.variants {
+                    if !names.insert(&variant.name) {
+                        return Err(Error::schema(
+                            variant.position,
+                            format!(
+                                "enumeration {name} has more than one variant named {}",
+                                variant.name
+                            ),
+                        ));
+                    }
+                    if !numbers.insert(variant.number) {
+                        return Err(Error::schema(
+                            variant.position,
+                            format!(
+                                "enumeration {name} has more than one variant with value {}",
+                                variant.number
+                            ),
+                        ));
+                    }
+                }
+            }
+        }
+    }
+    for message in schema.messages() {
+        check_unbounded_recursion(message, &types, &mut Vec::new())?;
+        if message.extensions {
+            check_recursive_extensions(message, &types)?;
+        }
+    }
+    Ok(())
+}
+
+/// Checks that a list of tag ranges is well formed, sorting it in the process.
+fn validate_tag_list(
+    tags: &mut [RangeInclusive<u32>],
+    range_size_limit: Option<u32>,
+    position: Position,
+) -> Result<(), Error> {
+    for range in tags.iter() {
+        if range.is_empty() {
+            return Err(Error::schema(
+                position,
+                format!("invalid tag range {}-{}", range.start(), range.end()),
+            ));
+        }
+        if let Some(limit) = range_size_limit {
+            if range.end() - range.start() >= limit - 1 {
+                return Err(Error::schema(
+                    position,
+                    format!(
+                        "too-large tag range {}-{}; use smaller ranges",
+                        range.start(),
+                        range.end()
+                    ),
+                ));
+            }
+        }
+    }
+    tags.sort_by_key(|range| (*range.start(), *range.end()));
+    for pair in tags.windows(2) {
+        if pair[0].end() >= pair[1].start() {
+            return Err(Error::schema(
+                position,
+                format!("tag {} is duplicated in tag list", pair[0].end()),
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn validate_message(message: &MessageDef, types: &BTreeMap<&str, Named>) -> Result<(), Error> {
+    let name = &message.name;
+    let mut reserved_tags = message.reserved_tags.clone();
+    validate_tag_list(&mut reserved_tags, None, message.position)?;
+
+    let mut field_names = BTreeSet::new();
+    let mut field_name = |field_name: &str, position: Position| {
+        if field_names.insert(field_name.to_string()) {
+            Ok(())
+        } else {
+            Err(Error::schema(
+                position,
+                format!("message {name} has more than one field named {field_name}"),
+            ))
+        }
+    };
+    if message.extensions {
+        field_name("extensions", message.position)?;
+    }
+
+    // Every tag in the message, with the name and position of the field that uses it
+    let mut all_tags: Vec<(u32, &str, Position)> = Vec::new();
+
+    for field in &message.fields {
+        field_name(&field.name, field.position)?;
+        check_field_type(field, &format!("{name}.{}", field.name), false, types)?;
+        if let Type::Named(ty) = &field.ty {
+            if let Some(Named::Enumeration { has_zero: false }) = types.get(ty.as_str()) {
+                return Err(Error::schema(
+                    field.position,
+                    format!(
+                        "field {name}.{} has type {ty}, which has no variant with the value 0; \
+                        it must be optional",
+                        field.name
+                    ),
+                ));
+            }
+        }
+        all_tags.push((field.tag, &field.name, field.position));
+    }
+
+    for oneof in &message.oneofs {
+        field_name(&oneof.name, oneof.position)?;
+        let oneof_name = format!("{name}.{}", oneof.name);
+        let mut tags = oneof.tags.clone();
+        validate_tag_list(&mut tags, Some(100), oneof.position)?;
+        if oneof.variants.is_empty() {
+            return Err(Error::schema(
+                oneof.position,
+                format!("oneof {oneof_name} must have at least one variant"),
+            ));
+        }
+        let mut variant_names = BTreeSet::new();
+        let mut variant_tags = BTreeSet::new();
+        for variant in &oneof.variants {
+            if !variant_names.insert(&variant.name) {
+                return Err(Error::schema(
+                    variant.position,
+                    format!(
+                        "oneof {oneof_name} has more than one variant named {}",
+                        variant.name
+                    ),
+                ));
+            }
+            if !variant_tags.insert(variant.tag) {
+                return Err(Error::schema(
+                    variant.position,
+                    format!(
+                        "invalid oneof {oneof_name}: multiple variants have tag {}",
+                        variant.tag
+                    ),
+                ));
+            }
+            check_field_type(
+                variant,
+                &format!("{oneof_name}.{}", variant.name),
+                true,
+                types,
+            )?;
+        }
+        let declared_tags: BTreeSet<u32> = tags.iter().cloned().flatten().collect();
+        if declared_tags != variant_tags {
+            let describe = |tags: &BTreeSet<u32>| {
+                tags.iter()
+                    .map(u32::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            };
+            return Err(Error::schema(
+                oneof.position,
+                format!(
+                    "tags don't match for oneof field {oneof_name}: declared ({}) but its \
+                    variants have ({})",
+                    describe(&declared_tags),
+                    describe(&variant_tags),
+                ),
+            ));
+        }
+        for tag in declared_tags {
+            all_tags.push((tag, &oneof.name, oneof.position));
+        }
+    }
+
+    all_tags.sort_by_key(|(tag, ..)| *tag);
+    for pair in all_tags.windows(2) {
+        if pair[0].0 == pair[1].0 {
+            return Err(Error::schema(
+                pair[1].2,
+                format!("message {name} has duplicate tag {}", pair[0].0),
+            ));
+        }
+    }
+    for (tag, field_name, position) in all_tags {
+        if reserved_tags.iter().any(|range| range.contains(&tag)) {
+            return Err(Error::schema(
+                position,
+                format!("message {name} field {field_name} has reserved tag {tag}"),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Checks that a field's type only names types that exist, and that its encoding supports it.
+/// Variants of oneofs are always present when they are set, so their types must be encoded as
+/// values.
+fn check_field_type(
+    field: &FieldDef,
+    qualified_name: &str,
+    in_oneof: bool,
+    types: &BTreeMap<&str, Named>,
+) -> Result<(), Error> {
+    let error = |message: String| {
+        Error::schema(
+            field.position,
+            format!("invalid field {qualified_name}: {message}"),
+        )
+    };
+    check_type_names(&field.ty, types).map_err(error)?;
+    let supported = if in_oneof {
+        is_value_encodable(&field.ty, &field.encoding)
+    } else {
+        is_field_encodable(&field.ty, &field.encoding)
+    };
+    if supported {
+        Ok(())
+    } else {
+        Err(error(format!(
+            "type {} is not supported by encoding {}",
+            field.ty, field.encoding
+        )))
+    }
+}
+
+/// Checks that every named type exists, and that the items of sets and the keys of maps can be
+/// ordered.
+fn check_type_names(ty: &Type, types: &BTreeMap<&str, Named>) -> Result<(), String> {
+    match ty {
+        Type::Named(name) if !types.contains_key(name.as_str()) => {
+            return Err(format!("unknown type {name}"));
+        }
+        Type::Optional(inner) | Type::List(inner) => check_type_names(inner, types)?,
+        Type::Set(key) | Type::Map(key, _) => {
+            check_type_names(key, types)?;
+            let orderable = match &**key {
+                Type::F32
+                | Type::F64
+                | Type::Optional(_)
+                | Type::List(_)
+                | Type::Set(_)
+                | Type::Map(..) => false,
+                Type::Named(name) => matches!(types[name.as_str()], Named::Enumeration { .. }),
+                _ => true,
+            };
+            if !orderable {
+                return Err(format!("type {key} cannot be a set item or map key"));
+            }
+            if let Type::Map(_, value) = ty {
+                check_type_names(value, types)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Returns true if a value of the type can be encoded by itself with the encoding, which is
+/// required of the values in oneofs, optional fields, collections, and maps.
+pub(crate) fn is_value_encodable(ty: &Type, encoding: &Encoding) -> bool {
+    use Encoding::*;
+    match (ty, encoding) {
+        (Type::Bool, General | Varint) => true,
+        (Type::U8 | Type::I8, Varint) => true,
+        (Type::U16 | Type::I16, General | Varint) => true,
+        (Type::U32 | Type::I32 | Type::U64 | Type::I64, General | Varint | Fixed) => true,
+        (Type::U128 | Type::I128 | Type::F32 | Type::F64, General | Fixed) => true,
+        (Type::String, General) => true,
+        (Type::Bytes, General | PlainBytes) => true,
+        (Type::Named(_), General) => true,
+        (Type::List(item) | Type::Set(item), Packed(item_encoding)) => {
+            is_value_encodable(item, item_encoding)
+        }
+        (Type::Map(key, value), General) => {
+            is_value_encodable(key, &General) && is_value_encodable(value, &General)
+        }
+        (Type::Map(key, value), Map(key_encoding, value_encoding)) => {
+            is_value_encodable(key, key_encoding) && is_value_encodable(value, value_encoding)
+        }
+        _ => false,
+    }
+}
+
+/// Returns true if a field of the type can be encoded with the encoding.
+pub(crate) fn is_field_encodable(ty: &Type, encoding: &Encoding) -> bool {
+    use Encoding::*;
+    is_value_encodable(ty, encoding)
+        || match (ty, encoding) {
+            (Type::Optional(inner), _) => is_value_encodable(inner, encoding),
+            // Lists are repeated fields with the general, fixed, and plainbytes encodings.
+            (Type::List(item), General | Fixed | PlainBytes) => is_value_encodable(item, encoding),
+            (Type::Set(item), General) => is_value_encodable(item, encoding),
+            (Type::List(item) | Type::Set(item), Unpacked(item_encoding)) => {
+                is_value_encodable(item, item_encoding)
+            }
+            _ => false,
+        }
+}
+
+/// Returns the message that a field of the type always contains, if it is a message type rather
+/// than something that can be empty, such as an optional value or a collection.
+fn required_message<'a>(ty: &'a Type, types: &BTreeMap<&str, Named<'a>>) -> Option<&'a MessageDef> {
+    match ty {
+        Type::Named(name) => match types[name.as_str()] {
+            Named::Message(message) => Some(message),
+            Named::Enumeration { .. } => None,
+        },
+        _ => None,
+    }
+}
+
+/// Checks that a message can't contain itself through a chain of fields that are always present,
+/// which could never be constructed.
+fn check_unbounded_recursion<'a>(
+    message: &'a MessageDef,
+    types: &BTreeMap<&str, Named<'a>>,
+    path: &mut Vec<&'a str>,
+) -> Result<(), Error> {
+    if path.first() == Some(&message.name.as_str()) {
+        return Err(Error::schema(
+            message.position,
+            format!(
+                "message {} contains itself through fields that are never empty ({} -> {}); \
+                one of them must be optional",
+                message.name,
+                path.join(" -> "),
+                message.name,
+            ),
+        ));
+    }
+    if path.contains(&message.name.as_str()) {
+        // This cycle doesn't include the message we started from, and will be reported when
+        // starting from a message that is part of it.
+        return Ok(());
+    }
+    path.push(&message.name);
+    for field in &message.fields {
+        if let Some(nested) = required_message(&field.ty, types) {
+            check_unbounded_recursion(nested, types, path)?;
+        }
+    }
+    path.pop();
+    Ok(())
+}
+
+/// Adds the names of the messages that appear in the type to `names`.
+fn referenced_messages<'a>(ty: &'a Type, types: &BTreeMap<&str, Named>, names: &mut Vec<&'a str>) {
+    match ty {
+        Type::Named(name) => {
+            if let Named::Message(_) = types[name.as_str()] {
+                names.push(name);
+            }
+        }
+        Type::Optional(inner) | Type::List(inner) | Type::Set(inner) => {
+            referenced_messages(inner, types, names)
+        }
+        Type::Map(key, value) => {
+            referenced_messages(key, types, names);
+            referenced_messages(value, types, names);
+        }
+        _ => {}
+    }
+}
+
+/// Checks that a message with extensions doesn't contain itself. The opaque extensions field of a
+/// message can't be borrowed for decoding the way its other fields are, which derived messages only
+/// allow for when the message is not part of a recursion.
+fn check_recursive_extensions(
+    message: &MessageDef,
+    types: &BTreeMap<&str, Named>,
+) -> Result<(), Error> {
+    let mut seen = BTreeSet::new();
+    let mut queue = Vec::new();
+    for field in all_fields(message) {
+        referenced_messages(&field.ty, types, &mut queue);
+    }
+    while let Some(name) = queue.pop() {
+        if name == message.name {
+            return Err(Error::schema(
+                message.position,
+                format!(
+                    "message {} has extensions and contains itself, which is not supported",
+                    message.name
+                ),
+            ));
+        }
+        if seen.insert(name) {
+            if let Named::Message(nested) = types[name] {
+                for field in all_fields(nested) {
+                    referenced_messages(&field.ty, types, &mut queue);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::schema::Schema;
+    use crate::Error;
+
+    #[track_caller]
+    fn assert_rejected(schema: &str, expected: &str) {
+        match Schema::parse(schema) {
+            Err(Error::Schema { message, .. }) => assert_eq!(message, expected),
+            other => panic!("expected schema to be rejected with {expected:?}, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_rejects_colliding_tags() {
+        assert_rejected(
+            "message M { a: u32 = 1; b: string = 1; }",
+            "message M has duplicate tag 1",
+        );
+        assert_rejected(
+            "message M { a: u32 = 2; oneof o(1-2) { X: u32 = 1; Y: u64 = 2; } }",
+            "message M has duplicate tag 2",
+        );
+    }
+
+    #[test]
+    fn test_rejects_reserved_tags() {
+        assert_rejected(
+            "message M { reserved_tags(3, 10-19); a: u32 = 1; b: u32 = 15; }",
+            "message M field b has reserved tag 15",
+        );
+        assert_rejected("message M { reserved_tags(5-1); }", "invalid tag range 5-1");
+        assert_rejected(
+            "message M { reserved_tags(1-3, 3); }",
+            "tag 3 is duplicated in tag list",
+        );
+    }
+
+    #[test]
+    fn test_rejects_mismatched_oneof_tags() {
+        assert_rejected(
+            "message M { oneof o(1-3) { X: u32 = 1; Y: u64 = 2; } }",
+            "tags don't match for oneof field M.o: declared (1, 2, 3) but its variants have (1, 2)",
+        );
+        assert_rejected(
+            "message M { oneof o(1-2) { X: u32 = 1; Y: u64 = 1; } }",
+            "invalid oneof M.o: multiple variants have tag 1",
+        );
+        assert_rejected(
+            "message M { oneof o(1-200) { X: u32 = 1; } }",
+            "too-large tag range 1-200; use smaller ranges",
+        );
+    }
+
+    #[test]
+    fn test_rejects_unsupported_encodings() {
+        assert_rejected(
+            "message M { a: string = 1 encoding(fixed); }",
+            "invalid field M.a: type string is not supported by encoding fixed",
+        );
+        assert_rejected(
+            "message M { a: u8 = 1; }",
+            "invalid field M.a: type u8 is not supported by encoding general",
+        );
+        assert_rejected(
+            "message M { a: list<string> = 1 encoding(packed<varint>); }",
+            "invalid field M.a: type list<string> is not supported by encoding packed<varint>",
+        );
+        assert_rejected(
+            "message M { a: set<f32> = 1; }",
+            "invalid field M.a: type f32 cannot be a set item or map key",
+        );
+        // Oneof variants are always present when they are set, so they can't be collections.
+        assert_rejected(
+            "message M { oneof o(1) { X: list<u32> = 1; } }",
+            "invalid field M.o.X: type list<u32> is not supported by encoding general",
+        );
+    }
+
+    #[test]
+    fn test_rejects_unknown_and_duplicate_names() {
+        assert_rejected(
+            "message M { a: Missing = 1; }",
+            "invalid field M.a: unknown type Missing",
+        );
+        assert_rejected(
+            "message M {} enum M { A = 0; }",
+            "type M is defined more than once",
+        );
+        assert_rejected(
+            "message M { a: u32 = 1; a: u64 = 2; }",
+            "message M has more than one field named a",
+        );
+        assert_rejected(
+            "message M { oneof choice(1) { A: u32 = 1; } } message MChoice {}",
+            "the type generated for this oneof, MChoice, is already defined",
+        );
+        assert_rejected(
+            "enum E { A = 0; B = 0; }",
+            "enumeration E has more than one variant with value 0",
+        );
+        assert_rejected(
+            "message M { self: u32 = 1; }",
+            "`self` cannot be used as a name",
+        );
+    }
+
+    #[test]
+    fn test_rejects_enumerations_without_zero_in_required_fields() {
+        assert!(Schema::parse("enum E { A = 1; } message M { a: optional<E> = 1; }").is_ok());
+        assert!(Schema::parse("enum E { A = 1; } message M { a: E = 1; }").is_err());
+    }
+
+    #[test]
+    fn test_rejects_unbounded_recursion() {
+        assert_rejected(
+            "message A { b: B = 1; } message B { a: A = 1; }",
+            "message A contains itself through fields that are never empty (A -> B -> A); one of \
+            them must be optional",
+        );
+        assert!(Schema::parse("message A { b: B = 1; } message B { a: optional<A> = 1; }").is_ok());
+        assert!(Schema::parse("message A { children: list<A> = 1; }").is_ok());
+    }
+
+    #[test]
+    fn test_rejects_recursive_messages_with_extensions() {
+        assert_rejected(
+            "message A { b: optional<B> = 1; } message B { a: list<A> = 1; extensions; }",
+            "message B has extensions and contains itself, which is not supported",
+        );
+        assert!(Schema::parse("message A { b: B = 1; extensions; } message B {}").is_ok());
+    }
+}
@@ -0,0 +1,118 @@
+//! Tests the `bilrost-compat` command line tool.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+fn write_schema(name: &str, text: &str) -> PathBuf {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("compat");
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(name);
+    std::fs::write(&path, text).unwrap();
+    path
+}
+
+/// Runs the tool and returns its exit code and output.
+fn run(args: &[&Path]) -> (i32, String) {
+    let output = Command::new(env!("CARGO_BIN_EXE_bilrost-compat"))
+        .args(args)
+        .output()
+        .unwrap();
+    let mut text = String::from_utf8(output.stdout).unwrap();
+    text.push_str(&String::from_utf8(output.stderr).unwrap());
+    (output.status.code().unwrap(), text)
+}
+
+#[test]
+fn reports_changes_between_schemas() {
+    let old = write_schema(
+        "old.bilrost",
+        "\
+message Shape {
+    reserved_tags(5);
+    sides: u32 = 1;
+    name: string = 2;
+    color: optional<Color> = 3;
+    label: bytes = 4;
+}
+message Color {
+    red: u16 = 1;
+    green: u32 = 2 encoding(fixed);
+}
+",
+    );
+    let widened = write_schema(
+        "widened.bilrost",
+        "\
+message Shape {
+    reserved_tags(5);
+    sides: u64 = 1;
+    name: bytes = 2;
+    color: optional<Color> = 3;
+    label: optional<bytes> = 4;
+}
+message Color {
+    red: u32 = 1;
+    green: u32 = 2 encoding(fixed);
+}
+",
+    );
+    let broken = write_schema(
+        "broken.bilrost",
+        "\
+message Shape {
+    sides: u32 = 1 encoding(fixed);
+    color: optional<Color> = 3;
+    area: f32 = 5;
+}
+message Color {
+    red: u16 = 1;
+    green: u32 = 2;
+}
+",
+    );
+
+    assert_eq!(run(&[&old, &old]), (0, "compatible\n".to_string()));
+    assert_eq!(
+        run(&[&old, &widened]),
+        (
+            0,
+            "\
+Shape.sides (tag 1): compatible: was widened from u32 to u64
+Shape.name (tag 2): compatible: was widened from string to bytes
+Shape.label (tag 4): compatible: can now be present with an empty value
+Color.red (tag 1): compatible: was widened from u16 to u32
+compatible
+"
+            .to_string()
+        )
+    );
+    let (code, output) = run(&[&old, &broken]);
+    assert_eq!(code, 1);
+    assert!(output.contains("Shape.area (tag 5): breaking: was added with a tag that was reserved"));
+    assert!(output.contains("Color.green (tag 2): breaking"));
+    assert!(output.ends_with("breaking\n"));
+
+    // Only the named messages and the messages in them are checked.
+    let (code, output) = run(&[&old, &broken, Path::new("Color")]);
+    assert_eq!(code, 1);
+    assert!(!output.contains("Shape"));
+
+    let (code, output) = run(&[&old, Path::new("tests/schemas/missing.bilrost")]);
+    assert_eq!(code, 2);
+    assert!(output.starts_with("tests/schemas/missing.bilrost: "));
+    assert_eq!(run(&[&old]).0, 2);
+}
+
+#[test]
+fn distinguished_fails_on_expedient_only_changes() {
+    let old = write_schema(
+        "optional.bilrost",
+        "message M {\n    value: optional<u32> = 1;\n}\n",
+    );
+    let new = write_schema("plain.bilrost", "message M {\n    value: u32 = 1;\n}\n");
+    let (code, output) = run(&[&old, &new]);
+    assert_eq!(code, 0);
+    assert!(output.ends_with("expedient-only\n"));
+    let (code, _) = run(&[Path::new("--distinguished"), &old, &new]);
+    assert_eq!(code, 1);
+}
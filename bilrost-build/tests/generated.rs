@@ -0,0 +1,200 @@
+//! Tests the code generated from the schemas in `tests/schemas`. When the code generator changes,
+//! regenerate the checked-in code with `bilrost_build::generate`.
+
+use bilrost::{DistinguishedMessage, Message, RawMessage};
+use bilrost_build::schema::{EnumerationVariantDef, FieldDef, MessageDef, Schema};
+
+mod example {
+    include!("schemas/example.rs");
+}
+
+use example::*;
+
+#[test]
+fn generated_code_is_current() {
+    let generated = bilrost_build::generate(include_str!("schemas/example.bilrost")).unwrap();
+    assert_eq!(generated, include_str!("schemas/example.rs"));
+}
+
+#[test]
+fn generated_messages_round_trip() {
+    let order = Order {
+        id: 5,
+        customer: "someone".to_string(),
+        status: OrderStatus::Delivered,
+        items: [("widget".to_string(), 2)].into(),
+        weights: vec![10, 20],
+        signature: vec![1, 2, 3],
+        priority: Some(Priority::High),
+        priorities: vec![Priority::Low, Priority::High],
+        small: 200,
+        address: Address {
+            street: "1 Main St".to_string(),
+            city: "Springfield".to_string(),
+            ..Default::default()
+        },
+        shipments: vec![Shipment {
+            weight: 1.5,
+            scores: [(1, vec![0.5, 0.25])].into(),
+            nested: vec![vec![-1, 1], vec![]],
+        }],
+        payment: Some(OrderPayment::Voucher(Address {
+            street: "2 Side St".to_string(),
+            city: String::new(),
+            ..Default::default()
+        })),
+        r#type: 9,
+        total: -(1 << 100),
+        ..Default::default()
+    };
+    let decoded = Order::decode(order.encode_to_vec().as_slice()).unwrap();
+    assert_eq!(decoded, order);
+
+    let node = Node {
+        name: "root".to_string(),
+        parent: None,
+        children: vec![Node {
+            name: "child".to_string(),
+            link: Some(NodeLink::Leaf(Box::new(Leaf {
+                owner: Some(Box::new(Node {
+                    name: "owner".to_string(),
+                    ..Default::default()
+                })),
+            }))),
+            ..Default::default()
+        }],
+        link: None,
+    };
+    let (decoded, canonicity) =
+        Node::decode_distinguished(node.encode_to_vec().as_slice()).unwrap();
+    assert_eq!(decoded, node);
+    assert_eq!(canonicity, bilrost::Canonicity::Canonical);
+}
+
+#[test]
+fn generated_messages_match_schema() {
+    let descriptor = Order::DESCRIPTOR;
+    assert_eq!(descriptor.reserved_tags, [4..=4, 20..=29]);
+    assert!(descriptor.has_extensions);
+    assert_eq!(descriptor.field_by_name("type").unwrap().tag, 30);
+    assert_eq!(
+        descriptor.field_by_tag(6).unwrap().encoding.to_string(),
+        "packed<fixed>"
+    );
+    assert_eq!(descriptor.oneof_for_tag(17).unwrap().name, "payment");
+    assert!(Node::DESCRIPTOR.field_by_name("children").unwrap().recurses);
+}
+
+#[test]
+fn compile_writes_generated_code() {
+    let out_dir = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("compile_schemas");
+    std::fs::create_dir_all(&out_dir).unwrap();
+    bilrost_build::Config::new()
+        .out_dir(&out_dir)
+        .compile(&["tests/schemas/example.bilrost"])
+        .unwrap();
+    assert_eq!(
+        std::fs::read_to_string(out_dir.join("example.rs")).unwrap(),
+        include_str!("schemas/example.rs")
+    );
+
+    let err = bilrost_build::Config::new()
+        .out_dir(&out_dir)
+        .compile(&["tests/schemas/missing.bilrost"])
+        .unwrap_err();
+    assert!(matches!(err, bilrost_build::Error::Io { .. }));
+}
+
+/// Summarizes the parts of a message definition that describe its encoding.
+fn encoding_of(message: &MessageDef) -> impl PartialEq + std::fmt::Debug {
+    let fields = |fields: &[FieldDef]| -> Vec<(String, u32, String, String)> {
+        fields
+            .iter()
+            .map(|field| {
+                (
+                    field.name.clone(),
+                    field.tag,
+                    field.ty.to_string(),
+                    field.encoding.to_string(),
+                )
+            })
+            .collect()
+    };
+    (
+        fields(&message.fields),
+        message
+            .oneofs
+            .iter()
+            .map(|oneof| {
+                (
+                    oneof.name.clone(),
+                    oneof.tags.clone(),
+                    fields(&oneof.variants),
+                )
+            })
+            .collect::<Vec<_>>(),
+        message.reserved_tags.clone(),
+        message.extensions,
+    )
+}
+
+#[test]
+fn exported_schemas_match_original() {
+    let original = Schema::parse(include_str!("schemas/example.bilrost")).unwrap();
+    for exported in [
+        bilrost::schema::export::<Order>(),
+        bilrost::schema::export::<Node>(),
+    ] {
+        let exported = Schema::parse(&exported).unwrap();
+        for message in exported.messages() {
+            let original = original.message(&message.name).unwrap();
+            assert_eq!(encoding_of(message), encoding_of(original));
+        }
+        for enumeration in exported.enumerations() {
+            let original = original.enumeration(&enumeration.name).unwrap();
+            let variants = |variants: &[EnumerationVariantDef]| {
+                variants
+                    .iter()
+                    .map(|variant| (variant.name.clone(), variant.number))
+                    .collect::<Vec<_>>()
+            };
+            assert_eq!(
+                variants(&enumeration.variants),
+                variants(&original.variants)
+            );
+        }
+    }
+    let exported = Schema::parse(&bilrost::schema::export::<Order>()).unwrap();
+    assert_eq!(exported.messages().count(), 3);
+    assert_eq!(exported.enumerations().count(), 2);
+}
+
+#[test]
+fn runtime_descriptors_match_generated_types() {
+    let schema = Schema::parse(include_str!("schemas/example.bilrost")).unwrap();
+    let descriptors = schema.message_descriptors();
+    let names: Vec<_> = descriptors
+        .iter()
+        .map(|descriptor| descriptor.name)
+        .collect();
+    let expected: Vec<_> = schema
+        .messages()
+        .map(|message| message.name.as_str())
+        .collect();
+    assert_eq!(names, expected);
+    for generated in [Order::DESCRIPTOR, Node::DESCRIPTOR] {
+        let built = descriptors
+            .iter()
+            .copied()
+            .find(|descriptor| descriptor.name == generated.name)
+            .unwrap();
+        assert_eq!(
+            bilrost::schema::export_descriptor(built),
+            bilrost::schema::export_descriptor(generated)
+        );
+        assert_eq!(bilrost::compat::check(generated, built).changes, []);
+        for (built, generated) in built.all_fields().zip(generated.all_fields()) {
+            assert_eq!(built.recurses, generated.recurses, "{}", built.name);
+        }
+    }
+}
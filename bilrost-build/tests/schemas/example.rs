@@ -0,0 +1,127 @@
+// This file is @generated by bilrost-build. Do not edit it by hand; edit the schema it was
+// generated from instead.
+
+/// How far along an order is.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, ::bilrost::CanonicalOrd, ::bilrost::Enumeration)]
+pub enum OrderStatus {
+    #[default]
+    #[bilrost(0)]
+    Pending,
+    #[bilrost(1)]
+    Shipped,
+    /// The order arrived.
+    #[bilrost(5)]
+    Delivered,
+}
+
+/// An enumeration with no zero value, which can only be used where a field can be empty.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, ::bilrost::CanonicalOrd, ::bilrost::Enumeration)]
+pub enum Priority {
+    #[bilrost(1)]
+    Low,
+    #[bilrost(2)]
+    High,
+}
+
+/// A customer order.
+#[derive(Clone, Debug, Default, PartialEq, ::bilrost::Message)]
+#[bilrost(reserved_tags(4, 20-29))]
+pub struct Order {
+    #[bilrost(1)]
+    pub id: u64,
+    #[bilrost(2)]
+    pub customer: ::bilrost::alloc::string::String,
+    #[bilrost(3)]
+    pub status: OrderStatus,
+    /// Item quantities, keyed by product.
+    #[bilrost(5)]
+    pub items: ::bilrost::alloc::collections::BTreeMap<::bilrost::alloc::string::String, u32>,
+    #[bilrost(tag(6), encoding(packed<fixed>))]
+    pub weights: ::bilrost::alloc::vec::Vec<u32>,
+    #[bilrost(7)]
+    pub notes: ::bilrost::alloc::vec::Vec<::bilrost::alloc::string::String>,
+    #[bilrost(8)]
+    pub labels: ::bilrost::alloc::collections::BTreeSet<::bilrost::alloc::string::String>,
+    #[bilrost(tag(9), encoding(plainbytes))]
+    pub signature: ::bilrost::alloc::vec::Vec<u8>,
+    #[bilrost(10)]
+    pub attachment: ::bilrost::Blob,
+    #[bilrost(11)]
+    pub priority: ::core::option::Option<Priority>,
+    #[bilrost(tag(12), encoding(packed<general>))]
+    pub priorities: ::bilrost::alloc::vec::Vec<Priority>,
+    #[bilrost(tag(13), encoding(varint))]
+    pub small: u8,
+    #[bilrost(14)]
+    pub address: Address,
+    #[bilrost(15)]
+    pub shipments: ::bilrost::alloc::vec::Vec<Shipment>,
+    #[bilrost(30)]
+    pub r#type: u32,
+    #[bilrost(31)]
+    pub total: i128,
+    /// How the order is paid for.
+    #[bilrost(oneof(16-18))]
+    pub payment: ::core::option::Option<OrderPayment>,
+    #[bilrost(extensions)]
+    pub extensions: ::bilrost::encoding::opaque::OpaqueMessage<'static>,
+}
+
+/// How the order is paid for.
+#[derive(Clone, Debug, PartialEq, Eq, ::bilrost::Oneof, ::bilrost::DistinguishedOneof)]
+pub enum OrderPayment {
+    #[bilrost(16)]
+    Card(::bilrost::alloc::string::String),
+    #[bilrost(tag(17), encoding(fixed))]
+    Account(u64),
+    #[bilrost(18)]
+    Voucher(Address),
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, ::bilrost::Message, ::bilrost::DistinguishedMessage)]
+pub struct Address {
+    #[bilrost(1)]
+    pub street: ::bilrost::alloc::string::String,
+    #[bilrost(2)]
+    pub city: ::bilrost::alloc::string::String,
+    #[bilrost(extensions)]
+    pub extensions: ::bilrost::encoding::opaque::OpaqueMessage<'static>,
+}
+
+/// A message containing floating point values, which can't be decoded in distinguished mode.
+#[derive(Clone, Debug, Default, PartialEq, ::bilrost::Message)]
+pub struct Shipment {
+    #[bilrost(1)]
+    pub weight: f64,
+    #[bilrost(tag(2), encoding(map<general, packed<general>>))]
+    pub scores: ::bilrost::alloc::collections::BTreeMap<u32, ::bilrost::alloc::vec::Vec<f32>>,
+    #[bilrost(tag(3), encoding(unpacked<packed<varint>>))]
+    pub nested: ::bilrost::alloc::vec::Vec<::bilrost::alloc::vec::Vec<i64>>,
+}
+
+/// A tree of messages that contain each other.
+#[derive(Clone, Debug, Default, PartialEq, Eq, ::bilrost::Message, ::bilrost::DistinguishedMessage)]
+pub struct Node {
+    #[bilrost(1)]
+    pub name: ::bilrost::alloc::string::String,
+    #[bilrost(tag(2), recurses)]
+    pub parent: ::core::option::Option<::bilrost::alloc::boxed::Box<Node>>,
+    #[bilrost(tag(3), recurses)]
+    pub children: ::bilrost::alloc::vec::Vec<Node>,
+    #[bilrost(oneof(4-5))]
+    pub link: ::core::option::Option<NodeLink>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, ::bilrost::Oneof, ::bilrost::DistinguishedOneof)]
+pub enum NodeLink {
+    #[bilrost(tag(4), recurses)]
+    Next(::bilrost::alloc::boxed::Box<Node>),
+    #[bilrost(tag(5), recurses)]
+    Leaf(::bilrost::alloc::boxed::Box<Leaf>),
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, ::bilrost::Message, ::bilrost::DistinguishedMessage)]
+pub struct Leaf {
+    #[bilrost(tag(1), recurses)]
+    pub owner: ::core::option::Option<::bilrost::alloc::boxed::Box<Node>>,
+}
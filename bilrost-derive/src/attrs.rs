@@ -1,3 +1,4 @@
+use alloc::string::ToString;
 use alloc::vec::Vec;
 use core::any::type_name;
 use core::ops::RangeInclusive;
@@ -170,3 +171,30 @@ pub fn word_attr(attr: &Meta, key: &str) -> bool {
         false
     }
 }
+
+/// How a field's value is updated when a message is merged into with `Message::merge_from`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MergeMode {
+    /// The field is cleared before the new data is decoded into it.
+    Replace,
+    /// New items are added to the field's existing value.
+    Append,
+    /// The new data is merged into the field's existing value, recursively.
+    Recurse,
+}
+
+/// Parses a `merge = "replace" | "append" | "recurse"` attribute.
+pub fn merge_attr(attr: &Meta) -> Result<Option<MergeMode>, Error> {
+    let Some(mode) = named_attr::<syn::Ident>(attr, "merge")? else {
+        return Ok(None);
+    };
+    Ok(Some(match mode.to_string().as_str() {
+        "replace" => MergeMode::Replace,
+        "append" => MergeMode::Append,
+        "recurse" => MergeMode::Recurse,
+        _ => bail!(
+            "invalid merge attribute, expected \"replace\", \"append\", or \"recurse\": {}",
+            quote!(#attr)
+        ),
+    }))
+}
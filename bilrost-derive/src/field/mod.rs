@@ -10,6 +10,8 @@ use quote::{quote, ToTokens};
 use syn::punctuated::Punctuated;
 use syn::{parse2, Attribute, LitInt, Meta, Token, Type};
 
+use crate::attrs::word_attr;
+
 mod oneof;
 mod value;
 
@@ -86,6 +88,59 @@ impl Field {
         }
     }
 
+    /// Returns the where clause condition asserting that this field's encoder can decode its type.
+    pub fn decoder_where_terms(&self) -> Vec<TokenStream> {
+        match self {
+            Field::Value(field) => field.decoder_where_terms(),
+            Field::Oneof(field) => field.decoder_where_terms(),
+        }
+    }
+
+    /// Returns the where clause condition asserting that this field's encoder can decode its type
+    /// in distinguished mode.
+    pub fn distinguished_decoder_where_terms(&self) -> Vec<TokenStream> {
+        match self {
+            Field::Value(field) => field.distinguished_decoder_where_terms(),
+            Field::Oneof(field) => field.distinguished_decoder_where_terms(),
+        }
+    }
+
+    /// Returns the where clause condition asserting that this field's encoder can decode its type
+    /// from a borrowed buffer.
+    pub fn borrowed_expedient_where_terms(&self) -> Vec<TokenStream> {
+        match self {
+            Field::Value(field) => field.borrowed_expedient_where_terms(),
+            Field::Oneof(field) => field.borrowed_expedient_where_terms(),
+        }
+    }
+
+    /// Returns the where clause condition asserting that this field's encoder can decode its type
+    /// from a borrowed buffer in distinguished mode.
+    pub fn borrowed_distinguished_where_terms(&self) -> Vec<TokenStream> {
+        match self {
+            Field::Value(field) => field.borrowed_distinguished_where_terms(),
+            Field::Oneof(field) => field.borrowed_distinguished_where_terms(),
+        }
+    }
+
+    /// Returns the where clause condition asserting that this field's type has a canonical
+    /// ordering.
+    pub fn canonical_ord_where_terms(&self) -> Vec<TokenStream> {
+        match self {
+            Field::Value(field) => field.canonical_ord_where_terms(),
+            Field::Oneof(field) => field.canonical_ord_where_terms(),
+        }
+    }
+
+    /// Returns the type of the field if it recurses, and so must be checked for having a canonical
+    /// ordering somewhere other than the where clause.
+    pub fn canonical_ord_recursive_type(&self) -> Option<&Type> {
+        match self {
+            Field::Value(field) => field.canonical_ord_recursive_type(),
+            Field::Oneof(_) => None,
+        }
+    }
+
     pub fn tag_list_guard(&self, field_name: String) -> Option<TokenStream> {
         match self {
             Field::Oneof(field) => {
@@ -131,6 +186,14 @@ impl Field {
         }
     }
 
+    /// Returns a statement which prepends the field to the front of a reverse buffer.
+    pub fn prepend(&self, ident: TokenStream) -> TokenStream {
+        match self {
+            Field::Value(scalar) => scalar.prepend(ident),
+            Field::Oneof(oneof) => oneof.prepend(ident),
+        }
+    }
+
     /// Returns an expression which evaluates to the result of decoding a value into the field.
     pub fn decode_expedient(&self, ident: TokenStream) -> TokenStream {
         match self {
@@ -139,6 +202,43 @@ impl Field {
         }
     }
 
+    /// Returns an expression which evaluates to the result of merging a decoded value into the
+    /// field with `Message::merge_from`, if that is different from decoding it normally. Oneof
+    /// fields are identified by their index among the message's oneofs.
+    pub fn merge_expedient(&self, ident: TokenStream, oneof_index: usize) -> Option<TokenStream> {
+        match self {
+            Field::Value(scalar) => scalar.merge_expedient(ident),
+            Field::Oneof(oneof) => Some(oneof.merge_expedient(ident, oneof_index)),
+        }
+    }
+
+    /// Returns a statement which writes the changes from the field in `ident` to the field in
+    /// `new_ident` to the patch writer `pw`.
+    pub fn diff(&self, ident: TokenStream, new_ident: TokenStream) -> TokenStream {
+        match self {
+            Field::Value(scalar) => scalar.diff(ident, new_ident),
+            Field::Oneof(oneof) => oneof.diff(ident, new_ident),
+        }
+    }
+
+    /// Returns an expression which evaluates to the result of applying the patch operation `op`
+    /// to the field.
+    pub fn apply(&self, ident: TokenStream) -> TokenStream {
+        match self {
+            Field::Value(scalar) => scalar.apply(ident),
+            Field::Oneof(oneof) => oneof.apply(ident),
+        }
+    }
+
+    /// Returns an expression which evaluates to the field's descriptor, given an expression for its
+    /// name. Value fields are described by a `FieldDescriptor` and oneofs by a `OneofDescriptor`.
+    pub fn descriptor(&self, name: TokenStream) -> TokenStream {
+        match self {
+            Field::Value(scalar) => scalar.descriptor(name),
+            Field::Oneof(oneof) => oneof.descriptor(name),
+        }
+    }
+
     /// Returns an expression which evaluates to the result of decoding a value into the field in
     /// distinguished mode.
     pub fn decode_distinguished(&self, ident: TokenStream) -> TokenStream {
@@ -148,6 +248,24 @@ impl Field {
         }
     }
 
+    /// Returns an expression which evaluates to the result of decoding a value from a borrowed
+    /// buffer into the field.
+    pub fn borrow_decode_expedient(&self, ident: TokenStream) -> TokenStream {
+        match self {
+            Field::Value(scalar) => scalar.borrow_decode_expedient(ident),
+            Field::Oneof(oneof) => oneof.borrow_decode_expedient(ident),
+        }
+    }
+
+    /// Returns an expression which evaluates to the result of decoding a value from a borrowed
+    /// buffer into the field in distinguished mode.
+    pub fn borrow_decode_distinguished(&self, ident: TokenStream) -> TokenStream {
+        match self {
+            Field::Value(scalar) => scalar.borrow_decode_distinguished(ident),
+            Field::Oneof(oneof) => oneof.borrow_decode_distinguished(ident),
+        }
+    }
+
     /// Returns an expression which evaluates to the encoded length of the field.
     pub fn encoded_len(&self, ident: TokenStream) -> TokenStream {
         match self {
@@ -173,6 +291,19 @@ impl Field {
     }
 }
 
+/// Checks whether a message field is marked as the extensions field of the message, which captures
+/// all the fields that the message itself does not know.
+pub fn is_extensions_field(attrs: &[Attribute]) -> Result<bool, Error> {
+    let attrs = bilrost_attrs(attrs.to_vec())?;
+    if !attrs.iter().any(|attr| word_attr(attr, "extensions")) {
+        return Ok(false);
+    }
+    if attrs.len() > 1 {
+        bail!("extensions attribute mixed with other attributes on the same field");
+    }
+    Ok(true)
+}
+
 /// Get the items belonging to the 'bilrost' list attribute, e.g. `#[bilrost(foo, bar="baz")]`.
 pub(crate) fn bilrost_attrs(attrs: Vec<Attribute>) -> Result<Vec<Meta>, Error> {
     let mut result = Vec::new();
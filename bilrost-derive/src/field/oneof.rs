@@ -6,23 +6,28 @@ use proc_macro2::TokenStream;
 use quote::quote;
 use syn::{Meta, Type};
 
-use crate::attrs::tag_list_attr;
+use crate::attrs::{merge_attr, tag_list_attr, MergeMode};
 use crate::field::set_option;
 
 #[derive(Clone)]
 pub struct Field {
     pub ty: Type,
     pub tags: Vec<u32>,
+    /// How the oneof is updated when merging with `Message::merge_from`, if it was specified.
+    pub merge: Option<MergeMode>,
 }
 
 impl Field {
     pub fn new(ty: &Type, attrs: &[Meta]) -> Result<Option<Field>, Error> {
         let mut oneof_tags = None;
+        let mut merge = None;
         let mut unknown_attrs = Vec::new();
 
         for attr in attrs {
             if let Some(tags) = tag_list_attr("oneof", Some(100), attr)? {
                 set_option(&mut oneof_tags, tags, "duplicate oneof attribute")?;
+            } else if let Some(m) = merge_attr(attr)? {
+                set_option(&mut merge, m, "duplicate merge attributes")?;
             } else {
                 unknown_attrs.push(attr);
             }
@@ -39,9 +44,14 @@ impl Field {
             );
         }
 
+        if merge == Some(MergeMode::Append) {
+            bail!("oneof fields cannot be merged with \"append\"");
+        }
+
         Ok(Some(Field {
             ty: ty.clone(),
             tags: tags.iter_tags().collect(),
+            merge,
         }))
     }
 
@@ -52,10 +62,51 @@ impl Field {
         }
     }
 
+    /// Returns a statement which writes the changes from the oneof field in `ident` to the oneof
+    /// field in `new_ident` to the patch writer `pw`.
+    pub fn diff(&self, ident: TokenStream, new_ident: TokenStream) -> TokenStream {
+        quote! {
+            pw.diff_oneof(&#ident, &#new_ident);
+        }
+    }
+
+    /// Returns an expression which evaluates to the result of applying the patch operation `op`
+    /// to the oneof field. Oneofs are only ever patched whole.
+    pub fn apply(&self, ident: TokenStream) -> TokenStream {
+        quote! {
+            if rest.is_empty() {
+                ::bilrost::patch::apply_to_oneof(#ident, tag, op)
+            } else {
+                ::core::result::Result::Err(::bilrost::DecodeError::new(
+                    ::bilrost::DecodeErrorKind::InvalidPatch,
+                ))
+            }
+        }
+    }
+
+    /// Returns an expression which evaluates to the oneof field's `OneofDescriptor`, given an
+    /// expression for its name.
+    pub fn descriptor(&self, name: TokenStream) -> TokenStream {
+        let ty = &self.ty;
+        quote! {
+            ::bilrost::descriptor::OneofDescriptor {
+                name: #name,
+                fields: <#ty as ::bilrost::encoding::Oneof>::FIELDS,
+            }
+        }
+    }
+
+    /// Returns a statement which prepends the oneof field to the front of a reverse buffer.
+    pub fn prepend(&self, ident: TokenStream) -> TokenStream {
+        quote! {
+            ::bilrost::encoding::Oneof::oneof_prepend(&#ident, buf, tw);
+        }
+    }
+
     /// Returns an expression which evaluates to the result of decoding the oneof field.
     pub fn decode_expedient(&self, ident: TokenStream) -> TokenStream {
         quote!(
-            ::bilrost::encoding::Oneof::oneof_decode_field(
+            ::bilrost::encoding::OneofDecoder::oneof_decode_field(
                 #ident,
                 tag,
                 wire_type,
@@ -66,10 +117,43 @@ impl Field {
         )
     }
 
+    /// Returns an expression which evaluates to the result of merging a decoded value into the
+    /// oneof field with `Message::merge_from`. The oneof is replaced by the first of its fields
+    /// that is present in the data; with `merge = "recurse"`, that field is instead merged into
+    /// the oneof's existing value if it already has the same field. `index` identifies the oneof
+    /// among the message's oneofs.
+    pub fn merge_expedient(&self, ident: TokenStream, index: usize) -> TokenStream {
+        let decode = self.decode_expedient(ident.clone());
+        let ctx = if self.merge == Some(MergeMode::Recurse) {
+            quote! {
+                if tracker.first_sighting(#index)
+                    && ::bilrost::encoding::Oneof::oneof_current_tag(&*#ident)
+                        != ::core::option::Option::Some(tag)
+                {
+                    ::bilrost::encoding::EmptyState::clear(&mut *#ident);
+                    ctx.without_merging()
+                } else {
+                    ctx
+                }
+            }
+        } else {
+            quote! {
+                if tracker.first_sighting(#index) {
+                    ::bilrost::encoding::EmptyState::clear(&mut *#ident);
+                }
+                ctx.without_merging()
+            }
+        };
+        quote!({
+            let ctx = { #ctx };
+            #decode
+        })
+    }
+
     /// Returns an expression which evaluates to the result of decoding the oneof field.
     pub fn decode_distinguished(&self, ident: TokenStream) -> TokenStream {
         quote!(
-            ::bilrost::encoding::DistinguishedOneof::oneof_decode_field_distinguished(
+            ::bilrost::encoding::DistinguishedOneofDecoder::oneof_decode_field_distinguished(
                 #ident,
                 tag,
                 wire_type,
@@ -80,6 +164,37 @@ impl Field {
         )
     }
 
+    /// Returns an expression which evaluates to the result of decoding the oneof field from a
+    /// borrowed buffer.
+    pub fn borrow_decode_expedient(&self, ident: TokenStream) -> TokenStream {
+        quote!(
+            ::bilrost::encoding::OneofBorrowDecoder::<'__a>::oneof_borrow_decode_field(
+                #ident,
+                tag,
+                wire_type,
+                duplicated,
+                buf,
+                ctx,
+            )
+        )
+    }
+
+    /// Returns an expression which evaluates to the result of decoding the oneof field from a
+    /// borrowed buffer in distinguished mode.
+    pub fn borrow_decode_distinguished(&self, ident: TokenStream) -> TokenStream {
+        quote!(
+            ::bilrost::encoding::DistinguishedOneofBorrowDecoder::<'__a>
+                ::oneof_borrow_decode_field_distinguished(
+                    #ident,
+                    tag,
+                    wire_type,
+                    duplicated,
+                    buf,
+                    ctx,
+                )
+        )
+    }
+
     /// Returns an expression which evaluates to the encoded length of the oneof field.
     pub fn encoded_len(&self, ident: TokenStream) -> TokenStream {
         quote!(::bilrost::encoding::Oneof::oneof_encoded_len(&#ident, tm))
@@ -102,4 +217,37 @@ impl Field {
         let ty = &self.ty;
         vec![quote!(#ty: ::bilrost::encoding::DistinguishedOneof)]
     }
+
+    /// Returns the where clause constraint term for the field being decodable.
+    pub fn decoder_where_terms(&self) -> Vec<TokenStream> {
+        let ty = &self.ty;
+        vec![quote!(#ty: ::bilrost::encoding::OneofDecoder)]
+    }
+
+    /// Returns the where clause constraint term for the field being decodable in distinguished
+    /// mode.
+    pub fn distinguished_decoder_where_terms(&self) -> Vec<TokenStream> {
+        let ty = &self.ty;
+        vec![quote!(#ty: ::bilrost::encoding::DistinguishedOneofDecoder)]
+    }
+
+    /// Returns the where clause constraint term for the field being decodable from a borrowed
+    /// buffer.
+    pub fn borrowed_expedient_where_terms(&self) -> Vec<TokenStream> {
+        let ty = &self.ty;
+        vec![quote!(#ty: ::bilrost::encoding::OneofBorrowDecoder<'__a>)]
+    }
+
+    /// Returns the where clause constraint term for the field being decodable from a borrowed
+    /// buffer in distinguished mode.
+    pub fn borrowed_distinguished_where_terms(&self) -> Vec<TokenStream> {
+        let ty = &self.ty;
+        vec![quote!(#ty: ::bilrost::encoding::DistinguishedOneofBorrowDecoder<'__a>)]
+    }
+
+    /// Returns the where clause constraint term for the field having a canonical ordering.
+    pub fn canonical_ord_where_terms(&self) -> Vec<TokenStream> {
+        let ty = &self.ty;
+        vec![quote!(#ty: ::bilrost::encoding::CanonicalOrd)]
+    }
 }
@@ -8,7 +8,7 @@ use proc_macro2::{Ident, Span, TokenStream};
 use quote::quote;
 use syn::{parse_str, Index, Meta, Type};
 
-use crate::attrs::{named_attr, tag_attr, word_attr};
+use crate::attrs::{merge_attr, named_attr, tag_attr, word_attr, MergeMode};
 use crate::field::{set_bool, set_option};
 
 /// A scalar protobuf field.
@@ -28,6 +28,8 @@ pub struct Field {
     /// be checked in the `where` clause of the implementation, and the type must always be
     /// supported by its encoder.
     pub recurses: bool,
+    /// How the field is updated when merging with `Message::merge_from`, if it was specified.
+    pub merge: Option<MergeMode>,
     /// When a value field is in a oneof, it must always encode a nonzero amount of data. The
     /// encoder must be a ValueEncoder to satisfy this; effectively, Oneof types are much like
     /// several fields whose values are each wrapped in an `Option`, but at most one of them can be
@@ -70,6 +72,7 @@ impl Field {
         let mut encoding = None;
         let mut enumeration_ty = None;
         let mut recurses = false;
+        let mut merge = None;
         let mut ignore = false;
         let mut unknown_attrs = Vec::new();
 
@@ -82,6 +85,8 @@ impl Field {
                 set_option(&mut enumeration_ty, t, "duplicate enumeration attributes")?;
             } else if word_attr(attr, "recurses") {
                 set_bool(&mut recurses, "duplicate recurses attributes")?;
+            } else if let Some(m) = merge_attr(attr)? {
+                set_option(&mut merge, m, "duplicate merge attributes")?;
             } else if word_attr(attr, "ignore") {
                 set_bool(&mut ignore, "duplicate ignore attributes")?;
             } else {
@@ -97,13 +102,19 @@ impl Field {
         }
 
         if ignore {
-            if let (None, None, None, false) = (tag, encoding, enumeration_ty, recurses) {
+            if let (None, None, None, false, None) =
+                (tag, encoding, enumeration_ty, recurses, merge)
+            {
                 return Ok(None);
             } else {
                 bail!("ignore attribute mixed with other attributes on the same field");
             }
         }
 
+        if in_oneof && merge.is_some() {
+            bail!("merge attribute is not supported on fields within a oneof");
+        }
+
         let tag = match tag.or(inferred_tag) {
             Some(tag) => tag,
             None => bail!("missing tag attribute"),
@@ -111,12 +122,36 @@ impl Field {
 
         let encoding = encoding.unwrap_or(parse_str::<Type>("general")?);
 
+        // The standard encodings that can never hold the kind of value a merge mode applies to are
+        // rejected here; other fields are checked against their value kind in the generated code.
+        let encoding_name = match &encoding {
+            Type::Path(path) if path.qself.is_none() => {
+                path.path.segments.last().map(|s| s.ident.to_string())
+            }
+            _ => None,
+        };
+        match (merge, encoding_name.as_deref()) {
+            (Some(MergeMode::Append), Some(name @ ("fixed" | "varint" | "plainbytes"))) => bail!(
+                "merge = \"append\" cannot be used with the {name} encoding; it only applies to \
+                collection and map fields"
+            ),
+            (
+                Some(MergeMode::Recurse),
+                Some(name @ ("fixed" | "varint" | "plainbytes" | "packed" | "unpacked" | "map")),
+            ) => bail!(
+                "merge = \"recurse\" cannot be used with the {name} encoding; it only applies to \
+                nested message fields"
+            ),
+            _ => {}
+        }
+
         Ok(Some(Field {
             tag,
             ty: ty.clone(),
             encoding,
             enumeration_ty,
             recurses,
+            merge,
             in_oneof,
             ident_within_variant,
         }))
@@ -157,6 +192,33 @@ impl Field {
         }
     }
 
+    /// Returns a statement which prepends the field to the front of reverse buffer `buf` using
+    /// reverse tag writer `tw`.
+    pub fn prepend(&self, ident: TokenStream) -> TokenStream {
+        let tag = self.tag;
+        let encoder = &self.encoding;
+        let ty = &self.ty;
+        if self.in_oneof {
+            quote! {
+                <#ty as ::bilrost::encoding::FieldEncoder<#encoder>>::prepend_field(
+                    #tag,
+                    &#ident,
+                    buf,
+                    tw,
+                );
+            }
+        } else {
+            quote! {
+                <#ty as ::bilrost::encoding::Encoder<#encoder>>::prepend_encode(
+                    #tag,
+                    &#ident,
+                    buf,
+                    tw,
+                );
+            }
+        }
+    }
+
     /// Returns an expression which evaluates to the result of merging a decoded value into the
     /// field. The given ident must be an &mut that already refers to the destination.
     pub fn decode_expedient(&self, ident: TokenStream) -> TokenStream {
@@ -164,7 +226,7 @@ impl Field {
         let ty = &self.ty;
         if self.in_oneof {
             quote!(
-                <#ty as ::bilrost::encoding::FieldEncoder<#encoder>>::decode_field(
+                <#ty as ::bilrost::encoding::FieldDecoder<#encoder>>::decode_field(
                     wire_type,
                     #ident,
                     buf,
@@ -173,7 +235,7 @@ impl Field {
             )
         } else {
             quote!(
-                <#ty as ::bilrost::encoding::Encoder<#encoder>>::decode(
+                <#ty as ::bilrost::encoding::Decoder<#encoder>>::decode(
                     wire_type,
                     duplicated,
                     #ident,
@@ -184,6 +246,89 @@ impl Field {
         }
     }
 
+    /// Returns an expression which evaluates to the result of merging a decoded value into the
+    /// field with `Message::merge_from`, if that is different from decoding it normally. The given
+    /// ident must be an &mut that already refers to the destination.
+    pub fn merge_expedient(&self, ident: TokenStream) -> Option<TokenStream> {
+        let encoder = &self.encoding;
+        let ty = &self.ty;
+        let decode = self.decode_expedient(ident.clone());
+        // "append" and "recurse" are how collections and messages are already merged, so they
+        // only need to check that the field holds that kind of value.
+        let check = match self.merge? {
+            MergeMode::Replace => {
+                return Some(quote!({
+                    if !duplicated {
+                        ::bilrost::encoding::EmptyState::clear(&mut *#ident);
+                    }
+                    let ctx = ctx.without_merging();
+                    #decode
+                }))
+            }
+            MergeMode::Append => quote!(APPEND),
+            MergeMode::Recurse => quote!(RECURSE),
+        };
+        Some(quote!({
+            let () = ::bilrost::encoding::MergeModeCheck::<#ty, #encoder>::#check;
+            #decode
+        }))
+    }
+
+    /// Returns a statement which writes the changes from the field in `ident` to the field in
+    /// `new_ident` to the patch writer `pw`.
+    pub fn diff(&self, ident: TokenStream, new_ident: TokenStream) -> TokenStream {
+        let tag = self.tag;
+        let encoder = &self.encoding;
+        let ty = &self.ty;
+        quote! {
+            <#ty as ::bilrost::encoding::Encoder<#encoder>>::diff_field(
+                #tag,
+                &#ident,
+                &#new_ident,
+                pw,
+            );
+        }
+    }
+
+    /// Returns an expression which evaluates to the result of applying the patch operation `op`
+    /// to the field, where `rest` is the remainder of the operation's path after the field's tag.
+    /// The given ident must be an &mut that already refers to the destination.
+    pub fn apply(&self, ident: TokenStream) -> TokenStream {
+        let tag = self.tag;
+        let encoder = &self.encoding;
+        let ty = &self.ty;
+        quote! {
+            if rest.is_empty() {
+                ::bilrost::patch::apply_to_field::<#ty, #encoder>(#ident, #tag, op)
+            } else {
+                <#ty as ::bilrost::encoding::Decoder<#encoder>>::apply_nested(#ident, rest, op)
+            }
+        }
+    }
+
+    /// Returns an expression which evaluates to the field's `FieldDescriptor`, given an
+    /// expression for its name.
+    pub fn descriptor(&self, name: TokenStream) -> TokenStream {
+        let tag = self.tag;
+        let encoder = &self.encoding;
+        let ty = &self.ty;
+        let recurses = self.recurses;
+        let value = if self.in_oneof {
+            quote!(<#ty as ::bilrost::encoding::ValueEncoder<#encoder>>::VALUE_KIND)
+        } else {
+            quote!(<#ty as ::bilrost::encoding::Encoder<#encoder>>::VALUE_KIND)
+        };
+        quote! {
+            ::bilrost::descriptor::FieldDescriptor {
+                name: #name,
+                tag: #tag,
+                encoding: <#encoder as ::bilrost::descriptor::DescribedEncoding>::DESCRIPTOR,
+                value: #value,
+                recurses: #recurses,
+            }
+        }
+    }
+
     /// Returns an expression which evaluates to the result of decoding a value into the field in
     /// distinguished mode. The given ident must be an &mut that already refers to the destination.
     pub fn decode_distinguished(&self, ident: TokenStream) -> TokenStream {
@@ -192,7 +337,7 @@ impl Field {
         if self.in_oneof {
             quote!(
                 <
-                    #ty as ::bilrost::encoding::DistinguishedFieldEncoder<#encoder>
+                    #ty as ::bilrost::encoding::DistinguishedFieldDecoder<#encoder>
                 >::decode_field_distinguished(
                     wire_type,
                     #ident,
@@ -203,7 +348,70 @@ impl Field {
             )
         } else {
             quote!(
-                <#ty as ::bilrost::encoding::DistinguishedEncoder<#encoder>>::decode_distinguished(
+                <#ty as ::bilrost::encoding::DistinguishedDecoder<#encoder>>::decode_distinguished(
+                    wire_type,
+                    duplicated,
+                    #ident,
+                    buf,
+                    ctx,
+                )
+            )
+        }
+    }
+
+    /// Returns an expression which evaluates to the result of merging a value decoded from a
+    /// borrowed buffer into the field. The given ident must be an &mut that already refers to the
+    /// destination.
+    pub fn borrow_decode_expedient(&self, ident: TokenStream) -> TokenStream {
+        let encoder = &self.encoding;
+        let ty = &self.ty;
+        if self.in_oneof {
+            quote!(
+                <
+                    #ty as ::bilrost::encoding::FieldBorrowDecoder<'__a, #encoder>
+                >::borrow_decode_field(
+                    wire_type,
+                    #ident,
+                    buf,
+                    ctx,
+                )
+            )
+        } else {
+            quote!(
+                <#ty as ::bilrost::encoding::BorrowDecoder<'__a, #encoder>>::borrow_decode(
+                    wire_type,
+                    duplicated,
+                    #ident,
+                    buf,
+                    ctx,
+                )
+            )
+        }
+    }
+
+    /// Returns an expression which evaluates to the result of decoding a value from a borrowed
+    /// buffer into the field in distinguished mode. The given ident must be an &mut that already
+    /// refers to the destination.
+    pub fn borrow_decode_distinguished(&self, ident: TokenStream) -> TokenStream {
+        let encoder = &self.encoding;
+        let ty = &self.ty;
+        if self.in_oneof {
+            quote!(
+                <
+                    #ty as ::bilrost::encoding::DistinguishedFieldBorrowDecoder<'__a, #encoder>
+                >::borrow_decode_field_distinguished(
+                    wire_type,
+                    #ident,
+                    buf,
+                    true, // Allow empty values: oneof field values are nested
+                    ctx,
+                )
+            )
+        } else {
+            quote!(
+                <
+                    #ty as ::bilrost::encoding::DistinguishedBorrowDecoder<'__a, #encoder>
+                >::borrow_decode_distinguished(
                     wire_type,
                     duplicated,
                     #ident,
@@ -276,6 +484,106 @@ impl Field {
         }
     }
 
+    /// Returns the where clause constraint terms for the field's encoder when decoding.
+    pub fn decoder_where_terms(&self) -> Vec<TokenStream> {
+        if self.recurses {
+            return vec![];
+        }
+        let ty = &self.ty;
+        let encoder = &self.encoding;
+        if self.in_oneof {
+            vec![
+                quote!(#ty: ::bilrost::encoding::ValueDecoder<#encoder>),
+                quote!(#ty: ::bilrost::encoding::NewForOverwrite),
+            ]
+        } else {
+            vec![
+                quote!(#ty: ::bilrost::encoding::Decoder<#encoder>),
+                quote!(#ty: ::bilrost::encoding::EmptyState),
+            ]
+        }
+    }
+
+    /// Returns the where clause constraint terms for the field's encoder when decoding in
+    /// distinguished mode.
+    pub fn distinguished_decoder_where_terms(&self) -> Vec<TokenStream> {
+        if self.recurses {
+            return vec![];
+        }
+        let ty = &self.ty;
+        let encoder = &self.encoding;
+        if self.in_oneof {
+            vec![
+                quote!(#ty: ::bilrost::encoding::DistinguishedValueDecoder<#encoder>),
+                quote!(#ty: ::bilrost::encoding::NewForOverwrite),
+                quote!(#ty: ::core::cmp::Eq),
+            ]
+        } else {
+            vec![
+                quote!(#ty: ::bilrost::encoding::DistinguishedDecoder<#encoder>),
+                quote!(#ty: ::bilrost::encoding::EmptyState),
+            ]
+        }
+    }
+
+    /// Returns the where clause constraint terms for the field's encoder when decoding from a
+    /// borrowed buffer.
+    pub fn borrowed_expedient_where_terms(&self) -> Vec<TokenStream> {
+        if self.recurses {
+            return vec![];
+        }
+        let ty = &self.ty;
+        let encoder = &self.encoding;
+        if self.in_oneof {
+            vec![
+                quote!(#ty: ::bilrost::encoding::ValueBorrowDecoder<'__a, #encoder>),
+                quote!(#ty: ::bilrost::encoding::NewForOverwrite),
+            ]
+        } else {
+            vec![
+                quote!(#ty: ::bilrost::encoding::BorrowDecoder<'__a, #encoder>),
+                quote!(#ty: ::bilrost::encoding::EmptyState),
+            ]
+        }
+    }
+
+    /// Returns the where clause constraint terms for the field's encoder when decoding from a
+    /// borrowed buffer in distinguished mode.
+    pub fn borrowed_distinguished_where_terms(&self) -> Vec<TokenStream> {
+        if self.recurses {
+            return vec![];
+        }
+        let ty = &self.ty;
+        let encoder = &self.encoding;
+        if self.in_oneof {
+            vec![
+                quote!(#ty: ::bilrost::encoding::DistinguishedValueBorrowDecoder<'__a, #encoder>),
+                quote!(#ty: ::bilrost::encoding::NewForOverwrite),
+                quote!(#ty: ::core::cmp::Eq),
+            ]
+        } else {
+            vec![
+                quote!(#ty: ::bilrost::encoding::DistinguishedBorrowDecoder<'__a, #encoder>),
+                quote!(#ty: ::bilrost::encoding::EmptyState),
+            ]
+        }
+    }
+
+    /// Returns the where clause constraint terms for the field's type having a canonical ordering.
+    pub fn canonical_ord_where_terms(&self) -> Vec<TokenStream> {
+        if self.recurses {
+            return vec![];
+        }
+        let ty = &self.ty;
+        vec![quote!(#ty: ::bilrost::encoding::CanonicalOrd)]
+    }
+
+    /// Returns the type of the field if it recurses, and so must be checked for having a canonical
+    /// ordering somewhere other than the where clause.
+    pub fn canonical_ord_recursive_type(&self) -> Option<&Type> {
+        self.recurses.then_some(&self.ty)
+    }
+
     /// Returns methods to embed in the message. `ident` must be the name of the field within the
     /// message struct.
     pub fn methods(&self, ident: &TokenStream) -> Option<TokenStream> {
@@ -25,13 +25,13 @@ use itertools::Itertools;
 use proc_macro2::{Span, TokenStream};
 use quote::{quote, ToTokens};
 use syn::{
-    parse2, Attribute, Data, DataEnum, DataStruct, DeriveInput, Expr, Fields, FieldsNamed,
-    FieldsUnnamed, Ident, ImplGenerics, Index, Meta, MetaList, MetaNameValue, TypeGenerics,
-    Variant, WhereClause,
+    parse2, parse_quote, Attribute, Data, DataEnum, DataStruct, DeriveInput, Expr, Fields,
+    FieldsNamed, FieldsUnnamed, Generics, Ident, ImplGenerics, Index, Meta, MetaList,
+    MetaNameValue, Type, TypeGenerics, Variant, WhereClause,
 };
 
 use crate::attrs::{tag_list_attr, TagList};
-use crate::field::{bilrost_attrs, set_option, Field};
+use crate::field::{bilrost_attrs, is_extensions_field, set_option, Field};
 
 mod attrs;
 mod field;
@@ -108,6 +108,9 @@ struct PreprocessedMessage<'a> {
     where_clause: Option<&'a WhereClause>,
     unsorted_fields: Vec<(TokenStream, Field)>,
     has_ignored_fields: bool,
+    /// The field, if any, that captures all the fields unknown to the message.
+    extensions: Option<(TokenStream, Type)>,
+    reserved_tags: TagList,
 }
 
 fn preprocess_message(input: &DeriveInput) -> Result<PreprocessedMessage, Error> {
@@ -163,6 +166,7 @@ fn preprocess_message(input: &DeriveInput) -> Result<PreprocessedMessage, Error>
 
     let mut next_tag = Some(1);
     let mut has_ignored_fields = false;
+    let mut extensions: Option<(TokenStream, Type)> = None;
     let unsorted_fields: Vec<(TokenStream, Field)> = fields
         .into_iter()
         .enumerate()
@@ -174,6 +178,25 @@ fn preprocess_message(input: &DeriveInput) -> Result<PreprocessedMessage, Error>
                 };
                 quote!(#index)
             });
+            match is_extensions_field(&field.attrs) {
+                Ok(true) => {
+                    if let Some((existing_ident, _)) = &extensions {
+                        return Some(Err(anyhow!(
+                            "message {ident} has multiple extensions fields: {existing_ident} and \
+                            {field_ident}"
+                        )));
+                    }
+                    extensions = Some((field_ident, field.ty));
+                    return None;
+                }
+                Ok(false) => {}
+                Err(err) => {
+                    return Some(Err(err.context(format!(
+                        "invalid message field {}.{}",
+                        ident, field_ident
+                    ))))
+                }
+            }
             match Field::new(field.ty, field.attrs, next_tag) {
                 Ok(Some(field)) => {
                     next_tag = field.last_tag().checked_add(1);
@@ -221,6 +244,8 @@ fn preprocess_message(input: &DeriveInput) -> Result<PreprocessedMessage, Error>
         where_clause,
         unsorted_fields,
         has_ignored_fields,
+        extensions,
+        reserved_tags,
     })
 }
 
@@ -388,6 +413,114 @@ fn append_distinguished_encoder_wheres<T>(
     )
 }
 
+fn append_expedient_decoder_wheres<T>(
+    where_clause: Option<&WhereClause>,
+    self_where: Option<TokenStream>,
+    fields: &[(T, Field)],
+) -> TokenStream {
+    impl_append_wheres(
+        where_clause,
+        self_where,
+        fields
+            .iter()
+            .flat_map(|(_, field)| field.decoder_where_terms()),
+    )
+}
+
+fn append_distinguished_decoder_wheres<T>(
+    where_clause: Option<&WhereClause>,
+    self_where: Option<TokenStream>,
+    fields: &[(T, Field)],
+) -> TokenStream {
+    impl_append_wheres(
+        where_clause,
+        self_where,
+        fields
+            .iter()
+            .flat_map(|(_, field)| field.distinguished_decoder_where_terms()),
+    )
+}
+
+fn append_borrowed_expedient_encoder_wheres<T>(
+    where_clause: Option<&WhereClause>,
+    self_where: Option<TokenStream>,
+    fields: &[(T, Field)],
+) -> TokenStream {
+    impl_append_wheres(
+        where_clause,
+        self_where,
+        fields
+            .iter()
+            .flat_map(|(_, field)| field.borrowed_expedient_where_terms()),
+    )
+}
+
+fn append_borrowed_distinguished_encoder_wheres<T>(
+    where_clause: Option<&WhereClause>,
+    self_where: Option<TokenStream>,
+    fields: &[(T, Field)],
+) -> TokenStream {
+    impl_append_wheres(
+        where_clause,
+        self_where,
+        fields
+            .iter()
+            .flat_map(|(_, field)| field.borrowed_distinguished_where_terms()),
+    )
+}
+
+/// Returns the impl generics for implementing a trait that decodes by borrowing from its input,
+/// which are those of the type plus the lifetime `'__a` of the borrowed input.
+fn borrowed_impl_generics(generics: &Generics) -> TokenStream {
+    let mut generics = generics.clone();
+    generics.params.insert(0, parse_quote!('__a));
+    let (impl_generics, _, _) = generics.split_for_impl();
+    impl_generics.to_token_stream()
+}
+
+/// When a message has an extensions field, the extension's fields may have any tag that isn't known
+/// to the message itself, so every one of the message's own fields must be ordered at runtime
+/// amongst them. This returns the parts of the message's own fields, each with its tag, that must be
+/// sorted together as closures taking `params`; `func` produces the body of each part's closure.
+fn extension_parts(
+    unsorted_fields: &[(TokenStream, Field)],
+    params: TokenStream,
+    func: impl Fn(&TokenStream, &Field) -> TokenStream,
+) -> Vec<TokenStream> {
+    unsorted_fields
+        .iter()
+        .sorted_unstable_by_key(|(_, field)| field.first_tag())
+        .map(|(field_ident, field)| {
+            let body = func(field_ident, field);
+            match field {
+                Field::Value(_) => {
+                    let tag = field.first_tag();
+                    quote! {
+                        parts[nparts] = (#tag, Some(|#params| { #body }));
+                        nparts += 1;
+                    }
+                }
+                Field::Oneof(_) => {
+                    let current_tag = field.current_tag(quote!(self.#field_ident));
+                    quote! {
+                        if let Some(tag) = #current_tag {
+                            parts[nparts] = (tag, Some(|#params| { #body }));
+                            nparts += 1;
+                        }
+                    }
+                }
+            }
+        })
+        .collect()
+}
+
+/// Returns a string literal naming a field in its descriptor, given the field's ident or index.
+fn descriptor_name(field_ident: &impl ToString) -> TokenStream {
+    let name = field_ident.to_string();
+    let name = name.trim_start_matches("r#");
+    quote!(#name)
+}
+
 fn try_message(input: TokenStream) -> Result<TokenStream, Error> {
     let input: DeriveInput = parse2(input)?;
 
@@ -398,9 +531,36 @@ fn try_message(input: TokenStream) -> Result<TokenStream, Error> {
         where_clause,
         unsorted_fields,
         has_ignored_fields,
+        extensions,
+        reserved_tags,
     } = preprocess_message(&input)?;
     let fields = sort_fields(unsorted_fields.clone());
-    let where_clause = append_expedient_encoder_wheres(where_clause, None, &unsorted_fields);
+    let borrowed_impl_generics = borrowed_impl_generics(&input.generics);
+    let borrowed_where_clause = append_borrowed_expedient_encoder_wheres(
+        where_clause,
+        Some(match &extensions {
+            Some((_, ty)) => {
+                quote!(Self: ::bilrost::RawMessage, #ty: ::bilrost::RawMessageBorrowDecoder<'__a>)
+            }
+            None => quote!(Self: ::bilrost::RawMessage),
+        }),
+        &unsorted_fields,
+    );
+    let decoder_where_clause = append_expedient_decoder_wheres(
+        where_clause,
+        Some(match &extensions {
+            Some((_, ty)) => quote!(Self: ::bilrost::RawMessage, #ty: ::bilrost::RawMessageDecoder),
+            None => quote!(Self: ::bilrost::RawMessage),
+        }),
+        &unsorted_fields,
+    );
+    let where_clause = append_expedient_encoder_wheres(
+        where_clause,
+        extensions
+            .as_ref()
+            .map(|(_, ty)| quote!(#ty: ::bilrost::MessageExtension)),
+        &unsorted_fields,
+    );
 
     let encoded_len = fields.iter().map(|chunk| match chunk {
         AlwaysOrdered((field_ident, field)) => field.encoded_len(quote!(self.#field_ident)),
@@ -457,45 +617,154 @@ fn try_message(input: TokenStream) -> Result<TokenStream, Error> {
         }
     });
 
-    let encode = fields.iter().map(|chunk| match chunk {
-        AlwaysOrdered((field_ident, field)) => field.encode(quote!(self.#field_ident)),
-        SortGroup(parts) => {
-            let parts: Vec<TokenStream> = parts
+    // Generates the statements that write the message's fields in order, or for `prepend` the
+    // statements that prepend them to a reverse buffer from the last field to the first.
+    let encode_fields = |prepend: bool| -> Vec<TokenStream> {
+        let (buf_ty, writer_ty) = if prepend {
+            (
+                quote!(::bilrost::encoding::ReverseBuffer),
+                quote!(::bilrost::encoding::TagRevWriter),
+            )
+        } else {
+            (quote!(__B), quote!(::bilrost::encoding::TagWriter))
+        };
+        let write_field = |field: &Field, ident: TokenStream| {
+            if prepend {
+                field.prepend(ident)
+            } else {
+                field.encode(ident)
+            }
+        };
+        let in_order = |mut items: Vec<TokenStream>| {
+            if prepend {
+                items.reverse();
+            }
+            items
+        };
+        let iterate_parts = if prepend {
+            quote!(parts.iter().rev())
+        } else {
+            quote!(parts.iter())
+        };
+        in_order(
+            fields
                 .iter()
-                .map(|part| match part {
-                    Contiguous(fields) => {
-                        let Some((_, first_field)) = fields.first() else {
-                            panic!("empty contiguous field group");
-                        };
-                        let first_tag = first_field.first_tag();
-                        let each_field = fields.iter().cloned().map(|(field_ident, field)| {
-                            field.encode(quote!(instance.#field_ident))
-                        });
-                        quote! {
-                            parts[nparts] = (#first_tag, Some(|instance, buf, tw| {
-                                #(#each_field)*
-                            }));
-                            nparts += 1;
-                        }
+                .map(|chunk| match chunk {
+                    AlwaysOrdered((field_ident, field)) => {
+                        write_field(field, quote!(self.#field_ident))
                     }
-                    Oneof((field_ident, field)) => {
-                        let current_tag = field.current_tag(quote!(self.#field_ident));
-                        let encode = field.encode(quote!(instance.#field_ident));
+                    SortGroup(parts) => {
+                        let parts: Vec<TokenStream> = parts
+                            .iter()
+                            .map(|part| match part {
+                                Contiguous(fields) => {
+                                    let Some((_, first_field)) = fields.first() else {
+                                        panic!("empty contiguous field group");
+                                    };
+                                    let first_tag = first_field.first_tag();
+                                    let each_field = in_order(
+                                        fields
+                                            .iter()
+                                            .map(|(field_ident, field)| {
+                                                write_field(field, quote!(instance.#field_ident))
+                                            })
+                                            .collect(),
+                                    );
+                                    quote! {
+                                        parts[nparts] = (#first_tag, Some(|instance, buf, tw| {
+                                            #(#each_field)*
+                                        }));
+                                        nparts += 1;
+                                    }
+                                }
+                                Oneof((field_ident, field)) => {
+                                    let current_tag = field.current_tag(quote!(self.#field_ident));
+                                    let encode = write_field(field, quote!(instance.#field_ident));
+                                    quote! {
+                                        if let Some(tag) = #current_tag {
+                                            parts[nparts] = (tag, Some(|instance, buf, tw| {
+                                                #encode
+                                            }));
+                                            nparts += 1;
+                                        }
+                                    }
+                                }
+                            })
+                            .collect();
+                        let max_parts = parts.len();
+                        // TODO(widders): when there are many parts, use Vec instead of array
                         quote! {
-                            if let Some(tag) = #current_tag {
-                                parts[nparts] = (tag, Some(|instance, buf, tw| {
-                                    #encode
-                                }));
-                                nparts += 1;
+                            {
+                                let mut parts = [
+                                    (0u32, ::core::option::Option::None::<
+                                               fn(&Self, &mut #buf_ty, &mut #writer_ty)
+                                           >);
+                                    #max_parts
+                                ];
+                                let mut nparts = 0usize;
+                                #(#parts)*
+                                let parts = &mut parts[..nparts];
+                                parts.sort_unstable_by_key(|(tag, _)| *tag);
+                                #iterate_parts.for_each(|(_, encode_func)| {
+                                    (encode_func.unwrap())(self, buf, tw)
+                                });
                             }
                         }
                     }
                 })
-                .collect();
-            let max_parts = parts.len();
-            // TODO(widders): when there are many parts, use Vec instead of array
-            quote! {
-                {
+                .collect(),
+        )
+    };
+
+    let (encoded_len, encode, prepend): (Vec<TokenStream>, Vec<TokenStream>, Vec<TokenStream>) =
+        if let Some((extensions_ident, _)) = &extensions {
+            let len_parts =
+                extension_parts(&unsorted_fields, quote!(instance, tm), |ident, field| {
+                    field.encoded_len(quote!(instance.#ident))
+                });
+            let encode_parts = extension_parts(
+                &unsorted_fields,
+                quote!(instance, buf, tw),
+                |ident, field| field.encode(quote!(instance.#ident)),
+            );
+            let prepend_parts = extension_parts(
+                &unsorted_fields,
+                quote!(instance, buf, tw),
+                |ident, field| field.prepend(quote!(instance.#ident)),
+            );
+            let max_parts = unsorted_fields.len();
+            (
+                vec![quote! {
+                    {
+                        let mut parts = [
+                            (0u32, ::core::option::Option::None::<
+                                       fn(&Self, &mut ::bilrost::encoding::TagMeasurer) -> usize
+                                   >);
+                            #max_parts
+                        ];
+                        let mut nparts = 0usize;
+                        #(#len_parts)*
+                        let parts = &mut parts[..nparts];
+                        parts.sort_unstable_by_key(|(tag, _)| *tag);
+                        let mut low = ::core::ops::Bound::Unbounded;
+                        let mut len = 0;
+                        for (tag, len_func) in parts.iter() {
+                            len += ::bilrost::MessageExtension::raw_tag_range_encoded_len(
+                                &self.#extensions_ident,
+                                (low, ::core::ops::Bound::Excluded(*tag)),
+                                tm,
+                            );
+                            len += (len_func.unwrap())(self, tm);
+                            low = ::core::ops::Bound::Included(*tag);
+                        }
+                        len + ::bilrost::MessageExtension::raw_tag_range_encoded_len(
+                            &self.#extensions_ident,
+                            (low, ::core::ops::Bound::Unbounded),
+                            tm,
+                        )
+                    }
+                }],
+                vec![quote! {
                     let mut parts = [
                         (0u32, ::core::option::Option::None::<
                                    fn(&Self, &mut __B, &mut ::bilrost::encoding::TagWriter)
@@ -503,30 +772,218 @@ fn try_message(input: TokenStream) -> Result<TokenStream, Error> {
                         #max_parts
                     ];
                     let mut nparts = 0usize;
-                    #(#parts)*
+                    #(#encode_parts)*
+                    let parts = &mut parts[..nparts];
+                    parts.sort_unstable_by_key(|(tag, _)| *tag);
+                    let mut low = ::core::ops::Bound::Unbounded;
+                    for (tag, encode_func) in parts.iter() {
+                        ::bilrost::MessageExtension::raw_encode_tag_range(
+                            &self.#extensions_ident,
+                            (low, ::core::ops::Bound::Excluded(*tag)),
+                            buf,
+                            tw,
+                        );
+                        (encode_func.unwrap())(self, buf, tw);
+                        low = ::core::ops::Bound::Included(*tag);
+                    }
+                    ::bilrost::MessageExtension::raw_encode_tag_range(
+                        &self.#extensions_ident,
+                        (low, ::core::ops::Bound::Unbounded),
+                        buf,
+                        tw,
+                    );
+                }],
+                vec![quote! {
+                    let mut parts = [
+                        (0u32, ::core::option::Option::None::<
+                                   fn(
+                                       &Self,
+                                       &mut ::bilrost::encoding::ReverseBuffer,
+                                       &mut ::bilrost::encoding::TagRevWriter,
+                                   )
+                               >);
+                        #max_parts
+                    ];
+                    let mut nparts = 0usize;
+                    #(#prepend_parts)*
                     let parts = &mut parts[..nparts];
                     parts.sort_unstable_by_key(|(tag, _)| *tag);
-                    parts.iter().for_each(|(_, encode_func)| (encode_func.unwrap())(self, buf, tw));
+                    let mut high = ::core::ops::Bound::Unbounded;
+                    for (tag, prepend_func) in parts.iter().rev() {
+                        ::bilrost::MessageExtension::raw_prepend_tag_range(
+                            &self.#extensions_ident,
+                            (::core::ops::Bound::Included(*tag), high),
+                            buf,
+                            tw,
+                        );
+                        (prepend_func.unwrap())(self, buf, tw);
+                        high = ::core::ops::Bound::Excluded(*tag);
+                    }
+                    ::bilrost::MessageExtension::raw_prepend_tag_range(
+                        &self.#extensions_ident,
+                        (::core::ops::Bound::Unbounded, high),
+                        buf,
+                        tw,
+                    );
+                }],
+            )
+        } else {
+            (
+                encoded_len.collect(),
+                encode_fields(false),
+                encode_fields(true),
+            )
+        };
+
+    let decode_arms = |decode_field: fn(&Field, TokenStream) -> TokenStream| {
+        unsorted_fields
+            .iter()
+            .map(|(field_ident, field)| {
+                let decode = decode_field(field, quote!(value));
+                let tags = field.tags().into_iter().map(|tag| quote!(#tag));
+                let tags = Itertools::intersperse(tags, quote!(|));
+
+                quote! {
+                    #(#tags)* => {
+                        let mut value = &mut self.#field_ident;
+                        #decode.map_err(|mut error| {
+                            error.push(STRUCT_NAME, stringify!(#field_ident));
+                            error
+                        })
+                    },
+                }
+            })
+            .collect::<Vec<_>>()
+    };
+    let decode = decode_arms(Field::decode_expedient);
+    let borrow_decode = decode_arms(Field::borrow_decode_expedient);
+
+    // Fields that are merged differently than they are decoded get their own arms in
+    // `raw_merge_field`; each oneof is numbered so its fields can be tracked while merging.
+    let mut oneof_count = 0usize;
+    let merge = unsorted_fields
+        .iter()
+        .filter_map(|(field_ident, field)| {
+            let oneof_index = oneof_count;
+            if let Field::Oneof(_) = field {
+                oneof_count += 1;
+            }
+            let merge = field.merge_expedient(quote!(value), oneof_index)?;
+            let tags = field.tags().into_iter().map(|tag| quote!(#tag));
+            let tags = Itertools::intersperse(tags, quote!(|));
+            Some(quote! {
+                #(#tags)* => {
+                    let mut value = &mut self.#field_ident;
+                    #merge.map_err(|mut error| {
+                        error.push(STRUCT_NAME, stringify!(#field_ident));
+                        error
+                    })
+                },
+            })
+        })
+        .collect::<Vec<_>>();
+    let merge_field = if merge.is_empty() {
+        quote!()
+    } else {
+        quote! {
+            #[allow(unused_variables)]
+            fn raw_merge_field<__B>(
+                &mut self,
+                tag: u32,
+                wire_type: ::bilrost::encoding::WireType,
+                duplicated: bool,
+                buf: ::bilrost::encoding::Capped<__B>,
+                ctx: ::bilrost::encoding::DecodeContext,
+                tracker: &mut ::bilrost::encoding::MergeTracker,
+            ) -> ::core::result::Result<(), ::bilrost::DecodeError>
+            where
+                __B: ::bilrost::bytes::Buf + ?Sized,
+            {
+                const STRUCT_NAME: &'static str = stringify!(#ident);
+                match tag {
+                    #(#merge)*
+                    _ => ::bilrost::RawMessageDecoder::raw_decode_field(
+                        self,
+                        tag,
+                        wire_type,
+                        duplicated,
+                        buf,
+                        ctx,
+                    ),
                 }
             }
         }
-    });
-
-    let decode = unsorted_fields.iter().map(|(field_ident, field)| {
-        let decode = field.decode_expedient(quote!(value));
-        let tags = field.tags().into_iter().map(|tag| quote!(#tag));
-        let tags = Itertools::intersperse(tags, quote!(|));
+    };
 
+    let diff = unsorted_fields
+        .iter()
+        .map(|(field_ident, field)| field.diff(quote!(self.#field_ident), quote!(new.#field_ident)))
+        .chain(extensions.as_ref().map(|(extensions_ident, _)| {
+            quote! {
+                ::bilrost::RawMessage::raw_diff(
+                    &self.#extensions_ident,
+                    &new.#extensions_ident,
+                    pw,
+                );
+            }
+        }))
+        .collect::<Vec<_>>();
+    let apply = unsorted_fields
+        .iter()
+        .map(|(field_ident, field)| {
+            let apply = field.apply(quote!(value));
+            let tags = field.tags().into_iter().map(|tag| quote!(#tag));
+            let tags = Itertools::intersperse(tags, quote!(|));
+            quote! {
+                #(#tags)* => {
+                    let value = &mut self.#field_ident;
+                    #apply.map_err(|mut error| {
+                        error.push(STRUCT_NAME, stringify!(#field_ident));
+                        error
+                    })
+                },
+            }
+        })
+        .collect::<Vec<_>>();
+    let apply_unknown = if let Some((extensions_ident, _)) = &extensions {
         quote! {
-            #(#tags)* => {
-                let mut value = &mut self.#field_ident;
-                #decode.map_err(|mut error| {
-                    error.push(STRUCT_NAME, stringify!(#field_ident));
-                    error
-                })
-            },
+            _ => ::bilrost::RawMessageDecoder::raw_apply(&mut self.#extensions_ident, path, op),
         }
-    });
+    } else {
+        quote! {
+            _ => ::core::result::Result::Err(::bilrost::DecodeError::new(
+                ::bilrost::DecodeErrorKind::InvalidPatch,
+            )),
+        }
+    };
+
+    let (decode_unknown, borrow_decode_unknown) = if let Some((extensions_ident, _)) = &extensions {
+        (
+            quote! {
+                _ => ::bilrost::RawMessageDecoder::raw_decode_field(
+                    &mut self.#extensions_ident,
+                    tag,
+                    wire_type,
+                    duplicated,
+                    buf,
+                    ctx,
+                ),
+            },
+            quote! {
+                _ => ::bilrost::RawMessageBorrowDecoder::raw_borrow_decode_field(
+                    &mut self.#extensions_ident,
+                    tag,
+                    wire_type,
+                    duplicated,
+                    buf,
+                    ctx,
+                ),
+            },
+        )
+    } else {
+        let skip = quote!(_ => ::bilrost::encoding::skip_field(wire_type, buf),);
+        (skip.clone(), skip)
+    };
 
     let struct_name = if unsorted_fields.is_empty() {
         quote!()
@@ -558,8 +1015,23 @@ fn try_message(input: TokenStream) -> Result<TokenStream, Error> {
     let field_idents: Vec<_> = unsorted_fields
         .iter()
         .map(|(field_ident, _)| field_ident)
+        .chain(extensions.as_ref().map(|(field_ident, _)| field_ident))
         .collect();
 
+    let field_descriptors = unsorted_fields
+        .iter()
+        .filter(|(_, field)| matches!(field, Field::Value(..)))
+        .map(|(field_ident, field)| field.descriptor(descriptor_name(field_ident)));
+    let oneof_descriptors = unsorted_fields
+        .iter()
+        .filter(|(_, field)| matches!(field, Field::Oneof(..)))
+        .map(|(field_ident, field)| field.descriptor(descriptor_name(field_ident)));
+    let reserved_tags = reserved_tags.iter_tag_ranges().map(|range| {
+        let (start, end) = (range.start(), range.end());
+        quote!(#start..=#end)
+    });
+    let has_extensions = extensions.is_some();
+
     let initialize_ignored = if has_ignored_fields {
         quote!(..::core::default::Default::default())
     } else {
@@ -570,6 +1042,15 @@ fn try_message(input: TokenStream) -> Result<TokenStream, Error> {
         impl #impl_generics ::bilrost::RawMessage for #ident #ty_generics #where_clause {
             const __ASSERTIONS: () = { #(#static_guards)* };
 
+            const DESCRIPTOR: &'static ::bilrost::descriptor::MessageDescriptor =
+                &::bilrost::descriptor::MessageDescriptor {
+                    name: stringify!(#ident),
+                    fields: &[#(#field_descriptors),*],
+                    oneofs: &[#(#oneof_descriptors),*],
+                    reserved_tags: &[#(#reserved_tags),*],
+                    has_extensions: #has_extensions,
+                };
+
             #[allow(unused_variables)]
             fn raw_encode<__B>(&self, buf: &mut __B)
             where
@@ -579,6 +1060,27 @@ fn try_message(input: TokenStream) -> Result<TokenStream, Error> {
                 #(#encode)*
             }
 
+            #[allow(unused_variables)]
+            fn raw_prepend(&self, buf: &mut ::bilrost::encoding::ReverseBuffer) {
+                let tw = &mut ::bilrost::encoding::TagRevWriter::new();
+                #(#prepend)*
+                tw.finalize(buf);
+            }
+
+            #[inline]
+            fn raw_encoded_len(&self) -> usize {
+                let tm = &mut ::bilrost::encoding::TagMeasurer::new();
+                0 #(+ #encoded_len)*
+            }
+
+            #[allow(unused_variables)]
+            fn raw_diff(&self, new: &Self, pw: &mut ::bilrost::patch::PatchWriter) {
+                #(#diff)*
+            }
+        }
+
+        impl #impl_generics ::bilrost::RawMessageDecoder
+        for #ident #ty_generics #decoder_where_clause {
             #[allow(unused_variables)]
             #[inline]
             fn raw_decode_field<__B>(
@@ -595,14 +1097,48 @@ fn try_message(input: TokenStream) -> Result<TokenStream, Error> {
                 #struct_name
                 match tag {
                     #(#decode)*
-                    _ => ::bilrost::encoding::skip_field(wire_type, buf),
+                    #decode_unknown
                 }
             }
 
+            #merge_field
+
+            #[allow(unused_variables)]
+            fn raw_apply(
+                &mut self,
+                path: &[u32],
+                op: &::bilrost::patch::PatchOp,
+            ) -> ::core::result::Result<(), ::bilrost::DecodeError> {
+                #struct_name
+                let ::core::option::Option::Some((&tag, rest)) = path.split_first() else {
+                    return ::core::result::Result::Err(::bilrost::DecodeError::new(
+                        ::bilrost::DecodeErrorKind::InvalidPatch,
+                    ));
+                };
+                match tag {
+                    #(#apply)*
+                    #apply_unknown
+                }
+            }
+        }
+
+        impl #borrowed_impl_generics ::bilrost::RawMessageBorrowDecoder<'__a>
+        for #ident #ty_generics #borrowed_where_clause {
+            #[allow(unused_variables)]
             #[inline]
-            fn raw_encoded_len(&self) -> usize {
-                let tm = &mut ::bilrost::encoding::TagMeasurer::new();
-                0 #(+ #encoded_len)*
+            fn raw_borrow_decode_field(
+                &mut self,
+                tag: u32,
+                wire_type: ::bilrost::encoding::WireType,
+                duplicated: bool,
+                buf: ::bilrost::encoding::Capped<&'__a [u8]>,
+                ctx: ::bilrost::encoding::DecodeContext,
+            ) -> ::core::result::Result<(), ::bilrost::DecodeError> {
+                #struct_name
+                match tag {
+                    #(#borrow_decode)*
+                    #borrow_decode_unknown
+                }
             }
         }
 
@@ -654,33 +1190,110 @@ fn try_distinguished_message(input: TokenStream) -> Result<TokenStream, Error> {
         where_clause,
         unsorted_fields,
         has_ignored_fields,
+        extensions,
+        ..
     } = preprocess_message(&input)?;
 
     if has_ignored_fields {
         bail!("messages with ignored fields cannot be distinguished");
     }
 
-    let where_clause = append_distinguished_encoder_wheres(
+    let self_where = match &extensions {
+        Some((_, ty)) => quote!(Self: ::core::cmp::Eq, #ty: ::bilrost::RawDistinguishedMessage),
+        None => quote!(Self: ::core::cmp::Eq),
+    };
+    let borrowed_impl_generics = borrowed_impl_generics(&input.generics);
+    let borrowed_self_where = match &extensions {
+        Some((_, ty)) => quote! {
+            Self: ::bilrost::RawDistinguishedMessage,
+            #ty: ::bilrost::RawDistinguishedMessageBorrowDecoder<'__a>
+        },
+        None => quote!(Self: ::bilrost::RawDistinguishedMessage),
+    };
+    let borrowed_where_clause = append_borrowed_distinguished_encoder_wheres(
         where_clause,
-        Some(quote!(Self: ::core::cmp::Eq)),
+        Some(borrowed_self_where),
         &unsorted_fields,
     );
+    let decoder_self_where = match &extensions {
+        Some((_, ty)) => quote! {
+            Self: ::bilrost::RawDistinguishedMessage,
+            #ty: ::bilrost::RawDistinguishedMessageDecoder
+        },
+        None => quote!(Self: ::bilrost::RawDistinguishedMessage),
+    };
+    let decoder_where_clause = append_distinguished_decoder_wheres(
+        where_clause,
+        Some(decoder_self_where),
+        &unsorted_fields,
+    );
+    let where_clause =
+        append_distinguished_encoder_wheres(where_clause, Some(self_where), &unsorted_fields);
 
-    let decode = unsorted_fields.iter().map(|(field_ident, field)| {
-        let decode = field.decode_distinguished(quote!(value));
-        let tags = field.tags().into_iter().map(|tag| quote!(#tag));
-        let tags = Itertools::intersperse(tags, quote!(|));
+    let decode_arms = |decode_field: fn(&Field, TokenStream) -> TokenStream| {
+        unsorted_fields
+            .iter()
+            .map(|(field_ident, field)| {
+                let decode = decode_field(field, quote!(value));
+                let tags = field.tags().into_iter().map(|tag| quote!(#tag));
+                let tags = Itertools::intersperse(tags, quote!(|));
+
+                quote! {
+                    #(#tags)* => {
+                        let mut value = &mut self.#field_ident;
+                        canon.update(#decode.map_err(|mut error| {
+                            error.push(STRUCT_NAME, stringify!(#field_ident));
+                            error
+                        })?);
+                    },
+                }
+            })
+            .collect::<Vec<_>>()
+    };
+    let decode = decode_arms(Field::decode_distinguished);
+    let borrow_decode = decode_arms(Field::borrow_decode_distinguished);
 
-        quote! {
-            #(#tags)* => {
-                let mut value = &mut self.#field_ident;
-                canon.update(#decode.map_err(|mut error| {
-                    error.push(STRUCT_NAME, stringify!(#field_ident));
-                    error
-                })?);
+    let (decode_unknown, borrow_decode_unknown) = if let Some((extensions_ident, _)) = &extensions {
+        (
+            quote! {
+                _ => {
+                    canon.update(
+                        ::bilrost::RawDistinguishedMessageDecoder::raw_decode_field_distinguished(
+                            &mut self.#extensions_ident,
+                            tag,
+                            wire_type,
+                            duplicated,
+                            buf,
+                            ctx,
+                        )?
+                    );
+                }
             },
-        }
-    });
+            quote! {
+                _ => {
+                    canon.update(
+                        ::bilrost::RawDistinguishedMessageBorrowDecoder
+                            ::raw_borrow_decode_field_distinguished(
+                                &mut self.#extensions_ident,
+                                tag,
+                                wire_type,
+                                duplicated,
+                                buf,
+                                ctx,
+                            )?
+                    );
+                }
+            },
+        )
+    } else {
+        let skip = quote! {
+            _ => {
+                canon.update(::bilrost::Canonicity::HasExtensions);
+                ::bilrost::encoding::skip_field(wire_type, buf)?;
+            }
+        };
+        (skip.clone(), skip)
+    };
 
     let struct_name = if unsorted_fields.is_empty() {
         quote!()
@@ -692,7 +1305,10 @@ fn try_distinguished_message(input: TokenStream) -> Result<TokenStream, Error> {
 
     let expanded = quote! {
         impl #impl_generics ::bilrost::RawDistinguishedMessage
-        for #ident #ty_generics #where_clause {
+        for #ident #ty_generics #where_clause {}
+
+        impl #impl_generics ::bilrost::RawDistinguishedMessageDecoder
+        for #ident #ty_generics #decoder_where_clause {
             #[allow(unused_variables)]
             fn raw_decode_field_distinguished<__B>(
                 &mut self,
@@ -709,10 +1325,28 @@ fn try_distinguished_message(input: TokenStream) -> Result<TokenStream, Error> {
                 let mut canon = ::bilrost::Canonicity::Canonical;
                 match tag {
                     #(#decode)*
-                    _ => {
-                        canon.update(::bilrost::Canonicity::HasExtensions);
-                        ::bilrost::encoding::skip_field(wire_type, buf)?;
-                    }
+                    #decode_unknown
+                }
+                Ok(canon)
+            }
+        }
+
+        impl #borrowed_impl_generics ::bilrost::RawDistinguishedMessageBorrowDecoder<'__a>
+        for #ident #ty_generics #borrowed_where_clause {
+            #[allow(unused_variables)]
+            fn raw_borrow_decode_field_distinguished(
+                &mut self,
+                tag: u32,
+                wire_type: ::bilrost::encoding::WireType,
+                duplicated: bool,
+                buf: ::bilrost::encoding::Capped<&'__a [u8]>,
+                ctx: ::bilrost::encoding::DecodeContext,
+            ) -> ::core::result::Result<::bilrost::Canonicity, ::bilrost::DecodeError> {
+                #struct_name
+                let mut canon = ::bilrost::Canonicity::Canonical;
+                match tag {
+                    #(#borrow_decode)*
+                    #borrow_decode_unknown
                 }
                 Ok(canon)
             }
@@ -742,6 +1376,7 @@ fn try_enumeration(input: TokenStream) -> Result<TokenStream, Error> {
 
     let generics = &input.generics;
     let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let borrowed_impl_generics = borrowed_impl_generics(generics);
 
     let punctuated_variants = match input.data {
         Data::Enum(DataEnum { variants, .. }) => variants,
@@ -795,6 +1430,15 @@ fn try_enumeration(input: TokenStream) -> Result<TokenStream, Error> {
         .iter()
         .map(|(variant, value)| quote!(#value => #ident::#variant));
 
+    let descriptor_variants = variants.iter().map(|(variant, value)| {
+        quote! {
+            ::bilrost::descriptor::EnumerationVariant {
+                name: stringify!(#variant),
+                number: #value,
+            }
+        }
+    });
+
     // When the type has a zero-valued variant, we implement `EmptyState`. When it doesn't, we
     // need an alternate way to create a value to be overwritten, so we impl `NewForOverwrite`
     // directly.
@@ -842,6 +1486,12 @@ fn try_enumeration(input: TokenStream) -> Result<TokenStream, Error> {
 
     let expanded = quote! {
         impl #impl_generics ::bilrost::Enumeration for #ident #ty_generics #where_clause {
+            const DESCRIPTOR: &'static ::bilrost::descriptor::EnumerationDescriptor =
+                &::bilrost::descriptor::EnumerationDescriptor {
+                    name: stringify!(#ident),
+                    variants: &[#(#descriptor_variants),*],
+                };
+
             #[inline]
             fn to_number(&self) -> u32 {
                 match self {
@@ -877,6 +1527,11 @@ fn try_enumeration(input: TokenStream) -> Result<TokenStream, Error> {
 
         impl #impl_generics ::bilrost::encoding::ValueEncoder<::bilrost::encoding::General>
         for #ident #ty_generics #where_clause {
+            const VALUE_KIND: &'static ::bilrost::descriptor::ValueKind =
+                &::bilrost::descriptor::ValueKind::Enumeration(
+                    <Self as ::bilrost::Enumeration>::DESCRIPTOR,
+                );
+
             #[inline]
             fn encode_value<__B: ::bilrost::bytes::BufMut + ?Sized>(value: &Self, buf: &mut __B) {
                 ::bilrost::encoding::encode_varint(
@@ -885,13 +1540,24 @@ fn try_enumeration(input: TokenStream) -> Result<TokenStream, Error> {
                 );
             }
 
+            #[inline]
+            fn prepend_value(value: &Self, buf: &mut ::bilrost::encoding::ReverseBuffer) {
+                ::bilrost::encoding::prepend_varint(
+                    ::bilrost::Enumeration::to_number(value) as u64,
+                    buf,
+                );
+            }
+
             #[inline]
             fn value_encoded_len(value: &Self) -> usize {
                 ::bilrost::encoding::encoded_len_varint(
                     ::bilrost::encoding::Enumeration::to_number(value) as u64
                 )
             }
+        }
 
+        impl #impl_generics ::bilrost::encoding::ValueDecoder<::bilrost::encoding::General>
+        for #ident #ty_generics #where_clause {
             #[inline]
             fn decode_value<__B: ::bilrost::bytes::Buf + ?Sized>(
                 value: &mut Self,
@@ -909,6 +1575,10 @@ fn try_enumeration(input: TokenStream) -> Result<TokenStream, Error> {
 
         impl #impl_generics
         ::bilrost::encoding::DistinguishedValueEncoder<::bilrost::encoding::General>
+        for #ident #ty_generics #where_clause {}
+
+        impl #impl_generics
+        ::bilrost::encoding::DistinguishedValueDecoder<::bilrost::encoding::General>
         for #ident #ty_generics #where_clause {
             #[inline]
             fn decode_value_distinguished<__B: ::bilrost::bytes::Buf + ?Sized>(
@@ -917,7 +1587,7 @@ fn try_enumeration(input: TokenStream) -> Result<TokenStream, Error> {
                 allow_empty: bool,
                 ctx: ::bilrost::encoding::DecodeContext,
             ) -> Result<::bilrost::Canonicity, ::bilrost::DecodeError> {
-                ::bilrost::encoding::ValueEncoder::<::bilrost::encoding::General>::decode_value(
+                ::bilrost::encoding::ValueDecoder::<::bilrost::encoding::General>::decode_value(
                     value,
                     buf,
                     ctx,
@@ -926,6 +1596,38 @@ fn try_enumeration(input: TokenStream) -> Result<TokenStream, Error> {
                 Ok(::bilrost::Canonicity::Canonical)
             }
         }
+
+        impl #borrowed_impl_generics
+        ::bilrost::encoding::ValueBorrowDecoder<'__a, ::bilrost::encoding::General>
+        for #ident #ty_generics #where_clause {
+            #[inline]
+            fn borrow_decode_value(
+                value: &mut Self,
+                buf: ::bilrost::encoding::Capped<&'__a [u8]>,
+                ctx: ::bilrost::encoding::DecodeContext,
+            ) -> Result<(), ::bilrost::DecodeError> {
+                ::bilrost::encoding::ValueDecoder::<::bilrost::encoding::General>::decode_value(
+                    value,
+                    buf,
+                    ctx,
+                )
+            }
+        }
+
+        impl #borrowed_impl_generics
+        ::bilrost::encoding::DistinguishedValueBorrowDecoder<'__a, ::bilrost::encoding::General>
+        for #ident #ty_generics #where_clause {
+            #[inline]
+            fn borrow_decode_value_distinguished(
+                value: &mut Self,
+                buf: ::bilrost::encoding::Capped<&'__a [u8]>,
+                allow_empty: bool,
+                ctx: ::bilrost::encoding::DecodeContext,
+            ) -> Result<::bilrost::Canonicity, ::bilrost::DecodeError> {
+                ::bilrost::encoding::DistinguishedValueDecoder::<::bilrost::encoding::General>
+                    ::decode_value_distinguished(value, buf, allow_empty, ctx)
+            }
+        }
     };
 
     Ok(expanded)
@@ -1064,6 +1766,25 @@ fn try_oneof(input: TokenStream) -> Result<TokenStream, Error> {
         empty_variant,
     } = preprocess_oneof(&input)?;
 
+    let borrowed_impl_generics = borrowed_impl_generics(&input.generics);
+    let borrowed_where_clause = append_borrowed_expedient_encoder_wheres(
+        where_clause,
+        Some(if empty_variant.is_some() {
+            quote!(Self: ::bilrost::encoding::Oneof)
+        } else {
+            quote!(Self: ::bilrost::encoding::NonEmptyOneof)
+        }),
+        &fields,
+    );
+    let decoder_where_clause = append_expedient_decoder_wheres(
+        where_clause,
+        Some(if empty_variant.is_some() {
+            quote!(Self: ::bilrost::encoding::Oneof)
+        } else {
+            quote!(Self: ::bilrost::encoding::NonEmptyOneof)
+        }),
+        &fields,
+    );
     let where_clause = append_expedient_encoder_wheres(where_clause, None, &fields);
 
     let sorted_tags: Vec<u32> = fields
@@ -1071,6 +1792,10 @@ fn try_oneof(input: TokenStream) -> Result<TokenStream, Error> {
         .flat_map(|(_, field)| field.tags())
         .sorted_unstable()
         .collect();
+    let field_descriptors = fields
+        .iter()
+        .map(|(variant_ident, field)| field.descriptor(descriptor_name(variant_ident)))
+        .collect::<Vec<_>>();
     if let Some((duplicate_tag, _)) = sorted_tags.iter().tuple_windows().find(|(a, b)| a == b) {
         bail!(
             "invalid oneof {}: multiple variants have tag {}",
@@ -1085,6 +1810,12 @@ fn try_oneof(input: TokenStream) -> Result<TokenStream, Error> {
         quote!(#ident::#variant_ident #with_value => { #encode })
     });
 
+    let prepend = fields.iter().map(|(variant_ident, field)| {
+        let prepend = field.prepend(quote!(*value));
+        let with_value = field.with_value(quote!(value));
+        quote!(#ident::#variant_ident #with_value => { #prepend })
+    });
+
     let encoded_len = fields.iter().map(|(variant_ident, field)| {
         let encoded_len = field.encoded_len(quote!(*value));
         let with_value = field.with_value(quote!(value));
@@ -1098,30 +1829,37 @@ fn try_oneof(input: TokenStream) -> Result<TokenStream, Error> {
             quote!(#ident::#variant_ident #ignored => ::core::option::Option::Some(#tag))
         });
 
-        let decode = fields.iter().map(|(variant_ident, field)| {
-            let tag = field.first_tag();
-            let decode = field.decode_expedient(quote!(value));
-            let with_new_value = field.with_value(quote!(new_value));
-            let with_value = field.with_value(quote!(value));
-            quote! {
-                #tag => match self {
-                    #ident::#empty_ident => {
-                        let mut new_value =
-                            ::bilrost::encoding::NewForOverwrite::new_for_overwrite();
-                        let mut value = &mut new_value;
-                        #decode?;
-                        *self = #ident::#variant_ident #with_new_value;
-                        Ok(())
-                    }
-                    #ident::#variant_ident #with_value => {
-                        #decode
+        let decode_arms = |decode_field: fn(&Field, TokenStream) -> TokenStream| {
+            fields
+                .iter()
+                .map(|(variant_ident, field)| {
+                    let tag = field.first_tag();
+                    let decode = decode_field(field, quote!(value));
+                    let with_new_value = field.with_value(quote!(new_value));
+                    let with_value = field.with_value(quote!(value));
+                    quote! {
+                        #tag => match self {
+                            #ident::#empty_ident => {
+                                let mut new_value =
+                                    ::bilrost::encoding::NewForOverwrite::new_for_overwrite();
+                                let mut value = &mut new_value;
+                                #decode?;
+                                *self = #ident::#variant_ident #with_new_value;
+                                Ok(())
+                            }
+                            #ident::#variant_ident #with_value => {
+                                #decode
+                            }
+                            _ => Err(::bilrost::DecodeError::new(
+                                ::bilrost::DecodeErrorKind::ConflictingFields
+                            )),
+                        }
                     }
-                    _ => Err(::bilrost::DecodeError::new(
-                        ::bilrost::DecodeErrorKind::ConflictingFields
-                    )),
-                }
-            }
-        });
+                })
+                .collect::<Vec<_>>()
+        };
+        let decode = decode_arms(Field::decode_expedient);
+        let borrow_decode = decode_arms(Field::borrow_decode_expedient);
 
         quote! {
             impl #impl_generics ::bilrost::encoding::Oneof
@@ -1129,6 +1867,9 @@ fn try_oneof(input: TokenStream) -> Result<TokenStream, Error> {
             {
                 const FIELD_TAGS: &'static [u32] = &[#(#sorted_tags),*];
 
+                const FIELDS: &'static [::bilrost::descriptor::FieldDescriptor] =
+                    &[#(#field_descriptors),*];
+
                 fn oneof_encode<__B: ::bilrost::bytes::BufMut + ?Sized>(
                     &self,
                     buf: &mut __B,
@@ -1136,7 +1877,18 @@ fn try_oneof(input: TokenStream) -> Result<TokenStream, Error> {
                 ) {
                     match self {
                         #ident::#empty_ident => {}
-                        #(#encode,)*
+                        #(#encode,)*
+                    }
+                }
+
+                fn oneof_prepend(
+                    &self,
+                    buf: &mut ::bilrost::encoding::ReverseBuffer,
+                    tw: &mut ::bilrost::encoding::TagRevWriter,
+                ) {
+                    match self {
+                        #ident::#empty_ident => {}
+                        #(#prepend,)*
                     }
                 }
 
@@ -1156,7 +1908,11 @@ fn try_oneof(input: TokenStream) -> Result<TokenStream, Error> {
                         #(#current_tag,)*
                     }
                 }
+            }
 
+            impl #impl_generics ::bilrost::encoding::OneofDecoder
+            for #ident #ty_generics #decoder_where_clause
+            {
                 fn oneof_decode_field<__B: ::bilrost::bytes::Buf + ?Sized>(
                     &mut self,
                     tag: u32,
@@ -1174,6 +1930,26 @@ fn try_oneof(input: TokenStream) -> Result<TokenStream, Error> {
                 }
             }
 
+            impl #borrowed_impl_generics ::bilrost::encoding::OneofBorrowDecoder<'__a>
+            for #ident #ty_generics #borrowed_where_clause
+            {
+                fn oneof_borrow_decode_field(
+                    &mut self,
+                    tag: u32,
+                    wire_type: ::bilrost::encoding::WireType,
+                    duplicated: bool,
+                    buf: ::bilrost::encoding::Capped<&'__a [u8]>,
+                    ctx: ::bilrost::encoding::DecodeContext,
+                ) -> ::core::result::Result<(), ::bilrost::DecodeError> {
+                    match tag {
+                        #(#borrow_decode,)*
+                        _ => unreachable!(
+                            concat!("invalid ", stringify!(#ident), " tag: {}"), tag,
+                        ),
+                    }
+                }
+            }
+
             impl #impl_generics ::bilrost::encoding::EmptyState
             for #ident #ty_generics #where_clause {
                 #[inline]
@@ -1200,30 +1976,37 @@ fn try_oneof(input: TokenStream) -> Result<TokenStream, Error> {
             quote!(#ident::#variant_ident #ignored => #tag)
         });
 
-        let decode = fields.iter().map(|(variant_ident, field)| {
-            let tag = field.first_tag();
-            let decode = field.decode_expedient(quote!(value));
-            let with_new_value = field.with_value(quote!(new_value));
-            let with_value = field.with_value(quote!(value));
-            quote! {
-                #tag => match field {
-                    ::core::option::Option::None => {
-                        let mut new_value =
-                            ::bilrost::encoding::NewForOverwrite::new_for_overwrite();
-                        let value = &mut new_value;
-                        #decode?;
-                        *field = Some(#ident::#variant_ident #with_new_value);
-                        Ok(())
-                    }
-                    ::core::option::Option::Some(#ident::#variant_ident #with_value) => {
-                        #decode
+        let decode_arms = |decode_field: fn(&Field, TokenStream) -> TokenStream| {
+            fields
+                .iter()
+                .map(|(variant_ident, field)| {
+                    let tag = field.first_tag();
+                    let decode = decode_field(field, quote!(value));
+                    let with_new_value = field.with_value(quote!(new_value));
+                    let with_value = field.with_value(quote!(value));
+                    quote! {
+                        #tag => match field {
+                            ::core::option::Option::None => {
+                                let mut new_value =
+                                    ::bilrost::encoding::NewForOverwrite::new_for_overwrite();
+                                let value = &mut new_value;
+                                #decode?;
+                                *field = Some(#ident::#variant_ident #with_new_value);
+                                Ok(())
+                            }
+                            ::core::option::Option::Some(#ident::#variant_ident #with_value) => {
+                                #decode
+                            }
+                            _ => Err(::bilrost::DecodeError::new(
+                                ::bilrost::DecodeErrorKind::ConflictingFields
+                            )),
+                        }
                     }
-                    _ => Err(::bilrost::DecodeError::new(
-                        ::bilrost::DecodeErrorKind::ConflictingFields
-                    )),
-                }
-            }
-        });
+                })
+                .collect::<Vec<_>>()
+        };
+        let decode = decode_arms(Field::decode_expedient);
+        let borrow_decode = decode_arms(Field::borrow_decode_expedient);
 
         quote! {
             impl #impl_generics ::bilrost::encoding::NonEmptyOneof
@@ -1231,6 +2014,9 @@ fn try_oneof(input: TokenStream) -> Result<TokenStream, Error> {
             {
                 const FIELD_TAGS: &'static [u32] = &[#(#sorted_tags),*];
 
+                const FIELDS: &'static [::bilrost::descriptor::FieldDescriptor] =
+                    &[#(#field_descriptors),*];
+
                 fn oneof_encode<__B: ::bilrost::bytes::BufMut + ?Sized>(
                     &self,
                     buf: &mut __B,
@@ -1241,6 +2027,16 @@ fn try_oneof(input: TokenStream) -> Result<TokenStream, Error> {
                     }
                 }
 
+                fn oneof_prepend(
+                    &self,
+                    buf: &mut ::bilrost::encoding::ReverseBuffer,
+                    tw: &mut ::bilrost::encoding::TagRevWriter,
+                ) {
+                    match self {
+                        #(#prepend,)*
+                    }
+                }
+
                 fn oneof_encoded_len(
                     &self,
                     tm: &mut ::bilrost::encoding::TagMeasurer,
@@ -1255,7 +2051,11 @@ fn try_oneof(input: TokenStream) -> Result<TokenStream, Error> {
                         #(#current_tag,)*
                     }
                 }
+            }
 
+            impl #impl_generics ::bilrost::encoding::NonEmptyOneofDecoder
+            for #ident #ty_generics #decoder_where_clause
+            {
                 fn oneof_decode_field<__B: ::bilrost::bytes::Buf + ?Sized>(
                     field: &mut ::core::option::Option<Self>,
                     tag: u32,
@@ -1272,6 +2072,26 @@ fn try_oneof(input: TokenStream) -> Result<TokenStream, Error> {
                     }
                 }
             }
+
+            impl #borrowed_impl_generics ::bilrost::encoding::NonEmptyOneofBorrowDecoder<'__a>
+            for #ident #ty_generics #borrowed_where_clause
+            {
+                fn oneof_borrow_decode_field(
+                    field: &mut ::core::option::Option<Self>,
+                    tag: u32,
+                    wire_type: ::bilrost::encoding::WireType,
+                    duplicated: bool,
+                    buf: ::bilrost::encoding::Capped<&'__a [u8]>,
+                    ctx: ::bilrost::encoding::DecodeContext,
+                ) -> ::core::result::Result<(), ::bilrost::DecodeError> {
+                    match tag {
+                        #(#borrow_decode,)*
+                        _ => unreachable!(
+                            concat!("invalid ", stringify!(#ident), " tag: {}"), tag,
+                        ),
+                    }
+                }
+            }
         }
     };
 
@@ -1301,40 +2121,63 @@ fn try_distinguished_oneof(input: TokenStream) -> Result<TokenStream, Error> {
         fields,
         empty_variant,
     } = preprocess_oneof(&input)?;
+    let borrowed_impl_generics = borrowed_impl_generics(&input.generics);
     let expanded = if let Some(empty_ident) = empty_variant {
+        let borrowed_where_clause = append_borrowed_distinguished_encoder_wheres(
+            where_clause,
+            Some(quote!(Self: ::bilrost::encoding::DistinguishedOneof)),
+            &fields,
+        );
+        let decoder_where_clause = append_distinguished_decoder_wheres(
+            where_clause,
+            Some(quote! {
+                Self: ::bilrost::encoding::DistinguishedOneof + ::bilrost::encoding::OneofDecoder
+            }),
+            &fields,
+        );
         let where_clause = append_distinguished_encoder_wheres(
             where_clause,
             Some(quote!(Self: ::bilrost::encoding::Oneof)),
             &fields,
         );
-        let decode = fields.iter().map(|(variant_ident, field)| {
-            let tag = field.first_tag();
-            let decode = field.decode_distinguished(quote!(value));
-            let with_new_value = field.with_value(quote!(new_value));
-            let with_value = field.with_value(quote!(value));
-            quote! {
-                #tag => match self {
-                    #ident::#empty_ident => {
-                        let mut new_value =
-                            ::bilrost::encoding::NewForOverwrite::new_for_overwrite();
-                        let mut value = &mut new_value;
-                        let canon = #decode?;
-                        *self = #ident::#variant_ident #with_new_value;
-                        Ok(canon)
-                    }
-                    #ident::#variant_ident #with_value => {
-                        #decode
+        let decode_arms = |decode_field: fn(&Field, TokenStream) -> TokenStream| {
+            fields
+                .iter()
+                .map(|(variant_ident, field)| {
+                    let tag = field.first_tag();
+                    let decode = decode_field(field, quote!(value));
+                    let with_new_value = field.with_value(quote!(new_value));
+                    let with_value = field.with_value(quote!(value));
+                    quote! {
+                        #tag => match self {
+                            #ident::#empty_ident => {
+                                let mut new_value =
+                                    ::bilrost::encoding::NewForOverwrite::new_for_overwrite();
+                                let mut value = &mut new_value;
+                                let canon = #decode?;
+                                *self = #ident::#variant_ident #with_new_value;
+                                Ok(canon)
+                            }
+                            #ident::#variant_ident #with_value => {
+                                #decode
+                            }
+                            _ => Err(::bilrost::DecodeError::new(
+                                ::bilrost::DecodeErrorKind::ConflictingFields
+                            )),
+                        }
                     }
-                    _ => Err(::bilrost::DecodeError::new(
-                        ::bilrost::DecodeErrorKind::ConflictingFields
-                    )),
-                }
-            }
-        });
+                })
+                .collect::<Vec<_>>()
+        };
+        let decode = decode_arms(Field::decode_distinguished);
+        let borrow_decode = decode_arms(Field::borrow_decode_distinguished);
 
         quote! {
             impl #impl_generics ::bilrost::encoding::DistinguishedOneof
-            for #ident #ty_generics #where_clause
+            for #ident #ty_generics #where_clause {}
+
+            impl #impl_generics ::bilrost::encoding::DistinguishedOneofDecoder
+            for #ident #ty_generics #decoder_where_clause
             {
                 fn oneof_decode_field_distinguished<__B: ::bilrost::bytes::Buf + ?Sized>(
                     &mut self,
@@ -1352,37 +2195,80 @@ fn try_distinguished_oneof(input: TokenStream) -> Result<TokenStream, Error> {
                     }
                 }
             }
+
+            impl #borrowed_impl_generics ::bilrost::encoding::DistinguishedOneofBorrowDecoder<'__a>
+            for #ident #ty_generics #borrowed_where_clause
+            {
+                fn oneof_borrow_decode_field_distinguished(
+                    &mut self,
+                    tag: u32,
+                    wire_type: ::bilrost::encoding::WireType,
+                    duplicated: bool,
+                    buf: ::bilrost::encoding::Capped<&'__a [u8]>,
+                    ctx: ::bilrost::encoding::DecodeContext,
+                ) -> ::core::result::Result<::bilrost::Canonicity, ::bilrost::DecodeError> {
+                    match tag {
+                        #(#borrow_decode,)*
+                        _ => unreachable!(
+                            concat!("invalid ", stringify!(#ident), " tag: {}"), tag,
+                        ),
+                    }
+                }
+            }
         }
     } else {
+        let borrowed_where_clause = append_borrowed_distinguished_encoder_wheres(
+            where_clause,
+            Some(quote!(Self: ::bilrost::encoding::NonEmptyDistinguishedOneof)),
+            &fields,
+        );
+        let decoder_where_clause = append_distinguished_decoder_wheres(
+            where_clause,
+            Some(quote! {
+                Self: ::bilrost::encoding::NonEmptyDistinguishedOneof
+                    + ::bilrost::encoding::NonEmptyOneofDecoder
+            }),
+            &fields,
+        );
         let where_clause = append_distinguished_encoder_wheres(where_clause, None, &fields);
-        let decode = fields.iter().map(|(variant_ident, field)| {
-            let tag = field.first_tag();
-            let decode = field.decode_distinguished(quote!(value));
-            let with_new_value = field.with_value(quote!(new_value));
-            let with_value = field.with_value(quote!(value));
-            quote! {
-                #tag => match field {
-                    ::core::option::Option::None => {
-                        let mut new_value =
-                            ::bilrost::encoding::NewForOverwrite::new_for_overwrite();
-                        let value = &mut new_value;
-                        let canon = #decode?;
-                        *field = Some(#ident::#variant_ident #with_new_value);
-                        Ok(canon)
-                    }
-                    ::core::option::Option::Some(#ident::#variant_ident #with_value) => {
-                        #decode
+        let decode_arms = |decode_field: fn(&Field, TokenStream) -> TokenStream| {
+            fields
+                .iter()
+                .map(|(variant_ident, field)| {
+                    let tag = field.first_tag();
+                    let decode = decode_field(field, quote!(value));
+                    let with_new_value = field.with_value(quote!(new_value));
+                    let with_value = field.with_value(quote!(value));
+                    quote! {
+                        #tag => match field {
+                            ::core::option::Option::None => {
+                                let mut new_value =
+                                    ::bilrost::encoding::NewForOverwrite::new_for_overwrite();
+                                let value = &mut new_value;
+                                let canon = #decode?;
+                                *field = Some(#ident::#variant_ident #with_new_value);
+                                Ok(canon)
+                            }
+                            ::core::option::Option::Some(#ident::#variant_ident #with_value) => {
+                                #decode
+                            }
+                            _ => Err(::bilrost::DecodeError::new(
+                                ::bilrost::DecodeErrorKind::ConflictingFields
+                            )),
+                        }
                     }
-                    _ => Err(::bilrost::DecodeError::new(
-                        ::bilrost::DecodeErrorKind::ConflictingFields
-                    )),
-                }
-            }
-        });
+                })
+                .collect::<Vec<_>>()
+        };
+        let decode = decode_arms(Field::decode_distinguished);
+        let borrow_decode = decode_arms(Field::borrow_decode_distinguished);
 
         quote! {
             impl #impl_generics ::bilrost::encoding::NonEmptyDistinguishedOneof
-            for #ident #ty_generics #where_clause
+            for #ident #ty_generics #where_clause {}
+
+            impl #impl_generics ::bilrost::encoding::NonEmptyDistinguishedOneofDecoder
+            for #ident #ty_generics #decoder_where_clause
             {
                 fn oneof_decode_field_distinguished<__B: ::bilrost::bytes::Buf + ?Sized>(
                     field: &mut ::core::option::Option<Self>,
@@ -1400,6 +2286,27 @@ fn try_distinguished_oneof(input: TokenStream) -> Result<TokenStream, Error> {
                     }
                 }
             }
+
+            impl #borrowed_impl_generics
+            ::bilrost::encoding::NonEmptyDistinguishedOneofBorrowDecoder<'__a>
+            for #ident #ty_generics #borrowed_where_clause
+            {
+                fn oneof_borrow_decode_field_distinguished(
+                    field: &mut ::core::option::Option<Self>,
+                    tag: u32,
+                    wire_type: ::bilrost::encoding::WireType,
+                    duplicated: bool,
+                    buf: ::bilrost::encoding::Capped<&'__a [u8]>,
+                    ctx: ::bilrost::encoding::DecodeContext,
+                ) -> ::core::result::Result<::bilrost::Canonicity, ::bilrost::DecodeError> {
+                    match tag {
+                        #(#borrow_decode,)*
+                        _ => unreachable!(
+                            concat!("invalid ", stringify!(#ident), " tag: {}"), tag,
+                        ),
+                    }
+                }
+            }
         }
     };
 
@@ -1420,6 +2327,167 @@ pub fn distinguished_oneof(input: proc_macro::TokenStream) -> proc_macro::TokenS
     try_distinguished_oneof(input.into()).unwrap().into()
 }
 
+fn try_canonical_ord(input: TokenStream) -> Result<TokenStream, Error> {
+    let input: DeriveInput = parse2(input)?;
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    // Fields that recurse can't be bounded in the where clause without making the bound cyclic, so
+    // their types are checked for a canonical ordering in the body of `cmp` instead.
+    let mut recursive_types = Vec::new();
+    let (where_clause, cmp) = match &input.data {
+        Data::Struct(..) => {
+            let PreprocessedMessage {
+                unsorted_fields,
+                has_ignored_fields,
+                extensions,
+                ..
+            } = preprocess_message(&input)?;
+            if has_ignored_fields {
+                bail!("messages with ignored fields cannot have a canonical ordering");
+            }
+            if extensions.is_some() {
+                bail!("messages with extensions cannot have a canonical ordering");
+            }
+            recursive_types.extend(
+                unsorted_fields
+                    .iter()
+                    .filter_map(|(_, field)| field.canonical_ord_recursive_type())
+                    .cloned(),
+            );
+            let where_clause = impl_append_wheres(
+                where_clause,
+                None,
+                unsorted_fields
+                    .iter()
+                    .flat_map(|(_, field)| field.canonical_ord_where_terms()),
+            );
+            // Fields are compared in the order of their tags; oneofs by their lowest tag.
+            let comparisons = unsorted_fields
+                .iter()
+                .sorted_unstable_by_key(|(_, field)| field.first_tag())
+                .map(|(field_ident, _)| {
+                    quote! {
+                        .then_with(|| ::core::cmp::Ord::cmp(&self.#field_ident, &other.#field_ident))
+                    }
+                });
+            (
+                where_clause,
+                quote!(::core::cmp::Ordering::Equal #(#comparisons)*),
+            )
+        }
+        Data::Enum(DataEnum { variants, .. })
+            if variants
+                .iter()
+                .all(|variant| matches!(variant.fields, Fields::Unit)) =>
+        {
+            // Enumerations, whose variants are all units, are compared by their numbers.
+            let where_clause = impl_append_wheres(
+                where_clause,
+                Some(quote!(Self: ::bilrost::Enumeration)),
+                None.into_iter(),
+            );
+            let cmp = quote! {
+                ::core::cmp::Ord::cmp(
+                    &::bilrost::Enumeration::to_number(self),
+                    &::bilrost::Enumeration::to_number(other),
+                )
+            };
+            (where_clause, cmp)
+        }
+        Data::Enum(..) => {
+            let PreprocessedOneof {
+                fields,
+                empty_variant,
+                ..
+            } = preprocess_oneof(&input)?;
+            recursive_types.extend(
+                fields
+                    .iter()
+                    .filter_map(|(_, field)| field.canonical_ord_recursive_type())
+                    .cloned(),
+            );
+            let where_clause = impl_append_wheres(
+                where_clause,
+                None,
+                fields
+                    .iter()
+                    .flat_map(|(_, field)| field.canonical_ord_where_terms()),
+            );
+            let tags = empty_variant
+                .iter()
+                .map(|empty_ident| quote!(#ident::#empty_ident { .. } => ::core::option::Option::None))
+                .chain(fields.iter().map(|(variant_ident, field)| {
+                    let tag = field.first_tag();
+                    quote!(#ident::#variant_ident { .. } => ::core::option::Option::Some(#tag))
+                }));
+            let same_variant = fields.iter().map(|(variant_ident, field)| {
+                let with_a = field.with_value(quote!(a));
+                let with_b = field.with_value(quote!(b));
+                quote! {
+                    (#ident::#variant_ident #with_a, #ident::#variant_ident #with_b) => {
+                        ::core::cmp::Ord::cmp(a, b)
+                    }
+                }
+            });
+            // Oneofs are compared by the tag of the field that is set, with no field first, and
+            // then by the value of the field.
+            let cmp = quote! {
+                let tag = |value: &Self| -> ::core::option::Option<u32> {
+                    match value {
+                        #(#tags,)*
+                    }
+                };
+                #[allow(unreachable_patterns)]
+                match (self, other) {
+                    #(#same_variant)*
+                    _ => ::core::cmp::Ord::cmp(&tag(self), &tag(other)),
+                }
+            };
+            (where_clause, cmp)
+        }
+        Data::Union(..) => bail!("CanonicalOrd can not be derived for a union"),
+    };
+
+    let recursive_checks = if recursive_types.is_empty() {
+        quote!()
+    } else {
+        quote! {
+            fn assert_canonical_ord<T: ::bilrost::encoding::CanonicalOrd + ?Sized>() {}
+            #(assert_canonical_ord::<#recursive_types>();)*
+        }
+    };
+
+    Ok(quote! {
+        impl #impl_generics ::core::cmp::PartialOrd for #ident #ty_generics #where_clause {
+            #[inline]
+            fn partial_cmp(
+                &self,
+                other: &Self,
+            ) -> ::core::option::Option<::core::cmp::Ordering> {
+                ::core::option::Option::Some(::core::cmp::Ord::cmp(self, other))
+            }
+        }
+
+        impl #impl_generics ::core::cmp::Ord for #ident #ty_generics #where_clause {
+            fn cmp(&self, other: &Self) -> ::core::cmp::Ordering {
+                #recursive_checks
+                #cmp
+            }
+        }
+
+        impl #impl_generics ::bilrost::encoding::CanonicalOrd
+        for #ident #ty_generics #where_clause {}
+    })
+}
+
+/// Derives `PartialOrd`, `Ord`, and `CanonicalOrd` for a message, enumeration, or oneof, ordering
+/// its values by the standardized canonical ordering for their type.
+#[proc_macro_derive(CanonicalOrd, attributes(bilrost))]
+pub fn canonical_ord(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    try_canonical_ord(input.into()).unwrap().into()
+}
+
 #[cfg(test)]
 mod test {
     use alloc::format;
@@ -1427,7 +2495,7 @@ mod test {
 
     use quote::quote;
 
-    use crate::{try_enumeration, try_message, try_oneof};
+    use crate::{try_canonical_ord, try_enumeration, try_message, try_oneof};
 
     #[test]
     fn test_rejects_colliding_message_fields() {
@@ -1530,6 +2598,103 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_rejects_invalid_extensions_fields() {
+        let output = try_message(quote! {
+            struct Invalid {
+                a: bool,
+                #[bilrost(extensions)]
+                b: OpaqueMessage<'static>,
+                #[bilrost(extensions)]
+                c: (),
+            }
+        });
+        assert_eq!(
+            output
+                .expect_err("multiple extensions fields not detected")
+                .to_string(),
+            "message Invalid has multiple extensions fields: b and c"
+        );
+
+        let output = try_message(quote! {
+            struct Invalid {
+                a: bool,
+                #[bilrost(extensions, tag = 2)]
+                b: OpaqueMessage<'static>,
+            }
+        });
+        assert_eq!(
+            format!("{:#}", output.expect_err("tagged extensions not detected")),
+            "invalid message field Invalid.b: extensions attribute mixed with other attributes on \
+            the same field"
+        );
+
+        _ = try_message(quote! {
+            struct Valid {
+                a: bool,
+                #[bilrost(extensions)]
+                b: OpaqueMessage<'static>,
+                #[bilrost(oneof(2, 3))]
+                c: SomeOneof,
+            }
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn test_rejects_inapplicable_merge_modes() {
+        let output = try_message(quote! {
+            struct Invalid {
+                #[bilrost(tag = 1, encoding(varint), merge = "append")]
+                a: u32,
+            }
+        });
+        assert_eq!(
+            format!("{:#}", output.expect_err("append on a scalar not detected")),
+            "invalid message field Invalid.a: merge = \"append\" cannot be used with the varint \
+            encoding; it only applies to collection and map fields"
+        );
+
+        let output = try_message(quote! {
+            struct Invalid {
+                #[bilrost(tag = 1, encoding(packed), merge = "recurse")]
+                a: Vec<u32>,
+            }
+        });
+        assert_eq!(
+            format!(
+                "{:#}",
+                output.expect_err("recurse on a collection not detected")
+            ),
+            "invalid message field Invalid.a: merge = \"recurse\" cannot be used with the packed \
+            encoding; it only applies to nested message fields"
+        );
+
+        let output = try_message(quote! {
+            struct Invalid {
+                #[bilrost(tag = 1, encoding(map<general, general>), merge = "recurse")]
+                a: BTreeMap<u32, Inner>,
+            }
+        });
+        assert_eq!(
+            format!("{:#}", output.expect_err("recurse on a map not detected")),
+            "invalid message field Invalid.a: merge = \"recurse\" cannot be used with the map \
+            encoding; it only applies to nested message fields"
+        );
+
+        _ = try_message(quote! {
+            struct Valid {
+                #[bilrost(tag = 1, encoding(packed), merge = "append")]
+                a: Vec<u32>,
+                #[bilrost(tag = 2, merge = "recurse")]
+                b: Inner,
+                #[bilrost(tag = 3, encoding(varint), merge = "replace")]
+                c: u32,
+            }
+        })
+        .unwrap();
+    }
+
     #[test]
     fn test_rejects_colliding_oneof_variants() {
         let output = try_oneof(quote! {
@@ -1830,4 +2995,45 @@ mod test {
             "Enumeration must have at least one variant"
         );
     }
+
+    #[test]
+    fn test_rejects_canonical_ord_for_incomplete_messages() {
+        let output = try_canonical_ord(quote! {
+            struct Invalid {
+                #[bilrost(1)]
+                a: bool,
+                #[bilrost(ignore)]
+                b: u32,
+            }
+        });
+        assert_eq!(
+            output.expect_err("ignored field not detected").to_string(),
+            "messages with ignored fields cannot have a canonical ordering"
+        );
+
+        let output = try_canonical_ord(quote! {
+            struct Invalid {
+                #[bilrost(1)]
+                a: bool,
+                #[bilrost(extensions)]
+                b: OpaqueMessage<'static>,
+            }
+        });
+        assert_eq!(
+            output.expect_err("extensions not detected").to_string(),
+            "messages with extensions cannot have a canonical ordering"
+        );
+
+        _ = try_canonical_ord(quote! {
+            struct Valid {
+                #[bilrost(2)]
+                a: bool,
+                #[bilrost(1)]
+                b: u32,
+                #[bilrost(oneof(3, 4))]
+                c: SomeOneof,
+            }
+        })
+        .unwrap();
+    }
 }
@@ -0,0 +1,624 @@
+#![doc(html_root_url = "https://docs.rs/bilrost-json/0.1005.0-dev")]
+
+//! `bilrost-json` converts [`bilrost`][bilrost] messages to and from JSON with a single, documented
+//! mapping, so that services and web clients can exchange the same message types as JSON.
+//!
+//! Any message can be converted, driven by its `MessageDescriptor`: derived message types with
+//! [`to_string`] and [`from_str`], and messages whose structure is only known at runtime with
+//! [`dynamic_to_value`] and [`dynamic_from_value`].
+//!
+//! # The mapping
+//!
+//! A message is a JSON object, whose members are its fields keyed by their names:
+//!
+//! * Fields that are empty are omitted, just as they are not encoded. Optional fields are present
+//!   whenever they are `Some`, even when their value is empty.
+//! * Each variant of a oneof is keyed by its own name, and only the variant that is set appears.
+//! * Unknown fields, such as those captured in extensions, are keyed by their tag in decimal. Their
+//!   values are arrays of strings, each holding one value in the text format of `OpaqueValue`.
+//!
+//! Values are mapped by their kind:
+//!
+//! | Kind                             | JSON                                                     |
+//! |----------------------------------|----------------------------------------------------------|
+//! | `bool`                           | `true` or `false`                                        |
+//! | `u8`, `u16`, `u32`, `i8`, `i16`, `i32` | number                                             |
+//! | `u64`, `i64`, `u128`, `i128`     | decimal string, like `"-12"`                             |
+//! | `f32`, `f64`                     | number, or `"NaN"`, `"Infinity"`, or `"-Infinity"`       |
+//! | `string`                         | string                                                   |
+//! | `bytes`                          | standard base64 string with padding                      |
+//! | enumeration                      | the name of the variant                                  |
+//! | message                          | object                                                   |
+//! | `bilrost_types::Timestamp`       | RFC 3339 string, like `"1970-01-01T00:00:01.500Z"`       |
+//! | `bilrost_types::Duration`        | seconds with an `s` suffix, like `"1.500s"`              |
+//! | `list<T>`, `set<T>`              | array                                                    |
+//! | `map<K, V>` with scalar keys     | object; keys are written as strings like the values      |
+//! | `map<K, V>` with other keys      | array of `[key, value]` pairs                            |
+//!
+//! Map keys are scalar when they are `bool`, integers, `string`, or enumerations; integer keys are
+//! always written in decimal, like `{"5": "five"}`.
+//!
+//! # Parsing
+//!
+//! Parsing is strict. Names that are not fields of the message or variants of the enumeration are
+//! rejected, as are tags of unknown fields unless the message has extensions to keep them. Setting
+//! more than one variant of a oneof, or the same field both by name and by tag, is rejected too.
+//! Where the mapping allows some leniency, parsing accepts: any integer may be given either as a
+//! number or as a decimal string, bytes may also be in the URL-safe base64 alphabet (though not
+//! mixed with the standard one) and without padding, and `null` is the same as omitting the field.
+//!
+//! [bilrost]: https://docs.rs/bilrost
+
+use std::fmt::{self, Display};
+use std::iter::Peekable;
+
+use base64::engine::{general_purpose, DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig};
+use base64::{alphabet, Engine};
+use bilrost::descriptor::{FieldDescriptor, MessageDescriptor, ValueKind};
+use bilrost::dynamic::{DynamicMessage, Value};
+use bilrost::encoding::opaque::{OpaqueMessage, OpaqueValue};
+use bilrost::{Message, OwnedMessage, RawMessage};
+use bilrost_types::{Duration, Timestamp};
+use serde_json::{Map, Number, Value as Json};
+
+/// An error converting a message to or from JSON.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Error {
+    path: String,
+    message: String,
+}
+
+impl Error {
+    fn new(message: impl Into<String>) -> Self {
+        Self {
+            path: String::new(),
+            message: message.into(),
+        }
+    }
+
+    /// Prepends a field name, or an index in brackets, to the path of the error.
+    fn within(mut self, name: &str) -> Self {
+        if !self.path.is_empty() && !self.path.starts_with('[') {
+            self.path.insert(0, '.');
+        }
+        self.path.insert_str(0, name);
+        self
+    }
+
+    /// Returns the path to the value where the error was found, like `items[2].name`. The path is
+    /// empty when the error is in the top-level message.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.path.is_empty() {
+            f.write_str(&self.message)
+        } else {
+            write!(f, "{}: {}", self.path, self.message)
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Converts a message to JSON.
+pub fn to_value<M: Message>(message: &M) -> Json {
+    let dynamic = DynamicMessage::decode(message.descriptor(), message.encode_to_vec().as_slice())
+        .expect("a message always decodes with its own descriptor");
+    dynamic_to_value(&dynamic)
+}
+
+/// Converts a message to a JSON string with no extra whitespace.
+pub fn to_string<M: Message>(message: &M) -> String {
+    to_value(message).to_string()
+}
+
+/// Parses a message from JSON.
+pub fn from_value<M: OwnedMessage>(json: &Json) -> Result<M, Error> {
+    let dynamic = dynamic_from_value(M::empty().descriptor(), json)?;
+    M::decode(dynamic.encode_to_vec().as_slice()).map_err(|err| Error::new(err.to_string()))
+}
+
+/// Parses a message from a JSON string.
+pub fn from_str<M: OwnedMessage>(text: &str) -> Result<M, Error> {
+    let json: Json = serde_json::from_str(text).map_err(|err| Error::new(err.to_string()))?;
+    from_value(&json)
+}
+
+/// Converts a message with a runtime descriptor to JSON.
+pub fn dynamic_to_value(message: &DynamicMessage) -> Json {
+    if let Some(text) = well_known_to_string(message) {
+        return Json::String(text);
+    }
+    let mut object = Map::new();
+    let mut unknown = message.unknown_fields().iter().peekable();
+    for (field, value) in message.iter() {
+        while let Some((tag, _)) = unknown.peek() {
+            if **tag > field.tag {
+                break;
+            }
+            unknown_to_value(&mut object, &mut unknown);
+        }
+        let kind = match field.value {
+            ValueKind::Optional(inner) => inner,
+            kind => kind,
+        };
+        object.insert(field.name.into(), value_to_json(kind, value));
+    }
+    while unknown.peek().is_some() {
+        unknown_to_value(&mut object, &mut unknown);
+    }
+    Json::Object(object)
+}
+
+/// Parses a message with a runtime descriptor from JSON.
+pub fn dynamic_from_value(
+    descriptor: &'static MessageDescriptor,
+    json: &Json,
+) -> Result<DynamicMessage, Error> {
+    let mut message = DynamicMessage::new(descriptor);
+    if let Some(parsed) = well_known_from_json(&mut message, json) {
+        parsed?;
+        return Ok(message);
+    }
+    let Json::Object(object) = json else {
+        return Err(Error::new(format!(
+            "expected a {} object, found {}",
+            descriptor.name,
+            json_kind(json)
+        )));
+    };
+    let mut seen = Vec::new();
+    for (key, json) in object {
+        let field = match descriptor.field_by_name(key) {
+            Some(field) => Some(field),
+            None => match key.parse::<u32>() {
+                Ok(tag) if key == &tag.to_string() => match descriptor.field_by_tag(tag) {
+                    Some(field) => Some(field),
+                    None if descriptor.has_extensions => {
+                        unknown_from_json(&mut message, tag, json)
+                            .map_err(|err| err.within(key))?;
+                        continue;
+                    }
+                    None => None,
+                },
+                _ => None,
+            },
+        };
+        let Some(field) = field else {
+            return Err(Error::new(format!(
+                "unknown field `{key}` in {}",
+                descriptor.name
+            )));
+        };
+        if seen.contains(&field.tag) {
+            return Err(Error::new(format!("field `{}` is given twice", field.name)));
+        }
+        seen.push(field.tag);
+        if let Some(oneof) = descriptor.oneof_for_tag(field.tag) {
+            if oneof
+                .fields
+                .iter()
+                .any(|variant| variant.tag != field.tag && seen.contains(&variant.tag))
+            {
+                return Err(Error::new(format!(
+                    "more than one field of oneof `{}` is set",
+                    oneof.name
+                )));
+            }
+        }
+        if json.is_null() {
+            continue;
+        }
+        set_field(&mut message, field, json).map_err(|err| err.within(key))?;
+    }
+    Ok(message)
+}
+
+fn set_field(
+    message: &mut DynamicMessage,
+    field: &'static FieldDescriptor,
+    json: &Json,
+) -> Result<(), Error> {
+    let kind = match field.value {
+        ValueKind::Optional(inner) => inner,
+        kind => kind,
+    };
+    let value = value_from_json(kind, json)?;
+    message
+        .set_by_tag(field.tag, value)
+        .map_err(|err| Error::new(err.to_string()))
+}
+
+fn json_kind(json: &Json) -> &'static str {
+    match json {
+        Json::Null => "null",
+        Json::Bool(_) => "a boolean",
+        Json::Number(_) => "a number",
+        Json::String(_) => "a string",
+        Json::Array(_) => "an array",
+        Json::Object(_) => "an object",
+    }
+}
+
+/// Returns true if the descriptors describe messages with the same name and fields.
+fn same_message(a: &MessageDescriptor, b: &MessageDescriptor) -> bool {
+    a.name == b.name
+        && a.oneofs.is_empty() == b.oneofs.is_empty()
+        && a.fields.len() == b.fields.len()
+        && a.fields
+            .iter()
+            .zip(b.fields)
+            .all(|(a, b)| a.name == b.name && a.tag == b.tag && a.value == b.value)
+}
+
+/// Reads the seconds and nanos fields shared by `Timestamp` and `Duration`.
+fn seconds_and_nanos(message: &DynamicMessage) -> (i64, i32) {
+    let seconds = match message.get("seconds") {
+        Some(Value::I64(seconds)) => *seconds,
+        _ => 0,
+    };
+    let nanos = match message.get("nanos") {
+        Some(Value::I32(nanos)) => *nanos,
+        _ => 0,
+    };
+    (seconds, nanos)
+}
+
+/// Returns the string form of the message if it is a `Timestamp` or `Duration`.
+fn well_known_to_string(message: &DynamicMessage) -> Option<String> {
+    let descriptor = message.descriptor();
+    if !message.unknown_fields().is_empty() {
+        return None;
+    }
+    let (seconds, nanos) = seconds_and_nanos(message);
+    if same_message(descriptor, Timestamp::DESCRIPTOR) {
+        Some(Timestamp { seconds, nanos }.to_string())
+    } else if same_message(descriptor, Duration::DESCRIPTOR) {
+        Some(Duration { seconds, nanos }.to_string())
+    } else {
+        None
+    }
+}
+
+/// Parses the message from its string form if it is a `Timestamp` or `Duration`.
+fn well_known_from_json(message: &mut DynamicMessage, json: &Json) -> Option<Result<(), Error>> {
+    let descriptor = message.descriptor();
+    let (name, parsed) = if same_message(descriptor, Timestamp::DESCRIPTOR) {
+        let parse = |text: &str| {
+            let timestamp = text.parse::<Timestamp>().ok()?;
+            Some((timestamp.seconds, timestamp.nanos))
+        };
+        ("timestamp", json.as_str().map(parse))
+    } else if same_message(descriptor, Duration::DESCRIPTOR) {
+        let parse = |text: &str| {
+            let duration = text.parse::<Duration>().ok()?;
+            Some((duration.seconds, duration.nanos))
+        };
+        ("duration", json.as_str().map(parse))
+    } else {
+        return None;
+    };
+    Some(match parsed {
+        Some(Some((seconds, nanos))) => {
+            message
+                .set("seconds", Value::I64(seconds))
+                .and_then(|()| message.set("nanos", Value::I32(nanos)))
+                .expect("well-known fields have the right kinds");
+            Ok(())
+        }
+        Some(None) => Err(Error::new(format!("invalid {name} string"))),
+        None => Err(Error::new(format!(
+            "expected a {name} string, found {}",
+            json_kind(json)
+        ))),
+    })
+}
+
+/// Writes all the unknown fields with the next tag to the object.
+fn unknown_to_value<'a>(
+    object: &mut Map<String, Json>,
+    unknown: &mut Peekable<impl Iterator<Item = (&'a u32, &'a OpaqueValue<'static>)>>,
+) {
+    let (&tag, value) = unknown.next().expect("there is a next unknown field");
+    let mut values = vec![Json::String(value.to_string())];
+    while let Some((_, value)) = unknown.next_if(|(next, _)| **next == tag) {
+        values.push(Json::String(value.to_string()));
+    }
+    object.insert(tag.to_string(), Json::Array(values));
+}
+
+/// Adds the unknown field values in the JSON to the message.
+fn unknown_from_json(message: &mut DynamicMessage, tag: u32, json: &Json) -> Result<(), Error> {
+    let Json::Array(values) = json else {
+        return Err(Error::new(format!(
+            "expected an array of unknown field values, found {}",
+            json_kind(json)
+        )));
+    };
+    for (index, value) in values.iter().enumerate() {
+        let parsed = value
+            .as_str()
+            .and_then(|text| format!("{tag}: {text}").parse::<OpaqueMessage>().ok())
+            .filter(|parsed| parsed.len() == 1);
+        let Some(parsed) = parsed else {
+            return Err(Error::new("expected an opaque value string").within(&format!("[{index}]")));
+        };
+        for (tag, value) in parsed {
+            message.unknown_fields_mut().insert(tag, value);
+        }
+    }
+    Ok(())
+}
+
+/// Returns the kinds of the keys and values of a map kind.
+fn map_kinds(kind: &'static ValueKind) -> (&'static ValueKind, &'static ValueKind) {
+    match kind {
+        ValueKind::Map(key, value) => (key, value),
+        _ => (&ValueKind::Unknown, &ValueKind::Unknown),
+    }
+}
+
+/// Returns true if maps with keys of this kind are represented as JSON objects.
+fn is_scalar_key(kind: &ValueKind) -> bool {
+    matches!(
+        kind,
+        ValueKind::Bool
+            | ValueKind::U8
+            | ValueKind::U16
+            | ValueKind::U32
+            | ValueKind::U64
+            | ValueKind::U128
+            | ValueKind::I8
+            | ValueKind::I16
+            | ValueKind::I32
+            | ValueKind::I64
+            | ValueKind::I128
+            | ValueKind::String
+            | ValueKind::Enumeration(_)
+    )
+}
+
+fn float_to_json(value: f64) -> Json {
+    match Number::from_f64(value) {
+        Some(number) => Json::Number(number),
+        None if value.is_nan() => Json::String("NaN".into()),
+        None if value > 0.0 => Json::String("Infinity".into()),
+        None => Json::String("-Infinity".into()),
+    }
+}
+
+fn value_to_json(kind: &'static ValueKind, value: &Value) -> Json {
+    match value {
+        Value::Bool(value) => Json::Bool(*value),
+        Value::U8(value) => Json::from(*value),
+        Value::U16(value) => Json::from(*value),
+        Value::U32(value) => Json::from(*value),
+        Value::I8(value) => Json::from(*value),
+        Value::I16(value) => Json::from(*value),
+        Value::I32(value) => Json::from(*value),
+        Value::U64(value) => Json::String(value.to_string()),
+        Value::I64(value) => Json::String(value.to_string()),
+        Value::U128(value) => Json::String(value.to_string()),
+        Value::I128(value) => Json::String(value.to_string()),
+        Value::F32(value) => float_to_json(f64::from(*value)),
+        Value::F64(value) => float_to_json(*value),
+        Value::String(value) => Json::String(value.clone()),
+        Value::Bytes(value) => Json::String(general_purpose::STANDARD.encode(value)),
+        Value::Enumeration(number) => match kind {
+            ValueKind::Enumeration(enumeration) => match enumeration.name_of(*number) {
+                Some(name) => Json::String(name.into()),
+                None => Json::from(*number),
+            },
+            _ => Json::from(*number),
+        },
+        Value::Message(message) => dynamic_to_value(message),
+        Value::List(items) | Value::Set(items) => {
+            let item_kind = match kind {
+                ValueKind::List(item) | ValueKind::Set(item) => item,
+                _ => &ValueKind::Unknown,
+            };
+            Json::Array(
+                items
+                    .iter()
+                    .map(|item| value_to_json(item_kind, item))
+                    .collect(),
+            )
+        }
+        Value::Map(entries) => {
+            let (key_kind, value_kind) = map_kinds(kind);
+            if is_scalar_key(key_kind) {
+                Json::Object(
+                    entries
+                        .iter()
+                        .map(|(key, value)| {
+                            let key = match value_to_json(key_kind, key) {
+                                Json::String(key) => key,
+                                key => key.to_string(),
+                            };
+                            (key, value_to_json(value_kind, value))
+                        })
+                        .collect(),
+                )
+            } else {
+                Json::Array(
+                    entries
+                        .iter()
+                        .map(|(key, value)| {
+                            Json::Array(vec![
+                                value_to_json(key_kind, key),
+                                value_to_json(value_kind, value),
+                            ])
+                        })
+                        .collect(),
+                )
+            }
+        }
+    }
+}
+
+/// Parses an integer given as a JSON number or a decimal string.
+fn integer_from_json<T: TryFrom<i128> + TryFrom<u128>>(
+    kind: &ValueKind,
+    json: &Json,
+) -> Result<T, Error> {
+    let integer = match json {
+        Json::Number(number) => number
+            .as_i64()
+            .map(i128::from)
+            .or_else(|| number.as_u64().map(i128::from))
+            .and_then(|integer| T::try_from(integer).ok()),
+        Json::String(text) => match text.parse::<i128>() {
+            Ok(integer) => T::try_from(integer).ok(),
+            // Only u128 values can be too large for i128.
+            Err(_) => text
+                .parse::<u128>()
+                .ok()
+                .and_then(|integer| T::try_from(integer).ok()),
+        },
+        _ => {
+            return Err(Error::new(format!(
+                "expected {kind}, found {}",
+                json_kind(json)
+            )))
+        }
+    };
+    integer.ok_or_else(|| Error::new(format!("{json} is not a valid {kind}")))
+}
+
+fn float_from_json(kind: &ValueKind, json: &Json) -> Result<f64, Error> {
+    match json {
+        Json::Number(number) => number
+            .as_f64()
+            .ok_or_else(|| Error::new(format!("{json} is not a valid {kind}"))),
+        Json::String(text) => match text.as_str() {
+            "NaN" => Ok(f64::NAN),
+            "Infinity" => Ok(f64::INFINITY),
+            "-Infinity" => Ok(f64::NEG_INFINITY),
+            _ => Err(Error::new(format!("{json} is not a valid {kind}"))),
+        },
+        _ => Err(Error::new(format!(
+            "expected {kind}, found {}",
+            json_kind(json)
+        ))),
+    }
+}
+
+fn value_from_json(kind: &'static ValueKind, json: &Json) -> Result<Value, Error> {
+    let expected = |what: &str| Error::new(format!("expected {what}, found {}", json_kind(json)));
+    Ok(match kind {
+        ValueKind::Bool => Value::Bool(json.as_bool().ok_or_else(|| expected("bool"))?),
+        ValueKind::U8 => Value::U8(integer_from_json(kind, json)?),
+        ValueKind::U16 => Value::U16(integer_from_json(kind, json)?),
+        ValueKind::U32 => Value::U32(integer_from_json(kind, json)?),
+        ValueKind::U64 => Value::U64(integer_from_json(kind, json)?),
+        ValueKind::U128 => Value::U128(integer_from_json(kind, json)?),
+        ValueKind::I8 => Value::I8(integer_from_json(kind, json)?),
+        ValueKind::I16 => Value::I16(integer_from_json(kind, json)?),
+        ValueKind::I32 => Value::I32(integer_from_json(kind, json)?),
+        ValueKind::I64 => Value::I64(integer_from_json(kind, json)?),
+        ValueKind::I128 => Value::I128(integer_from_json(kind, json)?),
+        ValueKind::F32 => {
+            let value = float_from_json(kind, json)?;
+            let single = value as f32;
+            if value.is_finite() && !single.is_finite() {
+                return Err(Error::new(format!("{json} is out of range for f32")));
+            }
+            Value::F32(single)
+        }
+        ValueKind::F64 => Value::F64(float_from_json(kind, json)?),
+        ValueKind::String => {
+            Value::String(json.as_str().ok_or_else(|| expected("a string"))?.into())
+        }
+        ValueKind::Bytes => {
+            let text = json.as_str().ok_or_else(|| expected("a base64 string"))?;
+            Value::Bytes(decode_base64(text).ok_or_else(|| Error::new("invalid base64"))?)
+        }
+        ValueKind::Enumeration(enumeration) => {
+            let name = json
+                .as_str()
+                .ok_or_else(|| expected(&format!("a {} name", enumeration.name)))?;
+            match enumeration.number_of(name) {
+                Some(number) => Value::Enumeration(number),
+                None => {
+                    return Err(Error::new(format!(
+                        "unknown {} value `{name}`",
+                        enumeration.name
+                    )))
+                }
+            }
+        }
+        ValueKind::Message(message) => Value::Message(dynamic_from_value(message.get(), json)?),
+        ValueKind::List(item) | ValueKind::Set(item) => {
+            let items = json.as_array().ok_or_else(|| expected("an array"))?;
+            let items = items
+                .iter()
+                .enumerate()
+                .map(|(index, json)| {
+                    value_from_json(item, json).map_err(|err| err.within(&format!("[{index}]")))
+                })
+                .collect::<Result<_, _>>()?;
+            match kind {
+                ValueKind::Set(_) => Value::Set(items),
+                _ => Value::List(items),
+            }
+        }
+        ValueKind::Map(key_kind, value_kind) => {
+            let mut entries = Vec::new();
+            if is_scalar_key(key_kind) {
+                let object = json.as_object().ok_or_else(|| expected("an object"))?;
+                for (key, json) in object {
+                    let key_json = match key_kind {
+                        ValueKind::Bool => match key.as_str() {
+                            "true" => Json::Bool(true),
+                            "false" => Json::Bool(false),
+                            _ => Json::String(key.clone()),
+                        },
+                        _ => Json::String(key.clone()),
+                    };
+                    let within = |err: Error| err.within(&format!("[{key:?}]"));
+                    entries.push((
+                        value_from_json(key_kind, &key_json).map_err(within)?,
+                        value_from_json(value_kind, json).map_err(within)?,
+                    ));
+                }
+            } else {
+                let pairs = json
+                    .as_array()
+                    .ok_or_else(|| expected("an array of pairs"))?;
+                for (index, pair) in pairs.iter().enumerate() {
+                    let within = |err: Error| err.within(&format!("[{index}]"));
+                    let Some([key, value]) = pair.as_array().map(Vec::as_slice) else {
+                        return Err(within(Error::new("expected a [key, value] pair")));
+                    };
+                    entries.push((
+                        value_from_json(key_kind, key).map_err(within)?,
+                        value_from_json(value_kind, value).map_err(within)?,
+                    ));
+                }
+            }
+            Value::Map(entries)
+        }
+        _ => {
+            return Err(Error::new(format!(
+                "values of kind {kind} are not supported"
+            )))
+        }
+    })
+}
+
+/// Decodes base64 in either the standard or the URL-safe alphabet, with or without padding, but
+/// not a mix of the two alphabets.
+fn decode_base64(text: &str) -> Option<Vec<u8>> {
+    const CONFIG: GeneralPurposeConfig =
+        GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent);
+    const STANDARD: GeneralPurpose = GeneralPurpose::new(&alphabet::STANDARD, CONFIG);
+    const URL_SAFE: GeneralPurpose = GeneralPurpose::new(&alphabet::URL_SAFE, CONFIG);
+    STANDARD
+        .decode(text)
+        .or_else(|_| URL_SAFE.decode(text))
+        .ok()
+}
@@ -0,0 +1,293 @@
+//! Tests the JSON mapping with derived message types.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use bilrost::encoding::opaque::{OpaqueMessage, OpaqueValue};
+use bilrost::encoding::EmptyState;
+use bilrost::{Enumeration, Message, Oneof};
+use bilrost_types::{Duration, Timestamp};
+use serde_json::json;
+
+#[derive(Clone, Debug, PartialEq, Eq, Enumeration)]
+enum Status {
+    Unknown = 0,
+    Active = 1,
+    Retired = 5,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Message)]
+struct Point {
+    #[bilrost(1)]
+    x: i32,
+    #[bilrost(2)]
+    y: i32,
+}
+
+#[derive(Clone, Debug, PartialEq, Oneof)]
+enum Contact {
+    Empty,
+    #[bilrost(20)]
+    Email(String),
+    #[bilrost(21)]
+    Phone(u64),
+}
+
+#[derive(Clone, Debug, PartialEq, Message)]
+struct Record {
+    #[bilrost(1)]
+    id: u64,
+    #[bilrost(2)]
+    delta: i64,
+    #[bilrost(3)]
+    small: i32,
+    #[bilrost(4)]
+    active: bool,
+    #[bilrost(5)]
+    name: String,
+    #[bilrost(tag(6), encoding(plainbytes))]
+    data: Vec<u8>,
+    #[bilrost(7)]
+    status: Status,
+    #[bilrost(8)]
+    ratio: f64,
+    #[bilrost(9)]
+    weight: f32,
+    #[bilrost(10)]
+    origin: Point,
+    #[bilrost(11)]
+    path: Vec<Point>,
+    #[bilrost(12)]
+    tags: BTreeSet<String>,
+    #[bilrost(13)]
+    counts: BTreeMap<u32, i64>,
+    #[bilrost(14)]
+    places: BTreeMap<Point, String>,
+    #[bilrost(15)]
+    created: Timestamp,
+    #[bilrost(16)]
+    timeout: Option<Duration>,
+    #[bilrost(17)]
+    maybe: Option<u32>,
+    #[bilrost(oneof(20, 21))]
+    contact: Contact,
+}
+
+#[derive(Clone, Debug, PartialEq, Message)]
+struct Extended {
+    #[bilrost(1)]
+    one: u32,
+    #[bilrost(extensions)]
+    ext: OpaqueMessage<'static>,
+}
+
+fn example() -> Record {
+    Record {
+        id: u64::MAX,
+        delta: -12,
+        small: -3,
+        active: true,
+        name: "hello".into(),
+        data: vec![0xfb, 0xff, 0x00, 0x01],
+        status: Status::Retired,
+        ratio: f64::NEG_INFINITY,
+        weight: 1.5,
+        origin: Point { x: 1, y: 0 },
+        path: vec![Point { x: 0, y: 0 }, Point { x: 2, y: 3 }],
+        tags: ["b".to_string(), "a".to_string()].into(),
+        counts: [(5, 50), (1, -1)].into(),
+        places: [(Point { x: 1, y: 2 }, "home".to_string())].into(),
+        created: Timestamp {
+            seconds: 1,
+            nanos: 500_000_000,
+        },
+        timeout: Some(Duration {
+            seconds: 0,
+            nanos: 0,
+        }),
+        maybe: Some(0),
+        contact: Contact::Phone(5551234),
+    }
+}
+
+#[test]
+fn maps_every_kind_of_value() {
+    let record = example();
+    let value = bilrost_json::to_value(&record);
+    assert_eq!(
+        value,
+        json!({
+            "id": "18446744073709551615",
+            "delta": "-12",
+            "small": -3,
+            "active": true,
+            "name": "hello",
+            "data": "+/8AAQ==",
+            "status": "Retired",
+            "ratio": "-Infinity",
+            "weight": 1.5,
+            "origin": {"x": 1},
+            "path": [{}, {"x": 2, "y": 3}],
+            "tags": ["a", "b"],
+            "counts": {"1": "-1", "5": "50"},
+            "places": [[{"x": 1, "y": 2}, "home"]],
+            "created": "1970-01-01T00:00:01.500Z",
+            "timeout": "0s",
+            "maybe": 0,
+            "Phone": "5551234",
+        })
+    );
+    assert_eq!(
+        bilrost_json::from_value::<Record>(&value),
+        Ok(record.clone())
+    );
+    let text = bilrost_json::to_string(&record);
+    assert_eq!(bilrost_json::from_str::<Record>(&text), Ok(record));
+}
+
+#[test]
+fn empty_fields_are_omitted() {
+    let empty = Record::empty();
+    assert_eq!(bilrost_json::to_string(&empty), "{}");
+    assert_eq!(bilrost_json::from_str::<Record>("{}"), Ok(empty.clone()));
+    assert_eq!(
+        bilrost_json::from_str::<Record>(r#"{"name": null, "Email": null, "maybe": null}"#),
+        Ok(empty)
+    );
+}
+
+#[test]
+fn parsing_is_lenient_where_specified() {
+    let parsed = bilrost_json::from_str::<Record>(
+        r#"{"1": 7, "delta": -2, "small": "4", "data": "-_8", "ratio": "NaN", "Email": "a@b"}"#,
+    )
+    .unwrap();
+    assert_eq!(parsed.id, 7);
+    assert_eq!(parsed.delta, -2);
+    assert_eq!(parsed.small, 4);
+    assert_eq!(parsed.data, [0xfb, 0xff]);
+    assert!(parsed.ratio.is_nan());
+    assert_eq!(parsed.contact, Contact::Email("a@b".into()));
+}
+
+#[test]
+fn parsing_is_strict() {
+    for (text, error) in [
+        (r#"{"nope": 1}"#, "unknown field `nope` in Record"),
+        (r#"{"99": 1}"#, "unknown field `99` in Record"),
+        (
+            r#"{"status": "Gone"}"#,
+            "status: unknown Status value `Gone`",
+        ),
+        (
+            r#"{"status": 1}"#,
+            "status: expected a Status name, found a number",
+        ),
+        (
+            r#"{"Email": "a", "Phone": 1}"#,
+            "more than one field of oneof `contact` is set",
+        ),
+        (r#"{"id": 1, "1": 1}"#, "field `id` is given twice"),
+        (
+            r#"{"small": 3000000000}"#,
+            "small: 3000000000 is not a valid i32",
+        ),
+        (r#"{"small": 1.5}"#, "small: 1.5 is not a valid i32"),
+        (
+            r#"{"weight": 1e300}"#,
+            "weight: 1e+300 is out of range for f32",
+        ),
+        (r#"{"data": "a"}"#, "data: invalid base64"),
+        (r#"{"data": "+_8"}"#, "data: invalid base64"),
+        (r#"{"data": "-_9"}"#, "data: invalid base64"),
+        (
+            r#"{"path": [{}, {"x": "y"}]}"#,
+            "path[1].x: \"y\" is not a valid i32",
+        ),
+        (
+            r#"{"counts": {"x": 1}}"#,
+            "counts[\"x\"]: \"x\" is not a valid u32",
+        ),
+        (
+            r#"{"created": "yesterday"}"#,
+            "created: invalid timestamp string",
+        ),
+        (
+            r#"{"origin": [1, 2]}"#,
+            "origin: expected a Point object, found an array",
+        ),
+        (r#"[]"#, "expected a Record object, found an array"),
+    ] {
+        let err = bilrost_json::from_str::<Record>(text).unwrap_err();
+        assert_eq!(err.to_string(), error, "{text}");
+    }
+}
+
+#[test]
+fn wide_integers_are_strings() {
+    #[derive(Clone, Debug, PartialEq, Message)]
+    struct Wide {
+        #[bilrost(1)]
+        big: u128,
+        #[bilrost(tag(2), encoding(fixed))]
+        negative: i128,
+        #[bilrost(3)]
+        by_key: BTreeMap<i128, u128>,
+    }
+
+    let wide = Wide {
+        big: u128::MAX,
+        negative: i128::MIN,
+        by_key: [(-1, 1 << 100)].into(),
+    };
+    let value = bilrost_json::to_value(&wide);
+    assert_eq!(
+        value,
+        json!({
+            "big": "340282366920938463463374607431768211455",
+            "negative": "-170141183460469231731687303715884105728",
+            "by_key": {"-1": "1267650600228229401496703205376"},
+        })
+    );
+    assert_eq!(bilrost_json::from_value::<Wide>(&value), Ok(wide));
+    assert_eq!(
+        bilrost_json::from_str::<Wide>(r#"{"big": 5, "negative": "-5"}"#),
+        Ok(Wide {
+            big: 5,
+            negative: -5,
+            by_key: BTreeMap::new(),
+        })
+    );
+    assert_eq!(
+        bilrost_json::from_str::<Wide>(r#"{"big": "-1"}"#)
+            .unwrap_err()
+            .to_string(),
+        "big: \"-1\" is not a valid u128"
+    );
+}
+
+#[test]
+fn unknown_fields_use_their_tags() {
+    let mut extended = Extended {
+        one: 1,
+        ext: OpaqueMessage::new(),
+    };
+    extended.ext.insert(3, OpaqueValue::u64(42));
+    extended.ext.insert(3, OpaqueValue::str("foo"));
+    extended.ext.insert(5, OpaqueValue::fixed_u32(7));
+    let value = bilrost_json::to_value(&extended);
+    assert_eq!(
+        value,
+        json!({
+            "one": 1,
+            "3": ["42", "\"foo\""],
+            "5": ["fixed32(7)"],
+        })
+    );
+    assert_eq!(bilrost_json::from_value::<Extended>(&value), Ok(extended));
+    assert_eq!(
+        bilrost_json::from_str::<Extended>(r#"{"3": ["1  4: 2"]}"#)
+            .unwrap_err()
+            .to_string(),
+        "3[0]: expected an opaque value string"
+    );
+}
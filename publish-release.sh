@@ -16,6 +16,8 @@ CRATES=( \
   "bilrost-derive" \
   "." \
   "bilrost-types" \
+  "bilrost-build" \
+  "bilrost-json" \
 )
 
 for CRATE in "${CRATES[@]}"; do
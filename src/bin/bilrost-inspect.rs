@@ -0,0 +1,405 @@
+//! Dumps the fields of encoded bilrost data without needing its schema.
+//!
+//! ```text
+//! bilrost-inspect [--hex | --base64] [--delimited] [--max-depth N] [FILE]
+//! ```
+//!
+//! Reads a single message, or with `--delimited` a stream of length-delimited messages, from FILE
+//! or from stdin when FILE is `-` or omitted. Input is raw binary unless `--hex` or `--base64` is
+//! given, in which case whitespace in it is ignored.
+//!
+//! Each field is shown on its own line with the byte offset of its key, its tag, the delta from the
+//! previous field's tag that is actually encoded in the key, and its value. Length-delimited values
+//! are shown as strings when they are printable UTF-8, as nested messages when they decode as a
+//! non-empty `OpaqueMessage`, as packed varints when they consist of two or more varints, and as
+//! hex bytes otherwise. Nested messages are only expanded up to `--max-depth` levels deep.
+//!
+//! Encodings that a derived message can never produce canonically are flagged on the line after
+//! the field with a `!`. The exit status is 1 if the data is not a valid encoding, 2 if the input
+//! can't be read, and 0 otherwise.
+
+use std::env;
+use std::fmt::Write;
+use std::fs;
+use std::io::{self, Read};
+use std::process::ExitCode;
+
+use base64::engine::{DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig};
+use base64::{alphabet, Engine};
+use bilrost::encoding::decode_varint;
+use bilrost::encoding::opaque::{OpaqueMessage, OpaqueValue};
+use bilrost::encoding::WireType;
+use bilrost::Message;
+
+const USAGE: &str =
+    "usage: bilrost-inspect [--hex | --base64] [--delimited] [--max-depth N] [FILE]";
+
+/// Same as the nesting limit of the `OpaqueMessage` text format.
+const DEFAULT_MAX_DEPTH: usize = 100;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Format {
+    Binary,
+    Hex,
+    Base64,
+}
+
+fn main() -> ExitCode {
+    let mut format = Format::Binary;
+    let mut delimited = false;
+    let mut max_depth = DEFAULT_MAX_DEPTH;
+    let mut path = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--hex" => format = Format::Hex,
+            "--base64" => format = Format::Base64,
+            "--delimited" => delimited = true,
+            "--max-depth" => match args.next().and_then(|depth| depth.parse().ok()) {
+                Some(depth) => max_depth = depth,
+                None => {
+                    eprintln!("{USAGE}");
+                    return ExitCode::from(2);
+                }
+            },
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                return ExitCode::SUCCESS;
+            }
+            _ if path.is_none() && (arg == "-" || !arg.starts_with('-')) => path = Some(arg),
+            _ => {
+                eprintln!("{USAGE}");
+                return ExitCode::from(2);
+            }
+        }
+    }
+    let data = match read_input(path.as_deref(), format) {
+        Ok(data) => data,
+        Err(err) => {
+            eprintln!("{err}");
+            return ExitCode::from(2);
+        }
+    };
+    let mut out = String::new();
+    let valid = if delimited {
+        inspect_stream(&data, max_depth, &mut out)
+    } else {
+        inspect_message(&data, 0, data.len(), 0, max_depth, &mut out)
+    };
+    print!("{out}");
+    if valid {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
+
+fn read_input(path: Option<&str>, format: Format) -> Result<Vec<u8>, String> {
+    let data = match path {
+        None | Some("-") => {
+            let mut data = Vec::new();
+            io::stdin()
+                .read_to_end(&mut data)
+                .map_err(|err| format!("stdin: {err}"))?;
+            data
+        }
+        Some(path) => fs::read(path).map_err(|err| format!("{path}: {err}"))?,
+    };
+    match format {
+        Format::Binary => Ok(data),
+        Format::Hex => decode_hex(&data),
+        Format::Base64 => decode_base64(&data),
+    }
+}
+
+fn decode_hex(text: &[u8]) -> Result<Vec<u8>, String> {
+    let digits: Vec<u8> = text
+        .iter()
+        .copied()
+        .filter(|c| !c.is_ascii_whitespace())
+        .map(|c| match c {
+            b'0'..=b'9' => Ok(c - b'0'),
+            b'a'..=b'f' => Ok(c - b'a' + 10),
+            b'A'..=b'F' => Ok(c - b'A' + 10),
+            _ => Err(format!("invalid hex digit {:?}", c as char)),
+        })
+        .collect::<Result<_, _>>()?;
+    if digits.len() % 2 != 0 {
+        return Err("hex input has an odd number of digits".into());
+    }
+    Ok(digits
+        .chunks(2)
+        .map(|pair| (pair[0] << 4) | pair[1])
+        .collect())
+}
+
+/// Decodes base64 in either the standard or the URL-safe alphabet, with or without padding.
+fn decode_base64(text: &[u8]) -> Result<Vec<u8>, String> {
+    const CONFIG: GeneralPurposeConfig =
+        GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent);
+    const STANDARD: GeneralPurpose = GeneralPurpose::new(&alphabet::STANDARD, CONFIG);
+    const URL_SAFE: GeneralPurpose = GeneralPurpose::new(&alphabet::URL_SAFE, CONFIG);
+    let text: Vec<u8> = text
+        .iter()
+        .copied()
+        .filter(|c| !c.is_ascii_whitespace())
+        .collect();
+    STANDARD
+        .decode(&text)
+        .or_else(|_| URL_SAFE.decode(&text))
+        .map_err(|err| format!("invalid base64: {err}"))
+}
+
+/// Shows each length-delimited message in the stream, returning whether they were all valid.
+fn inspect_stream(data: &[u8], max_depth: usize, out: &mut String) -> bool {
+    let mut rest = data;
+    let mut index = 0;
+    while !rest.is_empty() {
+        let offset = data.len() - rest.len();
+        let len = match decode_varint(&mut rest) {
+            Ok(len) if len <= rest.len() as u64 => len as usize,
+            _ => {
+                let _ = writeln!(
+                    out,
+                    "{offset:>8}  error: truncated length-delimited message"
+                );
+                return false;
+            }
+        };
+        let start = data.len() - rest.len();
+        let _ = writeln!(out, "{offset:>8}  message #{index} ({len} bytes)");
+        // Each message's fields are indented one level under its header, so they start at depth 1.
+        if !inspect_message(
+            data,
+            start,
+            start + len,
+            1,
+            max_depth.saturating_add(1),
+            out,
+        ) {
+            return false;
+        }
+        rest = &rest[len..];
+        index += 1;
+    }
+    true
+}
+
+/// Shows the fields of the message encoded in `data[start..end]`, indented for the given depth,
+/// returning whether it was valid.
+fn inspect_message(
+    data: &[u8],
+    start: usize,
+    end: usize,
+    depth: usize,
+    max_depth: usize,
+    out: &mut String,
+) -> bool {
+    let indent = "  ".repeat(depth);
+    let mut rest = &data[start..end];
+    let mut tag = 0u32;
+    let mut last = None;
+    while !rest.is_empty() {
+        let offset = end - rest.len();
+        let key = match decode_varint(&mut rest) {
+            Ok(key) => key,
+            Err(_) => {
+                let _ = writeln!(out, "{offset:>8}  {indent}error: invalid field key");
+                return false;
+            }
+        };
+        let Some(new_tag) = u32::try_from(key >> 2)
+            .ok()
+            .and_then(|delta| tag.checked_add(delta))
+        else {
+            let _ = writeln!(out, "{offset:>8}  {indent}error: tag overflowed");
+            return false;
+        };
+        let delta = new_tag - tag;
+        tag = new_tag;
+        let wire_type = WireType::from(key as u8);
+        let value = match wire_type {
+            WireType::Varint => decode_varint(&mut rest).ok().map(OpaqueValue::u64),
+            WireType::LengthDelimited => match decode_varint(&mut rest) {
+                Ok(len) if len <= rest.len() as u64 => {
+                    let (bytes, remaining) = rest.split_at(len as usize);
+                    rest = remaining;
+                    Some(OpaqueValue::byte_slice(bytes))
+                }
+                _ => None,
+            },
+            WireType::ThirtyTwoBit => rest.get(..4).map(|bytes| {
+                rest = &rest[4..];
+                OpaqueValue::ThirtyTwoBit(bytes.try_into().unwrap())
+            }),
+            WireType::SixtyFourBit => rest.get(..8).map(|bytes| {
+                rest = &rest[8..];
+                OpaqueValue::SixtyFourBit(bytes.try_into().unwrap())
+            }),
+        };
+        let _ = write!(out, "{offset:>8}  {indent}{tag} (+{delta}) ");
+        let Some(value) = value else {
+            let name = match wire_type {
+                WireType::Varint => "varint",
+                WireType::LengthDelimited => "length-delimited",
+                WireType::ThirtyTwoBit => "fixed32",
+                WireType::SixtyFourBit => "fixed64",
+            };
+            let _ = writeln!(out, "{name}\n{offset:>8}  {indent}error: truncated value");
+            return false;
+        };
+        let mut nested = None;
+        match &value {
+            OpaqueValue::Varint(value) => {
+                let signed = (value >> 1) as i64 ^ -((value & 1) as i64);
+                let _ = write!(out, "varint: {value} (zigzag {signed})");
+            }
+            OpaqueValue::LengthDelimited(bytes) => {
+                let _ = write!(out, "length-delimited[{}]: ", bytes.len());
+                if is_printable(bytes) {
+                    let _ = write!(out, "{value}");
+                } else if depth < max_depth
+                    && OpaqueMessage::decode_borrowed(bytes)
+                        .map_or(false, |message| !message.is_empty())
+                {
+                    let _ = write!(out, "message");
+                    let value_end = end - rest.len();
+                    nested = Some(value_end - bytes.len()..value_end);
+                } else if let Some(items) = packed_varints(bytes) {
+                    let _ = write!(out, "packed varints [");
+                    for (i, item) in items.iter().enumerate() {
+                        let separator = if i > 0 { ", " } else { "" };
+                        let _ = write!(out, "{separator}{item}");
+                    }
+                    let _ = write!(out, "]");
+                } else {
+                    let _ = write!(out, "0x");
+                    for byte in bytes.iter() {
+                        let _ = write!(out, "{byte:02x}");
+                    }
+                }
+            }
+            OpaqueValue::ThirtyTwoBit(_) | OpaqueValue::SixtyFourBit(_) => {
+                let _ = write!(out, "{value}");
+            }
+        }
+        let _ = writeln!(out);
+        for flag in canonicity_flags(tag, wire_type, &value, &mut last) {
+            let _ = writeln!(out, "{:>8}  {indent}  ! {flag}", "");
+        }
+        if let Some(range) = nested {
+            if !inspect_message(data, range.start, range.end, depth + 1, max_depth, out) {
+                return false;
+            }
+        }
+    }
+    true
+}
+
+/// Returns the reasons this field can never appear in a canonical encoding. `last` holds the tag
+/// and wire type of the previous field in the same message, which is updated to this field.
+fn canonicity_flags(
+    tag: u32,
+    wire_type: WireType,
+    value: &OpaqueValue,
+    last: &mut Option<(u32, WireType)>,
+) -> Vec<&'static str> {
+    let mut flags = Vec::new();
+    let empty = match value {
+        OpaqueValue::Varint(value) => *value == 0,
+        OpaqueValue::LengthDelimited(bytes) => bytes.is_empty(),
+        OpaqueValue::ThirtyTwoBit(bytes) => bytes == &[0; 4],
+        OpaqueValue::SixtyFourBit(bytes) => bytes == &[0; 8],
+    };
+    if empty {
+        flags.push("empty value, which is only canonical in an unpacked collection");
+    }
+    // Fields with the same tag are always adjacent, so only the previous field needs checking.
+    if matches!(*last, Some((last_tag, last_wire_type)) if last_tag == tag && last_wire_type != wire_type)
+    {
+        flags.push("wire type differs from the previous field with the same tag");
+    }
+    *last = Some((tag, wire_type));
+    flags
+}
+
+/// Whether the bytes are shown as a string, the same way the `OpaqueMessage` text format does.
+fn is_printable(bytes: &[u8]) -> bool {
+    std::str::from_utf8(bytes).map_or(false, |text| {
+        text.chars()
+            .all(|c| !c.is_control() || matches!(c, '\n' | '\r' | '\t'))
+    })
+}
+
+/// Decodes the bytes as packed varints if they consist of at least two of them.
+fn packed_varints(mut bytes: &[u8]) -> Option<Vec<u64>> {
+    let mut items = Vec::new();
+    while !bytes.is_empty() {
+        items.push(decode_varint(&mut bytes).ok()?);
+    }
+    (items.len() >= 2).then_some(items)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn inspect(data: &[u8], max_depth: usize) -> (String, bool) {
+        let mut out = String::new();
+        let valid = inspect_message(data, 0, data.len(), 0, max_depth, &mut out);
+        (out, valid)
+    }
+
+    #[test]
+    fn shows_offsets_deltas_and_nested_values() {
+        let data =
+            b"\x04\x2a\x15\x03foo\x01\x04\x08\x05\x10\x00\x05\x03\x01\x02\x03\x0a\x00\x00\xc0\x3f";
+        let (out, valid) = inspect(data, DEFAULT_MAX_DEPTH);
+        assert!(valid);
+        assert_eq!(
+            out,
+            "       0  1 (+1) varint: 42 (zigzag 21)\n\
+             \x20      2  6 (+5) length-delimited[3]: \"foo\"\n\
+             \x20      7  6 (+0) length-delimited[4]: message\n\
+             \x20      9    2 (+2) varint: 5 (zigzag -3)\n\
+             \x20     11    6 (+4) varint: 0 (zigzag 0)\n\
+             \x20             ! empty value, which is only canonical in an unpacked collection\n\
+             \x20     13  7 (+1) length-delimited[3]: packed varints [1, 2, 3]\n\
+             \x20     18  9 (+2) f32(1.5)\n"
+        );
+        let (out, _) = inspect(data, 0);
+        assert!(out.contains("length-delimited[4]: packed varints [8, 5, 16, 0]\n"));
+    }
+
+    #[test]
+    fn flags_mixed_wire_types() {
+        let (out, valid) = inspect(b"\x04\x01\x01\x01\x05", DEFAULT_MAX_DEPTH);
+        assert!(valid);
+        assert!(out.ends_with("! wire type differs from the previous field with the same tag\n"));
+    }
+
+    #[test]
+    fn reports_truncated_data() {
+        let (out, valid) = inspect(b"\x04\x01\x05\x03ab", DEFAULT_MAX_DEPTH);
+        assert!(!valid);
+        assert!(out.ends_with("       2  error: truncated value\n"));
+
+        let mut out = String::new();
+        assert!(!inspect_stream(
+            b"\x02\x04\x01\x05",
+            DEFAULT_MAX_DEPTH,
+            &mut out
+        ));
+        assert!(out.ends_with("       3  error: truncated length-delimited message\n"));
+    }
+
+    #[test]
+    fn decodes_text_input() {
+        assert_eq!(decode_hex(b"0a ff\n10"), Ok(vec![0x0a, 0xff, 0x10]));
+        assert!(decode_hex(b"abc").is_err());
+        assert_eq!(decode_base64(b"CCo="), Ok(vec![0x08, 0x2a]));
+        assert_eq!(decode_base64(b"-_8"), Ok(vec![0xfb, 0xff]));
+        assert!(decode_base64(b"C").is_err());
+        assert!(decode_base64(b"+_8").is_err());
+    }
+}
@@ -20,12 +20,15 @@ mod derived_message_tests {
         self, encode_varint, Collection, DistinguishedOneof, EmptyState, Fixed, General, Mapping,
         Oneof, Packed,
     };
-    use bilrost::Canonicity::{HasExtensions, NotCanonical};
+    use bilrost::Canonicity::{self, HasExtensions, NotCanonical};
     use bilrost::DecodeErrorKind::{
         ConflictingFields, InvalidValue, OutOfDomainValue, TagOverflowed, Truncated,
         UnexpectedlyRepeated, WrongWireType,
     };
-    use bilrost::{DecodeErrorKind, DistinguishedMessage, Enumeration, Message, Oneof};
+    use bilrost::{
+        DecodeErrorKind, DistinguishedMessage, DistinguishedOwnedMessage, Enumeration, Message,
+        Oneof, OwnedMessage,
+    };
     use bilrost_derive::DistinguishedOneof;
 
     trait IntoOpaqueMessage<'a> {
@@ -79,7 +82,7 @@ mod derived_message_tests {
         fn from_opaque<'a>(from: impl IntoOpaqueMessage<'a>) -> Self;
     }
 
-    impl<T: Message> FromOpaque for T {
+    impl<T: OwnedMessage> FromOpaque for T {
         fn from_opaque<'a>(from: impl IntoOpaqueMessage<'a>) -> Self {
             Self::decode(&*from.into_opaque_message().encode_to_vec()).expect("failed to decode")
         }
@@ -92,7 +95,7 @@ mod derived_message_tests {
 
         pub(super) fn decodes<'a, M>(from: impl IntoOpaqueMessage<'a>, into: M)
         where
-            M: Message + Debug + PartialEq + EmptyState,
+            M: OwnedMessage + Debug + PartialEq + EmptyState,
         {
             let encoded = from.into_opaque_message().encode_to_vec();
             assert_eq!(M::decode(encoded.as_slice()).as_ref(), Ok(&into));
@@ -103,7 +106,7 @@ mod derived_message_tests {
 
         pub(super) fn doesnt_decode<'a, M>(from: impl IntoOpaqueMessage<'a>, err: DecodeErrorKind)
         where
-            M: Message + Debug + EmptyState,
+            M: OwnedMessage + Debug + EmptyState,
         {
             let encoded = from.into_opaque_message().encode_to_vec();
             assert_eq!(
@@ -124,7 +127,7 @@ mod derived_message_tests {
 
         pub(super) fn decodes_distinguished<'a, M>(from: impl IntoOpaqueMessage<'a>, into: M)
         where
-            M: DistinguishedMessage + Debug + Eq + EmptyState,
+            M: DistinguishedOwnedMessage + Debug + Eq + EmptyState,
         {
             let encoded = from.into_opaque_message().encode_to_vec();
             assert_eq!(M::decode(encoded.as_slice()).as_ref(), Ok(&into));
@@ -149,6 +152,11 @@ mod derived_message_tests {
                 into.encode_to_vec(),
                 "distinguished encoding does not round trip"
             );
+            assert_eq!(
+                encoded,
+                into.encode_to_vec_fast(),
+                "prepend encoding does not round trip"
+            );
             assert_eq!(into.encoded_len(), encoded.len(), "encoded_len was wrong");
         }
 
@@ -157,7 +165,7 @@ mod derived_message_tests {
             into: M,
             expected_canon: Canonicity,
         ) where
-            M: DistinguishedMessage + Debug + Eq + EmptyState,
+            M: DistinguishedOwnedMessage + Debug + Eq + EmptyState,
         {
             assert_ne!(expected_canon, Canonical); // otherwise why call this function
             let encoded = from.into_opaque_message().encode_to_vec();
@@ -190,11 +198,16 @@ mod derived_message_tests {
                 round_tripped.len(),
                 "encoded_len was wrong"
             );
+            assert_eq!(
+                round_tripped,
+                into.encode_to_vec_fast(),
+                "prepend encoding was different"
+            );
         }
 
         pub(super) fn never_decodes<'a, M>(from: impl IntoOpaqueMessage<'a>, err: DecodeErrorKind)
         where
-            M: DistinguishedMessage + Debug + EmptyState,
+            M: DistinguishedOwnedMessage + Debug + EmptyState,
         {
             let encoded = from.into_opaque_message().encode_to_vec();
             assert_eq!(
@@ -227,18 +240,23 @@ mod derived_message_tests {
             );
         }
 
-        pub(super) fn encodes<'a, M: Message>(value: M, becomes: impl IntoOpaqueMessage<'a>) {
+        pub(super) fn encodes<'a, M: OwnedMessage>(value: M, becomes: impl IntoOpaqueMessage<'a>) {
             let encoded = value.encode_to_vec();
             assert_eq!(
                 OpaqueMessage::decode(&*encoded),
                 Ok(becomes.into_opaque_message())
             );
             assert_eq!(value.encoded_len(), encoded.len(), "encoded_len was wrong");
+            assert_eq!(
+                value.encode_to_vec_fast(),
+                encoded,
+                "prepend encoding was different"
+            );
         }
 
         pub(super) fn is_invalid<M>(value: impl AsRef<[u8]>, err: DecodeErrorKind)
         where
-            M: Message + Debug + EmptyState,
+            M: OwnedMessage + Debug + EmptyState,
         {
             assert_eq!(
                 M::decode(value.as_ref())
@@ -258,7 +276,7 @@ mod derived_message_tests {
 
         pub(super) fn is_invalid_distinguished<M>(value: impl AsRef<[u8]>, err: DecodeErrorKind)
         where
-            M: DistinguishedMessage + Debug + EmptyState,
+            M: DistinguishedOwnedMessage + Debug + EmptyState,
         {
             assert_eq!(
                 M::decode_distinguished(value.as_ref())
@@ -1083,6 +1101,183 @@ mod derived_message_tests {
         assert_eq!(decoded.0 .0.to_bits(), (-0.0f32).to_bits());
     }
 
+    #[test]
+    fn distinguished_floating_point_wrappers() {
+        use bilrost::{CanonicalNanF32, CanonicalNanF64, TotalF32, TotalF64};
+        use std::collections::{BTreeMap, BTreeSet};
+
+        #[derive(Debug, PartialEq, Eq, Message, DistinguishedMessage)]
+        struct Foo(
+            TotalF32,
+            #[bilrost(encoding(fixed))] TotalF64,
+            BTreeSet<TotalF64>,
+            BTreeMap<TotalF32, String>,
+        );
+
+        let nan32 = f32::from_bits(0xffff_4321);
+        let nan64 = f64::from_bits(0x7fff_dead_beef_cafe);
+        assert_ne!(TotalF64(0.0), TotalF64(-0.0));
+        assert_eq!(TotalF64(nan64), TotalF64(nan64));
+        assert_ne!(TotalF64(nan64), TotalF64(f64::NAN));
+        assert!(TotalF64(-0.0) < TotalF64(0.0));
+        assert!(TotalF64(f64::INFINITY) < TotalF64(nan64));
+
+        assert::decodes_distinguished([], Foo::empty());
+        assert::decodes_distinguished(
+            [
+                (1, OV::f32(-0.0)),
+                (2, OV::fixed_u64(0x7fff_dead_beef_cafe)),
+                (3, OV::f64(-1.0)),
+                (3, OV::f64(0.0)),
+                (3, OV::fixed_u64(0x7fff_dead_beef_cafe)),
+                (4, OV::packed([OV::fixed_u32(0xffff_4321), OV::str("nan")])),
+            ],
+            Foo(
+                TotalF32(-0.0),
+                TotalF64(nan64),
+                [TotalF64(-1.0), TotalF64(0.0), TotalF64(nan64)].into(),
+                [(TotalF32(nan32), "nan".to_string())].into(),
+            ),
+        );
+        assert::decodes_non_canonically([(1, OV::f32(0.0))], Foo::empty(), NotCanonical);
+        assert::decodes_non_canonically(
+            [(3, OV::f64(1.0)), (3, OV::f64(-1.0))],
+            Foo(
+                TotalF32(0.0),
+                TotalF64(0.0),
+                [TotalF64(-1.0), TotalF64(1.0)].into(),
+                BTreeMap::new(),
+            ),
+            NotCanonical,
+        );
+
+        // NaNs are all replaced with the canonical NaN, and any other NaN is not canonical
+        #[derive(Debug, PartialEq, Eq, Message, DistinguishedMessage)]
+        struct Bar(CanonicalNanF32, CanonicalNanF64);
+
+        assert_eq!(CanonicalNanF32::new(nan32), CanonicalNanF32::new(f32::NAN));
+        assert_eq!(
+            CanonicalNanF64::new(nan64).get().to_bits(),
+            0x7ff8_0000_0000_0000
+        );
+        assert::decodes_distinguished(
+            [
+                (1, OV::fixed_u32(0x7fc0_0000)),
+                (2, OV::fixed_u64(0x7ff8_0000_0000_0000)),
+            ],
+            Bar(CanonicalNanF32::new(nan32), CanonicalNanF64::new(nan64)),
+        );
+        assert::decodes_non_canonically(
+            [
+                (1, OV::fixed_u32(0xffff_4321)),
+                (2, OV::fixed_u64(0x7fff_dead_beef_cafe)),
+            ],
+            Bar(CanonicalNanF32::new(nan32), CanonicalNanF64::new(nan64)),
+            NotCanonical,
+        );
+        assert::encodes(
+            Bar(CanonicalNanF32::new(nan32), CanonicalNanF64::new(-0.0)),
+            [(1, OV::fixed_u32(0x7fc0_0000)), (2, OV::f64(-0.0))],
+        );
+    }
+    #[test]
+    fn wide_integers() {
+        use bilrost::dynamic::{DynamicMessage, Value};
+        use bilrost::RawMessage;
+        use std::collections::BTreeSet;
+
+        #[derive(Debug, PartialEq, Eq, Message, DistinguishedMessage)]
+        struct Foo(
+            u128,
+            i128,
+            #[bilrost(encoding(fixed))] u128,
+            #[bilrost(encoding(fixed))] i128,
+            BTreeSet<i128>,
+        );
+
+        assert::decodes_distinguished([], Foo::empty());
+        assert::encodes(
+            Foo(1, -1, 1, -1, BTreeSet::new()),
+            [
+                (1, OV::bytes([1])),
+                (2, OV::bytes([1])),
+                (3, OV::bytes(1u128.to_le_bytes())),
+                (4, OV::bytes([0xff; 16])),
+            ],
+        );
+        assert::decodes_distinguished(
+            [
+                (1, OV::u128(u128::MAX)),
+                (2, OV::i128(i128::MIN)),
+                (3, OV::fixed_u128(0x1234 << 100)),
+                (4, OV::fixed_i128(i128::MIN)),
+                (5, OV::i128(-300)),
+                (5, OV::i128(0)),
+                (5, OV::i128(1 << 70)),
+            ],
+            Foo(
+                u128::MAX,
+                i128::MIN,
+                0x1234 << 100,
+                i128::MIN,
+                [-300, 0, 1 << 70].into(),
+            ),
+        );
+        assert_eq!(OV::u128(0x1_0000), OV::bytes([0, 0, 1]));
+        assert_eq!(OV::i128(-2), OV::bytes([3]));
+
+        // Trailing zeros are not canonical in general encoding, and explicitly encoded zeros are
+        // not canonical either
+        assert::decodes_non_canonically(
+            [(1, OV::bytes([5, 0])), (2, OV::bytes([6, 0, 0]))],
+            Foo(5, 3, 0, 0, BTreeSet::new()),
+            NotCanonical,
+        );
+        assert::decodes_non_canonically([(1, OV::bytes([]))], Foo::empty(), NotCanonical);
+        assert::decodes_non_canonically([(3, OV::fixed_u128(0))], Foo::empty(), NotCanonical);
+
+        // Values that are too long, or the wrong length for fixed encoding, are invalid
+        for invalid in [
+            [(1, OV::bytes([1; 17]))],
+            [(2, OV::bytes([0; 17]))],
+            [(3, OV::bytes([1; 15]))],
+            [(4, OV::bytes([1; 17]))],
+        ] {
+            assert::never_decodes::<Foo>(invalid, InvalidValue);
+        }
+        assert::never_decodes::<Foo>([(1, OV::u64(1))], WrongWireType);
+
+        // Dynamic messages represent them too, and so does the serde bridge
+        let foo = Foo(
+            u128::MAX,
+            i128::MIN,
+            1 << 100,
+            -5,
+            [-300, 0, 1 << 70].into(),
+        );
+        let encoded = foo.encode_to_vec();
+        let dynamic = DynamicMessage::decode(Foo::DESCRIPTOR, encoded.as_slice()).unwrap();
+        assert_eq!(dynamic.get_by_tag(1), Some(&Value::U128(u128::MAX)));
+        assert_eq!(dynamic.get_by_tag(2), Some(&Value::I128(i128::MIN)));
+        assert_eq!(dynamic.encode_to_vec(), encoded);
+        let (dynamic, canon) =
+            DynamicMessage::decode_distinguished(Foo::DESCRIPTOR, encoded.as_slice()).unwrap();
+        assert_eq!(canon, Canonicity::Canonical);
+        assert_eq!(dynamic.encode_to_vec(), encoded);
+        #[cfg(feature = "serde")]
+        {
+            use bilrost::serde::{Deserializable, Serializable};
+
+            let json = serde_json::to_string(&Serializable(&foo)).unwrap();
+            assert_eq!(
+                serde_json::from_str::<Deserializable<Foo>>(&json)
+                    .unwrap()
+                    .0,
+                foo
+            );
+        }
+    }
+
     #[test]
     fn truncated_fixed() {
         #[derive(Debug, PartialEq, Eq, Oneof, DistinguishedOneof)]
@@ -1100,9 +1295,9 @@ mod derived_message_tests {
             T: Debug
                 + Eq
                 + EmptyState
-                + encoding::DistinguishedEncoder<Fixed>
-                + encoding::DistinguishedValueEncoder<Fixed>
-                + encoding::ValueEncoder<Fixed>,
+                + encoding::DistinguishedDecoder<Fixed>
+                + encoding::DistinguishedValueDecoder<Fixed>
+                + encoding::ValueDecoder<Fixed>,
         {
             let mut direct = [(1, val.clone())].into_opaque_message().encode_to_vec();
             let mut in_oneof = [(2, val.clone())].into_opaque_message().encode_to_vec();
@@ -1160,7 +1355,7 @@ mod derived_message_tests {
 
     fn parsing_string_type<'a, T>()
     where
-        T: 'a + Debug + Eq + From<&'a str> + EmptyState + encoding::DistinguishedEncoder<General>,
+        T: 'a + Debug + Eq + From<&'a str> + EmptyState + encoding::DistinguishedDecoder<General>,
     {
         #[derive(Debug, PartialEq, Eq, Message, DistinguishedMessage)]
         struct Foo<T>(T);
@@ -1474,7 +1669,7 @@ mod derived_message_tests {
 
     fn truncated_bool_string_map<T>()
     where
-        T: Debug + EmptyState + Mapping<Key = bool, Value = String> + encoding::Encoder<General>,
+        T: Debug + EmptyState + Mapping<Key = bool, Value = String> + encoding::Decoder<General>,
     {
         #[derive(Debug, PartialEq, Message)]
         struct Foo<T>(T, String);
@@ -1498,7 +1693,7 @@ mod derived_message_tests {
 
     fn truncated_string_int_map<T>()
     where
-        T: Debug + EmptyState + Mapping<Key = String, Value = u64> + encoding::Encoder<General>,
+        T: Debug + EmptyState + Mapping<Key = String, Value = u64> + encoding::Decoder<General>,
     {
         #[derive(Debug, PartialEq, Message)]
         struct Foo<T>(T, String);
@@ -1902,7 +2097,7 @@ mod derived_message_tests {
 
     fn truncated_packed_int<T>()
     where
-        T: Debug + EmptyState + Collection<Item = u64> + encoding::Encoder<General>,
+        T: Debug + EmptyState + Collection<Item = u64> + encoding::Decoder<General>,
     {
         #[derive(Debug, PartialEq, Message)]
         struct Foo<T>(T, String);
@@ -2484,4 +2679,2746 @@ mod derived_message_tests {
             NotCanonical,
         );
     }
+
+    #[test]
+    fn extensions_fields() {
+        #[derive(Debug, PartialEq, Eq, Oneof, DistinguishedOneof)]
+        enum InnerOneof {
+            Empty,
+            #[bilrost(3)]
+            Three(u32),
+            #[bilrost(6)]
+            Six(String),
+        }
+        use InnerOneof::*;
+
+        #[derive(Debug, PartialEq, Eq, Message, DistinguishedMessage)]
+        struct Foo {
+            #[bilrost(1)]
+            one: u64,
+            #[bilrost(4)]
+            four: String,
+            #[bilrost(oneof(3, 6))]
+            oneof: InnerOneof,
+            #[bilrost(extensions)]
+            ext: OpaqueMessage<'static>,
+        }
+
+        // Unknown fields are kept, and are re-encoded interleaved with the known fields
+        let with_unknowns = [
+            (0, OV::string("zero")),
+            (1, OV::u64(1)),
+            (2, OV::u32(2)),
+            (2, OV::string("two")),
+            (4, OV::string("four")),
+            (5, OV::u64(5)),
+            (6, OV::string("six")),
+            (10, OV::fixed_u32(10)),
+        ];
+        let expected = Foo {
+            one: 1,
+            four: "four".into(),
+            oneof: Six("six".into()),
+            ext: [
+                (0, OV::string("zero")),
+                (2, OV::u32(2)),
+                (2, OV::string("two")),
+                (5, OV::u64(5)),
+                (10, OV::fixed_u32(10)),
+            ]
+            .into_opaque_message(),
+        };
+        assert::decodes_distinguished(&with_unknowns, expected);
+        assert::encodes(
+            Foo {
+                oneof: Three(3),
+                ext: [(3, OV::u32(33)), (4, OV::u32(44))].into_opaque_message(),
+                ..EmptyState::empty()
+            },
+            [(3, OV::u32(3)), (3, OV::u32(33)), (4, OV::u32(44))],
+        );
+
+        // Clearing the message also clears the extensions
+        let mut cleared = Foo::from_opaque(&with_unknowns);
+        assert!(!cleared.is_empty());
+        cleared.clear();
+        assert!(cleared.is_empty());
+        assert_eq!(cleared, Foo::empty());
+
+        // Known fields still behave as normal
+        assert::never_decodes::<Foo>([(1, OV::u64(1)), (1, OV::u64(2))], UnexpectedlyRepeated);
+        assert::never_decodes::<Foo>([(3, OV::u32(3)), (6, OV::string("six"))], ConflictingFields);
+
+        #[derive(Debug, PartialEq, Eq, Message, DistinguishedMessage)]
+        struct Ignoring {
+            #[bilrost(1)]
+            one: u64,
+            #[bilrost(extensions)]
+            ext: (),
+        }
+
+        // Extensions of unit type discard unknown fields, exactly as if there were no extensions
+        assert::decodes_non_canonically(
+            [(1, OV::u64(1)), (2, OV::string("two"))],
+            Ignoring { one: 1, ext: () },
+            HasExtensions,
+        );
+    }
+
+    #[test]
+    fn borrowed_decoding() {
+        #[derive(Debug, PartialEq, Eq, Oneof, DistinguishedOneof)]
+        enum BorrowedOneof<'a> {
+            Empty,
+            #[bilrost(5)]
+            Five(&'a str),
+            #[bilrost(6)]
+            Six(&'a [u8]),
+        }
+
+        #[derive(Debug, PartialEq, Eq, Message, DistinguishedMessage)]
+        struct Inner<'a> {
+            #[bilrost(1)]
+            name: &'a str,
+        }
+
+        #[derive(Debug, PartialEq, Eq, Message, DistinguishedMessage)]
+        struct Borrowed<'a> {
+            #[bilrost(1)]
+            s: &'a str,
+            #[bilrost(2)]
+            b: &'a [u8],
+            #[bilrost(3)]
+            cow: Cow<'a, str>,
+            #[bilrost(4)]
+            inner: Inner<'a>,
+            #[bilrost(oneof(5, 6))]
+            oneof: BorrowedOneof<'a>,
+            #[bilrost(7)]
+            strs: Vec<&'a str>,
+            #[bilrost(8)]
+            number: u32,
+        }
+
+        let encoded = [
+            (1, OV::string("foo")),
+            (2, OV::bytes([1, 2, 3])),
+            (3, OV::string("cow")),
+            (
+                4,
+                OV::message(&[(1, OV::string("inner"))].into_opaque_message()),
+            ),
+            (6, OV::bytes([4, 5])),
+            (7, OV::string("a")),
+            (7, OV::string("b")),
+            (8, OV::u32(8)),
+        ]
+        .into_opaque_message()
+        .encode_to_vec();
+        let expected = Borrowed {
+            s: "foo",
+            b: &[1, 2, 3],
+            cow: Cow::Borrowed("cow"),
+            inner: Inner { name: "inner" },
+            oneof: BorrowedOneof::Six(&[4, 5]),
+            strs: vec!["a", "b"],
+            number: 8,
+        };
+
+        let decoded = Borrowed::decode_borrowed(&encoded).unwrap();
+        assert_eq!(decoded, expected);
+        let (decoded_distinguished, canon) =
+            Borrowed::decode_distinguished_borrowed(&encoded).unwrap();
+        assert_eq!(decoded_distinguished, expected);
+        assert_eq!(canon, Canonicity::Canonical);
+        assert_eq!(decoded.encode_to_vec(), encoded);
+
+        // The decoded values point directly into the input buffer
+        let input_range = encoded.as_ptr_range();
+        let points_into_input = |bytes: &[u8]| input_range.contains(&bytes.as_ptr());
+        assert!(points_into_input(decoded.s.as_bytes()));
+        assert!(points_into_input(decoded.b));
+        assert!(matches!(decoded.cow, Cow::Borrowed(cow) if points_into_input(cow.as_bytes())));
+        assert!(points_into_input(decoded.inner.name.as_bytes()));
+        assert!(matches!(decoded.oneof, BorrowedOneof::Six(six) if points_into_input(six)));
+        assert!(decoded.strs.iter().all(|s| points_into_input(s.as_bytes())));
+
+        // Messages with fields that must borrow cannot be decoded without borrowing; this only
+        // compiles when the type does not implement the owned decoding traits, because
+        // otherwise the impl for `A` is ambiguous.
+        trait NotOwned<A> {
+            fn check() {}
+        }
+        impl<T: ?Sized> NotOwned<()> for T {}
+        impl<T: ?Sized + OwnedMessage> NotOwned<u8> for T {}
+        impl<T: ?Sized + DistinguishedOwnedMessage> NotOwned<u16> for T {}
+        <Borrowed as NotOwned<_>>::check();
+        <Inner as NotOwned<_>>::check();
+
+        // Borrowed strings are still checked for validity
+        assert_eq!(
+            Borrowed::decode_borrowed(
+                &[(1, OV::bytes(b"\xff"))]
+                    .into_opaque_message()
+                    .encode_to_vec()
+            )
+            .unwrap_err()
+            .kind(),
+            InvalidValue
+        );
+        // Distinguished decoding still checks canonicity
+        assert_eq!(
+            Borrowed::decode_distinguished_borrowed(
+                &[(1, OV::string(""))].into_opaque_message().encode_to_vec()
+            )
+            .unwrap()
+            .1,
+            NotCanonical
+        );
+        assert_eq!(
+            Borrowed::decode_distinguished_borrowed(
+                &[(1, OV::string("foo")), (10, OV::u32(10))]
+                    .into_opaque_message()
+                    .encode_to_vec()
+            )
+            .unwrap()
+            .1,
+            HasExtensions
+        );
+        assert_eq!(
+            Borrowed::decode_borrowed(
+                &[(5, OV::string("five")), (6, OV::bytes([6]))]
+                    .into_opaque_message()
+                    .encode_to_vec()
+            )
+            .unwrap_err()
+            .kind(),
+            ConflictingFields
+        );
+
+        // Opaque messages can borrow their contents as well
+        let opaque = OpaqueMessage::decode_borrowed(&encoded).unwrap();
+        assert_eq!(opaque.encode_to_vec(), encoded);
+        let (opaque, canon) = OpaqueMessage::decode_distinguished_borrowed(&encoded).unwrap();
+        assert_eq!(canon, Canonicity::Canonical);
+        assert!(opaque.iter().all(|(_, value)| match value {
+            OV::LengthDelimited(Cow::Borrowed(bytes)) => points_into_input(bytes),
+            OV::LengthDelimited(Cow::Owned(_)) => false,
+            _ => true,
+        }));
+    }
+
+    #[test]
+    fn prepend_encoding() {
+        use bilrost::ReverseBuffer;
+        use std::collections::BTreeMap;
+
+        #[derive(Debug, PartialEq, Eq, Message, DistinguishedMessage)]
+        struct Nested {
+            #[bilrost(1)]
+            name: String,
+            #[bilrost(tag(2), encoding(packed))]
+            numbers: Vec<u32>,
+            #[bilrost(3)]
+            map: BTreeMap<String, u64>,
+            #[bilrost(tag(4), recurses)]
+            child: Option<Box<Nested>>,
+        }
+
+        let mut nested = Nested {
+            name: "leaf".to_string(),
+            numbers: vec![1, 200, 30000],
+            map: [("a".to_string(), 1), ("b".to_string(), 1 << 40)].into(),
+            child: None,
+        };
+        for depth in 0..50 {
+            nested = Nested {
+                name: format!("depth {depth}"),
+                numbers: vec![depth],
+                map: BTreeMap::new(),
+                child: Some(Box::new(nested)),
+            };
+        }
+
+        let encoded = nested.encode_to_vec();
+        assert_eq!(nested.encode_to_vec_fast(), encoded);
+        assert_eq!(nested.encode_to_bytes_fast(), encoded);
+        assert_eq!(Nested::decode(encoded.as_slice()).unwrap(), nested);
+
+        // Messages can be prepended one after another onto the same buffer
+        let mut buf = ReverseBuffer::new();
+        nested.prepend_length_delimited(&mut buf);
+        nested.prepend_length_delimited(&mut buf);
+        let mut delimited = nested.encode_length_delimited_to_vec();
+        delimited.extend(nested.encode_length_delimited_to_vec());
+        assert_eq!(buf.as_slice(), delimited.as_slice());
+
+        let empty = Nested::empty();
+        buf.clear();
+        empty.prepend(&mut buf);
+        assert!(buf.is_empty());
+        assert!(empty.encode_to_vec_fast().is_empty());
+    }
+
+    #[test]
+    fn cached_len() {
+        use bilrost::CachedLen;
+
+        #[derive(Clone, Debug, PartialEq, Eq, Message, DistinguishedMessage)]
+        struct Inner {
+            #[bilrost(1)]
+            name: String,
+            #[bilrost(2)]
+            values: Vec<u64>,
+        }
+
+        #[derive(Debug, PartialEq, Eq, Message, DistinguishedMessage)]
+        struct Outer {
+            #[bilrost(1)]
+            inner: CachedLen<Inner>,
+            #[bilrost(2)]
+            many: Vec<CachedLen<Inner>>,
+            #[bilrost(tag(3), recurses)]
+            child: Option<Box<CachedLen<Outer>>>,
+        }
+
+        #[derive(Debug, PartialEq, Eq, Message, DistinguishedMessage)]
+        struct Uncached {
+            #[bilrost(1)]
+            inner: Inner,
+            #[bilrost(2)]
+            many: Vec<Inner>,
+            #[bilrost(tag(3), recurses)]
+            child: Option<Box<Uncached>>,
+        }
+
+        let inner = Inner {
+            name: "inner".to_string(),
+            values: vec![1, 2, 3],
+        };
+        let mut outer = Outer {
+            inner: inner.clone().into(),
+            many: vec![inner.clone().into(), Inner::empty().into()],
+            child: Some(Box::new(
+                Outer {
+                    inner: inner.clone().into(),
+                    many: vec![],
+                    child: None,
+                }
+                .into(),
+            )),
+        };
+        let mut uncached = Uncached {
+            inner: inner.clone(),
+            many: vec![inner.clone(), Inner::empty()],
+            child: Some(Box::new(Uncached {
+                inner: inner.clone(),
+                many: vec![],
+                child: None,
+            })),
+        };
+
+        // The wrapper does not change the encoding, and measuring fills the caches
+        assert_eq!(outer.inner.cached_len(), None);
+        assert_eq!(outer.encoded_len(), uncached.encoded_len());
+        assert_eq!(outer.inner.cached_len(), Some(inner.encoded_len()));
+        assert_eq!(outer.encode_to_vec(), uncached.encode_to_vec());
+        assert_eq!(outer.encode_to_vec_fast(), uncached.encode_to_vec());
+
+        // Mutating through the wrapper discards the cached length
+        outer.inner.values.push(1 << 40);
+        uncached.inner.values.push(1 << 40);
+        assert_eq!(outer.inner.cached_len(), None);
+        outer
+            .child
+            .as_mut()
+            .unwrap()
+            .many
+            .push(inner.clone().into());
+        uncached.child.as_mut().unwrap().many.push(inner.clone());
+        assert_eq!(outer.encode_to_vec(), uncached.encode_to_vec());
+
+        let encoded = outer.encode_to_vec();
+        let (decoded, canon) = Outer::decode_distinguished(encoded.as_slice()).unwrap();
+        assert_eq!(canon, Canonicity::Canonical);
+        assert_eq!(decoded, outer);
+        assert_eq!(decoded.inner.cached_len(), None);
+
+        // Decoding into a message discards the cached length
+        let mut target = Outer::empty();
+        target.inner.name = "replaced".to_string();
+        assert!(target.encoded_len() > 0);
+        assert!(target.inner.cached_len().is_some());
+        target.replace_from(encoded.as_slice()).unwrap();
+        assert_eq!(target, outer);
+        assert_eq!(target.encoded_len(), encoded.len());
+        assert!(!target.inner.is_empty());
+        target.inner.clear();
+        assert_eq!(target.inner.cached_len(), None);
+        assert!(target.inner.is_empty());
+    }
+
+    #[test]
+    fn lazy_messages() {
+        use bilrost::bytes::Bytes;
+        use bilrost::{DecodeOptions, Lazy};
+        use std::collections::hash_map::DefaultHasher;
+        use std::hash::Hasher;
+
+        #[derive(Clone, Debug, PartialEq, Eq, Message, DistinguishedMessage)]
+        struct Payload {
+            #[bilrost(1)]
+            name: String,
+            #[bilrost(2)]
+            values: Vec<u64>,
+        }
+
+        #[derive(Debug, PartialEq, Eq, Message, DistinguishedMessage)]
+        struct Envelope {
+            #[bilrost(1)]
+            route: String,
+            #[bilrost(2)]
+            payload: Lazy<Payload>,
+        }
+
+        #[derive(Debug, PartialEq, Eq, Message)]
+        struct RawEnvelope {
+            #[bilrost(1)]
+            route: String,
+            #[bilrost(tag(2), encoding(plainbytes))]
+            payload: Vec<u8>,
+        }
+
+        let payload = Payload {
+            name: "payload".to_string(),
+            values: vec![1, 2, 3],
+        };
+        // The payload carries an unknown field, so it is not canonical and would not survive being
+        // decoded and re-encoded.
+        let mut payload_bytes = payload.encode_to_vec();
+        payload_bytes.extend(OpaqueMessage::from_iter([(100, OV::u64(5))]).encode_to_vec());
+        let input = Bytes::from(
+            RawEnvelope {
+                route: "somewhere".to_string(),
+                payload: payload_bytes.clone(),
+            }
+            .encode_to_vec(),
+        );
+
+        // Decoding from `Bytes` captures the payload without copying or decoding it
+        let mut envelope = Envelope::decode(input.clone()).unwrap();
+        assert_eq!(envelope.route, "somewhere");
+        assert!(!envelope.payload.is_decoded());
+        let captured = envelope.payload.encoded().unwrap().clone();
+        assert_eq!(captured, payload_bytes);
+        let input_range = input.as_ptr() as usize..input.as_ptr() as usize + input.len();
+        assert!(input_range.contains(&(captured.as_ptr() as usize)));
+        assert_eq!(envelope.encode_to_vec(), input);
+        assert_eq!(envelope.encode_to_vec_fast(), input);
+
+        // Reading the payload decodes it but it is still re-encoded verbatim
+        assert_eq!(envelope.payload.get().unwrap(), &payload);
+        assert!(envelope.payload.is_decoded());
+        assert_eq!(envelope.encode_to_vec(), input);
+
+        // Mutating the payload discards the original bytes
+        envelope.payload.get_mut().unwrap().values.push(4);
+        assert_eq!(envelope.payload.encoded(), None);
+        let mut modified = payload.clone();
+        modified.values.push(4);
+        assert_eq!(
+            envelope.encode_to_vec(),
+            Envelope {
+                route: "somewhere".to_string(),
+                payload: modified.clone().into(),
+            }
+            .encode_to_vec()
+        );
+        assert_eq!(envelope.payload.clone().into_inner().unwrap(), modified);
+
+        // Distinguished decoding validates the payload eagerly by default
+        let (envelope, canon) = Envelope::decode_distinguished(input.clone()).unwrap();
+        assert_eq!(canon, HasExtensions);
+        assert!(envelope.payload.is_decoded());
+        assert_eq!(
+            envelope.payload.get_distinguished().unwrap(),
+            (&payload, HasExtensions)
+        );
+        assert_eq!(envelope.encode_to_vec(), input);
+
+        // ...or lazily, reporting the payload's canonicity when it is accessed
+        let deferred = DecodeOptions::new().with_deferred_validation(true);
+        let (envelope, canon) =
+            Envelope::decode_distinguished_with_options(input.clone(), &deferred).unwrap();
+        assert_eq!(canon, Canonicity::Canonical);
+        assert!(!envelope.payload.is_decoded());
+        assert_eq!(
+            envelope.payload.get_distinguished().unwrap(),
+            (&payload, HasExtensions)
+        );
+        assert_eq!(envelope.encode_to_vec(), input);
+
+        // Lazy messages compare by how they would be encoded; the forwarded unknown field makes
+        // this payload differ from one created from its value
+        assert_ne!(envelope.payload, Lazy::new(payload.clone()));
+        assert_ne!(envelope.payload, Lazy::new(modified.clone()));
+        let canonical_payload = Lazy::<Payload>::from_encoded(payload.encode_to_vec().into());
+        assert_eq!(canonical_payload, Lazy::new(payload.clone()));
+        assert_ne!(canonical_payload, Lazy::new(modified));
+
+        // Invalid payloads are only detected when the payload is accessed or validated
+        let invalid = RawEnvelope {
+            route: "nowhere".to_string(),
+            payload: vec![0xff],
+        }
+        .encode_to_vec();
+        let envelope = Envelope::decode(invalid.as_slice()).unwrap();
+        assert_eq!(envelope.payload.get().unwrap_err().kind(), Truncated);
+        assert_eq!(envelope.encode_to_vec(), invalid);
+        assert_eq!(
+            Envelope::decode_distinguished(invalid.as_slice())
+                .unwrap_err()
+                .kind(),
+            Truncated
+        );
+        let (envelope, canon) =
+            Envelope::decode_distinguished_with_options(invalid.as_slice(), &deferred).unwrap();
+        assert_eq!(canon, Canonicity::Canonical);
+        assert_eq!(
+            envelope.payload.get_distinguished().unwrap_err().kind(),
+            Truncated
+        );
+
+        // Empty payloads are canonically omitted
+        let empty = Envelope::empty();
+        assert!(empty.payload.is_empty());
+        assert!(empty.encode_to_vec().is_empty());
+        assert_eq!(
+            Envelope::decode_distinguished([0x09, 0x00].as_slice()).unwrap(),
+            (Envelope::empty(), NotCanonical)
+        );
+
+        // Lazy messages created from bytes with unknown fields are still forwarded verbatim, so they
+        // only compare equal to messages that encode the same way
+        #[derive(Clone, Debug, PartialEq, Eq, Message, DistinguishedMessage)]
+        struct Inner {
+            #[bilrost(1)]
+            a: u32,
+        }
+
+        #[derive(Clone, Debug, PartialEq, Eq, Message, DistinguishedMessage)]
+        struct Outer {
+            #[bilrost(1)]
+            x: u32,
+            #[bilrost(2)]
+            inner: Lazy<Inner>,
+        }
+
+        let hash = |message: &Outer| {
+            let mut hasher = DefaultHasher::new();
+            message.canonical_hash(&mut hasher);
+            hasher.finish()
+        };
+        let non_canonical = [0x04, 0x01, 0x05, 0x02, 0x08, 0x00];
+        let from_bytes = Outer {
+            x: 1,
+            inner: Lazy::from_encoded(Bytes::from_static(&[0x08, 0x00])),
+        };
+        let from_value = Outer {
+            x: 1,
+            inner: Lazy::new(Inner { a: 0 }),
+        };
+        assert!(!from_bytes.inner.is_empty());
+        assert_eq!(from_bytes.encode_to_vec(), non_canonical);
+        assert_eq!(from_bytes.encode_to_vec_fast(), non_canonical);
+        assert_eq!(from_value.encode_to_vec(), [0x04, 0x01]);
+        assert_ne!(from_bytes, from_value);
+        assert_eq!(
+            from_bytes.inner.get().unwrap(),
+            from_value.inner.get().unwrap()
+        );
+        // Reading the message does not change how it is encoded or compared
+        assert_eq!(from_bytes.encode_to_vec(), non_canonical);
+        assert_ne!(from_bytes, from_value);
+        assert_eq!(from_bytes, from_bytes.clone());
+        assert_eq!(hash(&from_bytes), hash(&from_bytes.clone()));
+
+        // Canonical bytes compare equal to the value they encode, and hash the same
+        let from_canonical_bytes = Outer {
+            x: 1,
+            inner: Lazy::from_encoded(Bytes::from_static(&[0x04, 0x01])),
+        };
+        let from_nonzero_value = Outer {
+            x: 1,
+            inner: Lazy::new(Inner { a: 1 }),
+        };
+        assert_eq!(from_canonical_bytes, from_nonzero_value);
+        assert_eq!(
+            from_canonical_bytes.encode_to_vec(),
+            from_nonzero_value.encode_to_vec()
+        );
+        assert_eq!(hash(&from_canonical_bytes), hash(&from_nonzero_value));
+
+        // Distinguished decoding reports the non-canonical contents, but still forwards them
+        let (outer, canon) = Outer::decode_distinguished(non_canonical.as_slice()).unwrap();
+        assert_eq!(canon, HasExtensions);
+        assert_eq!(outer, from_bytes);
+        assert_eq!(outer.encode_to_vec(), non_canonical);
+        let (deferred_outer, canon) =
+            Outer::decode_distinguished_with_options(non_canonical.as_slice(), &deferred).unwrap();
+        assert_eq!(canon, Canonicity::Canonical);
+        assert_eq!(deferred_outer.encode_to_vec(), non_canonical);
+        assert_eq!(
+            deferred_outer.inner.get_distinguished().unwrap(),
+            (&Inner { a: 0 }, HasExtensions)
+        );
+        assert_eq!(hash(&deferred_outer), hash(&from_bytes));
+
+        // The same goes for bytes that encode a default value explicitly
+        let explicit_zero = [0x04, 0x01, 0x05, 0x02, 0x04, 0x00];
+        let (outer, canon) = Outer::decode_distinguished(explicit_zero.as_slice()).unwrap();
+        assert_eq!(canon, NotCanonical);
+        assert_eq!(outer.inner.get().unwrap(), &Inner { a: 0 });
+        assert_ne!(outer, from_value);
+        assert_eq!(outer.encode_to_vec(), explicit_zero);
+        assert_eq!(outer.encode_to_vec_fast(), explicit_zero);
+    }
+
+    #[test]
+    fn decode_only_selected_fields() {
+        use bilrost::TagFilter;
+        use std::collections::BTreeMap;
+
+        #[derive(Clone, Debug, PartialEq, Eq, Message)]
+        struct Header {
+            #[bilrost(1)]
+            route: String,
+            #[bilrost(2)]
+            timestamp: u64,
+            #[bilrost(3)]
+            labels: BTreeMap<String, String>,
+        }
+
+        #[derive(Clone, Debug, PartialEq, Eq, Message)]
+        struct Big {
+            #[bilrost(1)]
+            id: u64,
+            #[bilrost(2)]
+            body: String,
+            #[bilrost(3)]
+            header: Header,
+            #[bilrost(4)]
+            history: Vec<Header>,
+        }
+
+        let header = Header {
+            route: "a.b.c".to_string(),
+            timestamp: 1234,
+            labels: [("k".to_string(), "v".to_string())].into(),
+        };
+        let big = Big {
+            id: 5,
+            body: "lots of data".repeat(10),
+            header: header.clone(),
+            history: vec![header.clone(), header.clone()],
+        };
+        let encoded = big.encode_to_vec();
+
+        assert_eq!(
+            Big::decode_only(encoded.as_slice(), &[]).unwrap(),
+            Big::empty()
+        );
+        assert_eq!(Big::decode_only(encoded.as_slice(), &[&[]]).unwrap(), big);
+        assert_eq!(
+            Big::decode_only(encoded.as_slice(), &[&[1], &[3, 1]]).unwrap(),
+            Big {
+                id: 5,
+                header: Header {
+                    route: "a.b.c".to_string(),
+                    ..Header::empty()
+                },
+                ..Big::empty()
+            }
+        );
+        // Paths pass through collections of messages
+        let only_timestamps = Big::decode_only(encoded.as_slice(), &[&[4, 2]]).unwrap();
+        assert_eq!(
+            only_timestamps.history,
+            vec![
+                Header {
+                    timestamp: 1234,
+                    ..Header::empty()
+                };
+                2
+            ]
+        );
+        // Selecting a whole field includes all of its nested fields
+        let filter = TagFilter::from_paths(&[&[3, 1], &[3]]);
+        assert_eq!(filter, TagFilter::new().with_path(&[3]));
+        assert!(filter.selects(3));
+        assert!(!filter.selects(1));
+        assert_eq!(
+            Big::decode_filtered(encoded.as_slice(), &filter).unwrap(),
+            Big {
+                header: header.clone(),
+                ..Big::empty()
+            }
+        );
+
+        // Fields that are not selected are not decoded at all, so they cannot cause errors
+        let invalid = [0x04, 0x07, 0x05, 0x02, 0xff, 0xfe]; // id: 7, body: invalid UTF-8
+        assert_eq!(
+            Big::decode(invalid.as_slice()).unwrap_err().kind(),
+            InvalidValue
+        );
+        assert_eq!(
+            Big::decode_only(invalid.as_slice(), &[&[1]]).unwrap(),
+            Big {
+                id: 7,
+                ..Big::empty()
+            }
+        );
+    }
+
+    #[test]
+    fn merge_from() {
+        use std::collections::{BTreeMap, BTreeSet};
+
+        #[derive(Clone, Debug, PartialEq, Eq, Message)]
+        struct Inner {
+            #[bilrost(1)]
+            a: u32,
+            #[bilrost(2)]
+            b: String,
+        }
+
+        #[derive(Clone, Debug, PartialEq, Eq, Oneof)]
+        enum Choice {
+            Empty,
+            #[bilrost(7)]
+            Num(u64),
+            #[bilrost(8)]
+            Text(String),
+        }
+
+        #[derive(Clone, Debug, PartialEq, Eq, Oneof)]
+        enum Deep {
+            Empty,
+            #[bilrost(9)]
+            In(Inner),
+            #[bilrost(10)]
+            Other(u32),
+        }
+
+        #[derive(Clone, Debug, PartialEq, Eq, Message)]
+        struct Foo {
+            #[bilrost(1)]
+            count: u64,
+            #[bilrost(tag(2), merge = "append")]
+            items: Vec<u32>,
+            #[bilrost(3)]
+            names: BTreeMap<String, u32>,
+            #[bilrost(4)]
+            set: BTreeSet<u32>,
+            #[bilrost(tag(5), merge = "recurse")]
+            inner: Inner,
+            #[bilrost(tag(6), merge = "replace")]
+            replaced: Vec<u32>,
+            #[bilrost(oneof(7, 8))]
+            choice: Choice,
+            #[bilrost(oneof(9, 10), merge = "recurse")]
+            deep: Deep,
+        }
+
+        let mut foo = Foo {
+            count: 1,
+            items: vec![1, 2],
+            names: [("a".to_string(), 1), ("b".to_string(), 2)].into(),
+            set: [1, 2].into(),
+            inner: Inner {
+                a: 5,
+                b: "five".to_string(),
+            },
+            replaced: vec![1, 2],
+            choice: Choice::Num(3),
+            deep: Deep::In(Inner {
+                a: 1,
+                b: "one".to_string(),
+            }),
+        };
+        let update = Foo {
+            count: 2,
+            items: vec![3],
+            names: [("b".to_string(), 20), ("c".to_string(), 30)].into(),
+            set: [2, 3].into(),
+            inner: Inner {
+                a: 0,
+                b: "six".to_string(),
+            },
+            replaced: vec![3],
+            choice: Choice::Text("text".to_string()),
+            deep: Deep::In(Inner {
+                a: 2,
+                b: String::new(),
+            }),
+        };
+        foo.merge_from(update.encode_to_vec().as_slice()).unwrap();
+        assert_eq!(
+            foo,
+            Foo {
+                count: 2,
+                items: vec![1, 2, 3],
+                names: [
+                    ("a".to_string(), 1),
+                    ("b".to_string(), 20),
+                    ("c".to_string(), 30)
+                ]
+                .into(),
+                set: [1, 2, 3].into(),
+                inner: Inner {
+                    a: 5,
+                    b: "six".to_string(),
+                },
+                replaced: vec![3],
+                choice: Choice::Text("text".to_string()),
+                deep: Deep::In(Inner {
+                    a: 2,
+                    b: "one".to_string(),
+                }),
+            }
+        );
+
+        // Absent fields are left alone, and a oneof merged with "recurse" is still replaced by a
+        // different field
+        let update = Foo {
+            deep: Deep::Other(4),
+            ..Foo::empty()
+        };
+        let mut merged = foo.clone();
+        merged
+            .merge_from_length_delimited(update.encode_length_delimited_to_vec().as_slice())
+            .unwrap();
+        assert_eq!(
+            merged,
+            Foo {
+                deep: Deep::Other(4),
+                ..foo.clone()
+            }
+        );
+
+        // The data must still be valid by itself
+        let mut merged = foo.clone();
+        assert_eq!(
+            merged
+                .merge_from(
+                    [(7, OV::u64(1)), (8, OV::string("x"))]
+                        .into_opaque_message()
+                        .encode_to_vec()
+                        .as_slice()
+                )
+                .unwrap_err()
+                .kind(),
+            ConflictingFields
+        );
+        let mut merged = foo.clone();
+        assert_eq!(
+            merged
+                .merge_from(
+                    [(1, OV::u64(1)), (1, OV::u64(2))]
+                        .into_opaque_message()
+                        .encode_to_vec()
+                        .as_slice()
+                )
+                .unwrap_err()
+                .kind(),
+            UnexpectedlyRepeated
+        );
+        // Map keys and set items that are already present are merged, but ones repeated within the
+        // merged data are still rejected
+        let mut merged = foo.clone();
+        merged
+            .merge_from(
+                [
+                    (
+                        3,
+                        OV::packed([OV::string("a"), OV::u32(10), OV::string("d"), OV::u32(40)]),
+                    ),
+                    (4, OV::u32(1)),
+                    (4, OV::u32(4)),
+                ]
+                .into_opaque_message()
+                .encode_to_vec()
+                .as_slice(),
+            )
+            .unwrap();
+        assert_eq!(
+            merged,
+            Foo {
+                names: [
+                    ("a".to_string(), 10),
+                    ("b".to_string(), 20),
+                    ("c".to_string(), 30),
+                    ("d".to_string(), 40)
+                ]
+                .into(),
+                set: [1, 2, 3, 4].into(),
+                ..foo.clone()
+            }
+        );
+        let mut merged = foo.clone();
+        assert_eq!(
+            merged
+                .merge_from(
+                    [(
+                        3,
+                        OV::packed([OV::string("d"), OV::u32(4), OV::string("d"), OV::u32(5)])
+                    )]
+                    .into_opaque_message()
+                    .encode_to_vec()
+                    .as_slice()
+                )
+                .unwrap_err()
+                .kind(),
+            UnexpectedlyRepeated
+        );
+        let mut merged = foo.clone();
+        assert_eq!(
+            merged
+                .merge_from(
+                    [(4, OV::u32(1)), (4, OV::u32(1))]
+                        .into_opaque_message()
+                        .encode_to_vec()
+                        .as_slice()
+                )
+                .unwrap_err()
+                .kind(),
+            UnexpectedlyRepeated
+        );
+        let mut merged = foo.clone();
+        assert_eq!(
+            merged
+                .merge_from(
+                    [(9, OV::message(&Inner::empty())), (10, OV::u32(1))]
+                        .into_opaque_message()
+                        .encode_to_vec()
+                        .as_slice()
+                )
+                .unwrap_err()
+                .kind(),
+            ConflictingFields
+        );
+    }
+
+    #[test]
+    fn diff_and_patch() {
+        use bilrost::patch::{PatchOp, PatchOpKind};
+        use bilrost::{diff, Patch};
+        use std::collections::{BTreeMap, BTreeSet};
+
+        #[derive(Clone, Debug, PartialEq, Eq, Message)]
+        struct Inner {
+            #[bilrost(1)]
+            a: u32,
+            #[bilrost(2)]
+            b: String,
+        }
+
+        #[derive(Clone, Debug, PartialEq, Eq, Oneof)]
+        enum Choice {
+            Empty,
+            #[bilrost(7)]
+            Num(u64),
+            #[bilrost(8)]
+            Text(String),
+        }
+
+        #[derive(Clone, Debug, PartialEq, Eq, Message)]
+        struct Foo {
+            #[bilrost(1)]
+            count: u64,
+            #[bilrost(2)]
+            items: Vec<u32>,
+            #[bilrost(3)]
+            names: BTreeMap<String, u32>,
+            #[bilrost(4)]
+            set: BTreeSet<u32>,
+            #[bilrost(5)]
+            inner: Inner,
+            #[bilrost(tag(6), encoding(packed))]
+            packed: Vec<u32>,
+            #[bilrost(oneof(7, 8))]
+            choice: Choice,
+            #[bilrost(9)]
+            maybe: Option<Inner>,
+        }
+
+        let old = Foo {
+            count: 1,
+            items: vec![1, 2],
+            names: [("a".to_string(), 1), ("b".to_string(), 2)].into(),
+            set: [1, 3].into(),
+            inner: Inner {
+                a: 5,
+                b: "five".to_string(),
+            },
+            packed: vec![1, 2, 3],
+            choice: Choice::Num(3),
+            maybe: None,
+        };
+        let new = Foo {
+            count: 0,
+            items: vec![1, 2, 3],
+            names: [("b".to_string(), 20), ("c".to_string(), 30)].into(),
+            set: [1, 2, 3].into(),
+            inner: Inner {
+                a: 5,
+                b: "six".to_string(),
+            },
+            packed: vec![3, 2, 1],
+            choice: Choice::Text("text".to_string()),
+            maybe: Some(Inner {
+                a: 1,
+                b: String::new(),
+            }),
+        };
+
+        let patch = diff(&old, &new);
+        let shape: Vec<(&[u32], PatchOpKind)> = patch
+            .ops()
+            .iter()
+            .map(|op| (op.path.as_slice(), op.kind))
+            .collect();
+        assert_eq!(
+            shape,
+            [
+                (&[1][..], PatchOpKind::Clear),
+                (&[2], PatchOpKind::Append),
+                (&[3], PatchOpKind::RemoveKey),
+                (&[3], PatchOpKind::Append),
+                (&[4], PatchOpKind::Append),
+                (&[5, 2], PatchOpKind::Set),
+                (&[6], PatchOpKind::Set),
+                (&[8], PatchOpKind::Set),
+                (&[9], PatchOpKind::Set),
+            ]
+        );
+        let mut patched = old.clone();
+        patch.apply(&mut patched).unwrap();
+        assert_eq!(patched, new);
+
+        // Patches are messages, and can be sent elsewhere to be applied
+        let decoded = Patch::decode(patch.encode_to_vec().as_slice()).unwrap();
+        assert_eq!(decoded, patch);
+        let mut patched = old.clone();
+        patched.apply_patch(&decoded).unwrap();
+        assert_eq!(patched, new);
+
+        // Identical messages have no differences, and patching back to an empty message clears
+        assert!(diff(&new, &new).is_empty());
+        let mut patched = new.clone();
+        patched.apply_patch(&new.diff(&Foo::empty())).unwrap();
+        assert!(patched.is_empty());
+
+        // Operations that don't fit the message are rejected
+        for op in [
+            PatchOp {
+                path: vec![],
+                kind: PatchOpKind::Clear,
+                data: Default::default(),
+            },
+            PatchOp {
+                path: vec![100],
+                kind: PatchOpKind::Clear,
+                data: Default::default(),
+            },
+            PatchOp {
+                path: vec![1, 1],
+                kind: PatchOpKind::Clear,
+                data: Default::default(),
+            },
+            PatchOp {
+                path: vec![2],
+                kind: PatchOpKind::RemoveKey,
+                data: Default::default(),
+            },
+            PatchOp {
+                path: vec![1],
+                kind: PatchOpKind::Set,
+                data: [(2, OV::u64(1))].into_opaque_message().encode_to_bytes(),
+            },
+        ] {
+            let mut patched = old.clone();
+            assert_eq!(
+                patched
+                    .apply_patch(&Patch::from(vec![op.clone()]))
+                    .unwrap_err()
+                    .kind(),
+                DecodeErrorKind::InvalidPatch,
+                "{op:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn descriptors() {
+        use bilrost::descriptor::{
+            EncodingDescriptor, EnumerationVariant, MessageDescriptor, MessageRef, ValueKind,
+        };
+        use bilrost::RawMessage;
+        use std::collections::{BTreeMap, BTreeSet};
+
+        #[derive(Clone, Debug, PartialEq, Eq, Enumeration)]
+        enum Color {
+            Red = 0,
+            Green = 1,
+            #[bilrost(5)]
+            Blue,
+        }
+
+        #[derive(Clone, Debug, PartialEq, Eq, Oneof)]
+        enum Choice {
+            Empty,
+            #[bilrost(7)]
+            Num(u64),
+            #[bilrost(tag(8), encoding(plainbytes))]
+            Raw(Vec<u8>),
+        }
+
+        #[derive(Clone, Debug, PartialEq, Message)]
+        #[bilrost(reserved_tags(20, 30-39))]
+        struct Tree {
+            #[bilrost(1)]
+            name: String,
+            #[bilrost(tag(2), encoding(fixed))]
+            weight: Option<f64>,
+            #[bilrost(tag(3), encoding(packed))]
+            counts: Vec<i32>,
+            #[bilrost(4)]
+            labels: BTreeSet<String>,
+            #[bilrost(tag(5), encoding(map<general, packed>))]
+            index: BTreeMap<u32, Vec<u16>>,
+            #[bilrost(6)]
+            color: Color,
+            #[bilrost(oneof(7, 8))]
+            choice: Choice,
+            #[bilrost(tag(9), recurses)]
+            children: Vec<Tree>,
+            #[bilrost(10)]
+            r#type: bool,
+            #[bilrost(extensions)]
+            ext: OpaqueMessage<'static>,
+        }
+
+        const CHILDREN: ValueKind = ValueKind::List(&ValueKind::Message(MessageRef::of::<Tree>()));
+
+        let desc: &MessageDescriptor = Tree::DESCRIPTOR;
+        assert_eq!(desc.name, "Tree");
+        assert!(desc.has_extensions);
+        assert_eq!(desc.reserved_tags, [20..=20, 30..=39]);
+        assert!(desc.is_reserved(35));
+        assert!(!desc.is_reserved(10));
+
+        let fields: Vec<(&str, u32, String, &ValueKind)> = desc
+            .fields
+            .iter()
+            .map(|field| {
+                (
+                    field.name,
+                    field.tag,
+                    field.encoding.to_string(),
+                    field.value,
+                )
+            })
+            .collect();
+        assert_eq!(
+            fields,
+            [
+                ("name", 1, "general".to_string(), &ValueKind::String),
+                (
+                    "weight",
+                    2,
+                    "fixed".to_string(),
+                    &ValueKind::Optional(&ValueKind::F64)
+                ),
+                (
+                    "counts",
+                    3,
+                    "packed<general>".to_string(),
+                    &ValueKind::List(&ValueKind::I32)
+                ),
+                (
+                    "labels",
+                    4,
+                    "general".to_string(),
+                    &ValueKind::Set(&ValueKind::String)
+                ),
+                (
+                    "index",
+                    5,
+                    "map<general, packed<general>>".to_string(),
+                    &ValueKind::Map(&ValueKind::U32, &ValueKind::List(&ValueKind::U16))
+                ),
+                (
+                    "color",
+                    6,
+                    "general".to_string(),
+                    &ValueKind::Enumeration(Color::DESCRIPTOR)
+                ),
+                ("children", 9, "general".to_string(), &CHILDREN),
+                ("type", 10, "general".to_string(), &ValueKind::Bool),
+            ]
+        );
+        assert!(desc.field_by_name("children").unwrap().recurses);
+        assert!(!desc.field_by_name("name").unwrap().recurses);
+        assert_eq!(
+            desc.field_by_tag(3).unwrap().encoding,
+            &EncodingDescriptor::Packed(&EncodingDescriptor::General)
+        );
+
+        // Nested message descriptors can be followed, even when the message contains itself
+        let ValueKind::List(ValueKind::Message(child)) = desc.field_by_tag(9).unwrap().value else {
+            panic!("children should be a list of messages");
+        };
+        assert_eq!(child.get().name, "Tree");
+        assert_eq!(child.get().fields.len(), desc.fields.len());
+
+        // Oneof fields are described as part of their oneof
+        assert_eq!(desc.oneofs.len(), 1);
+        assert_eq!(desc.oneofs[0].name, "choice");
+        assert_eq!(desc.oneof_for_tag(8).unwrap().name, "choice");
+        assert!(desc.oneof_for_tag(6).is_none());
+        let raw = desc.field_by_name("Raw").unwrap();
+        assert_eq!((raw.tag, raw.value), (8, &ValueKind::Bytes));
+        assert_eq!(raw.encoding, &EncodingDescriptor::PlainBytes);
+        assert_eq!(desc.field_by_tag(7).unwrap().value, &ValueKind::U64);
+        assert_eq!(desc.all_fields().count(), 10);
+
+        // Enumerations describe their variants
+        assert_eq!(Color::DESCRIPTOR.name, "Color");
+        assert_eq!(
+            Color::DESCRIPTOR.variants,
+            [
+                EnumerationVariant {
+                    name: "Red",
+                    number: 0
+                },
+                EnumerationVariant {
+                    name: "Green",
+                    number: 1
+                },
+                EnumerationVariant {
+                    name: "Blue",
+                    number: 5
+                },
+            ]
+        );
+        assert_eq!(Color::DESCRIPTOR.name_of(5), Some("Blue"));
+        assert_eq!(Color::DESCRIPTOR.number_of("Green"), Some(1));
+        assert_eq!(Color::DESCRIPTOR.name_of(2), None);
+
+        // Descriptors are also available from message trait objects
+        let message: &dyn Message = &Tree::empty();
+        assert_eq!(message.descriptor().name, "Tree");
+        assert_eq!(<Box<Tree>>::DESCRIPTOR.name, "Tree");
+    }
+
+    #[test]
+    fn schema_export() {
+        use std::collections::BTreeMap;
+
+        #[derive(Clone, Debug, PartialEq, Eq, Enumeration)]
+        enum Shade {
+            Light = 0,
+            Dark = 1,
+        }
+
+        #[derive(Clone, Debug, PartialEq, Oneof)]
+        enum Contact {
+            Empty,
+            #[bilrost(3)]
+            Email(String),
+            #[bilrost(tag(4), encoding(varint))]
+            Phone(u64),
+            #[bilrost(6)]
+            Fax(u32),
+        }
+
+        #[derive(Clone, Debug, PartialEq, Message)]
+        struct Pair(u32, #[bilrost(encoding(plainbytes))] Vec<u8>);
+
+        #[derive(Clone, Debug, PartialEq, Message)]
+        #[bilrost(reserved_tags(5, 10-19))]
+        struct Person {
+            #[bilrost(1)]
+            name: String,
+            #[bilrost(2)]
+            shade: Option<Shade>,
+            #[bilrost(oneof(3, 4, 6))]
+            contact: Contact,
+            #[bilrost(tag(7), encoding(packed<fixed>))]
+            scores: Vec<f32>,
+            #[bilrost(8)]
+            pairs: BTreeMap<String, Pair>,
+            #[bilrost(tag(9), recurses)]
+            friends: Vec<Person>,
+            #[bilrost(tag(20), encoding(varint))]
+            r#type: u8,
+            #[bilrost(extensions)]
+            ext: OpaqueMessage<'static>,
+        }
+
+        assert_eq!(
+            bilrost::schema::export::<Person>(),
+            "\
+message Person {
+    reserved_tags(5, 10-19);
+    name: string = 1;
+    shade: optional<Shade> = 2;
+    scores: list<f32> = 7 encoding(packed<fixed>);
+    pairs: map<string, Pair> = 8;
+    friends: list<Person> = 9;
+    type: u8 = 20 encoding(varint);
+    oneof contact(3-4, 6) {
+        Email: string = 3;
+        Phone: u64 = 4 encoding(varint);
+        Fax: u32 = 6;
+    }
+    extensions;
+}
+
+enum Shade {
+    Light = 0;
+    Dark = 1;
+}
+
+message Pair {
+    _0: u32 = 1;
+    _1: bytes = 2 encoding(plainbytes);
+}
+"
+        );
+        // Nested messages export the types they refer to, and not the ones that refer to them
+        assert_eq!(
+            bilrost::schema::export::<Pair>(),
+            bilrost::schema::export_descriptor(Pair(0, vec![]).descriptor())
+        );
+        assert!(!bilrost::schema::export::<Pair>().contains("Person"));
+    }
+
+    #[test]
+    fn compatibility_check() {
+        use bilrost::compat::{check, Compatibility::*};
+        use bilrost::RawMessage;
+
+        mod old {
+            use bilrost::{Enumeration, Message};
+
+            #[derive(Clone, Debug, PartialEq, Eq, Enumeration)]
+            pub enum Status {
+                Active = 0,
+                Retired = 1,
+                Lost = 2,
+            }
+
+            #[derive(Message)]
+            pub struct Inner {
+                #[bilrost(1)]
+                pub count: u32,
+            }
+
+            #[derive(Message)]
+            #[bilrost(reserved_tags(30))]
+            pub struct Record {
+                #[bilrost(1)]
+                pub small: u32,
+                #[bilrost(2)]
+                pub big: u64,
+                #[bilrost(3)]
+                pub signed: u32,
+                #[bilrost(4)]
+                pub varint: u64,
+                #[bilrost(5)]
+                pub text: String,
+                #[bilrost(6)]
+                pub required: u32,
+                #[bilrost(7)]
+                pub optional: Option<u32>,
+                #[bilrost(8)]
+                pub maybe: Option<String>,
+                #[bilrost(tag(9), encoding(packed))]
+                pub packed_ints: Vec<u32>,
+                #[bilrost(tag(10), encoding(packed))]
+                pub packed_strings: Vec<String>,
+                #[bilrost(11)]
+                pub status: Status,
+                #[bilrost(12)]
+                pub renamed: bool,
+                #[bilrost(13)]
+                pub removed: bool,
+                #[bilrost(14)]
+                pub gone: bool,
+                #[bilrost(15)]
+                pub first: u32,
+                #[bilrost(16)]
+                pub second: u32,
+                #[bilrost(17)]
+                pub inner: Inner,
+                #[bilrost(18)]
+                pub items: Vec<u32>,
+                #[bilrost(19)]
+                pub general: u32,
+            }
+        }
+
+        mod new {
+            use bilrost::{Enumeration, Message, Oneof};
+            use std::collections::BTreeSet;
+
+            #[derive(Clone, Debug, PartialEq, Eq, Enumeration)]
+            pub enum Status {
+                Active = 0,
+                Retired = 1,
+                Found = 3,
+            }
+
+            #[derive(Message)]
+            pub struct Inner {
+                #[bilrost(1)]
+                pub count: i64,
+            }
+
+            #[derive(Oneof)]
+            pub enum Either {
+                Neither,
+                #[bilrost(15)]
+                First(u32),
+                #[bilrost(16)]
+                Second(u32),
+            }
+
+            #[derive(Message)]
+            #[bilrost(reserved_tags(14))]
+            pub struct Record {
+                #[bilrost(1)]
+                pub small: u64,
+                #[bilrost(2)]
+                pub big: u32,
+                #[bilrost(3)]
+                pub signed: i32,
+                #[bilrost(tag(4), encoding(fixed))]
+                pub varint: u64,
+                #[bilrost(tag(5), encoding(plainbytes))]
+                pub text: Vec<u8>,
+                #[bilrost(6)]
+                pub required: Option<u32>,
+                #[bilrost(7)]
+                pub optional: u32,
+                #[bilrost(8)]
+                pub maybe: Vec<String>,
+                #[bilrost(9)]
+                pub packed_ints: Vec<u32>,
+                #[bilrost(10)]
+                pub packed_strings: Vec<String>,
+                #[bilrost(11)]
+                pub status: Status,
+                #[bilrost(12)]
+                pub now_named: bool,
+                #[bilrost(oneof(15, 16))]
+                pub either: Either,
+                #[bilrost(17)]
+                pub inner: Inner,
+                #[bilrost(18)]
+                pub items: BTreeSet<u32>,
+                #[bilrost(tag(19), encoding(varint))]
+                pub general: u32,
+                #[bilrost(30)]
+                pub reused: bool,
+                #[bilrost(31)]
+                pub added: bool,
+            }
+        }
+
+        let report = check(old::Record::DESCRIPTOR, new::Record::DESCRIPTOR);
+        let changes: Vec<_> = report
+            .changes
+            .iter()
+            .map(|change| {
+                (
+                    change.message,
+                    change.field,
+                    change.tag,
+                    change.compatibility,
+                )
+            })
+            .collect();
+        assert_eq!(
+            changes,
+            [
+                ("Record", "small", 1, Compatible),
+                ("Record", "big", 2, Breaking),
+                ("Record", "signed", 3, Breaking),
+                ("Record", "varint", 4, Breaking),
+                ("Record", "text", 5, Compatible),
+                ("Record", "required", 6, Compatible),
+                ("Record", "optional", 7, ExpedientOnly),
+                ("Record", "maybe", 8, Compatible),
+                ("Record", "packed_ints", 9, ExpedientOnly),
+                ("Record", "packed_strings", 10, Breaking),
+                ("Record", "status", 11, Breaking),
+                ("Record", "status", 11, Compatible),
+                ("Record", "now_named", 12, Compatible),
+                ("Record", "removed", 13, ExpedientOnly),
+                ("Record", "gone", 14, ExpedientOnly),
+                ("Record", "First", 15, Compatible),
+                ("Record", "First", 15, Compatible),
+                ("Record", "First", 15, Compatible),
+                ("Record", "Second", 16, Compatible),
+                ("Record", "Second", 16, Compatible),
+                ("Record", "Second", 16, Breaking),
+                ("Record", "Second", 16, Compatible),
+                ("Record", "items", 18, Breaking),
+                ("Record", "reused", 30, Breaking),
+                ("Record", "added", 31, Compatible),
+                ("Inner", "count", 1, Breaking),
+            ]
+        );
+        assert_eq!(report.compatibility(), Breaking);
+        assert_eq!(
+            report.changes[1].to_string(),
+            "Record.big (tag 2): breaking: was narrowed from u64 to u32, so old values that are \
+            out of range fail to decode"
+        );
+        assert_eq!(
+            report.changes[13].to_string(),
+            "Record.removed (tag 13): expedient-only: was removed, so old data that sets it is not \
+            canonical; reserving its tag would keep it from being reused"
+        );
+        assert_eq!(
+            report.changes[20].description,
+            "shares oneof either with first (tag 15), but both could be present in old data, \
+            which then fails to decode"
+        );
+
+        // Messages are compatible with themselves, and changing between encodings that are the
+        // same for a type is not a change.
+        let unchanged = check(old::Record::DESCRIPTOR, old::Record::DESCRIPTOR);
+        assert_eq!(unchanged.changes, []);
+        assert_eq!(unchanged.compatibility(), Compatible);
+
+        // Different nested messages that happen to share a name are each compared
+        mod first {
+            #[derive(bilrost::Message)]
+            pub struct Inner(pub u32);
+        }
+        mod second {
+            #[derive(bilrost::Message)]
+            pub struct Inner(pub u64);
+        }
+        #[derive(Message)]
+        struct Before(first::Inner, first::Inner);
+        #[derive(Message)]
+        struct After(first::Inner, second::Inner);
+        let report = check(Before::DESCRIPTOR, After::DESCRIPTOR);
+        assert_eq!(
+            report.to_string(),
+            "Inner.0 (tag 1): compatible: was widened from u32 to u64\n"
+        );
+    }
+
+    #[test]
+    fn dynamic_message() {
+        use bilrost::dynamic::{DynamicMessage, FieldError, Value};
+        use bilrost::RawMessage;
+        use std::collections::{BTreeMap, BTreeSet};
+
+        #[derive(Clone, Debug, PartialEq, Eq, Enumeration)]
+        enum Color {
+            Red = 0,
+            Green = 1,
+            Blue = 5,
+        }
+
+        #[derive(Clone, Debug, PartialEq, Eq, Enumeration)]
+        enum Level {
+            Low = 1,
+            High = 2,
+        }
+
+        #[derive(Clone, Debug, PartialEq, Eq, Message, DistinguishedMessage)]
+        struct Inner {
+            #[bilrost(1)]
+            id: u32,
+            #[bilrost(2)]
+            name: String,
+        }
+
+        #[derive(Clone, Debug, PartialEq, Eq, Message, DistinguishedMessage)]
+        struct Tree {
+            #[bilrost(1)]
+            value: i32,
+            #[bilrost(tag(2), recurses)]
+            children: Vec<Tree>,
+        }
+
+        #[derive(Clone, Debug, PartialEq, Eq, Oneof, DistinguishedOneof)]
+        enum Choice {
+            Empty,
+            #[bilrost(20)]
+            Number(u64),
+            #[bilrost(21)]
+            Text(String),
+            #[bilrost(22)]
+            Nested(Inner),
+        }
+
+        #[derive(Clone, Debug, PartialEq, Eq, Message, DistinguishedMessage)]
+        struct Everything {
+            #[bilrost(1)]
+            flag: bool,
+            #[bilrost(2)]
+            small: u16,
+            #[bilrost(3)]
+            zigzag: i64,
+            #[bilrost(tag(4), encoding(fixed))]
+            fixed: u32,
+            #[bilrost(tag(5), encoding(varint))]
+            byte: u8,
+            #[bilrost(6)]
+            text: String,
+            #[bilrost(tag(7), encoding(plainbytes))]
+            data: Vec<u8>,
+            #[bilrost(8)]
+            color: Color,
+            #[bilrost(9)]
+            level: Option<Level>,
+            #[bilrost(10)]
+            inner: Inner,
+            #[bilrost(11)]
+            maybe_inner: Option<Inner>,
+            #[bilrost(12)]
+            maybe_count: Option<i32>,
+            #[bilrost(tag(13), encoding(packed))]
+            packed: Vec<u32>,
+            #[bilrost(14)]
+            unpacked: Vec<String>,
+            #[bilrost(tag(15), encoding(packed))]
+            packed_set: BTreeSet<i32>,
+            #[bilrost(16)]
+            set: BTreeSet<String>,
+            #[bilrost(17)]
+            map: BTreeMap<String, u64>,
+            #[bilrost(tag(18), encoding(map<fixed, general>))]
+            fixed_map: BTreeMap<u32, Inner>,
+            #[bilrost(19)]
+            levels: Vec<Level>,
+            #[bilrost(oneof(20, 21, 22))]
+            choice: Choice,
+            #[bilrost(23)]
+            tree: Tree,
+            #[bilrost(tag(24), encoding(unpacked<fixed>))]
+            fixed_list: Vec<i64>,
+            #[bilrost(tag(25), encoding(packed<fixed>))]
+            fixed_set: BTreeSet<u64>,
+        }
+
+        #[derive(Clone, Debug, PartialEq, Eq, Message, DistinguishedMessage)]
+        struct Extended {
+            #[bilrost(1)]
+            one: u64,
+            #[bilrost(oneof(3, 6))]
+            choice: Choice2,
+            #[bilrost(extensions)]
+            ext: OpaqueMessage<'static>,
+        }
+
+        #[derive(Clone, Debug, PartialEq, Eq, Oneof, DistinguishedOneof)]
+        enum Choice2 {
+            Empty,
+            #[bilrost(3)]
+            Three(u32),
+            #[bilrost(6)]
+            Six(String),
+        }
+
+        #[derive(Clone, Debug, PartialEq, Message)]
+        struct Floats {
+            #[bilrost(1)]
+            single: f32,
+            #[bilrost(2)]
+            double: f64,
+            #[bilrost(3)]
+            doubles: Vec<f64>,
+            #[bilrost(4)]
+            maybe: Option<f32>,
+        }
+
+        /// Decodes the data with both the derived type and `DynamicMessage`, and checks that they
+        /// agree on the result and re-encode the same bytes.
+        fn check<M: OwnedMessage + RawMessage + Debug>(data: &[u8]) {
+            let derived = M::decode(data);
+            let dynamic = DynamicMessage::decode(M::DESCRIPTOR, data);
+            match (&derived, &dynamic) {
+                (Ok(derived), Ok(dynamic)) => {
+                    assert_eq!(
+                        derived.encode_to_vec(),
+                        dynamic.encode_to_vec(),
+                        "{data:?} re-encoded differently: {dynamic:?}"
+                    );
+                    assert_eq!(derived.encoded_len(), dynamic.encoded_len());
+                }
+                (Err(derived), Err(dynamic)) => {
+                    assert_eq!(derived.to_string(), dynamic.to_string(), "{data:?}")
+                }
+                _ => panic!("{data:?} decoded differently: {derived:?} vs. {dynamic:?}"),
+            }
+        }
+
+        fn check_distinguished<M: DistinguishedOwnedMessage + RawMessage + Debug>(data: &[u8]) {
+            check::<M>(data);
+            let derived = M::decode_distinguished(data);
+            let dynamic = DynamicMessage::decode_distinguished(M::DESCRIPTOR, data);
+            match (&derived, &dynamic) {
+                (Ok((derived, derived_canon)), Ok((dynamic, dynamic_canon))) => {
+                    assert_eq!(derived_canon, dynamic_canon, "{data:?}");
+                    assert_eq!(
+                        derived.encode_to_vec(),
+                        dynamic.encode_to_vec(),
+                        "{data:?} re-encoded differently: {dynamic:?}"
+                    );
+                }
+                (Err(derived), Err(dynamic)) => {
+                    assert_eq!(derived.to_string(), dynamic.to_string(), "{data:?}")
+                }
+                _ => panic!("{data:?} decoded differently: {derived:?} vs. {dynamic:?}"),
+            }
+        }
+
+        let inner = |id, name: &str| Inner {
+            id,
+            name: name.into(),
+        };
+        let populated = Everything {
+            flag: true,
+            small: 300,
+            zigzag: -5,
+            fixed: 7,
+            byte: 255,
+            text: "text".into(),
+            data: b"data".to_vec(),
+            color: Color::Blue,
+            level: Some(Level::Low),
+            inner: inner(1, "one"),
+            maybe_inner: Some(inner(0, "")),
+            maybe_count: Some(0),
+            packed: vec![3, 1, 2],
+            unpacked: vec!["b".into(), "".into(), "a".into()],
+            packed_set: [-1, 0, 1].into_iter().collect(),
+            set: ["x".into(), "y".into()].into_iter().collect(),
+            map: [("k".into(), 1), ("l".into(), 0)].into_iter().collect(),
+            fixed_map: [(9, inner(2, "two")), (10, inner(0, ""))]
+                .into_iter()
+                .collect(),
+            levels: vec![Level::High, Level::Low],
+            choice: Choice::Nested(inner(0, "")),
+            tree: Tree {
+                value: 1,
+                children: vec![Tree {
+                    value: 2,
+                    children: vec![],
+                }],
+            },
+            fixed_list: vec![-1, 1],
+            fixed_set: [5, 6].into_iter().collect(),
+        };
+
+        let cases: Vec<OpaqueMessage> = vec![
+            OpaqueMessage::new(),
+            populated.encode_to_vec().into_opaque_message(),
+            [(1, OV::bool(true))].into_opaque_message(),
+            [(1, OV::bool(false))].into_opaque_message(),
+            [(1, OV::u32(2))].into_opaque_message(),
+            [(1, OV::bool(true)), (1, OV::bool(true))].into_opaque_message(),
+            [(1, OV::string("wrong"))].into_opaque_message(),
+            [(2, OV::u32(70000))].into_opaque_message(),
+            [(3, OV::i64(i64::MIN))].into_opaque_message(),
+            [(4, OV::fixed_u32(0))].into_opaque_message(),
+            [(4, OV::u32(1))].into_opaque_message(),
+            [(5, OV::u32(256))].into_opaque_message(),
+            [(6, OV::bytes(b"\xff".to_vec()))].into_opaque_message(),
+            [(6, OV::string(""))].into_opaque_message(),
+            [(7, OV::bytes(b"\xff".to_vec()))].into_opaque_message(),
+            [(8, OV::u32(5))].into_opaque_message(),
+            [(8, OV::u32(0))].into_opaque_message(),
+            [(8, OV::u32(2))].into_opaque_message(),
+            [(8, OV::u64(1 << 40))].into_opaque_message(),
+            [(9, OV::u32(0))].into_opaque_message(),
+            [(9, OV::u32(2))].into_opaque_message(),
+            [(10, OV::bytes(vec![]))].into_opaque_message(),
+            [(10, OV::message(&inner(0, "")))].into_opaque_message(),
+            [(10, OV::message(&[(1, OV::u32(0))].into_opaque_message()))].into_opaque_message(),
+            [(10, OV::message(&[(3, OV::u32(0))].into_opaque_message()))].into_opaque_message(),
+            [(10, OV::bytes(vec![0x80]))].into_opaque_message(),
+            [(11, OV::bytes(vec![]))].into_opaque_message(),
+            [(12, OV::u32(0))].into_opaque_message(),
+            [(12, OV::u32(0)), (12, OV::u32(1))].into_opaque_message(),
+            [(13, OV::packed([OV::u32(1), OV::u32(0)]))].into_opaque_message(),
+            [(13, OV::packed([]))].into_opaque_message(),
+            [(13, OV::u32(1)), (13, OV::u32(2))].into_opaque_message(),
+            [(13, OV::string("x"))].into_opaque_message(),
+            [(14, OV::string("a")), (14, OV::string("a"))].into_opaque_message(),
+            [(14, OV::packed([OV::string("a")]))].into_opaque_message(),
+            [(14, OV::string("a")), (14, OV::u32(1))].into_opaque_message(),
+            [(15, OV::packed([OV::i32(1), OV::i32(-1)]))].into_opaque_message(),
+            [(15, OV::packed([OV::i32(1), OV::i32(1)]))].into_opaque_message(),
+            [(15, OV::i32(2)), (15, OV::i32(1))].into_opaque_message(),
+            [(16, OV::string("b")), (16, OV::string("a"))].into_opaque_message(),
+            [(16, OV::string("a")), (16, OV::string("b"))].into_opaque_message(),
+            [(16, OV::string("a")), (16, OV::string("a"))].into_opaque_message(),
+            [(16, OV::packed([OV::string("b"), OV::string("a")]))].into_opaque_message(),
+            [(
+                17,
+                OV::packed([OV::string("b"), OV::u32(1), OV::string("a"), OV::u32(0)]),
+            )]
+            .into_opaque_message(),
+            [(
+                17,
+                OV::packed([OV::string("a"), OV::u32(1), OV::string("a"), OV::u32(0)]),
+            )]
+            .into_opaque_message(),
+            [(17, OV::packed([OV::string("a")]))].into_opaque_message(),
+            [(17, OV::packed([]))].into_opaque_message(),
+            [(
+                18,
+                OV::packed([OV::fixed_u32(1), OV::message(&inner(1, "x"))]),
+            )]
+            .into_opaque_message(),
+            [(18, OV::packed([OV::u32(1), OV::message(&inner(1, "x"))]))].into_opaque_message(),
+            [(18, OV::bytes(vec![1, 2, 3]))].into_opaque_message(),
+            [(19, OV::u32(2)), (19, OV::u32(1))].into_opaque_message(),
+            [(19, OV::u32(0))].into_opaque_message(),
+            [(20, OV::u64(0))].into_opaque_message(),
+            [(20, OV::u64(1)), (20, OV::u64(2))].into_opaque_message(),
+            [(20, OV::u64(1)), (21, OV::string("x"))].into_opaque_message(),
+            [(21, OV::string(""))].into_opaque_message(),
+            [
+                (22, OV::message(&inner(1, ""))),
+                (22, OV::message(&inner(0, "y"))),
+            ]
+            .into_opaque_message(),
+            [(22, OV::string("x"))].into_opaque_message(),
+            [(23, OV::message(&populated.tree))].into_opaque_message(),
+            [(
+                23,
+                OV::message(&[(2, OV::bytes(vec![]))].into_opaque_message()),
+            )]
+            .into_opaque_message(),
+            [(24, OV::packed([OV::fixed_i64(1), OV::fixed_i64(2)]))].into_opaque_message(),
+            [(24, OV::fixed_i64(1)), (24, OV::fixed_i64(1))].into_opaque_message(),
+            [(24, OV::bytes(vec![1, 2, 3]))].into_opaque_message(),
+            [(25, OV::packed([OV::fixed_u64(2), OV::fixed_u64(1)]))].into_opaque_message(),
+            [(25, OV::fixed_u64(1)), (25, OV::fixed_u64(2))].into_opaque_message(),
+            [(25, OV::u64(1))].into_opaque_message(),
+            [(26, OV::u32(1))].into_opaque_message(),
+            [(0, OV::u32(1)), (1, OV::bool(true))].into_opaque_message(),
+        ];
+
+        // Every case, and every pair of cases, decodes the same way
+        for (a, b) in cases.iter().cartesian_product(&cases) {
+            let merged: OpaqueMessage = a
+                .iter()
+                .chain(b.iter())
+                .map(|(t, v)| (*t, v.clone()))
+                .collect();
+            check_distinguished::<Everything>(&merged.encode_to_vec());
+            let mut concatenated = a.encode_to_vec();
+            concatenated.extend(b.encode_to_vec());
+            check_distinguished::<Everything>(&concatenated);
+        }
+        for case in &cases {
+            let data = case.encode_to_vec();
+            check_distinguished::<Inner>(&data);
+            check_distinguished::<Tree>(&data);
+            check_distinguished::<Extended>(&data);
+            check::<Floats>(&data);
+        }
+        for case in [
+            [(1, OV::f32(1.5)), (2, OV::f64(-0.0))].into_opaque_message(),
+            [(1, OV::fixed_u32(0)), (2, OV::fixed_u64(0))].into_opaque_message(),
+            [(3, OV::packed([OV::f64(1.0), OV::f64(f64::NAN)]))].into_opaque_message(),
+            [(3, OV::f64(1.0)), (3, OV::f64(2.0)), (4, OV::f32(0.0))].into_opaque_message(),
+            [(4, OV::u32(1))].into_opaque_message(),
+        ] {
+            check::<Floats>(&case.encode_to_vec());
+        }
+        for case in [
+            [
+                (0, OV::string("zero")),
+                (1, OV::u64(1)),
+                (2, OV::u32(2)),
+                (2, OV::string("two")),
+            ]
+            .into_opaque_message(),
+            [
+                (3, OV::u32(3)),
+                (3, OV::u32(33)),
+                (4, OV::u32(44)),
+                (6, OV::string("six")),
+            ]
+            .into_opaque_message(),
+            [(3, OV::u32(3)), (6, OV::string("six"))].into_opaque_message(),
+        ] {
+            check_distinguished::<Extended>(&case.encode_to_vec());
+        }
+
+        // Fields that can't be represented are kept with the unknown fields
+        #[derive(Clone, Debug, PartialEq, Eq, Message, DistinguishedMessage)]
+        struct Unsupported {
+            #[bilrost(1)]
+            one: u32,
+            #[bilrost(tag(2), encoding(fixed))]
+            raw: [u8; 4],
+        }
+        for case in [
+            [(1, OV::u32(1)), (2, OV::fixed_u32(0x01020304))].into_opaque_message(),
+            [(2, OV::fixed_u32(0)), (2, OV::fixed_u32(1))].into_opaque_message(),
+        ] {
+            check::<Unsupported>(&case.encode_to_vec());
+        }
+        let unsupported = DynamicMessage::decode(
+            Unsupported::DESCRIPTOR,
+            &*[(2, OV::fixed_u32(5))]
+                .into_opaque_message()
+                .encode_to_vec(),
+        )
+        .unwrap();
+        assert_eq!(
+            unsupported.unknown_fields(),
+            &[(2, OV::fixed_u32(5))].into_opaque_message()
+        );
+        assert_eq!(
+            DynamicMessage::decode_distinguished(Unsupported::DESCRIPTOR, [].as_slice())
+                .unwrap_err()
+                .kind(),
+            DecodeErrorKind::Other
+        );
+
+        // Messages with floats can't be decoded in distinguished mode
+        assert_eq!(
+            DynamicMessage::decode_distinguished(Floats::DESCRIPTOR, [].as_slice())
+                .unwrap_err()
+                .kind(),
+            DecodeErrorKind::Other
+        );
+
+        // Fields can be read and written by name and by tag
+        let mut message =
+            DynamicMessage::decode(Everything::DESCRIPTOR, &*populated.encode_to_vec()).unwrap();
+        assert_eq!(message.get("small"), Some(&Value::U16(300)));
+        assert_eq!(message.get_by_tag(8), Some(&Value::Enumeration(5)));
+        assert_eq!(message.get("maybe_count"), Some(&Value::I32(0)));
+        assert_eq!(message.get("Number"), None);
+        assert_eq!(
+            message.get("Nested"),
+            Some(&Value::Message(DynamicMessage::new(Inner::DESCRIPTOR)))
+        );
+        assert_eq!(message.set("Number", Value::U64(5)), Ok(()));
+        assert_eq!(message.get("Nested"), None);
+        assert_eq!(
+            message.set("small", Value::U32(1)),
+            Err(FieldError::WrongKind)
+        );
+        assert_eq!(
+            message.set("color", Value::Enumeration(2)),
+            Err(FieldError::WrongKind)
+        );
+        // A different message that happens to have the same name is not the right kind
+        mod other {
+            #[derive(bilrost::Message)]
+            pub struct Inner(pub u64);
+        }
+        assert_eq!(
+            message.set(
+                "Nested",
+                Value::Message(DynamicMessage::new(other::Inner::DESCRIPTOR))
+            ),
+            Err(FieldError::WrongKind)
+        );
+        assert_eq!(
+            message.set("missing", Value::Bool(true)),
+            Err(FieldError::NoSuchField)
+        );
+        assert_eq!(
+            message.set(
+                "set",
+                Value::Set(vec![Value::String("b".into()), Value::String("a".into())])
+            ),
+            Ok(())
+        );
+        assert_eq!(message.set("flag", Value::Bool(false)), Ok(()));
+        assert_eq!(message.get("flag"), None);
+        assert_eq!(message.remove("text"), Some(Value::String("text".into())));
+        assert_eq!(message.set("maybe_count", Value::I32(3)), Ok(()));
+        let decoded = Everything::decode(&*message.encode_to_vec()).unwrap();
+        assert_eq!(
+            decoded,
+            Everything {
+                flag: false,
+                text: String::new(),
+                maybe_count: Some(3),
+                set: ["a".into(), "b".into()].into_iter().collect(),
+                choice: Choice::Number(5),
+                ..populated.clone()
+            }
+        );
+        assert_eq!(
+            message
+                .iter()
+                .map(|(field, _)| field.tag)
+                .collect::<Vec<_>>(),
+            [2, 3, 4, 5, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 23, 24, 25]
+        );
+    }
+
+    #[test]
+    fn opaque_text_format() {
+        use bilrost::encoding::opaque::ParseError;
+
+        fn round_trips(message: &OpaqueMessage) {
+            let text = message.to_string();
+            assert_eq!(
+                text.parse::<OpaqueMessage>().as_ref(),
+                Ok(message),
+                "{text}"
+            );
+        }
+
+        let example = [
+            (1, OV::u32(42)),
+            (2, OV::str("foo")),
+            (
+                3,
+                OV::message(&[(1, OV::bytes([0x0a, 0x0b]))].into_opaque_message()),
+            ),
+            (4, OV::f32(1.5)),
+        ]
+        .into_opaque_message();
+        assert_eq!(
+            example.to_string(),
+            r#"1: 42  2: "foo"  3: {1: 0x0a0b}  4: f32(1.5)"#
+        );
+        round_trips(&example);
+
+        // Every kind of value is shown the same way whether it is borrowed or owned, and parses
+        // back to the same value
+        let values = [
+            (OV::u64(0), "0"),
+            (OV::u64(u64::MAX), "18446744073709551615"),
+            (OV::i32(-1), "1"),
+            (OV::fixed_u32(10), "fixed32(10)"),
+            (OV::fixed_i32(-1), "fixed32(4294967295)"),
+            (OV::fixed_u64(0), "fixed64(0)"),
+            (OV::fixed_i64(-2), "fixed64(18446744073709551614)"),
+            (OV::f32(-0.0), "fixed32(2147483648)"),
+            (OV::f32(-2.5e10), "f32(-25000000000.0)"),
+            (OV::f64(0.1), "f64(0.1)"),
+            (OV::f64(f64::NAN), "fixed64(9221120237041090560)"),
+            (OV::f64(1e300), "fixed64(9094988921128908188)"),
+            (OV::str(""), r#""""#),
+            (
+                OV::string("tab\tquote\"slash\\é"),
+                r#""tab\tquote\"slash\\é""#,
+            ),
+            (OV::byte_slice(b"\x00\xff"), "0x00ff"),
+            (OV::bytes(b"\x07bell".to_vec()), "0x0762656c6c"),
+            (OV::packed([OV::u32(1), OV::u32(2)]), "0x0102"),
+            (
+                OV::message(&[(0, OV::u32(1)), (0, OV::str("a"))].into_opaque_message()),
+                r#"{0: 1  0: "a"}"#,
+            ),
+        ];
+        for (value, text) in &values {
+            assert_eq!(&value.to_string(), text);
+            let message = [(7, value.clone())].into_opaque_message();
+            assert_eq!(message.to_string(), format!("7: {text}"));
+            round_trips(&message);
+            round_trips(&message.borrowed());
+        }
+        for pair in values.iter().permutations(2) {
+            round_trips(&[(1, pair[0].0.clone()), (1, pair[1].0.clone())].into_opaque_message());
+        }
+
+        // Messages nested past the display limit are shown as bytes, and still round trip
+        let mut deep = [(1, OV::u32(1))].into_opaque_message();
+        for _ in 0..150 {
+            deep = [(1, OV::message(&deep))].into_opaque_message();
+        }
+        assert!(deep.to_string().contains("0x"));
+        round_trips(&deep);
+
+        // Parsing is flexible about whitespace and accepts some additional spellings
+        assert_eq!(
+            "\n 1:5\t2 : \"\\u{e9}\\0\"  3: fixed32( 0xff )  3: f64(-inf) 4: {} 5: 0x\n"
+                .parse::<OpaqueMessage>(),
+            Ok([
+                (1, OV::u32(5)),
+                (2, OV::string("é\0")),
+                (3, OV::fixed_u32(0xff)),
+                (3, OV::f64(f64::NEG_INFINITY)),
+                (4, OV::bytes([])),
+                (5, OV::bytes([])),
+            ]
+            .into_opaque_message())
+        );
+        assert_eq!("".parse::<OpaqueMessage>(), Ok(OpaqueMessage::new()));
+
+        // Errors report where they were found
+        for (text, position, error) in [
+            (
+                "1 42",
+                2,
+                "invalid opaque message text at byte 2: expected `:` after tag",
+            ),
+            (
+                "1: 2x",
+                4,
+                "invalid opaque message text at byte 4: expected whitespace after value",
+            ),
+            (
+                "1: {2: 3",
+                8,
+                "invalid opaque message text at byte 8: unclosed `{`",
+            ),
+            (
+                "1: 2}",
+                4,
+                "invalid opaque message text at byte 4: expected a number",
+            ),
+            (
+                "1: 0xabc",
+                5,
+                "invalid opaque message text at byte 5: odd number of hexadecimal digits",
+            ),
+            (
+                "1: bogus(1)",
+                3,
+                "invalid opaque message text at byte 3: unknown kind of value",
+            ),
+            (
+                "1: fixed32(4294967296)",
+                11,
+                "invalid opaque message text at byte 11: invalid fixed-size value",
+            ),
+            (
+                "1: f32(1.5",
+                10,
+                "invalid opaque message text at byte 10: unclosed `(`",
+            ),
+            (
+                "1: \"\\q\"",
+                4,
+                "invalid opaque message text at byte 4: invalid escape",
+            ),
+            (
+                "1: \"\\u{d800}\"",
+                4,
+                "invalid opaque message text at byte 4: invalid unicode escape",
+            ),
+            (
+                "1: \"abc",
+                7,
+                "invalid opaque message text at byte 7: unclosed string",
+            ),
+            (
+                "4294967296: 1",
+                0,
+                "invalid opaque message text at byte 0: number out of range",
+            ),
+            (
+                "1: -1",
+                3,
+                "invalid opaque message text at byte 3: expected a value",
+            ),
+        ] {
+            let err: ParseError = text.parse::<OpaqueMessage>().unwrap_err();
+            assert_eq!(err.position(), position, "{text}");
+            assert_eq!(err.to_string(), error);
+        }
+        let too_deep = "1: {".repeat(101) + &"}".repeat(101);
+        assert_eq!(
+            too_deep.parse::<OpaqueMessage>().unwrap_err().to_string(),
+            "invalid opaque message text at byte 403: messages nested too deeply"
+        );
+    }
+
+    #[test]
+    fn decode_options_limits() {
+        use bilrost::DecodeOptions;
+        use std::collections::BTreeMap;
+
+        #[derive(Debug, PartialEq, Eq, Message, DistinguishedMessage)]
+        struct Limited {
+            #[bilrost(1)]
+            name: String,
+            #[bilrost(2)]
+            items: Vec<u32>,
+            #[bilrost(tag(3), encoding(packed))]
+            packed: Vec<u32>,
+            #[bilrost(4)]
+            map: BTreeMap<u32, u32>,
+            #[bilrost(tag(5), recurses)]
+            child: Option<Box<Limited>>,
+        }
+
+        let leaf = Limited {
+            name: "leaf".to_string(),
+            items: vec![1, 2, 3],
+            packed: vec![4, 5, 6],
+            map: [(1, 1), (2, 2), (3, 3)].into(),
+            child: None,
+        };
+        let root = Limited {
+            name: "root".to_string(),
+            child: Some(Box::new(Limited {
+                name: "middle".to_string(),
+                child: Some(Box::new(leaf)),
+                ..EmptyState::empty()
+            })),
+            ..EmptyState::empty()
+        };
+        let encoded = root.encode_to_vec();
+
+        let decode = |options: DecodeOptions| {
+            let owned = Limited::decode_with_options(encoded.as_slice(), &options);
+            let distinguished =
+                Limited::decode_distinguished_with_options(encoded.as_slice(), &options);
+            match (owned, distinguished) {
+                (Ok(owned), Ok((distinguished, canon))) => {
+                    assert_eq!(owned, root);
+                    assert_eq!(distinguished, root);
+                    assert_eq!(canon, Canonicity::Canonical);
+                    Ok(())
+                }
+                (Err(owned), Err(distinguished)) => {
+                    assert_eq!(owned.kind(), distinguished.kind());
+                    Err(owned.kind())
+                }
+                (owned, distinguished) => {
+                    panic!("mismatched results: {owned:?} vs. {distinguished:?}")
+                }
+            }
+        };
+
+        // The default options decode just like decoding without options
+        assert_eq!(decode(DecodeOptions::default()), Ok(()));
+
+        assert_eq!(decode(DecodeOptions::new().with_max_depth(2)), Ok(()));
+        assert_eq!(
+            decode(DecodeOptions::new().with_max_depth(1)),
+            Err(DecodeErrorKind::RecursionLimitReached)
+        );
+
+        assert_eq!(
+            decode(DecodeOptions::new().with_max_input_size(encoded.len())),
+            Ok(())
+        );
+        assert_eq!(
+            decode(DecodeOptions::new().with_max_input_size(encoded.len() - 1)),
+            Err(DecodeErrorKind::LimitExceeded)
+        );
+
+        assert_eq!(
+            decode(DecodeOptions::new().with_max_collection_items(3)),
+            Ok(())
+        );
+        assert_eq!(
+            decode(DecodeOptions::new().with_max_collection_items(2)),
+            Err(DecodeErrorKind::LimitExceeded)
+        );
+
+        assert_eq!(decode(DecodeOptions::new().with_max_bytes_len(6)), Ok(()));
+        assert_eq!(
+            decode(DecodeOptions::new().with_max_bytes_len(5)),
+            Err(DecodeErrorKind::LimitExceeded)
+        );
+
+        // Each string's bytes and each collection item count against the allocation budget
+        let needed = "rootmiddleleaf".len()
+            + 6 * std::mem::size_of::<u32>()
+            + 3 * std::mem::size_of::<(u32, u32)>();
+        assert_eq!(
+            decode(DecodeOptions::new().with_allocation_budget(needed)),
+            Ok(())
+        );
+        assert_eq!(
+            decode(DecodeOptions::new().with_allocation_budget(needed - 1)),
+            Err(DecodeErrorKind::LimitExceeded)
+        );
+
+        // Borrowed decoding checks the lengths of the values it borrows
+        assert_eq!(
+            OpaqueMessage::decode_borrowed_with_options(
+                &encoded,
+                &DecodeOptions::new().with_max_bytes_len(5)
+            )
+            .unwrap_err()
+            .kind(),
+            DecodeErrorKind::LimitExceeded
+        );
+        // ...and length-delimited decoding only counts the message itself towards the input size
+        let mut delimited = root.encode_length_delimited_to_vec();
+        delimited.extend_from_slice(b"trailing data");
+        assert_eq!(
+            Limited::decode_length_delimited_with_options(
+                delimited.as_slice(),
+                &DecodeOptions::new().with_max_input_size(encoded.len())
+            )
+            .unwrap(),
+            root
+        );
+    }
+
+    #[cfg(feature = "tokio-util")]
+    #[test]
+    fn codec_decodes_zero_copy() {
+        use bilrost::bytes::{Bytes, BytesMut};
+        use bilrost::codec::LengthDelimitedCodec;
+        use tokio_util::codec::{Decoder, Encoder};
+
+        #[derive(Debug, PartialEq, Eq, Message)]
+        struct Foo {
+            #[bilrost(1)]
+            data: Bytes,
+            #[bilrost(2)]
+            number: u32,
+        }
+
+        let mut codec = LengthDelimitedCodec::<Foo>::new();
+        let mut buf = BytesMut::new();
+        for number in 1..=2 {
+            let foo = Foo {
+                data: Bytes::from_static(b"some bytes"),
+                number,
+            };
+            codec.encode(&foo, &mut buf).unwrap();
+        }
+        let buffer_range = buf.as_ptr_range();
+        for number in 1..=2 {
+            let decoded = codec.decode(&mut buf).unwrap().unwrap();
+            assert_eq!(decoded.data, b"some bytes".as_slice());
+            assert_eq!(decoded.number, number);
+            // The decoded bytes refer directly to the codec's buffer
+            assert!(buffer_range.contains(&decoded.data.as_ptr()));
+        }
+        assert_eq!(codec.decode_eof(&mut buf).unwrap(), None);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_bridge() {
+        use bilrost::serde::{Deserializable, Serializable};
+        use serde_json::json;
+        use std::collections::{BTreeMap, BTreeSet};
+
+        #[derive(Clone, Debug, PartialEq, Eq, Enumeration)]
+        enum Color {
+            Red = 0,
+            Green = 1,
+            Blue = 5,
+        }
+
+        #[derive(Clone, Debug, PartialEq, Eq, Message)]
+        struct Inner {
+            #[bilrost(1)]
+            id: u32,
+            #[bilrost(2)]
+            name: String,
+        }
+
+        #[derive(Clone, Debug, PartialEq, Eq, Oneof)]
+        enum Choice {
+            Empty,
+            #[bilrost(20)]
+            Number(u64),
+            #[bilrost(21)]
+            Nested(Inner),
+        }
+
+        #[derive(Clone, Debug, PartialEq, Message)]
+        struct Outer {
+            #[bilrost(1)]
+            flag: bool,
+            #[bilrost(2)]
+            count: i64,
+            #[bilrost(3)]
+            text: String,
+            #[bilrost(tag(4), encoding(plainbytes))]
+            data: Vec<u8>,
+            #[bilrost(5)]
+            color: Color,
+            #[bilrost(6)]
+            maybe_color: Option<Color>,
+            #[bilrost(7)]
+            inner: Inner,
+            #[bilrost(8)]
+            list: Vec<Inner>,
+            #[bilrost(9)]
+            set: BTreeSet<u32>,
+            #[bilrost(10)]
+            map: BTreeMap<u32, String>,
+            #[bilrost(11)]
+            ratio: f64,
+            #[bilrost(oneof(20, 21))]
+            choice: Choice,
+        }
+
+        let outer = Outer {
+            flag: true,
+            count: -3,
+            text: "hello".into(),
+            data: vec![1, 2],
+            color: Color::Blue,
+            maybe_color: None,
+            inner: Inner {
+                id: 7,
+                name: "seven".into(),
+            },
+            list: vec![Inner {
+                id: 1,
+                name: "".into(),
+            }],
+            set: [3, 1].into_iter().collect(),
+            map: [(2, "two".to_string())].into_iter().collect(),
+            ratio: 0.5,
+            choice: Choice::Nested(Inner {
+                id: 9,
+                name: "nine".into(),
+            }),
+        };
+        let value = serde_json::to_value(Serializable(&outer)).unwrap();
+        assert_eq!(
+            value,
+            json!({
+                "flag": true,
+                "count": -3,
+                "text": "hello",
+                "data": [1, 2],
+                "color": "Blue",
+                "maybe_color": null,
+                "inner": {"id": 7, "name": "seven"},
+                "list": [{"id": 1, "name": ""}],
+                "set": [1, 3],
+                "map": {"2": "two"},
+                "ratio": 0.5,
+                "choice": {"Nested": {"id": 9, "name": "nine"}},
+            })
+        );
+        let Deserializable(decoded) =
+            serde_json::from_value::<Deserializable<Outer>>(value).unwrap();
+        assert_eq!(decoded, outer);
+
+        // Empty messages show every field, and missing fields deserialize as empty
+        let empty = Outer::empty();
+        let value = serde_json::to_value(Serializable(&empty)).unwrap();
+        assert_eq!(value["color"], json!("Red"));
+        assert_eq!(value["choice"], json!(null));
+        assert_eq!(
+            serde_json::from_value::<Deserializable<Outer>>(json!({})).unwrap(),
+            Deserializable(empty)
+        );
+        assert_eq!(
+            serde_json::from_str::<Deserializable<Outer>>(
+                r#"{"choice": {"Number": 5}, "maybe_color": "Green"}"#
+            )
+            .unwrap()
+            .0,
+            Outer {
+                choice: Choice::Number(5),
+                maybe_color: Some(Color::Green),
+                ..Outer::empty()
+            }
+        );
+
+        // Deserializing is strict about names and ranges
+        for (json, error) in [
+            (r#"{"nope": 1}"#, "unknown field `nope` in Outer"),
+            (
+                r#"{"color": "Purple"}"#,
+                "unknown variant `Purple` of Color",
+            ),
+            (
+                r#"{"choice": {"Text": "x"}}"#,
+                "unknown variant `Text` of oneof choice",
+            ),
+            (r#"{"inner": {"id": -1}}"#, "-1 is out of range for u32"),
+            (r#"{"flag": true, "flag": false}"#, "duplicate field `flag`"),
+        ] {
+            let err = serde_json::from_str::<Deserializable<Outer>>(json).unwrap_err();
+            assert!(err.to_string().starts_with(error), "{json}: {err}");
+        }
+    }
+
+    #[cfg(feature = "digest")]
+    #[test]
+    fn canonical_digests() {
+        use sha2::{Digest, Sha256};
+        use std::collections::hash_map::DefaultHasher;
+        use std::collections::BTreeMap;
+        use std::hash::Hasher;
+
+        #[derive(Debug, PartialEq, Eq, Message, DistinguishedMessage)]
+        struct Inner {
+            #[bilrost(1)]
+            id: u32,
+            #[bilrost(tag(2), encoding(plainbytes))]
+            blob: Vec<u8>,
+        }
+
+        #[derive(Debug, PartialEq, Eq, Message, DistinguishedMessage)]
+        struct Outer {
+            #[bilrost(1)]
+            name: String,
+            #[bilrost(2)]
+            inners: Vec<Inner>,
+            #[bilrost(3)]
+            map: BTreeMap<u64, Inner>,
+        }
+
+        let outer = Outer {
+            name: "a fairly long name that does not fit in one small chunk".into(),
+            inners: vec![
+                Inner {
+                    id: 1,
+                    blob: vec![7; 300],
+                },
+                Inner::empty(),
+            ],
+            map: [(
+                u64::MAX,
+                Inner {
+                    id: 5,
+                    blob: vec![],
+                },
+            )]
+            .into(),
+        };
+        let encoded = outer.encode_to_vec();
+
+        let mut sha = Sha256::new();
+        outer.canonical_digest(&mut sha);
+        assert_eq!(sha.finalize(), Sha256::digest(&encoded));
+
+        let mut hasher = DefaultHasher::new();
+        outer.canonical_hash(&mut hasher);
+        let mut expected = DefaultHasher::new();
+        expected.write(&encoded);
+        assert_eq!(hasher.finish(), expected.finish());
+    }
+
+    #[test]
+    fn signed_envelopes() {
+        use bilrost::bytes::Bytes;
+        use bilrost::envelope::{Signed, Signer, Verifier};
+        use bilrost::DecodeErrorKind::InvalidSignature;
+
+        // A toy signature scheme: the signature is the payload xored with the key, reversed.
+        struct Key(&'static [u8], u8);
+
+        fn toy_signature(key: u8, payload: &[u8]) -> Vec<u8> {
+            payload.iter().rev().map(|b| b ^ key).collect()
+        }
+
+        impl Signer for Key {
+            fn key_id(&self) -> Bytes {
+                Bytes::from_static(self.0)
+            }
+
+            fn sign(&self, payload: &[u8]) -> Vec<u8> {
+                toy_signature(self.1, payload)
+            }
+        }
+
+        impl Verifier for Key {
+            fn verify(&self, key_id: &[u8], payload: &[u8], signature: &[u8]) -> bool {
+                key_id == self.0 && signature == toy_signature(self.1, payload)
+            }
+        }
+
+        #[derive(Debug, PartialEq, Eq, Message, DistinguishedMessage)]
+        struct Entry {
+            #[bilrost(1)]
+            account: String,
+            #[bilrost(2)]
+            amount: i64,
+        }
+
+        let key = Key(b"ledger", 0x5a);
+        let entry = Entry {
+            account: "alice".into(),
+            amount: -20,
+        };
+        let signed = Signed::sign(&entry, &key);
+        assert_eq!(signed.payload(), &entry.encode_to_vec());
+        assert_eq!(signed.key_id(), &b"ledger"[..]);
+        let encoded = signed.encode_to_vec();
+        assert_eq!(
+            Signed::<Entry>::decode_verified(encoded.as_slice(), &key),
+            Ok(entry)
+        );
+        assert_eq!(
+            Signed::<Entry>::decode_distinguished(encoded.as_slice()),
+            Ok((signed.clone(), Canonicity::Canonical))
+        );
+
+        // Wrong keys and tampered signatures are rejected
+        for verifier in [Key(b"other", 0x5a), Key(b"ledger", 0x5b)] {
+            assert_eq!(signed.open(&verifier).unwrap_err().kind(), InvalidSignature);
+        }
+
+        // Payloads that are validly signed but not canonical are rejected too
+        let envelope = |payload: &OpaqueMessage| {
+            let payload = payload.encode_to_vec();
+            OpaqueMessage::from_iter([
+                (1, OV::byte_slice(&payload)),
+                (2, OV::byte_slice(b"ledger")),
+                (3, OV::byte_slice(&toy_signature(0x5a, &payload))),
+            ])
+            .encode_to_vec()
+        };
+        for (payload, kind) in [
+            (
+                OpaqueMessage::from_iter([(1, OV::str("alice")), (2, OV::i64(0))]),
+                DecodeErrorKind::NotCanonical,
+            ),
+            (
+                OpaqueMessage::from_iter([(1, OV::str("alice")), (5, OV::u32(1))]),
+                DecodeErrorKind::UnknownField,
+            ),
+        ] {
+            assert_eq!(
+                Signed::<Entry>::decode_verified(envelope(&payload).as_slice(), &key)
+                    .unwrap_err()
+                    .kind(),
+                kind
+            );
+        }
+    }
+
+    #[test]
+    fn canonical_ordering() {
+        use bilrost::CanonicalOrd;
+        use std::collections::{BTreeMap, BTreeSet};
+
+        // Enumerations are ordered by their numbers, not by the order of their variants
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Enumeration, CanonicalOrd)]
+        enum Level {
+            High = 10,
+            Low = 0,
+            Middle = 5,
+        }
+
+        assert!(Level::Low < Level::Middle);
+        assert!(Level::Middle < Level::High);
+
+        // Oneofs are ordered by the tag of their set field, with the empty variant first
+        #[derive(Clone, Debug, PartialEq, Eq, Oneof, DistinguishedOneof, CanonicalOrd)]
+        enum Pick {
+            Nothing,
+            #[bilrost(7)]
+            Name(String),
+            #[bilrost(6)]
+            Number(u64),
+        }
+
+        assert!(Pick::Nothing < Pick::Number(100));
+        assert!(Pick::Number(100) < Pick::Name("".into()));
+        assert!(Pick::Name("a".into()) < Pick::Name("b".into()));
+
+        // Messages are ordered field by field in tag order, not in declaration order
+        #[derive(Clone, Debug, PartialEq, Eq, Message, DistinguishedMessage, CanonicalOrd)]
+        struct Item {
+            #[bilrost(3)]
+            label: String,
+            #[bilrost(1)]
+            level: Level,
+            #[bilrost(oneof(6, 7))]
+            pick: Pick,
+        }
+
+        let item = |label: &str, level, pick| Item {
+            label: label.into(),
+            level,
+            pick,
+        };
+        assert!(item("z", Level::Low, Pick::Nothing) < item("a", Level::High, Pick::Nothing));
+        assert!(
+            item("a", Level::Low, Pick::Name("".into())) < item("b", Level::Low, Pick::Nothing)
+        );
+        assert!(item("a", Level::Low, Pick::Number(5)) > item("a", Level::Low, Pick::Nothing));
+
+        // Messages that contain themselves are ordered the same way
+        #[derive(Clone, Debug, PartialEq, Eq, Message, DistinguishedMessage, CanonicalOrd)]
+        struct Tree {
+            #[bilrost(1)]
+            value: u32,
+            #[bilrost(tag(2), recurses)]
+            children: Vec<Tree>,
+        }
+
+        let leaf = |value| Tree {
+            value,
+            children: vec![],
+        };
+        assert!(leaf(1) < leaf(2));
+        assert!(
+            Tree {
+                value: 1,
+                children: vec![leaf(1)],
+            } < Tree {
+                value: 1,
+                children: vec![leaf(2)],
+            }
+        );
+
+        // Which lets them be used in distinguished sets and maps
+        #[derive(Clone, Debug, PartialEq, Eq, Message, DistinguishedMessage)]
+        struct Collections {
+            #[bilrost(1)]
+            items: BTreeSet<Item>,
+            #[bilrost(2)]
+            by_level: BTreeMap<Level, String>,
+        }
+
+        let collections = Collections {
+            items: [
+                item("b", Level::High, Pick::Nothing),
+                item("a", Level::Middle, Pick::Number(3)),
+                item("a", Level::Middle, Pick::Nothing),
+            ]
+            .into(),
+            by_level: [(Level::High, "h".into()), (Level::Low, "l".into())].into(),
+        };
+        let encoded = collections.encode_to_vec();
+        assert_eq!(
+            Collections::decode_distinguished(encoded.as_slice()),
+            Ok((collections, Canonicity::Canonical))
+        );
+
+        // Set items must be sorted by their first field, even though their labels sort the other way
+        let set = |items: [(u32, &str); 2]| {
+            OpaqueMessage::from_iter(items.map(|(level, label)| {
+                let item = OpaqueMessage::from_iter([(1, OV::u32(level)), (3, OV::str(label))]);
+                (1, OV::message(&item))
+            }))
+        };
+        let expected = Collections {
+            items: [
+                item("z", Level::Middle, Pick::Nothing),
+                item("a", Level::High, Pick::Nothing),
+            ]
+            .into(),
+            by_level: BTreeMap::new(),
+        };
+        assert::decodes_non_canonically(set([(10, "a"), (5, "z")]), expected.clone(), NotCanonical);
+        assert::decodes_distinguished(set([(5, "z"), (10, "a")]), expected);
+    }
 }
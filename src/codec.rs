@@ -0,0 +1,398 @@
+//! [`tokio_util::codec`] encoders and decoders for framed streams of messages.
+//!
+//! Frames are decoded directly from the `BytesMut` buffer the codec is given, so fields of types
+//! like `Bytes` and `ByteString` refer to the frame's data without copying it.
+
+use core::fmt;
+use core::marker::PhantomData;
+use std::io;
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use tokio_util::codec::{Decoder, Encoder};
+
+use crate::stream::{peek_length_delimiter, StreamError, DEFAULT_MAX_FRAME_SIZE};
+use crate::{
+    encode_length_delimiter, length_delimiter_len, DistinguishedOwnedMessage, Message, OwnedMessage,
+};
+use crate::{DecodeError, WithCanonicity};
+
+/// The kind of length prefix that precedes each frame.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum LengthPrefix {
+    /// A varint length delimiter, as written by `Message::encode_length_delimited`.
+    #[default]
+    Varint,
+    /// A fixed-width 4 byte little-endian length.
+    FixedLe32,
+    /// A fixed-width 4 byte big-endian length.
+    FixedBe32,
+}
+
+impl LengthPrefix {
+    /// Decodes the length prefix at the start of `buf` without consuming it, returning the length
+    /// of the prefix itself and the length it specifies. Returns `Ok(None)` if the prefix is not
+    /// yet complete.
+    fn peek(self, buf: &[u8]) -> Result<Option<(usize, usize)>, StreamError> {
+        let fixed: fn([u8; 4]) -> u32 = match self {
+            LengthPrefix::Varint => {
+                return peek_length_delimiter(buf).map_err(StreamError::InvalidDelimiter)
+            }
+            LengthPrefix::FixedLe32 => u32::from_le_bytes,
+            LengthPrefix::FixedBe32 => u32::from_be_bytes,
+        };
+        Ok(buf
+            .get(..4)
+            .map(|prefix| (4, fixed(prefix.try_into().unwrap()) as usize)))
+    }
+
+    /// Returns the length of the prefix for a frame of the given length.
+    fn len(self, length: usize) -> usize {
+        match self {
+            LengthPrefix::Varint => length_delimiter_len(length),
+            LengthPrefix::FixedLe32 | LengthPrefix::FixedBe32 => 4,
+        }
+    }
+
+    /// The largest frame length this kind of prefix can represent.
+    fn max_length(self) -> usize {
+        match self {
+            LengthPrefix::Varint => usize::MAX,
+            LengthPrefix::FixedLe32 | LengthPrefix::FixedBe32 => u32::MAX as usize,
+        }
+    }
+
+    fn encode(self, length: usize, dst: &mut BytesMut) {
+        match self {
+            LengthPrefix::Varint => encode_length_delimiter(length, dst).unwrap(),
+            LengthPrefix::FixedLe32 => dst.put_u32_le(length as u32),
+            LengthPrefix::FixedBe32 => dst.put_u32(length as u32),
+        }
+    }
+}
+
+/// Configuration shared by the codecs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct FrameConfig {
+    max_frame_length: usize,
+    prefix: LengthPrefix,
+}
+
+impl FrameConfig {
+    const DEFAULT: Self = FrameConfig {
+        max_frame_length: DEFAULT_MAX_FRAME_SIZE,
+        prefix: LengthPrefix::Varint,
+    };
+
+    fn check_length(&self, length: usize) -> Result<(), StreamError> {
+        let max = self.max_frame_length.min(self.prefix.max_length());
+        if length > max {
+            Err(StreamError::FrameTooLarge { length, max })
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Splits the next complete frame from the front of `src`, if there is one.
+    fn decode_frame(&self, src: &mut BytesMut) -> Result<Option<Bytes>, StreamError> {
+        let Some((prefix_len, length)) = self.prefix.peek(src)? else {
+            return Ok(None);
+        };
+        self.check_length(length)?;
+        let frame_len = prefix_len + length;
+        if src.len() < frame_len {
+            src.reserve(frame_len - src.len());
+            return Ok(None);
+        }
+        src.advance(prefix_len);
+        Ok(Some(src.split_to(length).freeze()))
+    }
+
+    /// Reports whether any partial frame was left over when the stream ends.
+    fn finish_stream(&self, src: &BytesMut) -> Result<(), StreamError> {
+        if src.is_empty() {
+            Ok(())
+        } else {
+            Err(StreamError::TruncatedFrame)
+        }
+    }
+
+    fn encode_frame<M: Message>(&self, message: &M, dst: &mut BytesMut) -> Result<(), StreamError> {
+        let length = message.encoded_len();
+        self.check_length(length)?;
+        dst.reserve(self.prefix.len(length) + length);
+        self.prefix.encode(length, dst);
+        message.encode(dst).map_err(io::Error::from)?;
+        Ok(())
+    }
+}
+
+macro_rules! codec_config_methods {
+    () => {
+        /// Creates a new codec with the default maximum frame length and varint length prefixes.
+        pub const fn new() -> Self {
+            Self {
+                config: FrameConfig::DEFAULT,
+                _phantom: PhantomData,
+            }
+        }
+
+        /// Sets the maximum length of the message in a frame. Larger frames are rejected when
+        /// encoding and decoding.
+        pub const fn with_max_frame_length(mut self, max_frame_length: usize) -> Self {
+            self.config.max_frame_length = max_frame_length;
+            self
+        }
+
+        /// Sets the kind of length prefix that precedes each frame.
+        pub const fn with_length_prefix(mut self, prefix: LengthPrefix) -> Self {
+            self.config.prefix = prefix;
+            self
+        }
+
+        /// Returns the maximum length of the message in a frame.
+        pub const fn max_frame_length(&self) -> usize {
+            self.config.max_frame_length
+        }
+
+        /// Returns the kind of length prefix that precedes each frame.
+        pub const fn length_prefix(&self) -> LengthPrefix {
+            self.config.prefix
+        }
+    };
+}
+
+macro_rules! codec_common_impls {
+    ($codec:ident) => {
+        impl<M> Default for $codec<M> {
+            fn default() -> Self {
+                Self::new()
+            }
+        }
+
+        impl<M> Clone for $codec<M> {
+            fn clone(&self) -> Self {
+                *self
+            }
+        }
+
+        impl<M> Copy for $codec<M> {}
+
+        impl<M> fmt::Debug for $codec<M> {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.debug_struct(stringify!($codec))
+                    .field("max_frame_length", &self.config.max_frame_length)
+                    .field("length_prefix", &self.config.prefix)
+                    .finish()
+            }
+        }
+
+        impl<M: Message> Encoder<M> for $codec<M> {
+            type Error = StreamError;
+
+            fn encode(&mut self, item: M, dst: &mut BytesMut) -> Result<(), Self::Error> {
+                self.config.encode_frame(&item, dst)
+            }
+        }
+
+        impl<'a, M: Message> Encoder<&'a M> for $codec<M> {
+            type Error = StreamError;
+
+            fn encode(&mut self, item: &'a M, dst: &mut BytesMut) -> Result<(), Self::Error> {
+                self.config.encode_frame(item, dst)
+            }
+        }
+    };
+}
+
+/// Codec for frames containing a length prefix followed by a message of type `M`.
+pub struct LengthDelimitedCodec<M> {
+    config: FrameConfig,
+    _phantom: PhantomData<fn(M) -> M>,
+}
+
+impl<M> LengthDelimitedCodec<M> {
+    codec_config_methods!();
+}
+
+codec_common_impls!(LengthDelimitedCodec);
+
+impl<M: OwnedMessage> Decoder for LengthDelimitedCodec<M> {
+    type Item = M;
+    type Error = StreamError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<M>, StreamError> {
+        match self.config.decode_frame(src)? {
+            Some(frame) => Ok(Some(M::decode(frame)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn decode_eof(&mut self, src: &mut BytesMut) -> Result<Option<M>, StreamError> {
+        match self.decode(src)? {
+            Some(message) => Ok(Some(message)),
+            None => self.config.finish_stream(src).map(|()| None),
+        }
+    }
+}
+
+/// Codec for frames containing a length prefix followed by a message of type `M`, decoded in
+/// distinguished mode. Frames whose messages are not fully canonical are rejected with an error.
+pub struct DistinguishedCodec<M> {
+    config: FrameConfig,
+    _phantom: PhantomData<fn(M) -> M>,
+}
+
+impl<M> DistinguishedCodec<M> {
+    codec_config_methods!();
+}
+
+codec_common_impls!(DistinguishedCodec);
+
+impl<M: DistinguishedOwnedMessage> Decoder for DistinguishedCodec<M> {
+    type Item = M;
+    type Error = StreamError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<M>, StreamError> {
+        match self.config.decode_frame(src)? {
+            Some(frame) => {
+                let (message, canon) = M::decode_distinguished(frame)?;
+                canon.canonical().map_err(DecodeError::new)?;
+                Ok(Some(message))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn decode_eof(&mut self, src: &mut BytesMut) -> Result<Option<M>, StreamError> {
+        match self.decode(src)? {
+            Some(message) => Ok(Some(message)),
+            None => self.config.finish_stream(src).map(|()| None),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "opaque"))]
+mod test {
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    use bytes::BytesMut;
+    use tokio_util::codec::{Decoder, Encoder};
+
+    use super::{DistinguishedCodec, LengthDelimitedCodec, LengthPrefix};
+    use crate::encoding::opaque::{OpaqueMessage, OpaqueValue as OV};
+    use crate::stream::StreamError;
+    use crate::{DecodeErrorKind, Message};
+
+    fn messages() -> Vec<OpaqueMessage<'static>> {
+        vec![
+            OpaqueMessage::from_iter([(1, OV::string("hello")), (2, OV::u64(2))]),
+            OpaqueMessage::new(),
+            OpaqueMessage::from_iter([(5, OV::bytes(vec![0x55; 1000]))]),
+        ]
+    }
+
+    #[test]
+    fn round_trip_with_each_prefix() {
+        for prefix in [
+            LengthPrefix::Varint,
+            LengthPrefix::FixedLe32,
+            LengthPrefix::FixedBe32,
+        ] {
+            let mut codec = LengthDelimitedCodec::<OpaqueMessage>::new().with_length_prefix(prefix);
+            let mut buf = BytesMut::new();
+            for message in messages() {
+                codec.encode(&message, &mut buf).unwrap();
+            }
+            let first_len = messages()[0].encoded_len();
+            match prefix {
+                LengthPrefix::Varint => {
+                    assert_eq!(
+                        buf[..first_len + 1],
+                        messages()[0].encode_length_delimited_to_vec()
+                    );
+                }
+                LengthPrefix::FixedLe32 => {
+                    assert_eq!(buf[..4], (first_len as u32).to_le_bytes());
+                }
+                LengthPrefix::FixedBe32 => {
+                    assert_eq!(buf[..4], (first_len as u32).to_be_bytes());
+                }
+            }
+
+            // Feed the encoded data to the decoder a byte at a time
+            let encoded = buf.split().freeze();
+            let mut decoded = vec![];
+            for &byte in encoded.iter() {
+                buf.extend_from_slice(&[byte]);
+                if let Some(message) = codec.decode(&mut buf).unwrap() {
+                    decoded.push(message);
+                }
+            }
+            assert_eq!(codec.decode_eof(&mut buf).unwrap(), None);
+            assert_eq!(decoded, messages());
+        }
+    }
+
+    #[test]
+    fn truncated_frame() {
+        let mut codec = LengthDelimitedCodec::<OpaqueMessage>::new();
+        let mut buf = BytesMut::new();
+        codec.encode(&messages()[0], &mut buf).unwrap();
+        buf.truncate(buf.len() - 1);
+        assert_eq!(codec.decode(&mut buf).unwrap(), None);
+        assert!(matches!(
+            codec.decode_eof(&mut buf),
+            Err(StreamError::TruncatedFrame)
+        ));
+    }
+
+    #[test]
+    fn invalid_delimiter() {
+        let mut codec = LengthDelimitedCodec::<OpaqueMessage>::new();
+        let mut buf = BytesMut::from(&[0xff; 9][..]);
+        assert!(matches!(
+            codec.decode(&mut buf),
+            Err(StreamError::InvalidDelimiter(err)) if err.kind() == DecodeErrorKind::InvalidVarint
+        ));
+    }
+
+    #[test]
+    fn max_frame_length() {
+        let mut codec = LengthDelimitedCodec::<OpaqueMessage>::new().with_max_frame_length(100);
+        assert_eq!(codec.max_frame_length(), 100);
+        let mut buf = BytesMut::new();
+        codec.encode(&messages()[0], &mut buf).unwrap();
+        assert!(matches!(
+            codec.encode(&messages()[2], &mut buf),
+            Err(StreamError::FrameTooLarge { max: 100, .. })
+        ));
+
+        let mut big = LengthDelimitedCodec::<OpaqueMessage>::new();
+        big.encode(&messages()[2], &mut buf).unwrap();
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(messages()[0].clone()));
+        assert!(matches!(
+            codec.decode(&mut buf),
+            Err(StreamError::FrameTooLarge { max: 100, .. })
+        ));
+    }
+
+    #[test]
+    fn distinguished_rejects_non_canonical() {
+        let mut codec = DistinguishedCodec::<OpaqueMessage>::new();
+        let mut buf = BytesMut::new();
+        codec.encode(&messages()[0], &mut buf).unwrap();
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(messages()[0].clone()));
+
+        // The unit message has no fields, so any field it decodes is unknown
+        let mut codec = DistinguishedCodec::<()>::new();
+        buf.extend_from_slice(&[0, 2, 0x04, 0x01, 0]);
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(()));
+        assert!(matches!(
+            codec.decode(&mut buf),
+            Err(StreamError::Decode(err)) if err.kind() == DecodeErrorKind::UnknownField
+        ));
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(()));
+        assert!(buf.is_empty());
+    }
+}
@@ -0,0 +1,742 @@
+//! Checks whether data encoded with one version of a message's schema can be decoded with another.
+//!
+//! `check` compares the descriptors of two versions of a message, matching their fields by tag,
+//! and classifies every difference that can affect encoded data by how compatible it is. Changes
+//! follow the rules for compatible widening described in the README: for example, integers may be
+//! widened along the routes from `bool` through `u64` or `i64` with the `general` or `varint`
+//! encodings, strings may become bytes, values may become optional, and optional values may become
+//! unpacked collections. Nested messages are compared the same way.
+
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::{self, Display};
+
+use crate::descriptor::{
+    EncodingDescriptor, EnumerationDescriptor, FieldDescriptor, MessageDescriptor, ValueKind,
+};
+
+/// How compatible a change to a schema is, from least to most severe.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Compatibility {
+    /// Data encoded with the old schema decodes to corresponding values with the new schema, in
+    /// both expedient and distinguished mode. Data encoded with the new schema decodes with the
+    /// old one as long as its values are in range for the old types.
+    Compatible,
+    /// Data encoded with either schema still decodes with the other in expedient mode, but it may
+    /// not be canonical, and so may be rejected, in distinguished mode.
+    ExpedientOnly,
+    /// Data encoded with the old schema may fail to decode with the new schema, or decode to
+    /// different values.
+    Breaking,
+}
+
+impl Display for Compatibility {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Compatibility::Compatible => "compatible",
+            Compatibility::ExpedientOnly => "expedient-only",
+            Compatibility::Breaking => "breaking",
+        })
+    }
+}
+
+/// A single difference between two versions of a message.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Change {
+    /// The name of the message that changed, as it is named in the new schema. Changes to nested
+    /// messages are reported for the nested message.
+    pub message: &'static str,
+    /// The name of the field that changed, or of the removed field if it no longer exists.
+    pub field: &'static str,
+    /// The tag of the field that changed.
+    pub tag: u32,
+    pub compatibility: Compatibility,
+    /// Describes the change and how it affects encoded data.
+    pub description: String,
+}
+
+impl Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}.{} (tag {}): {}: {}",
+            self.message, self.field, self.tag, self.compatibility, self.description
+        )
+    }
+}
+
+/// The differences found between two versions of a message.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Report {
+    /// Every change found, in the order the messages were compared and then by tag.
+    pub changes: Vec<Change>,
+}
+
+impl Report {
+    /// Returns the compatibility of the most severe change, or `Compatible` if nothing changed.
+    pub fn compatibility(&self) -> Compatibility {
+        self.changes
+            .iter()
+            .map(|change| change.compatibility)
+            .max()
+            .unwrap_or(Compatibility::Compatible)
+    }
+
+    /// Returns an iterator over the changes that are at least as severe as the given
+    /// compatibility.
+    pub fn at_least(&self, compatibility: Compatibility) -> impl Iterator<Item = &Change> {
+        self.changes
+            .iter()
+            .filter(move |change| change.compatibility >= compatibility)
+    }
+}
+
+/// Lists each change on its own line.
+impl Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for change in &self.changes {
+            writeln!(f, "{change}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Compares two versions of a message and reports every change between them that can affect
+/// encoded data, along with changes to the messages nested in both.
+///
+/// Fields are matched by their tags, so renaming fields, oneofs, messages, or enumeration
+/// variants never affects compatibility. Fields that can't be described are assumed to be
+/// unchanged as long as their encoding is the same.
+pub fn check(old: &'static MessageDescriptor, new: &'static MessageDescriptor) -> Report {
+    let mut checker = Checker {
+        pairs: Vec::from([(old, new)]),
+        changes: Vec::new(),
+    };
+    // Pairs of nested messages are added to the list as they are found in the fields of the
+    // messages before them.
+    let mut index = 0;
+    while let Some(&(old, new)) = checker.pairs.get(index) {
+        checker.check_message(old, new);
+        index += 1;
+    }
+    Report {
+        changes: checker.changes,
+    }
+}
+
+/// How many values a field can have, as it is represented in encoded data.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Presence {
+    /// The field is absent when its value is empty.
+    Plain,
+    /// The field may be absent, or present with any value including an empty one.
+    Optional,
+    /// The field is repeated once for each of its values.
+    Repeated { set: bool },
+}
+
+/// How a single value is represented and interpreted in encoded data.
+#[derive(Clone, Copy)]
+enum Shape {
+    Varint(&'static ValueKind),
+    Fixed(&'static ValueKind),
+    /// A 128 bit integer, which is length-delimited.
+    Wide(&'static ValueKind, &'static EncodingDescriptor),
+    String,
+    Bytes,
+    Message(&'static MessageDescriptor),
+    Packed {
+        set: bool,
+        item: &'static ValueKind,
+        encoding: &'static EncodingDescriptor,
+    },
+    Map {
+        key: &'static ValueKind,
+        key_encoding: &'static EncodingDescriptor,
+        value: &'static ValueKind,
+        value_encoding: &'static EncodingDescriptor,
+    },
+    Unknown,
+}
+
+impl Shape {
+    fn of(value: &'static ValueKind, encoding: &'static EncodingDescriptor) -> Self {
+        use EncodingDescriptor::{Fixed, General, Map, Packed, PlainBytes, Varint};
+        match (value, encoding) {
+            (
+                ValueKind::Bool
+                | ValueKind::U8
+                | ValueKind::U16
+                | ValueKind::U32
+                | ValueKind::U64
+                | ValueKind::I8
+                | ValueKind::I16
+                | ValueKind::I32
+                | ValueKind::I64,
+                General | Varint,
+            )
+            | (ValueKind::Enumeration(_), General) => Shape::Varint(value),
+            (
+                ValueKind::U32
+                | ValueKind::U64
+                | ValueKind::I32
+                | ValueKind::I64
+                | ValueKind::Bytes,
+                Fixed,
+            )
+            | (ValueKind::F32 | ValueKind::F64, General | Fixed) => Shape::Fixed(value),
+            (ValueKind::U128 | ValueKind::I128, General | Fixed) => Shape::Wide(value, encoding),
+            (ValueKind::String, General) => Shape::String,
+            (ValueKind::Bytes, General | PlainBytes) => Shape::Bytes,
+            (ValueKind::Message(message), General) => Shape::Message(message.get()),
+            (ValueKind::List(item), Packed(encoding)) => Shape::Packed {
+                set: false,
+                item,
+                encoding,
+            },
+            (ValueKind::Set(item), Packed(encoding)) => Shape::Packed {
+                set: true,
+                item,
+                encoding,
+            },
+            (ValueKind::Map(key, value), General) => Shape::Map {
+                key,
+                key_encoding: &General,
+                value,
+                value_encoding: &General,
+            },
+            (ValueKind::Map(key, value), Map(key_encoding, value_encoding)) => Shape::Map {
+                key,
+                key_encoding,
+                value,
+                value_encoding,
+            },
+            _ => Shape::Unknown,
+        }
+    }
+
+    fn is_length_delimited(&self) -> bool {
+        matches!(
+            self,
+            Shape::Wide(..)
+                | Shape::String
+                | Shape::Bytes
+                | Shape::Message(_)
+                | Shape::Packed { .. }
+                | Shape::Map { .. }
+        )
+    }
+}
+
+/// Returns how many values the field can have, and the kind and encoding of each value. Oneof
+/// variants are optional, since they are present whenever they are set.
+fn field_values(
+    field: &'static FieldDescriptor,
+    in_oneof: bool,
+) -> (Presence, &'static ValueKind, &'static EncodingDescriptor) {
+    use EncodingDescriptor::{Fixed, General, PlainBytes, Unpacked, Varint};
+    if in_oneof {
+        return (Presence::Optional, field.value, field.encoding);
+    }
+    match (field.value, field.encoding) {
+        (ValueKind::Optional(inner), encoding) => (Presence::Optional, inner, encoding),
+        (ValueKind::List(item), Unpacked(encoding)) => {
+            (Presence::Repeated { set: false }, item, encoding)
+        }
+        (ValueKind::Set(item), Unpacked(encoding)) => {
+            (Presence::Repeated { set: true }, item, encoding)
+        }
+        (ValueKind::List(item), encoding @ (General | Fixed | Varint | PlainBytes)) => {
+            (Presence::Repeated { set: false }, item, encoding)
+        }
+        (ValueKind::Set(item), encoding @ (General | Fixed | Varint | PlainBytes)) => {
+            (Presence::Repeated { set: true }, item, encoding)
+        }
+        (value, encoding) => (Presence::Plain, value, encoding),
+    }
+}
+
+/// Where integer types are on the widening routes: `bool` widens to both the unsigned and the
+/// signed integers, which each widen to the larger ones of the same signedness.
+fn integer_rank(value: &ValueKind) -> Option<(u8, Option<bool>)> {
+    Some(match value {
+        ValueKind::Bool => (0, None),
+        ValueKind::U8 => (1, Some(false)),
+        ValueKind::U16 => (2, Some(false)),
+        ValueKind::U32 => (3, Some(false)),
+        ValueKind::U64 => (4, Some(false)),
+        ValueKind::I8 => (1, Some(true)),
+        ValueKind::I16 => (2, Some(true)),
+        ValueKind::I32 => (3, Some(true)),
+        ValueKind::I64 => (4, Some(true)),
+        _ => return None,
+    })
+}
+
+/// The largest value of an unsigned integer type, which `bool` is treated as for this purpose.
+fn unsigned_max(value: &ValueKind) -> Option<u64> {
+    Some(match value {
+        ValueKind::Bool => 1,
+        ValueKind::U8 => u8::MAX.into(),
+        ValueKind::U16 => u16::MAX.into(),
+        ValueKind::U32 => u32::MAX.into(),
+        ValueKind::U64 => u64::MAX,
+        _ => return None,
+    })
+}
+
+/// The fields of a message by their tags, with the name of the oneof each is part of.
+type FieldsByTag = BTreeMap<u32, (&'static FieldDescriptor, Option<&'static str>)>;
+
+struct Checker {
+    /// Pairs of old and new messages to compare, including those that are already compared.
+    pairs: Vec<(&'static MessageDescriptor, &'static MessageDescriptor)>,
+    changes: Vec<Change>,
+}
+
+/// Collects the changes found for a single field.
+struct FieldChanges {
+    changes: Vec<(Compatibility, String)>,
+}
+
+impl FieldChanges {
+    fn push(&mut self, compatibility: Compatibility, description: impl Into<String>) {
+        self.changes.push((compatibility, description.into()));
+    }
+}
+
+impl Checker {
+    fn check_message(&mut self, old: &'static MessageDescriptor, new: &'static MessageDescriptor) {
+        let fields_by_tag = |message: &'static MessageDescriptor| {
+            let mut fields: FieldsByTag = message
+                .fields
+                .iter()
+                .map(|field| (field.tag, (field, None)))
+                .collect();
+            for oneof in message.oneofs {
+                for field in oneof.fields {
+                    fields.insert(field.tag, (field, Some(oneof.name)));
+                }
+            }
+            fields
+        };
+        let old_fields = fields_by_tag(old);
+        let new_fields = fields_by_tag(new);
+        let mut tags: Vec<u32> = old_fields
+            .keys()
+            .chain(new_fields.keys())
+            .copied()
+            .collect();
+        tags.sort_unstable();
+        tags.dedup();
+
+        for tag in tags {
+            let mut changes = FieldChanges {
+                changes: Vec::new(),
+            };
+            let field_name = match (old_fields.get(&tag), new_fields.get(&tag)) {
+                (Some(&(old_field, old_oneof)), Some(&(new_field, new_oneof))) => {
+                    self.check_field(
+                        &mut changes,
+                        (old_field, old_oneof),
+                        (new_field, new_oneof),
+                        &old_fields,
+                        &new_fields,
+                    );
+                    new_field.name
+                }
+                (Some(&(old_field, _)), None) => {
+                    // Old data that sets the field still decodes, but the field is now unknown and
+                    // so is never canonical in distinguished mode.
+                    if new.is_reserved(tag) {
+                        changes.push(
+                            Compatibility::ExpedientOnly,
+                            "was removed and its tag is reserved, so old data that sets it is \
+                            not canonical",
+                        );
+                    } else {
+                        changes.push(
+                            Compatibility::ExpedientOnly,
+                            "was removed, so old data that sets it is not canonical; reserving \
+                            its tag would keep it from being reused",
+                        );
+                    }
+                    old_field.name
+                }
+                (None, Some(&(new_field, _))) => {
+                    if old.is_reserved(tag) {
+                        changes.push(
+                            Compatibility::Breaking,
+                            "was added with a tag that was reserved",
+                        );
+                    } else {
+                        changes.push(Compatibility::Compatible, "was added");
+                    }
+                    new_field.name
+                }
+                (None, None) => unreachable!(),
+            };
+            self.changes.extend(
+                changes
+                    .changes
+                    .into_iter()
+                    .map(|(compatibility, description)| Change {
+                        message: new.name,
+                        field: field_name,
+                        tag,
+                        compatibility,
+                        description,
+                    }),
+            );
+        }
+    }
+
+    fn check_field(
+        &mut self,
+        changes: &mut FieldChanges,
+        (old, old_oneof): (&'static FieldDescriptor, Option<&'static str>),
+        (new, new_oneof): (&'static FieldDescriptor, Option<&'static str>),
+        old_fields: &FieldsByTag,
+        new_fields: &FieldsByTag,
+    ) {
+        if old.name != new.name {
+            changes.push(
+                Compatibility::Compatible,
+                format!("was renamed from {}", old.name),
+            );
+        }
+        match (old_oneof, new_oneof) {
+            (None, Some(oneof)) => changes.push(
+                Compatibility::Compatible,
+                format!("moved into oneof {oneof}"),
+            ),
+            (Some(oneof), None) => changes.push(
+                Compatibility::Compatible,
+                format!("moved out of oneof {oneof}"),
+            ),
+            _ => {}
+        }
+        // Fields that share a oneof can't both be present, so they must not have been able to be
+        // present together before either.
+        if let Some(oneof) = new_oneof {
+            let conflict = new_fields
+                .iter()
+                .filter(|(&tag, &(_, other_oneof))| tag < new.tag && other_oneof == Some(oneof))
+                .find_map(|(tag, _)| match old_fields.get(tag) {
+                    Some(&(other, other_old_oneof))
+                        if other_old_oneof.is_none() || other_old_oneof != old_oneof =>
+                    {
+                        Some(other)
+                    }
+                    _ => None,
+                });
+            if let Some(other) = conflict {
+                changes.push(
+                    Compatibility::Breaking,
+                    format!(
+                        "shares oneof {oneof} with {} (tag {}), but both could be present in old \
+                        data, which then fails to decode",
+                        other.name, other.tag
+                    ),
+                );
+            }
+        }
+
+        let (old_presence, old_value, old_encoding) = field_values(old, old_oneof.is_some());
+        let (new_presence, new_value, new_encoding) = field_values(new, new_oneof.is_some());
+        let old_shape = Shape::of(old_value, old_encoding);
+        let new_shape = Shape::of(new_value, new_encoding);
+        match (old_presence, new_presence) {
+            (Presence::Repeated { set }, Presence::Plain) => {
+                if let Shape::Packed {
+                    set: new_set,
+                    item,
+                    encoding,
+                } = new_shape
+                {
+                    self.check_packing(changes, old_value, old_encoding, item, encoding);
+                    self.check_collection_kind(changes, set, new_set);
+                    return;
+                }
+                changes.push(
+                    Compatibility::Breaking,
+                    "is no longer repeated, so old data with more than one value fails to decode",
+                );
+            }
+            (Presence::Plain, Presence::Repeated { set }) => {
+                if let Shape::Packed {
+                    set: old_set,
+                    item,
+                    encoding,
+                } = old_shape
+                {
+                    self.check_packing(changes, item, encoding, new_value, new_encoding);
+                    self.check_collection_kind(changes, old_set, set);
+                    return;
+                }
+                changes.push(Compatibility::Compatible, "is now repeated");
+            }
+            (Presence::Repeated { .. }, Presence::Optional) => changes.push(
+                Compatibility::Breaking,
+                "is no longer repeated, so old data with more than one value fails to decode",
+            ),
+            (Presence::Optional, Presence::Repeated { .. }) => {
+                changes.push(Compatibility::Compatible, "is now repeated")
+            }
+            (Presence::Repeated { set: old_set }, Presence::Repeated { set: new_set }) => {
+                self.check_collection_kind(changes, old_set, new_set)
+            }
+            (Presence::Plain, Presence::Optional) => changes.push(
+                Compatibility::Compatible,
+                "can now be present with an empty value",
+            ),
+            (Presence::Optional, Presence::Plain) => changes.push(
+                Compatibility::ExpedientOnly,
+                "can no longer be present with an empty value, so old data that has one isn't \
+                canonical",
+            ),
+            (Presence::Plain, Presence::Plain) | (Presence::Optional, Presence::Optional) => {}
+        }
+        self.check_value(changes, old_value, old_encoding, new_value, new_encoding);
+    }
+
+    /// Checks a change between packed and unpacked collections with the given items.
+    fn check_packing(
+        &mut self,
+        changes: &mut FieldChanges,
+        old_item: &'static ValueKind,
+        old_encoding: &'static EncodingDescriptor,
+        new_item: &'static ValueKind,
+        new_encoding: &'static EncodingDescriptor,
+    ) {
+        if Shape::of(old_item, old_encoding).is_length_delimited()
+            || Shape::of(new_item, new_encoding).is_length_delimited()
+        {
+            changes.push(
+                Compatibility::Breaking,
+                "changed between packed and unpacked with length-delimited items, which are \
+                read differently",
+            );
+        } else {
+            changes.push(
+                Compatibility::ExpedientOnly,
+                "changed between packed and unpacked, so old data isn't canonical",
+            );
+        }
+        self.check_value(changes, old_item, old_encoding, new_item, new_encoding);
+    }
+
+    fn check_collection_kind(&mut self, changes: &mut FieldChanges, old_set: bool, new_set: bool) {
+        match (old_set, new_set) {
+            (false, true) => changes.push(
+                Compatibility::Breaking,
+                "is now a set, so old data with repeated items fails to decode",
+            ),
+            (true, false) => changes.push(Compatibility::Compatible, "is now a list"),
+            _ => {}
+        }
+    }
+
+    fn check_value(
+        &mut self,
+        changes: &mut FieldChanges,
+        old_value: &'static ValueKind,
+        old_encoding: &'static EncodingDescriptor,
+        new_value: &'static ValueKind,
+        new_encoding: &'static EncodingDescriptor,
+    ) {
+        match (
+            Shape::of(old_value, old_encoding),
+            Shape::of(new_value, new_encoding),
+        ) {
+            (Shape::Varint(old), Shape::Varint(new)) => self.check_varint(changes, old, new),
+            (Shape::Fixed(old), Shape::Fixed(new)) if old == new => {}
+            (Shape::Wide(old, old_encoding), Shape::Wide(new, new_encoding))
+                if old == new && old_encoding == new_encoding => {}
+            (Shape::String, Shape::String) | (Shape::Bytes, Shape::Bytes) => {}
+            (Shape::String, Shape::Bytes) => changes.push(
+                Compatibility::Compatible,
+                "was widened from string to bytes",
+            ),
+            (Shape::Bytes, Shape::String) => changes.push(
+                Compatibility::Breaking,
+                "changed from bytes to string, so old data that isn't valid UTF-8 fails to \
+                decode",
+            ),
+            (Shape::Message(old), Shape::Message(new)) => {
+                if !self.pairs.iter().any(|&(compared_old, compared_new)| {
+                    core::ptr::eq(compared_old, old) && core::ptr::eq(compared_new, new)
+                }) {
+                    self.pairs.push((old, new));
+                }
+            }
+            (
+                Shape::Packed {
+                    set: old_set,
+                    item: old_item,
+                    encoding: old_item_encoding,
+                },
+                Shape::Packed {
+                    set: new_set,
+                    item: new_item,
+                    encoding: new_item_encoding,
+                },
+            ) => {
+                self.check_collection_kind(changes, old_set, new_set);
+                self.check_value(
+                    changes,
+                    old_item,
+                    old_item_encoding,
+                    new_item,
+                    new_item_encoding,
+                );
+            }
+            (
+                Shape::Map {
+                    key: old_key,
+                    key_encoding: old_key_encoding,
+                    value: old_value,
+                    value_encoding: old_value_encoding,
+                },
+                Shape::Map {
+                    key: new_key,
+                    key_encoding: new_key_encoding,
+                    value: new_value,
+                    value_encoding: new_value_encoding,
+                },
+            ) => {
+                self.check_value(
+                    changes,
+                    old_key,
+                    old_key_encoding,
+                    new_key,
+                    new_key_encoding,
+                );
+                self.check_value(
+                    changes,
+                    old_value,
+                    old_value_encoding,
+                    new_value,
+                    new_value_encoding,
+                );
+            }
+            (Shape::Unknown, Shape::Unknown) if old_encoding == new_encoding => {}
+            _ => changes.push(
+                Compatibility::Breaking,
+                format!(
+                    "changed from {old_value} with encoding {old_encoding} to {new_value} with \
+                    encoding {new_encoding}, which are encoded differently"
+                ),
+            ),
+        }
+    }
+
+    fn check_varint(
+        &mut self,
+        changes: &mut FieldChanges,
+        old: &'static ValueKind,
+        new: &'static ValueKind,
+    ) {
+        match (old, new) {
+            (ValueKind::Enumeration(old), ValueKind::Enumeration(new)) => {
+                check_enumeration(changes, old, new)
+            }
+            (ValueKind::Enumeration(enumeration), _) => {
+                let fits = unsigned_max(new).map_or(false, |max| {
+                    enumeration
+                        .variants
+                        .iter()
+                        .all(|variant| u64::from(variant.number) <= max)
+                });
+                if fits {
+                    changes.push(
+                        Compatibility::Compatible,
+                        format!("changed from enumeration {} to {new}", enumeration.name),
+                    );
+                } else {
+                    changes.push(
+                        Compatibility::Breaking,
+                        format!(
+                            "changed from enumeration {} to {new}, which can't hold all of its \
+                            values",
+                            enumeration.name
+                        ),
+                    );
+                }
+            }
+            (_, ValueKind::Enumeration(enumeration)) => changes.push(
+                Compatibility::Breaking,
+                format!(
+                    "changed from {old} to enumeration {}, so old values that aren't in the \
+                    enumeration fail to decode",
+                    enumeration.name
+                ),
+            ),
+            _ => match (integer_rank(old), integer_rank(new)) {
+                (Some(_), Some(_)) if old == new => {}
+                (Some((old_rank, old_signed)), Some((new_rank, new_signed)))
+                    if old_signed.is_none() || old_signed == new_signed =>
+                {
+                    if new_rank > old_rank {
+                        changes.push(
+                            Compatibility::Compatible,
+                            format!("was widened from {old} to {new}"),
+                        );
+                    } else {
+                        changes.push(
+                            Compatibility::Breaking,
+                            format!(
+                                "was narrowed from {old} to {new}, so old values that are out of \
+                                range fail to decode"
+                            ),
+                        );
+                    }
+                }
+                _ => changes.push(
+                    Compatibility::Breaking,
+                    format!("changed from {old} to {new}, which interpret values differently"),
+                ),
+            },
+        }
+    }
+}
+
+fn check_enumeration(
+    changes: &mut FieldChanges,
+    old: &EnumerationDescriptor,
+    new: &EnumerationDescriptor,
+) {
+    for variant in old.variants {
+        match new.name_of(variant.number) {
+            None => changes.push(
+                Compatibility::Breaking,
+                format!(
+                    "enumeration {} no longer has the value {} ({}), so old data with it fails \
+                    to decode",
+                    new.name, variant.number, variant.name
+                ),
+            ),
+            Some(name) if name != variant.name => changes.push(
+                Compatibility::Compatible,
+                format!(
+                    "enumeration {} renamed the value {} from {} to {name}",
+                    new.name, variant.number, variant.name
+                ),
+            ),
+            Some(_) => {}
+        }
+    }
+    for variant in new.variants {
+        if old.name_of(variant.number).is_none() {
+            changes.push(
+                Compatibility::Compatible,
+                format!(
+                    "enumeration {} has the new value {} ({})",
+                    new.name, variant.number, variant.name
+                ),
+            );
+        }
+    }
+}
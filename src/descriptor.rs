@@ -0,0 +1,309 @@
+//! Runtime descriptions of the structure of messages.
+//!
+//! Every derived message has a `MessageDescriptor`, available as the constant
+//! `RawMessage::DESCRIPTOR` or from any message value via `Message::descriptor`. It names each of
+//! the message's fields and describes its tag, encoding, and the kind of value it holds, including
+//! the descriptors of nested messages and the variants of enumerations. Tools that work with any
+//! message, such as explainers and converters to other formats, can use these instead of needing
+//! code for each message type.
+
+use core::fmt::{self, Debug, Display};
+use core::ops::RangeInclusive;
+
+use crate::message::RawMessage;
+
+/// Describes the structure of a message type.
+#[derive(Debug)]
+pub struct MessageDescriptor {
+    /// The name of the message type, without its module path or generic parameters.
+    pub name: &'static str,
+    /// The message's fields that are not part of a oneof, in declaration order.
+    pub fields: &'static [FieldDescriptor],
+    /// The message's oneof fields, in declaration order.
+    pub oneofs: &'static [OneofDescriptor],
+    /// The ranges of tags that are reserved in the message and cannot be used by any field.
+    pub reserved_tags: &'static [RangeInclusive<u32>],
+    /// Whether the message has an extensions field that captures its unknown fields.
+    pub has_extensions: bool,
+}
+
+impl MessageDescriptor {
+    /// Returns an iterator over every field of the message, including the fields of its oneofs.
+    pub fn all_fields(&self) -> impl Iterator<Item = &FieldDescriptor> {
+        self.fields
+            .iter()
+            .chain(self.oneofs.iter().flat_map(|oneof| oneof.fields))
+    }
+
+    /// Returns the field with the given tag, if there is one. Fields that are part of a oneof are
+    /// found as well.
+    pub fn field_by_tag(&self, tag: u32) -> Option<&FieldDescriptor> {
+        self.all_fields().find(|field| field.tag == tag)
+    }
+
+    /// Returns the field with the given name, if there is one. Fields that are part of a oneof are
+    /// named by their variant in the oneof.
+    pub fn field_by_name(&self, name: &str) -> Option<&FieldDescriptor> {
+        self.all_fields().find(|field| field.name == name)
+    }
+
+    /// Returns the oneof that the field with the given tag is part of, if there is one.
+    pub fn oneof_for_tag(&self, tag: u32) -> Option<&OneofDescriptor> {
+        self.oneofs
+            .iter()
+            .find(|oneof| oneof.fields.iter().any(|field| field.tag == tag))
+    }
+
+    /// Returns true if the tag is reserved in the message.
+    pub fn is_reserved(&self, tag: u32) -> bool {
+        self.reserved_tags.iter().any(|range| range.contains(&tag))
+    }
+}
+
+/// Describes a single field of a message, or a single variant of a oneof.
+#[derive(Debug)]
+pub struct FieldDescriptor {
+    /// The name of the field, or of the variant when the field is part of a oneof. Fields of tuple
+    /// structs are named by their index.
+    pub name: &'static str,
+    /// The field's tag.
+    pub tag: u32,
+    /// The encoding of the field.
+    pub encoding: &'static EncodingDescriptor,
+    /// The kind of value held in the field.
+    pub value: &'static ValueKind,
+    /// Whether the field was marked with the `recurses` attribute.
+    pub recurses: bool,
+}
+
+/// Describes a oneof field of a message.
+#[derive(Debug)]
+pub struct OneofDescriptor {
+    /// The name of the oneof field in the message.
+    pub name: &'static str,
+    /// The variants of the oneof, each of which is a field with its own tag.
+    pub fields: &'static [FieldDescriptor],
+}
+
+/// Describes an enumeration type.
+#[derive(Debug, PartialEq, Eq)]
+pub struct EnumerationDescriptor {
+    /// The name of the enumeration type.
+    pub name: &'static str,
+    /// The variants of the enumeration, in declaration order.
+    pub variants: &'static [EnumerationVariant],
+}
+
+impl EnumerationDescriptor {
+    /// Returns the name of the variant with the given number, if there is one.
+    pub fn name_of(&self, number: u32) -> Option<&'static str> {
+        self.variants
+            .iter()
+            .find(|variant| variant.number == number)
+            .map(|variant| variant.name)
+    }
+
+    /// Returns the number of the variant with the given name, if there is one.
+    pub fn number_of(&self, name: &str) -> Option<u32> {
+        self.variants
+            .iter()
+            .find(|variant| variant.name == name)
+            .map(|variant| variant.number)
+    }
+}
+
+/// A single variant of an enumeration.
+#[derive(Debug, PartialEq, Eq)]
+pub struct EnumerationVariant {
+    /// The name of the variant.
+    pub name: &'static str,
+    /// The number that represents the variant when it is encoded.
+    pub number: u32,
+}
+
+/// Describes the encoding of a field, mirroring the encoding types in `bilrost::encoding`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum EncodingDescriptor {
+    General,
+    Fixed,
+    Varint,
+    PlainBytes,
+    Packed(&'static EncodingDescriptor),
+    Unpacked(&'static EncodingDescriptor),
+    Map(&'static EncodingDescriptor, &'static EncodingDescriptor),
+}
+
+impl Display for EncodingDescriptor {
+    /// Displays the encoding as it is spelled in the `encoding` attribute of a derived field.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EncodingDescriptor::General => f.write_str("general"),
+            EncodingDescriptor::Fixed => f.write_str("fixed"),
+            EncodingDescriptor::Varint => f.write_str("varint"),
+            EncodingDescriptor::PlainBytes => f.write_str("plainbytes"),
+            EncodingDescriptor::Packed(inner) => write!(f, "packed<{inner}>"),
+            EncodingDescriptor::Unpacked(inner) => write!(f, "unpacked<{inner}>"),
+            EncodingDescriptor::Map(key, value) => write!(f, "map<{key}, {value}>"),
+        }
+    }
+}
+
+/// Implemented by the encoding types to describe themselves at runtime.
+pub trait DescribedEncoding {
+    const DESCRIPTOR: &'static EncodingDescriptor;
+}
+
+/// Describes the kind of value held in a field.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ValueKind {
+    Bool,
+    U8,
+    U16,
+    U32,
+    U64,
+    U128,
+    I8,
+    I16,
+    I32,
+    I64,
+    I128,
+    F32,
+    F64,
+    /// A UTF-8 string.
+    String,
+    /// A string of bytes, of any length or of a fixed length.
+    Bytes,
+    /// An enumeration, encoded as its number.
+    Enumeration(&'static EnumerationDescriptor),
+    /// A nested message.
+    Message(MessageRef),
+    /// A value that may be absent, such as an `Option`.
+    Optional(&'static ValueKind),
+    /// An ordered collection of values, such as a `Vec`.
+    List(&'static ValueKind),
+    /// An unordered collection of distinct values, such as a `BTreeSet`.
+    Set(&'static ValueKind),
+    /// A mapping of keys to values, such as a `BTreeMap`.
+    Map(&'static ValueKind, &'static ValueKind),
+    /// A value whose kind is not known.
+    Unknown,
+}
+
+impl Display for ValueKind {
+    /// Displays the kind of value as its type is spelled in a `.bilrost` schema, naming messages
+    /// and enumerations by their names.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ValueKind::Bool => "bool",
+            ValueKind::U8 => "u8",
+            ValueKind::U16 => "u16",
+            ValueKind::U32 => "u32",
+            ValueKind::U64 => "u64",
+            ValueKind::U128 => "u128",
+            ValueKind::I8 => "i8",
+            ValueKind::I16 => "i16",
+            ValueKind::I32 => "i32",
+            ValueKind::I64 => "i64",
+            ValueKind::I128 => "i128",
+            ValueKind::F32 => "f32",
+            ValueKind::F64 => "f64",
+            ValueKind::String => "string",
+            ValueKind::Bytes => "bytes",
+            ValueKind::Enumeration(enumeration) => enumeration.name,
+            ValueKind::Message(message) => message.get().name,
+            ValueKind::Optional(inner) => return write!(f, "optional<{inner}>"),
+            ValueKind::List(inner) => return write!(f, "list<{inner}>"),
+            ValueKind::Set(inner) => return write!(f, "set<{inner}>"),
+            ValueKind::Map(key, value) => return write!(f, "map<{key}, {value}>"),
+            ValueKind::Unknown => "unknown",
+        })
+    }
+}
+
+impl ValueKind {
+    /// Returns true if values of this kind add to their existing items when they are merged, or if
+    /// their kind is not known.
+    pub(crate) const fn merges_by_appending(&self) -> bool {
+        match self {
+            ValueKind::Optional(inner) => inner.merges_by_appending(),
+            ValueKind::List(_) | ValueKind::Set(_) | ValueKind::Map(..) | ValueKind::Unknown => {
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns true if values of this kind are merged recursively, or if their kind is not known.
+    pub(crate) const fn merges_recursively(&self) -> bool {
+        match self {
+            ValueKind::Optional(inner) => inner.merges_recursively(),
+            ValueKind::Message(_) | ValueKind::Unknown => true,
+            _ => false,
+        }
+    }
+}
+
+/// Describes a collection with items of the given kind, which is a set if it is unordered.
+pub(crate) const fn collection_kind(unordered: bool, item: &'static ValueKind) -> ValueKind {
+    if unordered {
+        ValueKind::Set(item)
+    } else {
+        ValueKind::List(item)
+    }
+}
+
+/// Refers to the descriptor of a nested message.
+///
+/// The descriptor is only looked up when `get` is called, which allows messages to contain
+/// themselves.
+#[derive(Clone, Copy)]
+pub struct MessageRef(MessageSource);
+
+#[derive(Clone, Copy)]
+enum MessageSource {
+    Type(fn() -> &'static MessageDescriptor),
+    Function(&'static (dyn Fn() -> &'static MessageDescriptor + Sync)),
+}
+
+fn descriptor_of<M: RawMessage>() -> &'static MessageDescriptor {
+    M::DESCRIPTOR
+}
+
+impl MessageRef {
+    /// Refers to the descriptor of the given message type.
+    pub const fn of<M: RawMessage>() -> Self {
+        Self(MessageSource::Type(descriptor_of::<M>))
+    }
+
+    /// Refers to the descriptor returned by the function. This is for descriptors that are built
+    /// at runtime rather than derived, which may need to refer to descriptors that are built after
+    /// them.
+    pub const fn from_fn(get: &'static (dyn Fn() -> &'static MessageDescriptor + Sync)) -> Self {
+        Self(MessageSource::Function(get))
+    }
+
+    /// Returns the referenced descriptor.
+    pub fn get(&self) -> &'static MessageDescriptor {
+        match self.0 {
+            MessageSource::Type(get) => get(),
+            MessageSource::Function(get) => get(),
+        }
+    }
+}
+
+impl Debug for MessageRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("MessageRef").field(&self.get().name).finish()
+    }
+}
+
+/// Message references are equal when they refer to messages with the same name.
+impl PartialEq for MessageRef {
+    fn eq(&self, other: &Self) -> bool {
+        self.get().name == other.get().name
+    }
+}
+
+impl Eq for MessageRef {}
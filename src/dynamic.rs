@@ -0,0 +1,1545 @@
+//! Messages whose structure is only known at runtime.
+//!
+//! A `DynamicMessage` decodes and encodes any message described by a `MessageDescriptor`, such as
+//! the descriptor of a derived message type or one built from a schema at runtime. Its fields hold
+//! typed `Value`s that can be read and written by field name or tag. Decoding in expedient and
+//! distinguished mode follows the same rules as a derived message type with the same fields, and
+//! produces the same errors and canonicity; re-encoding a decoded message produces the same bytes
+//! as the derived type would.
+//!
+//! Only fields whose kind of value and encoding are supported by derived types can be represented.
+//! Fields of other kinds, such as fields of unknown kinds, are kept verbatim with the message's
+//! unknown fields when decoding in expedient mode, without checking their wire types, and cannot be
+//! decoded in distinguished mode.
+//! Floating point values cannot be decoded in distinguished mode either, just as derived types
+//! that contain them cannot be distinguished.
+
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::cmp::Ordering::{self, Equal, Greater, Less};
+use core::fmt::{self, Debug, Display};
+use core::ops::Bound;
+
+use bytes::{Buf, BufMut};
+
+use crate::descriptor::{
+    EncodingDescriptor, FieldDescriptor, MessageDescriptor, OneofDescriptor, ValueKind,
+};
+use crate::encoding::opaque::OpaqueMessage;
+use crate::encoding::{
+    check_wire_type, encode_varint, encoded_len_varint, peek_repeated_field, skip_field, Capped,
+    DecodeContext, DecodeLimits, DistinguishedValueDecoder, Fixed, General, PlainBytes,
+    TagMeasurer, TagReader, TagWriter, ValueDecoder, ValueEncoder, Varint, WireType,
+};
+use crate::DecodeErrorKind::{
+    ConflictingFields, Other, OutOfDomainValue, Truncated, UnexpectedlyRepeated,
+};
+use crate::{
+    Canonicity, DecodeError, DecodeErrorKind, DecodeOptions, EncodeError, MessageExtension,
+    RawDistinguishedMessageDecoder, RawMessageDecoder,
+};
+
+/// A value held in a field of a `DynamicMessage`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Bool(bool),
+    U8(u8),
+    U16(u16),
+    U32(u32),
+    U64(u64),
+    U128(u128),
+    I8(i8),
+    I16(i16),
+    I32(i32),
+    I64(i64),
+    I128(i128),
+    F32(f32),
+    F64(f64),
+    String(String),
+    Bytes(Vec<u8>),
+    /// The number of a variant of an enumeration.
+    Enumeration(u32),
+    Message(DynamicMessage),
+    /// The items of an ordered collection.
+    List(Vec<Value>),
+    /// The items of an unordered collection, in ascending order.
+    Set(Vec<Value>),
+    /// The entries of a mapping, in ascending order of their keys.
+    Map(Vec<(Value, Value)>),
+}
+
+/// The reasons a value can't be set into a field of a `DynamicMessage`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum FieldError {
+    /// The message has no field with the given name or tag.
+    NoSuchField,
+    /// The value is not of the kind that the field holds.
+    WrongKind,
+    /// The field's kind of value or encoding is not supported by `DynamicMessage`.
+    Unsupported,
+}
+
+impl Display for FieldError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            FieldError::NoSuchField => "no such field",
+            FieldError::WrongKind => "value is the wrong kind for the field",
+            FieldError::Unsupported => "field is not supported by DynamicMessage",
+        })
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for FieldError {}
+
+/// A message described by a runtime `MessageDescriptor`.
+///
+/// Fields that are absent are not stored: fields that are empty, optional fields that are `None`,
+/// and the variants of oneofs that are not set. Unknown fields are only kept when the descriptor
+/// has extensions, or when they belong to a field that `DynamicMessage` can't represent.
+#[derive(Clone)]
+pub struct DynamicMessage {
+    descriptor: &'static MessageDescriptor,
+    fields: BTreeMap<u32, Value>,
+    unknown: OpaqueMessage<'static>,
+}
+
+/// How the value of a field is encoded.
+#[derive(Clone, Copy)]
+enum Shape {
+    /// A single value, which is only encoded when it is not empty.
+    Plain(&'static ValueKind, &'static EncodingDescriptor, WireType),
+    /// A single value that is always encoded when it is present: the value of an optional field,
+    /// or of a field in a oneof.
+    Present(&'static ValueKind, &'static EncodingDescriptor, WireType),
+    /// A packed collection, which is encoded like a plain value but can also be decoded from the
+    /// unpacked representation.
+    Packed(&'static ValueKind, &'static EncodingDescriptor),
+    /// A collection whose items are each encoded as their own field, given the kind, encoding, and
+    /// wire type of the items.
+    Unpacked(&'static ValueKind, &'static EncodingDescriptor, WireType),
+    /// A field that can't be represented, which is kept with the unknown fields.
+    Unsupported,
+}
+
+impl Shape {
+    fn of(descriptor: &MessageDescriptor, field: &'static FieldDescriptor) -> Shape {
+        use EncodingDescriptor as E;
+        use ValueKind as K;
+
+        let value_shape = |kind, encoding, present| match value_wire_type(kind, encoding) {
+            Some(wire_type) if present => Shape::Present(kind, encoding, wire_type),
+            Some(wire_type) => Shape::Plain(kind, encoding, wire_type),
+            None => Shape::Unsupported,
+        };
+        if descriptor.oneof_for_tag(field.tag).is_some() {
+            return value_shape(field.value, field.encoding, true);
+        }
+        match (field.value, field.encoding) {
+            (K::Optional(inner), encoding) => value_shape(inner, encoding, true),
+            (K::List(item) | K::Set(item), E::Packed(inner)) => {
+                match value_wire_type(item, inner) {
+                    Some(_) => Shape::Packed(field.value, field.encoding),
+                    None => Shape::Unsupported,
+                }
+            }
+            (
+                K::List(item) | K::Set(item),
+                &E::Unpacked(encoding)
+                | encoding @ &(E::General | E::Fixed | E::Varint | E::PlainBytes),
+            ) => match value_wire_type(item, encoding) {
+                Some(wire_type) => Shape::Unpacked(item, encoding, wire_type),
+                None => Shape::Unsupported,
+            },
+            (kind, encoding) => value_shape(kind, encoding, false),
+        }
+    }
+}
+
+/// Returns the wire type of a single value of the given kind with the given encoding, or `None`
+/// if values of that kind can't be encoded that way.
+fn value_wire_type(kind: &ValueKind, encoding: &EncodingDescriptor) -> Option<WireType> {
+    use EncodingDescriptor as E;
+    use ValueKind as K;
+
+    Some(match (kind, encoding) {
+        (K::Bool | K::U16 | K::U32 | K::U64 | K::I16 | K::I32 | K::I64, E::General | E::Varint)
+        | (K::U8 | K::I8, E::Varint)
+        | (K::Enumeration(_), E::General) => WireType::Varint,
+        (K::U32 | K::I32 | K::F32, E::Fixed) | (K::F32, E::General) => WireType::ThirtyTwoBit,
+        (K::U64 | K::I64 | K::F64, E::Fixed) | (K::F64, E::General) => WireType::SixtyFourBit,
+        (K::String | K::Message(_), E::General)
+        | (K::Bytes, E::General | E::PlainBytes)
+        | (K::U128 | K::I128, E::General | E::Fixed) => WireType::LengthDelimited,
+        (K::List(item) | K::Set(item), E::Packed(inner)) => {
+            value_wire_type(item, inner)?;
+            WireType::LengthDelimited
+        }
+        (K::Map(key, value), E::General | E::Map(..)) => {
+            let (key_encoding, value_encoding) = map_encodings(encoding);
+            value_wire_type(key, key_encoding)?;
+            value_wire_type(value, value_encoding)?;
+            WireType::LengthDelimited
+        }
+        _ => return None,
+    })
+}
+
+/// Returns the encodings of the keys and values of a map with the given encoding.
+fn map_encodings(encoding: &EncodingDescriptor) -> (&EncodingDescriptor, &EncodingDescriptor) {
+    match encoding {
+        EncodingDescriptor::Map(key, value) => (key, value),
+        _ => (&EncodingDescriptor::General, &EncodingDescriptor::General),
+    }
+}
+
+/// Returns a new value of the given kind, which is empty if the kind has an empty value.
+pub(crate) fn new_value(kind: &ValueKind) -> Value {
+    match kind {
+        ValueKind::Bool => Value::Bool(false),
+        ValueKind::U8 => Value::U8(0),
+        ValueKind::U16 => Value::U16(0),
+        ValueKind::U32 => Value::U32(0),
+        ValueKind::U64 => Value::U64(0),
+        ValueKind::U128 => Value::U128(0),
+        ValueKind::I8 => Value::I8(0),
+        ValueKind::I16 => Value::I16(0),
+        ValueKind::I32 => Value::I32(0),
+        ValueKind::I64 => Value::I64(0),
+        ValueKind::I128 => Value::I128(0),
+        ValueKind::F32 => Value::F32(0.0),
+        ValueKind::F64 => Value::F64(0.0),
+        ValueKind::String => Value::String(String::new()),
+        ValueKind::Bytes => Value::Bytes(Vec::new()),
+        // Like derived enumerations, the zero variant is empty; enumerations without one start as
+        // their first variant.
+        ValueKind::Enumeration(enumeration) => Value::Enumeration(match enumeration.name_of(0) {
+            Some(_) => 0,
+            None => enumeration
+                .variants
+                .first()
+                .map_or(0, |variant| variant.number),
+        }),
+        ValueKind::Message(message) => Value::Message(DynamicMessage::new(message.get())),
+        ValueKind::Optional(inner) => new_value(inner),
+        ValueKind::List(_) => Value::List(Vec::new()),
+        ValueKind::Set(_) => Value::Set(Vec::new()),
+        ValueKind::Map(..) => Value::Map(Vec::new()),
+        ValueKind::Unknown => unreachable!("values of unknown kinds are never created"),
+    }
+}
+
+/// Returns true if the value is the empty value of its kind, which plain fields do not encode.
+fn is_empty_value(kind: &ValueKind, value: &Value) -> bool {
+    match value {
+        Value::Bool(value) => !value,
+        Value::U8(value) => *value == 0,
+        Value::U16(value) => *value == 0,
+        Value::U32(value) => *value == 0,
+        Value::U64(value) => *value == 0,
+        Value::U128(value) => *value == 0,
+        Value::I8(value) => *value == 0,
+        Value::I16(value) => *value == 0,
+        Value::I32(value) => *value == 0,
+        Value::I64(value) => *value == 0,
+        Value::I128(value) => *value == 0,
+        // Negative zero is not empty.
+        Value::F32(value) => value.to_bits() == 0,
+        Value::F64(value) => value.to_bits() == 0,
+        Value::String(value) => value.is_empty(),
+        Value::Bytes(value) => value.is_empty(),
+        Value::Enumeration(number) => {
+            *number == 0
+                && matches!(kind, ValueKind::Enumeration(enumeration)
+                    if enumeration.name_of(0).is_some())
+        }
+        Value::Message(message) => message.is_empty(),
+        Value::List(items) | Value::Set(items) => items.is_empty(),
+        Value::Map(entries) => entries.is_empty(),
+    }
+}
+
+/// Compares two values of the same kind, in the order that sets and the keys of maps are sorted.
+/// Enumerations are ordered by their numbers, and messages by their fields in declaration order.
+fn compare(a: &Value, b: &Value) -> Ordering {
+    match (a, b) {
+        (Value::Bool(a), Value::Bool(b)) => a.cmp(b),
+        (Value::U8(a), Value::U8(b)) => a.cmp(b),
+        (Value::U16(a), Value::U16(b)) => a.cmp(b),
+        (Value::U32(a), Value::U32(b)) => a.cmp(b),
+        (Value::U64(a), Value::U64(b)) => a.cmp(b),
+        (Value::U128(a), Value::U128(b)) => a.cmp(b),
+        (Value::I8(a), Value::I8(b)) => a.cmp(b),
+        (Value::I16(a), Value::I16(b)) => a.cmp(b),
+        (Value::I32(a), Value::I32(b)) => a.cmp(b),
+        (Value::I64(a), Value::I64(b)) => a.cmp(b),
+        (Value::I128(a), Value::I128(b)) => a.cmp(b),
+        (Value::F32(a), Value::F32(b)) => a.total_cmp(b),
+        (Value::F64(a), Value::F64(b)) => a.total_cmp(b),
+        (Value::String(a), Value::String(b)) => a.cmp(b),
+        (Value::Bytes(a), Value::Bytes(b)) => a.cmp(b),
+        (Value::Enumeration(a), Value::Enumeration(b)) => a.cmp(b),
+        (Value::Message(a), Value::Message(b)) => a.compare(b),
+        (Value::List(a), Value::List(b)) | (Value::Set(a), Value::Set(b)) => a
+            .iter()
+            .zip(b)
+            .map(|(a, b)| compare(a, b))
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or_else(|| a.len().cmp(&b.len())),
+        (Value::Map(a), Value::Map(b)) => a
+            .iter()
+            .zip(b)
+            .map(|((a_key, a_value), (b_key, b_value))| {
+                compare(a_key, b_key).then_with(|| compare(a_value, b_value))
+            })
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or_else(|| a.len().cmp(&b.len())),
+        _ => unreachable!("compared values of different kinds"),
+    }
+}
+
+/// Returns true if the value is of the given kind, sorting the items of its sets and the entries of
+/// its maps and dropping any duplicates.
+fn check_value(kind: &ValueKind, value: &mut Value) -> bool {
+    match (kind, value) {
+        (ValueKind::Bool, Value::Bool(_))
+        | (ValueKind::U8, Value::U8(_))
+        | (ValueKind::U16, Value::U16(_))
+        | (ValueKind::U32, Value::U32(_))
+        | (ValueKind::U64, Value::U64(_))
+        | (ValueKind::U128, Value::U128(_))
+        | (ValueKind::I8, Value::I8(_))
+        | (ValueKind::I16, Value::I16(_))
+        | (ValueKind::I32, Value::I32(_))
+        | (ValueKind::I64, Value::I64(_))
+        | (ValueKind::I128, Value::I128(_))
+        | (ValueKind::F32, Value::F32(_))
+        | (ValueKind::F64, Value::F64(_))
+        | (ValueKind::String, Value::String(_))
+        | (ValueKind::Bytes, Value::Bytes(_)) => true,
+        (ValueKind::Enumeration(enumeration), Value::Enumeration(number)) => {
+            enumeration.name_of(*number).is_some()
+        }
+        (ValueKind::Message(message), Value::Message(value)) => {
+            core::ptr::eq(value.descriptor, message.get())
+        }
+        (ValueKind::List(item), Value::List(items)) => {
+            items.iter_mut().all(|value| check_value(item, value))
+        }
+        (ValueKind::Set(item), Value::Set(items)) => {
+            if !items.iter_mut().all(|value| check_value(item, value)) {
+                return false;
+            }
+            items.sort_by(compare);
+            items.dedup_by(|a, b| compare(a, b).is_eq());
+            true
+        }
+        (ValueKind::Map(key, value), Value::Map(entries)) => {
+            if !entries
+                .iter_mut()
+                .all(|(k, v)| check_value(key, k) && check_value(value, v))
+            {
+                return false;
+            }
+            entries.sort_by(|(a, _), (b, _)| compare(a, b));
+            entries.dedup_by(|(a, _), (b, _)| compare(a, b).is_eq());
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Returns false if the kind of value contains floating point values, or values of unknown kinds.
+fn is_distinguishable(kind: &ValueKind) -> bool {
+    match kind {
+        ValueKind::F32 | ValueKind::F64 | ValueKind::Unknown => false,
+        ValueKind::Optional(inner) | ValueKind::List(inner) | ValueKind::Set(inner) => {
+            is_distinguishable(inner)
+        }
+        ValueKind::Map(key, value) => is_distinguishable(key) && is_distinguishable(value),
+        _ => true,
+    }
+}
+
+/// Dispatches a scalar value to the encoder implementation of its corresponding Rust type. `$body`
+/// is evaluated with the value bound to `$v` and that type's encoding bound to the type `$E`, except
+/// for floating point values, which evaluate `$float_body` instead.
+macro_rules! match_scalar {
+    (
+        match ($kind:expr, $encoding:expr, $value:expr) {
+            ($v:ident, $E:ident) => $body:expr,
+            floats ($fv:ident, $FE:ident) => $float_body:expr,
+            _ => $otherwise:expr $(,)?
+        }
+    ) => {{
+        use EncodingDescriptor as E;
+        use ValueKind as K;
+
+        match ($kind, $encoding, $value) {
+            (K::Bool, E::General | E::Varint, Value::Bool($v)) => {
+                type $E = Varint;
+                $body
+            }
+            (K::U8, E::Varint, Value::U8($v)) => {
+                type $E = Varint;
+                $body
+            }
+            (K::U16, E::General | E::Varint, Value::U16($v)) => {
+                type $E = Varint;
+                $body
+            }
+            (K::U32, E::General | E::Varint, Value::U32($v)) => {
+                type $E = Varint;
+                $body
+            }
+            (K::U64, E::General | E::Varint, Value::U64($v)) => {
+                type $E = Varint;
+                $body
+            }
+            (K::I8, E::Varint, Value::I8($v)) => {
+                type $E = Varint;
+                $body
+            }
+            (K::I16, E::General | E::Varint, Value::I16($v)) => {
+                type $E = Varint;
+                $body
+            }
+            (K::I32, E::General | E::Varint, Value::I32($v)) => {
+                type $E = Varint;
+                $body
+            }
+            (K::I64, E::General | E::Varint, Value::I64($v)) => {
+                type $E = Varint;
+                $body
+            }
+            (K::U32, E::Fixed, Value::U32($v)) => {
+                type $E = Fixed;
+                $body
+            }
+            (K::U64, E::Fixed, Value::U64($v)) => {
+                type $E = Fixed;
+                $body
+            }
+            (K::I32, E::Fixed, Value::I32($v)) => {
+                type $E = Fixed;
+                $body
+            }
+            (K::I64, E::Fixed, Value::I64($v)) => {
+                type $E = Fixed;
+                $body
+            }
+            (K::U128, E::General, Value::U128($v)) => {
+                type $E = General;
+                $body
+            }
+            (K::I128, E::General, Value::I128($v)) => {
+                type $E = General;
+                $body
+            }
+            (K::U128, E::Fixed, Value::U128($v)) => {
+                type $E = Fixed;
+                $body
+            }
+            (K::I128, E::Fixed, Value::I128($v)) => {
+                type $E = Fixed;
+                $body
+            }
+            (K::F32, E::General | E::Fixed, Value::F32($fv)) => {
+                type $FE = Fixed;
+                $float_body
+            }
+            (K::F64, E::General | E::Fixed, Value::F64($fv)) => {
+                type $FE = Fixed;
+                $float_body
+            }
+            (K::String, E::General, Value::String($v)) => {
+                type $E = General;
+                $body
+            }
+            (K::Bytes, E::General | E::PlainBytes, Value::Bytes($v)) => {
+                type $E = PlainBytes;
+                $body
+            }
+            _ => $otherwise,
+        }
+    }};
+}
+
+fn encode_value<B: BufMut + ?Sized>(
+    kind: &ValueKind,
+    encoding: &EncodingDescriptor,
+    value: &Value,
+    buf: &mut B,
+) {
+    match (kind, value) {
+        (ValueKind::Enumeration(_), Value::Enumeration(number)) => {
+            encode_varint(*number as u64, buf);
+        }
+        (ValueKind::Message(_), Value::Message(message)) => {
+            encode_varint(message.raw_encoded_len() as u64, buf);
+            message.raw_encode(buf);
+        }
+        (ValueKind::List(item) | ValueKind::Set(item), Value::List(items) | Value::Set(items)) => {
+            let EncodingDescriptor::Packed(item_encoding) = encoding else {
+                unreachable!("collection values are packed")
+            };
+            let len = items
+                .iter()
+                .map(|value| value_encoded_len(item, item_encoding, value))
+                .sum::<usize>();
+            encode_varint(len as u64, buf);
+            for value in items {
+                encode_value(item, item_encoding, value, buf);
+            }
+        }
+        (ValueKind::Map(key_kind, value_kind), Value::Map(entries)) => {
+            let (key_encoding, value_encoding) = map_encodings(encoding);
+            encode_varint(map_encoded_len(kind, encoding, entries) as u64, buf);
+            for (key, value) in entries {
+                encode_value(key_kind, key_encoding, key, buf);
+                encode_value(value_kind, value_encoding, value, buf);
+            }
+        }
+        (kind, value) => match_scalar!(match (kind, encoding, value) {
+            (v, E) => ValueEncoder::<E>::encode_value(v, buf),
+            floats(v, E) => ValueEncoder::<E>::encode_value(v, buf),
+            _ => unreachable!("value does not match its kind"),
+        }),
+    }
+}
+
+fn value_encoded_len(kind: &ValueKind, encoding: &EncodingDescriptor, value: &Value) -> usize {
+    match (kind, value) {
+        (ValueKind::Enumeration(_), Value::Enumeration(number)) => {
+            encoded_len_varint(*number as u64)
+        }
+        (ValueKind::Message(_), Value::Message(message)) => {
+            let inner_len = message.raw_encoded_len();
+            encoded_len_varint(inner_len as u64) + inner_len
+        }
+        (ValueKind::List(item) | ValueKind::Set(item), Value::List(items) | Value::Set(items)) => {
+            let EncodingDescriptor::Packed(item_encoding) = encoding else {
+                unreachable!("collection values are packed")
+            };
+            let inner_len = items
+                .iter()
+                .map(|value| value_encoded_len(item, item_encoding, value))
+                .sum::<usize>();
+            encoded_len_varint(inner_len as u64) + inner_len
+        }
+        (ValueKind::Map(..), Value::Map(entries)) => {
+            let inner_len = map_encoded_len(kind, encoding, entries);
+            encoded_len_varint(inner_len as u64) + inner_len
+        }
+        (kind, value) => match_scalar!(match (kind, encoding, value) {
+            (v, E) => ValueEncoder::<E>::value_encoded_len(v),
+            floats(v, E) => ValueEncoder::<E>::value_encoded_len(v),
+            _ => unreachable!("value does not match its kind"),
+        }),
+    }
+}
+
+fn map_encoded_len(
+    kind: &ValueKind,
+    encoding: &EncodingDescriptor,
+    entries: &[(Value, Value)],
+) -> usize {
+    let ValueKind::Map(key_kind, value_kind) = kind else {
+        unreachable!("map values are maps")
+    };
+    let (key_encoding, value_encoding) = map_encodings(encoding);
+    entries
+        .iter()
+        .map(|(key, value)| {
+            value_encoded_len(key_kind, key_encoding, key)
+                + value_encoded_len(value_kind, value_encoding, value)
+        })
+        .sum()
+}
+
+/// Returns the combined size of a key and value of a map, if both of them have a fixed size.
+fn map_entry_fixed_size(kind: &ValueKind, encoding: &EncodingDescriptor) -> Option<usize> {
+    let ValueKind::Map(key_kind, value_kind) = kind else {
+        return None;
+    };
+    let (key_encoding, value_encoding) = map_encodings(encoding);
+    Some(
+        value_wire_type(key_kind, key_encoding)?.fixed_size()?
+            + value_wire_type(value_kind, value_encoding)?.fixed_size()?,
+    )
+}
+
+/// Adds an item to a decoded collection, which rejects duplicate items if it is a set.
+fn insert_item(
+    items: &mut Vec<Value>,
+    unordered: bool,
+    item: Value,
+) -> Result<(), DecodeErrorKind> {
+    if !unordered {
+        items.push(item);
+        return Ok(());
+    }
+    match items.binary_search_by(|probe| compare(probe, &item)) {
+        Ok(_) => Err(UnexpectedlyRepeated),
+        Err(index) => {
+            items.insert(index, item);
+            Ok(())
+        }
+    }
+}
+
+/// Adds an item to a collection decoded in distinguished mode; the items of sets must be in order.
+fn insert_item_distinguished(
+    items: &mut Vec<Value>,
+    unordered: bool,
+    item: Value,
+) -> Result<Canonicity, DecodeErrorKind> {
+    if !unordered {
+        items.push(item);
+        return Ok(Canonicity::Canonical);
+    }
+    match items.last().map(|last| compare(&item, last)) {
+        Some(Less) => {
+            insert_item(items, true, item)?;
+            Ok(Canonicity::NotCanonical)
+        }
+        Some(Equal) => Err(UnexpectedlyRepeated),
+        Some(Greater) | None => {
+            items.push(item);
+            Ok(Canonicity::Canonical)
+        }
+    }
+}
+
+/// Adds an entry to a decoded map, rejecting duplicate keys.
+fn insert_entry(
+    entries: &mut Vec<(Value, Value)>,
+    key: Value,
+    value: Value,
+) -> Result<(), DecodeErrorKind> {
+    match entries.binary_search_by(|(probe, _)| compare(probe, &key)) {
+        Ok(_) => Err(UnexpectedlyRepeated),
+        Err(index) => {
+            entries.insert(index, (key, value));
+            Ok(())
+        }
+    }
+}
+
+/// Adds an entry to a map decoded in distinguished mode, whose keys must be in order.
+fn insert_entry_distinguished(
+    entries: &mut Vec<(Value, Value)>,
+    key: Value,
+    value: Value,
+) -> Result<Canonicity, DecodeErrorKind> {
+    match entries.last().map(|(last, _)| compare(&key, last)) {
+        Some(Less) => {
+            insert_entry(entries, key, value)?;
+            Ok(Canonicity::NotCanonical)
+        }
+        Some(Equal) => Err(UnexpectedlyRepeated),
+        Some(Greater) | None => {
+            entries.push((key, value));
+            Ok(Canonicity::Canonical)
+        }
+    }
+}
+
+/// Returns the items of a collection value and whether it is unordered.
+fn collection_items(value: &mut Value) -> (&mut Vec<Value>, bool) {
+    match value {
+        Value::List(items) => (items, false),
+        Value::Set(items) => (items, true),
+        _ => unreachable!("collection fields hold collections"),
+    }
+}
+
+/// Decodes a single value into `value`, which must already be a value of the given kind.
+fn decode_value<B: Buf + ?Sized>(
+    kind: &ValueKind,
+    encoding: &EncodingDescriptor,
+    value: &mut Value,
+    mut buf: Capped<B>,
+    ctx: DecodeContext,
+) -> Result<(), DecodeError> {
+    match (kind, value) {
+        (ValueKind::Enumeration(enumeration), Value::Enumeration(number)) => {
+            let decoded = buf.decode_varint()?;
+            let decoded = u32::try_from(decoded).map_err(|_| DecodeError::new(OutOfDomainValue))?;
+            if enumeration.name_of(decoded).is_none() {
+                return Err(DecodeError::new(OutOfDomainValue));
+            }
+            *number = decoded;
+            Ok(())
+        }
+        (ValueKind::Message(_), Value::Message(message)) => {
+            ctx.limit_reached()?;
+            message.merge(buf.take_length_delimited()?, ctx.enter_recursion())
+        }
+        (
+            ValueKind::List(item) | ValueKind::Set(item),
+            value @ (Value::List(_) | Value::Set(_)),
+        ) => {
+            let EncodingDescriptor::Packed(item_encoding) = encoding else {
+                unreachable!("collection values are packed")
+            };
+            decode_packed_items(item, item_encoding, value, buf, ctx)
+        }
+        (ValueKind::Map(key_kind, value_kind), Value::Map(entries)) => {
+            let (key_encoding, value_encoding) = map_encodings(encoding);
+            let mut capped = buf.take_length_delimited()?;
+            if map_entry_fixed_size(kind, encoding).map_or(false, |fixed_size| {
+                capped.remaining_before_cap() % fixed_size != 0
+            }) {
+                return Err(DecodeError::new(Truncated));
+            }
+            while capped.has_remaining()? {
+                ctx.check_collection_item::<(Value, Value)>(entries.len())?;
+                let mut new_key = new_value(key_kind);
+                let mut new_value = new_value(value_kind);
+                decode_value(key_kind, key_encoding, &mut new_key, capped.lend(), ctx)?;
+                decode_value(
+                    value_kind,
+                    value_encoding,
+                    &mut new_value,
+                    capped.lend(),
+                    ctx,
+                )?;
+                insert_entry(entries, new_key, new_value)?;
+            }
+            Ok(())
+        }
+        (kind, value) => match_scalar!(match (kind, encoding, value) {
+            (v, E) => ValueDecoder::<E>::decode_value(v, buf, ctx),
+            floats(v, E) => ValueDecoder::<E>::decode_value(v, buf, ctx),
+            _ => unreachable!("value does not match its kind"),
+        }),
+    }
+}
+
+/// Decodes a single value into `value` in distinguished mode, which must already be a value of the
+/// given kind.
+fn decode_value_distinguished<B: Buf + ?Sized>(
+    kind: &ValueKind,
+    encoding: &EncodingDescriptor,
+    value: &mut Value,
+    mut buf: Capped<B>,
+    allow_empty: bool,
+    ctx: DecodeContext,
+) -> Result<Canonicity, DecodeError> {
+    match (kind, value) {
+        (ValueKind::Enumeration(enumeration), value @ Value::Enumeration(_)) => {
+            decode_value(kind, encoding, value, buf, ctx)?;
+            Ok(
+                if !allow_empty
+                    && *value == Value::Enumeration(0)
+                    && enumeration.name_of(0).is_some()
+                {
+                    Canonicity::NotCanonical
+                } else {
+                    Canonicity::Canonical
+                },
+            )
+        }
+        (ValueKind::Message(_), Value::Message(message)) => {
+            ctx.limit_reached()?;
+            let buf = buf.take_length_delimited()?;
+            // Empty messages always encode to zero bytes, just like derived message types.
+            if !allow_empty && buf.remaining_before_cap() == 0 {
+                return Ok(Canonicity::NotCanonical);
+            }
+            message.merge_distinguished(buf, ctx.enter_recursion())
+        }
+        (
+            ValueKind::List(item) | ValueKind::Set(item),
+            value @ (Value::List(_) | Value::Set(_)),
+        ) => {
+            let EncodingDescriptor::Packed(item_encoding) = encoding else {
+                unreachable!("collection values are packed")
+            };
+            let (items, unordered) = collection_items(value);
+            let mut capped = buf.take_length_delimited()?;
+            if !allow_empty && capped.remaining_before_cap() == 0 {
+                return Ok(Canonicity::NotCanonical);
+            }
+            if value_wire_type(item, item_encoding)
+                .and_then(WireType::fixed_size)
+                .map_or(false, |fixed_size| {
+                    capped.remaining_before_cap() % fixed_size != 0
+                })
+            {
+                return Err(DecodeError::new(Truncated));
+            }
+            let mut canon = Canonicity::Canonical;
+            while capped.has_remaining()? {
+                ctx.check_collection_item::<Value>(items.len())?;
+                let mut new_item = new_value(item);
+                canon.update(decode_value_distinguished(
+                    item,
+                    item_encoding,
+                    &mut new_item,
+                    capped.lend(),
+                    true,
+                    ctx,
+                )?);
+                canon.update(insert_item_distinguished(items, unordered, new_item)?);
+            }
+            Ok(canon)
+        }
+        (ValueKind::Map(key_kind, value_kind), Value::Map(entries)) => {
+            let (key_encoding, value_encoding) = map_encodings(encoding);
+            let mut capped = buf.take_length_delimited()?;
+            if !allow_empty && capped.remaining_before_cap() == 0 {
+                return Ok(Canonicity::NotCanonical);
+            }
+            if map_entry_fixed_size(kind, encoding).map_or(false, |fixed_size| {
+                capped.remaining_before_cap() % fixed_size != 0
+            }) {
+                return Err(DecodeError::new(Truncated));
+            }
+            let mut canon = Canonicity::Canonical;
+            while capped.has_remaining()? {
+                ctx.check_collection_item::<(Value, Value)>(entries.len())?;
+                let mut new_key = new_value(key_kind);
+                let mut new_value = new_value(value_kind);
+                canon.update(decode_value_distinguished(
+                    key_kind,
+                    key_encoding,
+                    &mut new_key,
+                    capped.lend(),
+                    true,
+                    ctx,
+                )?);
+                canon.update(decode_value_distinguished(
+                    value_kind,
+                    value_encoding,
+                    &mut new_value,
+                    capped.lend(),
+                    true,
+                    ctx,
+                )?);
+                canon.update(insert_entry_distinguished(entries, new_key, new_value)?);
+            }
+            Ok(canon)
+        }
+        (kind, value) => match_scalar!(match (kind, encoding, value) {
+            (v, E) =>
+                DistinguishedValueDecoder::<E>::decode_value_distinguished(v, buf, allow_empty, ctx),
+            // Floating point values are rejected before decoding any fields in distinguished mode.
+            floats(_v, _E) => Err(DecodeError::new(Other)),
+            _ => unreachable!("value does not match its kind"),
+        }),
+    }
+}
+
+/// Decodes the items of an unpacked collection, greedily consuming consecutive fields as long as
+/// they have the same tag.
+fn decode_unpacked<B: Buf + ?Sized>(
+    item: &ValueKind,
+    encoding: &EncodingDescriptor,
+    item_wire_type: WireType,
+    wire_type: WireType,
+    collection: &mut Value,
+    mut buf: Capped<B>,
+    ctx: DecodeContext,
+) -> Result<(), DecodeError> {
+    check_wire_type(item_wire_type, wire_type)?;
+    let (items, unordered) = collection_items(collection);
+    loop {
+        ctx.check_collection_item::<Value>(items.len())?;
+        let mut new_item = new_value(item);
+        decode_value(item, encoding, &mut new_item, buf.lend(), ctx)?;
+        insert_item(items, unordered, new_item)?;
+
+        if let Some(next_wire_type) = peek_repeated_field(&mut buf) {
+            check_wire_type(item_wire_type, next_wire_type)?;
+        } else {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Decodes the items of an unpacked collection in distinguished mode, greedily consuming
+/// consecutive fields as long as they have the same tag.
+fn decode_unpacked_distinguished<B: Buf + ?Sized>(
+    item: &ValueKind,
+    encoding: &EncodingDescriptor,
+    item_wire_type: WireType,
+    wire_type: WireType,
+    collection: &mut Value,
+    mut buf: Capped<B>,
+    ctx: DecodeContext,
+) -> Result<Canonicity, DecodeError> {
+    check_wire_type(item_wire_type, wire_type)?;
+    let (items, unordered) = collection_items(collection);
+    let mut canon = Canonicity::Canonical;
+    loop {
+        ctx.check_collection_item::<Value>(items.len())?;
+        let mut new_item = new_value(item);
+        canon.update(decode_value_distinguished(
+            item,
+            encoding,
+            &mut new_item,
+            buf.lend(),
+            true,
+            ctx,
+        )?);
+        canon.update(insert_item_distinguished(items, unordered, new_item)?);
+
+        if let Some(next_wire_type) = peek_repeated_field(&mut buf) {
+            check_wire_type(item_wire_type, next_wire_type)?;
+        } else {
+            break;
+        }
+    }
+    Ok(canon)
+}
+
+impl DynamicMessage {
+    /// Creates an empty message with the given descriptor.
+    pub fn new(descriptor: &'static MessageDescriptor) -> Self {
+        Self {
+            descriptor,
+            fields: BTreeMap::new(),
+            unknown: OpaqueMessage::new(),
+        }
+    }
+
+    /// Returns the descriptor of the message.
+    pub fn descriptor(&self) -> &'static MessageDescriptor {
+        self.descriptor
+    }
+
+    /// Decodes a message with the given descriptor from a buffer in expedient mode.
+    ///
+    /// The entire buffer will be consumed.
+    pub fn decode<B: Buf>(
+        descriptor: &'static MessageDescriptor,
+        mut buf: B,
+    ) -> Result<Self, DecodeError> {
+        let mut message = Self::new(descriptor);
+        message.merge(Capped::new(&mut buf), DecodeContext::default())?;
+        Ok(message)
+    }
+
+    /// Decodes a message with the given descriptor from a buffer in expedient mode, enforcing the
+    /// limits in the given options.
+    pub fn decode_with_options<B: Buf>(
+        descriptor: &'static MessageDescriptor,
+        mut buf: B,
+        options: &DecodeOptions,
+    ) -> Result<Self, DecodeError> {
+        let buf = Capped::new(&mut buf);
+        let limits = DecodeLimits::new(options);
+        let ctx = DecodeContext::with_limits(&limits);
+        ctx.check_input_len(buf.remaining_before_cap())?;
+        let mut message = Self::new(descriptor);
+        message.merge(buf, ctx)?;
+        Ok(message)
+    }
+
+    /// Decodes a message with the given descriptor from a buffer in distinguished mode.
+    ///
+    /// This fails with `DecodeErrorKind::Other` if any message that is decoded has fields that
+    /// hold floating point values, or fields that `DynamicMessage` can't represent, since derived
+    /// message types with such fields can't be distinguished either.
+    pub fn decode_distinguished<B: Buf>(
+        descriptor: &'static MessageDescriptor,
+        mut buf: B,
+    ) -> Result<(Self, Canonicity), DecodeError> {
+        let mut message = Self::new(descriptor);
+        let canon = message.merge_distinguished(Capped::new(&mut buf), DecodeContext::default())?;
+        Ok((message, canon))
+    }
+
+    /// Decodes a message with the given descriptor from a buffer in distinguished mode, enforcing
+    /// the limits in the given options.
+    pub fn decode_distinguished_with_options<B: Buf>(
+        descriptor: &'static MessageDescriptor,
+        mut buf: B,
+        options: &DecodeOptions,
+    ) -> Result<(Self, Canonicity), DecodeError> {
+        let buf = Capped::new(&mut buf);
+        let limits = DecodeLimits::new(options);
+        let ctx = DecodeContext::with_limits(&limits);
+        ctx.check_input_len(buf.remaining_before_cap())?;
+        let mut message = Self::new(descriptor);
+        let canon = message.merge_distinguished(buf, ctx)?;
+        Ok((message, canon))
+    }
+
+    /// Encodes the message to a buffer.
+    ///
+    /// An error will be returned if the buffer does not have sufficient capacity.
+    pub fn encode<B: BufMut + ?Sized>(&self, buf: &mut B) -> Result<(), EncodeError> {
+        let required = self.encoded_len();
+        let remaining = buf.remaining_mut();
+        if required > remaining {
+            return Err(EncodeError::new(required, remaining));
+        }
+        self.raw_encode(buf);
+        Ok(())
+    }
+
+    /// Encodes the message to a newly allocated buffer.
+    pub fn encode_to_vec(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(self.encoded_len());
+        self.raw_encode(&mut buf);
+        buf
+    }
+
+    /// Returns the encoded length of the message without a length delimiter.
+    pub fn encoded_len(&self) -> usize {
+        self.raw_encoded_len()
+    }
+
+    /// Returns true if the message has no fields present and no unknown fields, in which case it
+    /// encodes to zero bytes.
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty() && self.unknown.is_empty()
+    }
+
+    /// Returns the value of the field with the given name, or `None` if there is no such field or
+    /// the field is absent. Fields that are part of a oneof are named by their variant.
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        let field = self.descriptor.field_by_name(name)?;
+        self.fields.get(&field.tag)
+    }
+
+    /// Returns the value of the field with the given tag, or `None` if there is no such field or
+    /// the field is absent.
+    pub fn get_by_tag(&self, tag: u32) -> Option<&Value> {
+        self.fields.get(&tag)
+    }
+
+    /// Sets the value of the field with the given name. Setting a field in a oneof clears the
+    /// oneof's other fields, and setting a plain field to an empty value makes it absent. The
+    /// items of sets and the entries of maps are sorted, keeping only the first of any duplicates.
+    pub fn set(&mut self, name: &str, value: Value) -> Result<(), FieldError> {
+        let field = self
+            .descriptor
+            .field_by_name(name)
+            .ok_or(FieldError::NoSuchField)?;
+        self.set_field(field, value)
+    }
+
+    /// Sets the value of the field with the given tag, just like `set`.
+    pub fn set_by_tag(&mut self, tag: u32, value: Value) -> Result<(), FieldError> {
+        let field = self
+            .descriptor
+            .field_by_tag(tag)
+            .ok_or(FieldError::NoSuchField)?;
+        self.set_field(field, value)
+    }
+
+    /// Removes the value of the field with the given name, returning it if it was present.
+    pub fn remove(&mut self, name: &str) -> Option<Value> {
+        let field = self.descriptor.field_by_name(name)?;
+        self.fields.remove(&field.tag)
+    }
+
+    /// Removes the value of the field with the given tag, returning it if it was present.
+    pub fn remove_by_tag(&mut self, tag: u32) -> Option<Value> {
+        self.fields.remove(&tag)
+    }
+
+    /// Returns an iterator over the fields that are present, in tag order.
+    pub fn iter(&self) -> impl Iterator<Item = (&'static FieldDescriptor, &Value)> + '_ {
+        let descriptor = self.descriptor;
+        self.fields.iter().map(move |(tag, value)| {
+            let field = descriptor
+                .field_by_tag(*tag)
+                .expect("fields have descriptors");
+            (field, value)
+        })
+    }
+
+    /// Returns the unknown fields of the message.
+    pub fn unknown_fields(&self) -> &OpaqueMessage<'static> {
+        &self.unknown
+    }
+
+    /// Returns the unknown fields of the message for modification. Fields added here are encoded
+    /// among the message's other fields, and must not have the tags of any of its known fields.
+    pub fn unknown_fields_mut(&mut self) -> &mut OpaqueMessage<'static> {
+        &mut self.unknown
+    }
+
+    fn set_field(
+        &mut self,
+        field: &'static FieldDescriptor,
+        mut value: Value,
+    ) -> Result<(), FieldError> {
+        let kind = match Shape::of(self.descriptor, field) {
+            Shape::Unsupported => return Err(FieldError::Unsupported),
+            Shape::Present(kind, ..) => kind,
+            Shape::Plain(..) | Shape::Packed(..) | Shape::Unpacked(..) => field.value,
+        };
+        if !check_value(kind, &mut value) {
+            return Err(FieldError::WrongKind);
+        }
+        if let Some(oneof) = self.descriptor.oneof_for_tag(field.tag) {
+            for variant in oneof.fields {
+                self.fields.remove(&variant.tag);
+            }
+        } else if !matches!(field.value, ValueKind::Optional(_)) && is_empty_value(kind, &value) {
+            self.fields.remove(&field.tag);
+            return Ok(());
+        }
+        self.fields.insert(field.tag, value);
+        Ok(())
+    }
+
+    /// Removes plain fields that decoded to empty values, which are absent just like when they are
+    /// not encoded.
+    fn remove_empty_fields(&mut self) {
+        let descriptor = self.descriptor;
+        self.fields.retain(|tag, value| {
+            let field = descriptor
+                .field_by_tag(*tag)
+                .expect("fields have descriptors");
+            match Shape::of(descriptor, field) {
+                Shape::Plain(..) | Shape::Packed(..) | Shape::Unpacked(..) => {
+                    !is_empty_value(field.value, value)
+                }
+                _ => true,
+            }
+        });
+    }
+
+    /// Compares two messages with the same descriptor by their fields in declaration order, then
+    /// by their oneofs.
+    fn compare(&self, other: &Self) -> Ordering {
+        for field in self.descriptor.fields {
+            let ordering = match (self.fields.get(&field.tag), other.fields.get(&field.tag)) {
+                (Some(a), Some(b)) => compare(a, b),
+                (None, None) => Equal,
+                (a, b) if matches!(field.value, ValueKind::Optional(_)) => {
+                    a.is_some().cmp(&b.is_some())
+                }
+                (a, b) => {
+                    let empty = new_value(field.value);
+                    compare(a.unwrap_or(&empty), b.unwrap_or(&empty))
+                }
+            };
+            if ordering.is_ne() {
+                return ordering;
+            }
+        }
+        for oneof in self.descriptor.oneofs {
+            let ordering = match (self.oneof_variant(oneof), other.oneof_variant(oneof)) {
+                (Some((a_index, a)), Some((b_index, b))) => {
+                    a_index.cmp(&b_index).then_with(|| compare(a, b))
+                }
+                (a, b) => a.is_some().cmp(&b.is_some()),
+            };
+            if ordering.is_ne() {
+                return ordering;
+            }
+        }
+        Equal
+    }
+
+    /// Returns the index in the oneof of its field that is present, with its value.
+    fn oneof_variant(&self, oneof: &OneofDescriptor) -> Option<(usize, &Value)> {
+        oneof
+            .fields
+            .iter()
+            .enumerate()
+            .find_map(|(index, field)| Some((index, self.fields.get(&field.tag)?)))
+    }
+
+    /// Merges fields from the buffer, to its cap, into the message.
+    fn merge<B: Buf + ?Sized>(
+        &mut self,
+        mut buf: Capped<B>,
+        ctx: DecodeContext,
+    ) -> Result<(), DecodeError> {
+        let tr = &mut TagReader::new();
+        let mut last_tag = None::<u32>;
+        while buf.has_remaining()? {
+            let (tag, wire_type) = tr.decode_key(buf.lend())?;
+            let duplicated = last_tag == Some(tag);
+            last_tag = Some(tag);
+            let Some(field) = self.descriptor.field_by_tag(tag) else {
+                if self.descriptor.has_extensions {
+                    self.unknown
+                        .raw_decode_field(tag, wire_type, duplicated, buf.lend(), ctx)?;
+                } else {
+                    skip_field(wire_type, buf.lend())?;
+                }
+                continue;
+            };
+            self.decode_field(field, wire_type, duplicated, buf.lend(), ctx)
+                .map_err(|mut error| {
+                    error.push(self.descriptor.name, self.field_path_name(field));
+                    error
+                })?;
+        }
+        self.remove_empty_fields();
+        Ok(())
+    }
+
+    /// Merges fields from the buffer, to its cap, into the message in distinguished mode.
+    fn merge_distinguished<B: Buf + ?Sized>(
+        &mut self,
+        mut buf: Capped<B>,
+        ctx: DecodeContext,
+    ) -> Result<Canonicity, DecodeError> {
+        self.check_distinguished()?;
+        let tr = &mut TagReader::new();
+        let mut last_tag = None::<u32>;
+        let mut canon = Canonicity::Canonical;
+        while buf.has_remaining()? {
+            let (tag, wire_type) = tr.decode_key(buf.lend())?;
+            let duplicated = last_tag == Some(tag);
+            last_tag = Some(tag);
+            let Some(field) = self.descriptor.field_by_tag(tag) else {
+                if self.descriptor.has_extensions {
+                    canon.update(self.unknown.raw_decode_field_distinguished(
+                        tag,
+                        wire_type,
+                        duplicated,
+                        buf.lend(),
+                        ctx,
+                    )?);
+                } else {
+                    canon.update(Canonicity::HasExtensions);
+                    skip_field(wire_type, buf.lend())?;
+                }
+                continue;
+            };
+            canon.update(
+                self.decode_field_distinguished(field, wire_type, duplicated, buf.lend(), ctx)
+                    .map_err(|mut error| {
+                        error.push(self.descriptor.name, self.field_path_name(field));
+                        error
+                    })?,
+            );
+        }
+        self.remove_empty_fields();
+        Ok(canon)
+    }
+
+    /// Returns an error if the message has fields that can't be decoded in distinguished mode.
+    fn check_distinguished(&self) -> Result<(), DecodeError> {
+        for field in self.descriptor.all_fields() {
+            if matches!(Shape::of(self.descriptor, field), Shape::Unsupported)
+                || !is_distinguishable(field.value)
+            {
+                return Err(DecodeError::new(Other));
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the name that errors in the field are reported with: the name of its oneof if it is
+    /// part of one, just like in derived message types.
+    fn field_path_name(&self, field: &'static FieldDescriptor) -> &'static str {
+        self.descriptor
+            .oneof_for_tag(field.tag)
+            .map_or(field.name, |oneof| oneof.name)
+    }
+
+    /// Returns an error if the field is in a oneof which already has a different field present.
+    fn check_oneof(&self, field: &FieldDescriptor) -> Result<(), DecodeError> {
+        let Some(oneof) = self.descriptor.oneof_for_tag(field.tag) else {
+            return Ok(());
+        };
+        if oneof
+            .fields
+            .iter()
+            .any(|other| other.tag != field.tag && self.fields.contains_key(&other.tag))
+        {
+            return Err(DecodeError::new(ConflictingFields));
+        }
+        Ok(())
+    }
+
+    fn decode_field<B: Buf + ?Sized>(
+        &mut self,
+        field: &'static FieldDescriptor,
+        wire_type: WireType,
+        duplicated: bool,
+        buf: Capped<B>,
+        ctx: DecodeContext,
+    ) -> Result<(), DecodeError> {
+        let shape = Shape::of(self.descriptor, field);
+        // Fields in oneofs may be decoded repeatedly, as long as they don't conflict. Unsupported
+        // fields are kept one at a time, so only unpacked collections may repeat.
+        let in_oneof = self.descriptor.oneof_for_tag(field.tag).is_some();
+        let repeats = matches!(shape, Shape::Unsupported)
+            && matches!(field.value, ValueKind::List(_) | ValueKind::Set(_))
+            && !matches!(field.encoding, EncodingDescriptor::Packed(_));
+        if duplicated && !in_oneof && !repeats {
+            return Err(DecodeError::new(UnexpectedlyRepeated));
+        }
+        self.check_oneof(field)?;
+        let tag = field.tag;
+        match shape {
+            Shape::Plain(kind, encoding, expected) | Shape::Present(kind, encoding, expected) => {
+                check_wire_type(expected, wire_type)?;
+                let value = self.fields.entry(tag).or_insert_with(|| new_value(kind));
+                decode_value(kind, encoding, value, buf, ctx)
+            }
+            Shape::Packed(kind, encoding) => {
+                let value = self.fields.entry(tag).or_insert_with(|| new_value(kind));
+                if wire_type == WireType::LengthDelimited {
+                    decode_value(kind, encoding, value, buf, ctx)
+                } else {
+                    // Packed collections also decode from the unpacked representation.
+                    let (
+                        ValueKind::List(item) | ValueKind::Set(item),
+                        EncodingDescriptor::Packed(item_encoding),
+                    ) = (kind, encoding)
+                    else {
+                        unreachable!("packed fields are collections")
+                    };
+                    let item_wire_type = value_wire_type(item, item_encoding).unwrap();
+                    decode_unpacked(
+                        item,
+                        item_encoding,
+                        item_wire_type,
+                        wire_type,
+                        value,
+                        buf,
+                        ctx,
+                    )
+                }
+            }
+            Shape::Unpacked(item, encoding, item_wire_type) => {
+                let value = self
+                    .fields
+                    .entry(tag)
+                    .or_insert_with(|| new_value(field.value));
+                if wire_type == WireType::LengthDelimited
+                    && item_wire_type != WireType::LengthDelimited
+                {
+                    // Unpacked collections also decode from the packed representation.
+                    decode_packed_items(item, encoding, value, buf, ctx)
+                } else {
+                    decode_unpacked(item, encoding, item_wire_type, wire_type, value, buf, ctx)
+                }
+            }
+            Shape::Unsupported => self
+                .unknown
+                .raw_decode_field(tag, wire_type, duplicated, buf, ctx),
+        }
+    }
+
+    fn decode_field_distinguished<B: Buf + ?Sized>(
+        &mut self,
+        field: &'static FieldDescriptor,
+        wire_type: WireType,
+        duplicated: bool,
+        buf: Capped<B>,
+        ctx: DecodeContext,
+    ) -> Result<Canonicity, DecodeError> {
+        let shape = Shape::of(self.descriptor, field);
+        let in_oneof = self.descriptor.oneof_for_tag(field.tag).is_some();
+        if duplicated && !in_oneof {
+            return Err(DecodeError::new(UnexpectedlyRepeated));
+        }
+        self.check_oneof(field)?;
+        let tag = field.tag;
+        match shape {
+            Shape::Plain(kind, encoding, expected) => {
+                check_wire_type(expected, wire_type)?;
+                let value = self.fields.entry(tag).or_insert_with(|| new_value(kind));
+                // Plain fields are not encoded when they are empty.
+                decode_value_distinguished(kind, encoding, value, buf, false, ctx)
+            }
+            Shape::Present(kind, encoding, expected) => {
+                check_wire_type(expected, wire_type)?;
+                let value = self.fields.entry(tag).or_insert_with(|| new_value(kind));
+                decode_value_distinguished(kind, encoding, value, buf, true, ctx)
+            }
+            Shape::Packed(kind, encoding) => {
+                let value = self.fields.entry(tag).or_insert_with(|| new_value(kind));
+                if wire_type == WireType::LengthDelimited {
+                    decode_value_distinguished(kind, encoding, value, buf, false, ctx)
+                } else {
+                    let (
+                        ValueKind::List(item) | ValueKind::Set(item),
+                        EncodingDescriptor::Packed(item_encoding),
+                    ) = (kind, encoding)
+                    else {
+                        unreachable!("packed fields are collections")
+                    };
+                    let item_wire_type = value_wire_type(item, item_encoding).unwrap();
+                    decode_unpacked(
+                        item,
+                        item_encoding,
+                        item_wire_type,
+                        wire_type,
+                        value,
+                        buf,
+                        ctx,
+                    )?;
+                    Ok(Canonicity::NotCanonical)
+                }
+            }
+            Shape::Unpacked(item, encoding, item_wire_type) => {
+                let value = self
+                    .fields
+                    .entry(tag)
+                    .or_insert_with(|| new_value(field.value));
+                if wire_type == WireType::LengthDelimited
+                    && item_wire_type != WireType::LengthDelimited
+                {
+                    // The data is already known to be non-canonical, so it is decoded expediently.
+                    decode_packed_items(item, encoding, value, buf, ctx)?;
+                    Ok(Canonicity::NotCanonical)
+                } else {
+                    decode_unpacked_distinguished(
+                        item,
+                        encoding,
+                        item_wire_type,
+                        wire_type,
+                        value,
+                        buf,
+                        ctx,
+                    )
+                }
+            }
+            Shape::Unsupported => Err(DecodeError::new(Other)),
+        }
+    }
+
+    fn raw_encode<B: BufMut + ?Sized>(&self, buf: &mut B) {
+        let tw = &mut TagWriter::new();
+        let mut after = Bound::Unbounded;
+        for (field, value) in self.iter() {
+            self.unknown
+                .raw_encode_tag_range((after, Bound::Excluded(field.tag)), buf, tw);
+            after = Bound::Included(field.tag);
+            match Shape::of(self.descriptor, field) {
+                Shape::Plain(kind, encoding, wire_type) => {
+                    if !is_empty_value(kind, value) {
+                        tw.encode_key(field.tag, wire_type, buf);
+                        encode_value(kind, encoding, value, buf);
+                    }
+                }
+                Shape::Present(kind, encoding, wire_type) => {
+                    tw.encode_key(field.tag, wire_type, buf);
+                    encode_value(kind, encoding, value, buf);
+                }
+                Shape::Packed(kind, encoding) => {
+                    if !is_empty_value(kind, value) {
+                        tw.encode_key(field.tag, WireType::LengthDelimited, buf);
+                        encode_value(kind, encoding, value, buf);
+                    }
+                }
+                Shape::Unpacked(item, encoding, wire_type) => {
+                    let (Value::List(items) | Value::Set(items)) = value else {
+                        unreachable!("collection fields hold collections")
+                    };
+                    for value in items {
+                        tw.encode_key(field.tag, wire_type, buf);
+                        encode_value(item, encoding, value, buf);
+                    }
+                }
+                Shape::Unsupported => unreachable!("unsupported fields are kept as unknown fields"),
+            }
+        }
+        self.unknown
+            .raw_encode_tag_range((after, Bound::Unbounded), buf, tw);
+    }
+
+    fn raw_encoded_len(&self) -> usize {
+        let tm = &mut TagMeasurer::new();
+        let mut after = Bound::Unbounded;
+        let mut len = 0;
+        for (field, value) in self.iter() {
+            len += self
+                .unknown
+                .raw_tag_range_encoded_len((after, Bound::Excluded(field.tag)), tm);
+            after = Bound::Included(field.tag);
+            len += match Shape::of(self.descriptor, field) {
+                Shape::Plain(kind, encoding, _) | Shape::Packed(kind, encoding) => {
+                    if !is_empty_value(kind, value) {
+                        tm.key_len(field.tag) + value_encoded_len(kind, encoding, value)
+                    } else {
+                        0
+                    }
+                }
+                Shape::Present(kind, encoding, _) => {
+                    tm.key_len(field.tag) + value_encoded_len(kind, encoding, value)
+                }
+                Shape::Unpacked(item, encoding, _) => {
+                    let (Value::List(items) | Value::Set(items)) = value else {
+                        unreachable!("collection fields hold collections")
+                    };
+                    if !items.is_empty() {
+                        // Each field after the first needs only 1 byte for its key.
+                        tm.key_len(field.tag)
+                            + items
+                                .iter()
+                                .map(|value| value_encoded_len(item, encoding, value))
+                                .sum::<usize>()
+                            + items.len()
+                            - 1
+                    } else {
+                        0
+                    }
+                }
+                Shape::Unsupported => unreachable!("unsupported fields are kept as unknown fields"),
+            };
+        }
+        len + self
+            .unknown
+            .raw_tag_range_encoded_len((after, Bound::Unbounded), tm)
+    }
+}
+
+/// Decodes the packed representation of a collection, given the kind and encoding of its items.
+fn decode_packed_items<B: Buf + ?Sized>(
+    item: &ValueKind,
+    encoding: &EncodingDescriptor,
+    collection: &mut Value,
+    mut buf: Capped<B>,
+    ctx: DecodeContext,
+) -> Result<(), DecodeError> {
+    let (items, unordered) = collection_items(collection);
+    let mut capped = buf.take_length_delimited()?;
+    if value_wire_type(item, encoding)
+        .and_then(WireType::fixed_size)
+        .map_or(false, |fixed_size| {
+            capped.remaining_before_cap() % fixed_size != 0
+        })
+    {
+        return Err(DecodeError::new(Truncated));
+    }
+    while capped.has_remaining()? {
+        ctx.check_collection_item::<Value>(items.len())?;
+        let mut new_item = new_value(item);
+        decode_value(item, encoding, &mut new_item, capped.lend(), ctx)?;
+        insert_item(items, unordered, new_item)?;
+    }
+    Ok(())
+}
+
+impl PartialEq for DynamicMessage {
+    /// Messages are equal when they have the same descriptor and equal fields.
+    fn eq(&self, other: &Self) -> bool {
+        self.descriptor.name == other.descriptor.name
+            && self.fields == other.fields
+            && self.unknown == other.unknown
+    }
+}
+
+impl Debug for DynamicMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut debug = f.debug_struct(self.descriptor.name);
+        for (field, value) in self.iter() {
+            debug.field(field.name, value);
+        }
+        if !self.unknown.is_empty() {
+            debug.field("unknown_fields", &self.unknown);
+        }
+        debug.finish()
+    }
+}
@@ -2,16 +2,23 @@ use alloc::vec::Vec;
 
 use bytes::{Buf, BufMut};
 
+use crate::descriptor::{DescribedEncoding, EncodingDescriptor, ValueKind};
 use crate::encoding::EmptyState;
 use crate::encoding::{
-    delegate_encoding, encoder_where_value_encoder, Canonicity, Capped, DecodeContext,
-    DistinguishedValueEncoder, Encoder, TagMeasurer, TagWriter, ValueEncoder, WireType, Wiretyped,
+    borrow_decoding_via_owned, delegate_encoding, empty_state_via_default, encode_varint,
+    encoder_where_value_encoder, prepend_varint, Canonicity, Capped, DecodeContext,
+    DistinguishedValueDecoder, DistinguishedValueEncoder, Encoder, ReverseBuffer, TagMeasurer,
+    TagWriter, ValueDecoder, ValueEncoder, WireType, Wiretyped,
 };
-use crate::DecodeError;
-use crate::DecodeErrorKind::Truncated;
+use crate::DecodeErrorKind::{InvalidValue, Truncated};
+use crate::{CanonicalNanF32, CanonicalNanF64, DecodeError, TotalF32, TotalF64};
 
 pub struct Fixed;
 
+impl DescribedEncoding for Fixed {
+    const DESCRIPTOR: &'static EncodingDescriptor = &EncodingDescriptor::Fixed;
+}
+
 encoder_where_value_encoder!(Fixed);
 
 delegate_encoding!(delegate from (Fixed) to (crate::encoding::Unpacked<Fixed>) for type (Vec<T>)
@@ -21,6 +28,7 @@ delegate_encoding!(delegate from (Fixed) to (crate::encoding::Unpacked<Fixed>) f
 macro_rules! fixed_width_common {
     (
         $ty:ty,
+        $kind:ident,
         $wire_type:ident,
         $put:ident,
         $get:ident
@@ -30,16 +38,25 @@ macro_rules! fixed_width_common {
         }
 
         impl ValueEncoder<Fixed> for $ty {
+            const VALUE_KIND: &'static ValueKind = &ValueKind::$kind;
+
             #[inline]
             fn encode_value<B: BufMut + ?Sized>(value: &$ty, buf: &mut B) {
                 buf.$put(*value);
             }
 
+            #[inline]
+            fn prepend_value(value: &$ty, buf: &mut ReverseBuffer) {
+                buf.prepend_slice(&value.to_le_bytes());
+            }
+
             #[inline]
             fn value_encoded_len(_value: &$ty) -> usize {
                 WireType::$wire_type.fixed_size().unwrap()
             }
+        }
 
+        impl ValueDecoder<Fixed> for $ty {
             #[inline]
             fn decode_value<B: Buf + ?Sized>(
                 value: &mut $ty,
@@ -60,13 +77,17 @@ macro_rules! fixed_width_int {
     (
         $test_name:ident,
         $ty:ty,
+        $kind:ident,
         $wire_type:ident,
         $put:ident,
         $get:ident
     ) => {
-        fixed_width_common!($ty, $wire_type, $put, $get);
+        fixed_width_common!($ty, $kind, $wire_type, $put, $get);
+        borrow_decoding_via_owned!(Fixed, $ty, including distinguished);
 
-        impl DistinguishedValueEncoder<Fixed> for $ty {
+        impl DistinguishedValueEncoder<Fixed> for $ty {}
+
+        impl DistinguishedValueDecoder<Fixed> for $ty {
             #[inline]
             fn decode_value_distinguished<B: Buf + ?Sized>(
                 value: &mut $ty,
@@ -74,7 +95,7 @@ macro_rules! fixed_width_int {
                 allow_empty: bool,
                 ctx: DecodeContext,
             ) -> Result<Canonicity, DecodeError> {
-                ValueEncoder::<Fixed>::decode_value(value, buf, ctx)?;
+                ValueDecoder::<Fixed>::decode_value(value, buf, ctx)?;
                 Ok(if !allow_empty && value.is_empty() {
                     Canonicity::NotCanonical
                 } else {
@@ -102,11 +123,13 @@ macro_rules! fixed_width_float {
     (
         $test_name:ident,
         $ty:ty,
+        $kind:ident,
         $wire_type:ident,
         $put:ident,
         $get:ident
     ) => {
-        fixed_width_common!($ty, $wire_type, $put, $get);
+        fixed_width_common!($ty, $kind, $wire_type, $put, $get);
+        borrow_decoding_via_owned!(Fixed, $ty);
 
         impl EmptyState for $ty {
             #[inline]
@@ -151,16 +174,25 @@ macro_rules! fixed_width_array {
         }
 
         impl ValueEncoder<Fixed> for [u8; $N] {
+            const VALUE_KIND: &'static ValueKind = &ValueKind::Bytes;
+
             #[inline]
             fn encode_value<B: BufMut + ?Sized>(value: &[u8; $N], mut buf: &mut B) {
                 (&mut buf).put(value.as_slice());
             }
 
+            #[inline]
+            fn prepend_value(value: &[u8; $N], buf: &mut ReverseBuffer) {
+                buf.prepend_slice(value.as_slice());
+            }
+
             #[inline]
             fn value_encoded_len(_value: &[u8; $N]) -> usize {
                 $N
             }
+        }
 
+        impl ValueDecoder<Fixed> for [u8; $N] {
             #[inline]
             fn decode_value<B: Buf + ?Sized>(
                 value: &mut [u8; $N],
@@ -175,7 +207,9 @@ macro_rules! fixed_width_array {
             }
         }
 
-        impl DistinguishedValueEncoder<Fixed> for [u8; $N] {
+        impl DistinguishedValueEncoder<Fixed> for [u8; $N] {}
+
+        impl DistinguishedValueDecoder<Fixed> for [u8; $N] {
             #[inline]
             fn decode_value_distinguished<B: Buf + ?Sized>(
                 value: &mut [u8; $N],
@@ -183,7 +217,7 @@ macro_rules! fixed_width_array {
                 allow_empty: bool,
                 ctx: DecodeContext,
             ) -> Result<Canonicity, DecodeError> {
-                ValueEncoder::<Fixed>::decode_value(value, buf, ctx)?;
+                ValueDecoder::<Fixed>::decode_value(value, buf, ctx)?;
                 Ok(if !allow_empty && value.is_empty() {
                     Canonicity::NotCanonical
                 } else {
@@ -192,6 +226,8 @@ macro_rules! fixed_width_array {
             }
         }
 
+        borrow_decoding_via_owned!(Fixed, [u8; $N], including distinguished);
+
         #[cfg(test)]
         mod $test_name {
             use crate::encoding::Fixed;
@@ -211,11 +247,294 @@ macro_rules! fixed_width_array {
     };
 }
 
-fixed_width_float!(f32, f32, ThirtyTwoBit, put_f32_le, get_f32_le);
-fixed_width_float!(f64, f64, SixtyFourBit, put_f64_le, get_f64_le);
-fixed_width_int!(fixed_u32, u32, ThirtyTwoBit, put_u32_le, get_u32_le);
-fixed_width_int!(fixed_u64, u64, SixtyFourBit, put_u64_le, get_u64_le);
-fixed_width_int!(fixed_i32, i32, ThirtyTwoBit, put_i32_le, get_i32_le);
-fixed_width_int!(fixed_i64, i64, SixtyFourBit, put_i64_le, get_i64_le);
+/// Macro which emits implementations for fixed width encoding of 128 bit integers. There is no 128
+/// bit wire type, so these are length-delimited and always exactly 16 bytes long.
+macro_rules! fixed_width_128 {
+    ($test_name:ident, $ty:ty, $kind:ident) => {
+        empty_state_via_default!($ty);
+
+        impl Wiretyped<Fixed> for $ty {
+            const WIRE_TYPE: WireType = WireType::LengthDelimited;
+        }
+
+        impl ValueEncoder<Fixed> for $ty {
+            const VALUE_KIND: &'static ValueKind = &ValueKind::$kind;
+
+            #[inline]
+            fn encode_value<B: BufMut + ?Sized>(value: &$ty, buf: &mut B) {
+                encode_varint(16, buf);
+                buf.put_slice(&value.to_le_bytes());
+            }
+
+            #[inline]
+            fn prepend_value(value: &$ty, buf: &mut ReverseBuffer) {
+                buf.prepend_slice(&value.to_le_bytes());
+                prepend_varint(16, buf);
+            }
+
+            #[inline]
+            fn value_encoded_len(_value: &$ty) -> usize {
+                17
+            }
+
+            #[inline]
+            fn many_values_encoded_len<I>(values: I) -> usize
+            where
+                I: ExactSizeIterator,
+                I::Item: core::ops::Deref<Target = $ty>,
+            {
+                values.len() * 17
+            }
+        }
+
+        impl ValueDecoder<Fixed> for $ty {
+            #[inline]
+            fn decode_value<B: Buf + ?Sized>(
+                value: &mut $ty,
+                mut buf: Capped<B>,
+                _ctx: DecodeContext,
+            ) -> Result<(), DecodeError> {
+                let mut delimited = buf.take_length_delimited()?;
+                if delimited.remaining_before_cap() != 16 {
+                    return Err(DecodeError::new(InvalidValue));
+                }
+                let mut bytes = [0u8; 16];
+                delimited.copy_to_slice(&mut bytes);
+                *value = <$ty>::from_le_bytes(bytes);
+                Ok(())
+            }
+        }
+
+        impl DistinguishedValueEncoder<Fixed> for $ty {}
+
+        impl DistinguishedValueDecoder<Fixed> for $ty {
+            #[inline]
+            fn decode_value_distinguished<B: Buf + ?Sized>(
+                value: &mut $ty,
+                buf: Capped<B>,
+                allow_empty: bool,
+                ctx: DecodeContext,
+            ) -> Result<Canonicity, DecodeError> {
+                ValueDecoder::<Fixed>::decode_value(value, buf, ctx)?;
+                Ok(if !allow_empty && value.is_empty() {
+                    Canonicity::NotCanonical
+                } else {
+                    Canonicity::Canonical
+                })
+            }
+        }
+
+        borrow_decoding_via_owned!(Fixed, $ty, including distinguished);
+
+        #[cfg(test)]
+        mod $test_name {
+            use crate::encoding::Fixed;
+            crate::encoding::test::check_type_test!(
+                Fixed,
+                expedient,
+                $ty,
+                WireType::LengthDelimited
+            );
+            crate::encoding::test::check_type_test!(
+                Fixed,
+                distinguished,
+                $ty,
+                WireType::LengthDelimited
+            );
+        }
+    };
+}
+
+/// Macro which emits implementations for the floating point wrapper types that support
+/// distinguished decoding, delegating to the wrapped floating point type.
+macro_rules! total_float {
+    (
+        $test_name:ident,
+        $ty:ident,
+        $canonical_ty:ident,
+        $float:ty,
+        $wire_type:ident
+    ) => {
+        impl Wiretyped<Fixed> for $ty {
+            const WIRE_TYPE: WireType = <$float as Wiretyped<Fixed>>::WIRE_TYPE;
+        }
+
+        impl ValueEncoder<Fixed> for $ty {
+            const VALUE_KIND: &'static ValueKind = <$float as ValueEncoder<Fixed>>::VALUE_KIND;
+
+            #[inline]
+            fn encode_value<B: BufMut + ?Sized>(value: &$ty, buf: &mut B) {
+                ValueEncoder::<Fixed>::encode_value(&value.0, buf)
+            }
+
+            #[inline]
+            fn prepend_value(value: &$ty, buf: &mut ReverseBuffer) {
+                ValueEncoder::<Fixed>::prepend_value(&value.0, buf)
+            }
+
+            #[inline]
+            fn value_encoded_len(value: &$ty) -> usize {
+                ValueEncoder::<Fixed>::value_encoded_len(&value.0)
+            }
+        }
+
+        impl ValueDecoder<Fixed> for $ty {
+            #[inline]
+            fn decode_value<B: Buf + ?Sized>(
+                value: &mut $ty,
+                buf: Capped<B>,
+                ctx: DecodeContext,
+            ) -> Result<(), DecodeError> {
+                ValueDecoder::<Fixed>::decode_value(&mut value.0, buf, ctx)
+            }
+        }
+
+        impl DistinguishedValueEncoder<Fixed> for $ty {}
+
+        impl DistinguishedValueDecoder<Fixed> for $ty {
+            #[inline]
+            fn decode_value_distinguished<B: Buf + ?Sized>(
+                value: &mut $ty,
+                buf: Capped<B>,
+                allow_empty: bool,
+                ctx: DecodeContext,
+            ) -> Result<Canonicity, DecodeError> {
+                ValueDecoder::<Fixed>::decode_value(value, buf, ctx)?;
+                Ok(if !allow_empty && value.is_empty() {
+                    Canonicity::NotCanonical
+                } else {
+                    Canonicity::Canonical
+                })
+            }
+        }
+
+        borrow_decoding_via_owned!(Fixed, $ty, including distinguished);
+
+        impl Wiretyped<Fixed> for $canonical_ty {
+            const WIRE_TYPE: WireType = <$float as Wiretyped<Fixed>>::WIRE_TYPE;
+        }
+
+        impl ValueEncoder<Fixed> for $canonical_ty {
+            const VALUE_KIND: &'static ValueKind = <$float as ValueEncoder<Fixed>>::VALUE_KIND;
+
+            #[inline]
+            fn encode_value<B: BufMut + ?Sized>(value: &$canonical_ty, buf: &mut B) {
+                ValueEncoder::<Fixed>::encode_value(&value.get(), buf)
+            }
+
+            #[inline]
+            fn prepend_value(value: &$canonical_ty, buf: &mut ReverseBuffer) {
+                ValueEncoder::<Fixed>::prepend_value(&value.get(), buf)
+            }
+
+            #[inline]
+            fn value_encoded_len(value: &$canonical_ty) -> usize {
+                ValueEncoder::<Fixed>::value_encoded_len(&value.get())
+            }
+        }
+
+        impl ValueDecoder<Fixed> for $canonical_ty {
+            #[inline]
+            fn decode_value<B: Buf + ?Sized>(
+                value: &mut $canonical_ty,
+                buf: Capped<B>,
+                ctx: DecodeContext,
+            ) -> Result<(), DecodeError> {
+                let mut decoded: $float = 0.0;
+                ValueDecoder::<Fixed>::decode_value(&mut decoded, buf, ctx)?;
+                *value = $canonical_ty::new(decoded);
+                Ok(())
+            }
+        }
+
+        impl DistinguishedValueEncoder<Fixed> for $canonical_ty {}
+
+        impl DistinguishedValueDecoder<Fixed> for $canonical_ty {
+            #[inline]
+            fn decode_value_distinguished<B: Buf + ?Sized>(
+                value: &mut $canonical_ty,
+                buf: Capped<B>,
+                allow_empty: bool,
+                ctx: DecodeContext,
+            ) -> Result<Canonicity, DecodeError> {
+                let mut decoded: $float = 0.0;
+                ValueDecoder::<Fixed>::decode_value(&mut decoded, buf, ctx)?;
+                *value = $canonical_ty::new(decoded);
+                Ok(
+                    if !$canonical_ty::is_canonical(decoded) || (!allow_empty && value.is_empty())
+                    {
+                        Canonicity::NotCanonical
+                    } else {
+                        Canonicity::Canonical
+                    },
+                )
+            }
+        }
+
+        borrow_decoding_via_owned!(Fixed, $canonical_ty, including distinguished);
+
+        #[cfg(test)]
+        mod $test_name {
+            use crate::encoding::Fixed;
+            use crate::$ty;
+            crate::encoding::test::check_type_test!(
+                Fixed,
+                expedient,
+                from $float,
+                into $ty,
+                WireType::$wire_type
+            );
+            crate::encoding::test::check_type_test!(
+                Fixed,
+                distinguished,
+                from $float,
+                into $ty,
+                WireType::$wire_type
+            );
+
+            mod canonical_nan {
+                use crate::encoding::Fixed;
+                use crate::$canonical_ty;
+                crate::encoding::test::check_type_test!(
+                    Fixed,
+                    expedient,
+                    from $float,
+                    into $canonical_ty,
+                    WireType::$wire_type
+                );
+                crate::encoding::test::check_type_test!(
+                    Fixed,
+                    distinguished,
+                    from $float,
+                    into $canonical_ty,
+                    WireType::$wire_type
+                );
+            }
+
+            mod delegated_from_general {
+                use crate::encoding::General;
+                use crate::$ty;
+                crate::encoding::test::check_type_test!(
+                    General,
+                    distinguished,
+                    from $float,
+                    into $ty,
+                    WireType::$wire_type
+                );
+            }
+        }
+    };
+}
+
+fixed_width_float!(f32, f32, F32, ThirtyTwoBit, put_f32_le, get_f32_le);
+fixed_width_float!(f64, f64, F64, SixtyFourBit, put_f64_le, get_f64_le);
+fixed_width_int!(fixed_u32, u32, U32, ThirtyTwoBit, put_u32_le, get_u32_le);
+fixed_width_int!(fixed_u64, u64, U64, SixtyFourBit, put_u64_le, get_u64_le);
+fixed_width_int!(fixed_i32, i32, I32, ThirtyTwoBit, put_i32_le, get_i32_le);
+fixed_width_int!(fixed_i64, i64, I64, SixtyFourBit, put_i64_le, get_i64_le);
+fixed_width_128!(fixed_u128, u128, U128);
+fixed_width_128!(fixed_i128, i128, I128);
 fixed_width_array!(u8_4, 4, ThirtyTwoBit);
 fixed_width_array!(u8_8, 8, SixtyFourBit);
+total_float!(total_f32, TotalF32, CanonicalNanF32, f32, ThirtyTwoBit);
+total_float!(total_f64, TotalF64, CanonicalNanF64, f64, SixtyFourBit);
@@ -7,18 +7,32 @@ use core::str;
 
 use bytes::{Buf, BufMut, Bytes};
 
+use crate::descriptor::{DescribedEncoding, EncodingDescriptor, MessageRef, ValueKind};
+use crate::encoding::varint::{i128_to_unsigned, u128_to_signed};
 use crate::encoding::{
-    delegate_encoding, delegate_value_encoding, encode_varint, encoded_len_varint,
-    encoder_where_value_encoder, Canonicity, Capped, DecodeContext, DecodeError,
-    DistinguishedValueEncoder, EmptyState, Encoder, Fixed, Map, PlainBytes, TagMeasurer, TagWriter,
-    Unpacked, ValueEncoder, Varint, WireType, Wiretyped,
+    borrow_decoding_via_owned, delegate_encoding, delegate_value_encoding, encode_varint,
+    encoded_len_varint, encoder_where_value_encoder, prepend_varint, Canonicity, Capped,
+    DecodeContext, DecodeError, DistinguishedValueBorrowDecoder, DistinguishedValueDecoder,
+    DistinguishedValueEncoder, EmptyState, Encoder, Fixed, Map, PlainBytes, ReverseBuffer,
+    TagMeasurer, TagWriter, Unpacked, ValueBorrowDecoder, ValueDecoder, ValueEncoder, Varint,
+    WireType, Wiretyped,
 };
-use crate::message::{merge, merge_distinguished, RawDistinguishedMessage, RawMessage};
-use crate::Blob;
+use crate::message::{
+    merge, merge_borrowed, merge_distinguished, merge_distinguished_borrowed, merge_update,
+    RawDistinguishedMessage, RawDistinguishedMessageBorrowDecoder, RawDistinguishedMessageDecoder,
+    RawMessage, RawMessageBorrowDecoder, RawMessageDecoder,
+};
+use crate::patch::{PatchOp, PatchWriter};
+use crate::types::LazyForm;
 use crate::DecodeErrorKind::InvalidValue;
+use crate::{Blob, CanonicalNanF32, CanonicalNanF64, Lazy, TotalF32, TotalF64};
 
 pub struct General;
 
+impl DescribedEncoding for General {
+    const DESCRIPTOR: &'static EncodingDescriptor = &EncodingDescriptor::General;
+}
+
 encoder_where_value_encoder!(General);
 
 // General implements unpacked encodings by default, but only for select collection types. Other
@@ -85,6 +99,134 @@ delegate_value_encoding!(delegate from (General) to (Varint)
 // General also encodes floating point values.
 delegate_value_encoding!(delegate from (General) to (Fixed) for type (f32));
 delegate_value_encoding!(delegate from (General) to (Fixed) for type (f64));
+delegate_value_encoding!(delegate from (General) to (Fixed)
+    for type (TotalF32) including distinguished);
+delegate_value_encoding!(delegate from (General) to (Fixed)
+    for type (TotalF64) including distinguished);
+delegate_value_encoding!(delegate from (General) to (Fixed)
+    for type (CanonicalNanF32) including distinguished);
+delegate_value_encoding!(delegate from (General) to (Fixed)
+    for type (CanonicalNanF64) including distinguished);
+
+/// Returns the bytes of a 128 bit integer as `General` encodes it: little-endian, with trailing zero
+/// bytes trimmed so that zero has no bytes at all. Returns the bytes and how many of them to keep.
+#[inline]
+pub(crate) fn trimmed_u128(value: u128) -> ([u8; 16], usize) {
+    (
+        value.to_le_bytes(),
+        (128 - value.leading_zeros() as usize + 7) / 8,
+    )
+}
+
+/// Decodes a 128 bit integer encoded as trimmed little-endian bytes, returning it and the number of
+/// bytes it was encoded with.
+#[inline]
+fn decode_trimmed_u128<B: Buf + ?Sized>(mut buf: Capped<B>) -> Result<(u128, usize), DecodeError> {
+    let mut delimited = buf.take_length_delimited()?;
+    let len = delimited.remaining_before_cap();
+    if len > 16 {
+        return Err(DecodeError::new(InvalidValue));
+    }
+    let mut bytes = [0u8; 16];
+    delimited.copy_to_slice(&mut bytes[..len]);
+    Ok((u128::from_le_bytes(bytes), len))
+}
+
+/// Macro which emits implementations for 128 bit integers, which General encodes as
+/// length-delimited little-endian bytes with trailing zeros trimmed. Signed values are zig-zag
+/// encoded first, so that small negative numbers are short too.
+macro_rules! general_128 {
+    ($test_name:ident, $ty:ty, to_u128($to_u128:path), from_u128($from_u128:path)) => {
+        impl Wiretyped<General> for $ty {
+            const WIRE_TYPE: WireType = WireType::LengthDelimited;
+        }
+
+        impl ValueEncoder<General> for $ty {
+            const VALUE_KIND: &'static ValueKind = <$ty as ValueEncoder<Fixed>>::VALUE_KIND;
+
+            #[inline]
+            fn encode_value<B: BufMut + ?Sized>(value: &$ty, buf: &mut B) {
+                let (bytes, len) = trimmed_u128($to_u128(*value));
+                encode_varint(len as u64, buf);
+                buf.put_slice(&bytes[..len]);
+            }
+
+            #[inline]
+            fn prepend_value(value: &$ty, buf: &mut ReverseBuffer) {
+                let (bytes, len) = trimmed_u128($to_u128(*value));
+                buf.prepend_slice(&bytes[..len]);
+                prepend_varint(len as u64, buf);
+            }
+
+            #[inline]
+            fn value_encoded_len(value: &$ty) -> usize {
+                1 + trimmed_u128($to_u128(*value)).1
+            }
+        }
+
+        impl ValueDecoder<General> for $ty {
+            #[inline]
+            fn decode_value<B: Buf + ?Sized>(
+                value: &mut $ty,
+                buf: Capped<B>,
+                _ctx: DecodeContext,
+            ) -> Result<(), DecodeError> {
+                *value = $from_u128(decode_trimmed_u128(buf)?.0);
+                Ok(())
+            }
+        }
+
+        impl DistinguishedValueEncoder<General> for $ty {}
+
+        impl DistinguishedValueDecoder<General> for $ty {
+            #[inline]
+            fn decode_value_distinguished<B: Buf + ?Sized>(
+                value: &mut $ty,
+                buf: Capped<B>,
+                allow_empty: bool,
+                _ctx: DecodeContext,
+            ) -> Result<Canonicity, DecodeError> {
+                let (decoded, len) = decode_trimmed_u128(buf)?;
+                *value = $from_u128(decoded);
+                // Trailing zero bytes are not canonical.
+                Ok(
+                    if trimmed_u128(decoded).1 != len || (!allow_empty && value.is_empty()) {
+                        Canonicity::NotCanonical
+                    } else {
+                        Canonicity::Canonical
+                    },
+                )
+            }
+        }
+
+        borrow_decoding_via_owned!(General, $ty, including distinguished);
+
+        #[cfg(test)]
+        mod $test_name {
+            use super::General;
+            use crate::encoding::test::check_type_test;
+            check_type_test!(General, expedient, $ty, WireType::LengthDelimited);
+            check_type_test!(General, distinguished, $ty, WireType::LengthDelimited);
+        }
+    };
+}
+
+general_128!(
+    general_u128,
+    u128,
+    to_u128(core::convert::identity),
+    from_u128(core::convert::identity)
+);
+general_128!(
+    general_i128,
+    i128,
+    to_u128(i128_to_unsigned),
+    from_u128(u128_to_signed)
+);
+
+// Borrowed byte slices have no ambiguity with repeated fields, so General encodes them as bytes.
+delegate_value_encoding!(delegate from (General) to (PlainBytes)
+    for type (&'b [u8]) including distinguished with generics ('b));
 
 impl EmptyState for String {
     #[inline]
@@ -108,19 +250,28 @@ impl Wiretyped<General> for String {
 }
 
 impl ValueEncoder<General> for String {
+    const VALUE_KIND: &'static ValueKind = &ValueKind::String;
+
     fn encode_value<B: BufMut + ?Sized>(value: &String, buf: &mut B) {
         encode_varint(value.len() as u64, buf);
         buf.put_slice(value.as_bytes());
     }
 
+    fn prepend_value(value: &String, buf: &mut ReverseBuffer) {
+        buf.prepend_slice(value.as_bytes());
+        prepend_varint(value.len() as u64, buf);
+    }
+
     fn value_encoded_len(value: &String) -> usize {
         encoded_len_varint(value.len() as u64) + value.len()
     }
+}
 
+impl ValueDecoder<General> for String {
     fn decode_value<B: Buf + ?Sized>(
         value: &mut String,
         mut buf: Capped<B>,
-        _ctx: DecodeContext,
+        ctx: DecodeContext,
     ) -> Result<(), DecodeError> {
         // ## Unsafety
         //
@@ -144,6 +295,7 @@ impl ValueEncoder<General> for String {
         }
 
         let source = buf.take_length_delimited()?.take_all();
+        ctx.check_bytes_alloc(source.remaining())?;
         // If we must copy, make sure to copy only once.
         value.clear();
         value.reserve(source.remaining());
@@ -162,7 +314,9 @@ impl ValueEncoder<General> for String {
     }
 }
 
-impl DistinguishedValueEncoder<General> for String {
+impl DistinguishedValueEncoder<General> for String {}
+
+impl DistinguishedValueDecoder<General> for String {
     fn decode_value_distinguished<B: Buf + ?Sized>(
         value: &mut String,
         buf: Capped<B>,
@@ -178,6 +332,8 @@ impl DistinguishedValueEncoder<General> for String {
     }
 }
 
+borrow_decoding_via_owned!(General, String, including distinguished);
+
 #[cfg(test)]
 mod string {
     use super::{General, String};
@@ -215,32 +371,43 @@ impl Wiretyped<General> for Cow<'_, str> {
 }
 
 impl ValueEncoder<General> for Cow<'_, str> {
+    const VALUE_KIND: &'static ValueKind = &ValueKind::String;
+
     fn encode_value<B: BufMut + ?Sized>(value: &Cow<str>, buf: &mut B) {
         encode_varint(value.len() as u64, buf);
         buf.put_slice(value.as_bytes());
     }
 
+    fn prepend_value(value: &Cow<str>, buf: &mut ReverseBuffer) {
+        buf.prepend_slice(value.as_bytes());
+        prepend_varint(value.len() as u64, buf);
+    }
+
     fn value_encoded_len(value: &Cow<str>) -> usize {
         encoded_len_varint(value.len() as u64) + value.len()
     }
+}
 
+impl ValueDecoder<General> for Cow<'_, str> {
     fn decode_value<B: Buf + ?Sized>(
         value: &mut Cow<str>,
         buf: Capped<B>,
         ctx: DecodeContext,
     ) -> Result<(), DecodeError> {
-        ValueEncoder::<General>::decode_value(value.to_mut(), buf, ctx)
+        ValueDecoder::<General>::decode_value(value.to_mut(), buf, ctx)
     }
 }
 
-impl DistinguishedValueEncoder<General> for Cow<'_, str> {
+impl DistinguishedValueEncoder<General> for Cow<'_, str> {}
+
+impl DistinguishedValueDecoder<General> for Cow<'_, str> {
     fn decode_value_distinguished<B: Buf + ?Sized>(
         value: &mut Cow<str>,
         buf: Capped<B>,
         allow_empty: bool,
         ctx: DecodeContext,
     ) -> Result<Canonicity, DecodeError> {
-        DistinguishedValueEncoder::<General>::decode_value_distinguished(
+        DistinguishedValueDecoder::<General>::decode_value_distinguished(
             value.to_mut(),
             buf,
             allow_empty,
@@ -249,6 +416,35 @@ impl DistinguishedValueEncoder<General> for Cow<'_, str> {
     }
 }
 
+impl<'a: 'b, 'b> ValueBorrowDecoder<'a, General> for Cow<'b, str> {
+    fn borrow_decode_value(
+        value: &mut Cow<'b, str>,
+        mut buf: Capped<&'a [u8]>,
+        ctx: DecodeContext,
+    ) -> Result<(), DecodeError> {
+        let bytes = buf.take_borrowed_length_delimited()?;
+        ctx.check_bytes_len(bytes.len())?;
+        *value = Cow::Borrowed(str::from_utf8(bytes).map_err(|_| DecodeError::new(InvalidValue))?);
+        Ok(())
+    }
+}
+
+impl<'a: 'b, 'b> DistinguishedValueBorrowDecoder<'a, General> for Cow<'b, str> {
+    fn borrow_decode_value_distinguished(
+        value: &mut Cow<'b, str>,
+        buf: Capped<&'a [u8]>,
+        allow_empty: bool,
+        ctx: DecodeContext,
+    ) -> Result<Canonicity, DecodeError> {
+        ValueBorrowDecoder::<General>::borrow_decode_value(value, buf, ctx)?;
+        Ok(if !allow_empty && value.is_empty() {
+            Canonicity::NotCanonical
+        } else {
+            Canonicity::Canonical
+        })
+    }
+}
+
 #[cfg(test)]
 mod cow_string {
     use super::{Cow, General};
@@ -257,6 +453,78 @@ mod cow_string {
     check_type_test!(General, distinguished, Cow<str>, WireType::LengthDelimited);
 }
 
+impl EmptyState for &str {
+    #[inline]
+    fn empty() -> Self {
+        ""
+    }
+
+    #[inline]
+    fn is_empty(&self) -> bool {
+        str::is_empty(self)
+    }
+
+    #[inline]
+    fn clear(&mut self) {
+        *self = "";
+    }
+}
+
+impl Wiretyped<General> for &str {
+    const WIRE_TYPE: WireType = WireType::LengthDelimited;
+}
+
+/// Borrowed strings can be encoded normally, but can only be decoded via the borrowed decoding
+/// traits; they do not implement `ValueDecoder`.
+impl ValueEncoder<General> for &str {
+    const VALUE_KIND: &'static ValueKind = &ValueKind::String;
+
+    fn encode_value<B: BufMut + ?Sized>(value: &&str, buf: &mut B) {
+        encode_varint(value.len() as u64, buf);
+        buf.put_slice(value.as_bytes());
+    }
+
+    fn prepend_value(value: &&str, buf: &mut ReverseBuffer) {
+        buf.prepend_slice(value.as_bytes());
+        prepend_varint(value.len() as u64, buf);
+    }
+
+    fn value_encoded_len(value: &&str) -> usize {
+        encoded_len_varint(value.len() as u64) + value.len()
+    }
+}
+
+impl DistinguishedValueEncoder<General> for &str {}
+
+impl<'a: 'b, 'b> ValueBorrowDecoder<'a, General> for &'b str {
+    fn borrow_decode_value(
+        value: &mut &'b str,
+        mut buf: Capped<&'a [u8]>,
+        ctx: DecodeContext,
+    ) -> Result<(), DecodeError> {
+        let bytes = buf.take_borrowed_length_delimited()?;
+        ctx.check_bytes_len(bytes.len())?;
+        *value = str::from_utf8(bytes).map_err(|_| DecodeError::new(InvalidValue))?;
+        Ok(())
+    }
+}
+
+impl<'a: 'b, 'b> DistinguishedValueBorrowDecoder<'a, General> for &'b str {
+    fn borrow_decode_value_distinguished(
+        value: &mut &'b str,
+        buf: Capped<&'a [u8]>,
+        allow_empty: bool,
+        ctx: DecodeContext,
+    ) -> Result<Canonicity, DecodeError> {
+        ValueBorrowDecoder::<General>::borrow_decode_value(value, buf, ctx)?;
+        Ok(if !allow_empty && value.is_empty() {
+            Canonicity::NotCanonical
+        } else {
+            Canonicity::Canonical
+        })
+    }
+}
+
 #[cfg(feature = "bytestring")]
 impl EmptyState for bytestring::ByteString {
     #[inline]
@@ -282,22 +550,33 @@ impl Wiretyped<General> for bytestring::ByteString {
 
 #[cfg(feature = "bytestring")]
 impl ValueEncoder<General> for bytestring::ByteString {
+    const VALUE_KIND: &'static ValueKind = &ValueKind::String;
+
     fn encode_value<B: BufMut + ?Sized>(value: &bytestring::ByteString, buf: &mut B) {
         encode_varint(value.len() as u64, buf);
         buf.put_slice(value.as_bytes());
     }
 
+    fn prepend_value(value: &bytestring::ByteString, buf: &mut ReverseBuffer) {
+        buf.prepend_slice(value.as_bytes());
+        prepend_varint(value.len() as u64, buf);
+    }
+
     fn value_encoded_len(value: &bytestring::ByteString) -> usize {
         encoded_len_varint(value.len() as u64) + value.len()
     }
+}
 
+#[cfg(feature = "bytestring")]
+impl ValueDecoder<General> for bytestring::ByteString {
     fn decode_value<B: Buf + ?Sized>(
         value: &mut bytestring::ByteString,
         mut buf: Capped<B>,
-        _ctx: DecodeContext,
+        ctx: DecodeContext,
     ) -> Result<(), DecodeError> {
         let mut string_data = buf.take_length_delimited()?;
         let string_len = string_data.remaining_before_cap();
+        ctx.check_bytes_alloc(string_len)?;
         *value = bytestring::ByteString::try_from(string_data.copy_to_bytes(string_len))
             .map_err(|_| DecodeError::new(InvalidValue))?;
         Ok(())
@@ -305,7 +584,10 @@ impl ValueEncoder<General> for bytestring::ByteString {
 }
 
 #[cfg(feature = "bytestring")]
-impl DistinguishedValueEncoder<General> for bytestring::ByteString {
+impl DistinguishedValueEncoder<General> for bytestring::ByteString {}
+
+#[cfg(feature = "bytestring")]
+impl DistinguishedValueDecoder<General> for bytestring::ByteString {
     fn decode_value_distinguished<B: Buf + ?Sized>(
         value: &mut bytestring::ByteString,
         buf: Capped<B>,
@@ -321,6 +603,9 @@ impl DistinguishedValueEncoder<General> for bytestring::ByteString {
     }
 }
 
+#[cfg(feature = "bytestring")]
+borrow_decoding_via_owned!(General, bytestring::ByteString, including distinguished);
+
 #[cfg(feature = "bytestring")]
 #[cfg(test)]
 mod bytestring_string {
@@ -353,28 +638,40 @@ impl Wiretyped<General> for Bytes {
 }
 
 impl ValueEncoder<General> for Bytes {
+    const VALUE_KIND: &'static ValueKind = &ValueKind::Bytes;
+
     fn encode_value<B: BufMut + ?Sized>(value: &Bytes, mut buf: &mut B) {
         encode_varint(value.len() as u64, buf);
         (&mut buf).put(value.clone()); // `put` needs Self to be sized, so we use the ref type
     }
 
+    fn prepend_value(value: &Bytes, buf: &mut ReverseBuffer) {
+        buf.prepend_slice(value);
+        prepend_varint(value.len() as u64, buf);
+    }
+
     fn value_encoded_len(value: &Bytes) -> usize {
         encoded_len_varint(value.len() as u64) + value.len()
     }
+}
 
+impl ValueDecoder<General> for Bytes {
     fn decode_value<B: Buf + ?Sized>(
         value: &mut Bytes,
         mut buf: Capped<B>,
-        _ctx: DecodeContext,
+        ctx: DecodeContext,
     ) -> Result<(), DecodeError> {
         let mut buf = buf.take_length_delimited()?;
         let len = buf.remaining_before_cap();
+        ctx.check_bytes_alloc(len)?;
         *value = buf.copy_to_bytes(len);
         Ok(())
     }
 }
 
-impl DistinguishedValueEncoder<General> for Bytes {
+impl DistinguishedValueEncoder<General> for Bytes {}
+
+impl DistinguishedValueDecoder<General> for Bytes {
     fn decode_value_distinguished<B: Buf + ?Sized>(
         value: &mut Bytes,
         buf: Capped<B>,
@@ -390,6 +687,8 @@ impl DistinguishedValueEncoder<General> for Bytes {
     }
 }
 
+borrow_decoding_via_owned!(General, Bytes, including distinguished);
+
 #[cfg(test)]
 mod bytes_blob {
     use super::{Bytes, General, Vec};
@@ -403,27 +702,37 @@ impl Wiretyped<General> for Blob {
 }
 
 impl ValueEncoder<General> for Blob {
+    const VALUE_KIND: &'static ValueKind = &ValueKind::Bytes;
+
     #[inline]
     fn encode_value<B: BufMut + ?Sized>(value: &Blob, buf: &mut B) {
         ValueEncoder::<PlainBytes>::encode_value(&**value, buf)
     }
 
+    fn prepend_value(value: &Blob, buf: &mut ReverseBuffer) {
+        ValueEncoder::<PlainBytes>::prepend_value(&**value, buf)
+    }
+
     #[inline]
     fn value_encoded_len(value: &Blob) -> usize {
         ValueEncoder::<PlainBytes>::value_encoded_len(&**value)
     }
+}
 
+impl ValueDecoder<General> for Blob {
     #[inline]
     fn decode_value<B: Buf + ?Sized>(
         value: &mut Blob,
         buf: Capped<B>,
         ctx: DecodeContext,
     ) -> Result<(), DecodeError> {
-        ValueEncoder::<PlainBytes>::decode_value(&mut **value, buf, ctx)
+        ValueDecoder::<PlainBytes>::decode_value(&mut **value, buf, ctx)
     }
 }
 
-impl DistinguishedValueEncoder<General> for Blob {
+impl DistinguishedValueEncoder<General> for Blob {}
+
+impl DistinguishedValueDecoder<General> for Blob {
     #[inline]
     fn decode_value_distinguished<B: Buf + ?Sized>(
         value: &mut Blob,
@@ -431,7 +740,7 @@ impl DistinguishedValueEncoder<General> for Blob {
         allow_empty: bool,
         ctx: DecodeContext,
     ) -> Result<Canonicity, DecodeError> {
-        DistinguishedValueEncoder::<PlainBytes>::decode_value_distinguished(
+        DistinguishedValueDecoder::<PlainBytes>::decode_value_distinguished(
             &mut **value,
             buf,
             allow_empty,
@@ -440,6 +749,90 @@ impl DistinguishedValueEncoder<General> for Blob {
     }
 }
 
+borrow_decoding_via_owned!(General, Blob, including distinguished);
+
+impl<M: RawMessageDecoder> Wiretyped<General> for Lazy<M> {
+    const WIRE_TYPE: WireType = WireType::LengthDelimited;
+}
+
+impl<M: RawMessageDecoder> ValueEncoder<General> for Lazy<M> {
+    const VALUE_KIND: &'static ValueKind = &ValueKind::Message(MessageRef::of::<M>());
+
+    fn encode_value<B: BufMut + ?Sized>(value: &Lazy<M>, buf: &mut B) {
+        match value.form() {
+            LazyForm::Encoded(encoded) => ValueEncoder::<General>::encode_value(encoded, buf),
+            LazyForm::Decoded(message) => ValueEncoder::<General>::encode_value(message, buf),
+        }
+    }
+
+    fn prepend_value(value: &Lazy<M>, buf: &mut ReverseBuffer) {
+        match value.form() {
+            LazyForm::Encoded(encoded) => ValueEncoder::<General>::prepend_value(encoded, buf),
+            LazyForm::Decoded(message) => ValueEncoder::<General>::prepend_value(message, buf),
+        }
+    }
+
+    fn value_encoded_len(value: &Lazy<M>) -> usize {
+        match value.form() {
+            LazyForm::Encoded(encoded) => ValueEncoder::<General>::value_encoded_len(encoded),
+            LazyForm::Decoded(message) => ValueEncoder::<General>::value_encoded_len(message),
+        }
+    }
+}
+
+impl<M: RawMessageDecoder> ValueDecoder<General> for Lazy<M> {
+    fn decode_value<B: Buf + ?Sized>(
+        value: &mut Lazy<M>,
+        mut buf: Capped<B>,
+        ctx: DecodeContext,
+    ) -> Result<(), DecodeError> {
+        let mut buf = buf.take_length_delimited()?;
+        let len = buf.remaining_before_cap();
+        ctx.check_bytes_alloc(len)?;
+        *value = Lazy::from_encoded(buf.copy_to_bytes(len));
+        Ok(())
+    }
+}
+
+/// Unmodified `Lazy` messages are re-encoded verbatim whether or not their bytes are canonical, but
+/// they only compare equal to values that encode identically, so equal values still have the same
+/// encoding.
+impl<M: RawDistinguishedMessageDecoder> DistinguishedValueEncoder<General> for Lazy<M> {}
+
+impl<M: RawDistinguishedMessageDecoder> DistinguishedValueDecoder<General> for Lazy<M> {
+    fn decode_value_distinguished<B: Buf + ?Sized>(
+        value: &mut Lazy<M>,
+        mut buf: Capped<B>,
+        allow_empty: bool,
+        ctx: DecodeContext,
+    ) -> Result<Canonicity, DecodeError> {
+        ctx.limit_reached()?;
+        let mut buf = buf.take_length_delimited()?;
+        let len = buf.remaining_before_cap();
+        if !allow_empty && len == 0 {
+            return Ok(Canonicity::NotCanonical);
+        }
+        ctx.check_bytes_alloc(len)?;
+        let encoded = buf.copy_to_bytes(len);
+        if ctx.defers_validation() {
+            // The canonicity of the contents will be reported when they are accessed.
+            *value = Lazy::from_encoded(encoded);
+            return Ok(Canonicity::Canonical);
+        }
+        let mut message = M::empty();
+        let mut reader = encoded.clone();
+        let canon = merge_distinguished(
+            &mut message,
+            Capped::new(&mut reader),
+            ctx.enter_recursion(),
+        )?;
+        *value = Lazy::from_validated(encoded, message, canon);
+        Ok(canon)
+    }
+}
+
+borrow_decoding_via_owned!(General, Lazy<M>, with generics (M), including distinguished);
+
 #[cfg(test)]
 mod blob {
     use super::{Blob, General};
@@ -459,29 +852,56 @@ impl<T> ValueEncoder<General> for T
 where
     T: RawMessage,
 {
+    const VALUE_KIND: &'static ValueKind = &ValueKind::Message(MessageRef::of::<T>());
+
     fn encode_value<B: BufMut + ?Sized>(value: &T, buf: &mut B) {
         encode_varint(value.raw_encoded_len() as u64, buf);
         value.raw_encode(buf);
     }
 
+    fn prepend_value(value: &T, buf: &mut ReverseBuffer) {
+        let end = buf.len();
+        value.raw_prepend(buf);
+        prepend_varint((buf.len() - end) as u64, buf);
+    }
+
     fn value_encoded_len(value: &T) -> usize {
         let inner_len = value.raw_encoded_len();
         encoded_len_varint(inner_len as u64) + inner_len
     }
 
+    fn diff_value(tag: u32, old: &T, new: &T, pw: &mut PatchWriter) {
+        pw.nested(tag, |pw| old.raw_diff(new, pw));
+    }
+}
+
+impl<T> ValueDecoder<General> for T
+where
+    T: RawMessageDecoder,
+{
     fn decode_value<B: Buf + ?Sized>(
         value: &mut T,
         mut buf: Capped<B>,
         ctx: DecodeContext,
     ) -> Result<(), DecodeError> {
         ctx.limit_reached()?;
-        merge(value, buf.take_length_delimited()?, ctx.enter_recursion())
+        if ctx.merging() {
+            merge_update(value, buf.take_length_delimited()?, ctx.enter_recursion())
+        } else {
+            merge(value, buf.take_length_delimited()?, ctx.enter_recursion())
+        }
+    }
+
+    fn apply_nested_value(value: &mut T, path: &[u32], op: &PatchOp) -> Result<(), DecodeError> {
+        value.raw_apply(path, op)
     }
 }
 
-impl<T> DistinguishedValueEncoder<General> for T
+impl<T> DistinguishedValueEncoder<General> for T where T: RawDistinguishedMessage + Eq {}
+
+impl<T> DistinguishedValueDecoder<General> for T
 where
-    T: RawDistinguishedMessage + Eq,
+    T: RawDistinguishedMessageDecoder + Eq,
 {
     fn decode_value_distinguished<B: Buf + ?Sized>(
         value: &mut T,
@@ -500,3 +920,39 @@ where
         merge_distinguished(value, buf, ctx.enter_recursion())
     }
 }
+
+impl<'a, T> ValueBorrowDecoder<'a, General> for T
+where
+    T: RawMessageBorrowDecoder<'a>,
+{
+    fn borrow_decode_value(
+        value: &mut T,
+        mut buf: Capped<&'a [u8]>,
+        ctx: DecodeContext,
+    ) -> Result<(), DecodeError> {
+        ctx.limit_reached()?;
+        merge_borrowed(value, buf.take_length_delimited()?, ctx.enter_recursion())
+    }
+}
+
+impl<'a, T> DistinguishedValueBorrowDecoder<'a, General> for T
+where
+    T: RawDistinguishedMessageBorrowDecoder<'a> + Eq,
+{
+    fn borrow_decode_value_distinguished(
+        value: &mut T,
+        mut buf: Capped<&'a [u8]>,
+        allow_empty: bool,
+        ctx: DecodeContext,
+    ) -> Result<Canonicity, DecodeError> {
+        ctx.limit_reached()?;
+        let buf = buf.take_length_delimited()?;
+        // Empty message types always encode and decode from zero bytes. It is far cheaper to check
+        // here than to check after the value has been decoded and checking the message's
+        // `is_empty()`.
+        if !allow_empty && buf.remaining_before_cap() == 0 {
+            return Ok(Canonicity::NotCanonical);
+        }
+        merge_distinguished_borrowed(value, buf, ctx.enter_recursion())
+    }
+}
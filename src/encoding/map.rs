@@ -1,15 +1,25 @@
+use alloc::vec::Vec;
+
 use bytes::{Buf, BufMut};
 
+use crate::descriptor::{DescribedEncoding, EncodingDescriptor, ValueKind};
 use crate::encoding::value_traits::{DistinguishedMapping, Mapping};
 use crate::encoding::{
-    encode_varint, encoded_len_varint, encoder_where_value_encoder, Canonicity, Capped,
-    DecodeContext, DecodeError, DistinguishedValueEncoder, Encoder, NewForOverwrite, TagMeasurer,
-    TagWriter, ValueEncoder, WireType, Wiretyped,
+    encode_varint, encoded_len_varint, encoder_where_value_encoder, prepend_varint, Canonicity,
+    Capped, DecodeContext, DecodeError, DistinguishedValueBorrowDecoder, DistinguishedValueDecoder,
+    DistinguishedValueEncoder, Encoder, NewForOverwrite, ReverseBuffer, TagMeasurer, TagWriter,
+    ValueBorrowDecoder, ValueDecoder, ValueEncoder, WireType, Wiretyped,
 };
-use crate::DecodeErrorKind::Truncated;
+use crate::patch::PatchWriter;
+use crate::DecodeErrorKind::{InvalidPatch, Truncated};
 
 pub struct Map<KE, VE>(KE, VE);
 
+impl<KE: DescribedEncoding, VE: DescribedEncoding> DescribedEncoding for Map<KE, VE> {
+    const DESCRIPTOR: &'static EncodingDescriptor =
+        &EncodingDescriptor::Map(KE::DESCRIPTOR, VE::DESCRIPTOR);
+}
+
 encoder_where_value_encoder!(Map<KE, VE>, with where clause (T: Mapping), with generics (KE, VE));
 
 /// Maps are always length delimited.
@@ -54,6 +64,8 @@ where
     K: NewForOverwrite + ValueEncoder<KE>,
     V: NewForOverwrite + ValueEncoder<VE>,
 {
+    const VALUE_KIND: &'static ValueKind = &ValueKind::Map(K::VALUE_KIND, V::VALUE_KIND);
+
     fn encode_value<B: BufMut + ?Sized>(value: &M, buf: &mut B) {
         encode_varint(map_encoded_length::<M, KE, VE>(value) as u64, buf);
         for (key, val) in value.iter() {
@@ -62,11 +74,31 @@ where
         }
     }
 
+    fn prepend_value(value: &M, buf: &mut ReverseBuffer) {
+        let end = buf.len();
+        for (key, val) in value.reversed() {
+            ValueEncoder::<VE>::prepend_value(val, buf);
+            ValueEncoder::<KE>::prepend_value(key, buf);
+        }
+        prepend_varint((buf.len() - end) as u64, buf);
+    }
+
     fn value_encoded_len(value: &M) -> usize {
         let inner_len = map_encoded_length::<M, KE, VE>(value);
         encoded_len_varint(inner_len as u64) + inner_len
     }
 
+    fn diff_value(tag: u32, old: &M, new: &M, pw: &mut PatchWriter) {
+        pw.diff_map::<M, K, V, KE, VE>(tag, old, new);
+    }
+}
+
+impl<M, K, V, KE, VE> ValueDecoder<Map<KE, VE>> for M
+where
+    M: Mapping<Key = K, Value = V>,
+    K: NewForOverwrite + ValueDecoder<KE>,
+    V: NewForOverwrite + ValueDecoder<VE>,
+{
     fn decode_value<B: Buf + ?Sized>(
         value: &mut M,
         mut buf: Capped<B>,
@@ -82,13 +114,39 @@ where
         }) {
             return Err(DecodeError::new(Truncated));
         }
+        // When merging, the entries are gathered first so that the map can still reject keys
+        // repeated within this input.
+        let merging = ctx.merging();
+        let mut new_entries = Vec::new();
         while capped.has_remaining()? {
+            ctx.check_collection_item::<(K, V)>(value.len() + new_entries.len())?;
             let mut new_key = K::new_for_overwrite();
             let mut new_val = V::new_for_overwrite();
-            ValueEncoder::<KE>::decode_value(&mut new_key, capped.lend(), ctx.clone())?;
-            ValueEncoder::<VE>::decode_value(&mut new_val, capped.lend(), ctx.clone())?;
-            value.insert(new_key, new_val)?;
+            ValueDecoder::<KE>::decode_value(&mut new_key, capped.lend(), ctx)?;
+            ValueDecoder::<VE>::decode_value(&mut new_val, capped.lend(), ctx)?;
+            if merging {
+                new_entries.push((new_key, new_val));
+            } else {
+                value.insert(new_key, new_val)?;
+            }
+        }
+        if merging {
+            value.merge_extend(new_entries)?;
+        }
+        Ok(())
+    }
+
+    fn remove_value_key(value: &mut M, mut key: &[u8]) -> Result<(), DecodeError> {
+        let mut decoded = K::new_for_overwrite();
+        ValueDecoder::<KE>::decode_value(
+            &mut decoded,
+            Capped::new(&mut key),
+            DecodeContext::default(),
+        )?;
+        if key.has_remaining() {
+            return Err(DecodeError::new(InvalidPatch));
         }
+        value.remove(&decoded);
         Ok(())
     }
 }
@@ -98,6 +156,14 @@ where
     M: DistinguishedMapping<Key = K, Value = V> + Eq,
     K: NewForOverwrite + Eq + DistinguishedValueEncoder<KE>,
     V: NewForOverwrite + Eq + DistinguishedValueEncoder<VE>,
+{
+}
+
+impl<M, K, V, KE, VE> DistinguishedValueDecoder<Map<KE, VE>> for M
+where
+    M: DistinguishedMapping<Key = K, Value = V> + Eq,
+    K: NewForOverwrite + Eq + DistinguishedValueDecoder<KE>,
+    V: NewForOverwrite + Eq + DistinguishedValueDecoder<VE>,
 {
     fn decode_value_distinguished<B: Buf + ?Sized>(
         value: &mut M,
@@ -120,19 +186,20 @@ where
         }
         let mut canon = Canonicity::Canonical;
         while capped.has_remaining()? {
+            ctx.check_collection_item::<(K, V)>(value.len())?;
             let mut new_key = K::new_for_overwrite();
             let mut new_val = V::new_for_overwrite();
-            canon.update(DistinguishedValueEncoder::<KE>::decode_value_distinguished(
+            canon.update(DistinguishedValueDecoder::<KE>::decode_value_distinguished(
                 &mut new_key,
                 capped.lend(),
                 true,
-                ctx.clone(),
+                ctx,
             )?);
-            canon.update(DistinguishedValueEncoder::<VE>::decode_value_distinguished(
+            canon.update(DistinguishedValueDecoder::<VE>::decode_value_distinguished(
                 &mut new_val,
                 capped.lend(),
                 true,
-                ctx.clone(),
+                ctx,
             )?);
             canon.update(value.insert_distinguished(new_key, new_val)?);
         }
@@ -140,6 +207,91 @@ where
     }
 }
 
+impl<'a, M, K, V, KE, VE> ValueBorrowDecoder<'a, Map<KE, VE>> for M
+where
+    M: Mapping<Key = K, Value = V>,
+    K: NewForOverwrite + ValueBorrowDecoder<'a, KE>,
+    V: NewForOverwrite + ValueBorrowDecoder<'a, VE>,
+{
+    fn borrow_decode_value(
+        value: &mut M,
+        mut buf: Capped<&'a [u8]>,
+        ctx: DecodeContext,
+    ) -> Result<(), DecodeError> {
+        let mut capped = buf.take_length_delimited()?;
+        if combined_fixed_size(
+            <M::Key as Wiretyped<KE>>::WIRE_TYPE,
+            <M::Value as Wiretyped<VE>>::WIRE_TYPE,
+        )
+        .map_or(false, |fixed_size| {
+            capped.remaining_before_cap() % fixed_size != 0
+        }) {
+            return Err(DecodeError::new(Truncated));
+        }
+        while capped.has_remaining()? {
+            ctx.check_collection_item::<(K, V)>(value.len())?;
+            let mut new_key = K::new_for_overwrite();
+            let mut new_val = V::new_for_overwrite();
+            ValueBorrowDecoder::<KE>::borrow_decode_value(&mut new_key, capped.lend(), ctx)?;
+            ValueBorrowDecoder::<VE>::borrow_decode_value(&mut new_val, capped.lend(), ctx)?;
+            value.insert(new_key, new_val)?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a, M, K, V, KE, VE> DistinguishedValueBorrowDecoder<'a, Map<KE, VE>> for M
+where
+    M: DistinguishedMapping<Key = K, Value = V> + Eq,
+    K: NewForOverwrite + Eq + DistinguishedValueBorrowDecoder<'a, KE>,
+    V: NewForOverwrite + Eq + DistinguishedValueBorrowDecoder<'a, VE>,
+{
+    fn borrow_decode_value_distinguished(
+        value: &mut M,
+        mut buf: Capped<&'a [u8]>,
+        allow_empty: bool,
+        ctx: DecodeContext,
+    ) -> Result<Canonicity, DecodeError> {
+        let mut capped = buf.take_length_delimited()?;
+        if !allow_empty && capped.remaining_before_cap() == 0 {
+            return Ok(Canonicity::NotCanonical);
+        }
+        if combined_fixed_size(
+            <M::Key as Wiretyped<KE>>::WIRE_TYPE,
+            <M::Value as Wiretyped<VE>>::WIRE_TYPE,
+        )
+        .map_or(false, |fixed_size| {
+            capped.remaining_before_cap() % fixed_size != 0
+        }) {
+            return Err(DecodeError::new(Truncated));
+        }
+        let mut canon = Canonicity::Canonical;
+        while capped.has_remaining()? {
+            ctx.check_collection_item::<(K, V)>(value.len())?;
+            let mut new_key = K::new_for_overwrite();
+            let mut new_val = V::new_for_overwrite();
+            canon.update(
+                DistinguishedValueBorrowDecoder::<KE>::borrow_decode_value_distinguished(
+                    &mut new_key,
+                    capped.lend(),
+                    true,
+                    ctx,
+                )?,
+            );
+            canon.update(
+                DistinguishedValueBorrowDecoder::<VE>::borrow_decode_value_distinguished(
+                    &mut new_val,
+                    capped.lend(),
+                    true,
+                    ctx,
+                )?,
+            );
+            canon.update(value.insert_distinguished(new_key, new_val)?);
+        }
+        Ok(canon)
+    }
+}
+
 #[cfg(test)]
 mod test {
     mod btree {
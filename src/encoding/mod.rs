@@ -1,14 +1,22 @@
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
 use core::cmp::{min, Eq, Ordering, PartialEq};
 use core::default::Default;
 use core::fmt::Debug;
+use core::marker::PhantomData;
+use core::mem;
 use core::ops::{Deref, DerefMut};
+use core::sync::atomic::AtomicUsize;
+use core::sync::atomic::Ordering::Relaxed;
 
 use bytes::buf::Take;
 use bytes::{Buf, BufMut};
 
+use crate::descriptor::{FieldDescriptor, ValueKind};
+use crate::patch::{PatchOp, PatchWriter};
 use crate::DecodeErrorKind::{
-    InvalidVarint, NotCanonical, TagOverflowed, Truncated, UnexpectedlyRepeated, UnknownField,
-    WrongWireType,
+    InvalidPatch, InvalidVarint, LimitExceeded, NotCanonical, TagOverflowed, Truncated,
+    UnexpectedlyRepeated, UnknownField, WrongWireType,
 };
 use crate::{decode_length_delimiter, DecodeError, DecodeErrorKind};
 
@@ -20,13 +28,14 @@ mod map;
 pub mod opaque;
 mod packed;
 mod plain_bytes;
+mod reverse;
 mod unpacked;
 mod value_traits;
 mod varint;
 
 pub use value_traits::{
-    Collection, DistinguishedCollection, DistinguishedMapping, EmptyState, Enumeration, Mapping,
-    NewForOverwrite,
+    CanonicalOrd, Collection, DistinguishedCollection, DistinguishedMapping, EmptyState,
+    Enumeration, Mapping, NewForOverwrite,
 };
 
 /// Fixed-size encoder. Encodes integers in fixed-size format.
@@ -41,6 +50,10 @@ pub use packed::Packed;
 /// Encoder that decodes bytes data directly into `Vec<u8>`, rather than requiring it to be wrapped
 /// in `Blob`.
 pub use plain_bytes::PlainBytes;
+/// Buffer that messages can be encoded into back to front in a single pass.
+pub use reverse::ReverseBuffer;
+#[cfg(feature = "opaque")]
+pub(crate) use unpacked::peek_repeated_field;
 /// Unpacked encoder. Encodes repeated types in unpacked format, writing repeated fields.
 pub use unpacked::Unpacked;
 /// Varint encoder. Encodes integer types as varints.
@@ -61,6 +74,21 @@ pub fn encode_varint<B: BufMut + ?Sized>(mut value: u64, buf: &mut B) {
     }
 }
 
+/// Encodes an integer value into LEB128-bijective variable length format, and prepends it to the
+/// front of the reverse buffer.
+#[inline(always)]
+pub fn prepend_varint(value: u64, buf: &mut ReverseBuffer) {
+    if value < 0x80 {
+        buf.prepend_u8(value as u8);
+    } else {
+        let mut encoded = [0u8; 9];
+        let mut rest = &mut encoded[..];
+        encode_varint(value, &mut rest);
+        let len = 9 - rest.len();
+        buf.prepend_slice(&encoded[..len]);
+    }
+}
+
 /// Decodes a LEB128-bijective-encoded variable length integer from the buffer.
 #[inline(always)]
 pub fn decode_varint<B: Buf + ?Sized>(buf: &mut B) -> Result<u64, DecodeError> {
@@ -194,43 +222,305 @@ fn decode_varint_slow<B: Buf + ?Sized>(buf: &mut B) -> Result<u64, DecodeError>
     // causes a 5x pessimization. Probably best not to worry about it too much.
 }
 
+/// Limits on the resources a single decoding operation may use, for decoding untrusted input.
+///
+/// Options are passed to methods like `Message::decode_with_options`. Any limit that is exceeded
+/// while decoding causes decoding to fail with `DecodeErrorKind::LimitExceeded`, except for the
+/// maximum depth, which fails with `DecodeErrorKind::RecursionLimitReached`. By default every limit
+/// is unlimited except for the maximum depth, which is the same as when decoding without options.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct DecodeOptions {
+    max_depth: u32,
+    max_input_size: usize,
+    max_collection_items: usize,
+    max_bytes_len: usize,
+    allocation_budget: usize,
+    deferred_validation: bool,
+}
+
+impl Default for DecodeOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DecodeOptions {
+    /// Creates options with the default limits.
+    pub const fn new() -> Self {
+        Self {
+            max_depth: crate::RECURSION_LIMIT,
+            max_input_size: usize::MAX,
+            max_collection_items: usize::MAX,
+            max_bytes_len: usize::MAX,
+            allocation_budget: usize::MAX,
+            deferred_validation: false,
+        }
+    }
+
+    /// Sets the maximum depth of nested messages.
+    pub const fn with_max_depth(mut self, max_depth: u32) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Sets the maximum size in bytes of the encoded message.
+    pub const fn with_max_input_size(mut self, max_input_size: usize) -> Self {
+        self.max_input_size = max_input_size;
+        self
+    }
+
+    /// Sets the maximum number of items in any one collection or entries in any one map.
+    pub const fn with_max_collection_items(mut self, max_collection_items: usize) -> Self {
+        self.max_collection_items = max_collection_items;
+        self
+    }
+
+    /// Sets the maximum length in bytes of any one string or blob value.
+    pub const fn with_max_bytes_len(mut self, max_bytes_len: usize) -> Self {
+        self.max_bytes_len = max_bytes_len;
+        self
+    }
+
+    /// Sets the total number of bytes that may be allocated for the decoded message's string and
+    /// blob data and collection items.
+    pub const fn with_allocation_budget(mut self, allocation_budget: usize) -> Self {
+        self.allocation_budget = allocation_budget;
+        self
+    }
+
+    /// Sets whether `Lazy` fields defer validating their contents when decoding in distinguished
+    /// mode. When this is set, the canonicity of their contents is not included in the canonicity
+    /// of the decoded message, and is reported by `Lazy::get_distinguished` instead.
+    pub const fn with_deferred_validation(mut self, deferred_validation: bool) -> Self {
+        self.deferred_validation = deferred_validation;
+        self
+    }
+
+    /// Returns the maximum depth of nested messages.
+    pub const fn max_depth(&self) -> u32 {
+        self.max_depth
+    }
+
+    /// Returns the maximum size in bytes of the encoded message.
+    pub const fn max_input_size(&self) -> usize {
+        self.max_input_size
+    }
+
+    /// Returns the maximum number of items in any one collection or entries in any one map.
+    pub const fn max_collection_items(&self) -> usize {
+        self.max_collection_items
+    }
+
+    /// Returns the maximum length in bytes of any one string or blob value.
+    pub const fn max_bytes_len(&self) -> usize {
+        self.max_bytes_len
+    }
+
+    /// Returns the total number of bytes that may be allocated for the decoded message's string
+    /// and blob data and collection items.
+    pub const fn allocation_budget(&self) -> usize {
+        self.allocation_budget
+    }
+
+    /// Returns whether `Lazy` fields defer validating their contents when decoding in
+    /// distinguished mode.
+    pub const fn deferred_validation(&self) -> bool {
+        self.deferred_validation
+    }
+}
+
+/// A selection of fields to decode, for decoding only part of a message.
+///
+/// Fields are selected by their paths: each path is a sequence of tags, starting with the tag of a
+/// field in the outermost message, then the tag of a field in the message that field contains, and
+/// so on. The path `[3, 1]` selects only field 1 of the message in field 3, while the path `[3]`
+/// selects the whole of field 3; an empty path selects everything. Paths pass through any
+/// collections or oneofs that contain the nested messages. All the fields that are not selected are
+/// skipped over without being decoded, and are left empty.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TagFilter {
+    /// The selected tags, each with the filter for the messages in that field, or `None` if every
+    /// field is selected.
+    tags: Option<BTreeMap<u32, TagFilter>>,
+}
+
+impl Default for TagFilter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TagFilter {
+    /// Creates a filter that selects no fields.
+    pub fn new() -> Self {
+        Self {
+            tags: Some(BTreeMap::new()),
+        }
+    }
+
+    /// Creates a filter that selects the fields at each of the given paths.
+    pub fn from_paths(paths: &[&[u32]]) -> Self {
+        paths
+            .iter()
+            .fold(Self::new(), |filter, path| filter.with_path(path))
+    }
+
+    /// Adds the field at the given path to the selected fields.
+    pub fn with_path(mut self, path: &[u32]) -> Self {
+        self.select(path);
+        self
+    }
+
+    fn select(&mut self, path: &[u32]) {
+        let Some(tags) = &mut self.tags else {
+            return; // Everything is already selected
+        };
+        match path.split_first() {
+            None => self.tags = None,
+            Some((&tag, rest)) => tags.entry(tag).or_default().select(rest),
+        }
+    }
+
+    /// Returns true if every field is selected.
+    pub fn selects_all(&self) -> bool {
+        self.tags.is_none()
+    }
+
+    /// Returns true if the field with the given tag is selected, either entirely or in part.
+    pub fn selects(&self, tag: u32) -> bool {
+        match &self.tags {
+            None => true,
+            Some(tags) => tags.contains_key(&tag),
+        }
+    }
+}
+
+/// The limits of a decoding operation with options, shared by all of its `DecodeContext`s.
+///
+/// Limits are created from `DecodeOptions` at the start of a decoding operation and track how much
+/// of the allocation budget remains as it proceeds, so a fresh `DecodeLimits` is needed for each
+/// operation.
+#[derive(Debug)]
+pub struct DecodeLimits {
+    options: DecodeOptions,
+    budget_remaining: AtomicUsize,
+}
+
+impl DecodeLimits {
+    /// Creates the limits for a decoding operation that enforces the given options.
+    pub fn new(options: &DecodeOptions) -> Self {
+        Self {
+            options: *options,
+            budget_remaining: AtomicUsize::new(options.allocation_budget),
+        }
+    }
+}
+
 /// Additional information passed to every decode/merge function.
 ///
-/// The context should be passed by value and can be freely cloned. When passing
+/// The context should be passed by value and can be freely copied. When passing
 /// to a function which is decoding a nested object, then use `enter_recursion`.
-#[derive(Clone, Debug)]
-pub struct DecodeContext {
+///
+/// Decoding without options or a tag filter uses a context with no limits and no filter, so the
+/// context stays a few words of plain data: it is `Copy`, has no drop glue, and the limit checks
+/// each cost only a test for `None`. Limits and filters are borrowed from the caller for the
+/// duration of the decoding operation rather than shared through reference counts.
+#[derive(Clone, Copy, Debug)]
+pub struct DecodeContext<'a> {
     /// How many times we can recurse in the current decode stack before we hit
     /// the recursion limit.
-    ///
-    /// The recursion limit is defined by `RECURSION_LIMIT` and cannot be
-    /// customized. The recursion limit can be ignored by building the Bilrost
-    /// crate with the `no-recursion-limit` feature.
-    #[cfg(not(feature = "no-recursion-limit"))]
     recurse_count: u32,
+    /// Whether decoded data is being merged into existing values, rather than decoded into empty
+    /// ones.
+    merging: bool,
+    /// Limits from the `DecodeOptions` this decode was started with, if any.
+    limits: Option<&'a DecodeLimits>,
+    /// The fields to decode at the current level of nesting, if only some of them are selected.
+    filter: Option<&'a TagFilter>,
 }
 
-impl Default for DecodeContext {
+impl Default for DecodeContext<'_> {
     #[inline]
-    fn default() -> DecodeContext {
+    fn default() -> Self {
         DecodeContext {
-            #[cfg(not(feature = "no-recursion-limit"))]
             recurse_count: crate::RECURSION_LIMIT,
+            merging: false,
+            limits: None,
+            filter: None,
         }
     }
 }
 
-impl DecodeContext {
+impl<'a> DecodeContext<'a> {
+    /// Creates a context for a decoding operation that enforces the given limits.
+    pub fn with_limits(limits: &'a DecodeLimits) -> Self {
+        DecodeContext {
+            recurse_count: limits.options.max_depth,
+            merging: false,
+            limits: Some(limits),
+            filter: None,
+        }
+    }
+
+    /// Returns this context, restricted to decoding only the fields selected by the given filter.
+    /// Tag filters only apply when decoding in expedient mode.
+    pub fn with_tag_filter(self, filter: &'a TagFilter) -> Self {
+        DecodeContext {
+            filter: (!filter.selects_all()).then_some(filter),
+            ..self
+        }
+    }
+
+    /// Returns the context for decoding the field with the given tag, or `None` if that field is
+    /// not selected by the context's tag filter and should be skipped.
+    #[inline]
+    pub(crate) fn for_field(&self, tag: u32) -> Option<Self> {
+        let Some(TagFilter { tags: Some(tags) }) = self.filter else {
+            return Some(*self);
+        };
+        let sub_filter = tags.get(&tag)?;
+        Some(DecodeContext {
+            filter: (!sub_filter.selects_all()).then_some(sub_filter),
+            ..*self
+        })
+    }
+
+    /// Returns this context for merging data into existing values with `Message::merge_from`.
+    #[inline]
+    pub(crate) fn for_merging(self) -> Self {
+        DecodeContext {
+            merging: true,
+            ..self
+        }
+    }
+
+    /// Returns this context for decoding into values that have just been cleared, when a field
+    /// is replaced rather than merged into. This is used by derived code.
+    #[doc(hidden)]
+    #[inline]
+    pub fn without_merging(self) -> Self {
+        DecodeContext {
+            merging: false,
+            ..self
+        }
+    }
+
+    /// Returns true if decoded data is being merged into existing values.
+    #[inline]
+    pub(crate) fn merging(&self) -> bool {
+        self.merging
+    }
+
     /// Call this function before recursively decoding.
     ///
     /// There is no `exit` function since this function creates a new `DecodeContext`
     /// to be used at the next level of recursion. Continue to use the old context
     // at the previous level of recursion.
     #[inline]
-    pub(crate) fn enter_recursion(&self) -> DecodeContext {
+    pub(crate) fn enter_recursion(&self) -> Self {
         DecodeContext {
-            #[cfg(not(feature = "no-recursion-limit"))]
             recurse_count: self.recurse_count - 1,
+            ..*self
         }
     }
 
@@ -240,9 +530,7 @@ impl DecodeContext {
     /// Returns `Ok<()>` if it is ok to continue recursing.
     /// Returns `Err<DecodeError>` if the recursion limit has been reached.
     #[inline]
-    #[allow(clippy::unnecessary_wraps)] // needed in other features
     pub(crate) fn limit_reached(&self) -> Result<(), DecodeError> {
-        #[cfg(not(feature = "no-recursion-limit"))]
         if self.recurse_count == 0 {
             return Err(DecodeError::new(
                 crate::DecodeErrorKind::RecursionLimitReached,
@@ -250,6 +538,116 @@ impl DecodeContext {
         }
         Ok(())
     }
+
+    /// Checks that an encoded message of the given length may be decoded.
+    #[inline]
+    pub(crate) fn check_input_len(&self, len: usize) -> Result<(), DecodeError> {
+        match self.limits {
+            Some(limits) if len > limits.options.max_input_size => {
+                Err(DecodeError::new(LimitExceeded))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks that a string or blob value of the given length may be decoded without copying it.
+    #[inline]
+    pub(crate) fn check_bytes_len(&self, len: usize) -> Result<(), DecodeError> {
+        match self.limits {
+            Some(limits) if len > limits.options.max_bytes_len => {
+                Err(DecodeError::new(LimitExceeded))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks that a string or blob value of the given length may be decoded, and charges its data
+    /// to the allocation budget.
+    #[inline]
+    pub(crate) fn check_bytes_alloc(&self, len: usize) -> Result<(), DecodeError> {
+        self.check_bytes_len(len)?;
+        self.charge_allocation(len)
+    }
+
+    /// Checks that another item may be added to a collection which currently has `len` items,
+    /// and charges the new item to the allocation budget.
+    #[inline]
+    pub(crate) fn check_collection_item<T>(&self, len: usize) -> Result<(), DecodeError> {
+        match self.limits {
+            Some(limits) if len >= limits.options.max_collection_items => {
+                Err(DecodeError::new(LimitExceeded))
+            }
+            _ => self.charge_allocation(mem::size_of::<T>()),
+        }
+    }
+
+    /// Returns whether `Lazy` fields should defer validating their contents.
+    #[inline]
+    pub(crate) fn defers_validation(&self) -> bool {
+        matches!(self.limits, Some(limits) if limits.options.deferred_validation)
+    }
+
+    /// Charges some allocated bytes to the allocation budget.
+    #[inline]
+    pub(crate) fn charge_allocation(&self, bytes: usize) -> Result<(), DecodeError> {
+        if let Some(limits) = self.limits {
+            // A decoding operation runs on a single thread, so the budget only needs atomic loads
+            // and stores to be shareable, not a read-modify-write.
+            let remaining = limits.budget_remaining.load(Relaxed);
+            if bytes > remaining {
+                return Err(DecodeError::new(LimitExceeded));
+            }
+            limits.budget_remaining.store(remaining - bytes, Relaxed);
+        }
+        Ok(())
+    }
+}
+
+/// Tracks which of the oneofs in a message have had a field merged into them from the current
+/// input when merging with `Message::merge_from`, so that a oneof can be replaced by the first
+/// of its fields in the input but conflicting fields within the same input are still rejected.
+#[derive(Debug, Default)]
+pub struct MergeTracker {
+    seen: u64,
+    seen_beyond: Vec<usize>,
+}
+
+impl MergeTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records that a field of the oneof with the given index has been seen, returning true if
+    /// this is the first time.
+    pub fn first_sighting(&mut self, index: usize) -> bool {
+        if index < 64 {
+            let bit = 1 << index;
+            let first = self.seen & bit == 0;
+            self.seen |= bit;
+            first
+        } else if self.seen_beyond.contains(&index) {
+            false
+        } else {
+            self.seen_beyond.push(index);
+            true
+        }
+    }
+}
+
+/// Checks at compile time that the `merge` attribute of a derived message field applies to the kind
+/// of value the field holds.
+#[doc(hidden)]
+pub struct MergeModeCheck<T, E>(PhantomData<(T, E)>);
+
+impl<T: Encoder<E>, E> MergeModeCheck<T, E> {
+    pub const APPEND: () = assert!(
+        T::VALUE_KIND.merges_by_appending(),
+        "merge = \"append\" only applies to collection and map fields"
+    );
+    pub const RECURSE: () = assert!(
+        T::VALUE_KIND.merges_recursively(),
+        "merge = \"recurse\" only applies to nested message fields"
+    );
 }
 
 /// Returns the encoded length of the value in LEB128-bijective variable length format.
@@ -317,7 +715,7 @@ impl From<u8> for WireType {
 }
 
 impl WireType {
-    const fn fixed_size(self) -> Option<usize> {
+    pub(crate) const fn fixed_size(self) -> Option<usize> {
         match self {
             WireType::SixtyFourBit => Some(8),
             WireType::ThirtyTwoBit => Some(4),
@@ -354,6 +752,71 @@ impl TagWriter {
     }
 }
 
+/// Writes keys for the provided tags when encoding in reverse, from the last field to the first.
+///
+/// Each key is encoded as a delta from the tag of the field before it, which is not known until
+/// that field is reached. The key for each field is therefore only written once the next field
+/// (the one before it in the encoding) begins, or once the message is finished.
+#[derive(Default)]
+pub struct TagRevWriter {
+    pending: Option<(u32, WireType)>,
+}
+
+impl TagRevWriter {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Begins a field with the given tag and wire type. This must be called before the field's
+    /// value is prepended to the buffer, and fields must be begun in descending order of tag.
+    #[inline(always)]
+    pub fn begin_field(&mut self, tag: u32, wire_type: WireType, buf: &mut ReverseBuffer) {
+        if let Some((pending_tag, pending_wire_type)) = self.pending {
+            let tag_delta = pending_tag
+                .checked_sub(tag)
+                .expect("fields encoded out of order");
+            prepend_varint(((tag_delta as u64) << 2) | (pending_wire_type as u64), buf);
+        }
+        self.pending = Some((tag, wire_type));
+    }
+
+    /// Prepends fields that were encoded forward, starting from a new `TagWriter`, to the front of
+    /// the buffer, exactly as if each of them had been begun and prepended in turn. This is how
+    /// fields are prepended by default by types that only know how to encode forward.
+    pub fn prepend_forward(&mut self, encoded: &[u8], buf: &mut ReverseBuffer) {
+        const MALFORMED: &str = "forward-encoded fields are malformed";
+        if encoded.is_empty() {
+            return;
+        }
+        let mut tr = TagReader::new();
+        let mut rest = encoded;
+        let first = tr.decode_key(Capped::new(&mut rest)).expect(MALFORMED);
+        let values = rest;
+        let mut last = first;
+        loop {
+            skip_field(last.1, Capped::new(&mut rest)).expect(MALFORMED);
+            if rest.is_empty() {
+                break;
+            }
+            last = tr.decode_key(Capped::new(&mut rest)).expect(MALFORMED);
+        }
+        // The key of the field after these is a delta from the last of them, but the key of
+        // the first of them is still pending until the field before it is begun.
+        self.begin_field(last.0, last.1, buf);
+        buf.prepend_slice(values);
+        self.pending = Some(first);
+    }
+
+    /// Writes the key of the first field of the message, if there was one. This must be called
+    /// after all of the message's fields have been prepended.
+    #[inline(always)]
+    pub fn finalize(&mut self, buf: &mut ReverseBuffer) {
+        if let Some((tag, wire_type)) = self.pending.take() {
+            prepend_varint(((tag as u64) << 2) | (wire_type as u64), buf);
+        }
+    }
+}
+
 /// Simulator for writing tags, capable of outputting their encoded length.
 #[derive(Default)]
 pub struct TagMeasurer {
@@ -535,6 +998,23 @@ impl<'a, B: Buf + ?Sized> DerefMut for Capped<'a, B> {
     }
 }
 
+impl<'a> Capped<'_, &'a [u8]> {
+    /// Reads a length delimiter from the beginning of the wrapped slice, then returns the delineated
+    /// bytes as a slice that borrows from the same underlying data as the wrapped slice, checking
+    /// that it does not overrun this instance's cap.
+    #[inline]
+    pub fn take_borrowed_length_delimited(&mut self) -> Result<&'a [u8], DecodeError> {
+        let len = decode_length_delimiter(&mut *self.buf)?;
+        let remaining = self.buf.len();
+        if len > remaining || remaining - len < self.extra_bytes_remaining {
+            return Err(DecodeError::new(Truncated));
+        }
+        let (taken, rest) = self.buf.split_at(len);
+        *self.buf = rest;
+        Ok(taken)
+    }
+}
+
 pub fn skip_field<B: Buf + ?Sized>(
     wire_type: WireType,
     mut buf: Capped<B>,
@@ -554,14 +1034,32 @@ pub fn skip_field<B: Buf + ?Sized>(
     Ok(())
 }
 
-/// The core trait for encoding and decoding bilrost data.
+/// The core trait for encoding bilrost data.
 pub trait Encoder<E> {
+    /// Describes the kind of value in the field. Unknown by default.
+    const VALUE_KIND: &'static ValueKind = &ValueKind::Unknown;
     /// Encodes the a field with the given tag and value.
     fn encode<B: BufMut + ?Sized>(tag: u32, value: &Self, buf: &mut B, tw: &mut TagWriter);
-    // TODO(widders): change to (or augment with) build-in-reverse-then-emit-forward and
-    //  emit-reversed
+    /// Prepends the field with the given tag and value to the front of the buffer, writing it in
+    /// reverse. By default this encodes the field forward into a scratch buffer and prepends that.
+    fn prepend_encode(tag: u32, value: &Self, buf: &mut ReverseBuffer, tw: &mut TagRevWriter) {
+        let mut forward = Vec::new();
+        Self::encode(tag, value, &mut forward, &mut TagWriter::new());
+        tw.prepend_forward(&forward, buf);
+    }
     /// Returns the encoded length of the field, including the key.
     fn encoded_len(tag: u32, value: &Self, tm: &mut TagMeasurer) -> usize;
+    /// Writes the operations that change the field with the given tag from the `old` value to the
+    /// `new` value to the patch writer. By default the field is replaced whole if it differs.
+    #[inline]
+    fn diff_field(tag: u32, old: &Self, new: &Self, pw: &mut PatchWriter) {
+        pw.replace_field::<Self, E>(tag, old, new)
+    }
+}
+
+/// The core trait for decoding bilrost data into values that hold their own data. Types that can
+/// only be decoded by borrowing from their input, like `&str`, implement `BorrowDecoder` instead.
+pub trait Decoder<E>: Encoder<E> {
     /// Decodes a field with the given wire type; the field's key should have already been consumed
     /// from the buffer.
     fn decode<B: Buf + ?Sized>(
@@ -571,12 +1069,27 @@ pub trait Encoder<E> {
         buf: Capped<B>,
         ctx: DecodeContext,
     ) -> Result<(), DecodeError>;
+    /// Applies a patch operation whose path leads into the value of the field; `path` is what
+    /// remains of it. By default this fails, since most values have no fields of their own.
+    #[inline]
+    fn apply_nested(_value: &mut Self, _path: &[u32], _op: &PatchOp) -> Result<(), DecodeError> {
+        Err(DecodeError::new(InvalidPatch))
+    }
+    /// Removes the entry with the given encoded key from the map in the field. By default this
+    /// fails, since most values are not maps.
+    #[inline]
+    fn remove_key(_value: &mut Self, _key: &[u8]) -> Result<(), DecodeError> {
+        Err(DecodeError::new(InvalidPatch))
+    }
 }
 
-/// Extension trait for canonical encoding and decoding. Distinguished decoding is available via
-/// this trait, and any type that implements this trait is guaranteed to always emit canonical data
-/// via `Encoder`.
-pub trait DistinguishedEncoder<E>: Encoder<E> {
+/// Marker trait for canonical encoding. Any type that implements this trait is guaranteed to always
+/// emit canonical data via `Encoder`; distinguished decoding is available via
+/// `DistinguishedDecoder` and `DistinguishedBorrowDecoder`.
+pub trait DistinguishedEncoder<E>: Encoder<E> {}
+
+/// Extension trait for canonical decoding of values that hold their own data.
+pub trait DistinguishedDecoder<E>: DistinguishedEncoder<E> + Decoder<E> {
     /// Decodes a field for the value, returning a value indicating how canonical the encoding was.
     fn decode_distinguished<B: Buf + ?Sized>(
         wire_type: WireType,
@@ -1112,11 +1625,21 @@ pub trait Wiretyped<E> {
 /// Trait for encoding implementations for raw values that always encode to a single value. Used as
 /// the basis for all the other plain, optional, and repeated encodings.
 pub trait ValueEncoder<E>: Wiretyped<E> {
+    /// Describes the kind of the value. Unknown by default.
+    const VALUE_KIND: &'static ValueKind = &ValueKind::Unknown;
+
     /// Encodes the given value unconditionally. This is guaranteed to emit data to the buffer.
     fn encode_value<B: BufMut + ?Sized>(value: &Self, buf: &mut B);
 
-    // TODO(widders): change to (or augment with) build-in-reverse-then-emit-forward and
-    //  emit-reversed
+    /// Prepends the given value unconditionally to the front of the buffer. This is guaranteed to
+    /// emit exactly the same data as `encode_value`, which it uses by default to encode into a
+    /// scratch buffer.
+    fn prepend_value(value: &Self, buf: &mut ReverseBuffer) {
+        let mut forward = Vec::with_capacity(Self::value_encoded_len(value));
+        Self::encode_value(value, &mut forward);
+        buf.prepend_slice(&forward);
+    }
+
     /// Returns the number of bytes the given value would be encoded as.
     fn value_encoded_len(value: &Self) -> usize;
 
@@ -1134,15 +1657,51 @@ pub trait ValueEncoder<E>: Wiretyped<E> {
         )
     }
 
+    /// Writes the operations that change a field with the given tag, which is present with both
+    /// values, from the `old` value to the `new` value to the patch writer. By default the field is
+    /// replaced whole if the values differ.
+    #[inline]
+    fn diff_value(tag: u32, old: &Self, new: &Self, pw: &mut PatchWriter) {
+        pw.replace_value::<Self, E>(tag, old, new)
+    }
+}
+
+/// Trait for decoding raw values that hold their own data, complementing `ValueEncoder`.
+pub trait ValueDecoder<E>: ValueEncoder<E> {
     /// Decodes a field assuming the encoder's wire type directly from the buffer.
     fn decode_value<B: Buf + ?Sized>(
         value: &mut Self,
         buf: Capped<B>,
         ctx: DecodeContext,
     ) -> Result<(), DecodeError>;
+
+    /// Applies a patch operation whose path leads into the value; `path` is what remains of it. By
+    /// default this fails, since most values have no fields of their own.
+    #[inline]
+    fn apply_nested_value(
+        _value: &mut Self,
+        _path: &[u32],
+        _op: &PatchOp,
+    ) -> Result<(), DecodeError> {
+        Err(DecodeError::new(InvalidPatch))
+    }
+
+    /// Removes the entry with the given encoded key from the value if it is a map. By default this
+    /// fails, since most values are not maps.
+    #[inline]
+    fn remove_value_key(_value: &mut Self, _key: &[u8]) -> Result<(), DecodeError> {
+        Err(DecodeError::new(InvalidPatch))
+    }
 }
 
+/// Marker trait for value encoders that always emit canonical data.
 pub trait DistinguishedValueEncoder<E>: Wiretyped<E>
+where
+    Self: Eq,
+{
+}
+
+pub trait DistinguishedValueDecoder<E>: DistinguishedValueEncoder<E>
 where
     Self: Eq,
 {
@@ -1158,19 +1717,14 @@ where
 }
 
 /// Affiliated helper trait for ValueEncoder that provides obligate implementations for handling
-/// field keys and wire types.
+/// field keys.
 pub trait FieldEncoder<E> {
     /// Encodes exactly one field with the given tag and value into the buffer.
     fn encode_field<B: BufMut + ?Sized>(tag: u32, value: &Self, buf: &mut B, tw: &mut TagWriter);
+    /// Prepends exactly one field with the given tag and value to the front of the buffer.
+    fn prepend_field(tag: u32, value: &Self, buf: &mut ReverseBuffer, tw: &mut TagRevWriter);
     /// Returns the encoded length of the field including its key.
     fn field_encoded_len(tag: u32, value: &Self, tm: &mut TagMeasurer) -> usize;
-    /// Decodes a field directly from the buffer, also checking the wire type.
-    fn decode_field<B: Buf + ?Sized>(
-        wire_type: WireType,
-        value: &mut Self,
-        buf: Capped<B>,
-        ctx: DecodeContext,
-    ) -> Result<(), DecodeError>;
 }
 
 impl<T, E> FieldEncoder<E> for T
@@ -1183,9 +1737,32 @@ where
         Self::encode_value(value, buf);
     }
     #[inline]
+    fn prepend_field(tag: u32, value: &Self, buf: &mut ReverseBuffer, tw: &mut TagRevWriter) {
+        tw.begin_field(tag, Self::WIRE_TYPE, buf);
+        Self::prepend_value(value, buf);
+    }
+    #[inline]
     fn field_encoded_len(tag: u32, value: &Self, tm: &mut TagMeasurer) -> usize {
         tm.key_len(tag) + Self::value_encoded_len(value)
     }
+}
+
+/// Affiliated helper trait for ValueDecoder that provides obligate implementations for handling
+/// wire types.
+pub trait FieldDecoder<E> {
+    /// Decodes a field directly from the buffer, also checking the wire type.
+    fn decode_field<B: Buf + ?Sized>(
+        wire_type: WireType,
+        value: &mut Self,
+        buf: Capped<B>,
+        ctx: DecodeContext,
+    ) -> Result<(), DecodeError>;
+}
+
+impl<T, E> FieldDecoder<E> for T
+where
+    Self: ValueDecoder<E>,
+{
     #[inline]
     fn decode_field<B: Buf + ?Sized>(
         wire_type: WireType,
@@ -1198,9 +1775,9 @@ where
     }
 }
 
-/// Affiliated helper trait for DistinguishedValueEncoder that provides obligate implementations for
-/// handling field keys and wire types.
-pub trait DistinguishedFieldEncoder<E> {
+/// Affiliated helper trait for DistinguishedValueDecoder that provides obligate implementations for
+/// handling wire types.
+pub trait DistinguishedFieldDecoder<E> {
     /// Decodes a field directly from the buffer, also checking the wire type.
     fn decode_field_distinguished<B: Buf + ?Sized>(
         wire_type: WireType,
@@ -1211,9 +1788,9 @@ pub trait DistinguishedFieldEncoder<E> {
     ) -> Result<Canonicity, DecodeError>;
 }
 
-impl<T, E> DistinguishedFieldEncoder<E> for T
+impl<T, E> DistinguishedFieldDecoder<E> for T
 where
-    Self: DistinguishedValueEncoder<E> + Eq,
+    Self: DistinguishedValueDecoder<E> + Eq,
 {
     #[inline]
     fn decode_field_distinguished<B: Buf + ?Sized>(
@@ -1228,6 +1805,64 @@ where
     }
 }
 
+/// Affiliated helper trait for ValueBorrowDecoder that provides obligate implementations for
+/// handling wire types when decoding fields from a borrowed buffer.
+pub trait FieldBorrowDecoder<'a, E> {
+    /// Decodes a field directly from the borrowed buffer, also checking the wire type.
+    fn borrow_decode_field(
+        wire_type: WireType,
+        value: &mut Self,
+        buf: Capped<&'a [u8]>,
+        ctx: DecodeContext,
+    ) -> Result<(), DecodeError>;
+}
+
+impl<'a, T, E> FieldBorrowDecoder<'a, E> for T
+where
+    Self: ValueBorrowDecoder<'a, E>,
+{
+    #[inline]
+    fn borrow_decode_field(
+        wire_type: WireType,
+        value: &mut Self,
+        buf: Capped<&'a [u8]>,
+        ctx: DecodeContext,
+    ) -> Result<(), DecodeError> {
+        check_wire_type(Self::WIRE_TYPE, wire_type)?;
+        Self::borrow_decode_value(value, buf, ctx)
+    }
+}
+
+/// Affiliated helper trait for DistinguishedValueBorrowDecoder that provides obligate
+/// implementations for handling wire types when decoding fields from a borrowed buffer.
+pub trait DistinguishedFieldBorrowDecoder<'a, E> {
+    /// Decodes a field directly from the borrowed buffer, also checking the wire type.
+    fn borrow_decode_field_distinguished(
+        wire_type: WireType,
+        value: &mut Self,
+        buf: Capped<&'a [u8]>,
+        allow_empty: bool,
+        ctx: DecodeContext,
+    ) -> Result<Canonicity, DecodeError>;
+}
+
+impl<'a, T, E> DistinguishedFieldBorrowDecoder<'a, E> for T
+where
+    Self: DistinguishedValueBorrowDecoder<'a, E> + Eq,
+{
+    #[inline]
+    fn borrow_decode_field_distinguished(
+        wire_type: WireType,
+        value: &mut T,
+        buf: Capped<&'a [u8]>,
+        allow_empty: bool,
+        ctx: DecodeContext,
+    ) -> Result<Canonicity, DecodeError> {
+        check_wire_type(Self::WIRE_TYPE, wire_type)?;
+        Self::borrow_decode_value_distinguished(value, buf, allow_empty, ctx)
+    }
+}
+
 /// Different value encoders may dispatch encoding their plain values slightly differently, but
 /// values wrapped in Option are always encoded the same.
 // TODO(widders): this would need to be broken up if a value type that may be encoded with different
@@ -1236,6 +1871,8 @@ impl<T, E> Encoder<E> for Option<T>
 where
     T: NewForOverwrite + ValueEncoder<E>,
 {
+    const VALUE_KIND: &'static ValueKind = &ValueKind::Optional(T::VALUE_KIND);
+
     #[inline]
     fn encode<B: BufMut + ?Sized>(tag: u32, value: &Self, buf: &mut B, tw: &mut TagWriter) {
         if let Some(value) = value {
@@ -1243,6 +1880,13 @@ where
         }
     }
 
+    #[inline]
+    fn prepend_encode(tag: u32, value: &Self, buf: &mut ReverseBuffer, tw: &mut TagRevWriter) {
+        if let Some(value) = value {
+            <T as FieldEncoder<E>>::prepend_field(tag, value, buf, tw);
+        }
+    }
+
     #[inline]
     fn encoded_len(tag: u32, value: &Self, tm: &mut TagMeasurer) -> usize {
         if let Some(value) = value {
@@ -1252,6 +1896,16 @@ where
         }
     }
 
+    #[inline]
+    fn diff_field(tag: u32, old: &Self, new: &Self, pw: &mut PatchWriter) {
+        pw.diff_present::<T, E>(tag, old.as_ref(), new.as_ref());
+    }
+}
+
+impl<T, E> Decoder<E> for Option<T>
+where
+    T: NewForOverwrite + ValueDecoder<E>,
+{
     #[inline]
     fn decode<B: Buf + ?Sized>(
         wire_type: WireType,
@@ -1263,21 +1917,41 @@ where
         if duplicated {
             return Err(DecodeError::new(UnexpectedlyRepeated));
         }
-        <T as FieldEncoder<E>>::decode_field(
+        <T as FieldDecoder<E>>::decode_field(
             wire_type,
             value.get_or_insert_with(T::new_for_overwrite),
             buf,
             ctx,
         )
     }
+
+    #[inline]
+    fn apply_nested(value: &mut Self, path: &[u32], op: &PatchOp) -> Result<(), DecodeError> {
+        T::apply_nested_value(value.get_or_insert_with(T::new_for_overwrite), path, op)
+    }
+
+    #[inline]
+    fn remove_key(value: &mut Self, key: &[u8]) -> Result<(), DecodeError> {
+        match value {
+            Some(value) => T::remove_value_key(value, key),
+            None => Ok(()),
+        }
+    }
 }
 
-/// Distinguished decoding for Option<T> is only different in that it calls the distinguished
-/// decoding codepath.
 impl<T, E> DistinguishedEncoder<E> for Option<T>
 where
     Self: Encoder<E>,
     T: DistinguishedValueEncoder<E> + NewForOverwrite + Eq,
+{
+}
+
+/// Distinguished decoding for Option<T> is only different in that it calls the distinguished
+/// decoding codepath.
+impl<T, E> DistinguishedDecoder<E> for Option<T>
+where
+    Self: Decoder<E> + DistinguishedEncoder<E>,
+    T: DistinguishedValueDecoder<E> + NewForOverwrite + Eq,
 {
     #[inline]
     fn decode_distinguished<B: Buf + ?Sized>(
@@ -1290,7 +1964,112 @@ where
         if duplicated {
             return Err(DecodeError::new(UnexpectedlyRepeated));
         }
-        <T as DistinguishedFieldEncoder<E>>::decode_field_distinguished(
+        <T as DistinguishedFieldDecoder<E>>::decode_field_distinguished(
+            wire_type,
+            value.get_or_insert_with(T::new_for_overwrite),
+            buf,
+            true, // Decoding an option, empty values are meaningful
+            ctx,
+        )
+    }
+}
+
+/// Trait for decoding fields by borrowing from the input, which must be a `&'a [u8]` slice, in
+/// addition to the regular decoding offered by `Encoder`. Types that hold their own data decode
+/// exactly the same way either way; types like `&'a str` and `Cow<'a, [u8]>` can refer directly to
+/// the bytes of the input.
+pub trait BorrowDecoder<'a, E>: Encoder<E> {
+    /// Decodes a field with the given wire type from the borrowed input; the field's key should
+    /// have already been consumed from the buffer.
+    fn borrow_decode(
+        wire_type: WireType,
+        duplicated: bool,
+        value: &mut Self,
+        buf: Capped<&'a [u8]>,
+        ctx: DecodeContext,
+    ) -> Result<(), DecodeError>;
+}
+
+/// Extension trait for canonical decoding of fields that may borrow from the input.
+pub trait DistinguishedBorrowDecoder<'a, E>: DistinguishedEncoder<E> {
+    /// Decodes a field for the value from the borrowed input, returning a value indicating how
+    /// canonical the encoding was.
+    fn borrow_decode_distinguished(
+        wire_type: WireType,
+        duplicated: bool,
+        value: &mut Self,
+        buf: Capped<&'a [u8]>,
+        ctx: DecodeContext,
+    ) -> Result<Canonicity, DecodeError>;
+}
+
+/// Trait for value encoders that can decode values by borrowing from the input, which must be a
+/// `&'a [u8]` slice.
+pub trait ValueBorrowDecoder<'a, E>: ValueEncoder<E> {
+    /// Decodes a field assuming the encoder's wire type directly from the borrowed input.
+    fn borrow_decode_value(
+        value: &mut Self,
+        buf: Capped<&'a [u8]>,
+        ctx: DecodeContext,
+    ) -> Result<(), DecodeError>;
+}
+
+pub trait DistinguishedValueBorrowDecoder<'a, E>: DistinguishedValueEncoder<E>
+where
+    Self: Eq,
+{
+    /// Decodes a field assuming the encoder's wire type directly from the borrowed input, also
+    /// performing any additional validation required to guarantee that the value would be
+    /// re-encoded into the exact same bytes.
+    fn borrow_decode_value_distinguished(
+        value: &mut Self,
+        buf: Capped<&'a [u8]>,
+        allow_empty: bool,
+        ctx: DecodeContext,
+    ) -> Result<Canonicity, DecodeError>;
+}
+
+impl<'a, T, E> BorrowDecoder<'a, E> for Option<T>
+where
+    T: NewForOverwrite + ValueBorrowDecoder<'a, E>,
+{
+    #[inline]
+    fn borrow_decode(
+        wire_type: WireType,
+        duplicated: bool,
+        value: &mut Self,
+        buf: Capped<&'a [u8]>,
+        ctx: DecodeContext,
+    ) -> Result<(), DecodeError> {
+        if duplicated {
+            return Err(DecodeError::new(UnexpectedlyRepeated));
+        }
+        <T as FieldBorrowDecoder<E>>::borrow_decode_field(
+            wire_type,
+            value.get_or_insert_with(T::new_for_overwrite),
+            buf,
+            ctx,
+        )
+    }
+}
+
+impl<'a, T, E> DistinguishedBorrowDecoder<'a, E> for Option<T>
+where
+    Self: DistinguishedEncoder<E>,
+    T: DistinguishedValueBorrowDecoder<'a, E> + NewForOverwrite + Eq,
+{
+    #[inline]
+    fn borrow_decode_distinguished(
+        wire_type: WireType,
+        duplicated: bool,
+        value: &mut Option<T>,
+        buf: Capped<&'a [u8]>,
+        ctx: DecodeContext,
+    ) -> Result<Canonicity, DecodeError> {
+        if duplicated {
+            return Err(DecodeError::new(UnexpectedlyRepeated));
+        }
+        <T as DistinguishedFieldBorrowDecoder<E>>::borrow_decode_field_distinguished(
             wire_type,
             value.get_or_insert_with(T::new_for_overwrite),
             buf,
@@ -1304,16 +2083,29 @@ where
 /// variants' tags and encoding.
 pub trait Oneof: EmptyState {
     const FIELD_TAGS: &'static [u32];
+    /// Describes each of the fields of the oneof.
+    const FIELDS: &'static [FieldDescriptor];
 
     /// Encodes the fields of the oneof into the given buffer.
     fn oneof_encode<B: BufMut + ?Sized>(&self, buf: &mut B, tw: &mut TagWriter);
 
+    /// Prepends the fields of the oneof to the front of the given buffer. By default this encodes
+    /// them forward into a scratch buffer and prepends that.
+    fn oneof_prepend(&self, buf: &mut ReverseBuffer, tw: &mut TagRevWriter) {
+        let mut forward = Vec::new();
+        self.oneof_encode(&mut forward, &mut TagWriter::new());
+        tw.prepend_forward(&forward, buf);
+    }
+
     /// Measures the number of bytes that would encode this oneof.
     fn oneof_encoded_len(&self, tm: &mut TagMeasurer) -> usize;
 
     /// Returns the current tag of the oneof, if any.
     fn oneof_current_tag(&self) -> Option<u32>;
+}
 
+/// Trait for oneofs whose fields can be decoded into values that hold their own data.
+pub trait OneofDecoder: Oneof {
     /// Decodes from the given buffer.
     fn oneof_decode_field<B: Buf + ?Sized>(
         &mut self,
@@ -1329,16 +2121,30 @@ pub trait Oneof: EmptyState {
 /// in an `Option`.
 pub trait NonEmptyOneof: Sized {
     const FIELD_TAGS: &'static [u32];
+    /// Describes each of the fields of the oneof.
+    const FIELDS: &'static [FieldDescriptor];
 
     /// Encodes the fields of the oneof into the given buffer.
     fn oneof_encode<B: BufMut + ?Sized>(&self, buf: &mut B, tw: &mut TagWriter);
 
+    /// Prepends the fields of the oneof to the front of the given buffer. By default this encodes
+    /// them forward into a scratch buffer and prepends that.
+    fn oneof_prepend(&self, buf: &mut ReverseBuffer, tw: &mut TagRevWriter) {
+        let mut forward = Vec::new();
+        self.oneof_encode(&mut forward, &mut TagWriter::new());
+        tw.prepend_forward(&forward, buf);
+    }
+
     /// Measures the number of bytes that would encode this oneof.
     fn oneof_encoded_len(&self, tm: &mut TagMeasurer) -> usize;
 
     /// Returns the current tag of the oneof, if any.
     fn oneof_current_tag(&self) -> u32;
+}
 
+/// Underlying trait for a oneof that has no inherent "empty" variant and whose fields can be
+/// decoded into values that hold their own data.
+pub trait NonEmptyOneofDecoder: NonEmptyOneof {
     /// Decodes from the given buffer.
     fn oneof_decode_field<B: Buf + ?Sized>(
         value: &mut Option<Self>,
@@ -1355,6 +2161,7 @@ where
     T: NonEmptyOneof,
 {
     const FIELD_TAGS: &'static [u32] = T::FIELD_TAGS;
+    const FIELDS: &'static [FieldDescriptor] = T::FIELDS;
 
     fn oneof_encode<B: BufMut + ?Sized>(&self, buf: &mut B, tw: &mut TagWriter) {
         if let Some(value) = self {
@@ -1362,6 +2169,12 @@ where
         }
     }
 
+    fn oneof_prepend(&self, buf: &mut ReverseBuffer, tw: &mut TagRevWriter) {
+        if let Some(value) = self {
+            value.oneof_prepend(buf, tw);
+        }
+    }
+
     fn oneof_encoded_len(&self, tm: &mut TagMeasurer) -> usize {
         if let Some(value) = self {
             value.oneof_encoded_len(tm)
@@ -1373,7 +2186,12 @@ where
     fn oneof_current_tag(&self) -> Option<u32> {
         self.as_ref().map(NonEmptyOneof::oneof_current_tag)
     }
+}
 
+impl<T> OneofDecoder for Option<T>
+where
+    T: NonEmptyOneofDecoder,
+{
     fn oneof_decode_field<B: Buf + ?Sized>(
         &mut self,
         tag: u32,
@@ -1386,9 +2204,12 @@ where
     }
 }
 
+/// Marker trait for oneofs that always emit canonical data.
+pub trait DistinguishedOneof: Oneof {}
+
 /// Trait to be implemented by (or more commonly derived for) oneofs, which have knowledge of their
 /// variants' tags and encoding.
-pub trait DistinguishedOneof: Oneof {
+pub trait DistinguishedOneofDecoder: DistinguishedOneof + OneofDecoder {
     /// Decodes from the given buffer in distinguished mode.
     fn oneof_decode_field_distinguished<B: Buf + ?Sized>(
         &mut self,
@@ -1400,9 +2221,14 @@ pub trait DistinguishedOneof: Oneof {
     ) -> Result<Canonicity, DecodeError>;
 }
 
+/// Marker trait for a oneof that has no inherent "empty" variant and always emits canonical data.
+pub trait NonEmptyDistinguishedOneof: NonEmptyOneof {}
+
 /// Underlying trait for a oneof that has no inherent "empty" variant, opting instead to be wrapped
 /// in an `Option`.
-pub trait NonEmptyDistinguishedOneof: Sized {
+pub trait NonEmptyDistinguishedOneofDecoder:
+    NonEmptyDistinguishedOneof + NonEmptyOneofDecoder
+{
     /// Decodes from the given buffer.
     fn oneof_decode_field_distinguished<B: Buf + ?Sized>(
         value: &mut Option<Self>,
@@ -1414,10 +2240,12 @@ pub trait NonEmptyDistinguishedOneof: Sized {
     ) -> Result<Canonicity, DecodeError>;
 }
 
-impl<T> DistinguishedOneof for Option<T>
+impl<T> DistinguishedOneof for Option<T> where T: NonEmptyDistinguishedOneof {}
+
+impl<T> DistinguishedOneofDecoder for Option<T>
 where
-    T: NonEmptyDistinguishedOneof,
-    Self: Oneof,
+    T: NonEmptyDistinguishedOneofDecoder,
+    Self: DistinguishedOneof + OneofDecoder,
 {
     fn oneof_decode_field_distinguished<B: Buf + ?Sized>(
         &mut self,
@@ -1431,6 +2259,93 @@ where
     }
 }
 
+/// Trait for oneofs that can decode their fields by borrowing from the input.
+pub trait OneofBorrowDecoder<'a>: Oneof {
+    /// Decodes from the given borrowed input.
+    fn oneof_borrow_decode_field(
+        &mut self,
+        tag: u32,
+        wire_type: WireType,
+        duplicated: bool,
+        buf: Capped<&'a [u8]>,
+        ctx: DecodeContext,
+    ) -> Result<(), DecodeError>;
+}
+
+/// Underlying trait for a oneof that has no inherent "empty" variant and can decode its fields by
+/// borrowing from the input.
+pub trait NonEmptyOneofBorrowDecoder<'a>: NonEmptyOneof {
+    /// Decodes from the given borrowed input.
+    fn oneof_borrow_decode_field(
+        value: &mut Option<Self>,
+        tag: u32,
+        wire_type: WireType,
+        duplicated: bool,
+        buf: Capped<&'a [u8]>,
+        ctx: DecodeContext,
+    ) -> Result<(), DecodeError>;
+}
+
+impl<'a, T> OneofBorrowDecoder<'a> for Option<T>
+where
+    T: NonEmptyOneofBorrowDecoder<'a>,
+{
+    fn oneof_borrow_decode_field(
+        &mut self,
+        tag: u32,
+        wire_type: WireType,
+        duplicated: bool,
+        buf: Capped<&'a [u8]>,
+        ctx: DecodeContext,
+    ) -> Result<(), DecodeError> {
+        T::oneof_borrow_decode_field(self, tag, wire_type, duplicated, buf, ctx)
+    }
+}
+
+/// Trait for oneofs that can decode their fields in distinguished mode by borrowing from the input.
+pub trait DistinguishedOneofBorrowDecoder<'a>: DistinguishedOneof {
+    /// Decodes from the given borrowed input in distinguished mode.
+    fn oneof_borrow_decode_field_distinguished(
+        &mut self,
+        tag: u32,
+        wire_type: WireType,
+        duplicated: bool,
+        buf: Capped<&'a [u8]>,
+        ctx: DecodeContext,
+    ) -> Result<Canonicity, DecodeError>;
+}
+
+/// Underlying trait for a oneof that has no inherent "empty" variant and can decode its fields in
+/// distinguished mode by borrowing from the input.
+pub trait NonEmptyDistinguishedOneofBorrowDecoder<'a>: NonEmptyDistinguishedOneof {
+    /// Decodes from the given borrowed input in distinguished mode.
+    fn oneof_borrow_decode_field_distinguished(
+        value: &mut Option<Self>,
+        tag: u32,
+        wire_type: WireType,
+        duplicated: bool,
+        buf: Capped<&'a [u8]>,
+        ctx: DecodeContext,
+    ) -> Result<Canonicity, DecodeError>;
+}
+
+impl<'a, T> DistinguishedOneofBorrowDecoder<'a> for Option<T>
+where
+    T: NonEmptyDistinguishedOneofBorrowDecoder<'a>,
+    Self: DistinguishedOneof,
+{
+    fn oneof_borrow_decode_field_distinguished(
+        &mut self,
+        tag: u32,
+        wire_type: WireType,
+        duplicated: bool,
+        buf: Capped<&'a [u8]>,
+        ctx: DecodeContext,
+    ) -> Result<Canonicity, DecodeError> {
+        T::oneof_borrow_decode_field_distinguished(self, tag, wire_type, duplicated, buf, ctx)
+    }
+}
+
 /// Trait used by derived enumeration helper functions to provide getters and setters for integer
 /// fields via their associated `Enumeration` type.
 pub trait EnumerationHelper<FieldType> {
@@ -1485,6 +2400,9 @@ macro_rules! delegate_encoding {
             Self: $crate::encoding::Encoder<$to_ty>,
             $($($where_clause)*)?
         {
+            const VALUE_KIND: &'static $crate::descriptor::ValueKind =
+                <Self as $crate::encoding::Encoder<$to_ty>>::VALUE_KIND;
+
             #[inline]
             fn encode<B: $crate::bytes::BufMut + ?Sized>(
                 tag: u32,
@@ -1495,6 +2413,16 @@ macro_rules! delegate_encoding {
                 $crate::encoding::Encoder::<$to_ty>::encode(tag, value, buf, tw)
             }
 
+            #[inline]
+            fn prepend_encode(
+                tag: u32,
+                value: &$value_ty,
+                buf: &mut $crate::encoding::ReverseBuffer,
+                tw: &mut $crate::encoding::TagRevWriter,
+            ) {
+                $crate::encoding::Encoder::<$to_ty>::prepend_encode(tag, value, buf, tw)
+            }
+
             #[inline]
             fn encoded_len(
                 tag: u32,
@@ -1504,6 +2432,22 @@ macro_rules! delegate_encoding {
                 $crate::encoding::Encoder::<$to_ty>::encoded_len(tag, value, tm)
             }
 
+            #[inline]
+            fn diff_field(
+                tag: u32,
+                old: &$value_ty,
+                new: &$value_ty,
+                pw: &mut $crate::patch::PatchWriter,
+            ) {
+                $crate::encoding::Encoder::<$to_ty>::diff_field(tag, old, new, pw)
+            }
+        }
+
+        impl$(<$($value_generics)*>)? $crate::encoding::Decoder<$from_ty> for $value_ty
+        where
+            Self: $crate::encoding::Decoder<$to_ty>,
+            $($($where_clause)*)?
+        {
             #[inline]
             fn decode<B: $crate::bytes::Buf + ?Sized>(
                 wire_type: $crate::encoding::WireType,
@@ -1512,7 +2456,45 @@ macro_rules! delegate_encoding {
                 buf: $crate::encoding::Capped<B>,
                 ctx: $crate::encoding::DecodeContext,
             ) -> Result<(), DecodeError> {
-                $crate::encoding::Encoder::<$to_ty>::decode(
+                $crate::encoding::Decoder::<$to_ty>::decode(
+                    wire_type,
+                    duplicated,
+                    value,
+                    buf,
+                    ctx,
+                )
+            }
+
+            #[inline]
+            fn apply_nested(
+                value: &mut $value_ty,
+                path: &[u32],
+                op: &$crate::patch::PatchOp,
+            ) -> Result<(), DecodeError> {
+                $crate::encoding::Decoder::<$to_ty>::apply_nested(value, path, op)
+            }
+
+            #[inline]
+            fn remove_key(value: &mut $value_ty, key: &[u8]) -> Result<(), DecodeError> {
+                $crate::encoding::Decoder::<$to_ty>::remove_key(value, key)
+            }
+        }
+
+        impl<'__a $(, $($value_generics)*)?> $crate::encoding::BorrowDecoder<'__a, $from_ty>
+        for $value_ty
+        where
+            Self: $crate::encoding::BorrowDecoder<'__a, $to_ty>,
+            $($($where_clause)*)?
+        {
+            #[inline]
+            fn borrow_decode(
+                wire_type: $crate::encoding::WireType,
+                duplicated: bool,
+                value: &mut $value_ty,
+                buf: $crate::encoding::Capped<&'__a [u8]>,
+                ctx: $crate::encoding::DecodeContext,
+            ) -> Result<(), DecodeError> {
+                $crate::encoding::BorrowDecoder::<$to_ty>::borrow_decode(
                     wire_type,
                     duplicated,
                     value,
@@ -1540,6 +2522,15 @@ macro_rules! delegate_encoding {
             Self: $crate::encoding::DistinguishedEncoder<$to_ty>
                 + $crate::encoding::Encoder<$to_ty>,
             $($($where_clause)*)?
+        {
+        }
+
+        impl$(<$($value_generics)*>)? $crate::encoding::DistinguishedDecoder<$from_ty>
+        for $value_ty
+        where
+            Self: $crate::encoding::DistinguishedDecoder<$to_ty>
+                + $crate::encoding::Decoder<$to_ty>,
+            $($($where_clause)*)?
         {
             #[inline]
             fn decode_distinguished<B: $crate::bytes::Buf + ?Sized>(
@@ -1549,7 +2540,33 @@ macro_rules! delegate_encoding {
                 buf: $crate::encoding::Capped<B>,
                 ctx: $crate::encoding::DecodeContext,
             ) -> Result<$crate::Canonicity, $crate::DecodeError> {
-                $crate::encoding::DistinguishedEncoder::<$to_ty>::decode_distinguished(
+                $crate::encoding::DistinguishedDecoder::<$to_ty>::decode_distinguished(
+                    wire_type,
+                    duplicated,
+                    value,
+                    buf,
+                    ctx,
+                )
+            }
+        }
+
+        impl<'__a $(, $($value_generics)*)?>
+        $crate::encoding::DistinguishedBorrowDecoder<'__a, $from_ty> for $value_ty
+        where
+            Self: $crate::encoding::DistinguishedBorrowDecoder<'__a, $to_ty>
+                + $crate::encoding::DistinguishedEncoder<$to_ty>
+                + $crate::encoding::Encoder<$to_ty>,
+            $($($where_clause)*)?
+        {
+            #[inline]
+            fn borrow_decode_distinguished(
+                wire_type: $crate::encoding::WireType,
+                duplicated: bool,
+                value: &mut $value_ty,
+                buf: $crate::encoding::Capped<&'__a [u8]>,
+                ctx: $crate::encoding::DecodeContext,
+            ) -> Result<$crate::Canonicity, $crate::DecodeError> {
+                $crate::encoding::DistinguishedBorrowDecoder::<$to_ty>::borrow_decode_distinguished(
                     wire_type,
                     duplicated,
                     value,
@@ -1583,11 +2600,19 @@ macro_rules! delegate_value_encoding {
             Self: $crate::encoding::ValueEncoder<$to_ty>,
             $($($where_clause)+ ,)?
         {
+            const VALUE_KIND: &'static $crate::descriptor::ValueKind =
+                <Self as $crate::encoding::ValueEncoder<$to_ty>>::VALUE_KIND;
+
             #[inline]
             fn encode_value<B: $crate::bytes::BufMut + ?Sized>(value: &$value_ty, buf: &mut B) {
                 $crate::encoding::ValueEncoder::<$to_ty>::encode_value(value, buf)
             }
 
+            #[inline]
+            fn prepend_value(value: &$value_ty, buf: &mut $crate::encoding::ReverseBuffer) {
+                $crate::encoding::ValueEncoder::<$to_ty>::prepend_value(value, buf)
+            }
+
             #[inline]
             fn value_encoded_len(value: &$value_ty) -> usize {
                 $crate::encoding::ValueEncoder::<$to_ty>::value_encoded_len(value)
@@ -1602,13 +2627,62 @@ macro_rules! delegate_value_encoding {
                 $crate::encoding::ValueEncoder::<$to_ty>::many_values_encoded_len(values)
             }
 
+            #[inline]
+            fn diff_value(
+                tag: u32,
+                old: &$value_ty,
+                new: &$value_ty,
+                pw: &mut $crate::patch::PatchWriter,
+            ) {
+                $crate::encoding::ValueEncoder::<$to_ty>::diff_value(tag, old, new, pw)
+            }
+        }
+
+        impl$(<$($value_generics)*>)? $crate::encoding::ValueDecoder<$from_ty> for $value_ty
+        where
+            Self: $crate::encoding::ValueDecoder<$to_ty>,
+            $($($where_clause)+ ,)?
+        {
             #[inline]
             fn decode_value<B: $crate::bytes::Buf + ?Sized>(
                 value: &mut $value_ty,
                 buf: $crate::encoding::Capped<B>,
                 ctx: $crate::encoding::DecodeContext,
             ) -> Result<(), $crate::DecodeError> {
-                $crate::encoding::ValueEncoder::<$to_ty>::decode_value(value, buf, ctx)
+                $crate::encoding::ValueDecoder::<$to_ty>::decode_value(value, buf, ctx)
+            }
+
+            #[inline]
+            fn apply_nested_value(
+                value: &mut $value_ty,
+                path: &[u32],
+                op: &$crate::patch::PatchOp,
+            ) -> Result<(), $crate::DecodeError> {
+                $crate::encoding::ValueDecoder::<$to_ty>::apply_nested_value(value, path, op)
+            }
+
+            #[inline]
+            fn remove_value_key(
+                value: &mut $value_ty,
+                key: &[u8],
+            ) -> Result<(), $crate::DecodeError> {
+                $crate::encoding::ValueDecoder::<$to_ty>::remove_value_key(value, key)
+            }
+        }
+
+        impl<'__a $(, $($value_generics)*)?> $crate::encoding::ValueBorrowDecoder<'__a, $from_ty>
+        for $value_ty
+        where
+            Self: $crate::encoding::ValueBorrowDecoder<'__a, $to_ty>,
+            $($($where_clause)+ ,)?
+        {
+            #[inline]
+            fn borrow_decode_value(
+                value: &mut $value_ty,
+                buf: $crate::encoding::Capped<&'__a [u8]>,
+                ctx: $crate::encoding::DecodeContext,
+            ) -> Result<(), $crate::DecodeError> {
+                $crate::encoding::ValueBorrowDecoder::<$to_ty>::borrow_decode_value(value, buf, ctx)
             }
         }
     };
@@ -1631,6 +2705,15 @@ macro_rules! delegate_value_encoding {
             Self: $crate::encoding::DistinguishedValueEncoder<$to_ty>,
             $($($expedient_where)+ ,)?
             $($($distinguished_where)+ ,)?
+        {
+        }
+
+        impl$(<$($value_generics)*>)? $crate::encoding::DistinguishedValueDecoder<$from_ty>
+        for $value_ty
+        where
+            Self: $crate::encoding::DistinguishedValueDecoder<$to_ty>,
+            $($($expedient_where)+ ,)?
+            $($($distinguished_where)+ ,)?
         {
             #[inline]
             fn decode_value_distinguished<B: Buf + ?Sized>(
@@ -1639,7 +2722,7 @@ macro_rules! delegate_value_encoding {
                 allow_empty: bool,
                 ctx: $crate::encoding::DecodeContext,
             ) -> Result<$crate::Canonicity, $crate::DecodeError> {
-                DistinguishedValueEncoder::<$to_ty>::decode_value_distinguished(
+                $crate::encoding::DistinguishedValueDecoder::<$to_ty>::decode_value_distinguished(
                     value,
                     buf,
                     allow_empty,
@@ -1647,6 +2730,25 @@ macro_rules! delegate_value_encoding {
                 )
             }
         }
+
+        impl<'__a $(, $($value_generics)*)?>
+        $crate::encoding::DistinguishedValueBorrowDecoder<'__a, $from_ty> for $value_ty
+        where
+            Self: $crate::encoding::DistinguishedValueBorrowDecoder<'__a, $to_ty>,
+            $($($expedient_where)+ ,)?
+            $($($distinguished_where)+ ,)?
+        {
+            #[inline]
+            fn borrow_decode_value_distinguished(
+                value: &mut $value_ty,
+                buf: $crate::encoding::Capped<&'__a [u8]>,
+                allow_empty: bool,
+                ctx: $crate::encoding::DecodeContext,
+            ) -> Result<$crate::Canonicity, $crate::DecodeError> {
+                $crate::encoding::DistinguishedValueBorrowDecoder::<$to_ty>
+                    ::borrow_decode_value_distinguished(value, buf, allow_empty, ctx)
+            }
+        }
     };
 }
 pub(crate) use delegate_value_encoding;
@@ -1666,6 +2768,9 @@ macro_rules! encoder_where_value_encoder {
             T: $crate::encoding::EmptyState + ValueEncoder<$encoding>,
             $($($where_clause)*)?
         {
+            const VALUE_KIND: &'static $crate::descriptor::ValueKind =
+                <T as ValueEncoder<$encoding>>::VALUE_KIND;
+
             #[inline]
             fn encode<B: BufMut + ?Sized>(tag: u32, value: &T, buf: &mut B, tw: &mut TagWriter) {
                 if !$crate::encoding::EmptyState::is_empty(value) {
@@ -1674,6 +2779,19 @@ macro_rules! encoder_where_value_encoder {
                 }
             }
 
+            #[inline]
+            fn prepend_encode(
+                tag: u32,
+                value: &T,
+                buf: &mut $crate::encoding::ReverseBuffer,
+                tw: &mut $crate::encoding::TagRevWriter,
+            ) {
+                if !$crate::encoding::EmptyState::is_empty(value) {
+                    $crate::encoding::FieldEncoder::<$encoding>::prepend_field(
+                        tag, value, buf, tw);
+                }
+            }
+
             #[inline]
             fn encoded_len(tag: u32, value: &T, tm: &mut TagMeasurer) -> usize {
                 if !$crate::encoding::EmptyState::is_empty(value) {
@@ -1684,6 +2802,21 @@ macro_rules! encoder_where_value_encoder {
                 }
             }
 
+            #[inline]
+            fn diff_field(tag: u32, old: &T, new: &T, pw: &mut $crate::patch::PatchWriter) {
+                pw.diff_present::<T, $encoding>(
+                    tag,
+                    (!$crate::encoding::EmptyState::is_empty(old)).then_some(old),
+                    (!$crate::encoding::EmptyState::is_empty(new)).then_some(new),
+                );
+            }
+        }
+
+        impl<T $(, $($generics)*)?> $crate::encoding::Decoder<$encoding> for T
+        where
+            T: $crate::encoding::EmptyState + $crate::encoding::ValueDecoder<$encoding>,
+            $($($where_clause)*)?
+        {
             #[inline]
             fn decode<B: Buf + ?Sized>(
                 wire_type: WireType,
@@ -1697,13 +2830,25 @@ macro_rules! encoder_where_value_encoder {
                         $crate::DecodeError::new($crate::DecodeErrorKind::UnexpectedlyRepeated)
                     );
                 }
-                $crate::encoding::FieldEncoder::<$encoding>::decode_field(
+                $crate::encoding::FieldDecoder::<$encoding>::decode_field(
                     wire_type, value, buf, ctx)
             }
+
+            #[inline]
+            fn apply_nested(
+                value: &mut T,
+                path: &[u32],
+                op: &$crate::patch::PatchOp,
+            ) -> Result<(), $crate::DecodeError> {
+                $crate::encoding::ValueDecoder::<$encoding>::apply_nested_value(value, path, op)
+            }
+
+            #[inline]
+            fn remove_key(value: &mut T, key: &[u8]) -> Result<(), $crate::DecodeError> {
+                $crate::encoding::ValueDecoder::<$encoding>::remove_value_key(value, key)
+            }
         }
 
-        /// Distinguished encoding for plain values forbids encoding defaulted values. This includes
-        /// directly-nested message types, which are not emitted when all their fields are default.
         impl<T $(, $($generics)*)?> $crate::encoding::DistinguishedEncoder<$encoding> for T
         where
             T: Eq
@@ -1711,6 +2856,18 @@ macro_rules! encoder_where_value_encoder {
                 + $crate::encoding::DistinguishedValueEncoder<$encoding>
                 + $crate::encoding::Encoder<$encoding>,
             $($($where_clause)*)?
+        {
+        }
+
+        /// Distinguished encoding for plain values forbids encoding defaulted values. This includes
+        /// directly-nested message types, which are not emitted when all their fields are default.
+        impl<T $(, $($generics)*)?> $crate::encoding::DistinguishedDecoder<$encoding> for T
+        where
+            T: Eq
+                + $crate::encoding::EmptyState
+                + $crate::encoding::DistinguishedValueDecoder<$encoding>
+                + $crate::encoding::Decoder<$encoding>,
+            $($($where_clause)*)?
         {
             #[inline]
             fn decode_distinguished<B: Buf + ?Sized>(
@@ -1722,10 +2879,10 @@ macro_rules! encoder_where_value_encoder {
             ) -> Result<$crate::Canonicity, $crate::DecodeError> {
                 if duplicated {
                     return Err(
-                        $crate::DecodeError::new(crate::DecodeErrorKind::UnexpectedlyRepeated)
+                        $crate::DecodeError::new($crate::DecodeErrorKind::UnexpectedlyRepeated)
                     );
                 }
-                $crate::encoding::DistinguishedFieldEncoder::<$encoding>
+                $crate::encoding::DistinguishedFieldDecoder::<$encoding>
                     ::decode_field_distinguished(
                         wire_type,
                         value,
@@ -1735,10 +2892,124 @@ macro_rules! encoder_where_value_encoder {
                     )
             }
         }
+
+        impl<'__a, T $(, $($generics)*)?> $crate::encoding::BorrowDecoder<'__a, $encoding> for T
+        where
+            T: $crate::encoding::EmptyState
+                + $crate::encoding::ValueBorrowDecoder<'__a, $encoding>,
+            $($($where_clause)*)?
+        {
+            #[inline]
+            fn borrow_decode(
+                wire_type: WireType,
+                duplicated: bool,
+                value: &mut T,
+                buf: Capped<&'__a [u8]>,
+                ctx: DecodeContext,
+            ) -> Result<(), $crate::DecodeError> {
+                if duplicated {
+                    return Err(
+                        $crate::DecodeError::new($crate::DecodeErrorKind::UnexpectedlyRepeated)
+                    );
+                }
+                $crate::encoding::FieldBorrowDecoder::<$encoding>::borrow_decode_field(
+                    wire_type,
+                    value,
+                    buf,
+                    ctx,
+                )
+            }
+        }
+
+        impl<'__a, T $(, $($generics)*)?>
+        $crate::encoding::DistinguishedBorrowDecoder<'__a, $encoding> for T
+        where
+            T: Eq
+                + $crate::encoding::EmptyState
+                + $crate::encoding::DistinguishedValueBorrowDecoder<'__a, $encoding>
+                + $crate::encoding::Encoder<$encoding>,
+            $($($where_clause)*)?
+        {
+            #[inline]
+            fn borrow_decode_distinguished(
+                wire_type: WireType,
+                duplicated: bool,
+                value: &mut T,
+                buf: Capped<&'__a [u8]>,
+                ctx: DecodeContext,
+            ) -> Result<$crate::Canonicity, $crate::DecodeError> {
+                if duplicated {
+                    return Err(
+                        $crate::DecodeError::new($crate::DecodeErrorKind::UnexpectedlyRepeated)
+                    );
+                }
+                $crate::encoding::DistinguishedFieldBorrowDecoder::<$encoding>
+                    ::borrow_decode_field_distinguished(
+                        wire_type,
+                        value,
+                        buf,
+                        false, // decoding a bare value, empty values are unacceptable
+                        ctx,
+                    )
+            }
+        }
     };
 }
 pub(crate) use encoder_where_value_encoder;
 
+/// Implements the borrowed decoding traits for a value type that holds all of its own data, and so
+/// decodes exactly the same way whether or not it is decoded from borrowed input.
+macro_rules! borrow_decoding_via_owned {
+    (
+        $encoding:ty, $ty:ty
+        $(, with generics ($($generics:tt)*))?
+    ) => {
+        impl<'__a $(, $($generics)*)?> $crate::encoding::ValueBorrowDecoder<'__a, $encoding>
+        for $ty
+        where
+            Self: $crate::encoding::ValueDecoder<$encoding>,
+        {
+            #[inline]
+            fn borrow_decode_value(
+                value: &mut Self,
+                buf: $crate::encoding::Capped<&'__a [u8]>,
+                ctx: $crate::encoding::DecodeContext,
+            ) -> Result<(), $crate::DecodeError> {
+                $crate::encoding::ValueDecoder::<$encoding>::decode_value(value, buf, ctx)
+            }
+        }
+    };
+    (
+        $encoding:ty, $ty:ty
+        $(, with generics ($($generics:tt)*))?
+        , including distinguished
+    ) => {
+        borrow_decoding_via_owned!($encoding, $ty $(, with generics ($($generics)*))?);
+
+        impl<'__a $(, $($generics)*)?>
+        $crate::encoding::DistinguishedValueBorrowDecoder<'__a, $encoding> for $ty
+        where
+            Self: $crate::encoding::DistinguishedValueDecoder<$encoding> + Eq,
+        {
+            #[inline]
+            fn borrow_decode_value_distinguished(
+                value: &mut Self,
+                buf: $crate::encoding::Capped<&'__a [u8]>,
+                allow_empty: bool,
+                ctx: $crate::encoding::DecodeContext,
+            ) -> Result<$crate::Canonicity, $crate::DecodeError> {
+                $crate::encoding::DistinguishedValueDecoder::<$encoding>::decode_value_distinguished(
+                    value,
+                    buf,
+                    allow_empty,
+                    ctx,
+                )
+            }
+        }
+    };
+}
+pub(crate) use borrow_decoding_via_owned;
+
 /// Implements `EmptyState` in terms of `Default`.
 macro_rules! empty_state_via_default {
     (
@@ -1867,6 +3138,16 @@ mod test {
                     let mut buf = BytesMut::with_capacity(expected_len);
                     <T as Encoder<E>>::encode(tag, &value, &mut buf, &mut TagWriter::new());
 
+                    let mut prepended = ReverseBuffer::new();
+                    let mut tw = TagRevWriter::new();
+                    <T as Encoder<E>>::prepend_encode(tag, &value, &mut prepended, &mut tw);
+                    tw.finalize(&mut prepended);
+                    prop_assert_eq!(
+                        prepended.as_slice(),
+                        buf.as_ref(),
+                        "prepended encoding differs"
+                    );
+
                     let buf = &mut buf.freeze();
                     let mut buf = Capped::new(buf);
                     let mut tr = TagReader::new();
@@ -1944,6 +3225,16 @@ mod test {
                     let mut buf = BytesMut::with_capacity(expected_len);
                     <T as Encoder<E>>::encode(tag, value.borrow(), &mut buf, &mut TagWriter::new());
 
+                    let mut prepended = ReverseBuffer::new();
+                    let mut tw = TagRevWriter::new();
+                    <T as Encoder<E>>::prepend_encode(tag, value.borrow(), &mut prepended, &mut tw);
+                    tw.finalize(&mut prepended);
+                    prop_assert_eq!(
+                        prepended.as_slice(),
+                        buf.as_ref(),
+                        "prepended encoding differs"
+                    );
+
                     let mut tr = TagReader::new();
                     let buf = &mut buf.freeze();
                     let mut buf = Capped::new(buf);
@@ -1998,13 +3289,13 @@ mod test {
             }
         };
     }
-    check_type!(expedient, Encoder, decode);
-    check_type!(distinguished, DistinguishedEncoder, decode_distinguished,
+    check_type!(expedient, Decoder, decode);
+    check_type!(distinguished, DistinguishedDecoder, decode_distinguished,
         enforce with canonical);
 
     fn present_empty_not_canon<T, E>()
     where
-        T: EmptyState + Eq + DistinguishedEncoder<E> + ValueEncoder<E>,
+        T: EmptyState + Eq + DistinguishedDecoder<E> + ValueEncoder<E>,
     {
         let mut encoded = <Vec<u8>>::new();
         Encoder::<E>::encode(123, &Some(T::empty()), &mut encoded, &mut TagWriter::new());
@@ -2014,7 +3305,7 @@ mod test {
         assert_eq!(tag, 123);
         let mut decoded = T::new_for_overwrite();
         assert_eq!(
-            DistinguishedEncoder::<E>::decode_distinguished(
+            DistinguishedDecoder::<E>::decode_distinguished(
                 wire_type,
                 false,
                 &mut decoded,
@@ -2108,7 +3399,7 @@ mod test {
         buf.extend([1; 12]);
 
         let mut parsed = Vec::<u64>::new();
-        let res = ValueEncoder::<Packed<Fixed>>::decode_value(
+        let res = ValueDecoder::<Packed<Fixed>>::decode_value(
             &mut parsed,
             Capped::new(&mut buf.as_slice()),
             DecodeContext::default(),
@@ -2118,7 +3409,7 @@ mod test {
                 .kind(),
             Truncated
         );
-        let res = DistinguishedValueEncoder::<Packed<Fixed>>::decode_value_distinguished(
+        let res = DistinguishedValueDecoder::<Packed<Fixed>>::decode_value_distinguished(
             &mut parsed,
             Capped::new(&mut buf.as_slice()),
             true,
@@ -2139,7 +3430,7 @@ mod test {
         buf.extend([1; 17]);
 
         let mut parsed = Vec::<u32>::new();
-        let res = ValueEncoder::<Packed<Fixed>>::decode_value(
+        let res = ValueDecoder::<Packed<Fixed>>::decode_value(
             &mut parsed,
             Capped::new(&mut buf.as_slice()),
             DecodeContext::default(),
@@ -2149,7 +3440,7 @@ mod test {
                 .kind(),
             Truncated
         );
-        let res = DistinguishedValueEncoder::<Packed<Fixed>>::decode_value_distinguished(
+        let res = DistinguishedValueDecoder::<Packed<Fixed>>::decode_value_distinguished(
             &mut parsed,
             Capped::new(&mut buf.as_slice()),
             true,
@@ -2173,7 +3464,7 @@ mod test {
 
         // The entries for this map always consume 12 bytes each.
         let mut parsed = BTreeMap::<u32, u64>::new();
-        let res = ValueEncoder::<Map<Fixed, Fixed>>::decode_value(
+        let res = ValueDecoder::<Map<Fixed, Fixed>>::decode_value(
             &mut parsed,
             Capped::new(&mut buf.as_slice()),
             DecodeContext::default(),
@@ -2183,7 +3474,7 @@ mod test {
                 .kind(),
             Truncated
         );
-        let res = DistinguishedValueEncoder::<Map<Fixed, Fixed>>::decode_value_distinguished(
+        let res = DistinguishedValueDecoder::<Map<Fixed, Fixed>>::decode_value_distinguished(
             &mut parsed,
             Capped::new(&mut buf.as_slice()),
             true,
@@ -2201,7 +3492,7 @@ mod test {
         let mut s = String::new();
         let buf = b"\x02\x80\x80";
 
-        let r = ValueEncoder::<General>::decode_value(
+        let r = ValueDecoder::<General>::decode_value(
             &mut s,
             Capped::new(&mut buf.as_slice()),
             DecodeContext::default(),
@@ -2437,10 +3728,10 @@ mod test {
         );
     }
 
-    fn check_rejects_wrong_wire_type<T: NewForOverwrite + Encoder<E>, E>(wire_type: WireType) {
+    fn check_rejects_wrong_wire_type<T: NewForOverwrite + Decoder<E>, E>(wire_type: WireType) {
         let mut out = T::new_for_overwrite();
         assert_eq!(
-            <T as Encoder<E>>::decode(
+            <T as Decoder<E>>::decode(
                 wire_type,
                 false,
                 &mut out,
@@ -2452,14 +3743,14 @@ mod test {
     }
 
     fn check_rejects_wrong_wire_type_distinguished<
-        T: NewForOverwrite + DistinguishedEncoder<E>,
+        T: NewForOverwrite + DistinguishedDecoder<E>,
         E,
     >(
         wire_type: WireType,
     ) {
         let mut out = T::new_for_overwrite();
         assert_eq!(
-            <T as DistinguishedEncoder<E>>::decode_distinguished(
+            <T as DistinguishedDecoder<E>>::decode_distinguished(
                 wire_type,
                 false,
                 &mut out,
@@ -2525,7 +3816,7 @@ mod test {
             let mut buf = Vec::<u8>::new();
             ValueEncoder::<General>::encode_value(&value, &mut buf);
             let mut out = 0u64;
-            prop_assert!(ValueEncoder::<General>::decode_value(
+            prop_assert!(ValueDecoder::<General>::decode_value(
                 &mut out,
                 Capped::new(&mut &*buf),
                 DecodeContext::default(),
@@ -2538,7 +3829,7 @@ mod test {
             let mut buf = Vec::<u8>::new();
             ValueEncoder::<General>::encode_value(&value, &mut buf);
             let mut out = 0i64;
-            prop_assert!(ValueEncoder::<General>::decode_value(
+            prop_assert!(ValueDecoder::<General>::decode_value(
                 &mut out,
                 Capped::new(&mut &*buf),
                 DecodeContext::default(),
@@ -2552,7 +3843,7 @@ mod test {
             let mut buf = Vec::<u8>::new();
             ValueEncoder::<General>::encode_value(&value, &mut buf);
             let mut out = 0u32;
-            prop_assert!(ValueEncoder::<General>::decode_value(
+            prop_assert!(ValueDecoder::<General>::decode_value(
                 &mut out,
                 Capped::new(&mut &*buf),
                 DecodeContext::default(),
@@ -2566,7 +3857,7 @@ mod test {
             let mut buf = Vec::<u8>::new();
             ValueEncoder::<General>::encode_value(&value, &mut buf);
             let mut out = 0i32;
-            prop_assert!(ValueEncoder::<General>::decode_value(
+            prop_assert!(ValueDecoder::<General>::decode_value(
                 &mut out,
                 Capped::new(&mut &*buf),
                 DecodeContext::default(),
@@ -2580,7 +3871,7 @@ mod test {
             ValueEncoder::<General>::encode_value(&value, &mut buf);
             let mut out = 0u32;
             prop_assert_eq!(
-                ValueEncoder::<General>::decode_value(
+                ValueDecoder::<General>::decode_value(
                     &mut out,
                     Capped::new(&mut &*buf),
                     DecodeContext::default(),
@@ -2599,7 +3890,7 @@ mod test {
                 ValueEncoder::<General>::encode_value(&value, &mut buf);
                 let mut out = 0i32;
                 prop_assert_eq!(
-                    ValueEncoder::<General>::decode_value(
+                    ValueDecoder::<General>::decode_value(
                         &mut out,
                         Capped::new(&mut &*buf),
                         DecodeContext::default(),
@@ -2615,7 +3906,7 @@ mod test {
             ValueEncoder::<General>::encode_value(&value, &mut buf);
             let mut out = 0u16;
             prop_assert_eq!(
-                ValueEncoder::<General>::decode_value(
+                ValueDecoder::<General>::decode_value(
                     &mut out,
                     Capped::new(&mut &*buf),
                     DecodeContext::default(),
@@ -2634,7 +3925,7 @@ mod test {
                 ValueEncoder::<General>::encode_value(&value, &mut buf);
                 let mut out = 0i16;
                 prop_assert_eq!(
-                    ValueEncoder::<General>::decode_value(
+                    ValueDecoder::<General>::decode_value(
                         &mut out,
                         Capped::new(&mut &*buf),
                         DecodeContext::default(),
@@ -2650,7 +3941,7 @@ mod test {
             ValueEncoder::<Varint>::encode_value(&value, &mut buf);
             let mut out = 0u8;
             prop_assert_eq!(
-                ValueEncoder::<Varint>::decode_value(
+                ValueDecoder::<Varint>::decode_value(
                     &mut out,
                     Capped::new(&mut &*buf),
                     DecodeContext::default(),
@@ -2669,7 +3960,7 @@ mod test {
                 ValueEncoder::<Varint>::encode_value(&value, &mut buf);
                 let mut out = 0i8;
                 prop_assert_eq!(
-                    ValueEncoder::<Varint>::decode_value(
+                    ValueDecoder::<Varint>::decode_value(
                         &mut out,
                         Capped::new(&mut &*buf),
                         DecodeContext::default(),
@@ -2685,7 +3976,7 @@ mod test {
             encode_varint(varint, &mut buf);
             let mut out = false;
             prop_assert_eq!(
-                ValueEncoder::<General>::decode_value(
+                ValueDecoder::<General>::decode_value(
                     &mut out,
                     Capped::new(&mut &*buf),
                     DecodeContext::default(),
@@ -2704,4 +3995,76 @@ mod test {
             );
         }
     }
+
+    #[test]
+    fn default_prepend_matches_encode() {
+        // Hand-written encoders that only know how to encode forward still prepend correctly
+        struct Forward;
+
+        #[derive(Debug, PartialEq)]
+        struct Repeated(Vec<u32>);
+
+        impl Encoder<Forward> for Repeated {
+            fn encode<B: BufMut + ?Sized>(tag: u32, value: &Self, buf: &mut B, tw: &mut TagWriter) {
+                for &n in &value.0 {
+                    tw.encode_key(tag, WireType::Varint, buf);
+                    encode_varint(n as u64, buf);
+                }
+            }
+
+            fn encoded_len(tag: u32, value: &Self, tm: &mut TagMeasurer) -> usize {
+                value
+                    .0
+                    .iter()
+                    .map(|&n| tm.key_len(tag) + encoded_len_varint(n as u64))
+                    .sum()
+            }
+        }
+
+        impl Wiretyped<Forward> for u32 {
+            const WIRE_TYPE: WireType = WireType::Varint;
+        }
+
+        impl ValueEncoder<Forward> for u32 {
+            fn encode_value<B: BufMut + ?Sized>(value: &Self, buf: &mut B) {
+                encode_varint(*value as u64 + 1, buf);
+            }
+
+            fn value_encoded_len(value: &Self) -> usize {
+                encoded_len_varint(*value as u64 + 1)
+            }
+        }
+
+        for repeated in [&[][..], &[5], &[1, 300, 70000]] {
+            let repeated = Repeated(repeated.to_vec());
+            let mut forward = Vec::new();
+            let tw = &mut TagWriter::new();
+            Encoder::<General>::encode(1, &10u32, &mut forward, tw);
+            FieldEncoder::<Forward>::encode_field(2, &20u32, &mut forward, tw);
+            Encoder::<Forward>::encode(5, &repeated, &mut forward, tw);
+            Encoder::<General>::encode(9, &90u32, &mut forward, tw);
+
+            let mut prepended = ReverseBuffer::new();
+            let tw = &mut TagRevWriter::new();
+            Encoder::<General>::prepend_encode(9, &90u32, &mut prepended, tw);
+            Encoder::<Forward>::prepend_encode(5, &repeated, &mut prepended, tw);
+            FieldEncoder::<Forward>::prepend_field(2, &20u32, &mut prepended, tw);
+            Encoder::<General>::prepend_encode(1, &10u32, &mut prepended, tw);
+            tw.finalize(&mut prepended);
+
+            assert_eq!(prepended.as_slice(), forward.as_slice(), "{repeated:?}");
+        }
+    }
+
+    #[test]
+    fn default_decode_context_is_plain_data() {
+        fn assert_copy_send_sync<T: Copy + Send + Sync>() {}
+        assert_copy_send_sync::<DecodeContext>();
+        // Without options the context is just the recursion count, the merging flag, and two null
+        // pointers; nothing is allocated, reference-counted, or dropped.
+        assert!(!mem::needs_drop::<DecodeContext>());
+        assert!(mem::size_of::<DecodeContext>() <= 3 * mem::size_of::<usize>());
+        let ctx = DecodeContext::default();
+        assert!(ctx.limits.is_none() && ctx.filter.is_none());
+    }
 }
@@ -1,15 +1,22 @@
 use alloc::borrow::{Cow, ToOwned};
 use alloc::string::String;
 use alloc::vec::Vec;
+use core::fmt::{self, Write};
 use core::mem;
-use core::ops::{Deref, DerefMut};
+use core::ops::{Bound, Deref, DerefMut};
+use core::str::FromStr;
 
 use btreemultimap::BTreeMultiMap;
 use bytes::{Buf, BufMut};
 
+use crate::descriptor::MessageDescriptor;
 use crate::encoding::{
-    encode_varint, encoded_len_varint, Capped, DecodeContext, EmptyState, TagMeasurer, TagWriter,
-    WireType,
+    encode_varint, encoded_len_varint, prepend_varint, Capped, DecodeContext, EmptyState,
+    ReverseBuffer, TagMeasurer, TagRevWriter, TagWriter, WireType,
+};
+use crate::message::{
+    MessageExtension, RawDistinguishedMessageBorrowDecoder, RawDistinguishedMessageDecoder,
+    RawMessageBorrowDecoder, RawMessageDecoder,
 };
 use crate::DecodeErrorKind::Truncated;
 use crate::{Canonicity, DecodeError, Message, RawDistinguishedMessage, RawMessage};
@@ -78,6 +85,23 @@ impl<'a> OpaqueValue<'a> {
         ThirtyTwoBit(value.to_le_bytes())
     }
 
+    pub fn u128(value: u128) -> Self {
+        let (bytes, len) = super::general::trimmed_u128(value);
+        LengthDelimited(Cow::Owned(bytes[..len].to_vec()))
+    }
+
+    pub fn i128(value: i128) -> Self {
+        Self::u128(super::varint::i128_to_unsigned(value))
+    }
+
+    pub fn fixed_u128(value: u128) -> Self {
+        LengthDelimited(Cow::Owned(value.to_le_bytes().to_vec()))
+    }
+
+    pub fn fixed_i128(value: i128) -> Self {
+        LengthDelimited(Cow::Owned(value.to_le_bytes().to_vec()))
+    }
+
     pub fn f64(value: f64) -> Self {
         SixtyFourBit(value.to_le_bytes())
     }
@@ -146,6 +170,29 @@ impl<'a> OpaqueValue<'a> {
         self.encode_value(buf);
     }
 
+    fn prepend_value(&self, buf: &mut ReverseBuffer) {
+        match self {
+            Varint(val) => {
+                prepend_varint(*val, buf);
+            }
+            LengthDelimited(val) => {
+                buf.prepend_slice(val);
+                prepend_varint(val.len() as u64, buf);
+            }
+            ThirtyTwoBit(val) => {
+                buf.prepend_slice(val.as_slice());
+            }
+            SixtyFourBit(val) => {
+                buf.prepend_slice(val.as_slice());
+            }
+        }
+    }
+
+    fn prepend_field(&self, tag: u32, buf: &mut ReverseBuffer, tw: &mut TagRevWriter) {
+        tw.begin_field(tag, self.wire_type(), buf);
+        self.prepend_value(buf);
+    }
+
     fn value_encoded_len(&self) -> usize {
         match self {
             Varint(val) => encoded_len_varint(*val),
@@ -158,12 +205,15 @@ impl<'a> OpaqueValue<'a> {
     fn decode_value<B: Buf + ?Sized>(
         wire_type: WireType,
         mut buf: Capped<B>,
+        ctx: &DecodeContext,
     ) -> Result<Self, DecodeError> {
         Ok(match wire_type {
             WireType::Varint => Varint(buf.decode_varint()?),
             WireType::LengthDelimited => {
+                let delimited = buf.take_length_delimited()?;
+                ctx.check_bytes_alloc(delimited.remaining_before_cap())?;
                 let mut val = Vec::new();
-                val.put(buf.take_length_delimited()?.take_all());
+                val.put(delimited.take_all());
                 LengthDelimited(Cow::Owned(val))
             }
             WireType::ThirtyTwoBit => {
@@ -185,6 +235,21 @@ impl<'a> OpaqueValue<'a> {
         })
     }
 
+    fn borrow_decode_value(
+        wire_type: WireType,
+        mut buf: Capped<&'a [u8]>,
+        ctx: &DecodeContext,
+    ) -> Result<Self, DecodeError> {
+        Ok(match wire_type {
+            WireType::LengthDelimited => {
+                let bytes = buf.take_borrowed_length_delimited()?;
+                ctx.check_bytes_len(bytes.len())?;
+                LengthDelimited(Cow::Borrowed(bytes))
+            }
+            _ => Self::decode_value(wire_type, buf, ctx)?,
+        })
+    }
+
     /// Get a copy of this value with borrowed or re-borrowed data.
     pub fn borrow(&self) -> OpaqueValue {
         match self {
@@ -331,6 +396,14 @@ impl EmptyState for OpaqueMessage<'_> {
 
 impl RawMessage for OpaqueMessage<'_> {
     const __ASSERTIONS: () = ();
+    /// Opaque messages have no known fields; every field they contain is unknown.
+    const DESCRIPTOR: &'static MessageDescriptor = &MessageDescriptor {
+        name: "OpaqueMessage",
+        fields: &[],
+        oneofs: &[],
+        reserved_tags: &[],
+        has_extensions: true,
+    };
 
     fn raw_encode<B: BufMut + ?Sized>(&self, buf: &mut B) {
         let mut tw = TagWriter::new();
@@ -339,30 +412,43 @@ impl RawMessage for OpaqueMessage<'_> {
         }
     }
 
+    fn raw_prepend(&self, buf: &mut ReverseBuffer) {
+        let mut tw = TagRevWriter::new();
+        for (tag, value) in self.iter().rev() {
+            value.prepend_field(*tag, buf, &mut tw);
+        }
+        tw.finalize(buf);
+    }
+
     fn raw_encoded_len(&self) -> usize {
         let mut tm = TagMeasurer::new();
         self.iter()
             .map(|(tag, value)| tm.key_len(*tag) + value.value_encoded_len())
             .sum()
     }
+}
 
+impl RawMessageDecoder for OpaqueMessage<'_> {
     fn raw_decode_field<B: Buf + ?Sized>(
         &mut self,
         tag: u32,
         wire_type: WireType,
         _duplicated: bool,
         buf: Capped<B>,
-        _ctx: DecodeContext,
+        ctx: DecodeContext,
     ) -> Result<(), DecodeError>
     where
         Self: Sized,
     {
-        self.insert(tag, OpaqueValue::decode_value(wire_type, buf)?);
+        ctx.charge_allocation(mem::size_of::<(u32, OpaqueValue)>())?;
+        self.insert(tag, OpaqueValue::decode_value(wire_type, buf, &ctx)?);
         Ok(())
     }
 }
 
-impl RawDistinguishedMessage for OpaqueMessage<'_> {
+impl RawDistinguishedMessage for OpaqueMessage<'_> {}
+
+impl RawDistinguishedMessageDecoder for OpaqueMessage<'_> {
     fn raw_decode_field_distinguished<B: Buf + ?Sized>(
         &mut self,
         tag: u32,
@@ -378,3 +464,426 @@ impl RawDistinguishedMessage for OpaqueMessage<'_> {
         Ok(Canonicity::Canonical)
     }
 }
+
+impl<'a: 'b, 'b> RawMessageBorrowDecoder<'a> for OpaqueMessage<'b> {
+    fn raw_borrow_decode_field(
+        &mut self,
+        tag: u32,
+        wire_type: WireType,
+        _duplicated: bool,
+        buf: Capped<&'a [u8]>,
+        ctx: DecodeContext,
+    ) -> Result<(), DecodeError>
+    where
+        Self: Sized,
+    {
+        ctx.charge_allocation(mem::size_of::<(u32, OpaqueValue)>())?;
+        self.insert(tag, OpaqueValue::borrow_decode_value(wire_type, buf, &ctx)?);
+        Ok(())
+    }
+}
+
+impl<'a: 'b, 'b> RawDistinguishedMessageBorrowDecoder<'a> for OpaqueMessage<'b> {
+    fn raw_borrow_decode_field_distinguished(
+        &mut self,
+        tag: u32,
+        wire_type: WireType,
+        duplicated: bool,
+        buf: Capped<&'a [u8]>,
+        ctx: DecodeContext,
+    ) -> Result<Canonicity, DecodeError>
+    where
+        Self: Sized,
+    {
+        self.raw_borrow_decode_field(tag, wire_type, duplicated, buf, ctx)?;
+        Ok(Canonicity::Canonical)
+    }
+}
+
+impl MessageExtension for OpaqueMessage<'_> {
+    fn raw_encode_tag_range<B: BufMut + ?Sized>(
+        &self,
+        range: (Bound<u32>, Bound<u32>),
+        buf: &mut B,
+        tw: &mut TagWriter,
+    ) {
+        for (tag, value) in self.range(range) {
+            value.encode_field(*tag, buf, tw);
+        }
+    }
+
+    fn raw_prepend_tag_range(
+        &self,
+        range: (Bound<u32>, Bound<u32>),
+        buf: &mut ReverseBuffer,
+        tw: &mut TagRevWriter,
+    ) {
+        for (tag, value) in self.range(range).rev() {
+            value.prepend_field(*tag, buf, tw);
+        }
+    }
+
+    fn raw_tag_range_encoded_len(
+        &self,
+        range: (Bound<u32>, Bound<u32>),
+        tm: &mut TagMeasurer,
+    ) -> usize {
+        self.range(range)
+            .map(|(tag, value)| tm.key_len(*tag) + value.value_encoded_len())
+            .sum()
+    }
+}
+
+/// Nesting depth beyond which length-delimited values are always shown as bytes, and past which
+/// nested messages are not parsed, so that the text format never overflows the stack.
+const TEXT_NESTING_LIMIT: usize = 100;
+
+/// Opaque messages are shown as their fields in order, separated by two spaces, like
+/// `1: 42  2: "foo"  3: {1: 0x0a0b}  4: f32(1.5)`. Varints are shown as decimal numbers.
+/// Length-delimited values are shown as strings if they are printable UTF-8, as nested messages in
+/// braces if they decode as a non-empty message, and as hexadecimal bytes otherwise. Fixed-size
+/// values are shown as `f32(...)` or `f64(...)` when they look like ordinary floating point
+/// numbers, and as little-endian integers like `fixed32(10)` or `fixed64(10)` otherwise.
+///
+/// This text can be parsed back into the exact same message with `FromStr`.
+impl fmt::Display for OpaqueMessage<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_text_fields(self, f, 0)
+    }
+}
+
+/// Opaque values are shown the same way as in the text format of `OpaqueMessage`.
+impl fmt::Display for OpaqueValue<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_text_value(self, f, 0)
+    }
+}
+
+fn write_text_fields(
+    message: &OpaqueMessage,
+    f: &mut fmt::Formatter<'_>,
+    depth: usize,
+) -> fmt::Result {
+    for (i, (tag, value)) in message.iter().enumerate() {
+        if i > 0 {
+            f.write_str("  ")?;
+        }
+        write!(f, "{tag}: ")?;
+        write_text_value(value, f, depth)?;
+    }
+    Ok(())
+}
+
+fn write_text_value(value: &OpaqueValue, f: &mut fmt::Formatter<'_>, depth: usize) -> fmt::Result {
+    match value {
+        Varint(value) => write!(f, "{value}"),
+        ThirtyTwoBit(bytes) => {
+            let float = f32::from_le_bytes(*bytes);
+            if float.is_normal() && (1e-6..1e15).contains(&float.abs()) {
+                write!(f, "f32({float:?})")
+            } else {
+                write!(f, "fixed32({})", u32::from_le_bytes(*bytes))
+            }
+        }
+        SixtyFourBit(bytes) => {
+            let float = f64::from_le_bytes(*bytes);
+            if float.is_normal() && (1e-6..1e15).contains(&float.abs()) {
+                write!(f, "f64({float:?})")
+            } else {
+                write!(f, "fixed64({})", u64::from_le_bytes(*bytes))
+            }
+        }
+        LengthDelimited(bytes) => {
+            if let Ok(text) = core::str::from_utf8(bytes) {
+                if text
+                    .chars()
+                    .all(|c| !c.is_control() || matches!(c, '\n' | '\r' | '\t'))
+                {
+                    return write_text_string(text, f);
+                }
+            }
+            if depth < TEXT_NESTING_LIMIT {
+                if let Ok(nested) = OpaqueMessage::decode_borrowed(bytes) {
+                    if !nested.is_empty() {
+                        f.write_str("{")?;
+                        write_text_fields(&nested, f, depth + 1)?;
+                        return f.write_str("}");
+                    }
+                }
+            }
+            f.write_str("0x")?;
+            for byte in bytes.iter() {
+                write!(f, "{byte:02x}")?;
+            }
+            Ok(())
+        }
+    }
+}
+
+fn write_text_string(text: &str, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str("\"")?;
+    for c in text.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            c => f.write_char(c)?,
+        }
+    }
+    f.write_str("\"")
+}
+
+/// Error returned when the text format of an `OpaqueMessage` can't be parsed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseError {
+    position: usize,
+    reason: &'static str,
+}
+
+impl ParseError {
+    /// Returns the byte offset in the text where the error was found.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid opaque message text at byte {}: {}",
+            self.position, self.reason
+        )
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ParseError {}
+
+/// Parses the text format shown by `Display`, rebuilding the exact same message.
+///
+/// Besides what `Display` produces, fields may be separated by any whitespace, strings may contain
+/// the escapes `\0` and `\u{...}`, `fixed32(...)` and `fixed64(...)` also accept hexadecimal
+/// numbers like `0xff`, and floating point values may be written any way Rust parses them,
+/// including `f32(NaN)` and `f64(-inf)`. The parsed values are always owned.
+impl FromStr for OpaqueMessage<'_> {
+    type Err = ParseError;
+
+    fn from_str(text: &str) -> Result<Self, ParseError> {
+        TextParser { text, pos: 0 }.fields(0, false)
+    }
+}
+
+struct TextParser<'s> {
+    text: &'s str,
+    pos: usize,
+}
+
+impl TextParser<'_> {
+    fn error(&self, reason: &'static str) -> ParseError {
+        ParseError {
+            position: self.pos,
+            reason,
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.text.as_bytes().get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().map_or(false, |b| b.is_ascii_whitespace()) {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, byte: u8, reason: &'static str) -> Result<(), ParseError> {
+        if self.peek() == Some(byte) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.error(reason))
+        }
+    }
+
+    /// Takes the longest run of bytes at the current position that satisfy the predicate.
+    fn take_while(&mut self, predicate: impl Fn(u8) -> bool) -> &str {
+        let start = self.pos;
+        while self.peek().map_or(false, &predicate) {
+            self.pos += 1;
+        }
+        &self.text[start..self.pos]
+    }
+
+    /// Parses fields until the end of the text, or until the closing brace of a nested message.
+    fn fields(&mut self, depth: usize, nested: bool) -> Result<OpaqueMessage<'static>, ParseError> {
+        let mut message = OpaqueMessage::new();
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                None if nested => return Err(self.error("unclosed `{`")),
+                None => return Ok(message),
+                Some(b'}') if nested => {
+                    self.pos += 1;
+                    return Ok(message);
+                }
+                Some(_) => {}
+            }
+            let tag = self.number()?;
+            self.skip_whitespace();
+            self.expect(b':', "expected `:` after tag")?;
+            self.skip_whitespace();
+            let value = self.value(depth)?;
+            message.insert(tag, value);
+            if self
+                .peek()
+                .map_or(false, |b| b != b'}' && !b.is_ascii_whitespace())
+            {
+                return Err(self.error("expected whitespace after value"));
+            }
+        }
+    }
+
+    fn value(&mut self, depth: usize) -> Result<OpaqueValue<'static>, ParseError> {
+        match self.peek() {
+            Some(b'"') => Ok(LengthDelimited(Cow::Owned(self.string()?.into_bytes()))),
+            Some(b'{') => {
+                if depth >= TEXT_NESTING_LIMIT {
+                    return Err(self.error("messages nested too deeply"));
+                }
+                self.pos += 1;
+                let nested = self.fields(depth + 1, true)?;
+                Ok(LengthDelimited(Cow::Owned(nested.encode_to_vec())))
+            }
+            Some(b'0') if self.text[self.pos..].starts_with("0x") => {
+                self.pos += 2;
+                let start = self.pos;
+                let digits = self.take_while(|b| b.is_ascii_hexdigit());
+                if digits.len() % 2 != 0 {
+                    self.pos = start;
+                    return Err(self.error("odd number of hexadecimal digits"));
+                }
+                let bytes = (0..digits.len())
+                    .step_by(2)
+                    .map(|i| u8::from_str_radix(&digits[i..i + 2], 16).unwrap())
+                    .collect::<Vec<u8>>();
+                Ok(LengthDelimited(Cow::Owned(bytes)))
+            }
+            Some(b'0'..=b'9') => Ok(Varint(self.number()?)),
+            Some(b'a'..=b'z') => {
+                let start = self.pos;
+                let name = self.take_while(|b| b.is_ascii_alphanumeric());
+                match name {
+                    "f32" => {
+                        self.parenthesized(|arg| arg.parse::<f32>().ok().map(OpaqueValue::f32))
+                    }
+                    "f64" => {
+                        self.parenthesized(|arg| arg.parse::<f64>().ok().map(OpaqueValue::f64))
+                    }
+                    "fixed32" => self.parenthesized(|arg| {
+                        let n = u32::try_from(parse_fixed_integer(arg)?).ok()?;
+                        Some(ThirtyTwoBit(n.to_le_bytes()))
+                    }),
+                    "fixed64" => self.parenthesized(|arg| {
+                        parse_fixed_integer(arg).map(|n| SixtyFourBit(n.to_le_bytes()))
+                    }),
+                    _ => {
+                        self.pos = start;
+                        Err(self.error("unknown kind of value"))
+                    }
+                }
+            }
+            _ => Err(self.error("expected a value")),
+        }
+    }
+
+    /// Parses the parenthesized argument of a fixed-size value with the given function.
+    fn parenthesized(
+        &mut self,
+        parse: impl FnOnce(&str) -> Option<OpaqueValue<'static>>,
+    ) -> Result<OpaqueValue<'static>, ParseError> {
+        self.expect(b'(', "expected `(`")?;
+        let start = self.pos;
+        let arg = self.take_while(|b| b != b')').trim();
+        let value = parse(arg);
+        self.expect(b')', "unclosed `(`")?;
+        value.ok_or(ParseError {
+            position: start,
+            reason: "invalid fixed-size value",
+        })
+    }
+
+    fn number<T: FromStr>(&mut self) -> Result<T, ParseError> {
+        let start = self.pos;
+        let digits = self.take_while(|b| b.is_ascii_digit());
+        if digits.is_empty() {
+            return Err(self.error("expected a number"));
+        }
+        digits.parse().map_err(|_| ParseError {
+            position: start,
+            reason: "number out of range",
+        })
+    }
+
+    fn string(&mut self) -> Result<String, ParseError> {
+        self.expect(b'"', "expected `\"`")?;
+        let mut result = String::new();
+        loop {
+            let rest = &self.text[self.pos..];
+            let Some(c) = rest.chars().next() else {
+                return Err(self.error("unclosed string"));
+            };
+            match c {
+                '"' => {
+                    self.pos += 1;
+                    return Ok(result);
+                }
+                '\\' => {
+                    let escape_start = self.pos;
+                    self.pos += 1;
+                    let escaped = match self.peek() {
+                        Some(b'"') => '"',
+                        Some(b'\\') => '\\',
+                        Some(b'n') => '\n',
+                        Some(b'r') => '\r',
+                        Some(b't') => '\t',
+                        Some(b'0') => '\0',
+                        Some(b'u') => {
+                            self.pos += 1;
+                            self.expect(b'{', "expected `{` after `\\u`")?;
+                            let digits = self.take_while(|b| b.is_ascii_hexdigit());
+                            let c = u32::from_str_radix(digits, 16)
+                                .ok()
+                                .and_then(char::from_u32);
+                            if self.peek() != Some(b'}') || c.is_none() {
+                                self.pos = escape_start;
+                                return Err(self.error("invalid unicode escape"));
+                            }
+                            c.unwrap()
+                        }
+                        _ => {
+                            self.pos = escape_start;
+                            return Err(self.error("invalid escape"));
+                        }
+                    };
+                    self.pos += 1;
+                    result.push(escaped);
+                }
+                c => {
+                    self.pos += c.len_utf8();
+                    result.push(c);
+                }
+            }
+        }
+    }
+}
+
+/// Parses the integer in `fixed32(...)` or `fixed64(...)`, in decimal or `0x` hexadecimal.
+fn parse_fixed_integer(arg: &str) -> Option<u64> {
+    match arg.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => arg.parse().ok(),
+    }
+}
@@ -1,15 +1,26 @@
+use alloc::vec::Vec;
+
 use bytes::{Buf, BufMut};
 
+use crate::descriptor::{collection_kind, DescribedEncoding, EncodingDescriptor, ValueKind};
 use crate::encoding::value_traits::{Collection, DistinguishedCollection};
 use crate::encoding::{
-    encode_varint, encoded_len_varint, unpacked, Canonicity, Capped, DecodeContext, DecodeError,
-    DistinguishedEncoder, DistinguishedValueEncoder, Encoder, FieldEncoder, General,
-    NewForOverwrite, TagMeasurer, TagWriter, ValueEncoder, WireType, Wiretyped,
+    encode_varint, encoded_len_varint, prepend_varint, unpacked, BorrowDecoder, Canonicity, Capped,
+    DecodeContext, DecodeError, Decoder, DistinguishedBorrowDecoder, DistinguishedDecoder,
+    DistinguishedEncoder, DistinguishedValueBorrowDecoder, DistinguishedValueDecoder,
+    DistinguishedValueEncoder, Encoder, FieldEncoder, General, NewForOverwrite, ReverseBuffer,
+    TagMeasurer, TagRevWriter, TagWriter, ValueBorrowDecoder, ValueDecoder, ValueEncoder, WireType,
+    Wiretyped,
 };
+use crate::patch::PatchWriter;
 use crate::DecodeErrorKind::{Truncated, UnexpectedlyRepeated};
 
 pub struct Packed<E = General>(E);
 
+impl<E: DescribedEncoding> DescribedEncoding for Packed<E> {
+    const DESCRIPTOR: &'static EncodingDescriptor = &EncodingDescriptor::Packed(E::DESCRIPTOR);
+}
+
 /// Packed encodings are always length delimited.
 impl<T, E> Wiretyped<Packed<E>> for T {
     const WIRE_TYPE: WireType = WireType::LengthDelimited;
@@ -20,6 +31,8 @@ where
     C: Collection<Item = T>,
     T: NewForOverwrite + ValueEncoder<E>,
 {
+    const VALUE_KIND: &'static ValueKind = &collection_kind(C::UNORDERED, T::VALUE_KIND);
+
     fn encode_value<B: BufMut + ?Sized>(value: &C, buf: &mut B) {
         encode_varint(
             ValueEncoder::<E>::many_values_encoded_len(value.iter()) as u64,
@@ -30,12 +43,30 @@ where
         }
     }
 
+    fn prepend_value(value: &C, buf: &mut ReverseBuffer) {
+        let end = buf.len();
+        for val in value.reversed() {
+            ValueEncoder::<E>::prepend_value(val, buf);
+        }
+        prepend_varint((buf.len() - end) as u64, buf);
+    }
+
     fn value_encoded_len(value: &C) -> usize {
         let inner_len = ValueEncoder::<E>::many_values_encoded_len(value.iter());
         // TODO(widders): address general cases where u64 may overflow usize, with care
         encoded_len_varint(inner_len as u64) + inner_len
     }
 
+    fn diff_value(tag: u32, old: &C, new: &C, pw: &mut PatchWriter) {
+        pw.diff_collection::<C, T, E>(tag, old, new, true);
+    }
+}
+
+impl<C, T, E> ValueDecoder<Packed<E>> for C
+where
+    C: Collection<Item = T>,
+    T: NewForOverwrite + ValueDecoder<E>,
+{
     fn decode_value<B: Buf + ?Sized>(
         value: &mut C,
         mut buf: Capped<B>,
@@ -50,10 +81,22 @@ where
         {
             return Err(DecodeError::new(Truncated));
         }
+        // When merging, the items are gathered first so that sets can still reject items repeated
+        // within this input.
+        let merging = ctx.merging();
+        let mut new_items = Vec::new();
         while capped.has_remaining()? {
+            ctx.check_collection_item::<T>(value.len() + new_items.len())?;
             let mut new_val = T::new_for_overwrite();
-            ValueEncoder::<E>::decode_value(&mut new_val, capped.lend(), ctx.clone())?;
-            value.insert(new_val)?;
+            ValueDecoder::<E>::decode_value(&mut new_val, capped.lend(), ctx)?;
+            if merging {
+                new_items.push(new_val);
+            } else {
+                value.insert(new_val)?;
+            }
+        }
+        if merging {
+            value.merge_extend(new_items)?;
         }
         Ok(())
     }
@@ -63,6 +106,13 @@ impl<C, T, E> DistinguishedValueEncoder<Packed<E>> for C
 where
     C: DistinguishedCollection<Item = T> + Eq,
     T: NewForOverwrite + Eq + DistinguishedValueEncoder<E>,
+{
+}
+
+impl<C, T, E> DistinguishedValueDecoder<Packed<E>> for C
+where
+    C: DistinguishedCollection<Item = T> + Eq,
+    T: NewForOverwrite + Eq + DistinguishedValueDecoder<E>,
 {
     fn decode_value_distinguished<B: Buf + ?Sized>(
         value: &mut C,
@@ -84,12 +134,13 @@ where
         }
         let mut canon = Canonicity::Canonical;
         while capped.has_remaining()? {
+            ctx.check_collection_item::<T>(value.len())?;
             let mut new_val = T::new_for_overwrite();
-            canon.update(DistinguishedValueEncoder::<E>::decode_value_distinguished(
+            canon.update(DistinguishedValueDecoder::<E>::decode_value_distinguished(
                 &mut new_val,
                 capped.lend(),
                 true,
-                ctx.clone(),
+                ctx,
             )?);
             canon.update(value.insert_distinguished(new_val)?);
         }
@@ -103,6 +154,8 @@ where
     C: Collection<Item = T> + ValueEncoder<Packed<E>>,
     T: NewForOverwrite + ValueEncoder<E>,
 {
+    const VALUE_KIND: &'static ValueKind = <C as ValueEncoder<Packed<E>>>::VALUE_KIND;
+
     #[inline]
     fn encode<B: BufMut + ?Sized>(tag: u32, value: &C, buf: &mut B, tw: &mut TagWriter) {
         if !value.is_empty() {
@@ -110,6 +163,13 @@ where
         }
     }
 
+    #[inline]
+    fn prepend_encode(tag: u32, value: &C, buf: &mut ReverseBuffer, tw: &mut TagRevWriter) {
+        if !value.is_empty() {
+            Self::prepend_field(tag, value, buf, tw);
+        }
+    }
+
     #[inline]
     fn encoded_len(tag: u32, value: &C, tm: &mut TagMeasurer) -> usize {
         if !value.is_empty() {
@@ -119,6 +179,21 @@ where
         }
     }
 
+    #[inline]
+    fn diff_field(tag: u32, old: &C, new: &C, pw: &mut PatchWriter) {
+        pw.diff_present::<C, Packed<E>>(
+            tag,
+            (!old.is_empty()).then_some(old),
+            (!new.is_empty()).then_some(new),
+        );
+    }
+}
+
+impl<C, T, E> Decoder<Packed<E>> for C
+where
+    C: Collection<Item = T> + ValueDecoder<Packed<E>>,
+    T: NewForOverwrite + ValueDecoder<E>,
+{
     #[inline]
     fn decode<B: Buf + ?Sized>(
         wire_type: WireType,
@@ -144,6 +219,13 @@ impl<C, T, E> DistinguishedEncoder<Packed<E>> for C
 where
     C: DistinguishedCollection<Item = T> + DistinguishedValueEncoder<Packed<E>>,
     T: NewForOverwrite + Eq + ValueEncoder<E>,
+{
+}
+
+impl<C, T, E> DistinguishedDecoder<Packed<E>> for C
+where
+    C: DistinguishedCollection<Item = T> + DistinguishedValueDecoder<Packed<E>>,
+    T: NewForOverwrite + Eq + ValueDecoder<E>,
 {
     #[inline]
     fn decode_distinguished<B: Buf + ?Sized>(
@@ -159,7 +241,7 @@ where
         if wire_type == WireType::LengthDelimited {
             // We've encountered the expected length-delimited type: decode it in packed format.
             // Set allow_empty=false: empty collections are not canonical
-            DistinguishedValueEncoder::<Packed<E>>::decode_value_distinguished(
+            DistinguishedValueDecoder::<Packed<E>>::decode_value_distinguished(
                 value, buf, false, ctx,
             )
         } else {
@@ -169,3 +251,129 @@ where
         }
     }
 }
+
+impl<'a, C, T, E> ValueBorrowDecoder<'a, Packed<E>> for C
+where
+    C: Collection<Item = T>,
+    T: NewForOverwrite + ValueBorrowDecoder<'a, E>,
+{
+    fn borrow_decode_value(
+        value: &mut C,
+        mut buf: Capped<&'a [u8]>,
+        ctx: DecodeContext,
+    ) -> Result<(), DecodeError> {
+        let mut capped = buf.take_length_delimited()?;
+        if <T as Wiretyped<E>>::WIRE_TYPE
+            .fixed_size()
+            .map_or(false, |fixed_size| {
+                capped.remaining_before_cap() % fixed_size != 0
+            })
+        {
+            return Err(DecodeError::new(Truncated));
+        }
+        while capped.has_remaining()? {
+            ctx.check_collection_item::<T>(value.len())?;
+            let mut new_val = T::new_for_overwrite();
+            ValueBorrowDecoder::<E>::borrow_decode_value(&mut new_val, capped.lend(), ctx)?;
+            value.insert(new_val)?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a, C, T, E> DistinguishedValueBorrowDecoder<'a, Packed<E>> for C
+where
+    C: DistinguishedCollection<Item = T> + Eq,
+    T: NewForOverwrite + Eq + DistinguishedValueBorrowDecoder<'a, E>,
+{
+    fn borrow_decode_value_distinguished(
+        value: &mut C,
+        mut buf: Capped<&'a [u8]>,
+        allow_empty: bool,
+        ctx: DecodeContext,
+    ) -> Result<Canonicity, DecodeError> {
+        let mut capped = buf.take_length_delimited()?;
+        if !allow_empty && capped.remaining_before_cap() == 0 {
+            return Ok(Canonicity::NotCanonical);
+        }
+        if <T as Wiretyped<E>>::WIRE_TYPE
+            .fixed_size()
+            .map_or(false, |fixed_size| {
+                capped.remaining_before_cap() % fixed_size != 0
+            })
+        {
+            return Err(DecodeError::new(Truncated));
+        }
+        let mut canon = Canonicity::Canonical;
+        while capped.has_remaining()? {
+            ctx.check_collection_item::<T>(value.len())?;
+            let mut new_val = T::new_for_overwrite();
+            canon.update(
+                DistinguishedValueBorrowDecoder::<E>::borrow_decode_value_distinguished(
+                    &mut new_val,
+                    capped.lend(),
+                    true,
+                    ctx,
+                )?,
+            );
+            canon.update(value.insert_distinguished(new_val)?);
+        }
+        Ok(canon)
+    }
+}
+
+impl<'a, C, T, E> BorrowDecoder<'a, Packed<E>> for C
+where
+    C: Collection<Item = T> + ValueBorrowDecoder<'a, Packed<E>>,
+    T: NewForOverwrite + ValueBorrowDecoder<'a, E>,
+{
+    #[inline]
+    fn borrow_decode(
+        wire_type: WireType,
+        duplicated: bool,
+        value: &mut C,
+        buf: Capped<&'a [u8]>,
+        ctx: DecodeContext,
+    ) -> Result<(), DecodeError> {
+        if duplicated {
+            return Err(DecodeError::new(UnexpectedlyRepeated));
+        }
+        if wire_type == WireType::LengthDelimited {
+            // We've encountered the expected length-delimited type: decode it in packed format.
+            Self::borrow_decode_value(value, buf, ctx)
+        } else {
+            // Otherwise, try decoding it in the unpacked representation
+            unpacked::borrow_decode::<C, E>(wire_type, value, buf, ctx)
+        }
+    }
+}
+
+impl<'a, C, T, E> DistinguishedBorrowDecoder<'a, Packed<E>> for C
+where
+    C: DistinguishedCollection<Item = T> + DistinguishedValueBorrowDecoder<'a, Packed<E>>,
+    T: NewForOverwrite + Eq + ValueBorrowDecoder<'a, E>,
+{
+    #[inline]
+    fn borrow_decode_distinguished(
+        wire_type: WireType,
+        duplicated: bool,
+        value: &mut C,
+        buf: Capped<&'a [u8]>,
+        ctx: DecodeContext,
+    ) -> Result<Canonicity, DecodeError> {
+        if duplicated {
+            return Err(DecodeError::new(UnexpectedlyRepeated));
+        }
+        if wire_type == WireType::LengthDelimited {
+            // We've encountered the expected length-delimited type: decode it in packed format.
+            // Set allow_empty=false: empty collections are not canonical
+            DistinguishedValueBorrowDecoder::<Packed<E>>::borrow_decode_value_distinguished(
+                value, buf, false, ctx,
+            )
+        } else {
+            // Otherwise, try decoding it in the unpacked representation
+            unpacked::borrow_decode::<C, E>(wire_type, value, buf, ctx)?;
+            Ok(Canonicity::NotCanonical)
+        }
+    }
+}
@@ -4,10 +4,13 @@ use core::ops::Deref;
 
 use bytes::{Buf, BufMut};
 
+use crate::descriptor::{DescribedEncoding, EncodingDescriptor, ValueKind};
 use crate::encoding::{
-    delegate_encoding, encode_varint, encoded_len_varint, encoder_where_value_encoder, Canonicity,
-    Capped, DecodeContext, DecodeError, DistinguishedValueEncoder, EmptyState, Encoder,
-    TagMeasurer, TagWriter, ValueEncoder, WireType, Wiretyped,
+    borrow_decoding_via_owned, delegate_encoding, encode_varint, encoded_len_varint,
+    encoder_where_value_encoder, prepend_varint, Canonicity, Capped, DecodeContext, DecodeError,
+    DistinguishedValueBorrowDecoder, DistinguishedValueDecoder, DistinguishedValueEncoder,
+    EmptyState, Encoder, ReverseBuffer, TagMeasurer, TagWriter, ValueBorrowDecoder, ValueDecoder,
+    ValueEncoder, WireType, Wiretyped,
 };
 use crate::DecodeErrorKind::InvalidValue;
 
@@ -18,6 +21,10 @@ use crate::DecodeErrorKind::InvalidValue;
 /// `Vec<u8>`.
 pub struct PlainBytes;
 
+impl DescribedEncoding for PlainBytes {
+    const DESCRIPTOR: &'static EncodingDescriptor = &EncodingDescriptor::PlainBytes;
+}
+
 encoder_where_value_encoder!(PlainBytes);
 
 impl Wiretyped<PlainBytes> for Vec<u8> {
@@ -25,21 +32,31 @@ impl Wiretyped<PlainBytes> for Vec<u8> {
 }
 
 impl ValueEncoder<PlainBytes> for Vec<u8> {
+    const VALUE_KIND: &'static ValueKind = &ValueKind::Bytes;
+
     fn encode_value<B: BufMut + ?Sized>(value: &Vec<u8>, buf: &mut B) {
         encode_varint(value.len() as u64, buf);
         buf.put_slice(value.as_slice());
     }
 
+    fn prepend_value(value: &Vec<u8>, buf: &mut ReverseBuffer) {
+        buf.prepend_slice(value);
+        prepend_varint(value.len() as u64, buf);
+    }
+
     fn value_encoded_len(value: &Vec<u8>) -> usize {
         encoded_len_varint(value.len() as u64) + value.len()
     }
+}
 
+impl ValueDecoder<PlainBytes> for Vec<u8> {
     fn decode_value<B: Buf + ?Sized>(
         value: &mut Vec<u8>,
         mut buf: Capped<B>,
-        _ctx: DecodeContext,
+        ctx: DecodeContext,
     ) -> Result<(), DecodeError> {
         let buf = buf.take_length_delimited()?;
+        ctx.check_bytes_alloc(buf.remaining_before_cap())?;
         value.clear();
         value.reserve(buf.remaining_before_cap());
         value.put(buf.take_all());
@@ -47,14 +64,16 @@ impl ValueEncoder<PlainBytes> for Vec<u8> {
     }
 }
 
-impl DistinguishedValueEncoder<PlainBytes> for Vec<u8> {
+impl DistinguishedValueEncoder<PlainBytes> for Vec<u8> {}
+
+impl DistinguishedValueDecoder<PlainBytes> for Vec<u8> {
     fn decode_value_distinguished<B: Buf + ?Sized>(
         value: &mut Vec<u8>,
         buf: Capped<B>,
         allow_empty: bool,
         ctx: DecodeContext,
     ) -> Result<Canonicity, DecodeError> {
-        ValueEncoder::<PlainBytes>::decode_value(value, buf, ctx)?;
+        ValueDecoder::<PlainBytes>::decode_value(value, buf, ctx)?;
         Ok(if !allow_empty && value.is_empty() {
             Canonicity::NotCanonical
         } else {
@@ -63,6 +82,8 @@ impl DistinguishedValueEncoder<PlainBytes> for Vec<u8> {
     }
 }
 
+borrow_decoding_via_owned!(PlainBytes, Vec<u8>, including distinguished);
+
 delegate_encoding!(delegate from (PlainBytes) to (crate::encoding::Unpacked<PlainBytes>)
     for type (Vec<Vec<u8>>) including distinguished);
 delegate_encoding!(delegate from (PlainBytes) to (crate::encoding::Unpacked<PlainBytes>)
@@ -86,28 +107,39 @@ impl Wiretyped<PlainBytes> for Cow<'_, [u8]> {
 }
 
 impl ValueEncoder<PlainBytes> for Cow<'_, [u8]> {
+    const VALUE_KIND: &'static ValueKind = &ValueKind::Bytes;
+
     #[inline]
     fn encode_value<B: BufMut + ?Sized>(value: &Cow<[u8]>, buf: &mut B) {
         encode_varint(value.len() as u64, buf);
         buf.put_slice(value.as_ref());
     }
 
+    fn prepend_value(value: &Cow<[u8]>, buf: &mut ReverseBuffer) {
+        buf.prepend_slice(value);
+        prepend_varint(value.len() as u64, buf);
+    }
+
     #[inline]
     fn value_encoded_len(value: &Cow<[u8]>) -> usize {
         encoded_len_varint(value.len() as u64) + value.len()
     }
+}
 
+impl ValueDecoder<PlainBytes> for Cow<'_, [u8]> {
     #[inline]
     fn decode_value<B: Buf + ?Sized>(
         value: &mut Cow<[u8]>,
         buf: Capped<B>,
         ctx: DecodeContext,
     ) -> Result<(), DecodeError> {
-        ValueEncoder::<PlainBytes>::decode_value(value.to_mut(), buf, ctx)
+        ValueDecoder::<PlainBytes>::decode_value(value.to_mut(), buf, ctx)
     }
 }
 
-impl DistinguishedValueEncoder<PlainBytes> for Cow<'_, [u8]> {
+impl DistinguishedValueEncoder<PlainBytes> for Cow<'_, [u8]> {}
+
+impl DistinguishedValueDecoder<PlainBytes> for Cow<'_, [u8]> {
     #[inline]
     fn decode_value_distinguished<B: Buf + ?Sized>(
         value: &mut Cow<[u8]>,
@@ -115,7 +147,7 @@ impl DistinguishedValueEncoder<PlainBytes> for Cow<'_, [u8]> {
         allow_empty: bool,
         ctx: DecodeContext,
     ) -> Result<Canonicity, DecodeError> {
-        DistinguishedValueEncoder::<PlainBytes>::decode_value_distinguished(
+        DistinguishedValueDecoder::<PlainBytes>::decode_value_distinguished(
             value.to_mut(),
             buf,
             allow_empty,
@@ -124,6 +156,37 @@ impl DistinguishedValueEncoder<PlainBytes> for Cow<'_, [u8]> {
     }
 }
 
+impl<'a: 'b, 'b> ValueBorrowDecoder<'a, PlainBytes> for Cow<'b, [u8]> {
+    #[inline]
+    fn borrow_decode_value(
+        value: &mut Cow<'b, [u8]>,
+        mut buf: Capped<&'a [u8]>,
+        ctx: DecodeContext,
+    ) -> Result<(), DecodeError> {
+        let bytes = buf.take_borrowed_length_delimited()?;
+        ctx.check_bytes_len(bytes.len())?;
+        *value = Cow::Borrowed(bytes);
+        Ok(())
+    }
+}
+
+impl<'a: 'b, 'b> DistinguishedValueBorrowDecoder<'a, PlainBytes> for Cow<'b, [u8]> {
+    #[inline]
+    fn borrow_decode_value_distinguished(
+        value: &mut Cow<'b, [u8]>,
+        buf: Capped<&'a [u8]>,
+        allow_empty: bool,
+        ctx: DecodeContext,
+    ) -> Result<Canonicity, DecodeError> {
+        ValueBorrowDecoder::<PlainBytes>::borrow_decode_value(value, buf, ctx)?;
+        Ok(if !allow_empty && value.is_empty() {
+            Canonicity::NotCanonical
+        } else {
+            Canonicity::Canonical
+        })
+    }
+}
+
 #[cfg(test)]
 mod cow_bytes {
     use super::{Cow, PlainBytes};
@@ -137,6 +200,82 @@ mod cow_bytes {
     );
 }
 
+impl EmptyState for &[u8] {
+    #[inline]
+    fn empty() -> Self {
+        &[]
+    }
+
+    #[inline]
+    fn is_empty(&self) -> bool {
+        <[u8]>::is_empty(self)
+    }
+
+    #[inline]
+    fn clear(&mut self) {
+        *self = &[];
+    }
+}
+
+impl Wiretyped<PlainBytes> for &[u8] {
+    const WIRE_TYPE: WireType = WireType::LengthDelimited;
+}
+
+/// Borrowed byte slices can be encoded normally, but can only be decoded via the borrowed decoding
+/// traits; they do not implement `ValueDecoder`.
+impl ValueEncoder<PlainBytes> for &[u8] {
+    const VALUE_KIND: &'static ValueKind = &ValueKind::Bytes;
+
+    #[inline]
+    fn encode_value<B: BufMut + ?Sized>(value: &&[u8], buf: &mut B) {
+        encode_varint(value.len() as u64, buf);
+        buf.put_slice(value);
+    }
+
+    fn prepend_value(value: &&[u8], buf: &mut ReverseBuffer) {
+        buf.prepend_slice(value);
+        prepend_varint(value.len() as u64, buf);
+    }
+
+    #[inline]
+    fn value_encoded_len(value: &&[u8]) -> usize {
+        encoded_len_varint(value.len() as u64) + value.len()
+    }
+}
+
+impl DistinguishedValueEncoder<PlainBytes> for &[u8] {}
+
+impl<'a: 'b, 'b> ValueBorrowDecoder<'a, PlainBytes> for &'b [u8] {
+    #[inline]
+    fn borrow_decode_value(
+        value: &mut &'b [u8],
+        mut buf: Capped<&'a [u8]>,
+        ctx: DecodeContext,
+    ) -> Result<(), DecodeError> {
+        let bytes = buf.take_borrowed_length_delimited()?;
+        ctx.check_bytes_len(bytes.len())?;
+        *value = bytes;
+        Ok(())
+    }
+}
+
+impl<'a: 'b, 'b> DistinguishedValueBorrowDecoder<'a, PlainBytes> for &'b [u8] {
+    #[inline]
+    fn borrow_decode_value_distinguished(
+        value: &mut &'b [u8],
+        buf: Capped<&'a [u8]>,
+        allow_empty: bool,
+        ctx: DecodeContext,
+    ) -> Result<Canonicity, DecodeError> {
+        ValueBorrowDecoder::<PlainBytes>::borrow_decode_value(value, buf, ctx)?;
+        Ok(if !allow_empty && value.is_empty() {
+            Canonicity::NotCanonical
+        } else {
+            Canonicity::Canonical
+        })
+    }
+}
+
 impl<const N: usize> EmptyState for [u8; N] {
     #[inline]
     fn empty() -> Self {
@@ -159,11 +298,18 @@ impl<const N: usize> Wiretyped<PlainBytes> for [u8; N] {
 }
 
 impl<const N: usize> ValueEncoder<PlainBytes> for [u8; N] {
+    const VALUE_KIND: &'static ValueKind = &ValueKind::Bytes;
+
     fn encode_value<B: BufMut + ?Sized>(value: &[u8; N], mut buf: &mut B) {
         encode_varint(N as u64, buf);
         (&mut buf).put(value.as_slice())
     }
 
+    fn prepend_value(value: &[u8; N], buf: &mut ReverseBuffer) {
+        buf.prepend_slice(value.as_slice());
+        prepend_varint(N as u64, buf);
+    }
+
     fn value_encoded_len(_value: &[u8; N]) -> usize {
         encoded_len_varint(N as u64) + N
     }
@@ -175,7 +321,9 @@ impl<const N: usize> ValueEncoder<PlainBytes> for [u8; N] {
     {
         values.len() * (encoded_len_varint(N as u64) + N)
     }
+}
 
+impl<const N: usize> ValueDecoder<PlainBytes> for [u8; N] {
     fn decode_value<B: Buf + ?Sized>(
         value: &mut [u8; N],
         mut buf: Capped<B>,
@@ -190,7 +338,9 @@ impl<const N: usize> ValueEncoder<PlainBytes> for [u8; N] {
     }
 }
 
-impl<const N: usize> DistinguishedValueEncoder<PlainBytes> for [u8; N] {
+impl<const N: usize> DistinguishedValueEncoder<PlainBytes> for [u8; N] {}
+
+impl<const N: usize> DistinguishedValueDecoder<PlainBytes> for [u8; N] {
     fn decode_value_distinguished<B: Buf + ?Sized>(
         value: &mut [u8; N],
         buf: Capped<B>,
@@ -206,6 +356,9 @@ impl<const N: usize> DistinguishedValueEncoder<PlainBytes> for [u8; N] {
     }
 }
 
+borrow_decoding_via_owned!(PlainBytes, [u8; N], with generics (const N: usize),
+    including distinguished);
+
 // TODO(widders): ArrayVec
 
 #[cfg(test)]
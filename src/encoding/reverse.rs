@@ -0,0 +1,227 @@
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cmp::max;
+use core::fmt::{self, Debug};
+
+use bytes::{Buf, Bytes};
+
+/// A growable byte buffer that is written from back to front.
+///
+/// Bilrost messages can be encoded into this buffer in a single pass: because each nested message
+/// is written before the length that prefixes it, the length is always already known and no
+/// message ever needs to be measured before it is encoded.
+///
+/// The data in the buffer is stored at the end of its allocation, so `into_bytes` is zero-copy.
+/// The buffer can also be read as a `Buf`.
+#[derive(Clone, Default)]
+pub struct ReverseBuffer {
+    buf: Vec<u8>,
+    start: usize,
+}
+
+impl ReverseBuffer {
+    /// Creates a new, empty buffer.
+    pub const fn new() -> Self {
+        Self {
+            buf: Vec::new(),
+            start: 0,
+        }
+    }
+
+    /// Creates a new, empty buffer which can have at least `capacity` bytes prepended to it
+    /// before it must reallocate.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            buf: vec![0; capacity],
+            start: capacity,
+        }
+    }
+
+    /// Returns the number of bytes in the buffer.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.buf.len() - self.start
+    }
+
+    /// Returns true if the buffer contains no bytes.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.start == self.buf.len()
+    }
+
+    /// Returns the number of bytes that can be prepended to the buffer before it must reallocate.
+    #[inline]
+    pub fn spare_capacity(&self) -> usize {
+        self.start
+    }
+
+    /// Removes all the bytes from the buffer, keeping its allocation.
+    pub fn clear(&mut self) {
+        self.start = self.buf.len();
+    }
+
+    /// Returns the contents of the buffer.
+    #[inline]
+    pub fn as_slice(&self) -> &[u8] {
+        &self.buf[self.start..]
+    }
+
+    /// Prepends a single byte to the front of the buffer.
+    #[inline]
+    pub fn prepend_u8(&mut self, byte: u8) {
+        self.reserve(1);
+        self.start -= 1;
+        self.buf[self.start] = byte;
+    }
+
+    /// Prepends the given bytes to the front of the buffer, so that the buffer begins with them.
+    #[inline]
+    pub fn prepend_slice(&mut self, bytes: &[u8]) {
+        self.reserve(bytes.len());
+        let new_start = self.start - bytes.len();
+        self.buf[new_start..self.start].copy_from_slice(bytes);
+        self.start = new_start;
+    }
+
+    /// Prepends all the remaining bytes of the given `Buf` to the front of the buffer, consuming
+    /// them.
+    pub fn prepend_buf<B: Buf + ?Sized>(&mut self, src: &mut B) {
+        let len = src.remaining();
+        self.reserve(len);
+        let new_start = self.start - len;
+        src.copy_to_slice(&mut self.buf[new_start..self.start]);
+        self.start = new_start;
+    }
+
+    /// Ensures that at least `additional` bytes can be prepended without reallocating.
+    #[inline]
+    pub fn reserve(&mut self, additional: usize) {
+        if additional > self.start {
+            self.grow(additional);
+        }
+    }
+
+    #[cold]
+    fn grow(&mut self, additional: usize) {
+        let len = self.len();
+        let new_capacity = max(
+            max(self.buf.len() * 2, 64),
+            len.checked_add(additional).expect("capacity overflow"),
+        );
+        let mut new_buf = vec![0; new_capacity];
+        let new_start = new_capacity - len;
+        new_buf[new_start..].copy_from_slice(self.as_slice());
+        self.buf = new_buf;
+        self.start = new_start;
+    }
+
+    /// Converts the buffer into a `Vec` containing its bytes.
+    pub fn into_vec(mut self) -> Vec<u8> {
+        if self.start > 0 {
+            self.buf.copy_within(self.start.., 0);
+            self.buf.truncate(self.buf.len() - self.start);
+        }
+        self.buf
+    }
+
+    /// Converts the buffer into `Bytes` containing its bytes without copying them.
+    pub fn into_bytes(self) -> Bytes {
+        Bytes::from(self.buf).slice(self.start..)
+    }
+}
+
+impl Debug for ReverseBuffer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("ReverseBuffer")
+            .field(&self.as_slice())
+            .finish()
+    }
+}
+
+impl PartialEq for ReverseBuffer {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_slice() == other.as_slice()
+    }
+}
+
+impl Eq for ReverseBuffer {}
+
+impl AsRef<[u8]> for ReverseBuffer {
+    fn as_ref(&self) -> &[u8] {
+        self.as_slice()
+    }
+}
+
+impl From<ReverseBuffer> for Vec<u8> {
+    fn from(value: ReverseBuffer) -> Self {
+        value.into_vec()
+    }
+}
+
+impl From<ReverseBuffer> for Bytes {
+    fn from(value: ReverseBuffer) -> Self {
+        value.into_bytes()
+    }
+}
+
+impl Buf for ReverseBuffer {
+    #[inline]
+    fn remaining(&self) -> usize {
+        self.len()
+    }
+
+    #[inline]
+    fn chunk(&self) -> &[u8] {
+        self.as_slice()
+    }
+
+    #[inline]
+    fn advance(&mut self, cnt: usize) {
+        assert!(cnt <= self.len(), "advanced past the end of the buffer");
+        self.start += cnt;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use alloc::vec::Vec;
+
+    use bytes::Buf;
+
+    use super::ReverseBuffer;
+
+    #[test]
+    fn prepending() {
+        let mut buf = ReverseBuffer::new();
+        assert!(buf.is_empty());
+        buf.prepend_slice(b"world");
+        buf.prepend_u8(b' ');
+        buf.prepend_buf(&mut &b"hello"[..]);
+        assert_eq!(buf.as_slice(), b"hello world");
+        assert_eq!(buf.len(), 11);
+
+        // Growing the buffer preserves its contents
+        let long: Vec<u8> = (0..=255).collect();
+        buf.prepend_slice(&long);
+        assert_eq!(&buf.as_slice()[..256], long.as_slice());
+        assert_eq!(&buf.as_slice()[256..], b"hello world");
+
+        assert_eq!(buf.clone().into_vec(), buf.as_slice());
+        assert_eq!(buf.clone().into_bytes(), buf.as_slice());
+
+        buf.advance(256);
+        assert_eq!(buf.chunk(), b"hello world");
+        buf.clear();
+        assert!(buf.is_empty());
+        assert!(buf.spare_capacity() >= 267);
+    }
+
+    #[test]
+    fn with_capacity() {
+        let mut buf = ReverseBuffer::with_capacity(4);
+        assert_eq!(buf.spare_capacity(), 4);
+        buf.prepend_slice(b"abcd");
+        assert_eq!(buf.spare_capacity(), 0);
+        assert_eq!(buf.into_vec(), b"abcd");
+    }
+}
@@ -1,20 +1,30 @@
+use alloc::vec::Vec;
+
 use bytes::{Buf, BufMut};
 
+use crate::descriptor::{collection_kind, DescribedEncoding, EncodingDescriptor, ValueKind};
 use crate::encoding::value_traits::{Collection, DistinguishedCollection};
 use crate::encoding::{
-    check_wire_type, Capped, DecodeContext, DistinguishedEncoder, DistinguishedValueEncoder,
-    Encoder, FieldEncoder, General, NewForOverwrite, Packed, TagMeasurer, TagWriter, ValueEncoder,
-    WireType, Wiretyped,
+    check_wire_type, BorrowDecoder, Capped, DecodeContext, Decoder, DistinguishedBorrowDecoder,
+    DistinguishedDecoder, DistinguishedEncoder, DistinguishedValueBorrowDecoder,
+    DistinguishedValueDecoder, DistinguishedValueEncoder, Encoder, FieldEncoder, General,
+    NewForOverwrite, Packed, ReverseBuffer, TagMeasurer, TagRevWriter, TagWriter,
+    ValueBorrowDecoder, ValueDecoder, ValueEncoder, WireType, Wiretyped,
 };
+use crate::patch::PatchWriter;
 use crate::DecodeErrorKind::UnexpectedlyRepeated;
 use crate::{Canonicity, DecodeError};
 
 pub struct Unpacked<E = General>(E);
 
+impl<E: DescribedEncoding> DescribedEncoding for Unpacked<E> {
+    const DESCRIPTOR: &'static EncodingDescriptor = &EncodingDescriptor::Unpacked(E::DESCRIPTOR);
+}
+
 /// Returns `Some` if there are more bytes in the buffer and the next data in the buffer begins
 /// with a "repeated" field key (a key with a tag delta of zero).
 #[inline(always)]
-fn peek_repeated_field<B: Buf + ?Sized>(buf: &mut Capped<B>) -> Option<WireType> {
+pub(crate) fn peek_repeated_field<B: Buf + ?Sized>(buf: &mut Capped<B>) -> Option<WireType> {
     if buf.remaining_before_cap() == 0 {
         return None;
     }
@@ -40,14 +50,23 @@ pub(crate) fn decode<T, E>(
 ) -> Result<(), DecodeError>
 where
     T: Collection,
-    T::Item: NewForOverwrite + ValueEncoder<E>,
+    T::Item: NewForOverwrite + ValueDecoder<E>,
 {
     check_wire_type(<T::Item as Wiretyped<E>>::WIRE_TYPE, wire_type)?;
+    // When merging, the items are gathered first so that sets can still reject items repeated
+    // within this input.
+    let merging = ctx.merging();
+    let mut new_items = Vec::new();
     loop {
         // Decode one item
+        ctx.check_collection_item::<T::Item>(collection.len() + new_items.len())?;
         let mut new_item = T::Item::new_for_overwrite();
-        ValueEncoder::<E>::decode_value(&mut new_item, buf.lend(), ctx.clone())?;
-        collection.insert(new_item)?;
+        ValueDecoder::<E>::decode_value(&mut new_item, buf.lend(), ctx)?;
+        if merging {
+            new_items.push(new_item);
+        } else {
+            collection.insert(new_item)?;
+        }
 
         if let Some(next_wire_type) = peek_repeated_field(&mut buf) {
             check_wire_type(<T::Item as Wiretyped<E>>::WIRE_TYPE, next_wire_type)?;
@@ -55,6 +74,9 @@ where
             break;
         }
     }
+    if merging {
+        collection.merge_extend(new_items)?;
+    }
     Ok(())
 }
 
@@ -69,18 +91,19 @@ pub(crate) fn decode_distinguished<T, E>(
 ) -> Result<Canonicity, DecodeError>
 where
     T: DistinguishedCollection,
-    T::Item: NewForOverwrite + Eq + DistinguishedValueEncoder<E>,
+    T::Item: NewForOverwrite + Eq + DistinguishedValueDecoder<E>,
 {
     check_wire_type(<T::Item as Wiretyped<E>>::WIRE_TYPE, wire_type)?;
     let mut canon = Canonicity::Canonical;
     loop {
         // Decode one item
+        ctx.check_collection_item::<T::Item>(collection.len())?;
         let mut new_item = T::Item::new_for_overwrite();
-        canon.update(DistinguishedValueEncoder::<E>::decode_value_distinguished(
+        canon.update(DistinguishedValueDecoder::<E>::decode_value_distinguished(
             &mut new_item,
             buf.lend(),
             true,
-            ctx.clone(),
+            ctx,
         )?);
         canon.update(collection.insert_distinguished(new_item)?);
 
@@ -93,6 +116,75 @@ where
     Ok(canon)
 }
 
+/// Decodes a collection value from the unpacked representation, borrowing from the input buffer.
+/// This greedily consumes consecutive fields as long as they have the same tag.
+#[inline]
+pub(crate) fn borrow_decode<'a, T, E>(
+    wire_type: WireType,
+    collection: &mut T,
+    mut buf: Capped<&'a [u8]>,
+    ctx: DecodeContext,
+) -> Result<(), DecodeError>
+where
+    T: Collection,
+    T::Item: NewForOverwrite + ValueBorrowDecoder<'a, E>,
+{
+    check_wire_type(<T::Item as Wiretyped<E>>::WIRE_TYPE, wire_type)?;
+    loop {
+        // Decode one item
+        ctx.check_collection_item::<T::Item>(collection.len())?;
+        let mut new_item = T::Item::new_for_overwrite();
+        ValueBorrowDecoder::<E>::borrow_decode_value(&mut new_item, buf.lend(), ctx)?;
+        collection.insert(new_item)?;
+
+        if let Some(next_wire_type) = peek_repeated_field(&mut buf) {
+            check_wire_type(<T::Item as Wiretyped<E>>::WIRE_TYPE, next_wire_type)?;
+        } else {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Decodes a collection value from the unpacked representation in distinguished mode, borrowing
+/// from the input buffer. This greedily consumes consecutive fields as long as they have the same
+/// tag.
+#[inline]
+pub(crate) fn borrow_decode_distinguished<'a, T, E>(
+    wire_type: WireType,
+    collection: &mut T,
+    mut buf: Capped<&'a [u8]>,
+    ctx: DecodeContext,
+) -> Result<Canonicity, DecodeError>
+where
+    T: DistinguishedCollection,
+    T::Item: NewForOverwrite + Eq + DistinguishedValueBorrowDecoder<'a, E>,
+{
+    check_wire_type(<T::Item as Wiretyped<E>>::WIRE_TYPE, wire_type)?;
+    let mut canon = Canonicity::Canonical;
+    loop {
+        // Decode one item
+        ctx.check_collection_item::<T::Item>(collection.len())?;
+        let mut new_item = T::Item::new_for_overwrite();
+        canon.update(
+            DistinguishedValueBorrowDecoder::<E>::borrow_decode_value_distinguished(
+                &mut new_item,
+                buf.lend(),
+                true,
+                ctx,
+            )?,
+        );
+        canon.update(collection.insert_distinguished(new_item)?);
+
+        if let Some(next_wire_type) = peek_repeated_field(&mut buf) {
+            check_wire_type(<T::Item as Wiretyped<E>>::WIRE_TYPE, next_wire_type)?;
+        } else {
+            break;
+        }
+    }
+    Ok(canon)
+}
+
 /// Unpacked encodes vecs as repeated fields and in relaxed decoding will accept both packed
 /// and un-packed encodings.
 impl<C, T, E> Encoder<Unpacked<E>> for C
@@ -100,12 +192,20 @@ where
     C: Collection<Item = T>,
     T: NewForOverwrite + ValueEncoder<E>,
 {
+    const VALUE_KIND: &'static ValueKind = &collection_kind(C::UNORDERED, T::VALUE_KIND);
+
     fn encode<B: BufMut + ?Sized>(tag: u32, value: &C, buf: &mut B, tw: &mut TagWriter) {
         for val in value.iter() {
             FieldEncoder::<E>::encode_field(tag, val, buf, tw);
         }
     }
 
+    fn prepend_encode(tag: u32, value: &C, buf: &mut ReverseBuffer, tw: &mut TagRevWriter) {
+        for val in value.reversed() {
+            FieldEncoder::<E>::prepend_field(tag, val, buf, tw);
+        }
+    }
+
     fn encoded_len(tag: u32, value: &C, tm: &mut TagMeasurer) -> usize {
         if !value.is_empty() {
             // Each *additional* field encoded after the first needs only 1 byte for the field key.
@@ -116,6 +216,16 @@ where
         }
     }
 
+    fn diff_field(tag: u32, old: &C, new: &C, pw: &mut PatchWriter) {
+        pw.diff_collection::<C, T, E>(tag, old, new, false);
+    }
+}
+
+impl<C, T, E> Decoder<Unpacked<E>> for C
+where
+    C: Collection<Item = T>,
+    T: NewForOverwrite + ValueDecoder<E>,
+{
     fn decode<B: Buf + ?Sized>(
         wire_type: WireType,
         duplicated: bool,
@@ -131,7 +241,7 @@ where
         {
             // We've encountered a length-delimited field when we aren't expecting one; try decoding
             // it in packed format instead.
-            ValueEncoder::<Packed<E>>::decode_value(value, buf, ctx)
+            ValueDecoder::<Packed<E>>::decode_value(value, buf, ctx)
         } else {
             // Otherwise, decode in unpacked mode.
             decode::<C, E>(wire_type, value, buf, ctx)
@@ -144,6 +254,13 @@ impl<C, T, E> DistinguishedEncoder<Unpacked<E>> for C
 where
     Self: DistinguishedCollection<Item = T> + ValueEncoder<Packed<E>> + Encoder<Unpacked<E>>,
     T: NewForOverwrite + Eq + DistinguishedValueEncoder<E>,
+{
+}
+
+impl<C, T, E> DistinguishedDecoder<Unpacked<E>> for C
+where
+    Self: DistinguishedCollection<Item = T> + ValueDecoder<Packed<E>> + Decoder<Unpacked<E>>,
+    T: NewForOverwrite + Eq + DistinguishedValueDecoder<E>,
 {
     fn decode_distinguished<B: Buf + ?Sized>(
         wire_type: WireType,
@@ -161,7 +278,7 @@ where
             // We've encountered a length-delimited field when we aren't expecting one; try decoding
             // it in packed format instead.
             // The data is already known to be non-canonical; use expedient decoding
-            <C as ValueEncoder<Packed<E>>>::decode_value(value, buf, ctx)?;
+            <C as ValueDecoder<Packed<E>>>::decode_value(value, buf, ctx)?;
             Ok(Canonicity::NotCanonical)
         } else {
             // Otherwise, decode in unpacked mode.
@@ -170,6 +287,66 @@ where
     }
 }
 
+impl<'a, C, T, E> BorrowDecoder<'a, Unpacked<E>> for C
+where
+    C: Collection<Item = T>,
+    T: NewForOverwrite + ValueBorrowDecoder<'a, E>,
+{
+    fn borrow_decode(
+        wire_type: WireType,
+        duplicated: bool,
+        value: &mut C,
+        buf: Capped<&'a [u8]>,
+        ctx: DecodeContext,
+    ) -> Result<(), DecodeError> {
+        if duplicated {
+            return Err(DecodeError::new(UnexpectedlyRepeated));
+        }
+        if wire_type == WireType::LengthDelimited
+            && <C::Item as Wiretyped<E>>::WIRE_TYPE != WireType::LengthDelimited
+        {
+            // We've encountered a length-delimited field when we aren't expecting one; try decoding
+            // it in packed format instead.
+            ValueBorrowDecoder::<Packed<E>>::borrow_decode_value(value, buf, ctx)
+        } else {
+            // Otherwise, decode in unpacked mode.
+            borrow_decode::<C, E>(wire_type, value, buf, ctx)
+        }
+    }
+}
+
+impl<'a, C, T, E> DistinguishedBorrowDecoder<'a, Unpacked<E>> for C
+where
+    Self: DistinguishedCollection<Item = T>
+        + ValueBorrowDecoder<'a, Packed<E>>
+        + BorrowDecoder<'a, Unpacked<E>>,
+    T: NewForOverwrite + Eq + DistinguishedValueBorrowDecoder<'a, E>,
+{
+    fn borrow_decode_distinguished(
+        wire_type: WireType,
+        duplicated: bool,
+        value: &mut C,
+        buf: Capped<&'a [u8]>,
+        ctx: DecodeContext,
+    ) -> Result<Canonicity, DecodeError> {
+        if duplicated {
+            return Err(DecodeError::new(UnexpectedlyRepeated));
+        }
+        if wire_type == WireType::LengthDelimited
+            && <T as Wiretyped<E>>::WIRE_TYPE != WireType::LengthDelimited
+        {
+            // We've encountered a length-delimited field when we aren't expecting one; try decoding
+            // it in packed format instead.
+            // The data is already known to be non-canonical; use expedient decoding
+            <C as ValueBorrowDecoder<Packed<E>>>::borrow_decode_value(value, buf, ctx)?;
+            Ok(Canonicity::NotCanonical)
+        } else {
+            // Otherwise, decode in unpacked mode.
+            borrow_decode_distinguished::<C, E>(wire_type, value, buf, ctx)
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use alloc::string::String;
@@ -1,12 +1,19 @@
-use alloc::borrow::Cow;
+use alloc::borrow::{Cow, ToOwned};
+use alloc::boxed::Box;
 use alloc::collections::{btree_map, btree_set, BTreeMap, BTreeSet};
+use alloc::string::String;
 use alloc::vec::Vec;
 use core::cmp::Ordering::{Equal, Greater, Less};
 #[cfg(feature = "std")]
 use std::collections::{hash_map, hash_set, HashMap, HashSet};
 
+use bytes::Bytes;
+
+use crate::descriptor::EnumerationDescriptor;
 use crate::DecodeErrorKind::UnexpectedlyRepeated;
-use crate::{Canonicity, DecodeErrorKind};
+use crate::{
+    Blob, CanonicalNanF32, CanonicalNanF64, Canonicity, DecodeErrorKind, TotalF32, TotalF64,
+};
 
 /// Trait for cheaply producing a new value that will always be overwritten or decoded into, rather
 /// than a value that is definitely empty. This is implemented for types that can be present
@@ -62,6 +69,9 @@ impl<T> EmptyState for Option<T> {
 
 /// Proxy trait for enumeration types conversions to and from `u32`
 pub trait Enumeration: Eq + Sized {
+    /// Describes the variants of the enumeration.
+    const DESCRIPTOR: &'static EnumerationDescriptor;
+
     /// Gets the numeric value of the enumeration.
     fn to_number(&self) -> u32;
 
@@ -72,28 +82,109 @@ pub trait Enumeration: Eq + Sized {
     fn is_valid(n: u32) -> bool;
 }
 
+/// Marker trait for types whose `Ord` implementation is the standardized canonical ordering of
+/// their values, which is the order they must appear in when they are the items of a set or the
+/// keys of a mapping in distinguished mode.
+///
+/// This is implemented for the standard types that have a standardized ordering, and can be derived
+/// for messages, enumerations, and oneofs along with their `PartialOrd` and `Ord` implementations:
+///
+/// * Enumerations are ordered by their numbers.
+/// * Oneofs are ordered first by the tag of the field that is set, with no field set ordering
+///   first, then by the value of that field.
+/// * Messages are ordered by the values of their fields in ascending order of their tags, each
+///   compared by its own canonical ordering; a oneof is compared at the position of its lowest tag.
+///
+/// Implementing this trait by hand is a promise that the type's `Ord` follows the same rules, so
+/// that its values are ordered the same way in every implementation of the schema.
+pub trait CanonicalOrd: Ord {}
+
+macro_rules! canonical_ord {
+    ($($ty:ty),* $(,)?) => {
+        $(impl CanonicalOrd for $ty {})*
+    };
+}
+
+canonical_ord!(
+    (),
+    bool,
+    u8,
+    u16,
+    u32,
+    u64,
+    u128,
+    i8,
+    i16,
+    i32,
+    i64,
+    i128,
+    str,
+    String,
+    Bytes,
+    Blob,
+    TotalF32,
+    TotalF64,
+    CanonicalNanF32,
+    CanonicalNanF64,
+);
+
+impl<T: CanonicalOrd + ?Sized> CanonicalOrd for &T {}
+impl<T: CanonicalOrd + ?Sized> CanonicalOrd for Box<T> {}
+impl<T: CanonicalOrd + ToOwned + ?Sized> CanonicalOrd for Cow<'_, T> {}
+impl<T: CanonicalOrd> CanonicalOrd for Option<T> {}
+impl<T: CanonicalOrd> CanonicalOrd for [T] {}
+impl<T: CanonicalOrd, const N: usize> CanonicalOrd for [T; N] {}
+impl<T: CanonicalOrd> CanonicalOrd for Vec<T> {}
+impl<T: CanonicalOrd> CanonicalOrd for BTreeSet<T> {}
+impl<K: CanonicalOrd, V: CanonicalOrd> CanonicalOrd for BTreeMap<K, V> {}
+#[cfg(feature = "bytestring")]
+impl CanonicalOrd for bytestring::ByteString {}
+#[cfg(feature = "smallvec")]
+impl<T: CanonicalOrd, A: smallvec::Array<Item = T>> CanonicalOrd for smallvec::SmallVec<A> {}
+#[cfg(feature = "thin-vec")]
+impl<T: CanonicalOrd> CanonicalOrd for thin_vec::ThinVec<T> {}
+#[cfg(feature = "tinyvec")]
+impl<T: CanonicalOrd, A: tinyvec::Array<Item = T>> CanonicalOrd for tinyvec::TinyVec<A> {}
+
 /// Trait for containers that store multiple items such as `Vec`, `BTreeSet`, and `HashSet`
 pub trait Collection: EmptyState {
+    /// True if the collection keeps its items in an order of its own rather than the order they
+    /// were inserted in, as sets do.
+    const UNORDERED: bool = false;
+
     type Item;
     type RefIter<'a>: ExactSizeIterator<Item = &'a Self::Item>
+    where
+        Self::Item: 'a,
+        Self: 'a;
+    type ReverseIter<'a>: Iterator<Item = &'a Self::Item>
     where
         Self::Item: 'a,
         Self: 'a;
 
     fn len(&self) -> usize;
     fn iter(&self) -> Self::RefIter<'_>;
+    /// Iterates the items in the reverse of the order yielded by `iter`.
+    fn reversed(&self) -> Self::ReverseIter<'_>;
     fn insert(&mut self, item: Self::Item) -> Result<(), DecodeErrorKind>;
+    /// Adds the items decoded from a single input when merging new data into an existing
+    /// collection. By default this inserts each item in turn; sets reject items repeated within
+    /// `new` but ignore items they already contain.
+    #[inline]
+    fn merge_extend<I: IntoIterator<Item = Self::Item>>(
+        &mut self,
+        new: I,
+    ) -> Result<(), DecodeErrorKind> {
+        for item in new {
+            self.insert(item)?;
+        }
+        Ok(())
+    }
 }
 
 /// Trait for collections that store multiple items and have a distinguished representation, such as
 /// `Vec` and `BTreeSet`. Returns an error if the items are inserted in the wrong order.
 pub trait DistinguishedCollection: Collection + Eq {
-    type ReverseIter<'a>: Iterator<Item = &'a Self::Item>
-    where
-        Self::Item: 'a,
-        Self: 'a;
-
-    fn reversed(&self) -> Self::ReverseIter<'_>;
     fn insert_distinguished(&mut self, item: Self::Item) -> Result<Canonicity, DecodeErrorKind>;
 }
 
@@ -102,6 +193,11 @@ pub trait Mapping: EmptyState {
     type Key;
     type Value;
     type RefIter<'a>: ExactSizeIterator<Item = (&'a Self::Key, &'a Self::Value)>
+    where
+        Self::Key: 'a,
+        Self::Value: 'a,
+        Self: 'a;
+    type ReverseIter<'a>: Iterator<Item = (&'a Self::Key, &'a Self::Value)>
     where
         Self::Key: 'a,
         Self::Value: 'a,
@@ -113,19 +209,29 @@ pub trait Mapping: EmptyState {
         self.len() == 0
     }
     fn iter(&self) -> Self::RefIter<'_>;
+    /// Iterates the entries in the reverse of the order yielded by `iter`.
+    fn reversed(&self) -> Self::ReverseIter<'_>;
     fn insert(&mut self, key: Self::Key, value: Self::Value) -> Result<(), DecodeErrorKind>;
+    /// Adds the entries decoded from a single input when merging new data into an existing
+    /// mapping. By default this inserts each entry in turn; maps reject keys repeated within `new`
+    /// but replace the value of any entry that already has the same key.
+    #[inline]
+    fn merge_extend<I: IntoIterator<Item = (Self::Key, Self::Value)>>(
+        &mut self,
+        new: I,
+    ) -> Result<(), DecodeErrorKind> {
+        for (key, value) in new {
+            self.insert(key, value)?;
+        }
+        Ok(())
+    }
+    /// Removes the entry with the given key, if there is one.
+    fn remove(&mut self, key: &Self::Key);
 }
 
 /// Trait for associative containers with a distinguished representation. Returns an error if the
 /// items are inserted in the wrong order.
 pub trait DistinguishedMapping: Mapping {
-    type ReverseIter<'a>: Iterator<Item = (&'a Self::Key, &'a Self::Value)>
-    where
-        Self::Key: 'a,
-        Self::Value: 'a,
-        Self: 'a;
-
-    fn reversed(&self) -> Self::ReverseIter<'_>;
     fn insert_distinguished(
         &mut self,
         key: Self::Key,
@@ -152,10 +258,16 @@ impl<T> EmptyState for Vec<T> {
 
 impl<T> Collection for Vec<T> {
     type Item = T;
-    type RefIter<'a> = core::slice::Iter<'a, T>
-        where
-            T: 'a,
-            Self: 'a;
+    type RefIter<'a>
+        = core::slice::Iter<'a, T>
+    where
+        T: 'a,
+        Self: 'a;
+    type ReverseIter<'a>
+        = core::iter::Rev<core::slice::Iter<'a, T>>
+    where
+        Self::Item: 'a,
+        Self: 'a;
 
     #[inline]
     fn len(&self) -> usize {
@@ -167,6 +279,11 @@ impl<T> Collection for Vec<T> {
         <[T]>::iter(self)
     }
 
+    #[inline]
+    fn reversed(&self) -> Self::ReverseIter<'_> {
+        <[T]>::iter(self).rev()
+    }
+
     #[inline]
     fn insert(&mut self, item: T) -> Result<(), DecodeErrorKind> {
         Vec::push(self, item);
@@ -178,16 +295,6 @@ impl<T> DistinguishedCollection for Vec<T>
 where
     T: Eq,
 {
-    type ReverseIter<'a> = core::iter::Rev<core::slice::Iter<'a, T>>
-        where
-            Self::Item: 'a,
-            Self: 'a;
-
-    #[inline]
-    fn reversed(&self) -> Self::ReverseIter<'_> {
-        <[T]>::iter(self).rev()
-    }
-
     #[inline]
     fn insert_distinguished(&mut self, item: Self::Item) -> Result<Canonicity, DecodeErrorKind> {
         Vec::push(self, item);
@@ -227,10 +334,16 @@ where
     T: Clone,
 {
     type Item = T;
-    type RefIter<'a> = core::slice::Iter<'a, T>
-        where
-            T: 'a,
-            Self: 'a;
+    type RefIter<'a>
+        = core::slice::Iter<'a, T>
+    where
+        T: 'a,
+        Self: 'a;
+    type ReverseIter<'a>
+        = core::iter::Rev<core::slice::Iter<'a, T>>
+    where
+        Self::Item: 'a,
+        Self: 'a;
     fn len(&self) -> usize {
         <[T]>::len(self)
     }
@@ -239,6 +352,11 @@ where
         <[T]>::iter(self)
     }
 
+    #[inline]
+    fn reversed(&self) -> Self::ReverseIter<'_> {
+        <[T]>::iter(self).rev()
+    }
+
     fn insert(&mut self, item: Self::Item) -> Result<(), DecodeErrorKind> {
         self.to_mut().push(item);
         Ok(())
@@ -249,16 +367,6 @@ impl<T> DistinguishedCollection for Cow<'_, [T]>
 where
     T: Clone + Eq,
 {
-    type ReverseIter<'a> = core::iter::Rev<core::slice::Iter<'a, T>>
-        where
-            Self::Item: 'a,
-            Self: 'a;
-
-    #[inline]
-    fn reversed(&self) -> Self::ReverseIter<'_> {
-        <[T]>::iter(self).rev()
-    }
-
     #[inline]
     fn insert_distinguished(&mut self, item: Self::Item) -> Result<Canonicity, DecodeErrorKind> {
         self.to_mut().push(item);
@@ -287,10 +395,16 @@ impl<T, A: smallvec::Array<Item = T>> EmptyState for smallvec::SmallVec<A> {
 #[cfg(feature = "smallvec")]
 impl<T, A: smallvec::Array<Item = T>> Collection for smallvec::SmallVec<A> {
     type Item = T;
-    type RefIter<'a> = core::slice::Iter<'a, T>
-        where
-            T: 'a,
-            Self: 'a;
+    type RefIter<'a>
+        = core::slice::Iter<'a, T>
+    where
+        T: 'a,
+        Self: 'a;
+    type ReverseIter<'a>
+        = core::iter::Rev<core::slice::Iter<'a, T>>
+    where
+        Self::Item: 'a,
+        Self: 'a;
 
     #[inline]
     fn len(&self) -> usize {
@@ -302,6 +416,11 @@ impl<T, A: smallvec::Array<Item = T>> Collection for smallvec::SmallVec<A> {
         <[T]>::iter(self)
     }
 
+    #[inline]
+    fn reversed(&self) -> Self::ReverseIter<'_> {
+        <[T]>::iter(self).rev()
+    }
+
     #[inline]
     fn insert(&mut self, item: T) -> Result<(), DecodeErrorKind> {
         smallvec::SmallVec::push(self, item);
@@ -314,16 +433,6 @@ impl<T, A: smallvec::Array<Item = T>> DistinguishedCollection for smallvec::Smal
 where
     T: Eq,
 {
-    type ReverseIter<'a> = core::iter::Rev<core::slice::Iter<'a, T>>
-        where
-            Self::Item: 'a,
-            Self: 'a;
-
-    #[inline]
-    fn reversed(&self) -> Self::ReverseIter<'_> {
-        <[T]>::iter(self).rev()
-    }
-
     #[inline]
     fn insert_distinguished(&mut self, item: Self::Item) -> Result<Canonicity, DecodeErrorKind> {
         smallvec::SmallVec::push(self, item);
@@ -352,10 +461,16 @@ impl<T> EmptyState for thin_vec::ThinVec<T> {
 #[cfg(feature = "thin-vec")]
 impl<T> Collection for thin_vec::ThinVec<T> {
     type Item = T;
-    type RefIter<'a> = core::slice::Iter<'a, T>
-        where
-            T: 'a,
-            Self: 'a;
+    type RefIter<'a>
+        = core::slice::Iter<'a, T>
+    where
+        T: 'a,
+        Self: 'a;
+    type ReverseIter<'a>
+        = core::iter::Rev<core::slice::Iter<'a, T>>
+    where
+        Self::Item: 'a,
+        Self: 'a;
 
     #[inline]
     fn len(&self) -> usize {
@@ -367,6 +482,11 @@ impl<T> Collection for thin_vec::ThinVec<T> {
         <[T]>::iter(self)
     }
 
+    #[inline]
+    fn reversed(&self) -> Self::ReverseIter<'_> {
+        <[T]>::iter(self).rev()
+    }
+
     #[inline]
     fn insert(&mut self, item: T) -> Result<(), DecodeErrorKind> {
         thin_vec::ThinVec::push(self, item);
@@ -379,16 +499,6 @@ impl<T> DistinguishedCollection for thin_vec::ThinVec<T>
 where
     T: Eq,
 {
-    type ReverseIter<'a> = core::iter::Rev<core::slice::Iter<'a, T>>
-        where
-            Self::Item: 'a,
-            Self: 'a;
-
-    #[inline]
-    fn reversed(&self) -> Self::ReverseIter<'_> {
-        <[T]>::iter(self).rev()
-    }
-
     #[inline]
     fn insert_distinguished(&mut self, item: Self::Item) -> Result<Canonicity, DecodeErrorKind> {
         thin_vec::ThinVec::push(self, item);
@@ -417,10 +527,16 @@ impl<T, A: tinyvec::Array<Item = T>> EmptyState for tinyvec::TinyVec<A> {
 #[cfg(feature = "tinyvec")]
 impl<T, A: tinyvec::Array<Item = T>> Collection for tinyvec::TinyVec<A> {
     type Item = T;
-    type RefIter<'a> = core::slice::Iter<'a, T>
-        where
-            T: 'a,
-            Self: 'a;
+    type RefIter<'a>
+        = core::slice::Iter<'a, T>
+    where
+        T: 'a,
+        Self: 'a;
+    type ReverseIter<'a>
+        = core::iter::Rev<core::slice::Iter<'a, T>>
+    where
+        Self::Item: 'a,
+        Self: 'a;
 
     #[inline]
     fn len(&self) -> usize {
@@ -432,6 +548,11 @@ impl<T, A: tinyvec::Array<Item = T>> Collection for tinyvec::TinyVec<A> {
         <[T]>::iter(self)
     }
 
+    #[inline]
+    fn reversed(&self) -> Self::ReverseIter<'_> {
+        <[T]>::iter(self).rev()
+    }
+
     #[inline]
     fn insert(&mut self, item: T) -> Result<(), DecodeErrorKind> {
         tinyvec::TinyVec::push(self, item);
@@ -444,16 +565,6 @@ impl<T, A: tinyvec::Array<Item = T>> DistinguishedCollection for tinyvec::TinyVe
 where
     T: Eq,
 {
-    type ReverseIter<'a> = core::iter::Rev<core::slice::Iter<'a, T>>
-        where
-            Self::Item: 'a,
-            Self: 'a;
-
-    #[inline]
-    fn reversed(&self) -> Self::ReverseIter<'_> {
-        <[T]>::iter(self).rev()
-    }
-
     #[inline]
     fn insert_distinguished(&mut self, item: Self::Item) -> Result<Canonicity, DecodeErrorKind> {
         tinyvec::TinyVec::push(self, item);
@@ -482,11 +593,19 @@ impl<T> Collection for BTreeSet<T>
 where
     T: Ord,
 {
+    const UNORDERED: bool = true;
+
     type Item = T;
-    type RefIter<'a> = btree_set::Iter<'a, T>
-        where
-            Self::Item: 'a,
-            Self: 'a;
+    type RefIter<'a>
+        = btree_set::Iter<'a, T>
+    where
+        Self::Item: 'a,
+        Self: 'a;
+    type ReverseIter<'a>
+        = core::iter::Rev<btree_set::Iter<'a, T>>
+    where
+        Self::Item: 'a,
+        Self: 'a;
 
     #[inline]
     fn len(&self) -> usize {
@@ -498,6 +617,11 @@ where
         BTreeSet::iter(self)
     }
 
+    #[inline]
+    fn reversed(&self) -> Self::ReverseIter<'_> {
+        BTreeSet::iter(self).rev()
+    }
+
     #[inline]
     fn insert(&mut self, item: Self::Item) -> Result<(), DecodeErrorKind> {
         if !BTreeSet::insert(self, item) {
@@ -505,22 +629,22 @@ where
         }
         Ok(())
     }
+
+    #[inline]
+    fn merge_extend<I: IntoIterator<Item = T>>(&mut self, new: I) -> Result<(), DecodeErrorKind> {
+        let mut new_items = Self::empty();
+        for item in new {
+            Collection::insert(&mut new_items, item)?;
+        }
+        self.extend(new_items);
+        Ok(())
+    }
 }
 
 impl<T> DistinguishedCollection for BTreeSet<T>
 where
-    T: Ord,
+    T: CanonicalOrd,
 {
-    type ReverseIter<'a> = core::iter::Rev<btree_set::Iter<'a, T>>
-        where
-            Self::Item: 'a,
-            Self: 'a;
-
-    #[inline]
-    fn reversed(&self) -> Self::ReverseIter<'_> {
-        BTreeSet::iter(self).rev()
-    }
-
     #[inline]
     fn insert_distinguished(&mut self, item: Self::Item) -> Result<Canonicity, DecodeErrorKind> {
         // MSRV: can't use .last()
@@ -564,11 +688,19 @@ impl<T> Collection for HashSet<T>
 where
     T: Eq + core::hash::Hash,
 {
+    const UNORDERED: bool = true;
+
     type Item = T;
-    type RefIter<'a> = hash_set::Iter<'a, T>
-        where
-            Self::Item: 'a,
-            Self: 'a;
+    type RefIter<'a>
+        = hash_set::Iter<'a, T>
+    where
+        Self::Item: 'a,
+        Self: 'a;
+    type ReverseIter<'a>
+        = alloc::vec::IntoIter<&'a T>
+    where
+        Self::Item: 'a,
+        Self: 'a;
 
     #[inline]
     fn len(&self) -> usize {
@@ -580,6 +712,14 @@ where
         HashSet::iter(self)
     }
 
+    #[inline]
+    fn reversed(&self) -> Self::ReverseIter<'_> {
+        // Hash sets have no reversible iterator
+        let mut items: Vec<&T> = HashSet::iter(self).collect();
+        items.reverse();
+        items.into_iter()
+    }
+
     #[inline]
     fn insert(&mut self, item: Self::Item) -> Result<(), DecodeErrorKind> {
         if !HashSet::insert(self, item) {
@@ -587,6 +727,16 @@ where
         }
         Ok(())
     }
+
+    #[inline]
+    fn merge_extend<I: IntoIterator<Item = T>>(&mut self, new: I) -> Result<(), DecodeErrorKind> {
+        let mut new_items = Self::empty();
+        for item in new {
+            Collection::insert(&mut new_items, item)?;
+        }
+        self.extend(new_items);
+        Ok(())
+    }
 }
 
 #[cfg(feature = "hashbrown")]
@@ -612,11 +762,19 @@ impl<T> Collection for hashbrown::HashSet<T>
 where
     T: Eq + core::hash::Hash,
 {
+    const UNORDERED: bool = true;
+
     type Item = T;
-    type RefIter<'a> = hashbrown::hash_set::Iter<'a, T>
-        where
-            Self::Item: 'a,
-            Self: 'a;
+    type RefIter<'a>
+        = hashbrown::hash_set::Iter<'a, T>
+    where
+        Self::Item: 'a,
+        Self: 'a;
+    type ReverseIter<'a>
+        = alloc::vec::IntoIter<&'a T>
+    where
+        Self::Item: 'a,
+        Self: 'a;
 
     #[inline]
     fn len(&self) -> usize {
@@ -628,6 +786,14 @@ where
         hashbrown::HashSet::iter(self)
     }
 
+    #[inline]
+    fn reversed(&self) -> Self::ReverseIter<'_> {
+        // Hash sets have no reversible iterator
+        let mut items: Vec<&T> = hashbrown::HashSet::iter(self).collect();
+        items.reverse();
+        items.into_iter()
+    }
+
     #[inline]
     fn insert(&mut self, item: Self::Item) -> Result<(), DecodeErrorKind> {
         if !hashbrown::HashSet::insert(self, item) {
@@ -635,6 +801,16 @@ where
         }
         Ok(())
     }
+
+    #[inline]
+    fn merge_extend<I: IntoIterator<Item = T>>(&mut self, new: I) -> Result<(), DecodeErrorKind> {
+        let mut new_items = Self::empty();
+        for item in new {
+            Collection::insert(&mut new_items, item)?;
+        }
+        self.extend(new_items);
+        Ok(())
+    }
 }
 
 impl<K, V> EmptyState for BTreeMap<K, V> {
@@ -660,11 +836,18 @@ where
 {
     type Key = K;
     type Value = V;
-    type RefIter<'a> = btree_map::Iter<'a, K, V>
-        where
-            K: 'a,
-            V: 'a,
-            Self: 'a;
+    type RefIter<'a>
+        = btree_map::Iter<'a, K, V>
+    where
+        K: 'a,
+        V: 'a,
+        Self: 'a;
+    type ReverseIter<'a>
+        = core::iter::Rev<btree_map::Iter<'a, K, V>>
+    where
+        K: 'a,
+        V: 'a,
+        Self: 'a;
 
     #[inline]
     fn len(&self) -> usize {
@@ -676,6 +859,11 @@ where
         BTreeMap::iter(self)
     }
 
+    #[inline]
+    fn reversed(&self) -> Self::ReverseIter<'_> {
+        BTreeMap::iter(self).rev()
+    }
+
     #[inline]
     fn insert(&mut self, key: K, value: V) -> Result<(), DecodeErrorKind> {
         if let btree_map::Entry::Vacant(entry) = self.entry(key) {
@@ -685,24 +873,31 @@ where
             Err(UnexpectedlyRepeated)
         }
     }
+
+    #[inline]
+    fn merge_extend<I: IntoIterator<Item = (K, V)>>(
+        &mut self,
+        new: I,
+    ) -> Result<(), DecodeErrorKind> {
+        let mut new_entries = Self::empty();
+        for (key, value) in new {
+            Mapping::insert(&mut new_entries, key, value)?;
+        }
+        self.extend(new_entries);
+        Ok(())
+    }
+
+    #[inline]
+    fn remove(&mut self, key: &K) {
+        BTreeMap::remove(self, key);
+    }
 }
 
 impl<K, V> DistinguishedMapping for BTreeMap<K, V>
 where
     Self: Eq,
-    K: Ord,
+    K: CanonicalOrd,
 {
-    type ReverseIter<'a> = core::iter::Rev<btree_map::Iter<'a, K, V>>
-        where
-            K: 'a,
-            V: 'a,
-            Self: 'a;
-
-    #[inline]
-    fn reversed(&self) -> Self::ReverseIter<'_> {
-        BTreeMap::iter(self).rev()
-    }
-
     #[inline]
     fn insert_distinguished(
         &mut self,
@@ -751,11 +946,18 @@ where
 {
     type Key = K;
     type Value = V;
-    type RefIter<'a> = hash_map::Iter<'a, K, V>
-        where
-            K: 'a,
-            V: 'a,
-            Self: 'a;
+    type RefIter<'a>
+        = hash_map::Iter<'a, K, V>
+    where
+        K: 'a,
+        V: 'a,
+        Self: 'a;
+    type ReverseIter<'a>
+        = alloc::vec::IntoIter<(&'a K, &'a V)>
+    where
+        K: 'a,
+        V: 'a,
+        Self: 'a;
 
     #[inline]
     fn len(&self) -> usize {
@@ -767,6 +969,14 @@ where
         HashMap::iter(self)
     }
 
+    #[inline]
+    fn reversed(&self) -> Self::ReverseIter<'_> {
+        // Hash maps have no reversible iterator
+        let mut items: Vec<(&K, &V)> = HashMap::iter(self).collect();
+        items.reverse();
+        items.into_iter()
+    }
+
     #[inline]
     fn insert(&mut self, key: K, value: V) -> Result<(), DecodeErrorKind> {
         if let hash_map::Entry::Vacant(entry) = self.entry(key) {
@@ -776,6 +986,24 @@ where
             Err(UnexpectedlyRepeated)
         }
     }
+
+    #[inline]
+    fn merge_extend<I: IntoIterator<Item = (K, V)>>(
+        &mut self,
+        new: I,
+    ) -> Result<(), DecodeErrorKind> {
+        let mut new_entries = Self::empty();
+        for (key, value) in new {
+            Mapping::insert(&mut new_entries, key, value)?;
+        }
+        self.extend(new_entries);
+        Ok(())
+    }
+
+    #[inline]
+    fn remove(&mut self, key: &K) {
+        HashMap::remove(self, key);
+    }
 }
 
 #[cfg(feature = "hashbrown")]
@@ -803,11 +1031,18 @@ where
 {
     type Key = K;
     type Value = V;
-    type RefIter<'a> = hashbrown::hash_map::Iter<'a, K, V>
-        where
-            K: 'a,
-            V: 'a,
-            Self: 'a;
+    type RefIter<'a>
+        = hashbrown::hash_map::Iter<'a, K, V>
+    where
+        K: 'a,
+        V: 'a,
+        Self: 'a;
+    type ReverseIter<'a>
+        = alloc::vec::IntoIter<(&'a K, &'a V)>
+    where
+        K: 'a,
+        V: 'a,
+        Self: 'a;
 
     #[inline]
     fn len(&self) -> usize {
@@ -819,6 +1054,14 @@ where
         hashbrown::HashMap::iter(self)
     }
 
+    #[inline]
+    fn reversed(&self) -> Self::ReverseIter<'_> {
+        // Hash maps have no reversible iterator
+        let mut items: Vec<(&K, &V)> = hashbrown::HashMap::iter(self).collect();
+        items.reverse();
+        items.into_iter()
+    }
+
     #[inline]
     fn insert(&mut self, key: K, value: V) -> Result<(), DecodeErrorKind> {
         if let hashbrown::hash_map::Entry::Vacant(entry) = self.entry(key) {
@@ -828,4 +1071,22 @@ where
             Err(UnexpectedlyRepeated)
         }
     }
+
+    #[inline]
+    fn merge_extend<I: IntoIterator<Item = (K, V)>>(
+        &mut self,
+        new: I,
+    ) -> Result<(), DecodeErrorKind> {
+        let mut new_entries = Self::empty();
+        for (key, value) in new {
+            Mapping::insert(&mut new_entries, key, value)?;
+        }
+        self.extend(new_entries);
+        Ok(())
+    }
+
+    #[inline]
+    fn remove(&mut self, key: &K) {
+        hashbrown::HashMap::remove(self, key);
+    }
 }
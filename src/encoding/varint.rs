@@ -1,13 +1,19 @@
+use crate::descriptor::{DescribedEncoding, EncodingDescriptor, ValueKind};
 use crate::encoding::{
-    empty_state_via_default, encode_varint, encoded_len_varint, encoder_where_value_encoder, Buf,
-    BufMut, Canonicity, Capped, DecodeContext, DistinguishedValueEncoder, EmptyState, Encoder,
-    TagMeasurer, TagWriter, ValueEncoder, WireType, Wiretyped,
+    borrow_decoding_via_owned, empty_state_via_default, encode_varint, encoded_len_varint,
+    encoder_where_value_encoder, prepend_varint, Buf, BufMut, Canonicity, Capped, DecodeContext,
+    DistinguishedValueDecoder, DistinguishedValueEncoder, EmptyState, Encoder, ReverseBuffer,
+    TagMeasurer, TagWriter, ValueDecoder, ValueEncoder, WireType, Wiretyped,
 };
 use crate::DecodeError;
 use crate::DecodeErrorKind::OutOfDomainValue;
 
 pub struct Varint;
 
+impl DescribedEncoding for Varint {
+    const DESCRIPTOR: &'static EncodingDescriptor = &EncodingDescriptor::Varint;
+}
+
 encoder_where_value_encoder!(Varint);
 
 /// Zig-zag encoding: These functions implement storing signed in unsigned integers by encoding the
@@ -52,11 +58,22 @@ pub(crate) fn u64_to_signed(value: u64) -> i64 {
     ((value >> 1) as i64) ^ (-((value & 1) as i64))
 }
 
+#[inline]
+pub(crate) fn i128_to_unsigned(value: i128) -> u128 {
+    ((value << 1) ^ (value >> 127)) as u128
+}
+
+#[inline]
+pub(crate) fn u128_to_signed(value: u128) -> i128 {
+    ((value >> 1) as i128) ^ (-((value & 1) as i128))
+}
+
 /// Macro which emits implementations for variable width numeric encoding.
 macro_rules! varint {
     (
         $name:ident,
         $ty:ty,
+        $kind:ident,
         to_uint64($to_uint64_value:ident) $to_uint64:expr,
         from_uint64($from_uint64_value:ident) $from_uint64:expr
     ) => {
@@ -67,16 +84,25 @@ macro_rules! varint {
         }
 
         impl ValueEncoder<Varint> for $ty {
+            const VALUE_KIND: &'static ValueKind = &ValueKind::$kind;
+
             #[inline]
             fn encode_value<B: BufMut + ?Sized>($to_uint64_value: &$ty, buf: &mut B) {
                 encode_varint($to_uint64, buf);
             }
 
+            #[inline]
+            fn prepend_value($to_uint64_value: &$ty, buf: &mut ReverseBuffer) {
+                prepend_varint($to_uint64, buf);
+            }
+
             #[inline]
             fn value_encoded_len($to_uint64_value: &$ty) -> usize {
                 encoded_len_varint($to_uint64)
             }
+        }
 
+        impl ValueDecoder<Varint> for $ty {
             #[inline]
             fn decode_value<B: Buf + ?Sized>(
                 __value: &mut $ty,
@@ -89,7 +115,9 @@ macro_rules! varint {
             }
         }
 
-        impl DistinguishedValueEncoder<Varint> for $ty {
+        impl DistinguishedValueEncoder<Varint> for $ty {}
+
+        impl DistinguishedValueDecoder<Varint> for $ty {
             #[inline]
             fn decode_value_distinguished<B: Buf + ?Sized>(
                 value: &mut $ty,
@@ -97,7 +125,7 @@ macro_rules! varint {
                 allow_empty: bool,
                 ctx: DecodeContext,
             ) -> Result<Canonicity, DecodeError> {
-                ValueEncoder::<Varint>::decode_value(value, buf, ctx)?;
+                ValueDecoder::<Varint>::decode_value(value, buf, ctx)?;
                 Ok(if !allow_empty && value.is_empty() {
                     Canonicity::NotCanonical
                 } else {
@@ -106,6 +134,8 @@ macro_rules! varint {
             }
         }
 
+        borrow_decoding_via_owned!(Varint, $ty, including distinguished);
+
         #[cfg(test)]
         mod $name {
             use crate::encoding::Varint;
@@ -115,7 +145,7 @@ macro_rules! varint {
     };
 }
 
-varint!(varint_bool, bool,
+varint!(varint_bool, bool, Bool,
 to_uint64(value) {
     u64::from(*value)
 },
@@ -127,7 +157,7 @@ from_uint64(value) {
     }
 });
 
-varint!(varint_u8, u8,
+varint!(varint_u8, u8, U8,
 to_uint64(value) {
     *value as u64
 },
@@ -135,7 +165,7 @@ from_uint64(value) {
     u8::try_from(value).map_err(|_| DecodeError::new(OutOfDomainValue))?
 });
 
-varint!(varint_u16, u16,
+varint!(varint_u16, u16, U16,
 to_uint64(value) {
     *value as u64
 },
@@ -143,7 +173,7 @@ from_uint64(value) {
     u16::try_from(value).map_err(|_| DecodeError::new(OutOfDomainValue))?
 });
 
-varint!(varint_u32, u32,
+varint!(varint_u32, u32, U32,
 to_uint64(value) {
     *value as u64
 },
@@ -151,7 +181,7 @@ from_uint64(value) {
     u32::try_from(value).map_err(|_| DecodeError::new(OutOfDomainValue))?
 });
 
-varint!(varint_u64, u64,
+varint!(varint_u64, u64, U64,
 to_uint64(value) {
     *value
 },
@@ -159,7 +189,7 @@ from_uint64(value) {
     value
 });
 
-varint!(varint_i8, i8,
+varint!(varint_i8, i8, I8,
 to_uint64(value) {
     i8_to_unsigned(*value) as u64
 },
@@ -169,7 +199,7 @@ from_uint64(value) {
     u8_to_signed(value)
 });
 
-varint!(varint_i16, i16,
+varint!(varint_i16, i16, I16,
 to_uint64(value) {
     i16_to_unsigned(*value) as u64
 },
@@ -179,7 +209,7 @@ from_uint64(value) {
     u16_to_signed(value)
 });
 
-varint!(varint_i32, i32,
+varint!(varint_i32, i32, I32,
 to_uint64(value) {
     i32_to_unsigned(*value) as u64
 },
@@ -189,7 +219,7 @@ from_uint64(value) {
     u32_to_signed(value)
 });
 
-varint!(varint_i64, i64,
+varint!(varint_i64, i64, I64,
 to_uint64(value) {
     i64_to_unsigned(*value)
 },
@@ -0,0 +1,259 @@
+//! Signed envelopes for distinguished messages.
+//!
+//! A `Signed<M>` carries the encoding of a message along with the id of the key that signed it and
+//! the signature over those exact bytes. Because `M` is a `DistinguishedMessage`, its encoding is
+//! unique to its value, and an envelope is only ever opened when its payload decodes canonically;
+//! a valid signature therefore vouches for exactly one value of the message, and can never be
+//! carried over to a different encoding of it that has extra fields or non-canonical values.
+//!
+//! Signing algorithms are provided by implementing the `Signer` and `Verifier` traits.
+
+use alloc::vec::Vec;
+use core::fmt;
+use core::marker::PhantomData;
+
+use bytes::{Buf, BufMut, Bytes};
+
+use crate::descriptor::{DescribedEncoding, FieldDescriptor, MessageDescriptor};
+use crate::encoding::{
+    skip_field, Capped, DecodeContext, Decoder, DistinguishedDecoder, EmptyState, Encoder, General,
+    ReverseBuffer, TagMeasurer, TagRevWriter, TagWriter, WireType,
+};
+use crate::message::{
+    DistinguishedOwnedMessage, RawDistinguishedMessage, RawDistinguishedMessageDecoder, RawMessage,
+    RawMessageDecoder,
+};
+use crate::DecodeErrorKind::InvalidSignature;
+use crate::{Canonicity, DecodeError, DistinguishedMessage, Message, WithCanonicity};
+
+/// Produces signatures for the payloads of signed envelopes.
+pub trait Signer {
+    /// Returns the id of the signing key, which is stored in the envelope so that verifiers can
+    /// find the key to check the signature with.
+    fn key_id(&self) -> Bytes;
+
+    /// Returns the signature of the given payload.
+    fn sign(&self, payload: &[u8]) -> Vec<u8>;
+}
+
+/// Checks the signatures of signed envelopes.
+pub trait Verifier {
+    /// Returns true if `signature` is a valid signature of `payload` by the key with the given id.
+    /// Keys that are not known to the verifier should never be valid.
+    fn verify(&self, key_id: &[u8], payload: &[u8], signature: &[u8]) -> bool;
+}
+
+/// An envelope holding the signed encoding of a distinguished message of type `M`.
+///
+/// The envelope is itself a message, which encodes the payload in field 1, the key id in field 2,
+/// and the signature in field 3. Envelopes can be decoded and passed around without verifying
+/// them, but the message inside can only be reached through `open`, which checks the signature
+/// first.
+pub struct Signed<M> {
+    payload: Bytes,
+    key_id: Bytes,
+    signature: Bytes,
+    _message: PhantomData<fn() -> M>,
+}
+
+impl<M: DistinguishedMessage> Signed<M> {
+    /// Encodes the message and signs its encoding with the given signer.
+    pub fn sign<S: Signer + ?Sized>(message: &M, signer: &S) -> Self {
+        let payload = message.encode_to_bytes();
+        let signature = signer.sign(&payload).into();
+        Self {
+            payload,
+            key_id: signer.key_id(),
+            signature,
+            _message: PhantomData,
+        }
+    }
+}
+
+impl<M: DistinguishedOwnedMessage> Signed<M> {
+    /// Verifies the signature of the envelope and returns the message inside it.
+    ///
+    /// An `InvalidSignature` error is returned if the verifier does not accept the signature. The
+    /// payload is then decoded in distinguished mode, and if it is not fully canonical an error is
+    /// returned as well, even though the signature is valid.
+    pub fn open<V: Verifier + ?Sized>(&self, verifier: &V) -> Result<M, DecodeError> {
+        if !verifier.verify(&self.key_id, &self.payload, &self.signature) {
+            return Err(DecodeError::new(InvalidSignature));
+        }
+        Ok(M::decode_distinguished(self.payload.clone()).canonical()?)
+    }
+
+    /// Decodes an envelope from the buffer and opens it with the given verifier.
+    pub fn decode_verified<B: Buf, V: Verifier + ?Sized>(
+        buf: B,
+        verifier: &V,
+    ) -> Result<M, DecodeError> {
+        <Self as Message>::decode(buf)?.open(verifier)
+    }
+}
+
+impl<M> Signed<M> {
+    /// Returns the encoded message that was signed. This has not necessarily been verified.
+    pub fn payload(&self) -> &Bytes {
+        &self.payload
+    }
+
+    /// Returns the id of the key that signed the payload.
+    pub fn key_id(&self) -> &Bytes {
+        &self.key_id
+    }
+
+    /// Returns the signature of the payload.
+    pub fn signature(&self) -> &Bytes {
+        &self.signature
+    }
+}
+
+impl<M> Clone for Signed<M> {
+    fn clone(&self) -> Self {
+        Self {
+            payload: self.payload.clone(),
+            key_id: self.key_id.clone(),
+            signature: self.signature.clone(),
+            _message: PhantomData,
+        }
+    }
+}
+
+impl<M> fmt::Debug for Signed<M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Signed")
+            .field("payload", &self.payload)
+            .field("key_id", &self.key_id)
+            .field("signature", &self.signature)
+            .finish()
+    }
+}
+
+impl<M> PartialEq for Signed<M> {
+    fn eq(&self, other: &Self) -> bool {
+        self.payload == other.payload
+            && self.key_id == other.key_id
+            && self.signature == other.signature
+    }
+}
+
+impl<M> Eq for Signed<M> {}
+
+impl<M> EmptyState for Signed<M> {
+    fn empty() -> Self {
+        Self {
+            payload: Bytes::new(),
+            key_id: Bytes::new(),
+            signature: Bytes::new(),
+            _message: PhantomData,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.payload.is_empty() && self.key_id.is_empty() && self.signature.is_empty()
+    }
+
+    fn clear(&mut self) {
+        *self = Self::empty();
+    }
+}
+
+impl<M> RawMessage for Signed<M> {
+    const __ASSERTIONS: () = ();
+    const DESCRIPTOR: &'static MessageDescriptor = &MessageDescriptor {
+        name: "Signed",
+        fields: &[
+            FieldDescriptor {
+                name: "payload",
+                tag: 1,
+                encoding: <General as DescribedEncoding>::DESCRIPTOR,
+                value: <Bytes as Encoder<General>>::VALUE_KIND,
+                recurses: false,
+            },
+            FieldDescriptor {
+                name: "key_id",
+                tag: 2,
+                encoding: <General as DescribedEncoding>::DESCRIPTOR,
+                value: <Bytes as Encoder<General>>::VALUE_KIND,
+                recurses: false,
+            },
+            FieldDescriptor {
+                name: "signature",
+                tag: 3,
+                encoding: <General as DescribedEncoding>::DESCRIPTOR,
+                value: <Bytes as Encoder<General>>::VALUE_KIND,
+                recurses: false,
+            },
+        ],
+        oneofs: &[],
+        reserved_tags: &[],
+        has_extensions: false,
+    };
+
+    fn raw_encode<B: BufMut + ?Sized>(&self, buf: &mut B) {
+        let tw = &mut TagWriter::new();
+        Encoder::<General>::encode(1, &self.payload, buf, tw);
+        Encoder::<General>::encode(2, &self.key_id, buf, tw);
+        Encoder::<General>::encode(3, &self.signature, buf, tw);
+    }
+
+    fn raw_prepend(&self, buf: &mut ReverseBuffer) {
+        let tw = &mut TagRevWriter::new();
+        Encoder::<General>::prepend_encode(3, &self.signature, buf, tw);
+        Encoder::<General>::prepend_encode(2, &self.key_id, buf, tw);
+        Encoder::<General>::prepend_encode(1, &self.payload, buf, tw);
+        tw.finalize(buf);
+    }
+
+    fn raw_encoded_len(&self) -> usize {
+        let tm = &mut TagMeasurer::new();
+        Encoder::<General>::encoded_len(1, &self.payload, tm)
+            + Encoder::<General>::encoded_len(2, &self.key_id, tm)
+            + Encoder::<General>::encoded_len(3, &self.signature, tm)
+    }
+}
+
+impl<M> RawMessageDecoder for Signed<M> {
+    fn raw_decode_field<B: Buf + ?Sized>(
+        &mut self,
+        tag: u32,
+        wire_type: WireType,
+        duplicated: bool,
+        buf: Capped<B>,
+        ctx: DecodeContext,
+    ) -> Result<(), DecodeError> {
+        match tag {
+            1 => Decoder::<General>::decode(wire_type, duplicated, &mut self.payload, buf, ctx),
+            2 => Decoder::<General>::decode(wire_type, duplicated, &mut self.key_id, buf, ctx),
+            3 => Decoder::<General>::decode(wire_type, duplicated, &mut self.signature, buf, ctx),
+            _ => skip_field(wire_type, buf),
+        }
+    }
+}
+
+impl<M> RawDistinguishedMessage for Signed<M> {}
+
+impl<M> RawDistinguishedMessageDecoder for Signed<M> {
+    fn raw_decode_field_distinguished<B: Buf + ?Sized>(
+        &mut self,
+        tag: u32,
+        wire_type: WireType,
+        duplicated: bool,
+        buf: Capped<B>,
+        ctx: DecodeContext,
+    ) -> Result<Canonicity, DecodeError> {
+        let value = match tag {
+            1 => &mut self.payload,
+            2 => &mut self.key_id,
+            3 => &mut self.signature,
+            _ => {
+                skip_field(wire_type, buf)?;
+                return Ok(Canonicity::HasExtensions);
+            }
+        };
+        DistinguishedDecoder::<General>::decode_distinguished(
+            wire_type, duplicated, value, buf, ctx,
+        )
+    }
+}
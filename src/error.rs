@@ -30,6 +30,12 @@ pub enum DecodeErrorKind {
     RecursionLimitReached,
     /// Size of a length-delimited region exceeds what is supported on this platform.
     Oversize,
+    /// A limit set in the `DecodeOptions` for decoding was exceeded.
+    LimitExceeded,
+    /// A patch operation did not fit the structure of the message it was applied to.
+    InvalidPatch,
+    /// The signature of a signed envelope was not valid for its payload.
+    InvalidSignature,
     /// Something else.
     Other,
 }
@@ -51,6 +57,9 @@ impl fmt::Display for DecodeErrorKind {
             UnknownField => "unknown field",
             RecursionLimitReached => "recursion limit reached",
             Oversize => "region too large to decode",
+            LimitExceeded => "decoding limit exceeded",
+            InvalidPatch => "patch does not fit the message",
+            InvalidSignature => "invalid signature",
             Other => "other error",
         })
     }
@@ -2,7 +2,9 @@
 #![doc(html_root_url = "https://docs.rs/bilrost/0.1005.0-dev")]
 #![doc = include_str!("../README.md")]
 
-extern crate alloc;
+// Re-exported for use by generated code.
+#[doc(hidden)]
+pub extern crate alloc;
 #[cfg(feature = "std")]
 extern crate std;
 
@@ -11,22 +13,45 @@ pub use bytes;
 
 #[cfg(feature = "derive")]
 #[doc(hidden)]
-pub use bilrost_derive::{DistinguishedMessage, DistinguishedOneof, Enumeration, Message, Oneof};
+pub use bilrost_derive::{
+    CanonicalOrd, DistinguishedMessage, DistinguishedOneof, Enumeration, Message, Oneof,
+};
 
+#[cfg(feature = "tokio-util")]
+pub mod codec;
+pub mod compat;
+pub mod descriptor;
+#[cfg(feature = "opaque")]
+pub mod dynamic;
+pub mod envelope;
 mod error;
 mod message;
+pub mod patch;
+pub mod schema;
+#[cfg(feature = "serde")]
+pub mod serde;
+#[cfg(feature = "std")]
+pub mod stream;
 mod types;
 
 #[doc(hidden)]
 pub mod encoding;
 
-pub use crate::encoding::{Canonicity, Enumeration, WithCanonicity};
+pub use crate::encoding::{
+    CanonicalOrd, Canonicity, DecodeOptions, Enumeration, ReverseBuffer, TagFilter, WithCanonicity,
+};
 pub use crate::error::{DecodeError, DecodeErrorKind, EncodeError};
-pub use crate::message::{DistinguishedMessage, Message};
+pub use crate::message::{
+    DistinguishedMessage, DistinguishedOwnedMessage, Message, MessageExtension, OwnedMessage,
+};
 #[doc(hidden)]
-pub use crate::message::{RawDistinguishedMessage, RawMessage};
+pub use crate::message::{
+    RawDistinguishedMessage, RawDistinguishedMessageBorrowDecoder, RawDistinguishedMessageDecoder,
+    RawMessage, RawMessageBorrowDecoder, RawMessageDecoder,
+};
 
-pub use types::Blob;
+pub use patch::{diff, Patch};
+pub use types::{Blob, CachedLen, CanonicalNanF32, CanonicalNanF64, Lazy, TotalF32, TotalF64};
 
 use bytes::{Buf, BufMut};
 #[cfg(feature = "extended-diagnostics")]
@@ -38,6 +63,8 @@ use crate::encoding::{decode_varint, encode_varint, encoded_len_varint};
 // 100 is the default recursion limit in the C++ implementation.
 #[cfg(not(feature = "no-recursion-limit"))]
 const RECURSION_LIMIT: u32 = 100;
+#[cfg(feature = "no-recursion-limit")]
+const RECURSION_LIMIT: u32 = u32::MAX;
 
 /// Encodes a length delimiter to the buffer.
 ///
@@ -1,18 +1,24 @@
 use alloc::boxed::Box;
 use alloc::vec::Vec;
+use core::hash::Hasher;
+use core::ops::Bound;
 
+use bytes::buf::UninitSlice;
 use bytes::{Buf, BufMut, Bytes, BytesMut};
 
+use crate::descriptor::MessageDescriptor;
 use crate::encoding::{
-    encode_varint, encoded_len_varint, Canonicity, Capped, DecodeContext, EmptyState, TagReader,
-    WireType,
+    encode_varint, encoded_len_varint, prepend_varint, skip_field, Canonicity, Capped,
+    DecodeContext, DecodeLimits, DecodeOptions, EmptyState, MergeTracker, ReverseBuffer, TagFilter,
+    TagMeasurer, TagReader, TagRevWriter, TagWriter, WireType,
 };
+use crate::patch::{Patch, PatchOp, PatchWriter};
 use crate::{DecodeError, EncodeError};
 
 /// Merges fields from the given buffer, to its cap, into the given `TaggedDecodable` value.
 /// Implemented as a private standalone method to discourage "merging" as a usage pattern.
 #[inline]
-pub(crate) fn merge<T: RawMessage, B: Buf + ?Sized>(
+pub(crate) fn merge<T: RawMessageDecoder, B: Buf + ?Sized>(
     value: &mut T,
     mut buf: Capped<B>,
     ctx: DecodeContext,
@@ -23,7 +29,36 @@ pub(crate) fn merge<T: RawMessage, B: Buf + ?Sized>(
         let (tag, wire_type) = tr.decode_key(buf.lend())?;
         let duplicated = last_tag == Some(tag);
         last_tag = Some(tag);
-        value.raw_decode_field(tag, wire_type, duplicated, buf.lend(), ctx.clone())?;
+        let Some(field_ctx) = ctx.for_field(tag) else {
+            skip_field(wire_type, buf.lend())?;
+            continue;
+        };
+        value.raw_decode_field(tag, wire_type, duplicated, buf.lend(), field_ctx)?;
+    }
+    Ok(())
+}
+
+/// Merges fields from the given buffer, to its cap, into the given message with the semantics of
+/// `Message::merge_from`: each field is merged into the message's existing values according to its
+/// merge behavior. The given context should be merging.
+#[inline]
+pub(crate) fn merge_update<T: RawMessageDecoder, B: Buf + ?Sized>(
+    value: &mut T,
+    mut buf: Capped<B>,
+    ctx: DecodeContext,
+) -> Result<(), DecodeError> {
+    let tr = &mut TagReader::new();
+    let tracker = &mut MergeTracker::new();
+    let mut last_tag = None::<u32>;
+    while buf.has_remaining()? {
+        let (tag, wire_type) = tr.decode_key(buf.lend())?;
+        let duplicated = last_tag == Some(tag);
+        last_tag = Some(tag);
+        let Some(field_ctx) = ctx.for_field(tag) else {
+            skip_field(wire_type, buf.lend())?;
+            continue;
+        };
+        value.raw_merge_field(tag, wire_type, duplicated, buf.lend(), field_ctx, tracker)?;
     }
     Ok(())
 }
@@ -31,7 +66,7 @@ pub(crate) fn merge<T: RawMessage, B: Buf + ?Sized>(
 /// Merges fields from the given buffer, to its cap, into the given `DistinguishedTaggedDecodable`
 /// value. Implemented as a private standalone method to discourage "merging" as a usage pattern.
 #[inline]
-pub(crate) fn merge_distinguished<T: RawDistinguishedMessage, B: Buf + ?Sized>(
+pub(crate) fn merge_distinguished<T: RawDistinguishedMessageDecoder, B: Buf + ?Sized>(
     value: &mut T,
     mut buf: Capped<B>,
     ctx: DecodeContext,
@@ -48,12 +83,88 @@ pub(crate) fn merge_distinguished<T: RawDistinguishedMessage, B: Buf + ?Sized>(
             wire_type,
             duplicated,
             buf.lend(),
-            ctx.clone(),
+            ctx,
         )?);
     }
     Ok(canon)
 }
 
+/// Merges fields from the given borrowed buffer, to its cap, into the given value. Values decoded
+/// this way may borrow from the buffer.
+#[inline]
+pub(crate) fn merge_borrowed<'a, T: RawMessageBorrowDecoder<'a>>(
+    value: &mut T,
+    mut buf: Capped<&'a [u8]>,
+    ctx: DecodeContext,
+) -> Result<(), DecodeError> {
+    let tr = &mut TagReader::new();
+    let mut last_tag = None::<u32>;
+    while buf.has_remaining()? {
+        let (tag, wire_type) = tr.decode_key(buf.lend())?;
+        let duplicated = last_tag == Some(tag);
+        last_tag = Some(tag);
+        let Some(field_ctx) = ctx.for_field(tag) else {
+            skip_field(wire_type, buf.lend())?;
+            continue;
+        };
+        value.raw_borrow_decode_field(tag, wire_type, duplicated, buf.lend(), field_ctx)?;
+    }
+    Ok(())
+}
+
+/// Merges fields from the given borrowed buffer, to its cap, into the given value in distinguished
+/// mode. Values decoded this way may borrow from the buffer.
+#[inline]
+pub(crate) fn merge_distinguished_borrowed<'a, T: RawDistinguishedMessageBorrowDecoder<'a>>(
+    value: &mut T,
+    mut buf: Capped<&'a [u8]>,
+    ctx: DecodeContext,
+) -> Result<Canonicity, DecodeError> {
+    let tr = &mut TagReader::new();
+    let mut last_tag = None::<u32>;
+    let mut canon = Canonicity::Canonical;
+    while buf.has_remaining()? {
+        let (tag, wire_type) = tr.decode_key(buf.lend())?;
+        let duplicated = last_tag == Some(tag);
+        last_tag = Some(tag);
+        canon.update(value.raw_borrow_decode_field_distinguished(
+            tag,
+            wire_type,
+            duplicated,
+            buf.lend(),
+            ctx,
+        )?);
+    }
+    Ok(canon)
+}
+
+/// Decodes a message from the given capped buffer, enforcing the limits in the given options.
+fn decode_with_options<T: OwnedMessage, B: Buf + ?Sized>(
+    buf: Capped<B>,
+    options: &DecodeOptions,
+) -> Result<T, DecodeError> {
+    let limits = DecodeLimits::new(options);
+    let ctx = DecodeContext::with_limits(&limits);
+    ctx.check_input_len(buf.remaining_before_cap())?;
+    let mut message = T::empty();
+    message.merge_capped(buf, ctx)?;
+    Ok(message)
+}
+
+/// Decodes a message in distinguished mode from the given capped buffer, enforcing the limits in
+/// the given options.
+fn decode_distinguished_with_options<T: DistinguishedOwnedMessage, B: Buf + ?Sized>(
+    buf: Capped<B>,
+    options: &DecodeOptions,
+) -> Result<(T, Canonicity), DecodeError> {
+    let limits = DecodeLimits::new(options);
+    let ctx = DecodeContext::with_limits(&limits);
+    ctx.check_input_len(buf.remaining_before_cap())?;
+    let mut message = T::empty();
+    let canon = message.merge_distinguished_capped(buf, ctx)?;
+    Ok((message, canon))
+}
+
 /// A Bilrost message. Provides basic encoding and decoding functionality for message types.
 pub trait Message: EmptyState {
     /// Encodes the message to a buffer.
@@ -75,39 +186,131 @@ pub trait Message: EmptyState {
     /// The entire buffer will be consumed.
     fn decode<B: Buf>(buf: B) -> Result<Self, DecodeError>
     where
-        Self: Sized;
+        Self: Sized + OwnedMessage;
 
     /// Decodes a length-delimited instance of the message from the buffer.
     fn decode_length_delimited<B: Buf>(buf: B) -> Result<Self, DecodeError>
     where
-        Self: Sized;
+        Self: Sized + OwnedMessage;
 
     /// Decodes an instance from the given `Capped` buffer, consuming it to its cap.
     #[doc(hidden)]
     fn decode_capped<B: Buf + ?Sized>(buf: Capped<B>) -> Result<Self, DecodeError>
     where
-        Self: Sized;
+        Self: Sized + OwnedMessage;
+
+    /// Decodes an instance of the message from a buffer, enforcing the limits in the given
+    /// options.
+    ///
+    /// The entire buffer will be consumed.
+    fn decode_with_options<B: Buf>(buf: B, options: &DecodeOptions) -> Result<Self, DecodeError>
+    where
+        Self: Sized + OwnedMessage;
+
+    /// Decodes a length-delimited instance of the message from the buffer, enforcing the limits in
+    /// the given options.
+    fn decode_length_delimited_with_options<B: Buf>(
+        buf: B,
+        options: &DecodeOptions,
+    ) -> Result<Self, DecodeError>
+    where
+        Self: Sized + OwnedMessage;
+
+    /// Decodes an instance of the message from a byte slice, borrowing from it. Fields of types
+    /// like `&str`, `&[u8]`, and `Cow` will refer directly to the bytes in the slice rather than
+    /// copying them.
+    ///
+    /// The entire slice will be consumed.
+    fn decode_borrowed<'a>(buf: &'a [u8]) -> Result<Self, DecodeError>
+    where
+        Self: Sized + RawMessageBorrowDecoder<'a>;
+
+    /// Decodes an instance of the message from a byte slice, borrowing from it and enforcing the
+    /// limits in the given options.
+    ///
+    /// The entire slice will be consumed.
+    fn decode_borrowed_with_options<'a>(
+        buf: &'a [u8],
+        options: &DecodeOptions,
+    ) -> Result<Self, DecodeError>
+    where
+        Self: Sized + RawMessageBorrowDecoder<'a>;
+
+    /// Decodes only some of the fields of an instance of the message from a buffer, selected by
+    /// their paths of tags as described in `TagFilter`. Fields which are not selected are skipped
+    /// without being decoded and are left empty.
+    ///
+    /// The entire buffer will be consumed.
+    fn decode_only<B: Buf>(buf: B, paths: &[&[u32]]) -> Result<Self, DecodeError>
+    where
+        Self: Sized + OwnedMessage;
+
+    /// Decodes only the fields of an instance of the message from a buffer that are selected by
+    /// the given filter. Fields which are not selected are skipped without being decoded and are
+    /// left empty.
+    ///
+    /// The entire buffer will be consumed.
+    fn decode_filtered<B: Buf>(buf: B, filter: &TagFilter) -> Result<Self, DecodeError>
+    where
+        Self: Sized + OwnedMessage;
 
     /// Decodes the non-ignored fields of this message from the buffer, replacing their values.
     fn replace_from<B: Buf>(&mut self, buf: B) -> Result<(), DecodeError>
     where
-        Self: Sized;
+        Self: Sized + OwnedMessage;
 
     /// Decodes the non-ignored fields of this message, replacing their values from a
     /// length-delimited value encoded in the buffer.
     fn replace_from_length_delimited<B: Buf>(&mut self, buf: B) -> Result<(), DecodeError>
     where
-        Self: Sized;
+        Self: Sized + OwnedMessage;
 
     /// Decodes the non-ignored fields of this message, replacing their values from the given capped
     /// buffer.
     #[doc(hidden)]
     fn replace_from_capped<B: Buf + ?Sized>(&mut self, buf: Capped<B>) -> Result<(), DecodeError>
+    where
+        Self: Sized + OwnedMessage;
+
+    /// Merges an encoded message from the buffer into this message, updating its existing values
+    /// rather than replacing them. By default, fields with single values are overwritten when they
+    /// are present in the data, collections have the new items appended, maps have the new entries
+    /// inserted (replacing the values of existing keys), nested messages are merged recursively,
+    /// and oneofs are replaced. Derived messages can override this for each field with the
+    /// `merge` attribute.
+    ///
+    /// The data must still be a valid encoding of the message by itself, so repeated and
+    /// conflicting fields within the data are rejected. If an error is returned, the message may
+    /// have been partially updated.
+    fn merge_from<B: Buf>(&mut self, buf: B) -> Result<(), DecodeError>
+    where
+        Self: Sized + OwnedMessage;
+
+    /// Merges a length-delimited message from the buffer into this message, as with `merge_from`.
+    fn merge_from_length_delimited<B: Buf>(&mut self, buf: B) -> Result<(), DecodeError>
+    where
+        Self: Sized + OwnedMessage;
+
+    /// Computes a patch that transforms this message into `new` when it is applied.
+    ///
+    /// Nested messages are compared field by field, collections that only grew are patched by
+    /// appending their new items, and maps are patched entry by entry. Any other field that
+    /// changed is set to its new value in full.
+    fn diff(&self, new: &Self) -> Patch
     where
         Self: Sized;
 
+    /// Applies a patch, such as one produced by `diff`, to this message. If an error is returned,
+    /// the message may have been partially updated.
+    fn apply_patch(&mut self, patch: &Patch) -> Result<(), DecodeError>
+    where
+        Self: Sized + OwnedMessage;
+
     // ------------ Object-safe methods follow ------------
 
+    /// Returns the descriptor of the message's type, which describes its fields.
+    fn descriptor(&self) -> &'static MessageDescriptor;
+
     /// Returns the encoded length of the message without a length delimiter.
     fn encoded_len(&self) -> usize;
 
@@ -117,6 +320,23 @@ pub trait Message: EmptyState {
     /// Encodes the message to a `Bytes` buffer.
     fn encode_to_bytes(&self) -> Bytes;
 
+    /// Prepends the encoded message to the front of a reverse buffer.
+    ///
+    /// Unlike the forward encoding methods, this encodes the message in a single pass without
+    /// measuring the encoded length of nested messages beforehand.
+    fn prepend(&self, buf: &mut ReverseBuffer);
+
+    /// Prepends the encoded message with a length-delimiter to the front of a reverse buffer.
+    fn prepend_length_delimited(&self, buf: &mut ReverseBuffer);
+
+    /// Encodes the message to a newly allocated buffer in a single pass, via `prepend`. This is
+    /// faster than `encode_to_vec` for messages that are deeply nested.
+    fn encode_to_vec_fast(&self) -> Vec<u8>;
+
+    /// Encodes the message to a `Bytes` buffer in a single pass, via `prepend`. This is faster
+    /// than `encode_to_bytes` for messages that are deeply nested.
+    fn encode_to_bytes_fast(&self) -> Bytes;
+
     /// Encodes the message to a `Bytes` buffer.
     fn encode_dyn(&self, buf: &mut dyn BufMut) -> Result<(), EncodeError>;
 
@@ -128,6 +348,40 @@ pub trait Message: EmptyState {
 
     /// Encodes the message with a length-delimiter to a `Bytes` buffer.
     fn encode_length_delimited_dyn(&self, buf: &mut dyn BufMut) -> Result<(), EncodeError>;
+}
+
+/// Bilrost messages that can be decoded without borrowing from their input. The decoding methods of
+/// `Message`, such as `decode`, are only available for messages that implement this trait; messages
+/// with fields that can only be decoded by borrowing, such as `&str`, must use `decode_borrowed`.
+pub trait OwnedMessage: Message {
+    /// Decodes fields from the given capped buffer into this message, to its cap.
+    #[doc(hidden)]
+    fn merge_capped<B: Buf + ?Sized>(
+        &mut self,
+        buf: Capped<B>,
+        ctx: DecodeContext,
+    ) -> Result<(), DecodeError>
+    where
+        Self: Sized;
+
+    /// Merges fields from the given capped buffer into this message with the semantics of
+    /// `merge_from`.
+    #[doc(hidden)]
+    fn merge_update_capped<B: Buf + ?Sized>(
+        &mut self,
+        buf: Capped<B>,
+        ctx: DecodeContext,
+    ) -> Result<(), DecodeError>
+    where
+        Self: Sized;
+
+    /// Applies each operation of the patch to this message.
+    #[doc(hidden)]
+    fn apply_patch_ops(&mut self, patch: &Patch) -> Result<(), DecodeError>
+    where
+        Self: Sized;
+
+    // ------------ Object-safe methods follow ------------
 
     /// Decodes the non-ignored fields of this message from the buffer, replacing their values.
     fn replace_from_slice(&mut self, buf: &[u8]) -> Result<(), DecodeError>;
@@ -161,14 +415,14 @@ pub trait DistinguishedMessage: Message {
     /// The entire buffer will be consumed.
     fn decode_distinguished<B: Buf>(buf: B) -> Result<(Self, Canonicity), DecodeError>
     where
-        Self: Sized;
+        Self: Sized + DistinguishedOwnedMessage;
 
     /// Decodes a length-delimited instance of the message from the buffer in distinguished mode.
     fn decode_distinguished_length_delimited<B: Buf>(
         buf: B,
     ) -> Result<(Self, Canonicity), DecodeError>
     where
-        Self: Sized;
+        Self: Sized + DistinguishedOwnedMessage;
 
     /// Decodes an instance from the given `Capped` buffer in distinguished mode, consuming it to
     /// its cap.
@@ -177,13 +431,53 @@ pub trait DistinguishedMessage: Message {
         buf: Capped<B>,
     ) -> Result<(Self, Canonicity), DecodeError>
     where
-        Self: Sized;
+        Self: Sized + DistinguishedOwnedMessage;
+
+    /// Decodes an instance of the message from a buffer in distinguished mode, enforcing the
+    /// limits in the given options.
+    ///
+    /// The entire buffer will be consumed.
+    fn decode_distinguished_with_options<B: Buf>(
+        buf: B,
+        options: &DecodeOptions,
+    ) -> Result<(Self, Canonicity), DecodeError>
+    where
+        Self: Sized + DistinguishedOwnedMessage;
+
+    /// Decodes a length-delimited instance of the message from the buffer in distinguished mode,
+    /// enforcing the limits in the given options.
+    fn decode_distinguished_length_delimited_with_options<B: Buf>(
+        buf: B,
+        options: &DecodeOptions,
+    ) -> Result<(Self, Canonicity), DecodeError>
+    where
+        Self: Sized + DistinguishedOwnedMessage;
+
+    /// Decodes an instance of the message from a byte slice in distinguished mode, borrowing from
+    /// it. Fields of types like `&str`, `&[u8]`, and `Cow` will refer directly to the bytes in the
+    /// slice rather than copying them.
+    ///
+    /// The entire slice will be consumed.
+    fn decode_distinguished_borrowed<'a>(buf: &'a [u8]) -> Result<(Self, Canonicity), DecodeError>
+    where
+        Self: Sized + RawDistinguishedMessageBorrowDecoder<'a>;
+
+    /// Decodes an instance of the message from a byte slice in distinguished mode, borrowing from
+    /// it and enforcing the limits in the given options.
+    ///
+    /// The entire slice will be consumed.
+    fn decode_distinguished_borrowed_with_options<'a>(
+        buf: &'a [u8],
+        options: &DecodeOptions,
+    ) -> Result<(Self, Canonicity), DecodeError>
+    where
+        Self: Sized + RawDistinguishedMessageBorrowDecoder<'a>;
 
     /// Decodes the non-ignored fields of this message from the buffer in distinguished mode,
     /// replacing their values.
     fn replace_distinguished_from<B: Buf>(&mut self, buf: B) -> Result<Canonicity, DecodeError>
     where
-        Self: Sized;
+        Self: Sized + DistinguishedOwnedMessage;
 
     /// Decodes the non-ignored fields of this message in distinguished mode, replacing their values
     /// from a length-delimited value encoded in the buffer.
@@ -192,7 +486,7 @@ pub trait DistinguishedMessage: Message {
         buf: B,
     ) -> Result<Canonicity, DecodeError>
     where
-        Self: Sized;
+        Self: Sized + DistinguishedOwnedMessage;
 
     /// Decodes the non-ignored fields of this message in distinguished mode, replacing their values
     /// from the given capped buffer.
@@ -201,6 +495,36 @@ pub trait DistinguishedMessage: Message {
         &mut self,
         buf: Capped<B>,
     ) -> Result<Canonicity, DecodeError>
+    where
+        Self: Sized + DistinguishedOwnedMessage;
+
+    /// Feeds the message's canonical encoding to a cryptographic digest, such as a hash from the
+    /// `sha2` or `blake3` crates, without allocating. The digest receives exactly the bytes that
+    /// `encode_to_vec` would return, so equal messages always produce equal digests.
+    #[cfg(feature = "digest")]
+    fn canonical_digest<D: digest::Update>(&self, digest: &mut D)
+    where
+        Self: Sized;
+
+    /// Feeds the message's canonical encoding to a `Hasher` without allocating. The hasher receives
+    /// exactly the bytes that `encode_to_vec` would return, possibly split across several writes.
+    fn canonical_hash<H: Hasher>(&self, hasher: &mut H)
+    where
+        Self: Sized;
+}
+
+/// Distinguished Bilrost messages that can be decoded without borrowing from their input. The
+/// decoding methods of `DistinguishedMessage`, such as `decode_distinguished`, are only available
+/// for messages that implement this trait.
+pub trait DistinguishedOwnedMessage: DistinguishedMessage + OwnedMessage {
+    /// Decodes fields from the given capped buffer into this message in distinguished mode, to its
+    /// cap.
+    #[doc(hidden)]
+    fn merge_distinguished_capped<B: Buf + ?Sized>(
+        &mut self,
+        buf: Capped<B>,
+        ctx: DecodeContext,
+    ) -> Result<Canonicity, DecodeError>
     where
         Self: Sized;
 
@@ -241,14 +565,8 @@ pub trait DistinguishedMessage: Message {
 
 /// `Message` is implemented as a usability layer on top of the basic functionality afforded by
 /// `RawMessage`.
-// TODO(widders): in the future, make it possible to decode with extension Message types for all
-//  fields not covered by the own type. The default extension can be `()`, which always skips in
-//  expedient mode and always errs in distinguished mode; the most permissive possible extension
-//  would then be OpaqueMessage, which losslessly captures all unknown fields. A composing wrapper
-//  type that combines two message types in an overlay can be implemented. This will require an
-//  alternate encoding mode which emits field groups to be sorted in a stricter way, only grouping
-//  truly contiguous runs of field ids so that they can be sorted with any other type's fields at
-//  runtime.
+// TODO(widders): a composing wrapper type that combines two extension message types in an overlay
+//  could be implemented on top of `MessageExtension`.
 impl<T> Message for T
 where
     T: RawMessage,
@@ -276,36 +594,156 @@ where
         Ok(())
     }
 
-    fn decode<B: Buf>(mut buf: B) -> Result<Self, DecodeError> {
+    fn decode<B: Buf>(mut buf: B) -> Result<Self, DecodeError>
+    where
+        Self: OwnedMessage,
+    {
         Self::decode_capped(Capped::new(&mut buf))
     }
 
-    fn decode_length_delimited<B: Buf>(mut buf: B) -> Result<Self, DecodeError> {
+    fn decode_length_delimited<B: Buf>(mut buf: B) -> Result<Self, DecodeError>
+    where
+        Self: OwnedMessage,
+    {
         Self::decode_capped(Capped::new_length_delimited(&mut buf)?)
     }
 
     #[doc(hidden)]
-    fn decode_capped<B: Buf + ?Sized>(buf: Capped<B>) -> Result<Self, DecodeError> {
+    fn decode_capped<B: Buf + ?Sized>(buf: Capped<B>) -> Result<Self, DecodeError>
+    where
+        Self: OwnedMessage,
+    {
         let mut message = Self::empty();
-        merge(&mut message, buf, DecodeContext::default())?;
+        message.merge_capped(buf, DecodeContext::default())?;
         Ok(message)
     }
 
-    fn replace_from<B: Buf>(&mut self, mut buf: B) -> Result<(), DecodeError> {
+    fn decode_with_options<B: Buf>(mut buf: B, options: &DecodeOptions) -> Result<Self, DecodeError>
+    where
+        Self: OwnedMessage,
+    {
+        decode_with_options(Capped::new(&mut buf), options)
+    }
+
+    fn decode_length_delimited_with_options<B: Buf>(
+        mut buf: B,
+        options: &DecodeOptions,
+    ) -> Result<Self, DecodeError>
+    where
+        Self: OwnedMessage,
+    {
+        decode_with_options(Capped::new_length_delimited(&mut buf)?, options)
+    }
+
+    fn decode_borrowed<'a>(mut buf: &'a [u8]) -> Result<Self, DecodeError>
+    where
+        Self: RawMessageBorrowDecoder<'a>,
+    {
+        let mut message = Self::empty();
+        merge_borrowed(
+            &mut message,
+            Capped::new(&mut buf),
+            DecodeContext::default(),
+        )?;
+        Ok(message)
+    }
+
+    fn decode_borrowed_with_options<'a>(
+        mut buf: &'a [u8],
+        options: &DecodeOptions,
+    ) -> Result<Self, DecodeError>
+    where
+        Self: RawMessageBorrowDecoder<'a>,
+    {
+        let limits = DecodeLimits::new(options);
+        let ctx = DecodeContext::with_limits(&limits);
+        ctx.check_input_len(buf.len())?;
+        let mut message = Self::empty();
+        merge_borrowed(&mut message, Capped::new(&mut buf), ctx)?;
+        Ok(message)
+    }
+
+    fn decode_only<B: Buf>(buf: B, paths: &[&[u32]]) -> Result<Self, DecodeError>
+    where
+        Self: OwnedMessage,
+    {
+        Self::decode_filtered(buf, &TagFilter::from_paths(paths))
+    }
+
+    fn decode_filtered<B: Buf>(mut buf: B, filter: &TagFilter) -> Result<Self, DecodeError>
+    where
+        Self: OwnedMessage,
+    {
+        let mut message = Self::empty();
+        message.merge_capped(
+            Capped::new(&mut buf),
+            DecodeContext::default().with_tag_filter(filter),
+        )?;
+        Ok(message)
+    }
+
+    fn replace_from<B: Buf>(&mut self, mut buf: B) -> Result<(), DecodeError>
+    where
+        Self: OwnedMessage,
+    {
         self.replace_from_capped(Capped::new(&mut buf))
     }
 
-    fn replace_from_length_delimited<B: Buf>(&mut self, mut buf: B) -> Result<(), DecodeError> {
+    fn replace_from_length_delimited<B: Buf>(&mut self, mut buf: B) -> Result<(), DecodeError>
+    where
+        Self: OwnedMessage,
+    {
         self.replace_from_capped(Capped::new_length_delimited(&mut buf)?)
     }
 
     #[doc(hidden)]
-    fn replace_from_capped<B: Buf + ?Sized>(&mut self, buf: Capped<B>) -> Result<(), DecodeError> {
+    fn replace_from_capped<B: Buf + ?Sized>(&mut self, buf: Capped<B>) -> Result<(), DecodeError>
+    where
+        Self: OwnedMessage,
+    {
         self.clear();
-        merge(self, buf, DecodeContext::default()).map_err(|err| {
-            self.clear();
-            err
-        })
+        self.merge_capped(buf, DecodeContext::default())
+            .map_err(|err| {
+                self.clear();
+                err
+            })
+    }
+
+    fn merge_from<B: Buf>(&mut self, mut buf: B) -> Result<(), DecodeError>
+    where
+        Self: OwnedMessage,
+    {
+        self.merge_update_capped(
+            Capped::new(&mut buf),
+            DecodeContext::default().for_merging(),
+        )
+    }
+
+    fn merge_from_length_delimited<B: Buf>(&mut self, mut buf: B) -> Result<(), DecodeError>
+    where
+        Self: OwnedMessage,
+    {
+        self.merge_update_capped(
+            Capped::new_length_delimited(&mut buf)?,
+            DecodeContext::default().for_merging(),
+        )
+    }
+
+    fn diff(&self, new: &Self) -> Patch {
+        let mut pw = PatchWriter::new();
+        self.raw_diff(new, &mut pw);
+        pw.into_patch()
+    }
+
+    fn apply_patch(&mut self, patch: &Patch) -> Result<(), DecodeError>
+    where
+        Self: OwnedMessage,
+    {
+        self.apply_patch_ops(patch)
+    }
+
+    fn descriptor(&self) -> &'static MessageDescriptor {
+        Self::DESCRIPTOR
     }
 
     fn encoded_len(&self) -> usize {
@@ -324,6 +762,28 @@ where
         buf.freeze()
     }
 
+    fn prepend(&self, buf: &mut ReverseBuffer) {
+        self.raw_prepend(buf);
+    }
+
+    fn prepend_length_delimited(&self, buf: &mut ReverseBuffer) {
+        let end = buf.len();
+        self.raw_prepend(buf);
+        prepend_varint((buf.len() - end) as u64, buf);
+    }
+
+    fn encode_to_vec_fast(&self) -> Vec<u8> {
+        let mut buf = ReverseBuffer::new();
+        self.raw_prepend(&mut buf);
+        buf.into_vec()
+    }
+
+    fn encode_to_bytes_fast(&self) -> Bytes {
+        let mut buf = ReverseBuffer::new();
+        self.raw_prepend(&mut buf);
+        buf.into_bytes()
+    }
+
     fn encode_dyn(&self, buf: &mut dyn BufMut) -> Result<(), EncodeError> {
         self.encode(buf)
     }
@@ -349,6 +809,31 @@ where
     fn encode_length_delimited_dyn(&self, buf: &mut dyn BufMut) -> Result<(), EncodeError> {
         self.encode_length_delimited(buf)
     }
+}
+
+impl<T> OwnedMessage for T
+where
+    T: RawMessageDecoder,
+{
+    fn merge_capped<B: Buf + ?Sized>(
+        &mut self,
+        buf: Capped<B>,
+        ctx: DecodeContext,
+    ) -> Result<(), DecodeError> {
+        merge(self, buf, ctx)
+    }
+
+    fn merge_update_capped<B: Buf + ?Sized>(
+        &mut self,
+        buf: Capped<B>,
+        ctx: DecodeContext,
+    ) -> Result<(), DecodeError> {
+        merge_update(self, buf, ctx)
+    }
+
+    fn apply_patch_ops(&mut self, patch: &Patch) -> Result<(), DecodeError> {
+        crate::patch::apply_patch(self, patch)
+    }
 
     fn replace_from_slice(&mut self, buf: &[u8]) -> Result<(), DecodeError> {
         self.replace_from(buf)
@@ -376,36 +861,98 @@ impl<T> DistinguishedMessage for T
 where
     T: RawDistinguishedMessage + Message,
 {
-    fn decode_distinguished<B: Buf>(mut buf: B) -> Result<(Self, Canonicity), DecodeError> {
+    fn decode_distinguished<B: Buf>(mut buf: B) -> Result<(Self, Canonicity), DecodeError>
+    where
+        Self: DistinguishedOwnedMessage,
+    {
         Self::decode_distinguished_capped(Capped::new(&mut buf))
     }
 
     fn decode_distinguished_length_delimited<B: Buf>(
         mut buf: B,
-    ) -> Result<(Self, Canonicity), DecodeError> {
+    ) -> Result<(Self, Canonicity), DecodeError>
+    where
+        Self: DistinguishedOwnedMessage,
+    {
         Self::decode_distinguished_capped(Capped::new_length_delimited(&mut buf)?)
     }
 
     #[doc(hidden)]
     fn decode_distinguished_capped<B: Buf + ?Sized>(
         buf: Capped<B>,
-    ) -> Result<(Self, Canonicity), DecodeError> {
+    ) -> Result<(Self, Canonicity), DecodeError>
+    where
+        Self: DistinguishedOwnedMessage,
+    {
         let mut message = Self::empty();
-        let canon = merge_distinguished(&mut message, buf, DecodeContext::default())?;
+        let canon = message.merge_distinguished_capped(buf, DecodeContext::default())?;
         Ok((message, canon))
     }
 
-    fn replace_distinguished_from<B: Buf>(
-        &mut self,
+    fn decode_distinguished_with_options<B: Buf>(
         mut buf: B,
-    ) -> Result<Canonicity, DecodeError> {
+        options: &DecodeOptions,
+    ) -> Result<(Self, Canonicity), DecodeError>
+    where
+        Self: DistinguishedOwnedMessage,
+    {
+        decode_distinguished_with_options(Capped::new(&mut buf), options)
+    }
+
+    fn decode_distinguished_length_delimited_with_options<B: Buf>(
+        mut buf: B,
+        options: &DecodeOptions,
+    ) -> Result<(Self, Canonicity), DecodeError>
+    where
+        Self: DistinguishedOwnedMessage,
+    {
+        decode_distinguished_with_options(Capped::new_length_delimited(&mut buf)?, options)
+    }
+
+    fn decode_distinguished_borrowed<'a>(
+        mut buf: &'a [u8],
+    ) -> Result<(Self, Canonicity), DecodeError>
+    where
+        Self: RawDistinguishedMessageBorrowDecoder<'a>,
+    {
+        let mut message = Self::empty();
+        let canon = merge_distinguished_borrowed(
+            &mut message,
+            Capped::new(&mut buf),
+            DecodeContext::default(),
+        )?;
+        Ok((message, canon))
+    }
+
+    fn decode_distinguished_borrowed_with_options<'a>(
+        mut buf: &'a [u8],
+        options: &DecodeOptions,
+    ) -> Result<(Self, Canonicity), DecodeError>
+    where
+        Self: RawDistinguishedMessageBorrowDecoder<'a>,
+    {
+        let limits = DecodeLimits::new(options);
+        let ctx = DecodeContext::with_limits(&limits);
+        ctx.check_input_len(buf.len())?;
+        let mut message = Self::empty();
+        let canon = merge_distinguished_borrowed(&mut message, Capped::new(&mut buf), ctx)?;
+        Ok((message, canon))
+    }
+
+    fn replace_distinguished_from<B: Buf>(&mut self, mut buf: B) -> Result<Canonicity, DecodeError>
+    where
+        Self: DistinguishedOwnedMessage,
+    {
         self.replace_distinguished_from_capped(Capped::new(&mut buf))
     }
 
     fn replace_distinguished_from_length_delimited<B: Buf>(
         &mut self,
         mut buf: B,
-    ) -> Result<Canonicity, DecodeError> {
+    ) -> Result<Canonicity, DecodeError>
+    where
+        Self: DistinguishedOwnedMessage,
+    {
         self.replace_distinguished_from_capped(Capped::new_length_delimited(&mut buf)?)
     }
 
@@ -413,12 +960,38 @@ where
     fn replace_distinguished_from_capped<B: Buf + ?Sized>(
         &mut self,
         buf: Capped<B>,
-    ) -> Result<Canonicity, DecodeError> {
+    ) -> Result<Canonicity, DecodeError>
+    where
+        Self: DistinguishedOwnedMessage,
+    {
         self.clear();
-        merge_distinguished(self, buf, DecodeContext::default()).map_err(|err| {
-            self.clear();
-            err
-        })
+        self.merge_distinguished_capped(buf, DecodeContext::default())
+            .map_err(|err| {
+                self.clear();
+                err
+            })
+    }
+
+    #[cfg(feature = "digest")]
+    fn canonical_digest<D: digest::Update>(&self, digest: &mut D) {
+        self.raw_encode(&mut HashingSink::new(|bytes: &[u8]| digest.update(bytes)));
+    }
+
+    fn canonical_hash<H: Hasher>(&self, hasher: &mut H) {
+        self.raw_encode(&mut HashingSink::new(|bytes: &[u8]| hasher.write(bytes)));
+    }
+}
+
+impl<T> DistinguishedOwnedMessage for T
+where
+    T: RawDistinguishedMessageDecoder,
+{
+    fn merge_distinguished_capped<B: Buf + ?Sized>(
+        &mut self,
+        buf: Capped<B>,
+        ctx: DecodeContext,
+    ) -> Result<Canonicity, DecodeError> {
+        merge_distinguished(self, buf, ctx)
     }
 
     fn replace_distinguished_from_slice(&mut self, buf: &[u8]) -> Result<Canonicity, DecodeError> {
@@ -455,19 +1028,78 @@ where
     }
 }
 
+/// A write-only `BufMut` that passes everything written to it on to a hashing function instead of
+/// keeping it. Writes that go through `chunk_mut` are staged in a small scratch buffer.
+struct HashingSink<F> {
+    update: F,
+    scratch: [u8; 32],
+}
+
+impl<F: FnMut(&[u8])> HashingSink<F> {
+    fn new(update: F) -> Self {
+        Self {
+            update,
+            scratch: [0; 32],
+        }
+    }
+}
+
+unsafe impl<F: FnMut(&[u8])> BufMut for HashingSink<F> {
+    fn remaining_mut(&self) -> usize {
+        usize::MAX
+    }
+
+    unsafe fn advance_mut(&mut self, cnt: usize) {
+        (self.update)(&self.scratch[..cnt]);
+    }
+
+    fn chunk_mut(&mut self) -> &mut UninitSlice {
+        // SAFETY: the scratch buffer is always initialized and lives as long as the borrow.
+        unsafe { UninitSlice::from_raw_parts_mut(self.scratch.as_mut_ptr(), self.scratch.len()) }
+    }
+
+    fn put_slice(&mut self, src: &[u8]) {
+        (self.update)(src);
+    }
+}
+
 /// Trait to be implemented by messages, which have knowledge of their fields' tags and encoding.
 /// The methods of this trait are meant to only be used by the `Message` implementation.
 pub trait RawMessage: EmptyState {
     const __ASSERTIONS: ();
+    /// Describes the structure of the message.
+    const DESCRIPTOR: &'static MessageDescriptor;
 
     /// Encodes the message to a buffer.
     ///
     /// This method will panic if the buffer has insufficient capacity.
     fn raw_encode<B: BufMut + ?Sized>(&self, buf: &mut B);
 
+    /// Prepends the encoded message to the front of a reverse buffer, without a length delimiter.
+    /// By default this encodes the message forward into a scratch buffer and prepends that.
+    fn raw_prepend(&self, buf: &mut ReverseBuffer) {
+        let mut forward = Vec::with_capacity(self.raw_encoded_len());
+        self.raw_encode(&mut forward);
+        buf.prepend_slice(&forward);
+    }
+
     /// Returns the encoded length of the message without a length delimiter.
     fn raw_encoded_len(&self) -> usize;
 
+    /// Writes the operations that transform `self` into `new` to the patch writer. By default
+    /// this compares the encoded fields of the two messages.
+    #[inline]
+    fn raw_diff(&self, new: &Self, pw: &mut PatchWriter)
+    where
+        Self: Sized,
+    {
+        crate::patch::diff_opaque(self, new, pw)
+    }
+}
+
+/// Underlying trait for messages that can be decoded without borrowing from their input, which is
+/// the case when all of their fields own their values.
+pub trait RawMessageDecoder: RawMessage {
     /// Decodes a field from a buffer into `self`.
     fn raw_decode_field<B: Buf + ?Sized>(
         &mut self,
@@ -479,11 +1111,44 @@ pub trait RawMessage: EmptyState {
     ) -> Result<(), DecodeError>
     where
         Self: Sized;
+
+    /// Merges a field from a buffer into `self` with the semantics of `Message::merge_from`. By
+    /// default this is the same as `raw_decode_field`, which merges into the existing value of the
+    /// field.
+    #[inline]
+    fn raw_merge_field<B: Buf + ?Sized>(
+        &mut self,
+        tag: u32,
+        wire_type: WireType,
+        duplicated: bool,
+        buf: Capped<B>,
+        ctx: DecodeContext,
+        _tracker: &mut MergeTracker,
+    ) -> Result<(), DecodeError>
+    where
+        Self: Sized,
+    {
+        self.raw_decode_field(tag, wire_type, duplicated, buf, ctx)
+    }
+
+    /// Applies a single patch operation, whose path is relative to `self`. By default this
+    /// re-encodes the message, applies the operation to the encoded fields, and decodes it again.
+    #[inline]
+    fn raw_apply(&mut self, path: &[u32], op: &PatchOp) -> Result<(), DecodeError>
+    where
+        Self: Sized,
+    {
+        crate::patch::apply_opaque(self, path, op)
+    }
 }
 
 /// Complementary underlying trait for distinguished messages, all of whose fields have a
 /// distinguished encoding.
-pub trait RawDistinguishedMessage: RawMessage + Eq {
+pub trait RawDistinguishedMessage: RawMessage + Eq {}
+
+/// Complementary underlying trait for distinguished messages that can be decoded without borrowing
+/// from their input.
+pub trait RawDistinguishedMessageDecoder: RawDistinguishedMessage + RawMessageDecoder {
     fn raw_decode_field_distinguished<B: Buf + ?Sized>(
         &mut self,
         tag: u32,
@@ -496,6 +1161,81 @@ pub trait RawDistinguishedMessage: RawMessage + Eq {
         Self: Sized;
 }
 
+/// Underlying trait for messages that can be decoded from a borrowed byte slice, such that their
+/// fields may refer directly into it.
+pub trait RawMessageBorrowDecoder<'a>: RawMessage {
+    /// Decodes a field from a borrowed buffer into `self`.
+    fn raw_borrow_decode_field(
+        &mut self,
+        tag: u32,
+        wire_type: WireType,
+        duplicated: bool,
+        buf: Capped<&'a [u8]>,
+        ctx: DecodeContext,
+    ) -> Result<(), DecodeError>
+    where
+        Self: Sized;
+}
+
+/// Complementary underlying trait for distinguished messages that can be decoded from a borrowed
+/// byte slice.
+pub trait RawDistinguishedMessageBorrowDecoder<'a>: RawDistinguishedMessage {
+    fn raw_borrow_decode_field_distinguished(
+        &mut self,
+        tag: u32,
+        wire_type: WireType,
+        duplicated: bool,
+        buf: Capped<&'a [u8]>,
+        ctx: DecodeContext,
+    ) -> Result<Canonicity, DecodeError>
+    where
+        Self: Sized;
+}
+
+/// Trait for message types that can hold the fields of another message that are unknown to it, via
+/// a field of the containing message marked with `#[bilrost(extensions)]`. All fields that the
+/// containing message does not know are decoded into the extension message, and when encoding, the
+/// extension's fields are interleaved with the containing message's own fields in tag order.
+///
+/// The unit type `()` is the trivial extension, which skips all unknown fields just like a message
+/// with no extensions field. `OpaqueMessage` is the lossless extension, capturing every unknown
+/// field exactly as it was encoded; when it is used in a distinguished message, the unknown fields
+/// round trip exactly and decoding reports `Canonical` rather than `HasExtensions`.
+///
+/// Extension messages should never contain fields with tags that are known to the message
+/// containing them; such fields will be encoded alongside the known field with the same tag.
+pub trait MessageExtension: RawMessage {
+    /// Encodes only the fields of the message whose tags are within the given range.
+    fn raw_encode_tag_range<B: BufMut + ?Sized>(
+        &self,
+        range: (Bound<u32>, Bound<u32>),
+        buf: &mut B,
+        tw: &mut TagWriter,
+    );
+
+    /// Prepends only the fields of the message whose tags are within the given range to the front
+    /// of a reverse buffer. By default this encodes them forward into a scratch buffer and
+    /// prepends that.
+    fn raw_prepend_tag_range(
+        &self,
+        range: (Bound<u32>, Bound<u32>),
+        buf: &mut ReverseBuffer,
+        tw: &mut TagRevWriter,
+    ) {
+        let mut forward = Vec::new();
+        self.raw_encode_tag_range(range, &mut forward, &mut TagWriter::new());
+        tw.prepend_forward(&forward, buf);
+    }
+
+    /// Returns the encoded length of only the fields of the message whose tags are within the given
+    /// range.
+    fn raw_tag_range_encoded_len(
+        &self,
+        range: (Bound<u32>, Bound<u32>),
+        tm: &mut TagMeasurer,
+    ) -> usize;
+}
+
 impl<T> EmptyState for Box<T>
 where
     T: EmptyState,
@@ -518,15 +1258,29 @@ where
     T: RawMessage,
 {
     const __ASSERTIONS: () = ();
+    const DESCRIPTOR: &'static MessageDescriptor = T::DESCRIPTOR;
 
     fn raw_encode<B: BufMut + ?Sized>(&self, buf: &mut B) {
         (**self).raw_encode(buf)
     }
 
+    fn raw_prepend(&self, buf: &mut ReverseBuffer) {
+        (**self).raw_prepend(buf)
+    }
+
     fn raw_encoded_len(&self) -> usize {
         (**self).raw_encoded_len()
     }
 
+    fn raw_diff(&self, new: &Self, pw: &mut PatchWriter) {
+        (**self).raw_diff(new, pw)
+    }
+}
+
+impl<T> RawMessageDecoder for Box<T>
+where
+    T: RawMessageDecoder,
+{
     fn raw_decode_field<B: Buf + ?Sized>(
         &mut self,
         tag: u32,
@@ -540,11 +1294,32 @@ where
     {
         (**self).raw_decode_field(tag, wire_type, duplicated, buf, ctx)
     }
+
+    fn raw_merge_field<B: Buf + ?Sized>(
+        &mut self,
+        tag: u32,
+        wire_type: WireType,
+        duplicated: bool,
+        buf: Capped<B>,
+        ctx: DecodeContext,
+        tracker: &mut MergeTracker,
+    ) -> Result<(), DecodeError>
+    where
+        Self: Sized,
+    {
+        (**self).raw_merge_field(tag, wire_type, duplicated, buf, ctx, tracker)
+    }
+
+    fn raw_apply(&mut self, path: &[u32], op: &PatchOp) -> Result<(), DecodeError> {
+        (**self).raw_apply(path, op)
+    }
 }
 
-impl<T> RawDistinguishedMessage for Box<T>
+impl<T> RawDistinguishedMessage for Box<T> where T: RawDistinguishedMessage {}
+
+impl<T> RawDistinguishedMessageDecoder for Box<T>
 where
-    T: RawDistinguishedMessage,
+    T: RawDistinguishedMessageDecoder,
 {
     fn raw_decode_field_distinguished<B: Buf + ?Sized>(
         &mut self,
@@ -561,15 +1336,87 @@ where
     }
 }
 
+impl<'a, T> RawMessageBorrowDecoder<'a> for Box<T>
+where
+    T: RawMessageBorrowDecoder<'a>,
+{
+    fn raw_borrow_decode_field(
+        &mut self,
+        tag: u32,
+        wire_type: WireType,
+        duplicated: bool,
+        buf: Capped<&'a [u8]>,
+        ctx: DecodeContext,
+    ) -> Result<(), DecodeError>
+    where
+        Self: Sized,
+    {
+        (**self).raw_borrow_decode_field(tag, wire_type, duplicated, buf, ctx)
+    }
+}
+
+impl<'a, T> RawDistinguishedMessageBorrowDecoder<'a> for Box<T>
+where
+    T: RawDistinguishedMessageBorrowDecoder<'a>,
+{
+    fn raw_borrow_decode_field_distinguished(
+        &mut self,
+        tag: u32,
+        wire_type: WireType,
+        duplicated: bool,
+        buf: Capped<&'a [u8]>,
+        ctx: DecodeContext,
+    ) -> Result<Canonicity, DecodeError>
+    where
+        Self: Sized,
+    {
+        (**self).raw_borrow_decode_field_distinguished(tag, wire_type, duplicated, buf, ctx)
+    }
+}
+
+impl<T> MessageExtension for Box<T>
+where
+    T: MessageExtension,
+{
+    fn raw_encode_tag_range<B: BufMut + ?Sized>(
+        &self,
+        range: (Bound<u32>, Bound<u32>),
+        buf: &mut B,
+        tw: &mut TagWriter,
+    ) {
+        (**self).raw_encode_tag_range(range, buf, tw)
+    }
+
+    fn raw_prepend_tag_range(
+        &self,
+        range: (Bound<u32>, Bound<u32>),
+        buf: &mut ReverseBuffer,
+        tw: &mut TagRevWriter,
+    ) {
+        (**self).raw_prepend_tag_range(range, buf, tw)
+    }
+
+    fn raw_tag_range_encoded_len(
+        &self,
+        range: (Bound<u32>, Bound<u32>),
+        tm: &mut TagMeasurer,
+    ) -> usize {
+        (**self).raw_tag_range_encoded_len(range, tm)
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{DistinguishedMessage, Message, Vec};
+    use super::{DistinguishedMessage, DistinguishedOwnedMessage, Message, OwnedMessage, Vec};
     use crate::WithCanonicity;
 
     const _MESSAGE_DYN_IS_OBJECT_SAFE: Option<&dyn Message> = None;
     const _DISTINGUISHED_MESSAGE_DYN_IS_OBJECT_SAFE: Option<&dyn DistinguishedMessage> = None;
+    const _OWNED_MESSAGE_DYN_IS_OBJECT_SAFE: Option<&dyn OwnedMessage> = None;
+    const _DISTINGUISHED_OWNED_MESSAGE_DYN_IS_OBJECT_SAFE: Option<&dyn DistinguishedOwnedMessage> =
+        None;
 
-    fn use_dyn_messages<M: Message>(safe: &mut dyn Message, mut msg: M) {
+    fn use_dyn_messages<M: OwnedMessage>(safe: &mut dyn OwnedMessage, mut msg: M) {
         let mut vec = Vec::<u8>::new();
 
         safe.encoded_len();
@@ -584,8 +1431,8 @@ mod tests {
         msg.clear();
     }
 
-    fn use_dyn_distinguished_messages<M: DistinguishedMessage>(
-        safe: &mut dyn DistinguishedMessage,
+    fn use_dyn_distinguished_messages<M: DistinguishedOwnedMessage>(
+        safe: &mut dyn DistinguishedOwnedMessage,
         mut msg: M,
     ) {
         let mut vec = Vec::<u8>::new();
@@ -0,0 +1,760 @@
+//! Structural differences between values of a message.
+//!
+//! A `Patch` holds the changes that turn one value of a message into another, as found by `diff`.
+//! Each of its operations is addressed to a field by the path of tags that leads to it from the
+//! message the patch applies to, and nested messages, collections, and maps are compared
+//! piecewise rather than replaced whole. Patches are themselves bilrost messages, so they can be
+//! encoded and stored or sent elsewhere to be applied.
+
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::vec::Vec;
+
+use bytes::{Buf, BufMut, Bytes};
+
+use crate::descriptor::{DescribedEncoding, FieldDescriptor, MessageDescriptor};
+use crate::encoding::{
+    decode_varint, encode_varint, skip_field, Capped, Collection, DecodeContext, Decoder,
+    EmptyState, Encoder, General, Mapping, Oneof, OneofDecoder, Packed, ReverseBuffer, TagMeasurer,
+    TagReader, TagRevWriter, TagWriter, ValueEncoder, WireType,
+};
+use crate::message::{merge, OwnedMessage, RawMessage, RawMessageDecoder};
+use crate::DecodeErrorKind::{InvalidPatch, OutOfDomainValue, RecursionLimitReached};
+use crate::{DecodeError, Message};
+
+/// Returns a patch of the changes that turn `old` into `new`. Applying the patch to `old` makes it
+/// encode exactly the same as `new`.
+pub fn diff<M: Message>(old: &M, new: &M) -> Patch {
+    old.diff(new)
+}
+
+/// The changes that turn one value of a message into another, as a list of operations on its
+/// fields. Patches are found with `diff` and applied with `apply`.
+///
+/// A patch is also a message, which encodes its operations in field 1.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Patch {
+    ops: Vec<PatchOp>,
+}
+
+impl Patch {
+    /// Creates a new, empty patch.
+    pub const fn new() -> Self {
+        Self { ops: Vec::new() }
+    }
+
+    /// Returns the operations of the patch in the order they are applied.
+    pub fn ops(&self) -> &[PatchOp] {
+        &self.ops
+    }
+
+    /// Adds an operation to the end of the patch.
+    pub fn push(&mut self, op: PatchOp) {
+        self.ops.push(op);
+    }
+
+    /// Returns true if the patch makes no changes.
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+
+    /// Applies the operations of the patch to the message in order. If an error is returned, the
+    /// operations before the one that failed have already been applied.
+    pub fn apply<M: OwnedMessage>(&self, message: &mut M) -> Result<(), DecodeError> {
+        message.apply_patch(self)
+    }
+}
+
+impl From<Vec<PatchOp>> for Patch {
+    fn from(ops: Vec<PatchOp>) -> Self {
+        Self { ops }
+    }
+}
+
+impl FromIterator<PatchOp> for Patch {
+    fn from_iter<T: IntoIterator<Item = PatchOp>>(iter: T) -> Self {
+        Self {
+            ops: iter.into_iter().collect(),
+        }
+    }
+}
+
+/// A single operation on a field of a message.
+///
+/// Values in the operation's data are encoded the same way the field encodes them in its message,
+/// keys included, so the data for a field with tag 3 consists only of fields with tag 3.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PatchOp {
+    /// The tags of the fields leading from the message the patch is applied to, through any
+    /// nested messages, to the field the operation changes.
+    pub path: Vec<u32>,
+    /// What the operation does to the field.
+    pub kind: PatchOpKind,
+    /// The encoded data for the operation.
+    pub data: Bytes,
+}
+
+/// The kinds of operation in a patch.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum PatchOpKind {
+    /// Replaces the value of the field with the value encoded in the data.
+    #[default]
+    Set = 0,
+    /// Clears the field. The data is empty.
+    Clear = 1,
+    /// Adds the items or map entries encoded in the data to the field. Map entries replace any
+    /// existing entries with the same keys.
+    Append = 2,
+    /// Removes the entry with the key in the data from the map in the field. The data holds only
+    /// the encoded key, with no field key or length.
+    RemoveKey = 3,
+}
+
+impl PatchOpKind {
+    fn from_number(n: u32) -> Option<Self> {
+        Some(match n {
+            0 => PatchOpKind::Set,
+            1 => PatchOpKind::Clear,
+            2 => PatchOpKind::Append,
+            3 => PatchOpKind::RemoveKey,
+            _ => return None,
+        })
+    }
+}
+
+/// Collects the operations of a patch while two values of a message are compared, keeping track of
+/// the path to the message that is currently being compared.
+#[derive(Debug, Default)]
+pub struct PatchWriter {
+    path: Vec<u32>,
+    ops: Vec<PatchOp>,
+}
+
+impl PatchWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the patch with all the operations that were written.
+    pub fn into_patch(self) -> Patch {
+        Patch { ops: self.ops }
+    }
+
+    /// Adds an operation on the field with the given tag in the current message.
+    pub fn push(&mut self, tag: u32, kind: PatchOpKind, data: Bytes) {
+        let mut path = Vec::with_capacity(self.path.len() + 1);
+        path.extend_from_slice(&self.path);
+        path.push(tag);
+        self.ops.push(PatchOp { path, kind, data });
+    }
+
+    /// Compares the fields of a nested message in the field with the given tag, writing the
+    /// operations within the given function to the fields of that message.
+    pub fn nested(&mut self, tag: u32, f: impl FnOnce(&mut Self)) {
+        self.path.push(tag);
+        f(self);
+        self.path.pop();
+    }
+
+    /// Replaces or clears the field with the given tag if it encodes differently in `new`.
+    pub fn replace_field<T: Encoder<E> + ?Sized, E>(&mut self, tag: u32, old: &T, new: &T) {
+        let mut old_encoded = Vec::new();
+        T::encode(tag, old, &mut old_encoded, &mut TagWriter::new());
+        let mut new_encoded = Vec::new();
+        T::encode(tag, new, &mut new_encoded, &mut TagWriter::new());
+        if old_encoded == new_encoded {
+            return;
+        }
+        if new_encoded.is_empty() {
+            self.push(tag, PatchOpKind::Clear, Bytes::new());
+        } else {
+            self.push(tag, PatchOpKind::Set, new_encoded.into());
+        }
+    }
+
+    /// Replaces the field with the given tag, which has a value in both `old` and `new`, if its
+    /// value encodes differently in `new`.
+    pub fn replace_value<T: ValueEncoder<E> + ?Sized, E>(&mut self, tag: u32, old: &T, new: &T) {
+        let old_encoded = encode_value::<T, E>(old);
+        let new_encoded = encode_value::<T, E>(new);
+        if old_encoded != new_encoded {
+            let mut data = Vec::new();
+            TagWriter::new().encode_key(tag, T::WIRE_TYPE, &mut data);
+            data.extend_from_slice(&new_encoded);
+            self.push(tag, PatchOpKind::Set, data.into());
+        }
+    }
+
+    /// Compares a field that may or may not be present in `old` and `new`, diffing the values
+    /// themselves when it is present in both.
+    pub(crate) fn diff_present<T: ValueEncoder<E> + ?Sized, E>(
+        &mut self,
+        tag: u32,
+        old: Option<&T>,
+        new: Option<&T>,
+    ) {
+        match (old, new) {
+            (None, None) => {}
+            (Some(_), None) => self.push(tag, PatchOpKind::Clear, Bytes::new()),
+            (None, Some(new)) => {
+                let mut data = Vec::new();
+                TagWriter::new().encode_key(tag, T::WIRE_TYPE, &mut data);
+                T::encode_value(new, &mut data);
+                self.push(tag, PatchOpKind::Set, data.into());
+            }
+            (Some(old), Some(new)) => T::diff_value(tag, old, new, self),
+        }
+    }
+
+    /// Compares the items of a collection field, appending the new items when the items of `old`
+    /// are all still present in `new` and replacing the whole field otherwise.
+    pub(crate) fn diff_collection<C, T, E>(&mut self, tag: u32, old: &C, new: &C, packed: bool)
+    where
+        C: Collection<Item = T>,
+        T: ValueEncoder<E>,
+    {
+        let old_items: Vec<Vec<u8>> = old.iter().map(encode_value::<T, E>).collect();
+        let new_items: Vec<Vec<u8>> = new.iter().map(encode_value::<T, E>).collect();
+        if old_items == new_items {
+            return;
+        }
+        if new_items.is_empty() {
+            self.push(tag, PatchOpKind::Clear, Bytes::new());
+            return;
+        }
+        let added: Option<Vec<&[u8]>> = if new_items.starts_with(&old_items) {
+            Some(
+                new_items[old_items.len()..]
+                    .iter()
+                    .map(Vec::as_slice)
+                    .collect(),
+            )
+        } else if C::UNORDERED {
+            let new_set: BTreeSet<&[u8]> = new_items.iter().map(Vec::as_slice).collect();
+            let old_set: BTreeSet<&[u8]> = old_items.iter().map(Vec::as_slice).collect();
+            old_set
+                .is_subset(&new_set)
+                .then(|| new_set.difference(&old_set).copied().collect())
+        } else {
+            None
+        };
+        let (kind, items) = match added {
+            Some(added) if !old_items.is_empty() => (PatchOpKind::Append, added),
+            _ => (
+                PatchOpKind::Set,
+                new_items.iter().map(Vec::as_slice).collect(),
+            ),
+        };
+        let data = if packed {
+            encode_packed(tag, &items)
+        } else {
+            encode_unpacked(tag, T::WIRE_TYPE, &items)
+        };
+        self.push(tag, kind, data);
+    }
+
+    /// Compares the entries of a map field, removing the keys that are missing from `new` and
+    /// upserting the entries that were added or changed.
+    pub(crate) fn diff_map<M, K, V, KE, VE>(&mut self, tag: u32, old: &M, new: &M)
+    where
+        M: Mapping<Key = K, Value = V>,
+        K: ValueEncoder<KE>,
+        V: ValueEncoder<VE>,
+    {
+        let entries = |map: &M| -> BTreeMap<Vec<u8>, Vec<u8>> {
+            map.iter()
+                .map(|(k, v)| (encode_value::<K, KE>(k), encode_value::<V, VE>(v)))
+                .collect()
+        };
+        let old_entries = entries(old);
+        let new_entries = entries(new);
+        for key in old_entries.keys() {
+            if !new_entries.contains_key(key) {
+                self.push(tag, PatchOpKind::RemoveKey, Bytes::copy_from_slice(key));
+            }
+        }
+        let changed: Vec<&[u8]> = new_entries
+            .iter()
+            .filter(|&(key, value)| old_entries.get(key) != Some(value))
+            .flat_map(|(key, value)| [key.as_slice(), value.as_slice()])
+            .collect();
+        if !changed.is_empty() {
+            self.push(tag, PatchOpKind::Append, encode_packed(tag, &changed));
+        }
+    }
+
+    /// Compares a oneof field, replacing it if it encodes differently in `new`.
+    pub fn diff_oneof<T: Oneof>(&mut self, old: &T, new: &T) {
+        let mut old_encoded = Vec::new();
+        old.oneof_encode(&mut old_encoded, &mut TagWriter::new());
+        let mut new_encoded = Vec::new();
+        new.oneof_encode(&mut new_encoded, &mut TagWriter::new());
+        if old_encoded == new_encoded {
+            return;
+        }
+        match (old.oneof_current_tag(), new.oneof_current_tag()) {
+            (_, Some(tag)) => self.push(tag, PatchOpKind::Set, new_encoded.into()),
+            (Some(tag), None) => self.push(tag, PatchOpKind::Clear, Bytes::new()),
+            (None, None) => {}
+        }
+    }
+}
+
+fn encode_value<T: ValueEncoder<E> + ?Sized, E>(value: &T) -> Vec<u8> {
+    let mut encoded = Vec::new();
+    T::encode_value(value, &mut encoded);
+    encoded
+}
+
+/// Encodes already-encoded values as the data for a field with the given tag, packed into a single
+/// length-delimited value.
+fn encode_packed(tag: u32, values: &[&[u8]]) -> Bytes {
+    let mut data = Vec::new();
+    TagWriter::new().encode_key(tag, WireType::LengthDelimited, &mut data);
+    encode_varint(
+        values.iter().map(|value| value.len()).sum::<usize>() as u64,
+        &mut data,
+    );
+    for value in values {
+        data.extend_from_slice(value);
+    }
+    data.into()
+}
+
+/// Encodes already-encoded values as the data for a field with the given tag, each in a field of
+/// its own.
+fn encode_unpacked(tag: u32, wire_type: WireType, values: &[&[u8]]) -> Bytes {
+    let mut data = Vec::new();
+    let mut tw = TagWriter::new();
+    for value in values {
+        tw.encode_key(tag, wire_type, &mut data);
+        data.extend_from_slice(value);
+    }
+    data.into()
+}
+
+/// Decodes the data of an operation on the field with the given tag, which must consist only of
+/// fields with that tag.
+fn decode_fragment(
+    tag: u32,
+    data: &[u8],
+    mut decode: impl FnMut(WireType, bool, Capped<&[u8]>) -> Result<(), DecodeError>,
+) -> Result<(), DecodeError> {
+    let mut data = data;
+    let mut buf = Capped::new(&mut data);
+    let tr = &mut TagReader::new();
+    let mut duplicated = false;
+    while buf.has_remaining()? {
+        let (field_tag, wire_type) = tr.decode_key(buf.lend())?;
+        if field_tag != tag {
+            return Err(DecodeError::new(InvalidPatch));
+        }
+        decode(wire_type, duplicated, buf.lend())?;
+        duplicated = true;
+    }
+    Ok(())
+}
+
+/// Applies an operation to the field with the given tag. Used by derived code.
+#[doc(hidden)]
+pub fn apply_to_field<T, E>(value: &mut T, tag: u32, op: &PatchOp) -> Result<(), DecodeError>
+where
+    T: Decoder<E> + EmptyState,
+{
+    match op.kind {
+        PatchOpKind::Set => {
+            value.clear();
+            decode_fragment(tag, &op.data, |wire_type, duplicated, buf| {
+                T::decode(wire_type, duplicated, value, buf, DecodeContext::default())
+            })
+        }
+        PatchOpKind::Clear => {
+            value.clear();
+            Ok(())
+        }
+        PatchOpKind::Append => decode_fragment(tag, &op.data, |wire_type, duplicated, buf| {
+            let ctx = DecodeContext::default().for_merging();
+            T::decode(wire_type, duplicated, value, buf, ctx)
+        }),
+        PatchOpKind::RemoveKey => T::remove_key(value, &op.data),
+    }
+}
+
+/// Applies an operation to the field with the given tag in a oneof. Used by derived code.
+#[doc(hidden)]
+pub fn apply_to_oneof<T: OneofDecoder>(
+    value: &mut T,
+    tag: u32,
+    op: &PatchOp,
+) -> Result<(), DecodeError> {
+    match op.kind {
+        PatchOpKind::Set => {
+            value.clear();
+            decode_fragment(tag, &op.data, |wire_type, duplicated, buf| {
+                value.oneof_decode_field(tag, wire_type, duplicated, buf, DecodeContext::default())
+            })
+        }
+        PatchOpKind::Clear => {
+            if value.oneof_current_tag() == Some(tag) {
+                value.clear();
+            }
+            Ok(())
+        }
+        PatchOpKind::Append | PatchOpKind::RemoveKey => Err(DecodeError::new(InvalidPatch)),
+    }
+}
+
+/// Applies every operation in a patch to a message, checking that their paths are not too deep.
+pub(crate) fn apply_patch<M: RawMessageDecoder>(
+    message: &mut M,
+    patch: &Patch,
+) -> Result<(), DecodeError> {
+    for op in &patch.ops {
+        if op.path.len() > crate::RECURSION_LIMIT as usize {
+            return Err(DecodeError::new(RecursionLimitReached));
+        }
+        message.raw_apply(&op.path, op)?;
+    }
+    Ok(())
+}
+
+/// The encoded values of each field of a message, each with its wire type, keyed by tag.
+type RawFields = BTreeMap<u32, Vec<(WireType, Bytes)>>;
+
+fn read_raw_fields(mut data: &[u8]) -> Result<RawFields, DecodeError> {
+    let mut fields = RawFields::new();
+    let mut tr = TagReader::new();
+    while data.has_remaining() {
+        let (tag, wire_type) = tr.decode_key(Capped::new(&mut data))?;
+        let value_start = data;
+        skip_field(wire_type, Capped::new(&mut data))?;
+        let value = &value_start[..value_start.len() - data.len()];
+        fields
+            .entry(tag)
+            .or_default()
+            .push((wire_type, Bytes::copy_from_slice(value)));
+    }
+    Ok(fields)
+}
+
+fn write_raw_field(tag: u32, values: &[(WireType, Bytes)], buf: &mut Vec<u8>, tw: &mut TagWriter) {
+    for (wire_type, value) in values {
+        tw.encode_key(tag, *wire_type, buf);
+        buf.put_slice(value);
+    }
+}
+
+fn write_raw_fields(fields: &RawFields) -> Vec<u8> {
+    let mut buf = Vec::new();
+    let mut tw = TagWriter::new();
+    for (&tag, values) in fields {
+        write_raw_field(tag, values, &mut buf, &mut tw);
+    }
+    buf
+}
+
+fn to_raw_fields<M: RawMessage>(message: &M) -> RawFields {
+    let mut encoded = Vec::new();
+    message.raw_encode(&mut encoded);
+    read_raw_fields(&encoded).expect("encoded messages always have well-formed fields")
+}
+
+/// Compares two messages field by field via their encodings, replacing each field that differs.
+/// This is how messages that don't know how to compare their own fields are diffed.
+pub(crate) fn diff_opaque<M: RawMessage>(old: &M, new: &M, pw: &mut PatchWriter) {
+    let old = to_raw_fields(old);
+    let new = to_raw_fields(new);
+    let tags: BTreeSet<u32> = old.keys().chain(new.keys()).copied().collect();
+    for tag in tags {
+        let new_values = new.get(&tag);
+        if old.get(&tag) == new_values {
+            continue;
+        }
+        match new_values {
+            None => pw.push(tag, PatchOpKind::Clear, Bytes::new()),
+            Some(values) => {
+                let mut data = Vec::new();
+                write_raw_field(tag, values, &mut data, &mut TagWriter::new());
+                pw.push(tag, PatchOpKind::Set, data.into());
+            }
+        }
+    }
+}
+
+/// Applies an operation to a message via its encoding, reading and rewriting its fields without
+/// interpreting them. This is how patches are applied to messages that don't know how to apply
+/// them to their own fields.
+pub(crate) fn apply_opaque<M: RawMessageDecoder>(
+    message: &mut M,
+    path: &[u32],
+    op: &PatchOp,
+) -> Result<(), DecodeError> {
+    let mut fields = to_raw_fields(message);
+    apply_to_raw_fields(&mut fields, path, op)?;
+    let encoded = write_raw_fields(&fields);
+    let mut replacement = M::empty();
+    merge(
+        &mut replacement,
+        Capped::new(&mut encoded.as_slice()),
+        DecodeContext::default(),
+    )?;
+    *message = replacement;
+    Ok(())
+}
+
+fn apply_to_raw_fields(
+    fields: &mut RawFields,
+    path: &[u32],
+    op: &PatchOp,
+) -> Result<(), DecodeError> {
+    let Some((&tag, rest)) = path.split_first() else {
+        return Err(DecodeError::new(InvalidPatch));
+    };
+    if rest.is_empty() {
+        let fragment_values = || -> Result<Vec<(WireType, Bytes)>, DecodeError> {
+            let mut fragment = read_raw_fields(&op.data)?;
+            if fragment.keys().any(|&field_tag| field_tag != tag) {
+                return Err(DecodeError::new(InvalidPatch));
+            }
+            Ok(fragment.remove(&tag).unwrap_or_default())
+        };
+        match op.kind {
+            PatchOpKind::Set => {
+                fields.insert(tag, fragment_values()?);
+            }
+            PatchOpKind::Clear => {
+                fields.remove(&tag);
+            }
+            PatchOpKind::Append => fields.entry(tag).or_default().extend(fragment_values()?),
+            PatchOpKind::RemoveKey => return Err(DecodeError::new(InvalidPatch)),
+        }
+        return Ok(());
+    }
+    // The operation is on a field of a nested message, which must be a single length-delimited
+    // value if it is present.
+    let mut nested = match fields.remove(&tag).as_deref() {
+        None => RawFields::new(),
+        Some([(WireType::LengthDelimited, value)]) => {
+            let mut value = value.as_ref();
+            decode_varint(&mut value)?;
+            read_raw_fields(value)?
+        }
+        Some(_) => return Err(DecodeError::new(InvalidPatch)),
+    };
+    apply_to_raw_fields(&mut nested, rest, op)?;
+    if !nested.is_empty() {
+        let encoded = write_raw_fields(&nested);
+        let mut value = Vec::new();
+        encode_varint(encoded.len() as u64, &mut value);
+        value.extend_from_slice(&encoded);
+        fields.insert(tag, alloc::vec![(WireType::LengthDelimited, value.into())]);
+    }
+    Ok(())
+}
+
+impl EmptyState for PatchOp {
+    fn empty() -> Self {
+        Self::default()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.path.is_empty() && self.kind == PatchOpKind::Set && self.data.is_empty()
+    }
+
+    fn clear(&mut self) {
+        *self = Self::default();
+    }
+}
+
+impl RawMessage for PatchOp {
+    const __ASSERTIONS: () = ();
+    const DESCRIPTOR: &'static MessageDescriptor = &MessageDescriptor {
+        name: "PatchOp",
+        fields: &[
+            FieldDescriptor {
+                name: "path",
+                tag: 1,
+                encoding: <Packed as DescribedEncoding>::DESCRIPTOR,
+                value: <Vec<u32> as Encoder<Packed>>::VALUE_KIND,
+                recurses: false,
+            },
+            FieldDescriptor {
+                name: "kind",
+                tag: 2,
+                encoding: <General as DescribedEncoding>::DESCRIPTOR,
+                value: <u32 as Encoder<General>>::VALUE_KIND,
+                recurses: false,
+            },
+            FieldDescriptor {
+                name: "data",
+                tag: 3,
+                encoding: <General as DescribedEncoding>::DESCRIPTOR,
+                value: <Bytes as Encoder<General>>::VALUE_KIND,
+                recurses: false,
+            },
+        ],
+        oneofs: &[],
+        reserved_tags: &[],
+        has_extensions: false,
+    };
+
+    fn raw_encode<B: BufMut + ?Sized>(&self, buf: &mut B) {
+        let tw = &mut TagWriter::new();
+        Encoder::<Packed>::encode(1, &self.path, buf, tw);
+        Encoder::<General>::encode(2, &(self.kind as u32), buf, tw);
+        Encoder::<General>::encode(3, &self.data, buf, tw);
+    }
+
+    fn raw_prepend(&self, buf: &mut ReverseBuffer) {
+        let tw = &mut TagRevWriter::new();
+        Encoder::<General>::prepend_encode(3, &self.data, buf, tw);
+        Encoder::<General>::prepend_encode(2, &(self.kind as u32), buf, tw);
+        Encoder::<Packed>::prepend_encode(1, &self.path, buf, tw);
+        tw.finalize(buf);
+    }
+
+    fn raw_encoded_len(&self) -> usize {
+        let tm = &mut TagMeasurer::new();
+        Encoder::<Packed>::encoded_len(1, &self.path, tm)
+            + Encoder::<General>::encoded_len(2, &(self.kind as u32), tm)
+            + Encoder::<General>::encoded_len(3, &self.data, tm)
+    }
+}
+
+impl RawMessageDecoder for PatchOp {
+    fn raw_decode_field<B: Buf + ?Sized>(
+        &mut self,
+        tag: u32,
+        wire_type: WireType,
+        duplicated: bool,
+        buf: Capped<B>,
+        ctx: DecodeContext,
+    ) -> Result<(), DecodeError> {
+        match tag {
+            1 => Decoder::<Packed>::decode(wire_type, duplicated, &mut self.path, buf, ctx),
+            2 => {
+                let mut kind = self.kind as u32;
+                Decoder::<General>::decode(wire_type, duplicated, &mut kind, buf, ctx)?;
+                self.kind = PatchOpKind::from_number(kind)
+                    .ok_or_else(|| DecodeError::new(OutOfDomainValue))?;
+                Ok(())
+            }
+            3 => Decoder::<General>::decode(wire_type, duplicated, &mut self.data, buf, ctx),
+            _ => skip_field(wire_type, buf),
+        }
+    }
+}
+
+impl EmptyState for Patch {
+    fn empty() -> Self {
+        Self::new()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+
+    fn clear(&mut self) {
+        self.ops.clear();
+    }
+}
+
+impl RawMessage for Patch {
+    const __ASSERTIONS: () = ();
+    const DESCRIPTOR: &'static MessageDescriptor = &MessageDescriptor {
+        name: "Patch",
+        fields: &[FieldDescriptor {
+            name: "ops",
+            tag: 1,
+            encoding: <General as DescribedEncoding>::DESCRIPTOR,
+            value: <Vec<PatchOp> as Encoder<General>>::VALUE_KIND,
+            recurses: false,
+        }],
+        oneofs: &[],
+        reserved_tags: &[],
+        has_extensions: false,
+    };
+
+    fn raw_encode<B: BufMut + ?Sized>(&self, buf: &mut B) {
+        Encoder::<General>::encode(1, &self.ops, buf, &mut TagWriter::new());
+    }
+
+    fn raw_prepend(&self, buf: &mut ReverseBuffer) {
+        let tw = &mut TagRevWriter::new();
+        Encoder::<General>::prepend_encode(1, &self.ops, buf, tw);
+        tw.finalize(buf);
+    }
+
+    fn raw_encoded_len(&self) -> usize {
+        Encoder::<General>::encoded_len(1, &self.ops, &mut TagMeasurer::new())
+    }
+}
+
+impl RawMessageDecoder for Patch {
+    fn raw_decode_field<B: Buf + ?Sized>(
+        &mut self,
+        tag: u32,
+        wire_type: WireType,
+        duplicated: bool,
+        buf: Capped<B>,
+        ctx: DecodeContext,
+    ) -> Result<(), DecodeError> {
+        match tag {
+            1 => Decoder::<General>::decode(wire_type, duplicated, &mut self.ops, buf, ctx),
+            _ => skip_field(wire_type, buf),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "opaque"))]
+mod test {
+    use alloc::vec::Vec;
+
+    use super::{diff, Patch, PatchOpKind};
+    use crate::encoding::opaque::{OpaqueMessage, OpaqueValue as OV};
+    use crate::Message;
+
+    #[test]
+    fn opaque_fallback() {
+        // Messages that don't compare their own fields are diffed and patched via their encodings
+        let old = OpaqueMessage::from_iter([
+            (1, OV::u64(1)),
+            (2, OV::string("same")),
+            (3, OV::message(&OpaqueMessage::from_iter([(1, OV::u32(5))]))),
+        ]);
+        let new = OpaqueMessage::from_iter([
+            (2, OV::string("same")),
+            (3, OV::message(&OpaqueMessage::from_iter([(1, OV::u32(6))]))),
+            (4, OV::u64(4)),
+            (4, OV::u64(5)),
+        ]);
+        let patch = diff(&old, &new);
+        let kinds: Vec<_> = patch.ops().iter().map(|op| op.kind).collect();
+        assert_eq!(
+            kinds,
+            [PatchOpKind::Clear, PatchOpKind::Set, PatchOpKind::Set]
+        );
+        let mut patched = old.clone();
+        patch.apply(&mut patched).unwrap();
+        assert_eq!(patched, new);
+
+        // Operations can also reach into nested messages through their encodings
+        let nested = diff(
+            &OpaqueMessage::from_iter([(1, OV::u32(6))]),
+            &OpaqueMessage::from_iter([(1, OV::u32(7))]),
+        );
+        let nested: Patch = nested
+            .ops()
+            .iter()
+            .cloned()
+            .map(|mut op| {
+                op.path.insert(0, 3);
+                op
+            })
+            .collect();
+        patched.apply_patch(&nested).unwrap();
+        assert_eq!(
+            patched.get_vec(&3).unwrap().as_slice(),
+            [OV::message(&OpaqueMessage::from_iter([(1, OV::u32(7))]))]
+        );
+    }
+}
@@ -0,0 +1,221 @@
+//! Exports the `.bilrost` schema of message types.
+//!
+//! The schema of a message lists its fields, oneofs, and reserved tags, followed by those of every
+//! message and enumeration it refers to, in the same schema language that `bilrost-build`
+//! generates message types from. This lets the wire contract of message types that are written in
+//! Rust be reviewed and shared with projects that don't use them directly.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::{self, Write};
+use core::ops::RangeInclusive;
+
+use crate::descriptor::{
+    EncodingDescriptor, EnumerationDescriptor, FieldDescriptor, MessageDescriptor, ValueKind,
+};
+use crate::message::RawMessage;
+
+/// Returns the schema of the message type and every message and enumeration it refers to.
+///
+/// Types are exported in the order they are first referred to, starting with the message itself,
+/// and are identified by their names; if two different types that share a name are referred to,
+/// only the first is exported. Every field is written with its tag, and with its encoding when it
+/// is not `general`. Fields whose values can't be described are written as comments.
+pub fn export<M: RawMessage>() -> String {
+    export_descriptor(M::DESCRIPTOR)
+}
+
+/// Returns the schema of the described message and every message and enumeration it refers to.
+/// See [`export`].
+pub fn export_descriptor(descriptor: &'static MessageDescriptor) -> String {
+    let mut out = String::new();
+    write_schema(&mut out, descriptor).expect("writing to a string does not fail");
+    out
+}
+
+#[derive(Clone, Copy)]
+enum Item {
+    Message(&'static MessageDescriptor),
+    Enumeration(&'static EnumerationDescriptor),
+}
+
+impl Item {
+    fn name(&self) -> &'static str {
+        match self {
+            Item::Message(message) => message.name,
+            Item::Enumeration(enumeration) => enumeration.name,
+        }
+    }
+}
+
+/// Adds the messages and enumerations that appear in the value to `items`, unless a type with the
+/// same name is already there.
+fn add_referenced_items(value: &'static ValueKind, items: &mut Vec<Item>) {
+    let item = match value {
+        ValueKind::Enumeration(enumeration) => Item::Enumeration(enumeration),
+        ValueKind::Message(message) => Item::Message(message.get()),
+        ValueKind::Optional(inner) | ValueKind::List(inner) | ValueKind::Set(inner) => {
+            return add_referenced_items(inner, items);
+        }
+        ValueKind::Map(key, value) => {
+            add_referenced_items(key, items);
+            return add_referenced_items(value, items);
+        }
+        _ => return,
+    };
+    if !items.iter().any(|existing| existing.name() == item.name()) {
+        items.push(item);
+    }
+}
+
+fn write_schema(out: &mut impl Write, descriptor: &'static MessageDescriptor) -> fmt::Result {
+    let mut items = Vec::from([Item::Message(descriptor)]);
+    // Items are added to the list as they are found in the fields of the messages before them.
+    let mut index = 0;
+    while let Some(&item) = items.get(index) {
+        if index > 0 {
+            out.write_char('\n')?;
+        }
+        match item {
+            Item::Message(message) => {
+                for field in message.all_fields() {
+                    add_referenced_items(field.value, &mut items);
+                }
+                write_message(out, message)?;
+            }
+            Item::Enumeration(enumeration) => write_enumeration(out, enumeration)?,
+        }
+        index += 1;
+    }
+    Ok(())
+}
+
+fn write_enumeration(out: &mut impl Write, enumeration: &EnumerationDescriptor) -> fmt::Result {
+    writeln!(out, "enum {} {{", enumeration.name)?;
+    for variant in enumeration.variants {
+        writeln!(out, "    {} = {};", variant.name, variant.number)?;
+    }
+    out.write_str("}\n")
+}
+
+fn write_message(out: &mut impl Write, message: &MessageDescriptor) -> fmt::Result {
+    writeln!(out, "message {} {{", message.name)?;
+    if !message.reserved_tags.is_empty() {
+        writeln!(
+            out,
+            "    reserved_tags({});",
+            TagList(message.reserved_tags)
+        )?;
+    }
+    for field in message.fields {
+        write_field(out, field, "    ")?;
+    }
+    for oneof in message.oneofs {
+        let mut tags: Vec<u32> = oneof.fields.iter().map(|field| field.tag).collect();
+        tags.sort_unstable();
+        writeln!(
+            out,
+            "    oneof {}({}) {{",
+            FieldName(oneof.name),
+            TagList(&tag_ranges(&tags))
+        )?;
+        for field in oneof.fields {
+            write_field(out, field, "        ")?;
+        }
+        out.write_str("    }\n")?;
+    }
+    if message.has_extensions {
+        out.write_str("    extensions;\n")?;
+    }
+    out.write_str("}\n")
+}
+
+fn write_field(out: &mut impl Write, field: &FieldDescriptor, indent: &str) -> fmt::Result {
+    let name = FieldName(field.name);
+    if !is_describable(field.value) {
+        return writeln!(
+            out,
+            "{indent}// {name} = {}: the type of this field can't be described",
+            field.tag
+        );
+    }
+    write!(out, "{indent}{name}: {} = {}", field.value, field.tag)?;
+    if field.encoding != &EncodingDescriptor::General {
+        write!(out, " encoding({})", field.encoding)?;
+    }
+    out.write_str(";\n")
+}
+
+/// Displays the name of a field. Fields of tuple structs are named by their index, which must be
+/// prefixed to be a name in a schema.
+struct FieldName<'a>(&'a str);
+
+impl fmt::Display for FieldName<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0.starts_with(|c: char| c.is_ascii_digit()) {
+            f.write_char('_')?;
+        }
+        f.write_str(self.0)
+    }
+}
+
+fn is_describable(value: &ValueKind) -> bool {
+    match value {
+        ValueKind::Unknown => false,
+        ValueKind::Optional(inner) | ValueKind::List(inner) | ValueKind::Set(inner) => {
+            is_describable(inner)
+        }
+        ValueKind::Map(key, value) => is_describable(key) && is_describable(value),
+        _ => true,
+    }
+}
+
+/// Groups sorted tags into ranges of consecutive tags.
+fn tag_ranges(tags: &[u32]) -> Vec<RangeInclusive<u32>> {
+    let mut ranges: Vec<RangeInclusive<u32>> = Vec::new();
+    for &tag in tags {
+        match ranges.last_mut() {
+            Some(range) if range.end().checked_add(1) == Some(tag) => {
+                *range = *range.start()..=tag;
+            }
+            _ => ranges.push(tag..=tag),
+        }
+    }
+    ranges
+}
+
+/// Displays tag ranges the way they are written in the `reserved_tags` and `oneof` attributes.
+struct TagList<'a>(&'a [RangeInclusive<u32>]);
+
+impl fmt::Display for TagList<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, range) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            if range.start() == range.end() {
+                write!(f, "{}", range.start())?;
+            } else {
+                write!(f, "{}-{}", range.start(), range.end())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{tag_ranges, TagList};
+    use alloc::string::ToString;
+
+    #[test]
+    fn groups_consecutive_tags() {
+        assert_eq!(tag_ranges(&[]), []);
+        assert_eq!(tag_ranges(&[1, 2, 3, 5, 7, 8]), [1..=3, 5..=5, 7..=8]);
+        assert_eq!(
+            tag_ranges(&[u32::MAX - 1, u32::MAX]),
+            [u32::MAX - 1..=u32::MAX]
+        );
+        assert_eq!(TagList(&[1..=3, 5..=5, 7..=8]).to_string(), "1-3, 5, 7-8");
+    }
+}
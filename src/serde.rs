@@ -0,0 +1,526 @@
+//! Bridges derived messages to the serde data model, so that any serde format can render them
+//! without each message type also deriving serde's traits.
+//!
+//! `Serializable(&message)` implements `serde::Serialize` and `Deserializable<M>` implements
+//! `serde::Deserialize`, both driven by the message's `MessageDescriptor`. Messages are
+//! represented as structs with their fields named as in their descriptors:
+//!
+//! * Every field that is not part of a oneof is present, including fields with empty values.
+//!   Optional fields that are `None` are represented as none.
+//! * Each oneof is a field named for the oneof, holding an externally tagged enum whose variant is
+//!   the name of the field that is set, or none if no field is set.
+//! * Strings and bytes are represented as strings and byte sequences.
+//! * Enumerations are represented as unit variants named for their values.
+//! * Collections are represented as sequences and maps as maps.
+//!
+//! Unknown fields, including those captured in extensions, are not represented. `DynamicMessage`
+//! implements `serde::Serialize` the same way.
+//!
+//! Deserializing is strict: fields and enumeration values with names that are not in the
+//! descriptor are rejected, as are values out of their field's range. Since field names are only
+//! known at runtime, messages are deserialized as maps, and only self-describing formats such as
+//! JSON, YAML and CBOR are supported.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+
+use ::serde::de::{
+    self, DeserializeSeed, EnumAccess, MapAccess, SeqAccess, VariantAccess, Visitor,
+};
+use ::serde::ser::{self, SerializeMap, SerializeSeq, SerializeStruct};
+use ::serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::descriptor::{MessageDescriptor, OneofDescriptor, ValueKind};
+use crate::dynamic::{new_value, DynamicMessage, Value};
+use crate::{Message, OwnedMessage};
+
+/// Serializes a message through the serde data model, as described in the module documentation.
+#[derive(Clone, Copy, Debug)]
+pub struct Serializable<'a, M: ?Sized>(pub &'a M);
+
+impl<M: Message + ?Sized> Serialize for Serializable<'_, M> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let message =
+            DynamicMessage::decode(self.0.descriptor(), self.0.encode_to_vec().as_slice())
+                .map_err(ser::Error::custom)?;
+        message.serialize(serializer)
+    }
+}
+
+/// Deserializes a message through the serde data model, as described in the module
+/// documentation. The message is available in the public field.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Deserializable<M>(pub M);
+
+impl<'de, M: OwnedMessage> Deserialize<'de> for Deserializable<M> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let message = MessageSeed(M::empty().descriptor()).deserialize(deserializer)?;
+        M::decode(message.encode_to_vec().as_slice())
+            .map(Deserializable)
+            .map_err(de::Error::custom)
+    }
+}
+
+impl Serialize for DynamicMessage {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let descriptor = self.descriptor();
+        let mut state = serializer.serialize_struct(
+            descriptor.name,
+            descriptor.fields.len() + descriptor.oneofs.len(),
+        )?;
+        for field in descriptor.fields {
+            if field.value == &ValueKind::Unknown {
+                continue;
+            }
+            let value = self.get_by_tag(field.tag);
+            match field.value {
+                ValueKind::Optional(inner) => state.serialize_field(
+                    field.name,
+                    &value.map(|value| SerializeValue(inner, value)),
+                )?,
+                kind => {
+                    let empty;
+                    let value = match value {
+                        Some(value) => value,
+                        None => {
+                            empty = new_value(kind);
+                            &empty
+                        }
+                    };
+                    state.serialize_field(field.name, &SerializeValue(kind, value))?
+                }
+            }
+        }
+        for oneof in descriptor.oneofs {
+            let variant = oneof.fields.iter().enumerate().find_map(|(index, field)| {
+                self.get_by_tag(field.tag).map(|value| SerializeVariant {
+                    oneof: oneof.name,
+                    index: index as u32,
+                    name: field.name,
+                    value: SerializeValue(field.value, value),
+                })
+            });
+            state.serialize_field(oneof.name, &variant)?;
+        }
+        state.end()
+    }
+}
+
+/// Serializes a value with the kind of value it holds.
+struct SerializeValue<'a>(&'static ValueKind, &'a Value);
+
+impl Serialize for SerializeValue<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let item_kind = |kind: &'static ValueKind| match kind {
+            ValueKind::List(item) | ValueKind::Set(item) => item,
+            _ => &ValueKind::Unknown,
+        };
+        match self.1 {
+            Value::Bool(value) => serializer.serialize_bool(*value),
+            Value::U8(value) => serializer.serialize_u8(*value),
+            Value::U16(value) => serializer.serialize_u16(*value),
+            Value::U32(value) => serializer.serialize_u32(*value),
+            Value::U64(value) => serializer.serialize_u64(*value),
+            Value::U128(value) => serializer.serialize_u128(*value),
+            Value::I8(value) => serializer.serialize_i8(*value),
+            Value::I16(value) => serializer.serialize_i16(*value),
+            Value::I32(value) => serializer.serialize_i32(*value),
+            Value::I64(value) => serializer.serialize_i64(*value),
+            Value::I128(value) => serializer.serialize_i128(*value),
+            Value::F32(value) => serializer.serialize_f32(*value),
+            Value::F64(value) => serializer.serialize_f64(*value),
+            Value::String(value) => serializer.serialize_str(value),
+            Value::Bytes(value) => serializer.serialize_bytes(value),
+            Value::Enumeration(number) => {
+                let ValueKind::Enumeration(enumeration) = self.0 else {
+                    return serializer.serialize_u32(*number);
+                };
+                match enumeration
+                    .variants
+                    .iter()
+                    .position(|variant| variant.number == *number)
+                {
+                    Some(index) => serializer.serialize_unit_variant(
+                        enumeration.name,
+                        index as u32,
+                        enumeration.variants[index].name,
+                    ),
+                    None => serializer.serialize_u32(*number),
+                }
+            }
+            Value::Message(message) => message.serialize(serializer),
+            Value::List(items) | Value::Set(items) => {
+                let item_kind = item_kind(self.0);
+                let mut seq = serializer.serialize_seq(Some(items.len()))?;
+                for item in items {
+                    seq.serialize_element(&SerializeValue(item_kind, item))?;
+                }
+                seq.end()
+            }
+            Value::Map(entries) => {
+                let (key_kind, value_kind) = match self.0 {
+                    ValueKind::Map(key, value) => (*key, *value),
+                    _ => (&ValueKind::Unknown, &ValueKind::Unknown),
+                };
+                let mut map = serializer.serialize_map(Some(entries.len()))?;
+                for (key, value) in entries {
+                    map.serialize_entry(
+                        &SerializeValue(key_kind, key),
+                        &SerializeValue(value_kind, value),
+                    )?;
+                }
+                map.end()
+            }
+        }
+    }
+}
+
+/// Serializes the field of a oneof that is set as an externally tagged enum variant.
+struct SerializeVariant<'a> {
+    oneof: &'static str,
+    index: u32,
+    name: &'static str,
+    value: SerializeValue<'a>,
+}
+
+impl Serialize for SerializeVariant<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_newtype_variant(self.oneof, self.index, self.name, &self.value)
+    }
+}
+
+/// Deserializes a message with the given descriptor.
+struct MessageSeed(&'static MessageDescriptor);
+
+impl<'de> DeserializeSeed<'de> for MessageSeed {
+    type Value = DynamicMessage;
+
+    fn deserialize<D: Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> Result<DynamicMessage, D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de> Visitor<'de> for MessageSeed {
+    type Value = DynamicMessage;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a {} message", self.0.name)
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<DynamicMessage, A::Error> {
+        let descriptor = self.0;
+        let mut message = DynamicMessage::new(descriptor);
+        let mut seen: Vec<String> = Vec::new();
+        while let Some(name) = map.next_key::<String>()? {
+            if seen.contains(&name) {
+                return Err(de::Error::custom(format!("duplicate field `{name}`")));
+            }
+            if let Some(field) = descriptor.field_by_name(&name) {
+                if descriptor.oneof_for_tag(field.tag).is_none() {
+                    let value = match field.value {
+                        ValueKind::Optional(inner) => map.next_value_seed(OptionalSeed(inner))?,
+                        kind => Some(map.next_value_seed(ValueSeed(kind))?),
+                    };
+                    if let Some(value) = value {
+                        message
+                            .set_by_tag(field.tag, value)
+                            .map_err(|err| de::Error::custom(format!("field `{name}`: {err}")))?;
+                    }
+                    seen.push(name);
+                    continue;
+                }
+            }
+            let Some(oneof) = descriptor.oneofs.iter().find(|oneof| oneof.name == name) else {
+                return Err(de::Error::custom(format!(
+                    "unknown field `{name}` in {}",
+                    descriptor.name
+                )));
+            };
+            if let Some((tag, value)) = map.next_value_seed(OneofSeed(oneof))? {
+                message
+                    .set_by_tag(tag, value)
+                    .map_err(|err| de::Error::custom(format!("field `{name}`: {err}")))?;
+            }
+            seen.push(name);
+        }
+        Ok(message)
+    }
+}
+
+/// Deserializes a value of the given kind.
+struct ValueSeed(&'static ValueKind);
+
+impl<'de> DeserializeSeed<'de> for ValueSeed {
+    type Value = Value;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Value, D::Error> {
+        match self.0 {
+            ValueKind::Bool => deserializer.deserialize_bool(self),
+            ValueKind::U8 => deserializer.deserialize_u8(self),
+            ValueKind::U16 => deserializer.deserialize_u16(self),
+            ValueKind::U32 => deserializer.deserialize_u32(self),
+            ValueKind::U64 => deserializer.deserialize_u64(self),
+            ValueKind::U128 => deserializer.deserialize_u128(self),
+            ValueKind::I8 => deserializer.deserialize_i8(self),
+            ValueKind::I16 => deserializer.deserialize_i16(self),
+            ValueKind::I32 => deserializer.deserialize_i32(self),
+            ValueKind::I64 => deserializer.deserialize_i64(self),
+            ValueKind::I128 => deserializer.deserialize_i128(self),
+            ValueKind::F32 => deserializer.deserialize_f32(self),
+            ValueKind::F64 => deserializer.deserialize_f64(self),
+            ValueKind::String => deserializer.deserialize_string(self),
+            ValueKind::Bytes => deserializer.deserialize_byte_buf(self),
+            ValueKind::Enumeration(enumeration) => {
+                deserializer.deserialize_enum(enumeration.name, &[], self)
+            }
+            ValueKind::Message(message) => MessageSeed(message.get())
+                .deserialize(deserializer)
+                .map(Value::Message),
+            ValueKind::List(_) | ValueKind::Set(_) => deserializer.deserialize_seq(self),
+            ValueKind::Map(..) => deserializer.deserialize_map(self),
+            ValueKind::Optional(_) | ValueKind::Unknown => Err(de::Error::custom(format!(
+                "values of kind {} are not supported",
+                self.0
+            ))),
+        }
+    }
+}
+
+impl ValueSeed {
+    fn integer<E: de::Error>(&self, value: i128) -> Result<Value, E> {
+        let out_of_range = || E::custom(format!("{value} is out of range for {}", self.0));
+        Ok(match self.0 {
+            ValueKind::U8 => Value::U8(value.try_into().map_err(|_| out_of_range())?),
+            ValueKind::U16 => Value::U16(value.try_into().map_err(|_| out_of_range())?),
+            ValueKind::U32 => Value::U32(value.try_into().map_err(|_| out_of_range())?),
+            ValueKind::U64 => Value::U64(value.try_into().map_err(|_| out_of_range())?),
+            ValueKind::U128 => Value::U128(value.try_into().map_err(|_| out_of_range())?),
+            ValueKind::I8 => Value::I8(value.try_into().map_err(|_| out_of_range())?),
+            ValueKind::I16 => Value::I16(value.try_into().map_err(|_| out_of_range())?),
+            ValueKind::I32 => Value::I32(value.try_into().map_err(|_| out_of_range())?),
+            ValueKind::I64 => Value::I64(value.try_into().map_err(|_| out_of_range())?),
+            ValueKind::I128 => Value::I128(value),
+            ValueKind::F32 => Value::F32(value as f32),
+            ValueKind::F64 => Value::F64(value as f64),
+            ValueKind::Enumeration(enumeration) => {
+                let number = value.try_into().map_err(|_| out_of_range())?;
+                if enumeration.name_of(number).is_none() {
+                    return Err(E::custom(format!(
+                        "{number} is not a value of {}",
+                        enumeration.name
+                    )));
+                }
+                Value::Enumeration(number)
+            }
+            _ => {
+                return Err(de::Error::invalid_type(
+                    de::Unexpected::Other("integer"),
+                    self,
+                ))
+            }
+        })
+    }
+}
+
+impl<'de> Visitor<'de> for ValueSeed {
+    type Value = Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            ValueKind::Message(message) => write!(f, "a {} message", message.get().name),
+            ValueKind::Enumeration(enumeration) => write!(f, "a {} value", enumeration.name),
+            kind => write!(f, "a value of kind {kind}"),
+        }
+    }
+
+    fn visit_bool<E: de::Error>(self, value: bool) -> Result<Value, E> {
+        match self.0 {
+            ValueKind::Bool => Ok(Value::Bool(value)),
+            _ => Err(E::invalid_type(de::Unexpected::Bool(value), &self)),
+        }
+    }
+
+    fn visit_i64<E: de::Error>(self, value: i64) -> Result<Value, E> {
+        self.integer(value.into())
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<Value, E> {
+        self.integer(value.into())
+    }
+
+    fn visit_i128<E: de::Error>(self, value: i128) -> Result<Value, E> {
+        self.integer(value)
+    }
+
+    fn visit_u128<E: de::Error>(self, value: u128) -> Result<Value, E> {
+        match (self.0, i128::try_from(value)) {
+            (ValueKind::U128, _) => Ok(Value::U128(value)),
+            (_, Ok(value)) => self.integer(value),
+            (_, Err(_)) => Err(E::custom(format!("{value} is out of range for {}", self.0))),
+        }
+    }
+
+    fn visit_f64<E: de::Error>(self, value: f64) -> Result<Value, E> {
+        match self.0 {
+            ValueKind::F32 => Ok(Value::F32(value as f32)),
+            ValueKind::F64 => Ok(Value::F64(value)),
+            _ => Err(E::invalid_type(de::Unexpected::Float(value), &self)),
+        }
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<Value, E> {
+        match self.0 {
+            ValueKind::String => Ok(Value::String(value.into())),
+            ValueKind::Enumeration(enumeration) => match enumeration.number_of(value) {
+                Some(number) => Ok(Value::Enumeration(number)),
+                None => Err(E::custom(format!(
+                    "unknown variant `{value}` of {}",
+                    enumeration.name
+                ))),
+            },
+            _ => Err(E::invalid_type(de::Unexpected::Str(value), &self)),
+        }
+    }
+
+    fn visit_string<E: de::Error>(self, value: String) -> Result<Value, E> {
+        match self.0 {
+            ValueKind::String => Ok(Value::String(value)),
+            _ => self.visit_str(&value),
+        }
+    }
+
+    fn visit_bytes<E: de::Error>(self, value: &[u8]) -> Result<Value, E> {
+        match self.0 {
+            ValueKind::Bytes => Ok(Value::Bytes(value.into())),
+            _ => Err(E::invalid_type(de::Unexpected::Bytes(value), &self)),
+        }
+    }
+
+    fn visit_byte_buf<E: de::Error>(self, value: Vec<u8>) -> Result<Value, E> {
+        match self.0 {
+            ValueKind::Bytes => Ok(Value::Bytes(value)),
+            _ => self.visit_bytes(&value),
+        }
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Value, A::Error> {
+        match self.0 {
+            // Formats without a native representation of bytes, like JSON, show them as sequences.
+            ValueKind::Bytes => {
+                let mut bytes = Vec::new();
+                while let Some(byte) = seq.next_element::<u8>()? {
+                    bytes.push(byte);
+                }
+                Ok(Value::Bytes(bytes))
+            }
+            ValueKind::List(item) | ValueKind::Set(item) => {
+                let mut items = Vec::new();
+                while let Some(item) = seq.next_element_seed(ValueSeed(item))? {
+                    items.push(item);
+                }
+                Ok(match self.0 {
+                    ValueKind::Set(_) => Value::Set(items),
+                    _ => Value::List(items),
+                })
+            }
+            _ => Err(de::Error::invalid_type(de::Unexpected::Seq, &self)),
+        }
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Value, A::Error> {
+        let ValueKind::Map(key_kind, value_kind) = self.0 else {
+            return Err(de::Error::invalid_type(de::Unexpected::Map, &self));
+        };
+        let mut entries = Vec::new();
+        while let Some(key) = map.next_key_seed(ValueSeed(key_kind))? {
+            entries.push((key, map.next_value_seed(ValueSeed(value_kind))?));
+        }
+        Ok(Value::Map(entries))
+    }
+
+    fn visit_enum<A: EnumAccess<'de>>(self, data: A) -> Result<Value, A::Error> {
+        let (name, variant) = data.variant::<String>()?;
+        variant.unit_variant()?;
+        self.visit_string(name)
+    }
+}
+
+/// Deserializes an optional value of the given kind.
+struct OptionalSeed(&'static ValueKind);
+
+impl<'de> DeserializeSeed<'de> for OptionalSeed {
+    type Value = Option<Value>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Option<Value>, D::Error> {
+        deserializer.deserialize_option(self)
+    }
+}
+
+impl<'de> Visitor<'de> for OptionalSeed {
+    type Value = Option<Value>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "an optional value of kind {}", self.0)
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<Option<Value>, E> {
+        Ok(None)
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<Option<Value>, E> {
+        Ok(None)
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Option<Value>, D::Error> {
+        ValueSeed(self.0).deserialize(deserializer).map(Some)
+    }
+}
+
+/// Deserializes a oneof as an optional externally tagged enum, returning the tag and value of the
+/// field that is set.
+struct OneofSeed(&'static OneofDescriptor);
+
+impl<'de> DeserializeSeed<'de> for OneofSeed {
+    type Value = Option<(u32, Value)>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_option(self)
+    }
+}
+
+impl<'de> Visitor<'de> for OneofSeed {
+    type Value = Option<(u32, Value)>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a variant of oneof {}", self.0.name)
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<Self::Value, E> {
+        Ok(None)
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<Self::Value, E> {
+        Ok(None)
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_enum(self.0.name, &[], self)
+    }
+
+    fn visit_enum<A: EnumAccess<'de>>(self, data: A) -> Result<Self::Value, A::Error> {
+        let (name, variant) = data.variant::<String>()?;
+        let Some(field) = self.0.fields.iter().find(|field| field.name == name) else {
+            return Err(de::Error::custom(format!(
+                "unknown variant `{name}` of oneof {}",
+                self.0.name
+            )));
+        };
+        let value = variant.newtype_variant_seed(ValueSeed(field.value))?;
+        Ok(Some((field.tag, value)))
+    }
+}
@@ -0,0 +1,826 @@
+//! Reading and writing streams of length-delimited messages.
+//!
+//! A message stream is a sequence of frames, each consisting of a message encoded with
+//! [`encode_length_delimited`](Message::encode_length_delimited): a varint length delimiter
+//! followed by exactly that many bytes of message data. Readers buffer partial frames from the
+//! underlying source until each complete frame is available, and distinguish a stream that ends
+//! cleanly between frames from one that ends partway through a frame.
+
+use alloc::vec::Vec;
+use core::fmt;
+use core::marker::PhantomData;
+use core::ops::Range;
+use std::io;
+
+#[cfg(feature = "tokio")]
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::{
+    decode_length_delimiter, Canonicity, DecodeError, DistinguishedOwnedMessage, Message,
+    OwnedMessage,
+};
+
+/// The default maximum size of the message in a single frame, 64MiB.
+pub const DEFAULT_MAX_FRAME_SIZE: usize = 64 << 20;
+
+/// How many bytes we try to read from the underlying source at a time.
+const READ_CHUNK_SIZE: usize = 8 << 10;
+
+/// The longest a varint length delimiter can be.
+const MAX_DELIMITER_LEN: usize = 9;
+
+/// Errors that can occur while reading or writing a stream of messages.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum StreamError {
+    /// The underlying reader or writer returned an error.
+    Io(io::Error),
+    /// The message in the frame failed to decode. The frame is skipped, and the stream can
+    /// continue to be read.
+    Decode(DecodeError),
+    /// The frame's length delimiter was invalid. This ends the stream.
+    InvalidDelimiter(DecodeError),
+    /// The frame's message is larger than the maximum frame size. This ends the stream.
+    FrameTooLarge {
+        /// The size of the message in the frame.
+        length: usize,
+        /// The maximum allowed frame size.
+        max: usize,
+    },
+    /// The stream ended partway through a frame.
+    TruncatedFrame,
+    /// The stream was already ended by an earlier error, and nothing more can be read from it.
+    Poisoned,
+}
+
+impl fmt::Display for StreamError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StreamError::Io(err) => write!(f, "message stream I/O error: {}", err),
+            StreamError::Decode(err) => err.fmt(f),
+            StreamError::InvalidDelimiter(err) => {
+                write!(f, "invalid message frame length delimiter: {}", err)
+            }
+            StreamError::FrameTooLarge { length, max } => write!(
+                f,
+                "message frame too large (length: {}, max: {})",
+                length, max
+            ),
+            StreamError::TruncatedFrame => f.write_str("message stream ended mid-frame"),
+            StreamError::Poisoned => {
+                f.write_str("message stream was already ended by an earlier error")
+            }
+        }
+    }
+}
+
+impl std::error::Error for StreamError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            StreamError::Io(err) => Some(err),
+            StreamError::Decode(err) | StreamError::InvalidDelimiter(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for StreamError {
+    fn from(error: io::Error) -> Self {
+        StreamError::Io(error)
+    }
+}
+
+impl From<DecodeError> for StreamError {
+    fn from(error: DecodeError) -> Self {
+        StreamError::Decode(error)
+    }
+}
+
+impl From<StreamError> for io::Error {
+    fn from(error: StreamError) -> io::Error {
+        match error {
+            StreamError::Io(err) => err,
+            StreamError::Decode(err) => err.into(),
+            StreamError::TruncatedFrame => io::Error::new(io::ErrorKind::UnexpectedEof, error),
+            StreamError::InvalidDelimiter(_)
+            | StreamError::FrameTooLarge { .. }
+            | StreamError::Poisoned => io::Error::new(io::ErrorKind::InvalidData, error),
+        }
+    }
+}
+
+/// Decodes the length delimiter at the start of `buf` without consuming it, returning the length of
+/// the delimiter itself and the length it specifies. Returns `Ok(None)` if the delimiter is not yet
+/// complete.
+pub(crate) fn peek_length_delimiter(buf: &[u8]) -> Result<Option<(usize, usize)>, DecodeError> {
+    // Varints end at the first byte without its high bit set, or after their ninth byte.
+    let delimiter_len = match buf
+        .iter()
+        .take(MAX_DELIMITER_LEN)
+        .position(|&byte| byte < 0x80)
+    {
+        Some(last) => last + 1,
+        None if buf.len() >= MAX_DELIMITER_LEN => MAX_DELIMITER_LEN,
+        None => return Ok(None),
+    };
+    let length = decode_length_delimiter(&buf[..delimiter_len])?;
+    Ok(Some((delimiter_len, length)))
+}
+
+/// Buffer for bytes read from a stream that have not yet been yielded as frames.
+#[derive(Debug)]
+struct FrameBuffer {
+    buf: Vec<u8>,
+    /// The number of bytes at the front of `buf` that belong to already-yielded frames.
+    consumed: usize,
+    /// The number of bytes at the front of `buf` that have been read from the stream.
+    filled: usize,
+    max_frame_size: usize,
+    /// Set when the stream can't find the start of the next frame, after which no more frames are
+    /// yielded.
+    failed: bool,
+}
+
+impl FrameBuffer {
+    fn new(max_frame_size: usize) -> Self {
+        Self {
+            buf: Vec::new(),
+            consumed: 0,
+            filled: 0,
+            max_frame_size,
+            failed: false,
+        }
+    }
+
+    fn buffered(&self) -> &[u8] {
+        &self.buf[self.consumed..self.filled]
+    }
+
+    /// Returns the range of the message data in the next frame if the whole frame is buffered.
+    fn next_frame(&self) -> Result<Option<Range<usize>>, StreamError> {
+        let buffered = self.buffered();
+        let Some((delimiter_len, length)) =
+            peek_length_delimiter(buffered).map_err(StreamError::InvalidDelimiter)?
+        else {
+            return Ok(None);
+        };
+        if length > self.max_frame_size {
+            return Err(StreamError::FrameTooLarge {
+                length,
+                max: self.max_frame_size,
+            });
+        }
+        if buffered.len() - delimiter_len < length {
+            return Ok(None);
+        }
+        let start = self.consumed + delimiter_len;
+        Ok(Some(start..start + length))
+    }
+
+    /// Decodes the message in a frame returned by `next_frame`, consuming the frame whether or not
+    /// decoding succeeds.
+    fn decode_frame<M: OwnedMessage>(&mut self, frame: Range<usize>) -> Result<M, StreamError> {
+        let decoded = M::decode(&self.buf[frame.clone()]);
+        self.consumed = frame.end;
+        Ok(decoded?)
+    }
+
+    /// Decodes the message in a frame returned by `next_frame` in distinguished mode, consuming the
+    /// frame whether or not decoding succeeds.
+    fn decode_frame_distinguished<M: DistinguishedOwnedMessage>(
+        &mut self,
+        frame: Range<usize>,
+    ) -> Result<(M, Canonicity), StreamError> {
+        let decoded = M::decode_distinguished(&self.buf[frame.clone()]);
+        self.consumed = frame.end;
+        Ok(decoded?)
+    }
+
+    /// Discards consumed bytes and returns space at the end of the buffer to read into. The read
+    /// must then be completed with `finish_read`.
+    fn read_space(&mut self) -> &mut [u8] {
+        if self.consumed > 0 {
+            self.buf.copy_within(self.consumed..self.filled, 0);
+            self.filled -= self.consumed;
+            self.consumed = 0;
+        }
+        if self.buf.len() < self.filled + READ_CHUNK_SIZE {
+            self.buf.resize(self.filled + READ_CHUNK_SIZE, 0);
+        }
+        &mut self.buf[self.filled..]
+    }
+
+    /// Completes a read into the space returned by `read_space`, keeping the first `read` bytes.
+    fn finish_read(&mut self, read: usize) {
+        self.filled += read;
+    }
+
+    /// Returns an error if an earlier read has already ended the stream.
+    fn check_poisoned(&self) -> Result<(), StreamError> {
+        if self.failed {
+            Err(StreamError::Poisoned)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Records the result of reading the next frame. An invalid or oversized length delimiter
+    /// leaves no way to find where the next frame starts, so after one of those has been returned
+    /// every later read fails with `StreamError::Poisoned`. I/O errors and truncated frames leave
+    /// the buffered bytes intact, so reading can be retried after them.
+    fn check_read(
+        &mut self,
+        read: Result<Option<Range<usize>>, StreamError>,
+    ) -> Result<Option<Range<usize>>, StreamError> {
+        if let Err(StreamError::InvalidDelimiter(_) | StreamError::FrameTooLarge { .. }) = read {
+            self.failed = true;
+        }
+        read
+    }
+
+    /// Called when the underlying stream has ended; the stream ended cleanly if there are no
+    /// leftover bytes.
+    fn finish_stream(&self) -> Result<Option<Range<usize>>, StreamError> {
+        if self.buffered().is_empty() {
+            Ok(None)
+        } else {
+            Err(StreamError::TruncatedFrame)
+        }
+    }
+}
+
+/// Reads successive length-delimited messages from an [`io::Read`].
+///
+/// Bytes are read from the underlying reader in chunks and buffered, so the reader may have read
+/// past the end of the last frame that was yielded; these bytes are available from
+/// [`buffered`](Self::buffered).
+#[derive(Debug)]
+pub struct MessageStreamReader<R> {
+    reader: R,
+    frames: FrameBuffer,
+}
+
+impl<R: io::Read> MessageStreamReader<R> {
+    /// Creates a new reader with the default maximum frame size.
+    pub fn new(reader: R) -> Self {
+        Self::with_max_frame_size(reader, DEFAULT_MAX_FRAME_SIZE)
+    }
+
+    /// Creates a new reader that rejects frames whose messages are larger than `max_frame_size`
+    /// bytes.
+    pub fn with_max_frame_size(reader: R, max_frame_size: usize) -> Self {
+        Self {
+            reader,
+            frames: FrameBuffer::new(max_frame_size),
+        }
+    }
+
+    /// Returns the maximum size of a message this reader will accept.
+    pub fn max_frame_size(&self) -> usize {
+        self.frames.max_frame_size
+    }
+
+    /// Returns a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        &self.reader
+    }
+
+    /// Returns a mutable reference to the underlying reader. Reading from it directly will
+    /// probably corrupt the stream.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.reader
+    }
+
+    /// Returns the bytes that have been read from the underlying reader but not yet yielded.
+    pub fn buffered(&self) -> &[u8] {
+        self.frames.buffered()
+    }
+
+    /// Unwraps this reader, returning the underlying reader. Any buffered bytes are lost.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    fn next_frame(&mut self) -> Result<Option<Range<usize>>, StreamError> {
+        self.frames.check_poisoned()?;
+        let read = self.read_frame();
+        self.frames.check_read(read)
+    }
+
+    fn read_frame(&mut self) -> Result<Option<Range<usize>>, StreamError> {
+        loop {
+            if let Some(frame) = self.frames.next_frame()? {
+                return Ok(Some(frame));
+            }
+            let space = self.frames.read_space();
+            let read = loop {
+                match self.reader.read(space) {
+                    Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                    read => break read,
+                }
+            };
+            self.frames.finish_read(*read.as_ref().unwrap_or(&0));
+            if read? == 0 {
+                return self.frames.finish_stream();
+            }
+        }
+    }
+
+    /// Reads the next message from the stream. Returns `Ok(None)` if the stream ended cleanly
+    /// between frames.
+    ///
+    /// If the frame was read successfully but its message failed to decode, the frame is skipped
+    /// and the stream can continue to be read. Reading can also be retried after I/O errors and
+    /// truncated frames, as no data is lost. An invalid or oversized length delimiter ends the
+    /// stream, and every later read fails with `StreamError::Poisoned`.
+    pub fn read_message<M: OwnedMessage>(&mut self) -> Result<Option<M>, StreamError> {
+        match self.next_frame()? {
+            Some(frame) => self.frames.decode_frame(frame).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Reads the next message from the stream in distinguished mode. Returns `Ok(None)` if the
+    /// stream ended cleanly between frames.
+    ///
+    /// If the frame was read successfully but its message failed to decode, the frame is skipped
+    /// and the stream can continue to be read. Reading can also be retried after I/O errors and
+    /// truncated frames, as no data is lost. An invalid or oversized length delimiter ends the
+    /// stream, and every later read fails with `StreamError::Poisoned`.
+    pub fn read_distinguished_message<M: DistinguishedOwnedMessage>(
+        &mut self,
+    ) -> Result<Option<(M, Canonicity)>, StreamError> {
+        match self.next_frame()? {
+            Some(frame) => self.frames.decode_frame_distinguished(frame).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns an iterator over the messages in the stream.
+    pub fn messages<M: OwnedMessage>(&mut self) -> Messages<'_, R, M> {
+        Messages {
+            reader: self,
+            done: false,
+            _phantom: PhantomData,
+        }
+    }
+}
+
+/// Iterator over the messages read from a [`MessageStreamReader`]. The iterator continues past
+/// frames whose messages fail to decode, and ends after any other error.
+#[derive(Debug)]
+pub struct Messages<'a, R, M> {
+    reader: &'a mut MessageStreamReader<R>,
+    done: bool,
+    _phantom: PhantomData<fn() -> M>,
+}
+
+impl<R: io::Read, M: OwnedMessage> Iterator for Messages<'_, R, M> {
+    type Item = Result<M, StreamError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let read = self.reader.read_message().transpose();
+        self.done = !matches!(read, Some(Ok(_) | Err(StreamError::Decode(_))));
+        read
+    }
+}
+
+/// Writes length-delimited messages to an [`io::Write`].
+#[derive(Debug)]
+pub struct MessageStreamWriter<W> {
+    writer: W,
+    buf: Vec<u8>,
+}
+
+impl<W: io::Write> MessageStreamWriter<W> {
+    /// Creates a new writer.
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            buf: Vec::new(),
+        }
+    }
+
+    /// Returns a reference to the underlying writer.
+    pub fn get_ref(&self) -> &W {
+        &self.writer
+    }
+
+    /// Returns a mutable reference to the underlying writer.
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.writer
+    }
+
+    /// Unwraps this writer, returning the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    /// Writes a message to the stream as a single frame.
+    pub fn write_message<M: Message>(&mut self, message: &M) -> io::Result<()> {
+        self.buf.clear();
+        message.encode_length_delimited(&mut self.buf)?;
+        self.writer.write_all(&self.buf)
+    }
+
+    /// Flushes the underlying writer.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Reads successive length-delimited messages from a tokio [`AsyncRead`].
+///
+/// Bytes are read from the underlying reader in chunks and buffered, so the reader may have read
+/// past the end of the last frame that was yielded; these bytes are available from
+/// [`buffered`](Self::buffered).
+#[cfg(feature = "tokio")]
+#[derive(Debug)]
+pub struct AsyncMessageStreamReader<R> {
+    reader: R,
+    frames: FrameBuffer,
+}
+
+#[cfg(feature = "tokio")]
+impl<R: AsyncRead + Unpin> AsyncMessageStreamReader<R> {
+    /// Creates a new reader with the default maximum frame size.
+    pub fn new(reader: R) -> Self {
+        Self::with_max_frame_size(reader, DEFAULT_MAX_FRAME_SIZE)
+    }
+
+    /// Creates a new reader that rejects frames whose messages are larger than `max_frame_size`
+    /// bytes.
+    pub fn with_max_frame_size(reader: R, max_frame_size: usize) -> Self {
+        Self {
+            reader,
+            frames: FrameBuffer::new(max_frame_size),
+        }
+    }
+
+    /// Returns the maximum size of a message this reader will accept.
+    pub fn max_frame_size(&self) -> usize {
+        self.frames.max_frame_size
+    }
+
+    /// Returns a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        &self.reader
+    }
+
+    /// Returns a mutable reference to the underlying reader. Reading from it directly will
+    /// probably corrupt the stream.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.reader
+    }
+
+    /// Returns the bytes that have been read from the underlying reader but not yet yielded.
+    pub fn buffered(&self) -> &[u8] {
+        self.frames.buffered()
+    }
+
+    /// Unwraps this reader, returning the underlying reader. Any buffered bytes are lost.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    async fn next_frame(&mut self) -> Result<Option<Range<usize>>, StreamError> {
+        self.frames.check_poisoned()?;
+        let read = self.read_frame().await;
+        self.frames.check_read(read)
+    }
+
+    async fn read_frame(&mut self) -> Result<Option<Range<usize>>, StreamError> {
+        loop {
+            if let Some(frame) = self.frames.next_frame()? {
+                return Ok(Some(frame));
+            }
+            let read = self.reader.read(self.frames.read_space()).await;
+            self.frames.finish_read(*read.as_ref().unwrap_or(&0));
+            if read? == 0 {
+                return self.frames.finish_stream();
+            }
+        }
+    }
+
+    /// Reads the next message from the stream. Returns `Ok(None)` if the stream ended cleanly
+    /// between frames.
+    ///
+    /// If the frame was read successfully but its message failed to decode, the frame is skipped
+    /// and the stream can continue to be read. Reading can also be retried after I/O errors and
+    /// truncated frames, as no data is lost. An invalid or oversized length delimiter ends the
+    /// stream, and every later read fails with `StreamError::Poisoned`.
+    pub async fn read_message<M: OwnedMessage>(&mut self) -> Result<Option<M>, StreamError> {
+        match self.next_frame().await? {
+            Some(frame) => self.frames.decode_frame(frame).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Reads the next message from the stream in distinguished mode. Returns `Ok(None)` if the
+    /// stream ended cleanly between frames.
+    ///
+    /// If the frame was read successfully but its message failed to decode, the frame is skipped
+    /// and the stream can continue to be read. Reading can also be retried after I/O errors and
+    /// truncated frames, as no data is lost. An invalid or oversized length delimiter ends the
+    /// stream, and every later read fails with `StreamError::Poisoned`.
+    pub async fn read_distinguished_message<M: DistinguishedOwnedMessage>(
+        &mut self,
+    ) -> Result<Option<(M, Canonicity)>, StreamError> {
+        match self.next_frame().await? {
+            Some(frame) => self.frames.decode_frame_distinguished(frame).map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Writes length-delimited messages to a tokio [`AsyncWrite`].
+#[cfg(feature = "tokio")]
+#[derive(Debug)]
+pub struct AsyncMessageStreamWriter<W> {
+    writer: W,
+    buf: Vec<u8>,
+}
+
+#[cfg(feature = "tokio")]
+impl<W: AsyncWrite + Unpin> AsyncMessageStreamWriter<W> {
+    /// Creates a new writer.
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            buf: Vec::new(),
+        }
+    }
+
+    /// Returns a reference to the underlying writer.
+    pub fn get_ref(&self) -> &W {
+        &self.writer
+    }
+
+    /// Returns a mutable reference to the underlying writer.
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.writer
+    }
+
+    /// Unwraps this writer, returning the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    /// Writes a message to the stream as a single frame.
+    pub async fn write_message<M: Message>(&mut self, message: &M) -> io::Result<()> {
+        self.buf.clear();
+        message.encode_length_delimited(&mut self.buf)?;
+        self.writer.write_all(&self.buf).await
+    }
+
+    /// Flushes the underlying writer.
+    pub async fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush().await
+    }
+
+    /// Shuts down the underlying writer.
+    pub async fn shutdown(&mut self) -> io::Result<()> {
+        self.writer.shutdown().await
+    }
+}
+
+#[cfg(all(test, feature = "opaque"))]
+mod test {
+    use alloc::vec;
+    use alloc::vec::Vec;
+    use std::io;
+
+    use super::{MessageStreamReader, MessageStreamWriter, StreamError};
+    use crate::encoding::opaque::{OpaqueMessage, OpaqueValue as OV};
+    use crate::{Canonicity, DecodeErrorKind, Message};
+
+    fn messages() -> Vec<OpaqueMessage<'static>> {
+        vec![
+            OpaqueMessage::from_iter([(1, OV::string("hello")), (2, OV::u64(2))]),
+            OpaqueMessage::new(),
+            OpaqueMessage::from_iter([(5, OV::bytes(vec![0x55; 20_000]))]),
+            OpaqueMessage::from_iter([(3, OV::fixed_u32(3))]),
+        ]
+    }
+
+    fn encoded_stream() -> Vec<u8> {
+        let mut writer = MessageStreamWriter::new(Vec::new());
+        for message in messages() {
+            writer.write_message(&message).unwrap();
+        }
+        writer.flush().unwrap();
+        writer.into_inner()
+    }
+
+    /// Reader that returns at most one byte at a time, interrupting every other read.
+    struct Trickle<'a> {
+        data: &'a [u8],
+        interrupt: bool,
+    }
+
+    impl io::Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.interrupt = !self.interrupt;
+            if self.interrupt {
+                return Err(io::ErrorKind::Interrupted.into());
+            }
+            io::Read::read(&mut io::Read::take(&mut self.data, 1), buf)
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let encoded = encoded_stream();
+        let mut expected_encoding = Vec::new();
+        for message in messages() {
+            expected_encoding.extend(message.encode_length_delimited_to_vec());
+        }
+        assert_eq!(encoded, expected_encoding);
+
+        let mut reader = MessageStreamReader::new(encoded.as_slice());
+        let read: Result<Vec<OpaqueMessage>, _> = reader.messages().collect();
+        assert_eq!(read.unwrap(), messages());
+        assert!(reader.read_message::<OpaqueMessage>().unwrap().is_none());
+
+        let mut reader = MessageStreamReader::new(encoded.as_slice());
+        for message in messages() {
+            assert_eq!(
+                reader.read_distinguished_message().unwrap(),
+                Some((message, Canonicity::Canonical))
+            );
+        }
+        assert!(reader
+            .read_distinguished_message::<OpaqueMessage>()
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn partial_reads() {
+        let encoded = encoded_stream();
+        let mut reader = MessageStreamReader::new(Trickle {
+            data: &encoded,
+            interrupt: false,
+        });
+        let read: Result<Vec<OpaqueMessage>, _> = reader.messages().collect();
+        assert_eq!(read.unwrap(), messages());
+    }
+
+    #[test]
+    fn truncated_frame() {
+        let encoded = encoded_stream();
+        for truncated_len in [1, 5, 9, 12, 100, encoded.len() - 1] {
+            let mut reader = MessageStreamReader::new(&encoded[..truncated_len]);
+            let mut messages = reader.messages::<OpaqueMessage>();
+            let err = messages.find_map(Result::err).unwrap();
+            assert!(matches!(err, StreamError::TruncatedFrame));
+            assert_eq!(io::Error::from(err).kind(), io::ErrorKind::UnexpectedEof);
+            // The iterator ends after the error, but the partial frame is still there
+            assert!(messages.next().is_none());
+            assert!(matches!(
+                reader.read_message::<OpaqueMessage>(),
+                Err(StreamError::TruncatedFrame)
+            ));
+        }
+    }
+
+    #[test]
+    fn io_errors_can_be_retried() {
+        /// Reader that times out once partway through its data, then fails at its end.
+        struct Failing<'a> {
+            parts: [&'a [u8]; 2],
+            reads: usize,
+        }
+
+        impl io::Read for Failing<'_> {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                self.reads += 1;
+                match self.reads {
+                    1 => io::Read::read(&mut self.parts[0], buf),
+                    2 => Err(io::ErrorKind::TimedOut.into()),
+                    3 => io::Read::read(&mut self.parts[1], buf),
+                    _ => Err(io::ErrorKind::BrokenPipe.into()),
+                }
+            }
+        }
+
+        let encoded = messages()[0].encode_length_delimited_to_vec();
+        let mut reader = MessageStreamReader::new(Failing {
+            parts: [&encoded[..3], &encoded[3..]],
+            reads: 0,
+        });
+        assert!(matches!(
+            reader.read_message::<OpaqueMessage>(),
+            Err(StreamError::Io(err)) if err.kind() == io::ErrorKind::TimedOut
+        ));
+        assert_eq!(
+            reader.read_message::<OpaqueMessage>().unwrap(),
+            Some(messages()[0].clone())
+        );
+        // Errors at the end of the data are not mistaken for the stream ending cleanly
+        for _ in 0..2 {
+            assert!(matches!(
+                reader.read_message::<OpaqueMessage>(),
+                Err(StreamError::Io(err)) if err.kind() == io::ErrorKind::BrokenPipe
+            ));
+        }
+    }
+
+    #[test]
+    fn max_frame_size() {
+        let encoded = encoded_stream();
+        let mut reader = MessageStreamReader::with_max_frame_size(encoded.as_slice(), 10_000);
+        assert_eq!(reader.max_frame_size(), 10_000);
+        assert_eq!(
+            reader.read_message::<OpaqueMessage>().unwrap(),
+            Some(messages()[0].clone())
+        );
+        assert_eq!(
+            reader.read_message::<OpaqueMessage>().unwrap(),
+            Some(messages()[1].clone())
+        );
+        assert!(matches!(
+            reader.read_message::<OpaqueMessage>(),
+            Err(StreamError::FrameTooLarge { max: 10_000, .. })
+        ));
+        assert!(matches!(
+            reader.read_message::<OpaqueMessage>(),
+            Err(StreamError::Poisoned)
+        ));
+        let mut messages = reader.messages::<OpaqueMessage>();
+        assert!(matches!(messages.next(), Some(Err(StreamError::Poisoned))));
+        assert!(messages.next().is_none());
+    }
+
+    #[test]
+    fn decode_errors_skip_frame() {
+        let mut encoded = vec![];
+        // A frame containing an invalid message
+        encoded.extend([2, 0x80, 0x80]);
+        encoded.extend(messages()[0].encode_length_delimited_to_vec());
+        let mut reader = MessageStreamReader::new(encoded.as_slice());
+        assert!(matches!(
+            reader.read_message::<OpaqueMessage>(),
+            Err(StreamError::Decode(err)) if err.kind() == DecodeErrorKind::Truncated
+        ));
+        assert_eq!(
+            reader.read_message::<OpaqueMessage>().unwrap(),
+            Some(messages()[0].clone())
+        );
+        assert!(reader.read_message::<OpaqueMessage>().unwrap().is_none());
+    }
+
+    #[test]
+    fn invalid_delimiter_ends_stream() {
+        let mut reader = MessageStreamReader::new([0xff; 9].as_slice());
+        assert!(matches!(
+            reader.read_message::<OpaqueMessage>(),
+            Err(StreamError::InvalidDelimiter(err)) if err.kind() == DecodeErrorKind::InvalidVarint
+        ));
+        assert!(matches!(
+            reader.read_distinguished_message::<OpaqueMessage>(),
+            Err(StreamError::Poisoned)
+        ));
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn async_round_trip() {
+        use super::{AsyncMessageStreamReader, AsyncMessageStreamWriter};
+
+        let mut writer = AsyncMessageStreamWriter::new(Vec::new());
+        for message in messages() {
+            writer.write_message(&message).await.unwrap();
+        }
+        writer.shutdown().await.unwrap();
+        let encoded = writer.into_inner();
+        assert_eq!(encoded, encoded_stream());
+
+        let mut reader = AsyncMessageStreamReader::new(encoded.as_slice());
+        for message in messages() {
+            assert_eq!(reader.read_message().await.unwrap(), Some(message));
+        }
+        assert!(reader
+            .read_message::<OpaqueMessage>()
+            .await
+            .unwrap()
+            .is_none());
+
+        let mut reader = AsyncMessageStreamReader::new(&encoded[..encoded.len() - 1]);
+        for message in &messages()[..3] {
+            assert_eq!(
+                reader.read_distinguished_message().await.unwrap(),
+                Some((message.clone(), Canonicity::Canonical))
+            );
+        }
+        for _ in 0..2 {
+            assert!(matches!(
+                reader.read_message::<OpaqueMessage>().await,
+                Err(StreamError::TruncatedFrame)
+            ));
+        }
+    }
+}
@@ -2,12 +2,25 @@ use alloc::borrow::Cow;
 use alloc::boxed::Box;
 use alloc::vec::Vec;
 use core::borrow::{Borrow, BorrowMut};
-use core::ops::{Deref, DerefMut};
-
-use bytes::{Buf, BufMut};
-
-use crate::encoding::{skip_field, Canonicity, Capped, DecodeContext, EmptyState, WireType};
-use crate::message::{RawDistinguishedMessage, RawMessage};
+use core::cell::{Cell, UnsafeCell};
+use core::cmp::Ordering;
+use core::fmt::{self, Debug};
+use core::hash::{Hash, Hasher};
+use core::ops::{Bound, Deref, DerefMut};
+
+use bytes::{Buf, BufMut, Bytes};
+
+use crate::descriptor::MessageDescriptor;
+use crate::encoding::{
+    skip_field, Canonicity, Capped, DecodeContext, EmptyState, MergeTracker, ReverseBuffer,
+    TagMeasurer, TagRevWriter, TagWriter, WireType,
+};
+use crate::message::{
+    DistinguishedMessage, Message, MessageExtension, RawDistinguishedMessage,
+    RawDistinguishedMessageBorrowDecoder, RawDistinguishedMessageDecoder, RawMessage,
+    RawMessageBorrowDecoder, RawMessageDecoder,
+};
+use crate::patch::{PatchOp, PatchWriter};
 use crate::DecodeError;
 
 /// Newtype wrapper to act as a simple "bytes data" type in Bilrost. It transparently wraps a
@@ -154,6 +167,647 @@ impl proptest::arbitrary::Arbitrary for Blob {
     >;
 }
 
+macro_rules! total_float {
+    ($name:ident, $float:ty, $canonical_name:ident, $canonical_nan_bits:literal) => {
+        /// Wrapper for a floating point number that is compared by its exact bits, which makes it
+        /// usable in distinguished messages and as the items of sets and keys of maps.
+        ///
+        #[doc = concat!(
+            "Two `", stringify!($name), "`s are equal only if their bits are the same, so `-0.0` ",
+            "and `0.0` differ and each NaN is equal to itself. They are ordered by the IEEE 754 ",
+            "`totalOrder` predicate, as with [`", stringify!($float), "::total_cmp`]."
+        )]
+        ///
+        /// The encoding is exactly that of the wrapped value.
+        #[derive(Clone, Copy, Debug, Default)]
+        #[repr(transparent)]
+        pub struct $name(pub $float);
+
+        impl PartialEq for $name {
+            fn eq(&self, other: &Self) -> bool {
+                self.0.to_bits() == other.0.to_bits()
+            }
+        }
+
+        impl Eq for $name {}
+
+        impl PartialOrd for $name {
+            fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+                Some(self.cmp(other))
+            }
+        }
+
+        impl Ord for $name {
+            fn cmp(&self, other: &Self) -> Ordering {
+                self.0.total_cmp(&other.0)
+            }
+        }
+
+        impl Hash for $name {
+            fn hash<H: Hasher>(&self, state: &mut H) {
+                self.0.to_bits().hash(state)
+            }
+        }
+
+        impl From<$float> for $name {
+            fn from(value: $float) -> Self {
+                Self(value)
+            }
+        }
+
+        impl From<$name> for $float {
+            fn from(value: $name) -> Self {
+                value.0
+            }
+        }
+
+        impl EmptyState for $name {
+            fn empty() -> Self {
+                Self(0.0)
+            }
+
+            fn is_empty(&self) -> bool {
+                self.0.is_empty()
+            }
+
+            fn clear(&mut self) {
+                *self = Self::empty();
+            }
+        }
+
+        #[doc = concat!(
+            "Wrapper for a floating point number like [`", stringify!($name), "`] that only ",
+            "ever holds one NaN value."
+        )]
+        ///
+        /// Every NaN is replaced with the canonical quiet NaN, which has a positive sign and an
+        /// empty payload, when the value is created or decoded; in distinguished mode, decoding
+        /// any other NaN is not canonical. This is useful when NaNs from different sources should
+        /// all be considered the same value.
+        #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+        #[repr(transparent)]
+        pub struct $canonical_name($name);
+
+        impl $canonical_name {
+            /// Wraps the value, replacing it with the canonical NaN if it is any NaN.
+            pub fn new(value: $float) -> Self {
+                Self($name(if value.is_nan() {
+                    <$float>::from_bits($canonical_nan_bits)
+                } else {
+                    value
+                }))
+            }
+
+            /// Returns the wrapped value.
+            pub fn get(self) -> $float {
+                self.0 .0
+            }
+
+            /// Returns true if the value is not a NaN other than the canonical NaN.
+            pub(crate) fn is_canonical(value: $float) -> bool {
+                !value.is_nan() || value.to_bits() == $canonical_nan_bits
+            }
+        }
+
+        impl From<$float> for $canonical_name {
+            fn from(value: $float) -> Self {
+                Self::new(value)
+            }
+        }
+
+        impl From<$canonical_name> for $float {
+            fn from(value: $canonical_name) -> Self {
+                value.get()
+            }
+        }
+
+        impl EmptyState for $canonical_name {
+            fn empty() -> Self {
+                Self($name::empty())
+            }
+
+            fn is_empty(&self) -> bool {
+                self.0.is_empty()
+            }
+
+            fn clear(&mut self) {
+                *self = Self::empty();
+            }
+        }
+    };
+}
+
+total_float!(TotalF32, f32, CanonicalNanF32, 0x7fc0_0000);
+total_float!(TotalF64, f64, CanonicalNanF64, 0x7ff8_0000_0000_0000);
+
+/// Wrapper for a message type that remembers its encoded length.
+///
+/// When a message is nested inside another, its encoded length is needed both when measuring the
+/// outer message and again for the length prefix when it is encoded, so deeply nested messages can
+/// be measured many times over. `CachedLen` stores the length of the wrapped message the first
+/// time it is measured and reuses it until the message is mutated.
+///
+/// The cached length is cleared whenever the message is borrowed mutably, cleared, or decoded
+/// into. Messages with interior mutability that can change their encoded length through a shared
+/// reference should not be wrapped in this type. Because the cache is a `Cell`, `CachedLen` is not
+/// `Sync`.
+///
+/// Equality, hashing, and the encoded representation are exactly those of the wrapped message.
+#[derive(Default)]
+pub struct CachedLen<M> {
+    message: M,
+    len: Cell<Option<usize>>,
+}
+
+impl<M> CachedLen<M> {
+    /// Creates a new `CachedLen` containing the given message, with no cached length.
+    pub fn new(message: M) -> Self {
+        Self {
+            message,
+            len: Cell::new(None),
+        }
+    }
+
+    /// Returns the wrapped message, discarding the cached length.
+    pub fn into_inner(self) -> M {
+        self.message
+    }
+
+    /// Returns the cached encoded length of the message, if there is one.
+    pub fn cached_len(&self) -> Option<usize> {
+        self.len.get()
+    }
+
+    /// Discards the cached encoded length.
+    pub fn invalidate(&mut self) {
+        self.len.set(None);
+    }
+}
+
+impl<M: Clone> Clone for CachedLen<M> {
+    fn clone(&self) -> Self {
+        Self {
+            message: self.message.clone(),
+            len: self.len.clone(),
+        }
+    }
+}
+
+impl<M: Debug> Debug for CachedLen<M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.message.fmt(f)
+    }
+}
+
+impl<M: PartialEq> PartialEq for CachedLen<M> {
+    fn eq(&self, other: &Self) -> bool {
+        self.message == other.message
+    }
+}
+
+impl<M: Eq> Eq for CachedLen<M> {}
+
+impl<M: PartialOrd> PartialOrd for CachedLen<M> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        self.message.partial_cmp(&other.message)
+    }
+}
+
+impl<M: Ord> Ord for CachedLen<M> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.message.cmp(&other.message)
+    }
+}
+
+impl<M: Hash> Hash for CachedLen<M> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.message.hash(state)
+    }
+}
+
+impl<M> Deref for CachedLen<M> {
+    type Target = M;
+
+    fn deref(&self) -> &M {
+        &self.message
+    }
+}
+
+impl<M> DerefMut for CachedLen<M> {
+    fn deref_mut(&mut self) -> &mut M {
+        self.invalidate();
+        &mut self.message
+    }
+}
+
+impl<M> From<M> for CachedLen<M> {
+    fn from(value: M) -> Self {
+        Self::new(value)
+    }
+}
+
+impl<M: EmptyState> EmptyState for CachedLen<M> {
+    fn empty() -> Self {
+        Self::new(M::empty())
+    }
+
+    fn is_empty(&self) -> bool {
+        self.message.is_empty()
+    }
+
+    fn clear(&mut self) {
+        self.invalidate();
+        self.message.clear()
+    }
+}
+
+impl<M: RawMessage> RawMessage for CachedLen<M> {
+    const __ASSERTIONS: () = ();
+    const DESCRIPTOR: &'static MessageDescriptor = M::DESCRIPTOR;
+
+    fn raw_encode<B: BufMut + ?Sized>(&self, buf: &mut B) {
+        self.message.raw_encode(buf)
+    }
+
+    fn raw_prepend(&self, buf: &mut ReverseBuffer) {
+        self.message.raw_prepend(buf)
+    }
+
+    fn raw_encoded_len(&self) -> usize {
+        if let Some(len) = self.len.get() {
+            return len;
+        }
+        let len = self.message.raw_encoded_len();
+        self.len.set(Some(len));
+        len
+    }
+
+    fn raw_diff(&self, new: &Self, pw: &mut PatchWriter) {
+        self.message.raw_diff(&new.message, pw)
+    }
+}
+
+impl<M: RawMessageDecoder> RawMessageDecoder for CachedLen<M> {
+    fn raw_decode_field<B: Buf + ?Sized>(
+        &mut self,
+        tag: u32,
+        wire_type: WireType,
+        duplicated: bool,
+        buf: Capped<B>,
+        ctx: DecodeContext,
+    ) -> Result<(), DecodeError>
+    where
+        Self: Sized,
+    {
+        self.invalidate();
+        self.message
+            .raw_decode_field(tag, wire_type, duplicated, buf, ctx)
+    }
+
+    fn raw_merge_field<B: Buf + ?Sized>(
+        &mut self,
+        tag: u32,
+        wire_type: WireType,
+        duplicated: bool,
+        buf: Capped<B>,
+        ctx: DecodeContext,
+        tracker: &mut MergeTracker,
+    ) -> Result<(), DecodeError>
+    where
+        Self: Sized,
+    {
+        self.invalidate();
+        self.message
+            .raw_merge_field(tag, wire_type, duplicated, buf, ctx, tracker)
+    }
+
+    fn raw_apply(&mut self, path: &[u32], op: &PatchOp) -> Result<(), DecodeError> {
+        self.invalidate();
+        self.message.raw_apply(path, op)
+    }
+}
+
+impl<M: RawDistinguishedMessage> RawDistinguishedMessage for CachedLen<M> {}
+
+impl<M: RawDistinguishedMessageDecoder> RawDistinguishedMessageDecoder for CachedLen<M> {
+    fn raw_decode_field_distinguished<B: Buf + ?Sized>(
+        &mut self,
+        tag: u32,
+        wire_type: WireType,
+        duplicated: bool,
+        buf: Capped<B>,
+        ctx: DecodeContext,
+    ) -> Result<Canonicity, DecodeError>
+    where
+        Self: Sized,
+    {
+        self.invalidate();
+        self.message
+            .raw_decode_field_distinguished(tag, wire_type, duplicated, buf, ctx)
+    }
+}
+
+impl<'a, M: RawMessageBorrowDecoder<'a>> RawMessageBorrowDecoder<'a> for CachedLen<M> {
+    fn raw_borrow_decode_field(
+        &mut self,
+        tag: u32,
+        wire_type: WireType,
+        duplicated: bool,
+        buf: Capped<&'a [u8]>,
+        ctx: DecodeContext,
+    ) -> Result<(), DecodeError>
+    where
+        Self: Sized,
+    {
+        self.invalidate();
+        self.message
+            .raw_borrow_decode_field(tag, wire_type, duplicated, buf, ctx)
+    }
+}
+
+impl<'a, M: RawDistinguishedMessageBorrowDecoder<'a>> RawDistinguishedMessageBorrowDecoder<'a>
+    for CachedLen<M>
+{
+    fn raw_borrow_decode_field_distinguished(
+        &mut self,
+        tag: u32,
+        wire_type: WireType,
+        duplicated: bool,
+        buf: Capped<&'a [u8]>,
+        ctx: DecodeContext,
+    ) -> Result<Canonicity, DecodeError>
+    where
+        Self: Sized,
+    {
+        self.invalidate();
+        self.message
+            .raw_borrow_decode_field_distinguished(tag, wire_type, duplicated, buf, ctx)
+    }
+}
+
+impl<M: MessageExtension> MessageExtension for CachedLen<M> {
+    fn raw_encode_tag_range<B: BufMut + ?Sized>(
+        &self,
+        range: (Bound<u32>, Bound<u32>),
+        buf: &mut B,
+        tw: &mut TagWriter,
+    ) {
+        self.message.raw_encode_tag_range(range, buf, tw)
+    }
+
+    fn raw_prepend_tag_range(
+        &self,
+        range: (Bound<u32>, Bound<u32>),
+        buf: &mut ReverseBuffer,
+        tw: &mut TagRevWriter,
+    ) {
+        self.message.raw_prepend_tag_range(range, buf, tw)
+    }
+
+    fn raw_tag_range_encoded_len(
+        &self,
+        range: (Bound<u32>, Bound<u32>),
+        tm: &mut TagMeasurer,
+    ) -> usize {
+        self.message.raw_tag_range_encoded_len(range, tm)
+    }
+}
+
+/// Wrapper for a message type that is kept in its encoded form until it is first accessed.
+///
+/// When a `Lazy` field is decoded, the encoded bytes of the message are captured without decoding
+/// them; when decoding from `Bytes` this does not copy them. The message is decoded the first time
+/// it is accessed with `get` or `get_mut`, and as long as it is never borrowed mutably the
+/// original bytes are re-encoded verbatim. This makes it cheap to decode and re-encode messages
+/// that contain large sub-messages which are usually forwarded untouched.
+///
+/// Because unmodified bytes are re-encoded as they are, including any unknown fields or
+/// non-canonical encodings they contain, `Lazy` values only compare equal when they would be
+/// encoded identically. This keeps encodings and canonical hashes of equal messages identical.
+/// Whether the bytes themselves are canonical is reported when decoding in distinguished mode.
+///
+/// Messages decoded on access are decoded without any `DecodeOptions` limits, and with a fresh
+/// recursion limit.
+///
+/// When decoding in distinguished mode, the nested message is validated eagerly by default: it is
+/// decoded immediately and its canonicity is included in that of the outer message. If the decode
+/// options were created with `with_deferred_validation(true)`, the bytes are only captured and the
+/// outer message's canonicity does not include the contents of the `Lazy` field; that is reported
+/// instead by `get_distinguished` when the message is first accessed.
+///
+/// Because the message is decoded through a shared reference, `Lazy` is not `Sync`.
+pub struct Lazy<M> {
+    /// The encoded form of the message, present until the message is borrowed mutably.
+    encoded: Option<Bytes>,
+    /// The decoded message, present once it has been accessed. This is only ever written while it
+    /// is `None`, so references to its contents are never invalidated through a shared reference.
+    decoded: UnsafeCell<Option<M>>,
+    /// The canonicity of the encoded bytes, if they have been decoded in distinguished mode.
+    canonicity: Cell<Option<Canonicity>>,
+}
+
+pub(crate) enum LazyForm<'a, M> {
+    Encoded(&'a Bytes),
+    Decoded(&'a M),
+}
+
+impl<M> Lazy<M> {
+    /// Creates a new `Lazy` containing an already-decoded message.
+    pub fn new(message: M) -> Self {
+        Self {
+            encoded: None,
+            decoded: UnsafeCell::new(Some(message)),
+            canonicity: Cell::new(None),
+        }
+    }
+
+    /// Creates a new `Lazy` from the encoded bytes of a message, which will not be decoded until
+    /// it is accessed.
+    pub fn from_encoded(encoded: Bytes) -> Self {
+        Self {
+            encoded: Some(encoded),
+            decoded: UnsafeCell::new(None),
+            canonicity: Cell::new(None),
+        }
+    }
+
+    /// Returns the original encoded bytes of the message if it has not been borrowed mutably
+    /// since it was decoded or created from bytes.
+    pub fn encoded(&self) -> Option<&Bytes> {
+        self.encoded.as_ref()
+    }
+
+    /// Returns true if the message has been decoded or was never encoded.
+    pub fn is_decoded(&self) -> bool {
+        self.decoded().is_some()
+    }
+
+    fn decoded(&self) -> Option<&M> {
+        // SAFETY: the cell is only written by `fill` while it is empty, and through `&mut self`.
+        unsafe { &*self.decoded.get() }.as_ref()
+    }
+
+    fn fill(&self, message: M) -> &M {
+        debug_assert!(self.decoded().is_none());
+        // SAFETY: the cell is empty, so there are no references to its contents that could be
+        // invalidated by writing to it.
+        unsafe { *self.decoded.get() = Some(message) };
+        self.decoded().unwrap()
+    }
+
+    /// Returns the form of the message that should be encoded: its original bytes if it is
+    /// unmodified, and otherwise the decoded message.
+    pub(crate) fn form(&self) -> LazyForm<'_, M> {
+        match (&self.encoded, self.decoded()) {
+            (Some(encoded), _) => LazyForm::Encoded(encoded),
+            (None, Some(message)) => LazyForm::Decoded(message),
+            (None, None) => unreachable!("lazy message has neither encoded nor decoded form"),
+        }
+    }
+
+    /// Returns the encoded bytes, for internal use when the message has not been decoded.
+    fn encoded_for_decode(&self) -> Bytes {
+        // If the message has not been decoded, it must still be encoded.
+        self.encoded.clone().unwrap_or_default()
+    }
+}
+
+impl<M: RawMessageDecoder> Lazy<M> {
+    /// Returns a reference to the message, decoding it if it has not been decoded yet.
+    pub fn get(&self) -> Result<&M, DecodeError> {
+        match self.decoded() {
+            Some(message) => Ok(message),
+            None => Ok(self.fill(M::decode(self.encoded_for_decode())?)),
+        }
+    }
+
+    /// Returns a mutable reference to the message, decoding it if it has not been decoded yet.
+    /// After this, the original encoded bytes are discarded and the message will be re-encoded
+    /// from its fields.
+    pub fn get_mut(&mut self) -> Result<&mut M, DecodeError> {
+        if self.decoded().is_none() {
+            let message = M::decode(self.encoded_for_decode())?;
+            *self.decoded.get_mut() = Some(message);
+        }
+        self.encoded = None;
+        self.canonicity.set(None);
+        Ok(self.decoded.get_mut().as_mut().unwrap())
+    }
+
+    /// Returns the message, decoding it if it has not been decoded yet.
+    pub fn into_inner(self) -> Result<M, DecodeError> {
+        match self.decoded.into_inner() {
+            Some(message) => Ok(message),
+            None => M::decode(self.encoded.unwrap_or_default()),
+        }
+    }
+}
+
+impl<M: RawDistinguishedMessageDecoder> Lazy<M> {
+    /// Returns a reference to the message along with the canonicity of its encoded bytes,
+    /// decoding it in distinguished mode if its canonicity is not known yet. A message which was
+    /// never encoded or has been borrowed mutably is always canonical.
+    pub fn get_distinguished(&self) -> Result<(&M, Canonicity), DecodeError> {
+        if self.encoded.is_none() {
+            return Ok((self.get()?, Canonicity::Canonical));
+        }
+        if let Some(canon) = self.canonicity.get() {
+            return Ok((self.get()?, canon));
+        }
+        let (message, canon) = M::decode_distinguished(self.encoded_for_decode())?;
+        self.canonicity.set(Some(canon));
+        let message = match self.decoded() {
+            // The message was already decoded in expedient mode; the result is the same.
+            Some(existing) => existing,
+            None => self.fill(message),
+        };
+        Ok((message, canon))
+    }
+
+    /// Creates a `Lazy` for a message that has already been decoded from the given bytes in
+    /// distinguished mode.
+    pub(crate) fn from_validated(encoded: Bytes, message: M, canon: Canonicity) -> Self {
+        Self {
+            encoded: Some(encoded),
+            decoded: UnsafeCell::new(Some(message)),
+            canonicity: Cell::new(Some(canon)),
+        }
+    }
+}
+
+impl<M: EmptyState> Default for Lazy<M> {
+    fn default() -> Self {
+        Self::empty()
+    }
+}
+
+impl<M: Clone> Clone for Lazy<M> {
+    fn clone(&self) -> Self {
+        Self {
+            encoded: self.encoded.clone(),
+            decoded: UnsafeCell::new(self.decoded().cloned()),
+            canonicity: self.canonicity.clone(),
+        }
+    }
+}
+
+impl<M: Debug> Debug for Lazy<M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.decoded(), &self.encoded) {
+            (Some(message), _) => f.debug_tuple("Lazy").field(message).finish(),
+            (None, encoded) => f.debug_struct("Lazy").field("encoded", encoded).finish(),
+        }
+    }
+}
+
+/// Lazy messages are equal when they would be encoded identically: unmodified messages are compared
+/// by their encoded bytes, and modified or newly created messages by their values. Comparing an
+/// unmodified message with a modified one encodes the latter.
+impl<M: RawMessage + PartialEq> PartialEq for Lazy<M> {
+    fn eq(&self, other: &Self) -> bool {
+        match (self.form(), other.form()) {
+            (LazyForm::Encoded(a), LazyForm::Encoded(b)) => a == b,
+            (LazyForm::Decoded(a), LazyForm::Decoded(b)) => a == b,
+            (LazyForm::Encoded(encoded), LazyForm::Decoded(message))
+            | (LazyForm::Decoded(message), LazyForm::Encoded(encoded)) => {
+                if message.raw_encoded_len() != encoded.len() {
+                    return false;
+                }
+                let mut buf = Vec::with_capacity(encoded.len());
+                message.raw_encode(&mut buf);
+                buf == *encoded
+            }
+        }
+    }
+}
+
+impl<M: RawMessage + Eq> Eq for Lazy<M> {}
+
+impl<M> From<M> for Lazy<M> {
+    fn from(value: M) -> Self {
+        Self::new(value)
+    }
+}
+
+impl<M: EmptyState> EmptyState for Lazy<M> {
+    fn empty() -> Self {
+        Self::new(M::empty())
+    }
+
+    fn is_empty(&self) -> bool {
+        match &self.encoded {
+            // Unmodified messages are re-encoded verbatim, so they are only empty if their
+            // encoding is.
+            Some(encoded) => encoded.is_empty(),
+            None => self.decoded().map_or(true, M::is_empty),
+        }
+    }
+
+    fn clear(&mut self) {
+        *self = Self::empty();
+    }
+}
+
 impl EmptyState for () {
     fn empty() -> Self {}
 
@@ -166,13 +820,24 @@ impl EmptyState for () {
 
 impl RawMessage for () {
     const __ASSERTIONS: () = ();
+    const DESCRIPTOR: &'static MessageDescriptor = &MessageDescriptor {
+        name: "()",
+        fields: &[],
+        oneofs: &[],
+        reserved_tags: &[],
+        has_extensions: false,
+    };
 
     fn raw_encode<B: BufMut + ?Sized>(&self, _buf: &mut B) {}
 
+    fn raw_prepend(&self, _buf: &mut ReverseBuffer) {}
+
     fn raw_encoded_len(&self) -> usize {
         0
     }
+}
 
+impl RawMessageDecoder for () {
     fn raw_decode_field<B: Buf + ?Sized>(
         &mut self,
         _tag: u32,
@@ -188,7 +853,9 @@ impl RawMessage for () {
     }
 }
 
-impl RawDistinguishedMessage for () {
+impl RawDistinguishedMessage for () {}
+
+impl RawDistinguishedMessageDecoder for () {
     fn raw_decode_field_distinguished<B: Buf + ?Sized>(
         &mut self,
         _tag: u32,
@@ -204,3 +871,62 @@ impl RawDistinguishedMessage for () {
         Ok(Canonicity::HasExtensions)
     }
 }
+
+impl<'a> RawMessageBorrowDecoder<'a> for () {
+    fn raw_borrow_decode_field(
+        &mut self,
+        _tag: u32,
+        wire_type: WireType,
+        _duplicated: bool,
+        buf: Capped<&'a [u8]>,
+        _ctx: DecodeContext,
+    ) -> Result<(), DecodeError>
+    where
+        Self: Sized,
+    {
+        skip_field(wire_type, buf)
+    }
+}
+
+impl<'a> RawDistinguishedMessageBorrowDecoder<'a> for () {
+    fn raw_borrow_decode_field_distinguished(
+        &mut self,
+        _tag: u32,
+        wire_type: WireType,
+        _duplicated: bool,
+        buf: Capped<&'a [u8]>,
+        _ctx: DecodeContext,
+    ) -> Result<Canonicity, DecodeError>
+    where
+        Self: Sized,
+    {
+        skip_field(wire_type, buf)?;
+        Ok(Canonicity::HasExtensions)
+    }
+}
+
+impl MessageExtension for () {
+    fn raw_encode_tag_range<B: BufMut + ?Sized>(
+        &self,
+        _range: (Bound<u32>, Bound<u32>),
+        _buf: &mut B,
+        _tw: &mut TagWriter,
+    ) {
+    }
+
+    fn raw_prepend_tag_range(
+        &self,
+        _range: (Bound<u32>, Bound<u32>),
+        _buf: &mut ReverseBuffer,
+        _tw: &mut TagRevWriter,
+    ) {
+    }
+
+    fn raw_tag_range_encoded_len(
+        &self,
+        _range: (Bound<u32>, Bound<u32>),
+        _tm: &mut TagMeasurer,
+    ) -> usize {
+        0
+    }
+}